@@ -59,6 +59,7 @@ async fn main() -> std::io::Result<()> {
             }
         },
         timeout: args.timeout,
+        json: args.json,
         url: args.url,
     };
 
@@ -70,12 +71,15 @@ async fn main() -> std::io::Result<()> {
             command.exec(client).await;
         }
         Commands::Database(command) => command.exec(client).await,
+        Commands::Job(command) => command.exec(client).await,
         Commands::Account(command) => command.exec(client).await,
         Commands::Domain(command) => command.exec(client).await,
         Commands::List(command) => command.exec(client).await,
         Commands::Group(command) => command.exec(client).await,
         Commands::Queue(command) => command.exec(client).await,
         Commands::Report(command) => command.exec(client).await,
+        Commands::Quarantine(command) => command.exec(client).await,
+        Commands::Dkim(command) => command.exec(),
     }
 
     Ok(())
@@ -193,12 +197,64 @@ impl Client {
             })
     }
 
+    pub fn print_json<T: Serialize>(&self, value: &T) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_result("serialize JSON")
+        );
+    }
+
+    pub async fn print_json_request(&self, url: &str) {
+        let result = self
+            .http_request::<serde_json::Value, String>(Method::GET, url, None)
+            .await;
+        self.print_json(&result);
+    }
+
     pub async fn http_request<R: DeserializeOwned, B: Serialize>(
         &self,
         method: Method,
         url: &str,
         body: Option<B>,
     ) -> R {
+        let mut request = self.build_request(method, url);
+
+        if let Some(body) = body {
+            request = request.body(serde_json::to_string(&body).unwrap_result("serialize body"));
+        }
+
+        let response = request.send().await.unwrap_result("send HTTP request");
+        let response = assert_response(response).await;
+
+        match serde_json::from_slice::<Response<R>>(
+            &response.bytes().await.unwrap_result("fetch bytes"),
+        )
+        .unwrap_result("deserialize response")
+        {
+            Response::Data { data } => data,
+            Response::Error { error, details } => {
+                eprintln!("Request failed: {details} ({error:?})");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub async fn http_download(&self, url: &str) -> Vec<u8> {
+        let response = self
+            .build_request(Method::GET, url)
+            .send()
+            .await
+            .unwrap_result("send HTTP request");
+
+        assert_response(response)
+            .await
+            .bytes()
+            .await
+            .unwrap_result("fetch bytes")
+            .to_vec()
+    }
+
+    fn build_request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
         let url = format!(
             "{}{}{}",
             self.url,
@@ -209,7 +265,7 @@ impl Client {
             },
             url
         );
-        let mut request = reqwest::Client::builder()
+        reqwest::Client::builder()
             .danger_accept_invalid_certs(is_localhost(&url))
             .timeout(Duration::from_secs(self.timeout.unwrap_or(60)))
             .build()
@@ -221,39 +277,23 @@ impl Client {
                     Credentials::Basic(s) => format!("Basic {s}"),
                     Credentials::Bearer(s) => format!("Bearer {s}"),
                 },
-            );
+            )
+    }
+}
 
-        if let Some(body) = body {
-            request = request.body(serde_json::to_string(&body).unwrap_result("serialize body"));
+async fn assert_response(response: reqwest::Response) -> reqwest::Response {
+    match response.status() {
+        StatusCode::OK => response,
+        StatusCode::UNAUTHORIZED => {
+            eprintln!("Authentication failed. Make sure the credentials are correct and that the account has administrator rights.");
+            std::process::exit(1);
         }
-
-        let response = request.send().await.unwrap_result("send HTTP request");
-
-        match response.status() {
-            StatusCode::OK => (),
-            StatusCode::UNAUTHORIZED => {
-                eprintln!("Authentication failed. Make sure the credentials are correct and that the account has administrator rights.");
-                std::process::exit(1);
-            }
-            _ => {
-                eprintln!(
-                    "Request failed: {}",
-                    response.text().await.unwrap_result("fetch text")
-                );
-                std::process::exit(1);
-            }
-        }
-
-        match serde_json::from_slice::<Response<R>>(
-            &response.bytes().await.unwrap_result("fetch bytes"),
-        )
-        .unwrap_result("deserialize response")
-        {
-            Response::Data { data } => data,
-            Response::Error { error, details } => {
-                eprintln!("Request failed: {details} ({error:?})");
-                std::process::exit(1);
-            }
+        _ => {
+            eprintln!(
+                "Request failed: {}",
+                response.text().await.unwrap_result("fetch text")
+            );
+            std::process::exit(1);
         }
     }
 }
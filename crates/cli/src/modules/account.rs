@@ -251,6 +251,10 @@ impl Client {
                 None,
             )
             .await;
+        if self.json {
+            self.print_json(&principal);
+            return;
+        }
         let mut table = Table::new();
         if let Some(name) = principal.name {
             table.add_row(Row::new(vec![
@@ -335,6 +339,10 @@ impl Client {
         let results = self
             .http_request::<Vec<String>, String>(Method::GET, &query.finish(), None)
             .await;
+        if self.json {
+            self.print_json(&results);
+            return;
+        }
         if !results.is_empty() {
             let mut table = Table::new();
             table.add_row(Row::new(vec![
@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand, ValueEnum};
 use jmap_client::client::Credentials;
 use mail_parser::DateTime;
@@ -41,6 +43,9 @@ pub struct Cli {
     /// Connection timeout in seconds
     #[clap(short, long)]
     pub timeout: Option<u64>,
+    /// Print results as JSON instead of tables
+    #[clap(short, long)]
+    pub json: bool,
 }
 
 #[derive(Subcommand)]
@@ -61,7 +66,7 @@ pub enum Commands {
     #[clap(subcommand)]
     Group(GroupCommands),
 
-    /// Import JMAP accounts and Maildir/mbox mailboxes
+    /// Import JMAP accounts and Maildir/mbox/PST mailboxes
     #[clap(subcommand)]
     Import(ImportCommands),
 
@@ -73,6 +78,10 @@ pub enum Commands {
     #[clap(subcommand)]
     Database(DatabaseCommands),
 
+    /// Manage background jobs
+    #[clap(subcommand)]
+    Job(JobCommands),
+
     /// Manage SMTP message queue
     #[clap(subcommand)]
     Queue(QueueCommands),
@@ -80,12 +89,21 @@ pub enum Commands {
     /// Manage SMTP DMARC/TLS report queue
     #[clap(subcommand)]
     Report(ReportCommands),
+
+    /// Manage quarantined messages
+    #[clap(subcommand)]
+    Quarantine(QuarantineCommands),
+
+    /// Generate DKIM signing keys
+    #[clap(subcommand)]
+    Dkim(DkimCommands),
 }
 
 pub struct Client {
     pub url: String,
     pub credentials: Credentials,
     pub timeout: Option<u64>,
+    pub json: bool,
 }
 
 #[derive(Subcommand)]
@@ -362,6 +380,10 @@ pub enum ImportCommands {
         #[clap(short, long)]
         num_concurrent: Option<usize>,
 
+        /// Import Maildir messages one at a time following their Dovecot UID order
+        #[clap(long)]
+        uid_order: bool,
+
         /// Account name or email to import messages into
         account: String,
 
@@ -396,12 +418,57 @@ pub enum ExportCommands {
         /// Path to export the account to
         path: String,
     },
+
+    /// Build an encrypted archive with all the data stored for an account
+    Archive {
+        /// Account name to export
+        account: String,
+
+        /// Password used to encrypt the archive
+        password: String,
+
+        /// File to write the archive to, defaults to <account>-<id>.tar.pgp
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum DatabaseCommands {
     /// Perform database maintenance
     Maintenance {},
+
+    /// Rebuild full-text and property indexes while accounts stay online
+    Reindex {
+        /// Account to reindex, defaults to all accounts
+        #[clap(short, long)]
+        account: Option<String>,
+    },
+
+    /// Report key space usage by subsystem and account
+    Analyze {
+        /// Number of accounts to show
+        #[clap(short, long, default_value = "20")]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum JobCommands {
+    /// Shows queued, running and recently finished jobs
+    List {},
+
+    /// Shows the status of a job
+    Status {
+        /// Job ID
+        id: String,
+    },
+
+    /// Cancels a pending job or removes a finished one
+    Cancel {
+        /// Job ID
+        id: String,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -412,6 +479,8 @@ pub enum MailboxFormat {
     Maildir,
     /// Maildir with hierarchical folders (i.e. Dovecot)
     MaildirNested,
+    /// Outlook PST file (requires readpst from libpst)
+    Pst,
 }
 
 #[derive(Subcommand)]
@@ -443,6 +512,15 @@ pub enum QueueCommands {
         ids: Vec<String>,
     },
 
+    /// Displays the delivery history of a message
+    History {
+        /// Lookup messages by Message-ID
+        #[clap(short, long)]
+        message_id: Option<String>,
+        // Show one or multiple message ids
+        ids: Vec<String>,
+    },
+
     /// Reschedule delivery
     Retry {
         /// Apply to messages matching a sender address
@@ -486,6 +564,24 @@ pub enum QueueCommands {
         // Cancel one or multiple message ids
         ids: Vec<String>,
     },
+
+    /// Shows addresses on the outbound suppression list
+    Suppressed {
+        /// Filter by sender domain
+        #[clap(short, long)]
+        domain: Option<String>,
+        /// Export entries as JSON to a file
+        #[clap(short, long)]
+        export: Option<PathBuf>,
+    },
+
+    /// Removes an address from the outbound suppression list
+    Unsuppress {
+        /// Sender domain
+        domain: String,
+        /// Suppressed recipient address
+        address: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -517,6 +613,53 @@ pub enum ReportCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum QuarantineCommands {
+    /// Shows quarantined messages
+    List {},
+
+    /// Shows recent data loss prevention incidents
+    Incidents {},
+
+    /// Releases quarantined messages for delivery
+    Release {
+        #[clap(required = true)]
+        ids: Vec<String>,
+    },
+
+    /// Deletes quarantined messages
+    Delete {
+        #[clap(required = true)]
+        ids: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DkimCommands {
+    /// Generates a DKIM key pair and prints its DNS record
+    Generate {
+        /// Signing domain
+        domain: String,
+        /// DKIM selector
+        #[clap(short, long, default_value = "stalwart")]
+        selector: String,
+        /// Signature algorithm
+        #[clap(short, long, value_enum, default_value = "rsa")]
+        algorithm: DkimAlgorithm,
+        /// Directory where the key files are written
+        #[clap(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DkimAlgorithm {
+    /// RSA-SHA256 (2048 bits)
+    Rsa,
+    /// Ed25519-SHA256
+    Ed25519,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
 pub enum ReportFormat {
     /// DMARC report
@@ -21,20 +21,196 @@
  * for more details.
 */
 
+use std::collections::HashMap;
+
+use human_size::{Byte, SpecificSize};
+use prettytable::{Attr, Cell, Row, Table};
 use reqwest::Method;
-use serde_json::Value;
+use serde::Deserialize;
+
+use super::{
+    cli::{Client, DatabaseCommands},
+    jobs::Job,
+};
+
+#[derive(Debug, Deserialize)]
+struct KeySpaceAnalysis {
+    #[serde(rename = "totalBytes")]
+    total_bytes: u64,
+    total: HashMap<String, KeySpaceUsage>,
+    accounts: Vec<AccountKeySpace>,
+    anomalies: Vec<KeySpaceAnomaly>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+struct KeySpaceUsage {
+    keys: u64,
+    #[serde(rename = "keyBytes")]
+    key_bytes: u64,
+    #[serde(rename = "valueBytes")]
+    value_bytes: u64,
+}
 
-use super::cli::{Client, DatabaseCommands};
+#[derive(Debug, Deserialize)]
+struct AccountKeySpace {
+    #[serde(rename = "accountId")]
+    account_id: u32,
+    name: Option<String>,
+    #[serde(rename = "totalBytes")]
+    total_bytes: u64,
+    usage: HashMap<String, KeySpaceUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeySpaceAnomaly {
+    #[serde(rename = "accountId")]
+    account_id: Option<u32>,
+    name: Option<String>,
+    #[serde(rename = "keySpace")]
+    key_space: Option<String>,
+    keys: u64,
+    reason: String,
+}
 
 impl DatabaseCommands {
     pub async fn exec(self, client: Client) {
         match self {
             DatabaseCommands::Maintenance {} => {
-                client
-                    .http_request::<Value, String>(Method::GET, "/admin/store/maintenance", None)
+                let job = client
+                    .http_request::<Job, String>(Method::GET, "/admin/store/maintenance", None)
                     .await;
-                eprintln!("Success.");
+                eprintln!("Maintenance job {} submitted.", job.id);
+            }
+            DatabaseCommands::Reindex { account } => {
+                let job = client
+                    .http_request::<Job, _>(
+                        Method::POST,
+                        "/admin/jobs",
+                        Some(serde_json::json!({
+                            "type": "reindex",
+                            "account": account,
+                        })),
+                    )
+                    .await;
+                eprintln!("Reindex job {} submitted.", job.id);
+            }
+            DatabaseCommands::Analyze { top } => {
+                // The analysis scans the whole store, wait for the job to finish
+                let job = client
+                    .http_request::<Job, _>(
+                        Method::POST,
+                        "/admin/jobs",
+                        Some(serde_json::json!({
+                            "type": "key-space-analysis",
+                        })),
+                    )
+                    .await;
+                let job = client.wait_for_job(&job.id).await;
+                if job.status != "completed" {
+                    eprintln!(
+                        "Key space analysis {}: {}",
+                        job.status,
+                        job.reason.as_deref().unwrap_or("Unknown error")
+                    );
+                    std::process::exit(1);
+                }
+                let url = format!("/admin/reports/key-space?top={top}");
+                if client.json {
+                    client.print_json_request(&url).await;
+                    return;
+                }
+                let analysis = client
+                    .http_request::<KeySpaceAnalysis, String>(Method::GET, &url, None)
+                    .await;
+
+                let mut total = analysis.total.into_iter().collect::<Vec<_>>();
+                total.sort_unstable_by(|a, b| {
+                    (b.1.key_bytes + b.1.value_bytes).cmp(&(a.1.key_bytes + a.1.value_bytes))
+                });
+                let mut table = Table::new();
+                table.add_row(Row::new(
+                    ["Key space", "Keys", "Key size", "Value size"]
+                        .iter()
+                        .map(|p| Cell::new(p).with_style(Attr::Bold))
+                        .collect(),
+                ));
+                for (key_space, usage) in &total {
+                    table.add_row(Row::new(vec![
+                        Cell::new(key_space),
+                        Cell::new(&usage.keys.to_string()),
+                        Cell::new(&format_size(usage.key_bytes)),
+                        Cell::new(&format_size(usage.value_bytes)),
+                    ]));
+                }
+                eprintln!();
+                table.printstd();
+                eprintln!("\nTotal size: {}", format_size(analysis.total_bytes));
+
+                if !analysis.accounts.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        [
+                            "Account",
+                            "Size",
+                            "Properties",
+                            "Change logs",
+                            "Indexes",
+                            "Full-text",
+                        ]
+                        .iter()
+                        .map(|p| Cell::new(p).with_style(Attr::Bold))
+                        .collect(),
+                    ));
+                    for account in &analysis.accounts {
+                        let keys = |key_space: &str| {
+                            account
+                                .usage
+                                .get(key_space)
+                                .map_or(0, |usage| usage.keys)
+                                .to_string()
+                        };
+                        table.add_row(Row::new(vec![
+                            Cell::new(
+                                &account
+                                    .name
+                                    .clone()
+                                    .unwrap_or_else(|| format!("#{}", account.account_id)),
+                            ),
+                            Cell::new(&format_size(account.total_bytes)),
+                            Cell::new(&keys("properties")),
+                            Cell::new(&keys("change-logs")),
+                            Cell::new(&keys("indexes")),
+                            Cell::new(&keys("full-text")),
+                        ]));
+                    }
+                    eprintln!();
+                    table.printstd();
+                }
+
+                if !analysis.anomalies.is_empty() {
+                    eprintln!("\nAnomalies:");
+                    for anomaly in &analysis.anomalies {
+                        let account = match (&anomaly.name, anomaly.account_id) {
+                            (Some(name), _) => name.clone(),
+                            (None, Some(account_id)) => format!("#{account_id}"),
+                            (None, None) => "server".to_string(),
+                        };
+                        eprintln!(
+                            "  {account}: {} ({} {} keys)",
+                            anomaly.reason,
+                            anomaly.keys,
+                            anomaly.key_space.as_deref().unwrap_or("total")
+                        );
+                    }
+                }
+                eprintln!();
             }
         }
     }
 }
+
+fn format_size(size: u64) -> String {
+    SpecificSize::new(size as f64, Byte)
+        .map(|size| size.to_string())
+        .unwrap_or_else(|_| size.to_string())
+}
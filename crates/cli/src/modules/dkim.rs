@@ -0,0 +1,213 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, path::Path};
+
+use base64::{engine::general_purpose, Engine};
+use openssl::{pkey::PKey, rsa::Rsa};
+
+use super::{
+    cli::{DkimAlgorithm, DkimCommands},
+    UnwrapResult,
+};
+
+impl DkimCommands {
+    pub fn exec(self) {
+        match self {
+            DkimCommands::Generate {
+                domain,
+                selector,
+                algorithm,
+                output,
+            } => {
+                let (private, public) = generate_key(algorithm).unwrap_result("generate key");
+
+                // Write key files using the same layout as the installer
+                fs::create_dir_all(&output).unwrap_result("create output directory");
+                let pk_path = output.join(format!("{domain}.key"));
+                fs::write(&pk_path, private).unwrap_result("write private key");
+                let pub_path = output.join(format!("{domain}.cert"));
+                fs::write(&pub_path, public.as_bytes()).unwrap_result("write public key");
+
+                eprintln!(
+                    "\nKeys written to {} and {}.\n",
+                    pk_path.display(),
+                    pub_path.display()
+                );
+                eprintln!("Add the following DNS record to your domain:\n");
+                println!(
+                    "{selector}._domainkey.{domain}. IN TXT {}",
+                    dns_record(algorithm, &public)
+                );
+                eprintln!("\nAnd the following signature to your configuration:\n");
+                eprintln!(
+                    "{}",
+                    config_snippet(algorithm, &domain, &selector, &pk_path, &pub_path)
+                );
+            }
+        }
+    }
+}
+
+fn generate_key(algorithm: DkimAlgorithm) -> Result<(Vec<u8>, String), openssl::error::ErrorStack> {
+    let mut public = String::new();
+    let private = match algorithm {
+        DkimAlgorithm::Rsa => {
+            let rsa = Rsa::generate(2048)?;
+            general_purpose::STANDARD.encode_string(rsa.public_key_to_der()?, &mut public);
+            rsa.private_key_to_pem()?
+        }
+        DkimAlgorithm::Ed25519 => {
+            // Ed25519 DKIM records publish the raw public key (RFC 8463)
+            let key = PKey::generate_ed25519()?;
+            general_purpose::STANDARD.encode_string(key.raw_public_key()?, &mut public);
+            key.private_key_to_pem_pkcs8()?
+        }
+    };
+
+    Ok((private, public))
+}
+
+fn dns_record(algorithm: DkimAlgorithm, public: &str) -> String {
+    let record = format!(
+        "v=DKIM1; k={}; p={public}",
+        match algorithm {
+            DkimAlgorithm::Rsa => "rsa",
+            DkimAlgorithm::Ed25519 => "ed25519",
+        }
+    );
+
+    // TXT character strings are limited to 255 bytes
+    record
+        .as_bytes()
+        .chunks(255)
+        .map(|chunk| format!("\"{}\"", String::from_utf8_lossy(chunk)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn config_snippet(
+    algorithm: DkimAlgorithm,
+    domain: &str,
+    selector: &str,
+    pk_path: &Path,
+    pub_path: &Path,
+) -> String {
+    let pk_path = fs::canonicalize(pk_path).unwrap_or_else(|_| pk_path.to_path_buf());
+    let pub_path = fs::canonicalize(pub_path).unwrap_or_else(|_| pub_path.to_path_buf());
+
+    format!(
+        concat!(
+            "[signature.\"{domain}\"]\n",
+            "{public_key}private-key = \"file://{pk_path}\"\n",
+            "domain = \"{domain}\"\n",
+            "selector = \"{selector}\"\n",
+            "headers = [\"From\", \"To\", \"Date\", \"Subject\", \"Message-ID\"]\n",
+            "algorithm = \"{algorithm}\"\n",
+            "canonicalization = \"relaxed/relaxed\"\n",
+        ),
+        domain = domain,
+        selector = selector,
+        pk_path = pk_path.display(),
+        public_key = match algorithm {
+            DkimAlgorithm::Rsa => String::new(),
+            DkimAlgorithm::Ed25519 => format!("public-key = \"file://{}\"\n", pub_path.display()),
+        },
+        algorithm = match algorithm {
+            DkimAlgorithm::Rsa => "rsa-sha256",
+            DkimAlgorithm::Ed25519 => "ed25519-sha256",
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use base64::{engine::general_purpose, Engine};
+    use openssl::{pkey::PKey, rsa::Rsa};
+
+    use crate::modules::cli::DkimAlgorithm;
+
+    #[test]
+    fn generate_dkim_keys() {
+        // RSA records publish the DER encoded public key of the private key
+        let (private, public) = super::generate_key(DkimAlgorithm::Rsa).unwrap();
+        let rsa = Rsa::private_key_from_pem(&private).unwrap();
+        assert_eq!(rsa.size(), 256);
+        assert_eq!(
+            general_purpose::STANDARD.decode(&public).unwrap(),
+            rsa.public_key_to_der().unwrap()
+        );
+
+        // Long records are split into 255 byte character strings
+        let record = super::dns_record(DkimAlgorithm::Rsa, &public);
+        let strings = record
+            .strip_prefix('"')
+            .and_then(|r| r.strip_suffix('"'))
+            .unwrap()
+            .split("\" \"")
+            .collect::<Vec<_>>();
+        assert!(strings.len() > 1);
+        assert!(strings.iter().all(|s| s.len() <= 255));
+        assert_eq!(strings.concat(), format!("v=DKIM1; k=rsa; p={public}"));
+
+        // Ed25519 records publish the raw 32 byte public key
+        let (private, public) = super::generate_key(DkimAlgorithm::Ed25519).unwrap();
+        let key = PKey::private_key_from_pem(&private).unwrap();
+        assert_eq!(
+            general_purpose::STANDARD.decode(&public).unwrap(),
+            key.raw_public_key().unwrap()
+        );
+        assert_eq!(key.raw_public_key().unwrap().len(), 32);
+        assert_eq!(
+            super::dns_record(DkimAlgorithm::Ed25519, &public),
+            format!("\"v=DKIM1; k=ed25519; p={public}\"")
+        );
+    }
+
+    #[test]
+    fn dkim_config_snippet() {
+        let pk_path = Path::new("/keys/example.org.key");
+        let pub_path = Path::new("/keys/example.org.cert");
+
+        let rsa =
+            super::config_snippet(DkimAlgorithm::Rsa, "example.org", "mail", pk_path, pub_path);
+        assert!(rsa.starts_with("[signature.\"example.org\"]\n"));
+        assert!(rsa.contains("private-key = \"file:///keys/example.org.key\"\n"));
+        assert!(rsa.contains("selector = \"mail\"\n"));
+        assert!(rsa.contains("algorithm = \"rsa-sha256\"\n"));
+        assert!(!rsa.contains("public-key"));
+
+        // Ed25519 signatures also need the public key
+        let ed25519 = super::config_snippet(
+            DkimAlgorithm::Ed25519,
+            "example.org",
+            "mail",
+            pk_path,
+            pub_path,
+        );
+        assert!(ed25519.contains("public-key = \"file:///keys/example.org.cert\"\n"));
+        assert!(ed25519.contains("algorithm = \"ed25519-sha256\"\n"));
+    }
+}
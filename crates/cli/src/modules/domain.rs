@@ -72,6 +72,10 @@ impl DomainCommands {
                 let domains = client
                     .http_request::<Vec<String>, String>(Method::GET, query.as_ref(), None)
                     .await;
+                if client.json {
+                    client.print_json(&domains);
+                    return;
+                }
                 if !domains.is_empty() {
                     let mut table = Table::new();
                     table.add_row(Row::new(vec![
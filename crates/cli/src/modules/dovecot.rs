@@ -0,0 +1,359 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use mail_parser::mailbox::maildir::Flag;
+
+// Reads Maildir folders along with the metadata kept by Dovecot, custom
+// keywords are stored in "dovecot-keywords" and referenced by the lowercase
+// letters in the file name flags, while "dovecot-uidlist" maps each file to
+// its IMAP UID.
+pub struct MessageIterator {
+    keywords: Vec<String>,
+    files: std::vec::IntoIter<(Option<u32>, PathBuf)>,
+}
+
+pub struct Message {
+    pub uid: Option<u32>,
+    pub path: PathBuf,
+    pub flags: Vec<Flag>,
+    pub keywords: Vec<String>,
+    pub internal_date: u64,
+    pub contents: Vec<u8>,
+}
+
+impl MessageIterator {
+    // Returns None when the folder has no Dovecot metadata
+    pub fn new(path: &Path) -> io::Result<Option<Self>> {
+        let keywords = read_keywords(&path.join("dovecot-keywords"))?;
+        let uids = read_uidlist(&path.join("dovecot-uidlist"))?;
+        if keywords.is_none() && uids.is_none() {
+            return Ok(None);
+        }
+        let keywords = keywords.unwrap_or_default();
+        let uids = uids.unwrap_or_default();
+
+        let mut files = Vec::new();
+        for sub_dir in ["cur", "new"] {
+            let sub_dir = path.join(sub_dir);
+            if !sub_dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(sub_dir)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+                        if !file_name.starts_with('.') {
+                            files.push((uids.get(base_name(file_name)).copied(), path));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Messages are returned in UID order, followed by any unlisted ones
+        files.sort_unstable_by(|(uid_a, path_a), (uid_b, path_b)| match (uid_a, uid_b) {
+            (Some(uid_a), Some(uid_b)) => uid_a.cmp(uid_b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => path_a.cmp(path_b),
+        });
+
+        Ok(Some(MessageIterator {
+            keywords,
+            files: files.into_iter(),
+        }))
+    }
+}
+
+impl Iterator for MessageIterator {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (uid, path) = self.files.next()?;
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let mut flags = Vec::new();
+        let mut keywords = Vec::new();
+        if let Some((_, info)) = file_name
+            .rsplit_once(":2,")
+            .or_else(|| file_name.rsplit_once("!2,"))
+        {
+            for ch in info.chars() {
+                match ch {
+                    'P' => flags.push(Flag::Passed),
+                    'R' => flags.push(Flag::Replied),
+                    'S' => flags.push(Flag::Seen),
+                    'T' => flags.push(Flag::Trashed),
+                    'D' => flags.push(Flag::Draft),
+                    'F' => flags.push(Flag::Flagged),
+                    'a'..='z' => {
+                        if let Some(keyword) = self.keywords.get((ch as u8 - b'a') as usize) {
+                            if !keyword.is_empty() {
+                                keywords.push(keyword.clone());
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Some(fs::metadata(&path).and_then(|metadata| {
+            Ok(Message {
+                uid,
+                internal_date: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |time| time.as_secs()),
+                contents: fs::read(&path)?,
+                flags,
+                keywords,
+                path,
+            })
+        }))
+    }
+}
+
+// Returns the subscribed folder names, each split into its hierarchy parts
+pub fn read_subscriptions(path: &Path, separator: &str) -> io::Result<HashSet<Vec<String>>> {
+    let contents = match fs::read_to_string(path.join("subscriptions")) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err),
+    };
+    let mut lines = contents.lines().peekable();
+
+    // Version 2 files use tabs as the hierarchy separator
+    let separator = if lines.peek().map_or(false, |line| line.starts_with("V\t")) {
+        lines.next();
+        "\t"
+    } else {
+        separator
+    };
+
+    Ok(lines
+        .filter(|line| !line.is_empty() && !line.eq_ignore_ascii_case("INBOX"))
+        .map(|line| {
+            line.split(separator)
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+fn read_keywords(path: &Path) -> io::Result<Option<Vec<String>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    // Each line contains a keyword index followed by its name
+    let mut keywords = Vec::new();
+    for line in contents.lines() {
+        if let Some((idx, keyword)) = line.split_once(' ') {
+            if let Ok(idx) = idx.parse::<usize>() {
+                if idx < 26 {
+                    if keywords.len() <= idx {
+                        keywords.resize(idx + 1, String::new());
+                    }
+                    keywords[idx] = keyword.trim().to_string();
+                }
+            }
+        }
+    }
+
+    Ok(Some(keywords))
+}
+
+fn read_uidlist(path: &Path) -> io::Result<Option<HashMap<String, u32>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut lines = contents.lines();
+    let version = lines
+        .next()
+        .and_then(|header| header.split_once(' '))
+        .and_then(|(version, _)| version.parse::<u32>().ok())
+        .unwrap_or(1);
+
+    let mut uids = HashMap::new();
+    for line in lines {
+        if let Some((uid, rest)) = line.split_once(' ') {
+            if let Ok(uid) = uid.parse::<u32>() {
+                // Version 3 lines contain optional extension fields before
+                // the file name, which is prefixed with a colon.
+                let file_name = if version >= 3 {
+                    if let Some(file_name) = rest.strip_prefix(':') {
+                        file_name
+                    } else if let Some((_, file_name)) = rest.split_once(" :") {
+                        file_name
+                    } else {
+                        continue;
+                    }
+                } else {
+                    rest
+                };
+                uids.insert(base_name(file_name.trim()).to_string(), uid);
+            }
+        }
+    }
+
+    Ok(Some(uids))
+}
+
+// Dovecot matches files by their base name, ignoring the info flags and any
+// extra fields such as ",S=<size>" that may be added when the file is renamed
+fn base_name(file_name: &str) -> &str {
+    let file_name = file_name
+        .split_once(":2,")
+        .or_else(|| file_name.split_once("!2,"))
+        .map_or(file_name, |(base_name, _)| base_name);
+    file_name
+        .split_once(',')
+        .map_or(file_name, |(base_name, _)| base_name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, fs};
+
+    use mail_parser::mailbox::maildir::Flag;
+
+    #[test]
+    fn read_dovecot_maildir() {
+        let path = std::env::temp_dir().join(format!("dovecot_maildir_{}", std::process::id()));
+        for sub_dir in ["cur", "new", "tmp"] {
+            fs::create_dir_all(path.join(sub_dir)).unwrap();
+        }
+        fs::write(
+            path.join("dovecot-keywords"),
+            "0 $Forwarded\n1 Work\n3 Later\n",
+        )
+        .unwrap();
+        fs::write(
+            path.join("dovecot-uidlist"),
+            concat!(
+                "3 V1700000000 N5 G0123456789abcdef\n",
+                "2 :1700000002.M2.host,S=20\n",
+                "4 W1234 S1200 :1700000001.M1.host\n",
+                "7 :1700000003.M3.host\n",
+            ),
+        )
+        .unwrap();
+        for (file_name, contents) in [
+            (
+                "cur/1700000001.M1.host,S=1200:2,FSab",
+                "Subject: first\r\n\r\n",
+            ),
+            ("cur/1700000002.M2.host:2,RTd", "Subject: second\r\n\r\n"),
+            ("new/1700000003.M3.host", "Subject: third\r\n\r\n"),
+            ("new/1700000000.M0.host", "Subject: unlisted\r\n\r\n"),
+            ("cur/.hidden", "ignored"),
+        ] {
+            fs::write(path.join(file_name), contents).unwrap();
+        }
+
+        // Messages are returned in UID order with their flags and keywords
+        let messages = super::MessageIterator::new(&path)
+            .unwrap()
+            .unwrap()
+            .map(|message| {
+                let message = message.unwrap();
+                (
+                    message.uid,
+                    message.flags,
+                    message.keywords,
+                    String::from_utf8(message.contents).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Some(2),
+                    vec![Flag::Replied, Flag::Trashed],
+                    vec!["Later".to_string()],
+                    "Subject: second\r\n\r\n".to_string()
+                ),
+                (
+                    Some(4),
+                    vec![Flag::Flagged, Flag::Seen],
+                    vec!["$Forwarded".to_string(), "Work".to_string()],
+                    "Subject: first\r\n\r\n".to_string()
+                ),
+                (
+                    Some(7),
+                    vec![],
+                    vec![],
+                    "Subject: third\r\n\r\n".to_string()
+                ),
+                (
+                    None,
+                    vec![],
+                    vec![],
+                    "Subject: unlisted\r\n\r\n".to_string()
+                ),
+            ]
+        );
+
+        // Subscriptions are split into their hierarchy parts
+        fs::write(
+            path.join("subscriptions"),
+            "INBOX\nWork.Projects\nArchive\n",
+        )
+        .unwrap();
+        assert_eq!(
+            super::read_subscriptions(&path, ".").unwrap(),
+            HashSet::from_iter([
+                vec!["Work".to_string(), "Projects".to_string()],
+                vec!["Archive".to_string()],
+            ])
+        );
+        fs::write(path.join("subscriptions"), "V\t2\nWork\tProjects.2024\n").unwrap();
+        assert_eq!(
+            super::read_subscriptions(&path, ".").unwrap(),
+            HashSet::from_iter([vec!["Work".to_string(), "Projects.2024".to_string()]])
+        );
+
+        // Folders without Dovecot metadata are not handled
+        fs::remove_file(path.join("dovecot-keywords")).unwrap();
+        fs::remove_file(path.join("dovecot-uidlist")).unwrap();
+        assert!(super::MessageIterator::new(&path).unwrap().is_none());
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
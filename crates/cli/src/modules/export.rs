@@ -34,7 +34,8 @@ use jmap_client::{
     sieve::{self, SieveScript},
     vacation_response::{self, VacationResponse},
 };
-use serde::Serialize;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
 
 use crate::modules::RETRY_ATTEMPTS;
@@ -44,15 +45,25 @@ use super::{
     name_to_id, UnwrapResult,
 };
 
+#[derive(Debug, Deserialize)]
+struct ServerExport {
+    id: String,
+    status: String,
+    #[serde(default)]
+    progress: u8,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
 impl ExportCommands {
     pub async fn exec(self, client: Client) {
-        let mut client = client.into_jmap_client().await;
         match self {
             ExportCommands::Account {
                 num_concurrent,
                 account,
                 path,
             } => {
+                let mut client = client.into_jmap_client().await;
                 client.set_default_account_id(name_to_id(&client, &account).await);
                 let max_objects_in_get = client
                     .session()
@@ -136,6 +147,59 @@ impl ExportCommands {
                 // Wait for remaining futures
                 while futures.next().await.is_some() {}
             }
+            ExportCommands::Archive {
+                account,
+                password,
+                output,
+            } => {
+                // Request the archive, it is built in the background by the server
+                let url = format!("/admin/export/{}", account);
+                let export = client
+                    .http_request::<ServerExport, _>(
+                        Method::POST,
+                        &url,
+                        Some(serde_json::json!({
+                            "password": password,
+                        })),
+                    )
+                    .await;
+                let url = format!("{}/{}", url, export.id);
+                let mut last_progress = u8::MAX;
+                loop {
+                    let export = client
+                        .http_request::<ServerExport, String>(Method::GET, &url, None)
+                        .await;
+                    match export.status.as_str() {
+                        "completed" => break,
+                        "failed" => {
+                            eprintln!(
+                                "Export failed: {}",
+                                export.reason.as_deref().unwrap_or("Unknown error")
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            if export.progress != last_progress {
+                                eprintln!("Exporting {}... {}%", account, export.progress);
+                                last_progress = export.progress;
+                            }
+                            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                        }
+                    }
+                }
+
+                // Download encrypted archive
+                let output = output
+                    .unwrap_or_else(|| PathBuf::from(format!("{}-{}.tar.pgp", account, export.id)));
+                let archive = client.http_download(&format!("{}/archive", url)).await;
+                std::fs::write(&output, &archive)
+                    .unwrap_result(&format!("write {}", output.display()));
+                eprintln!(
+                    "Wrote {} bytes to {}, decrypt it with 'gpg --decrypt'.",
+                    archive.len(),
+                    output.display()
+                );
+            }
         }
     }
 }
@@ -51,16 +51,20 @@ use crate::modules::{name_to_id, UnwrapResult, RETRY_ATTEMPTS};
 
 use super::{
     cli::{Client, ImportCommands, MailboxFormat},
+    dovecot,
     export::{
         fetch_emails, fetch_identities, fetch_mailboxes, fetch_sieve_scripts,
         fetch_vacation_responses,
     },
+    pst::{self, PstExport},
     read_file,
 };
 
 enum Mailbox {
     Mbox(mbox::MessageIterator<Cursor<Vec<u8>>>),
     Maildir(maildir::MessageIterator),
+    Dovecot(dovecot::MessageIterator),
+    Pst(pst::MessageIterator),
     None,
 }
 
@@ -75,6 +79,7 @@ enum MailboxId<'x> {
 struct Message {
     identifier: String,
     flags: Vec<maildir::Flag>,
+    keywords: Vec<String>,
     internal_date: u64,
     contents: Vec<u8>,
 }
@@ -85,6 +90,7 @@ impl ImportCommands {
         match self {
             ImportCommands::Messages {
                 num_concurrent,
+                uid_order,
                 format,
                 account,
                 path,
@@ -93,6 +99,8 @@ impl ImportCommands {
                 let mut create_mailboxes = Vec::new();
                 let mut create_mailbox_names = Vec::new();
                 let mut create_mailbox_ids = Vec::new();
+                let mut subscriptions = HashSet::new();
+                let mut pst_export = None;
 
                 eprintln!("{} Parsing mailbox...", style("[1/4]").bold().dim(),);
 
@@ -110,11 +118,31 @@ impl ImportCommands {
                             (None, "/")
                         };
 
-                        for folder in maildir::FolderIterator::new(path, folder_sep)
+                        subscriptions = dovecot::read_subscriptions(Path::new(&path), folder_split)
+                            .unwrap_result("read Dovecot subscriptions");
+
+                        for folder in maildir::FolderIterator::new(path.clone(), folder_sep)
                             .unwrap_result("read Maildir folder")
                         {
                             let folder = folder.unwrap_result("read Maildir folder");
-                            if let Some(folder_name) = folder.name() {
+
+                            // Prefer Dovecot's metadata when available
+                            let folder_path = match (folder.name(), folder_sep) {
+                                (Some(name), Some(sep)) => {
+                                    Path::new(&path).join(format!("{sep}{name}"))
+                                }
+                                (Some(name), None) => Path::new(&path).join(name),
+                                (None, _) => PathBuf::from(&path),
+                            };
+                            let folder_name = folder.name().map(|name| name.to_string());
+                            let folder = match dovecot::MessageIterator::new(&folder_path)
+                                .unwrap_result("read Dovecot metadata")
+                            {
+                                Some(folder) => Mailbox::Dovecot(folder),
+                                None => Mailbox::Maildir(folder),
+                            };
+
+                            if let Some(folder_name) = folder_name {
                                 let mut folder_parts = Vec::new();
                                 for folder_name in folder_name.split(folder_split) {
                                     let mut folder_name = folder_name.trim();
@@ -128,13 +156,41 @@ impl ImportCommands {
                                     }
                                 }
 
-                                *create_mailboxes.last_mut().unwrap() = Mailbox::Maildir(folder);
+                                *create_mailboxes.last_mut().unwrap() = folder;
                             } else {
-                                create_mailboxes.push(Mailbox::Maildir(folder));
+                                create_mailboxes.push(folder);
                                 create_mailbox_names.push(Vec::new());
                             };
                         }
                     }
+                    MailboxFormat::Pst => {
+                        let export = PstExport::convert(&path).unwrap_result("convert PST file");
+
+                        for (folder_name, folder) in
+                            export.folders().unwrap_result("read PST folders")
+                        {
+                            if !folder_name.is_empty() {
+                                for pos in 1..=folder_name.len() {
+                                    let folder_parts = folder_name[..pos].to_vec();
+                                    if !create_mailbox_names.contains(&folder_parts) {
+                                        create_mailboxes.push(Mailbox::None);
+                                        create_mailbox_names.push(folder_parts);
+                                    }
+                                }
+                                let pos = create_mailbox_names
+                                    .iter()
+                                    .position(|name| name == &folder_name)
+                                    .unwrap();
+                                create_mailboxes[pos] = Mailbox::Pst(folder);
+                            } else {
+                                create_mailboxes.push(Mailbox::Pst(folder));
+                                create_mailbox_names.push(Vec::new());
+                            }
+                        }
+
+                        // Converted messages are removed once the import completes
+                        pst_export = Some(export);
+                    }
                 }
 
                 // Fetch all mailboxes for the account
@@ -268,13 +324,45 @@ impl ImportCommands {
                     }
                 }
 
+                // Restore folder subscriptions
+                if !subscriptions.is_empty() {
+                    let mut request = client.build();
+                    let set_request = request.set_mailbox();
+                    let mut has_updates = false;
+                    for (mailbox_name, mailbox_id) in
+                        create_mailbox_names.iter().zip(&create_mailbox_ids)
+                    {
+                        if subscriptions.contains(mailbox_name) {
+                            let mailbox_id = match mailbox_id {
+                                MailboxId::ExistingId(id) => *id,
+                                MailboxId::CreateId(id) => id.as_str(),
+                                MailboxId::None => unreachable!(),
+                            };
+                            set_request.update(mailbox_id).is_subscribed(true);
+                            has_updates = true;
+                        }
+                    }
+                    if has_updates {
+                        request
+                            .send_set_mailbox()
+                            .await
+                            .unwrap_result("update mailbox subscriptions");
+                    }
+                }
+
                 // Import messages
                 eprintln!("{} Importing messages...", style("[4/4]").bold().dim(),);
 
                 let client = Arc::new(client);
                 let total_imported = Arc::new(AtomicUsize::from(0));
                 let m = MultiProgress::new();
-                let num_concurrent = num_concurrent.unwrap_or_else(num_cpus::get);
+                // UIDs are assigned in import order, which requires importing
+                // messages sequentially to preserve them
+                let num_concurrent = if uid_order {
+                    1
+                } else {
+                    num_concurrent.unwrap_or_else(num_cpus::get)
+                };
                 let spinner_style =
                     ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")
                         .unwrap()
@@ -340,7 +428,9 @@ impl ImportCommands {
                                             .email_import(
                                                 message.contents.clone(),
                                                 [mailbox_id.as_ref()],
-                                                if !message.flags.is_empty() {
+                                                if !message.flags.is_empty()
+                                                    || !message.keywords.is_empty()
+                                                {
                                                     message
                                                         .flags
                                                         .iter()
@@ -352,6 +442,12 @@ impl ImportCommands {
                                                             maildir::Flag::Draft => "$draft",
                                                             maildir::Flag::Flagged => "$flagged",
                                                         })
+                                                        .chain(
+                                                            message
+                                                                .keywords
+                                                                .iter()
+                                                                .map(|k| k.as_str()),
+                                                        )
                                                         .into()
                                                 } else {
                                                     None
@@ -422,6 +518,9 @@ impl ImportCommands {
                         eprintln!("{}", failure);
                     }
                 }
+
+                // Remove any converted PST messages
+                drop(pst_export);
             }
 
             ImportCommands::Account {
@@ -1022,6 +1121,7 @@ impl Iterator for Mailbox {
                 r.map(|m| Message {
                     identifier: m.from().to_string(),
                     flags: Vec::new(),
+                    keywords: Vec::new(),
                     internal_date: m.internal_date(),
                     contents: m.unwrap_contents(),
                 })
@@ -1038,10 +1138,34 @@ impl Iterator for Mailbox {
                         .unwrap_or("unknown")
                         .to_string(),
                     flags: m.flags().to_vec(),
+                    keywords: Vec::new(),
                     internal_date: m.internal_date(),
                     contents: m.unwrap_contents(),
                 })
             }),
+            Mailbox::Pst(it) => it.next().map(|r| {
+                r.map(|m| Message {
+                    identifier: m.path.to_string_lossy().into_owned(),
+                    flags: m.flags,
+                    keywords: Vec::new(),
+                    internal_date: m.internal_date,
+                    contents: m.contents,
+                })
+            }),
+            Mailbox::Dovecot(it) => it.next().map(|r| {
+                r.map(|m| Message {
+                    identifier: m
+                        .path
+                        .file_name()
+                        .and_then(|f| f.to_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                    flags: m.flags,
+                    keywords: m.keywords,
+                    internal_date: m.internal_date,
+                    contents: m.contents,
+                })
+            }),
             Mailbox::None => None,
         }
     }
@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_parser::DateTime;
+use prettytable::{Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::cli::{Client, JobCommands};
+
+#[derive(Debug, Deserialize)]
+pub struct Job {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(rename = "requestedBy")]
+    pub requested_by: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    #[serde(rename = "runAt")]
+    pub run_at: u64,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<u64>,
+    pub status: String,
+    #[serde(default)]
+    pub progress: Option<u8>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl JobCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            JobCommands::List {} => {
+                if client.json {
+                    client.print_json_request("/admin/jobs").await;
+                    return;
+                }
+                let mut jobs = client
+                    .http_request::<Vec<Job>, String>(Method::GET, "/admin/jobs", None)
+                    .await;
+                jobs.sort_unstable_by_key(|job| job.created_at);
+                if !jobs.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        ["ID", "Type", "Requested by", "Run at", "Status"]
+                            .iter()
+                            .map(|p| Cell::new(p).with_style(Attr::Bold))
+                            .collect(),
+                    ));
+                    for job in &jobs {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&job.id),
+                            Cell::new(&job.typ),
+                            Cell::new(&job.requested_by),
+                            Cell::new(&DateTime::from_timestamp(job.run_at as i64).to_rfc822()),
+                            Cell::new(&job.status_text()),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+                eprintln!("\n{} job(s) found.", jobs.len());
+            }
+            JobCommands::Status { id } => {
+                let url = format!("/admin/jobs/{id}");
+                if client.json {
+                    client.print_json_request(&url).await;
+                    return;
+                }
+                let job = client
+                    .http_request::<Job, String>(Method::GET, &url, None)
+                    .await;
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![
+                    Cell::new("ID").with_style(Attr::Bold),
+                    Cell::new(&job.id),
+                ]));
+                table.add_row(Row::new(vec![
+                    Cell::new("Type").with_style(Attr::Bold),
+                    Cell::new(&job.typ),
+                ]));
+                table.add_row(Row::new(vec![
+                    Cell::new("Requested by").with_style(Attr::Bold),
+                    Cell::new(&job.requested_by),
+                ]));
+                table.add_row(Row::new(vec![
+                    Cell::new("Created").with_style(Attr::Bold),
+                    Cell::new(&DateTime::from_timestamp(job.created_at as i64).to_rfc822()),
+                ]));
+                if let Some(finished_at) = job.finished_at {
+                    table.add_row(Row::new(vec![
+                        Cell::new("Finished").with_style(Attr::Bold),
+                        Cell::new(&DateTime::from_timestamp(finished_at as i64).to_rfc822()),
+                    ]));
+                }
+                table.add_row(Row::new(vec![
+                    Cell::new("Status").with_style(Attr::Bold),
+                    Cell::new(&job.status_text()),
+                ]));
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+            }
+            JobCommands::Cancel { id } => {
+                let job = client
+                    .http_request::<Job, String>(Method::DELETE, &format!("/admin/jobs/{id}"), None)
+                    .await;
+                match job.status.as_str() {
+                    "queued" | "running" => eprintln!("Job {} cancelled.", job.id),
+                    _ => eprintln!("Job {} removed.", job.id),
+                }
+            }
+        }
+    }
+}
+
+impl Client {
+    pub async fn wait_for_job(&self, id: &str) -> Job {
+        let url = format!("/admin/jobs/{id}");
+        let mut last_progress = None;
+        loop {
+            let job = self
+                .http_request::<Job, String>(Method::GET, &url, None)
+                .await;
+            match job.status.as_str() {
+                "queued" | "running" => {
+                    if job.progress != last_progress {
+                        eprintln!("Job {}... {}%", job.id, job.progress.unwrap_or_default());
+                        last_progress = job.progress;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+                _ => return job,
+            }
+        }
+    }
+}
+
+impl Job {
+    fn status_text(&self) -> String {
+        match (self.status.as_str(), self.progress, &self.reason) {
+            ("running", Some(progress), _) => format!("running ({progress}%)"),
+            ("failed", _, Some(reason)) => format!("failed: {reason}"),
+            (status, _, _) => status.to_string(),
+        }
+    }
+}
@@ -32,11 +32,16 @@ use serde::{Deserialize, Serialize};
 pub mod account;
 pub mod cli;
 pub mod database;
+pub mod dkim;
 pub mod domain;
+pub mod dovecot;
 pub mod export;
 pub mod group;
 pub mod import;
+pub mod jobs;
 pub mod list;
+pub mod pst;
+pub mod quarantine;
 pub mod queue;
 pub mod report;
 
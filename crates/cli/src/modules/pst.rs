@@ -0,0 +1,286 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use mail_parser::{mailbox::maildir::Flag, MessageParser};
+use rand::Rng;
+
+// PST files are converted with readpst (libpst), which handles both the ANSI
+// and Unicode formats, into a directory tree with one RFC 5322 file per item.
+pub struct PstExport {
+    path: PathBuf,
+}
+
+pub struct MessageIterator {
+    files: std::vec::IntoIter<PathBuf>,
+}
+
+pub struct Message {
+    pub path: PathBuf,
+    pub flags: Vec<Flag>,
+    pub internal_date: u64,
+    pub contents: Vec<u8>,
+}
+
+impl PstExport {
+    pub fn convert(pst_path: &str) -> io::Result<Self> {
+        let path =
+            std::env::temp_dir().join(format!("stalwart-pst-{}", rand::thread_rng().gen::<u64>()));
+        fs::create_dir_all(&path)?;
+        let export = PstExport { path };
+
+        let status = Command::new("readpst")
+            .arg("-e") // One file per item, folders as directories
+            .arg("-8") // UTF-8 bodies
+            .arg("-b") // Skip RTF bodies
+            .arg("-q")
+            .args(["-t", "e"]) // E-mail items only
+            .arg("-o")
+            .arg(&export.path)
+            .arg(pst_path)
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("failed to run readpst, make sure libpst is installed ({err})"),
+                )
+            })?;
+        if status.success() {
+            Ok(export)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("readpst exited with {status}"),
+            ))
+        }
+    }
+
+    // Returns the folders in the export, parents first. Messages stored in the
+    // root folder are returned with an empty name and imported into the Inbox.
+    pub fn folders(&self) -> io::Result<Vec<(Vec<String>, MessageIterator)>> {
+        // readpst places all folders under the PST display name
+        let mut root = self.path.clone();
+        let entries = sorted_entries(&root)?;
+        if let [single_dir] = entries.as_slice() {
+            if single_dir.is_dir() {
+                root = single_dir.clone();
+            }
+        }
+
+        let mut folders = Vec::new();
+        let mut stack = vec![(Vec::new(), root)];
+        while let Some((name, path)) = stack.pop() {
+            let mut files = Vec::new();
+            let mut sub_folders = Vec::new();
+            for entry in sorted_entries(&path)? {
+                if entry.is_dir() {
+                    if let Some(folder_name) = entry.file_name().and_then(|n| n.to_str()) {
+                        let mut folder_parts = name.clone();
+                        folder_parts.push(folder_name.to_string());
+                        sub_folders.push((folder_parts, entry));
+                    }
+                } else if entry.extension().map_or(false, |ext| ext == "eml") {
+                    files.push(entry);
+                }
+            }
+
+            if !files.is_empty() || !name.is_empty() {
+                folders.push((
+                    name,
+                    MessageIterator {
+                        files: files.into_iter(),
+                    },
+                ));
+            }
+            stack.extend(sub_folders.into_iter().rev());
+        }
+
+        Ok(folders)
+    }
+}
+
+impl Drop for PstExport {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl Iterator for MessageIterator {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.files.next()?;
+        Some(fs::read(&path).map(|contents| {
+            let mut flags = Vec::new();
+            let mut internal_date = 0;
+
+            if let Some(message) = MessageParser::new().parse_headers(&contents) {
+                if let Some(date) = message.date() {
+                    internal_date = date.to_timestamp().max(0) as u64;
+                }
+
+                // Read and flag states use the mbox conventions
+                if let Some(status) = message.header_raw("Status") {
+                    if status.contains('R') {
+                        flags.push(Flag::Seen);
+                    }
+                }
+                if let Some(status) = message.header_raw("X-Status") {
+                    for (ch, flag) in [
+                        ('A', Flag::Replied),
+                        ('F', Flag::Flagged),
+                        ('T', Flag::Draft),
+                    ] {
+                        if status.contains(ch) {
+                            flags.push(flag);
+                        }
+                    }
+                }
+            }
+
+            Message {
+                path,
+                flags,
+                internal_date,
+                contents,
+            }
+        }))
+    }
+}
+
+fn sorted_entries(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    // readpst names items 1.eml, 2.eml, ... so sort them numerically
+    entries.sort_unstable_by(|a, b| {
+        let num = |path: &PathBuf| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+        };
+        match (num(a), num(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        }
+    });
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use mail_parser::mailbox::maildir::Flag;
+
+    use super::PstExport;
+
+    #[test]
+    fn read_pst_export() {
+        // Simulate the directory tree written by readpst
+        let path = std::env::temp_dir().join(format!("pst_export_{}", std::process::id()));
+        let root = path.join("Outlook Data File");
+        for (file_name, contents) in [
+            ("Inbox/2.eml", "Subject: two\r\nStatus: RO\r\n\r\n"),
+            ("Inbox/10.eml", "Subject: ten\r\nX-Status: AF\r\n\r\n"),
+            (
+                "Inbox/1.eml",
+                "Date: Sat, 20 Nov 2021 14:22:01 -0800\r\nSubject: one\r\n\r\n",
+            ),
+            (
+                "Inbox/Projects/1.eml",
+                "Subject: draft\r\nX-Status: T\r\n\r\n",
+            ),
+            ("Inbox/Projects/notes.txt", "ignored"),
+            ("Sent Items/1.eml", "Subject: sent\r\nStatus: R\r\n\r\n"),
+        ] {
+            let file_path = root.join(file_name);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(file_path, contents).unwrap();
+        }
+        fs::create_dir_all(root.join("Empty")).unwrap();
+        let export = PstExport { path: path.clone() };
+
+        // Folders are returned parents first with their messages in numeric order
+        let folders = export
+            .folders()
+            .unwrap()
+            .into_iter()
+            .map(|(name, messages)| {
+                (
+                    name.join("/"),
+                    messages
+                        .map(|message| {
+                            let message = message.unwrap();
+                            (
+                                message
+                                    .path
+                                    .file_name()
+                                    .unwrap()
+                                    .to_str()
+                                    .unwrap()
+                                    .to_string(),
+                                message.flags,
+                                message.internal_date,
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            folders,
+            vec![
+                ("Empty".to_string(), vec![]),
+                (
+                    "Inbox".to_string(),
+                    vec![
+                        ("1.eml".to_string(), vec![], 1637446921),
+                        ("2.eml".to_string(), vec![Flag::Seen], 0),
+                        ("10.eml".to_string(), vec![Flag::Replied, Flag::Flagged], 0),
+                    ]
+                ),
+                (
+                    "Inbox/Projects".to_string(),
+                    vec![("1.eml".to_string(), vec![Flag::Draft], 0)]
+                ),
+                (
+                    "Sent Items".to_string(),
+                    vec![("1.eml".to_string(), vec![Flag::Seen], 0)]
+                ),
+            ]
+        );
+
+        // The converted files are removed once the export is dropped
+        drop(export);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,213 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::{
+    cli::{Client, QuarantineCommands},
+    queue::{build_query, deserialize_datetime, parse_ids},
+};
+use human_size::{Byte, SpecificSize};
+use mail_parser::DateTime;
+use prettytable::{Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: u64,
+    pub rule: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub authenticated_as: String,
+    pub size: usize,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub created: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Incident {
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub timestamp: DateTime,
+    pub rule: String,
+    pub action: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub authenticated_as: String,
+    #[serde(default)]
+    pub queue_id: Option<u64>,
+}
+
+impl QuarantineCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            QuarantineCommands::List {} => {
+                if client.json {
+                    client.print_json_request("/admin/quarantine/list").await;
+                    return;
+                }
+                let entries = client
+                    .http_request::<Vec<QuarantineEntry>, String>(
+                        Method::GET,
+                        "/admin/quarantine/list",
+                        None,
+                    )
+                    .await;
+                if !entries.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        ["ID", "Created", "Rule", "Sender", "Recipients", "Size"]
+                            .iter()
+                            .map(|p| Cell::new(p).with_style(Attr::Bold))
+                            .collect(),
+                    ));
+                    for entry in &entries {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&format!("{:X}", entry.id)),
+                            Cell::new(&entry.created.to_rfc822()),
+                            Cell::new(&entry.rule),
+                            Cell::new(sender(&entry.return_path, &entry.authenticated_as).as_str()),
+                            Cell::new(&entry.recipients.join("\n")),
+                            Cell::new(
+                                &SpecificSize::new(entry.size as u32, Byte)
+                                    .unwrap()
+                                    .to_string(),
+                            ),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+                eprintln!("\n{} quarantined message(s) found.", entries.len());
+            }
+            QuarantineCommands::Incidents {} => {
+                if client.json {
+                    client
+                        .print_json_request("/admin/quarantine/incidents")
+                        .await;
+                    return;
+                }
+                let incidents = client
+                    .http_request::<Vec<Incident>, String>(
+                        Method::GET,
+                        "/admin/quarantine/incidents",
+                        None,
+                    )
+                    .await;
+                if !incidents.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        ["Date", "Rule", "Action", "Sender", "Recipients", "ID"]
+                            .iter()
+                            .map(|p| Cell::new(p).with_style(Attr::Bold))
+                            .collect(),
+                    ));
+                    for incident in &incidents {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&incident.timestamp.to_rfc822()),
+                            Cell::new(&incident.rule),
+                            Cell::new(&incident.action),
+                            Cell::new(
+                                sender(&incident.return_path, &incident.authenticated_as).as_str(),
+                            ),
+                            Cell::new(&incident.recipients.join("\n")),
+                            Cell::new(
+                                &incident
+                                    .queue_id
+                                    .map(|id| format!("{id:X}"))
+                                    .unwrap_or_default(),
+                            ),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+                eprintln!("\n{} incident(s) found.", incidents.len());
+            }
+            QuarantineCommands::Release { ids } => {
+                client.update_quarantine(ids, "release").await;
+            }
+            QuarantineCommands::Delete { ids } => {
+                client.update_quarantine(ids, "delete").await;
+            }
+        }
+    }
+}
+
+impl Client {
+    async fn update_quarantine(&self, ids: Vec<String>, action: &str) {
+        let mut success_count = 0;
+        let mut failed_list = vec![];
+        for (success, id) in self
+            .http_request::<Vec<bool>, String>(
+                Method::GET,
+                &build_query(
+                    &format!("/admin/quarantine/{action}?ids="),
+                    &parse_ids(&ids),
+                ),
+                None,
+            )
+            .await
+            .into_iter()
+            .zip(ids)
+        {
+            if success {
+                success_count += 1;
+            } else {
+                failed_list.push(id);
+            }
+        }
+        eprint!(
+            "\n{} {success_count} message(s).",
+            if action == "release" {
+                "Released"
+            } else {
+                "Deleted"
+            }
+        );
+        if !failed_list.is_empty() {
+            eprint!(
+                " Unable to {action} message id(s): {}.",
+                failed_list.join(", ")
+            );
+        }
+        eprintln!();
+    }
+}
+
+fn sender(return_path: &str, authenticated_as: &str) -> String {
+    let return_path = if !return_path.is_empty() {
+        return_path
+    } else {
+        "<>"
+    };
+    if !authenticated_as.is_empty() {
+        format!("{return_path}\n(as {authenticated_as})")
+    } else {
+        return_path.to_string()
+    }
+}
@@ -63,6 +63,34 @@ pub struct Recipient {
     pub orcpt: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MessageHistory {
+    pub id: u64,
+    pub message_id: Option<String>,
+    pub events: Vec<HistoryEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryEvent {
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub timestamp: DateTime,
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(flatten)]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuppressionEntry {
+    pub domain: String,
+    pub address: String,
+    pub reason: String,
+    #[serde(default)]
+    pub details: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub created: DateTime,
+}
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 pub enum Status {
     #[serde(rename = "scheduled")]
@@ -87,6 +115,12 @@ impl QueueCommands {
             } => {
                 let stdout = Term::buffered_stdout();
                 let ids = client.query_messages(&sender, &rcpt, &before, &after).await;
+                if client.json {
+                    client
+                        .print_json_request(&build_query("/admin/queue/status?ids=", &ids))
+                        .await;
+                    return;
+                }
                 let ids_len = ids.len();
                 let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
                 let pages_total = (ids_len as f64 / page_size as f64).ceil() as usize;
@@ -173,6 +207,15 @@ impl QueueCommands {
                 eprintln!("\n{ids_len} queued message(s) found.")
             }
             QueueCommands::Status { ids } => {
+                if client.json {
+                    client
+                        .print_json_request(&build_query(
+                            "/admin/queue/status?ids=",
+                            &parse_ids(&ids),
+                        ))
+                        .await;
+                    return;
+                }
                 for (message, id) in client
                     .http_request::<Vec<Option<Message>>, String>(
                         Method::GET,
@@ -289,6 +332,133 @@ impl QueueCommands {
                     eprintln!();
                 }
             }
+            QueueCommands::History { message_id, ids } => {
+                if ids.is_empty() && message_id.is_none() {
+                    eprintln!("Please specify a Message-ID or one or more queue ids.");
+                    std::process::exit(1);
+                }
+                let mut query = build_query("/admin/queue/history?ids=", &parse_ids(&ids));
+                if let Some(message_id) = &message_id {
+                    query = form_urlencoded::Serializer::new(query + "&")
+                        .append_pair("message-id", message_id)
+                        .finish();
+                }
+                let history = client
+                    .http_request::<Vec<MessageHistory>, String>(Method::GET, &query, None)
+                    .await;
+                if history.is_empty() {
+                    eprintln!("No delivery history found.");
+                }
+
+                for message in history {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("ID").with_style(Attr::Bold),
+                        Cell::new(&format!("{:X}", message.id)).with_hspan(2),
+                    ]));
+                    if let Some(message_id) = &message.message_id {
+                        table.add_row(Row::new(vec![
+                            Cell::new("Message-ID").with_style(Attr::Bold),
+                            Cell::new(message_id).with_hspan(2),
+                        ]));
+                    }
+                    for event in &message.events {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&event.timestamp.to_rfc822()),
+                            Cell::new(&event.typ).with_style(Attr::Bold),
+                            Cell::new(
+                                &event
+                                    .details
+                                    .iter()
+                                    .map(|(key, value)| match value {
+                                        serde_json::Value::String(value) => {
+                                            format!("{key}: {value}")
+                                        }
+                                        value => format!("{key}: {value}"),
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join("\n"),
+                            ),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+            }
+            QueueCommands::Suppressed { domain, export } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/suppression/list?".to_string());
+                if let Some(domain) = &domain {
+                    query.append_pair("domain", domain);
+                }
+                let query = query.finish();
+
+                if let Some(export) = export {
+                    let entries = client
+                        .http_request::<Vec<serde_json::Value>, String>(Method::GET, &query, None)
+                        .await;
+                    if let Err(err) = std::fs::write(
+                        &export,
+                        serde_json::to_string_pretty(&entries).unwrap_or_default(),
+                    ) {
+                        eprintln!("Failed to write {}: {}", export.display(), err);
+                        std::process::exit(1);
+                    }
+                    eprintln!(
+                        "Exported {} entries to {}.",
+                        entries.len(),
+                        export.display()
+                    );
+                    return;
+                }
+
+                let entries = client
+                    .http_request::<Vec<SuppressionEntry>, String>(Method::GET, &query, None)
+                    .await;
+                if entries.is_empty() {
+                    eprintln!("No suppressed addresses found.");
+                    return;
+                }
+
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![
+                    Cell::new("Domain").with_style(Attr::Bold),
+                    Cell::new("Address").with_style(Attr::Bold),
+                    Cell::new("Reason").with_style(Attr::Bold),
+                    Cell::new("Created").with_style(Attr::Bold),
+                    Cell::new("Details").with_style(Attr::Bold),
+                ]));
+                for entry in &entries {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&entry.domain),
+                        Cell::new(&entry.address),
+                        Cell::new(&entry.reason),
+                        Cell::new(&entry.created.to_rfc822()),
+                        Cell::new(&entry.details),
+                    ]));
+                }
+
+                eprintln!();
+                table.printstd();
+                eprintln!();
+            }
+            QueueCommands::Unsuppress { domain, address } => {
+                let query =
+                    form_urlencoded::Serializer::new("/admin/suppression/remove?".to_string())
+                        .append_pair("domain", &domain)
+                        .append_pair("address", &address)
+                        .finish();
+                if client
+                    .http_request::<bool, String>(Method::DELETE, &query, None)
+                    .await
+                {
+                    eprintln!("Successfully removed {address} from the suppression list.");
+                } else {
+                    eprintln!("Address {address} is not on the suppression list of {domain}.");
+                }
+            }
             QueueCommands::Retry {
                 sender,
                 domain,
@@ -464,7 +634,7 @@ where
     }
 }
 
-fn parse_ids(ids: &[String]) -> Vec<u64> {
+pub fn parse_ids(ids: &[String]) -> Vec<u64> {
     let mut result = Vec::with_capacity(ids.len());
     for id in ids {
         match u64::from_str_radix(id, 16) {
@@ -480,7 +650,7 @@ fn parse_ids(ids: &[String]) -> Vec<u64> {
     result
 }
 
-fn build_query(path: &str, ids: &[u64]) -> String {
+pub fn build_query(path: &str, ids: &[u64]) -> String {
     let mut query = String::with_capacity(path.len() + (ids.len() * 10));
     query.push_str(path);
     append_ids(query, ids)
@@ -63,6 +63,12 @@ impl ReportCommands {
                 let ids = client
                     .http_request::<Vec<String>, String>(Method::GET, &query.finish(), None)
                     .await;
+                if client.json {
+                    client
+                        .print_json_request(&format!("/admin/report/status?ids={}", ids.join(",")))
+                        .await;
+                    return;
+                }
                 let ids_len = ids.len();
                 let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
                 let pages_total = (ids_len as f64 / page_size as f64).ceil() as usize;
@@ -114,6 +120,12 @@ impl ReportCommands {
                 eprintln!("\n{ids_len} queued message(s) found.")
             }
             ReportCommands::Status { ids } => {
+                if client.json {
+                    client
+                        .print_json_request(&format!("/admin/report/status?ids={}", ids.join(",")))
+                        .await;
+                    return;
+                }
                 for (report, id) in client
                     .http_request::<Vec<Option<Report>>, String>(
                         Method::GET,
@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use ahash::AHashMap;
+use utils::config::{utils::AsKey, Config};
+
+use crate::Directory;
+
+use super::CompositeDirectory;
+
+impl CompositeDirectory {
+    pub fn from_config(
+        config: &Config,
+        prefix: impl AsKey,
+        directories: &AHashMap<String, Arc<Directory>>,
+    ) -> utils::config::Result<Self> {
+        let prefix = prefix.as_key();
+        let get_directory = |id: &str| {
+            directories
+                .get(id)
+                .cloned()
+                .ok_or_else(|| format!("Directory {id:?} referenced by {prefix:?} does not exist."))
+        };
+        let mut directory = CompositeDirectory {
+            fallback: Vec::new(),
+            routes: AHashMap::new(),
+            members: Vec::new(),
+        };
+
+        for (_, id) in config.values((&prefix, "directories")) {
+            directory.fallback.push(get_directory(id)?);
+        }
+
+        for route_id in config.sub_keys((prefix.as_str(), "routes")) {
+            let route = get_directory(config.value_require((
+                prefix.as_str(),
+                "routes",
+                route_id,
+                "directory",
+            ))?)?;
+            for (_, domain) in config.values((prefix.as_str(), "routes", route_id, "domains")) {
+                directory
+                    .routes
+                    .insert(domain.to_lowercase(), route.clone());
+            }
+        }
+
+        if directory.fallback.is_empty() && directory.routes.is_empty() {
+            return Err(format!(
+                "Composite directory {prefix:?} does not define any directories or routes."
+            ));
+        }
+
+        for member in directory.fallback.iter().chain(directory.routes.values()) {
+            if !directory
+                .members
+                .iter()
+                .any(|other| Arc::ptr_eq(member, other))
+            {
+                directory.members.push(member.clone());
+            }
+        }
+
+        Ok(directory)
+    }
+}
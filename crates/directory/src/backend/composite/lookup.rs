@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use futures::{future::BoxFuture, FutureExt};
+use mail_send::Credentials;
+
+use crate::{Principal, QueryBy};
+
+use super::CompositeDirectory;
+
+// Member directories are queried through boxed futures, as their
+// dispatch may lead back into a composite directory.
+impl CompositeDirectory {
+    pub fn query<'x>(
+        &'x self,
+        by: QueryBy<'x>,
+        return_member_of: bool,
+    ) -> BoxFuture<'x, crate::Result<Option<Principal<u32>>>> {
+        async move {
+            let directories = match by {
+                QueryBy::Name(name) => self.route(name),
+                QueryBy::Credentials(
+                    Credentials::Plain { username, .. } | Credentials::XOauth2 { username, .. },
+                ) => self.route(username),
+                QueryBy::Credentials(Credentials::OAuthBearer { .. }) | QueryBy::Id(_) => {
+                    &self.fallback
+                }
+            };
+
+            for directory in directories {
+                if let Some(mut principal) = directory.query(by, return_member_of).await? {
+                    // Merge group memberships from the other directories
+                    if return_member_of {
+                        for other in &self.members {
+                            if Arc::ptr_eq(directory, other) {
+                                continue;
+                            }
+                            if let Ok(Some(other)) =
+                                other.query(QueryBy::Name(&principal.name), true).await
+                            {
+                                for member_of in other.member_of {
+                                    if !principal.member_of.contains(&member_of) {
+                                        principal.member_of.push(member_of);
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    return Ok(Some(principal));
+                }
+            }
+
+            Ok(None)
+        }
+        .boxed()
+    }
+
+    pub fn email_to_ids<'x>(&'x self, address: &'x str) -> BoxFuture<'x, crate::Result<Vec<u32>>> {
+        async move {
+            for directory in self.route(address) {
+                let ids = directory.email_to_ids(address).await?;
+                if !ids.is_empty() {
+                    return Ok(ids);
+                }
+            }
+
+            Ok(vec![])
+        }
+        .boxed()
+    }
+
+    pub fn is_local_domain<'x>(&'x self, domain: &'x str) -> BoxFuture<'x, crate::Result<bool>> {
+        async move {
+            for directory in self.route(domain) {
+                if directory.is_local_domain(domain).await? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        }
+        .boxed()
+    }
+
+    pub fn rcpt<'x>(&'x self, address: &'x str) -> BoxFuture<'x, crate::Result<bool>> {
+        async move {
+            for directory in self.route(address) {
+                if directory.rcpt(address).await? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        }
+        .boxed()
+    }
+
+    pub fn vrfy<'x>(&'x self, address: &'x str) -> BoxFuture<'x, crate::Result<Vec<String>>> {
+        async move {
+            for directory in self.route(address) {
+                let result = directory.vrfy(address).await?;
+                if !result.is_empty() {
+                    return Ok(result);
+                }
+            }
+
+            Ok(vec![])
+        }
+        .boxed()
+    }
+
+    pub fn expn<'x>(&'x self, address: &'x str) -> BoxFuture<'x, crate::Result<Vec<String>>> {
+        async move {
+            for directory in self.route(address) {
+                let result = directory.expn(address).await?;
+                if !result.is_empty() {
+                    return Ok(result);
+                }
+            }
+
+            Ok(vec![])
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,53 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use ahash::AHashMap;
+
+use crate::Directory;
+
+pub mod config;
+pub mod lookup;
+
+pub struct CompositeDirectory {
+    // Directories queried in order for domains without a route
+    fallback: Vec<Arc<Directory>>,
+    // Domain to directory routes
+    routes: AHashMap<String, Arc<Directory>>,
+    // All distinct member directories, used to merge group memberships
+    members: Vec<Arc<Directory>>,
+}
+
+impl CompositeDirectory {
+    fn route(&self, address: &str) -> &[Arc<Directory>] {
+        let domain = address
+            .rsplit_once('@')
+            .map_or(address, |(_, domain)| domain);
+        if let Some(directory) = self.routes.get(&domain.to_lowercase()) {
+            std::slice::from_ref(directory)
+        } else {
+            &self.fallback
+        }
+    }
+}
@@ -109,6 +109,13 @@ impl DirectoryStore for Store {
             } else {
                 self.get_members(ptype.account_id).await.map_err(Into::into)
             }
+        } else if let Some(account_id) = self
+            .get_value::<u32>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::MaskedEmailToId(email.as_bytes().to_vec()),
+            )))
+            .await?
+        {
+            Ok(vec![account_id])
         } else {
             Ok(Vec::new())
         }
@@ -124,12 +131,20 @@ impl DirectoryStore for Store {
     }
 
     async fn rcpt(&self, address: &str) -> crate::Result<bool> {
-        self.get_value::<()>(ValueKey::from(ValueClass::Directory(
+        for key in [
             DirectoryClass::EmailToId(address.as_bytes().to_vec()),
-        )))
-        .await
-        .map(|ids| ids.is_some())
-        .map_err(Into::into)
+            DirectoryClass::MaskedEmailToId(address.as_bytes().to_vec()),
+        ] {
+            if self
+                .get_value::<()>(ValueKey::from(ValueClass::Directory(key)))
+                .await?
+                .is_some()
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
     }
 
     async fn vrfy(&self, address: &str) -> crate::Result<Vec<String>> {
@@ -161,15 +176,29 @@ impl DirectoryStore for Store {
 
     async fn expn(&self, address: &str) -> crate::Result<Vec<String>> {
         let mut results = Vec::new();
-        for account_id in self.email_to_ids(address).await? {
-            if let Some(email) = self
+        let mut account_ids = self.email_to_ids(address).await?;
+        let mut seen_ids = Vec::new();
+        account_ids.reverse();
+        while let Some(account_id) = account_ids.pop() {
+            if seen_ids.contains(&account_id) {
+                continue;
+            }
+            seen_ids.push(account_id);
+
+            if let Some(principal) = self
                 .get_value::<Principal<u32>>(ValueKey::from(ValueClass::Directory(
                     DirectoryClass::Principal(account_id),
                 )))
                 .await?
-                .and_then(|p| p.emails.into_iter().next())
             {
-                results.push(email);
+                if principal.typ == Type::Group {
+                    // Groups expand to the addresses of their members
+                    let mut members = self.get_members(account_id).await?;
+                    members.reverse();
+                    account_ids.extend(members);
+                } else if let Some(email) = principal.emails.into_iter().next() {
+                    results.push(email);
+                }
             }
         }
 
@@ -53,6 +53,8 @@ pub trait ManageDirectory: Sized {
         changes: Vec<PrincipalUpdate>,
     ) -> crate::Result<()>;
     async fn delete_account(&self, by: QueryBy<'_>) -> crate::Result<()>;
+    async fn add_masked_email(&self, account_id: u32, email: &str) -> crate::Result<()>;
+    async fn remove_masked_email(&self, account_id: u32, email: &str) -> crate::Result<()>;
     async fn list_accounts(
         &self,
         start_from: Option<&str>,
@@ -301,6 +303,29 @@ impl ManageDirectory for Store {
             batch.clear(DirectoryClass::EmailToId(email.into_bytes()));
         }
 
+        // Stop routing the account's masked addresses
+        let mut masked_emails = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::MaskedEmailToId(
+                    vec![0u8],
+                ))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::MaskedEmailToId(
+                    vec![u8::MAX; 10],
+                ))),
+            ),
+            |key, value| {
+                if u32::deserialize(value)? == account_id {
+                    masked_emails.push(key.get(1..).unwrap_or_default().to_vec());
+                }
+                Ok(true)
+            },
+        )
+        .await?;
+        for email in masked_emails {
+            batch.clear(DirectoryClass::MaskedEmailToId(email));
+        }
+
         for member_id in self.get_member_of(account_id).await? {
             batch.clear(DirectoryClass::MemberOf {
                 principal_id: account_id,
@@ -328,6 +353,57 @@ impl ManageDirectory for Store {
         Ok(())
     }
 
+    async fn add_masked_email(&self, account_id: u32, email: &str) -> crate::Result<()> {
+        // Make sure the e-mail is not taken and validate domain
+        let email = email.to_lowercase();
+        if self.rcpt(&email).await? {
+            return Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                field: PrincipalField::Emails,
+                value: email,
+            }));
+        }
+        if let Some(domain) = email.split('@').nth(1) {
+            if !self.is_local_domain(domain).await? {
+                return Err(DirectoryError::Management(ManagementError::NotFound(
+                    domain.to_string(),
+                )));
+            }
+        }
+
+        let key = ValueClass::Directory(DirectoryClass::MaskedEmailToId(email.as_bytes().to_vec()));
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(key.clone(), ())
+            .set(key, account_id.serialize());
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(()),
+            Err(store::Error::AssertValueFailed) => {
+                Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                    field: PrincipalField::Emails,
+                    value: email,
+                }))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn remove_masked_email(&self, account_id: u32, email: &str) -> crate::Result<()> {
+        let key = ValueClass::Directory(DirectoryClass::MaskedEmailToId(
+            email.to_lowercase().into_bytes(),
+        ));
+        match self.get_value::<u32>(ValueKey::from(key.clone())).await? {
+            Some(owner_id) if owner_id == account_id => {
+                let mut batch = BatchBuilder::new();
+                batch.assert_value(key.clone(), account_id).clear(key);
+                self.write(batch.build()).await.map_err(Into::into)
+            }
+            Some(_) => Err(DirectoryError::Management(ManagementError::NotFound(
+                email.to_string(),
+            ))),
+            None => Ok(()),
+        }
+    }
+
     async fn update_account(
         &self,
         by: QueryBy<'_>,
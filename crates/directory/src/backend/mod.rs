@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod composite;
 pub mod imap;
 pub mod internal;
 pub mod ldap;
@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{process::Stdio, time::Duration};
+
+use mail_send::Credentials;
+use reqwest::header::CONTENT_TYPE;
+use tokio::{io::AsyncWriteExt, process::Command};
+use utils::config::{utils::AsKey, Config};
+
+use crate::DirectoryError;
+
+pub enum AuthHook {
+    Http {
+        url: String,
+        client: reqwest::Client,
+    },
+    Exec {
+        command: String,
+        arguments: Vec<String>,
+        timeout: Duration,
+    },
+}
+
+#[derive(Debug, serde::Serialize)]
+struct HookRequest<'x> {
+    mechanism: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'x str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<&'x str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<&'x str>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HookResponse {
+    result: HookResult,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HookResult {
+    Accept,
+    Reject,
+}
+
+impl AuthHook {
+    pub fn try_from_config(
+        config: &Config,
+        prefix: impl AsKey,
+    ) -> utils::config::Result<Option<Self>> {
+        let prefix = prefix.as_key();
+        let timeout = config.property_or_static((&prefix, "auth-hook.timeout"), "10s")?;
+        match config.value((&prefix, "auth-hook.type")) {
+            Some("http") => Ok(Some(AuthHook::Http {
+                url: config
+                    .value_require((&prefix, "auth-hook.url"))?
+                    .to_string(),
+                client: reqwest::Client::builder()
+                    .timeout(timeout)
+                    .danger_accept_invalid_certs(config.property_or_static(
+                        (&prefix, "auth-hook.tls.allow-invalid-certs"),
+                        "false",
+                    )?)
+                    .build()
+                    .map_err(|err| {
+                        format!("Failed to create HTTP client for auth hook {prefix:?}: {err}")
+                    })?,
+            })),
+            Some("exec") => Ok(Some(AuthHook::Exec {
+                command: config
+                    .value_require((&prefix, "auth-hook.command"))?
+                    .to_string(),
+                arguments: config
+                    .values((&prefix, "auth-hook.arguments"))
+                    .map(|(_, v)| v.to_string())
+                    .collect(),
+                timeout,
+            })),
+            Some(unknown) => Err(format!(
+                "Unknown auth hook type {unknown:?} for directory {prefix:?}."
+            )),
+            None => Ok(None),
+        }
+    }
+
+    // Returns the name of the principal the credentials belong to,
+    // or None if the hook rejected them.
+    pub async fn authenticate(
+        &self,
+        credentials: &Credentials<String>,
+    ) -> crate::Result<Option<String>> {
+        let request = match credentials {
+            Credentials::Plain { username, secret } => HookRequest {
+                mechanism: "plain",
+                username: username.as_str().into(),
+                secret: secret.as_str().into(),
+                token: None,
+            },
+            Credentials::XOauth2 { username, secret } => HookRequest {
+                mechanism: "xoauth2",
+                username: username.as_str().into(),
+                secret: secret.as_str().into(),
+                token: None,
+            },
+            Credentials::OAuthBearer { token } => HookRequest {
+                mechanism: "oauthbearer",
+                username: None,
+                secret: None,
+                token: token.as_str().into(),
+            },
+        };
+        let body = serde_json::to_vec(&request).unwrap_or_default();
+
+        let response = match self {
+            AuthHook::Http { url, client } => {
+                let response = client
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        if err.is_timeout() {
+                            DirectoryError::timeout("auth-hook")
+                        } else {
+                            DirectoryError::hook(format!("HTTP request to {url:?} failed: {err}"))
+                        }
+                    })?;
+
+                if response.status().is_success() {
+                    response.bytes().await.map(|b| b.to_vec()).map_err(|err| {
+                        DirectoryError::hook(format!("Failed to read response from {url:?}: {err}"))
+                    })?
+                } else {
+                    return Err(DirectoryError::hook(format!(
+                        "{url:?} responded with HTTP status {}",
+                        response.status()
+                    )));
+                }
+            }
+            AuthHook::Exec {
+                command,
+                arguments,
+                timeout,
+            } => {
+                let mut child = Command::new(command)
+                    .args(arguments)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|err| {
+                        DirectoryError::hook(format!("Failed to spawn {command:?}: {err}"))
+                    })?;
+                let mut stdin = child.stdin.take().unwrap();
+
+                let output = tokio::time::timeout(*timeout, async move {
+                    stdin.write_all(&body).await?;
+                    drop(stdin);
+                    child.wait_with_output().await
+                })
+                .await
+                .map_err(|_| DirectoryError::timeout("auth-hook"))?
+                .map_err(|err| {
+                    DirectoryError::hook(format!("Failed to execute {command:?}: {err}"))
+                })?;
+
+                if output.status.success() {
+                    output.stdout
+                } else {
+                    return Err(DirectoryError::hook(format!(
+                        "{command:?} exited with {}",
+                        output.status
+                    )));
+                }
+            }
+        };
+
+        let response = serde_json::from_slice::<HookResponse>(&response)
+            .map_err(|err| DirectoryError::hook(format!("Invalid hook response: {err}")))?;
+        match response.result {
+            HookResult::Accept => response
+                .name
+                .filter(|name| !name.is_empty())
+                .or_else(|| request.username.map(|name| name.to_string()))
+                .map(Some)
+                .ok_or_else(|| {
+                    DirectoryError::hook("Hook accepted a token without returning a name")
+                }),
+            HookResult::Reject => Ok(None),
+        }
+    }
+}
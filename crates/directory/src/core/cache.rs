@@ -28,11 +28,32 @@ use std::{
 };
 
 use parking_lot::Mutex;
+use store::{LookupKey, LookupStore, LookupValue, Stores};
 use utils::config::{utils::AsKey, Config};
 
+use crate::{Directory, Principal};
+
 pub struct CachedDirectory {
     cached_domains: Mutex<LookupCache<String>>,
     cached_rcpts: Mutex<LookupCache<String>>,
+    cached_principals: Mutex<ValueCache<PrincipalKey, Option<Principal<u32>>>>,
+    cached_emails: Mutex<ValueCache<String, Vec<u32>>>,
+    shared: Option<SharedCache>,
+}
+
+// Lookup results shared by all nodes through a lookup store. Principals are
+// never shared as they may contain secrets.
+struct SharedCache {
+    store: LookupStore,
+    prefix: String,
+    ttl_pos: u64,
+    ttl_neg: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PrincipalKey {
+    Name { name: String, member_of: bool },
+    Id { id: u32, member_of: bool },
 }
 
 #[allow(clippy::type_complexity)]
@@ -44,18 +65,36 @@ pub struct LookupCache<T: Hash + Eq> {
     ttl_neg: Duration,
 }
 
+#[derive(Debug)]
+pub struct ValueCache<K: Hash + Eq, V> {
+    cache: lru_cache::LruCache<K, (V, Instant), ahash::RandomState>,
+    ttl_pos: Duration,
+    ttl_neg: Duration,
+}
+
 impl CachedDirectory {
     pub fn try_from_config(
         config: &Config,
+        stores: &Stores,
         prefix: impl AsKey,
     ) -> utils::config::Result<Option<Self>> {
         let prefix = prefix.as_key();
-        if let Some(cached_entries) = config.property((&prefix, "cache.entries"))? {
+        let shared_store = stores.get_lookup_store(config, (&prefix, "cache.store"))?;
+        let cached_entries = match (
+            config.property::<usize>((&prefix, "cache.entries"))?,
+            &shared_store,
+        ) {
+            (Some(cached_entries), _) => Some(cached_entries),
+            (None, Some(_)) => Some(128),
+            (None, None) => None,
+        };
+
+        if let Some(cached_entries) = cached_entries {
             let cache_ttl_positive = config
                 .property((&prefix, "cache.ttl.positive"))?
                 .unwrap_or(Duration::from_secs(86400));
             let cache_ttl_negative = config
-                .property((&prefix, "cache.ttl.positive"))?
+                .property((&prefix, "cache.ttl.negative"))?
                 .unwrap_or_else(|| Duration::from_secs(3600));
 
             Ok(Some(CachedDirectory {
@@ -69,17 +108,49 @@ impl CachedDirectory {
                     cache_ttl_positive,
                     cache_ttl_negative,
                 )),
+                cached_principals: Mutex::new(ValueCache::new(
+                    cached_entries,
+                    cache_ttl_positive,
+                    cache_ttl_negative,
+                )),
+                cached_emails: Mutex::new(ValueCache::new(
+                    cached_entries,
+                    cache_ttl_positive,
+                    cache_ttl_negative,
+                )),
+                shared: shared_store.map(|store| SharedCache {
+                    store,
+                    prefix: format!("{}:", prefix),
+                    ttl_pos: cache_ttl_positive.as_secs().max(1),
+                    ttl_neg: cache_ttl_negative.as_secs().max(1),
+                }),
             }))
         } else {
             Ok(None)
         }
     }
 
-    pub fn get_rcpt(&self, address: &str) -> Option<bool> {
-        self.cached_rcpts.lock().get(address)
+    pub async fn get_rcpt(&self, address: &str) -> Option<bool> {
+        let result = self.cached_rcpts.lock().get(address);
+        if result.is_some() {
+            return result;
+        }
+
+        let result = self.shared.as_ref()?.get(b'r', address).await?;
+        self.set_rcpt_local(address, result == "1");
+        Some(result == "1")
     }
 
-    pub fn set_rcpt(&self, address: &str, exists: bool) {
+    pub async fn set_rcpt(&self, address: &str, exists: bool) {
+        self.set_rcpt_local(address, exists);
+        if let Some(shared) = &self.shared {
+            shared
+                .set(b'r', address, if exists { "1" } else { "0" }, exists)
+                .await;
+        }
+    }
+
+    fn set_rcpt_local(&self, address: &str, exists: bool) {
         if exists {
             self.cached_rcpts.lock().insert_pos(address.to_string());
         } else {
@@ -87,17 +158,149 @@ impl CachedDirectory {
         }
     }
 
-    pub fn get_domain(&self, domain: &str) -> Option<bool> {
-        self.cached_domains.lock().get(domain)
+    pub async fn get_domain(&self, domain: &str) -> Option<bool> {
+        let result = self.cached_domains.lock().get(domain);
+        if result.is_some() {
+            return result;
+        }
+
+        let result = self.shared.as_ref()?.get(b'd', domain).await?;
+        self.set_domain_local(domain, result == "1");
+        Some(result == "1")
+    }
+
+    pub async fn set_domain(&self, domain: &str, exists: bool) {
+        self.set_domain_local(domain, exists);
+        if let Some(shared) = &self.shared {
+            shared
+                .set(b'd', domain, if exists { "1" } else { "0" }, exists)
+                .await;
+        }
     }
 
-    pub fn set_domain(&self, domain: &str, exists: bool) {
+    fn set_domain_local(&self, domain: &str, exists: bool) {
         if exists {
             self.cached_domains.lock().insert_pos(domain.to_string());
         } else {
             self.cached_domains.lock().insert_neg(domain.to_string());
         }
     }
+
+    pub fn get_principal(&self, key: &PrincipalKey) -> Option<Option<Principal<u32>>> {
+        self.cached_principals.lock().get(key)
+    }
+
+    pub fn set_principal(&self, key: PrincipalKey, principal: Option<Principal<u32>>) {
+        let exists = principal.is_some();
+        self.cached_principals.lock().insert(key, principal, exists);
+    }
+
+    pub async fn get_email_ids(&self, address: &str) -> Option<Vec<u32>> {
+        let result = self.cached_emails.lock().get(address);
+        if result.is_some() {
+            return result;
+        }
+
+        let ids = self
+            .shared
+            .as_ref()?
+            .get(b'e', address)
+            .await?
+            .split(',')
+            .filter_map(|id| id.parse::<u32>().ok())
+            .collect::<Vec<_>>();
+        self.set_email_ids_local(address, ids.clone());
+        Some(ids)
+    }
+
+    pub async fn set_email_ids(&self, address: &str, ids: Vec<u32>) {
+        if let Some(shared) = &self.shared {
+            let value = ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            shared.set(b'e', address, &value, !ids.is_empty()).await;
+        }
+        self.set_email_ids_local(address, ids);
+    }
+
+    fn set_email_ids_local(&self, address: &str, ids: Vec<u32>) {
+        let exists = !ids.is_empty();
+        self.cached_emails
+            .lock()
+            .insert(address.to_string(), ids, exists);
+    }
+
+    // Entries in the shared cache are not removed, they expire after their TTL.
+    pub fn clear(&self) {
+        self.cached_domains.lock().clear();
+        self.cached_rcpts.lock().clear();
+        self.cached_principals.lock().clear();
+        self.cached_emails.lock().clear();
+    }
+}
+
+impl SharedCache {
+    async fn get(&self, class: u8, key: &str) -> Option<String> {
+        match self
+            .store
+            .key_get::<String>(LookupKey::Key(self.key(class, key)))
+            .await
+        {
+            Ok(LookupValue::Value { value, .. }) => Some(value),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "error",
+                    key = key,
+                    reason = %err,
+                    "Failed to read shared cache entry."
+                );
+                None
+            }
+        }
+    }
+
+    async fn set(&self, class: u8, key: &str, value: &str, exists: bool) {
+        if let Err(err) = self
+            .store
+            .key_set(
+                self.key(class, key),
+                LookupValue::Value {
+                    value: value.as_bytes().to_vec(),
+                    expires: if exists { self.ttl_pos } else { self.ttl_neg },
+                },
+            )
+            .await
+        {
+            tracing::debug!(
+                context = "directory",
+                event = "error",
+                key = key,
+                reason = %err,
+                "Failed to write shared cache entry."
+            );
+        }
+    }
+
+    fn key(&self, class: u8, key: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.prefix.len() + key.len() + 2);
+        bytes.extend_from_slice(self.prefix.as_bytes());
+        bytes.push(class);
+        bytes.push(b':');
+        bytes.extend_from_slice(key.as_bytes());
+        bytes
+    }
+}
+
+impl Directory {
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
 }
 
 impl<T: Hash + Eq> LookupCache<T> {
@@ -129,7 +332,7 @@ impl<T: Hash + Eq> LookupCache<T> {
         if *valid_until >= Instant::now() {
             Some(false)
         } else {
-            self.cache_pos.remove(name);
+            self.cache_neg.remove(name);
             None
         }
     }
@@ -147,3 +350,41 @@ impl<T: Hash + Eq> LookupCache<T> {
         self.cache_neg.clear();
     }
 }
+
+impl<K: Hash + Eq, V: Clone> ValueCache<K, V> {
+    pub fn new(capacity: usize, ttl_pos: Duration, ttl_neg: Duration) -> Self {
+        Self {
+            cache: lru_cache::LruCache::with_hasher(capacity, ahash::RandomState::new()),
+            ttl_pos,
+            ttl_neg,
+        }
+    }
+
+    pub fn get<Q: ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let (value, valid_until) = self.cache.get_mut(key)?;
+        if *valid_until >= Instant::now() {
+            Some(value.clone())
+        } else {
+            self.cache.remove(key);
+            None
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V, is_positive: bool) {
+        let valid_until = Instant::now()
+            + if is_positive {
+                self.ttl_pos
+            } else {
+                self.ttl_neg
+            };
+        self.cache.insert(key, (value, valid_until));
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
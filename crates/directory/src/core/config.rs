@@ -37,13 +37,13 @@ use ahash::AHashMap;
 
 use crate::{
     backend::{
-        imap::ImapDirectory, internal::manage::ManageDirectory, ldap::LdapDirectory,
-        memory::MemoryDirectory, smtp::SmtpDirectory, sql::SqlDirectory,
+        composite::CompositeDirectory, imap::ImapDirectory, internal::manage::ManageDirectory,
+        ldap::LdapDirectory, memory::MemoryDirectory, smtp::SmtpDirectory, sql::SqlDirectory,
     },
     AddressMapping, Directories, Directory, DirectoryInner, Lookup,
 };
 
-use super::cache::CachedDirectory;
+use super::{auth_hook::AuthHook, cache::CachedDirectory};
 
 #[async_trait::async_trait]
 pub trait ConfigDirectory {
@@ -66,6 +66,7 @@ impl ConfigDirectory for Config {
             lookups: AHashMap::new(),
         };
         let id_store = id_store.and_then(|id| stores.stores.get(id).cloned());
+        let mut composites = Vec::new();
 
         for id in self.sub_keys("directory") {
             // Parse directory
@@ -116,43 +117,69 @@ impl ConfigDirectory for Config {
                 "memory" => DirectoryInner::Memory(
                     MemoryDirectory::from_config(self, prefix, id_store.clone()).await?,
                 ),
+                "composite" => {
+                    // Composite directories are built once their members have been parsed
+                    composites.push(id);
+                    continue;
+                }
                 unknown => {
                     return Err(format!("Unknown directory type: {unknown:?}"));
                 }
             };
 
-            // Build directory
-            let directory = Arc::new(Directory {
-                store,
-                catch_all: AddressMapping::from_config(
-                    self,
-                    ("directory", id, "options.catch-all"),
-                )?,
-                subaddressing: AddressMapping::from_config(
-                    self,
-                    ("directory", id, "options.subaddressing"),
-                )?,
-                cache: CachedDirectory::try_from_config(self, ("directory", id))?,
-            });
-
-            // Add lookups
-            config.lookups.insert(
-                format!("{id}/domains"),
-                Lookup::DomainExists(directory.clone()),
-            );
-            config.lookups.insert(
-                format!("{id}/recipients"),
-                Lookup::EmailExists(directory.clone()),
-            );
-
-            // Add directory
-            config.directories.insert(id.to_string(), directory);
+            config.insert_directory(self, stores, id, store)?;
+        }
+
+        for id in composites {
+            let store = DirectoryInner::Composite(CompositeDirectory::from_config(
+                self,
+                ("directory", id),
+                &config.directories,
+            )?);
+            config.insert_directory(self, stores, id, store)?;
         }
 
         Ok(config)
     }
 }
 
+impl Directories {
+    fn insert_directory(
+        &mut self,
+        config: &Config,
+        stores: &Stores,
+        id: &str,
+        store: DirectoryInner,
+    ) -> utils::config::Result<()> {
+        // Build directory
+        let directory = Arc::new(Directory {
+            store,
+            catch_all: AddressMapping::from_config(config, ("directory", id, "options.catch-all"))?,
+            subaddressing: AddressMapping::from_config(
+                config,
+                ("directory", id, "options.subaddressing"),
+            )?,
+            cache: CachedDirectory::try_from_config(config, stores, ("directory", id))?,
+            auth_hook: AuthHook::try_from_config(config, ("directory", id))?,
+        });
+
+        // Add lookups
+        self.lookups.insert(
+            format!("{id}/domains"),
+            Lookup::DomainExists(directory.clone()),
+        );
+        self.lookups.insert(
+            format!("{id}/recipients"),
+            Lookup::EmailExists(directory.clone()),
+        );
+
+        // Add directory
+        self.directories.insert(id.to_string(), directory);
+
+        Ok(())
+    }
+}
+
 impl AddressMapping {
     pub fn from_config(config: &Config, key: impl AsKey) -> utils::config::Result<Self> {
         let key = key.as_key();
@@ -25,23 +25,79 @@ use crate::{
     backend::internal::lookup::DirectoryStore, Directory, DirectoryInner, Principal, QueryBy,
 };
 
+use super::cache::PrincipalKey;
+
 impl Directory {
     pub async fn query(
         &self,
         by: QueryBy<'_>,
         return_member_of: bool,
     ) -> crate::Result<Option<Principal<u32>>> {
-        match &self.store {
+        // Delegate credential verification to the authentication hook
+        let name;
+        let by = match (by, &self.auth_hook) {
+            (QueryBy::Credentials(credentials), Some(hook)) => {
+                if let Some(principal_name) = hook.authenticate(credentials).await? {
+                    name = principal_name;
+                    QueryBy::Name(&name)
+                } else {
+                    return Ok(None);
+                }
+            }
+            (by, _) => by,
+        };
+
+        // Check cache, credentials are always verified against the backend
+        let cache_key = match (&by, &self.cache) {
+            (QueryBy::Name(name), Some(_)) => Some(PrincipalKey::Name {
+                name: name.to_string(),
+                member_of: return_member_of,
+            }),
+            (QueryBy::Id(id), Some(_)) => Some(PrincipalKey::Id {
+                id: *id,
+                member_of: return_member_of,
+            }),
+            _ => None,
+        };
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key) {
+            if let Some(result) = cache.get_principal(cache_key) {
+                return Ok(result);
+            }
+        }
+
+        let result = match &self.store {
             DirectoryInner::Internal(store) => store.query(by, return_member_of).await,
             DirectoryInner::Ldap(store) => store.query(by, return_member_of).await,
             DirectoryInner::Sql(store) => store.query(by, return_member_of).await,
             DirectoryInner::Imap(store) => store.query(by).await,
             DirectoryInner::Smtp(store) => store.query(by).await,
             DirectoryInner::Memory(store) => store.query(by).await,
+            DirectoryInner::Composite(store) => store.query(by, return_member_of).await,
+        }?;
+
+        // Update cache, principals holding secrets are not cached as the cache
+        // is only invalidated locally and a stale secret would keep validating
+        // credentials and password reset tokens on other nodes.
+        if let (Some(cache), Some(cache_key)) = (&self.cache, cache_key) {
+            if result
+                .as_ref()
+                .map_or(true, |principal| principal.secrets.is_empty())
+            {
+                cache.set_principal(cache_key, result.clone());
+            }
         }
+
+        Ok(result)
     }
 
     pub async fn email_to_ids(&self, email: &str) -> crate::Result<Vec<u32>> {
+        // Check cache
+        if let Some(cache) = &self.cache {
+            if let Some(result) = cache.get_email_ids(email).await {
+                return Ok(result);
+            }
+        }
+
         let mut address = self.subaddressing.to_subaddress(email);
         for _ in 0..2 {
             let result = match &self.store {
@@ -51,9 +107,14 @@ impl Directory {
                 DirectoryInner::Imap(store) => store.email_to_ids(address.as_ref()).await,
                 DirectoryInner::Smtp(store) => store.email_to_ids(address.as_ref()).await,
                 DirectoryInner::Memory(store) => store.email_to_ids(address.as_ref()).await,
+                DirectoryInner::Composite(store) => store.email_to_ids(address.as_ref()).await,
             }?;
 
             if !result.is_empty() {
+                // Update cache
+                if let Some(cache) = &self.cache {
+                    cache.set_email_ids(email, result.clone()).await;
+                }
                 return Ok(result);
             } else if let Some(catch_all) = self.catch_all.to_catch_all(email) {
                 address = catch_all;
@@ -62,13 +123,18 @@ impl Directory {
             }
         }
 
+        // Update cache
+        if let Some(cache) = &self.cache {
+            cache.set_email_ids(email, vec![]).await;
+        }
+
         Ok(vec![])
     }
 
     pub async fn is_local_domain(&self, domain: &str) -> crate::Result<bool> {
         // Check cache
         if let Some(cache) = &self.cache {
-            if let Some(result) = cache.get_domain(domain) {
+            if let Some(result) = cache.get_domain(domain).await {
                 return Ok(result);
             }
         }
@@ -80,11 +146,12 @@ impl Directory {
             DirectoryInner::Imap(store) => store.is_local_domain(domain).await,
             DirectoryInner::Smtp(store) => store.is_local_domain(domain).await,
             DirectoryInner::Memory(store) => store.is_local_domain(domain).await,
+            DirectoryInner::Composite(store) => store.is_local_domain(domain).await,
         }?;
 
         // Update cache
         if let Some(cache) = &self.cache {
-            cache.set_domain(domain, result);
+            cache.set_domain(domain, result).await;
         }
 
         Ok(result)
@@ -96,7 +163,7 @@ impl Directory {
 
         // Check cache
         if let Some(cache) = &self.cache {
-            if let Some(result) = cache.get_rcpt(address.as_ref()) {
+            if let Some(result) = cache.get_rcpt(address.as_ref()).await {
                 return Ok(result);
             }
         }
@@ -109,18 +176,19 @@ impl Directory {
                 DirectoryInner::Imap(store) => store.rcpt(address.as_ref()).await,
                 DirectoryInner::Smtp(store) => store.rcpt(address.as_ref()).await,
                 DirectoryInner::Memory(store) => store.rcpt(address.as_ref()).await,
+                DirectoryInner::Composite(store) => store.rcpt(address.as_ref()).await,
             }?;
 
             if result {
                 // Update cache
                 if let Some(cache) = &self.cache {
-                    cache.set_rcpt(address.as_ref(), true);
+                    cache.set_rcpt(address.as_ref(), true).await;
                 }
                 return Ok(true);
             } else if let Some(catch_all) = self.catch_all.to_catch_all(email) {
                 // Check cache
                 if let Some(cache) = &self.cache {
-                    if let Some(result) = cache.get_rcpt(catch_all.as_ref()) {
+                    if let Some(result) = cache.get_rcpt(catch_all.as_ref()).await {
                         return Ok(result);
                     }
                 }
@@ -132,7 +200,7 @@ impl Directory {
 
         // Update cache
         if let Some(cache) = &self.cache {
-            cache.set_rcpt(address.as_ref(), false);
+            cache.set_rcpt(address.as_ref(), false).await;
         }
 
         Ok(false)
@@ -147,6 +215,7 @@ impl Directory {
             DirectoryInner::Imap(store) => store.vrfy(address.as_ref()).await,
             DirectoryInner::Smtp(store) => store.vrfy(address.as_ref()).await,
             DirectoryInner::Memory(store) => store.vrfy(address.as_ref()).await,
+            DirectoryInner::Composite(store) => store.vrfy(address.as_ref()).await,
         }
     }
 
@@ -159,6 +228,7 @@ impl Directory {
             DirectoryInner::Imap(store) => store.expn(address.as_ref()).await,
             DirectoryInner::Smtp(store) => store.expn(address.as_ref()).await,
             DirectoryInner::Memory(store) => store.expn(address.as_ref()).await,
+            DirectoryInner::Composite(store) => store.expn(address.as_ref()).await,
         }
     }
 }
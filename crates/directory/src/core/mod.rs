@@ -23,6 +23,7 @@
 
 use crate::{backend::memory::MemoryDirectory, AddressMapping, Directory, DirectoryInner};
 
+pub mod auth_hook;
 pub mod cache;
 pub mod config;
 pub mod dispatch;
@@ -35,6 +36,7 @@ impl Default for Directory {
             catch_all: AddressMapping::Disable,
             subaddressing: AddressMapping::Disable,
             cache: None,
+            auth_hook: None,
         }
     }
 }
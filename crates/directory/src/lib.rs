@@ -21,11 +21,12 @@
  * for more details.
 */
 
-use core::cache::CachedDirectory;
+use core::{auth_hook::AuthHook, cache::CachedDirectory};
 use std::{borrow::Cow, fmt::Debug, sync::Arc};
 
 use ahash::AHashMap;
 use backend::{
+    composite::CompositeDirectory,
     imap::{ImapDirectory, ImapError},
     internal::PrincipalField,
     ldap::LdapDirectory,
@@ -47,6 +48,7 @@ pub struct Directory {
     catch_all: AddressMapping,
     subaddressing: AddressMapping,
     cache: Option<CachedDirectory>,
+    auth_hook: Option<AuthHook>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -96,6 +98,7 @@ pub enum DirectoryError {
     Smtp(mail_send::Error),
     Pool(String),
     Management(ManagementError),
+    Hook(String),
     TimedOut,
     Unsupported,
 }
@@ -117,8 +120,10 @@ pub enum DirectoryInner {
     Imap(ImapDirectory),
     Smtp(SmtpDirectory),
     Memory(MemoryDirectory),
+    Composite(CompositeDirectory),
 }
 
+#[derive(Clone, Copy)]
 pub enum QueryBy<'x> {
     Name(&'x str),
     Id(u32),
@@ -145,6 +150,16 @@ impl Debug for Directory {
     }
 }
 
+impl Directory {
+    // Returns the backing store when accounts are managed internally
+    pub fn internal_store(&self) -> Option<&Store> {
+        match &self.store {
+            DirectoryInner::Internal(store) => Some(store),
+            _ => None,
+        }
+    }
+}
+
 impl Type {
     pub fn to_jmap(&self) -> &'static str {
         match self {
@@ -281,6 +296,18 @@ impl DirectoryError {
         DirectoryError::Unsupported
     }
 
+    pub fn hook(reason: impl Into<String>) -> Self {
+        let reason = reason.into();
+        tracing::warn!(
+            context = "directory",
+            event = "error",
+            protocol = "auth-hook",
+            reason = %reason,
+            "Authentication hook error"
+        );
+        DirectoryError::Hook(reason)
+    }
+
     pub fn timeout(protocol: &str) -> Self {
         tracing::warn!(
             context = "directory",
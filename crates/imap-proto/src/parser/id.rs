@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    protocol::id,
+    receiver::{Request, Token},
+    Command,
+};
+
+impl Request<Command> {
+    pub fn parse_id(self) -> crate::Result<id::Arguments> {
+        let mut tokens = self.tokens.into_iter().peekable();
+        let mut parameters = Vec::new();
+
+        match tokens.next() {
+            // A bare ID command is accepted as if NIL was sent
+            None => (),
+            Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NIL") => (),
+            Some(Token::ParenthesisOpen) => loop {
+                let key = match tokens.next() {
+                    Some(Token::ParenthesisClose) => break,
+                    Some(token @ Token::Argument(_)) => {
+                        token.unwrap_string().map_err(|v| (self.tag.as_str(), v))?
+                    }
+                    _ => {
+                        return Err((self.tag.as_str(), "Invalid ID field name.").into());
+                    }
+                };
+                let value = match tokens.next() {
+                    Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NIL") => None,
+                    Some(Token::Nil) => Some(String::new()),
+                    Some(token @ Token::Argument(_)) => {
+                        Some(token.unwrap_string().map_err(|v| (self.tag.as_str(), v))?)
+                    }
+                    _ => {
+                        return Err((self.tag.as_str(), "Missing ID field value.").into());
+                    }
+                };
+                if parameters.len() < 30 {
+                    parameters.push((key, value));
+                }
+            },
+            _ => {
+                return Err((self.tag.as_str(), "Expected parenthesized list or NIL.").into());
+            }
+        }
+
+        if tokens.peek().is_none() {
+            Ok(id::Arguments {
+                tag: self.tag,
+                parameters,
+            })
+        } else {
+            Err((self.tag, "Too many arguments.").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{protocol::id, receiver::Receiver};
+
+    #[test]
+    fn parse_id() {
+        let mut receiver = Receiver::new();
+
+        for (command, parameters) in [
+            ("a023 ID NIL\r\n", vec![]),
+            ("a023 ID\r\n", vec![]),
+            (
+                "a023 ID (\"name\" \"sodr\" \"version\" \"19.34\" \"vendor\" NIL)\r\n",
+                vec![
+                    ("name".to_string(), Some("sodr".to_string())),
+                    ("version".to_string(), Some("19.34".to_string())),
+                    ("vendor".to_string(), None),
+                ],
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_id()
+                    .unwrap(),
+                id::Arguments {
+                    tag: "a023".to_string(),
+                    parameters,
+                },
+                "{command}"
+            );
+        }
+    }
+}
@@ -32,7 +32,7 @@ use crate::{
 };
 
 impl Request<Command> {
-    pub fn parse_lsub(self) -> crate::Result<list::Arguments> {
+    pub fn parse_lsub(self, version: ProtocolVersion) -> crate::Result<list::Arguments> {
         if self.tokens.len() > 1 {
             let mut tokens = self.tokens.into_iter();
 
@@ -48,7 +48,7 @@ impl Request<Command> {
                         .ok_or((self.tag.as_str(), "Missing mailbox name."))?
                         .unwrap_string()
                         .map_err(|v| (self.tag.as_str(), v))?,
-                    ProtocolVersion::Rev1,
+                    version,
                 )],
                 selection_options: vec![SelectionOption::Subscribed],
                 return_options: vec![],
@@ -63,7 +63,10 @@ impl Request<Command> {
 #[cfg(test)]
 mod tests {
     use crate::{
-        protocol::list::{self, SelectionOption},
+        protocol::{
+            list::{self, SelectionOption},
+            ProtocolVersion,
+        },
         receiver::Receiver,
     };
 
@@ -97,7 +100,7 @@ mod tests {
                 receiver
                     .parse(&mut command.as_bytes().iter())
                     .unwrap()
-                    .parse_lsub()
+                    .parse_lsub(ProtocolVersion::Rev1)
                     .unwrap(),
                 arguments
             );
@@ -29,6 +29,7 @@ pub mod create;
 pub mod delete;
 pub mod enable;
 pub mod fetch;
+pub mod id;
 pub mod list;
 pub mod login;
 pub mod lsub;
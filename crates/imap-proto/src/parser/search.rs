@@ -323,6 +323,29 @@ pub fn parse_filters(
                             .ok_or_else(|| Cow::from("Expected an THREADID value."))?
                             .unwrap_string()?,
                     ));
+                } else if value.eq_ignore_ascii_case(b"SAVEDBEFORE") {
+                    filters.push(Filter::SavedBefore(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDON") {
+                    filters.push(Filter::SavedOn(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDSINCE") {
+                    filters.push(Filter::SavedSince(parse_date(
+                        &tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Expected date"))?
+                            .unwrap_bytes(),
+                    )?));
+                } else if value.eq_ignore_ascii_case(b"SAVEDATESUPPORTED") {
+                    filters.push(Filter::SaveDateSupported);
                 } else if value.eq_ignore_ascii_case(b"OR") {
                     if filters_stack.len() > 10 {
                         return Err(Cow::from("Too many nested filters"));
@@ -484,6 +507,23 @@ mod tests {
                     sort: None,
                 },
             ),
+            (
+                b"A284 SEARCH SAVEDATESUPPORTED SAVEDSINCE 1-Feb-1994 NOT SAVEDON 1-Feb-1994\r\n"
+                    .to_vec(),
+                search::Arguments {
+                    tag: "A284".to_string(),
+                    result_options: vec![],
+                    filter: vec![
+                        Filter::SaveDateSupported,
+                        Filter::SavedSince(760060800),
+                        Filter::Not,
+                        Filter::SavedOn(760060800),
+                        Filter::End,
+                    ],
+                    is_esearch: true,
+                    sort: None,
+                },
+            ),
             (
                 b"A301 SEARCH $ SMALLER 4096\r\n".to_vec(),
                 search::Arguments {
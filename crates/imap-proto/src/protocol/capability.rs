@@ -58,10 +58,12 @@ pub enum Capability {
     Move,
     CondStore,
     QResync,
-    LiteralPlus, //LITERAL+
+    LiteralPlus,  //LITERAL+
+    LiteralMinus, //LITERAL-
     UnAuthenticate,
     StatusSize, //STATUS=SIZE
     ObjectId,
+    SaveDate,
     Preview,
     Utf8Accept,
     Auth(Mechanism),
@@ -82,9 +84,11 @@ impl Capability {
             Capability::CondStore => b"CONDSTORE",
             Capability::QResync => b"QRESYNC",
             Capability::LiteralPlus => b"LITERAL+",
+            Capability::LiteralMinus => b"LITERAL-",
             Capability::UnAuthenticate => b"UNAUTHENTICATE",
             Capability::StatusSize => b"STATUS=SIZE",
             Capability::ObjectId => b"OBJECTID",
+            Capability::SaveDate => b"SAVEDATE",
             Capability::Preview => b"PREVIEW",
             Capability::Idle => b"IDLE",
             Capability::Namespace => b"NAMESPACE",
@@ -112,13 +116,21 @@ impl Capability {
         });
     }
 
-    pub fn all_capabilities(is_authenticated: bool, is_tls: bool) -> Vec<Capability> {
+    pub fn all_capabilities(
+        is_authenticated: bool,
+        is_tls: bool,
+        literal_plus: bool,
+    ) -> Vec<Capability> {
         let mut capabilties = vec![
             Capability::IMAP4rev2,
             Capability::IMAP4rev1,
             Capability::Enable,
             Capability::SASLIR,
-            Capability::LiteralPlus,
+            if literal_plus {
+                Capability::LiteralPlus
+            } else {
+                Capability::LiteralMinus
+            },
             Capability::Id,
             Capability::Utf8Accept,
         ];
@@ -149,6 +161,7 @@ impl Capability {
                 Capability::UnAuthenticate,
                 Capability::StatusSize,
                 Capability::ObjectId,
+                Capability::SaveDate,
                 Capability::Preview,
             ]);
         } else {
@@ -79,13 +79,18 @@ pub enum State {
     LiteralData { remaining: u32 },
 }
 
+// Maximum size of a non-synchronizing literal when LITERAL- is advertised (RFC 7888)
+pub const LITERAL_MINUS_MAX_SIZE: usize = 4096;
+
 pub struct Receiver<T: CommandParser> {
     buf: Vec<u8>,
     pub request: Request<T>,
     pub state: State,
     pub max_request_size: usize,
+    pub max_non_sync_literal: usize,
     pub current_request_size: usize,
     pub start_state: State,
+    non_sync_exceeded: bool,
 }
 
 impl<T: CommandParser> Receiver<T> {
@@ -109,6 +114,11 @@ impl<T: CommandParser> Receiver<T> {
         }
     }
 
+    pub fn with_max_non_sync_literal(mut self, max_non_sync_literal: usize) -> Self {
+        self.max_non_sync_literal = max_non_sync_literal;
+        self
+    }
+
     pub fn error_reset(&mut self, message: impl Into<Cow<'static, str>>) -> Error {
         let request = std::mem::take(&mut self.request);
         let err = Error::err(
@@ -122,6 +132,7 @@ impl<T: CommandParser> Receiver<T> {
         self.buf = Vec::with_capacity(10);
         self.state = self.start_state;
         self.current_request_size = 0;
+        self.non_sync_exceeded = false;
         err
     }
 
@@ -274,6 +285,12 @@ impl<T: CommandParser> Receiver<T> {
                     }
                     b'\n' => {
                         self.push_argument(false)?;
+                        if self.non_sync_exceeded {
+                            return Err(self.error_reset(format!(
+                                "Non-synchronizing literals are limited to {} bytes.",
+                                self.max_non_sync_literal
+                            )));
+                        }
                         self.state = self.start_state;
                         self.current_request_size = 0;
                         return Ok(std::mem::take(&mut self.request));
@@ -337,6 +354,11 @@ impl<T: CommandParser> Receiver<T> {
                                         self.max_request_size
                                     )));
                                 }
+                                // Oversized non-synchronizing literals are read and the
+                                // command is rejected once it has been fully received
+                                if non_sync && size as usize > self.max_non_sync_literal {
+                                    self.non_sync_exceeded = true;
+                                }
                                 self.state = State::LiteralSeek { size, non_sync };
                                 self.buf = Vec::with_capacity(size as usize);
                             } else {
@@ -499,7 +521,9 @@ impl<T: CommandParser> Default for Receiver<T> {
             state: State::Start,
             start_state: State::Start,
             max_request_size: 25 * 1024 * 1024,
+            max_non_sync_literal: usize::MAX,
             current_request_size: 0,
+            non_sync_exceeded: false,
         }
     }
 }
@@ -1086,4 +1110,43 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn receiver_parse_literal_minus() {
+        let mut receiver =
+            Receiver::<Command>::new().with_max_non_sync_literal(super::LITERAL_MINUS_MAX_SIZE);
+        let large = "a".repeat(super::LITERAL_MINUS_MAX_SIZE + 1);
+
+        // Oversized non-synchronizing literals are consumed and the command is rejected
+        let frame = format!(
+            "A001 LOGIN {{{}+}}\r\n{} secret\r\nA002 LOGIN {{5+}}\r\nhello world\r\n",
+            large.len(),
+            large
+        );
+        let mut bytes = frame.as_bytes().iter();
+        match receiver.parse(&mut bytes) {
+            Err(Error::Error { response }) => {
+                assert_eq!(response.tag.as_deref(), Some("A001"));
+            }
+            result => panic!("Expected error, got: {:?}", result),
+        }
+        assert_eq!(
+            receiver.parse(&mut bytes).unwrap(),
+            Request {
+                tag: "A002".to_string(),
+                command: Command::Login,
+                tokens: vec![
+                    Token::Argument(b"hello".to_vec()),
+                    Token::Argument(b"world".to_vec()),
+                ],
+            }
+        );
+
+        // Synchronizing literals are not affected by the limit
+        let frame = format!("A003 LOGIN {{{}}}\r\n", large.len());
+        match receiver.parse(&mut frame.as_bytes().iter()) {
+            Err(Error::NeedsLiteral { size }) => assert_eq!(size as usize, large.len()),
+            result => panic!("Expected literal request, got: {:?}", result),
+        }
+    }
 }
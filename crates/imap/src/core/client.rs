@@ -27,7 +27,7 @@ use imap_proto::{
     receiver::{self, Request},
     Command, ResponseCode, StatusResponse,
 };
-use jmap::auth::rate_limit::AuthenticatedLimiter;
+use jmap::auth::{rate_limit::AuthenticatedLimiter, sessions::SessionRevoke};
 use parking_lot::Mutex;
 use tokio::io::AsyncRead;
 use utils::listener::limiter::{ConcurrencyLimiter, RateLimiter};
@@ -368,6 +368,15 @@ impl State {
         }
     }
 
+    pub fn revoke(&self) -> Option<Arc<SessionRevoke>> {
+        match self {
+            State::Authenticated { data } | State::Selected { data, .. } => {
+                data.live.revoke.clone().into()
+            }
+            _ => None,
+        }
+    }
+
     pub fn is_authenticated(&self) -> bool {
         matches!(self, State::Authenticated { .. } | State::Selected { .. })
     }
@@ -4,7 +4,7 @@ use ahash::AHashMap;
 use directory::QueryBy;
 use imap_proto::{protocol::list::Attribute, StatusResponse};
 use jmap::{
-    auth::{acl::EffectiveAcl, AccessToken},
+    auth::{acl::EffectiveAcl, sessions::LiveSessionGuard, AccessToken},
     mailbox::INBOX_ID,
 };
 use jmap_proto::{
@@ -33,6 +33,13 @@ impl SessionData {
             mailboxes: Mutex::new(vec![]),
             state: access_token.state().into(),
             in_flight,
+            live: LiveSessionGuard::new(
+                session.jmap.clone(),
+                access_token.primary_id(),
+                "IMAP",
+                &session.remote_addr,
+                session.client.as_deref().unwrap_or_default(),
+            ),
         };
 
         // Fetch mailboxes for the main account
@@ -37,6 +37,7 @@ use imap_proto::{
 use jmap::{
     auth::{
         rate_limit::{AuthenticatedLimiter, RemoteAddress},
+        sessions::LiveSessionGuard,
         AccessToken,
     },
     JMAP,
@@ -76,10 +77,13 @@ pub struct IMAP {
     pub name_shared: String,
     pub allow_plain_auth: bool,
     pub enable_uidplus: bool,
+    pub literal_plus: bool,
+    pub store_batch_size: usize,
 
     pub timeout_auth: Duration,
     pub timeout_unauth: Duration,
     pub timeout_idle: Duration,
+    pub timeout_idle_shutdown: Duration,
 
     pub greeting_plain: Vec<u8>,
     pub greeting_tls: Vec<u8>,
@@ -99,6 +103,8 @@ pub struct Session<T: AsyncRead> {
     pub is_tls: bool,
     pub is_condstore: bool,
     pub is_qresync: bool,
+    pub is_utf8: bool,
+    pub client: Option<String>,
     pub writer: mpsc::Sender<writer::Event>,
     pub stream_rx: ReadHalf<T>,
     pub in_flight: InFlight,
@@ -115,6 +121,7 @@ pub struct SessionData {
     pub writer: mpsc::Sender<writer::Event>,
     pub state: AtomicU32,
     pub in_flight: InFlight,
+    pub live: LiveSessionGuard,
 }
 
 #[derive(Debug, Default)]
@@ -209,6 +216,18 @@ pub enum State {
     },
 }
 
+impl<T: AsyncRead> Session<T> {
+    // Mailbox names are exchanged in UTF-8 under IMAP4rev2 or once UTF8=ACCEPT
+    // has been enabled, otherwise they are encoded as modified UTF-7.
+    pub fn mailbox_name_version(&self) -> ProtocolVersion {
+        if self.is_utf8 {
+            ProtocolVersion::Rev2
+        } else {
+            self.version
+        }
+    }
+}
+
 impl SessionData {
     pub async fn get_access_token(&self) -> crate::op::Result<Arc<AccessToken>> {
         self.jmap
@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use imap_proto::{protocol::ProtocolVersion, receiver::Receiver};
+use imap_proto::{
+    protocol::ProtocolVersion,
+    receiver::{Receiver, LITERAL_MINUS_MAX_SIZE},
+};
 use jmap::auth::rate_limit::RemoteAddress;
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
@@ -59,6 +62,8 @@ impl<T: AsyncRead> Session<T> {
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
         loop {
+            let revoke = self.state.revoke().unwrap_or_default();
+
             tokio::select! {
                 result = tokio::time::timeout(
                     if !matches!(self.state, State::NotAuthenticated {..}) {
@@ -101,6 +106,11 @@ impl<T: AsyncRead> Session<T> {
                     tracing::debug!(parent: &self.span, event = "shutdown", "IMAP server shutting down.");
                     break;
                 }
+                _ = revoke.revoked() => {
+                    self.write_bytes(&b"* BYE Session revoked.\r\n"[..]).await.ok();
+                    tracing::debug!(parent: &self.span, event = "revoked", "IMAP session revoked.");
+                    break;
+                }
             };
         }
 
@@ -124,13 +134,20 @@ impl Session<TcpStream> {
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
 
         Ok(Session {
-            receiver: Receiver::with_max_request_size(manager.imap.max_request_size),
+            receiver: Receiver::with_max_request_size(manager.imap.max_request_size)
+                .with_max_non_sync_literal(if manager.imap.literal_plus {
+                    usize::MAX
+                } else {
+                    LITERAL_MINUS_MAX_SIZE
+                }),
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             writer: writer::spawn_writer(writer::Event::Stream(stream_tx), session.span.clone()),
             is_tls: false,
             is_condstore: false,
             is_qresync: false,
+            is_utf8: false,
+            client: None,
             imap: manager.imap,
             jmap: manager.jmap,
             instance: session.instance,
@@ -181,6 +198,8 @@ impl Session<TcpStream> {
             is_tls: true,
             is_condstore: self.is_condstore,
             is_qresync: self.is_qresync,
+            is_utf8: self.is_utf8,
+            client: self.client,
             writer: self.writer,
             span: self.span,
             in_flight: self.in_flight,
@@ -213,13 +232,20 @@ impl Session<TlsStream<TcpStream>> {
         let (stream_rx, stream_tx) = tokio::io::split(stream);
 
         Ok(Session {
-            receiver: Receiver::with_max_request_size(manager.imap.max_request_size),
+            receiver: Receiver::with_max_request_size(manager.imap.max_request_size)
+                .with_max_non_sync_literal(if manager.imap.literal_plus {
+                    usize::MAX
+                } else {
+                    LITERAL_MINUS_MAX_SIZE
+                }),
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             writer: writer::spawn_writer(writer::Event::StreamTls(stream_tx), span.clone()),
             is_tls: true,
             is_condstore: false,
             is_qresync: false,
+            is_utf8: false,
+            client: None,
             imap: manager.imap,
             jmap: manager.jmap,
             instance: session.instance,
@@ -40,6 +40,9 @@ static SERVER_GREETING: &str = concat!(
 
 impl IMAP {
     pub async fn init(config: &Config) -> utils::config::Result<Arc<Self>> {
+        // Non-synchronizing literals are limited in size when LITERAL+ is disabled
+        let literal_plus = config.property_or_static("imap.protocol.literal-plus", "true")?;
+
         Ok(Arc::new(IMAP {
             max_request_size: config.property_or_static("imap.request.max-size", "52428800")?,
             max_auth_failures: config.property_or_static("imap.auth.max-failures", "3")?,
@@ -50,14 +53,15 @@ impl IMAP {
             timeout_auth: config.property_or_static("imap.timeout.authenticated", "30m")?,
             timeout_unauth: config.property_or_static("imap.timeout.anonymous", "1m")?,
             timeout_idle: config.property_or_static("imap.timeout.idle", "30m")?,
+            timeout_idle_shutdown: config.property_or_static("imap.timeout.idle-shutdown", "5s")?,
             greeting_plain: StatusResponse::ok(SERVER_GREETING)
                 .with_code(ResponseCode::Capability {
-                    capabilities: Capability::all_capabilities(false, false),
+                    capabilities: Capability::all_capabilities(false, false, literal_plus),
                 })
                 .into_bytes(),
             greeting_tls: StatusResponse::ok(SERVER_GREETING)
                 .with_code(ResponseCode::Capability {
-                    capabilities: Capability::all_capabilities(false, true),
+                    capabilities: Capability::all_capabilities(false, true, literal_plus),
                 })
                 .into_bytes(),
             rate_limiter: DashMap::with_capacity_and_hasher_and_shard_amount(
@@ -74,6 +78,11 @@ impl IMAP {
             rate_concurrent: config.property("imap.rate-limit.concurrent")?.unwrap_or(4),
             allow_plain_auth: config.property_or_static("imap.auth.allow-plain-text", "false")?,
             enable_uidplus: config.property_or_static("imap.protocol.uidplus", "true")?,
+            literal_plus,
+            store_batch_size: config
+                .property::<usize>("imap.store.batch-size")?
+                .unwrap_or(500)
+                .max(1),
         }))
     }
 }
@@ -56,10 +56,10 @@ use crate::core::{MailboxId, Session, SessionData};
 
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_get_acl(&mut self, request: Request<Command>) -> crate::OpResult {
-        match request.parse_acl(self.version) {
+        match request.parse_acl(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
-                let is_rev2 = self.version.is_rev2();
+                let is_rev2 = self.mailbox_name_version().is_rev2();
 
                 tokio::spawn(async move {
                     match data.get_acl_mailbox(&arguments, true).await {
@@ -151,10 +151,10 @@ impl<T: AsyncRead> Session<T> {
     }
 
     pub async fn handle_my_rights(&mut self, request: Request<Command>) -> crate::OpResult {
-        match request.parse_acl(self.version) {
+        match request.parse_acl(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
-                let is_rev2 = self.version.is_rev2();
+                let is_rev2 = self.mailbox_name_version().is_rev2();
 
                 tokio::spawn(async move {
                     match data.get_acl_mailbox(&arguments, false).await {
@@ -227,7 +227,7 @@ impl<T: AsyncRead> Session<T> {
 
     pub async fn handle_set_acl(&mut self, request: Request<Command>) -> crate::OpResult {
         let command = request.command;
-        match request.parse_acl(self.version) {
+        match request.parse_acl(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
 
@@ -416,7 +416,7 @@ impl<T: AsyncRead> Session<T> {
     }
 
     pub async fn handle_list_rights(&mut self, request: Request<Command>) -> crate::OpResult {
-        match request.parse_acl(self.version) {
+        match request.parse_acl(self.mailbox_name_version()) {
             Ok(arguments) => {
                 self.write_bytes(
                     StatusResponse::completed(Command::ListRights)
@@ -437,7 +437,7 @@ impl<T: AsyncRead> Session<T> {
                                     vec![Rights::Administer],
                                 ],
                             }
-                            .into_bytes(self.version.is_rev2()),
+                            .into_bytes(self.mailbox_name_version().is_rev2()),
                         ),
                 )
                 .await
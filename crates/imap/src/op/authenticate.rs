@@ -100,7 +100,12 @@ impl<T: AsyncRead> Session<T> {
         tag: String,
     ) -> crate::Result<()> {
         // Throttle authentication requests
-        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
+        if self
+            .jmap
+            .is_auth_allowed_soft(&self.remote_addr)
+            .await
+            .is_err()
+        {
             self.write_bytes(
                 StatusResponse::bye("Too many authentication requests from this IP address.")
                     .into_bytes(),
@@ -117,7 +122,7 @@ impl<T: AsyncRead> Session<T> {
         let access_token = match credentials {
             Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
                 self.jmap
-                    .authenticate_plain(&username, &secret, &self.remote_addr)
+                    .authenticate_plain(&username, &secret, &self.remote_addr, "IMAP")
                     .await
             }
             Credentials::OAuthBearer { token } => {
@@ -126,7 +131,7 @@ impl<T: AsyncRead> Session<T> {
                     .validate_access_token("access_token", &token)
                     .await
                 {
-                    Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
+                    Ok(token) => self.jmap.get_access_token(token.account_id).await,
                     Err(err) => {
                         tracing::debug!(
                             parent: &self.span,
@@ -147,8 +152,18 @@ impl<T: AsyncRead> Session<T> {
                 .get_authenticated_limiter(access_token.primary_id())
                 .lock()
                 .concurrent_requests
-                .is_allowed();
+                .is_allowed()
+                .and_then(|mut in_flight| {
+                    // Connections shared with other protocols
+                    in_flight.link(self.instance.connections.is_account_allowed(
+                        access_token.primary_id(),
+                        self.instance.max_connections_account,
+                    )?);
+                    Some(in_flight)
+                });
             if let Some(in_flight) = in_flight {
+                self.span.record("account", access_token.name.as_str());
+
                 // Cache access token
                 let access_token = Arc::new(access_token);
                 self.jmap.cache_access_token(access_token.clone());
@@ -160,7 +175,11 @@ impl<T: AsyncRead> Session<T> {
                 self.write_bytes(
                     StatusResponse::ok("Authentication successful")
                         .with_code(ResponseCode::Capability {
-                            capabilities: Capability::all_capabilities(true, self.is_tls),
+                            capabilities: Capability::all_capabilities(
+                                true,
+                                self.is_tls,
+                                self.imap.literal_plus,
+                            ),
                         })
                         .with_tag(tag)
                         .into_bytes(),
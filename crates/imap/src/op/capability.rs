@@ -32,7 +32,7 @@ use imap_proto::{
 
 use tokio::io::AsyncRead;
 
-use crate::core::Session;
+use crate::core::{Session, State};
 
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_capability(&mut self, request: Request<Command>) -> crate::OpResult {
@@ -44,6 +44,7 @@ impl<T: AsyncRead> Session<T> {
                         capabilities: Capability::all_capabilities(
                             self.state.is_authenticated(),
                             self.is_tls,
+                            self.imap.literal_plus,
                         ),
                     }
                     .serialize(),
@@ -53,9 +54,34 @@ impl<T: AsyncRead> Session<T> {
     }
 
     pub async fn handle_id(&mut self, request: Request<Command>) -> crate::OpResult {
+        let arguments = match request.parse_id() {
+            Ok(arguments) => arguments,
+            Err(response) => return self.write_bytes(response.into_bytes()).await,
+        };
+
+        // Keep track of the first client name advertised in this session
+        if let Some(name) = arguments.get("name").filter(|_| self.client.is_none()) {
+            let version = arguments.get("version").unwrap_or_default();
+            tracing::debug!(parent: &self.span,
+                event = "id",
+                context = "imap",
+                client = name,
+                version = version,
+                "Client identified.");
+
+            let client = format!("{name} {version}").trim().to_string();
+            if let State::Authenticated { data } | State::Selected { data, .. } = &self.state {
+                if let Some(mut session) = self.jmap.live_sessions.get_mut(&data.live.id) {
+                    session.client = client.clone();
+                }
+            }
+            self.jmap.client_usage_record("IMAP", name, version).await;
+            self.client = client.into();
+        }
+
         self.write_bytes(
             StatusResponse::completed(Command::Id)
-                .with_tag(request.tag)
+                .with_tag(arguments.tag)
                 .serialize(
                     concat!(
                         "* ID (\"name\" \"Stalwart IMAP\" \"version\" \"",
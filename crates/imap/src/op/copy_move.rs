@@ -28,12 +28,18 @@ use imap_proto::{
     StatusResponse,
 };
 
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::set::TagManager,
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox,
+    },
+};
 use jmap_proto::{
     error::{method::MethodError, set::SetErrorType},
     types::{
-        acl::Acl, collection::Collection, id::Id, property::Property, state::StateChange,
-        type_state::DataType,
+        acl::Acl, collection::Collection, id::Id, keyword::Keyword, property::Property,
+        state::StateChange, type_state::DataType,
     },
 };
 use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, F_VALUE};
@@ -48,7 +54,7 @@ impl<T: AsyncRead> Session<T> {
         is_move: bool,
         is_uid: bool,
     ) -> crate::OpResult {
-        match request.parse_copy_move(self.version) {
+        match request.parse_copy_move(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let (data, src_mailbox) = self.state.mailbox_state();
 
@@ -210,10 +216,25 @@ impl SessionData {
                     continue;
                 }
 
+                // Obtain keywords and size to update the mailbox counters
+                let (keywords, size) = if let Some(result) = self
+                    .get_counter_tags(account_id, id)
+                    .await
+                    .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
+                {
+                    result
+                } else {
+                    continue;
+                };
+                let unread = is_unread(&keywords.inner);
+                let mut counters = MailboxCounters::new();
+
                 // Add destination folder
                 mailboxes.update(dest_mailbox_id, true);
+                counters.add_message(dest_mailbox_id.mailbox_id, unread, size);
                 if is_move {
                     mailboxes.update(UidMailbox::from(src_mailbox.id.mailbox_id), false);
+                    counters.remove_message(src_mailbox.id.mailbox_id, unread, size);
                 }
 
                 // Write changes
@@ -221,7 +242,8 @@ impl SessionData {
                 batch
                     .with_account_id(account_id)
                     .with_collection(Collection::Email)
-                    .update_document(id);
+                    .update_document(id)
+                    .assert_value(Property::Keywords, &keywords);
                 mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 if changelog.change_id == u64::MAX {
                     changelog.change_id =
@@ -229,7 +251,9 @@ impl SessionData {
                             StatusResponse::database_failure().with_tag(&arguments.tag)
                         })?
                 }
-                batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                batch
+                    .value(Property::Cid, changelog.change_id, F_VALUE)
+                    .custom(counters);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
@@ -314,10 +338,11 @@ impl SessionData {
                     if !mailboxes.current().contains(&src_mailbox_id) {
                         continue;
                     } else if mailboxes.current().len() == 1 {
-                        // Delete message if it is no longer in any mailbox
+                        // Delete message if it is no longer in any mailbox, moved
+                        // messages are not kept as recoverable items
                         if let Ok(changes) = self
                             .jmap
-                            .email_delete(src_account_id, id)
+                            .email_delete_with_retention(src_account_id, id, None)
                             .await
                             .map_err(|_| {
                                 StatusResponse::database_failure().with_tag(&arguments.tag)
@@ -327,12 +352,31 @@ impl SessionData {
                             changelog.merge(changes);
                         }
                     } else {
+                        // Obtain keywords and size to update the mailbox counters
+                        let (keywords, size) = if let Some(result) = self
+                            .get_counter_tags(src_account_id, id)
+                            .await
+                            .map_err(|_| {
+                                StatusResponse::database_failure().with_tag(&arguments.tag)
+                            })? {
+                            result
+                        } else {
+                            continue;
+                        };
+                        let mut counters = MailboxCounters::new();
+                        counters.remove_message(
+                            src_mailbox_id.mailbox_id,
+                            is_unread(&keywords.inner),
+                            size,
+                        );
+
                         // Remove mailbox tag from message
                         let mut batch = BatchBuilder::new();
                         batch
                             .with_account_id(src_account_id)
                             .with_collection(Collection::Email)
-                            .update_document(id);
+                            .update_document(id)
+                            .assert_value(Property::Keywords, &keywords);
                         mailboxes.update(src_mailbox_id, false);
                         mailboxes.update_batch(&mut batch, Property::MailboxIds);
                         if changelog.change_id == u64::MAX {
@@ -344,7 +388,9 @@ impl SessionData {
                                 StatusResponse::database_failure().with_tag(&arguments.tag)
                             })?
                         }
-                        batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                        batch
+                            .value(Property::Cid, changelog.change_id, F_VALUE)
+                            .custom(counters);
                         match self.jmap.write_batch(batch).await {
                             Ok(_) => {
                                 changelog
@@ -495,4 +541,34 @@ impl SessionData {
             Ok(None)
         }
     }
+
+    pub async fn get_counter_tags(
+        &self,
+        account_id: u32,
+        id: u32,
+    ) -> Result<Option<(HashedValue<Vec<Keyword>>, u64)>, MethodError> {
+        // Obtain the keywords and size used by the mailbox counters
+        if let Some(keywords) = self
+            .jmap
+            .get_property::<HashedValue<Vec<Keyword>>>(
+                account_id,
+                Collection::Email,
+                id,
+                Property::Keywords,
+            )
+            .await?
+        {
+            Ok(Some((
+                keywords,
+                self.jmap.email_size(account_id, id).await?,
+            )))
+        } else {
+            tracing::debug!(
+                account_id = account_id,
+                document_id = id,
+                "Message not found"
+            );
+            Ok(None)
+        }
+    }
 }
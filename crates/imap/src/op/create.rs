@@ -44,7 +44,7 @@ impl<T: AsyncRead> Session<T> {
         let mut arguments = Vec::with_capacity(requests.len());
 
         for request in requests {
-            match request.parse_create(self.version) {
+            match request.parse_create(self.mailbox_name_version()) {
                 Ok(argument) => {
                     arguments.push(argument);
                 }
@@ -148,7 +148,7 @@ impl SessionData {
         // Build response
         StatusResponse::ok("Mailbox created.")
             .with_code(ResponseCode::MailboxId {
-                mailbox_id: Id::from_parts(params.account_id, parent_id - 1).to_string(),
+                mailbox_id: Id::from(parent_id - 1).to_string(),
             })
             .with_tag(arguments.tag)
     }
@@ -33,7 +33,7 @@ impl<T: AsyncRead> Session<T> {
         let mut arguments = Vec::with_capacity(requests.len());
 
         for request in requests {
-            match request.parse_delete(self.version) {
+            match request.parse_delete(self.mailbox_name_version()) {
                 Ok(argument) => {
                     arguments.push(argument);
                 }
@@ -49,7 +49,9 @@ impl<T: AsyncRead> Session<T> {
                         Capability::QResync => {
                             self.is_qresync = true;
                         }
-                        Capability::Utf8Accept => {}
+                        Capability::Utf8Accept => {
+                            self.is_utf8 = true;
+                        }
                         _ => {
                             let mut buf = Vec::with_capacity(10);
                             capability.serialize(&mut buf);
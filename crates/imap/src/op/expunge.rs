@@ -30,7 +30,13 @@ use imap_proto::{
     Command, ResponseCode, StatusResponse,
 };
 
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::set::TagManager,
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox,
+    },
+};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -194,6 +200,12 @@ impl SessionData {
                 // Untag message from this mailbox and remove Deleted flag
                 mailboxes.update(mailbox_id, false);
                 keywords.update(Keyword::Deleted, false);
+                let mut counters = MailboxCounters::new();
+                counters.remove_message(
+                    mailbox_id.mailbox_id,
+                    is_unread(keywords.current()),
+                    self.jmap.email_size(account_id, id).await?,
+                );
 
                 // Write changes
                 let mut batch = BatchBuilder::new();
@@ -206,7 +218,9 @@ impl SessionData {
                 if changelog.change_id == u64::MAX {
                     changelog.change_id = self.jmap.assign_change_id(account_id).await?
                 }
-                batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                batch
+                    .value(Property::Cid, changelog.change_id, F_VALUE)
+                    .custom(counters);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, ops::Range, sync::Arc};
 
 use ahash::AHashMap;
 use imap_proto::{
@@ -37,7 +37,13 @@ use imap_proto::{
     receiver::Request,
     Command, ResponseCode, StatusResponse,
 };
-use jmap::{email::metadata::MessageMetadata, Bincode};
+use jmap::{
+    email::metadata::{
+        MessageMetadata, MessageMetadataContents, MessagePartStats, MetadataPartType, PartStats,
+    },
+    mailbox::{counters::MailboxCounters, UidMailbox},
+    Bincode,
+};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -45,7 +51,7 @@ use jmap_proto::{
         state::StateChange, type_state::DataType,
     },
 };
-use mail_parser::{Address, GetHeader, HeaderName, Message, PartType};
+use mail_parser::{Address, Encoding, GetHeader, HeaderName, Message, PartType};
 use store::{
     query::log::{Change, Query},
     write::{assert::HashedValue, BatchBuilder, F_BITMAP, F_VALUE},
@@ -219,14 +225,14 @@ impl SessionData {
         let mut set_seen_flags = false;
         let mut needs_thread_id = false;
         let mut needs_blobs = false;
+        let mut needs_body_stats = false;
 
         for attribute in &arguments.attributes {
             match attribute {
-                Attribute::Envelope
-                | Attribute::Rfc822Header
-                | Attribute::Body
-                | Attribute::BodyStructure
-                | Attribute::BinarySize { .. } => {
+                Attribute::Body | Attribute::BodyStructure => {
+                    needs_body_stats = true;
+                }
+                Attribute::Envelope | Attribute::Rfc822Header | Attribute::BinarySize { .. } => {
                     /*
                         Note that this did not result in \Seen being set, because
                         RFC822.HEADER response data occurs as a result of a FETCH
@@ -248,7 +254,7 @@ impl SessionData {
                     }
                     needs_blobs = true;
                 }
-                Attribute::ThreadId => {
+                Attribute::ThreadId | Attribute::EmailId => {
                     needs_thread_id = true;
                 }
                 _ => (),
@@ -309,10 +315,34 @@ impl SessionData {
                 continue;
             };
 
+            // Obtain part statistics, the blob is only retrieved when these are
+            // not available (for example, messages stored by older versions)
+            let mut body_stats = if needs_body_stats {
+                self.jmap
+                    .get_property::<Bincode<MessagePartStats>>(
+                        account_id,
+                        Collection::Email,
+                        id,
+                        &Property::BodyStats,
+                    )
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|stats| stats.inner)
+            } else {
+                None
+            };
+
             // Fetch and parse blob
-            let raw_message = if needs_blobs {
-                // Retrieve raw message if needed
-                match self.jmap.get_blob(&email.blob_hash, 0..u32::MAX).await {
+            let raw_message = if needs_blobs || (needs_body_stats && body_stats.is_none()) {
+                // Retrieve raw message if needed, header and partial fetches only
+                // read the leading bytes of the blob that contain the requested sections
+                let range = if needs_body_stats && body_stats.is_none() {
+                    0..u32::MAX
+                } else {
+                    blob_range(&email.contents, &arguments.attributes)
+                };
+                match self.jmap.get_blob(&email.blob_hash, range).await {
                     Ok(Some(raw_message)) => raw_message.into(),
                     Ok(None) => {
                         tracing::warn!(event = "not-found",
@@ -330,9 +360,32 @@ impl SessionData {
             } else {
                 None
             };
-            let message = email
-                .contents
-                .into_message(raw_message.as_deref().unwrap_or_default());
+            let message = if let Some(raw_message) = raw_message.as_deref() {
+                email.contents.into_message(raw_message)
+            } else {
+                email.contents.into_message_structure()
+            };
+
+            // Cache part statistics
+            if needs_body_stats && body_stats.is_none() {
+                let stats = Bincode::new(MessagePartStats::new(&message));
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email)
+                    .update_document(id)
+                    .value(Property::BodyStats, &stats, F_VALUE);
+                if let Err(err) = self.jmap.write_batch(batch).await {
+                    tracing::debug!(
+                        event = "error",
+                        context = "fetch",
+                        account_id = account_id,
+                        document_id = id,
+                        error = ?err,
+                        "Failed to store message part statistics");
+                }
+                body_stats = stats.inner.into();
+            }
 
             // Build response
             let mut items = Vec::with_capacity(arguments.attributes.len());
@@ -426,12 +479,18 @@ impl SessionData {
                     }
                     Attribute::Body => {
                         items.push(DataItem::Body {
-                            part: message.body_structure(false),
+                            part: message.body_structure_with_stats(
+                                false,
+                                &body_stats.as_ref().unwrap().parts,
+                            ),
                         });
                     }
                     Attribute::BodyStructure => {
                         items.push(DataItem::BodyStructure {
-                            part: message.body_structure(true),
+                            part: message.body_structure_with_stats(
+                                true,
+                                &body_stats.as_ref().unwrap().parts,
+                            ),
                         });
                     }
                     Attribute::BodySection {
@@ -493,13 +552,32 @@ impl SessionData {
                         }
                     }
                     Attribute::EmailId => {
+                        // Same as the JMAP id so clients can correlate both protocols
                         items.push(DataItem::EmailId {
-                            email_id: Id::from_parts(account_id, id).to_string(),
+                            email_id: Id::from_parts(thread_id, id).to_string(),
                         });
                     }
                     Attribute::ThreadId => {
                         items.push(DataItem::ThreadId {
-                            thread_id: Id::from_parts(account_id, thread_id).to_string(),
+                            thread_id: Id::from(thread_id).to_string(),
+                        });
+                    }
+                    Attribute::SaveDate => {
+                        // Messages are saved once per account, copies to other
+                        // mailboxes of the same account keep the original save date.
+                        items.push(DataItem::SaveDate {
+                            date: self
+                                .jmap
+                                .get_property::<u64>(
+                                    account_id,
+                                    Collection::Email,
+                                    id,
+                                    Property::CreatedAt,
+                                )
+                                .await
+                                .ok()
+                                .flatten()
+                                .map(|date| date as i64),
                         });
                     }
                 }
@@ -538,6 +616,28 @@ impl SessionData {
                 }
             };
             for (id, mut keywords) in set_seen_ids {
+                // Obtain mailboxes to update their unread counters
+                let mailboxes = match self
+                    .jmap
+                    .get_property::<HashedValue<Vec<UidMailbox>>>(
+                        account_id,
+                        Collection::Email,
+                        id.document_id(),
+                        Property::MailboxIds,
+                    )
+                    .await
+                {
+                    Ok(Some(mailboxes)) => mailboxes,
+                    Ok(None) => continue,
+                    Err(_) => {
+                        return StatusResponse::database_failure().with_tag(arguments.tag);
+                    }
+                };
+                let mut counters = MailboxCounters::new();
+                for mailbox_id in &mailboxes.inner {
+                    counters.update_unread(mailbox_id.mailbox_id, false);
+                }
+
                 keywords.inner.push(Keyword::Seen);
                 let mut batch = BatchBuilder::new();
                 batch
@@ -545,9 +645,11 @@ impl SessionData {
                     .with_collection(Collection::Email)
                     .update_document(id.document_id())
                     .assert_value(Property::Keywords, &keywords)
+                    .assert_value(Property::MailboxIds, &mailboxes)
                     .value(Property::Keywords, keywords.inner, F_VALUE)
                     .value(Property::Keywords, Keyword::Seen, F_BITMAP)
-                    .value(Property::Cid, changelog.change_id, F_VALUE);
+                    .value(Property::Cid, changelog.change_id, F_VALUE)
+                    .custom(counters);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, id);
@@ -592,6 +694,7 @@ impl SessionData {
 #[allow(clippy::result_unit_err)]
 pub trait AsImapDataItem<'x> {
     fn body_structure(&self, is_extended: bool) -> BodyPart;
+    fn body_structure_with_stats(&self, is_extended: bool, stats: &[PartStats]) -> BodyPart;
     fn body_section<'z: 'x>(
         &'z self,
         sections: &[Section],
@@ -603,12 +706,17 @@ pub trait AsImapDataItem<'x> {
         partial: Option<(u32, u32)>,
     ) -> Result<Option<BodyContents>, ()>;
     fn binary_size(&self, sections: &[u32]) -> Option<usize>;
-    fn as_body_part(&self, part_id: usize, is_extended: bool) -> BodyPart;
+    fn as_body_part(&self, part_id: usize, is_extended: bool, stats: PartStats) -> BodyPart;
     fn envelope(&self) -> Envelope;
 }
 
 impl<'x> AsImapDataItem<'x> for Message<'x> {
     fn body_structure(&self, is_extended: bool) -> BodyPart {
+        self.body_structure_with_stats(is_extended, &MessagePartStats::new(self).parts)
+    }
+
+    fn body_structure_with_stats(&self, is_extended: bool, stats: &[PartStats]) -> BodyPart {
+        let mut stats = stats.iter();
         let mut stack = Vec::new();
         let mut parts = [0].iter();
         let mut message = self;
@@ -616,7 +724,11 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
 
         loop {
             while let Some(part_id) = parts.next() {
-                let mut part = message.as_body_part(*part_id, is_extended);
+                let mut part = message.as_body_part(
+                    *part_id,
+                    is_extended,
+                    stats.next().copied().unwrap_or_default(),
+                );
 
                 match &message.parts[*part_id].body {
                     PartType::Message(nested_message) => {
@@ -661,9 +773,8 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
         root_part.unwrap()
     }
 
-    fn as_body_part(&self, part_id: usize, is_extended: bool) -> BodyPart {
+    fn as_body_part(&self, part_id: usize, is_extended: bool, stats: PartStats) -> BodyPart {
         let part = &self.parts[part_id];
-        let body = self.raw_message.get(part.offset_body..part.offset_end);
         let (is_multipart, is_text) = match &part.body {
             PartType::Text(_) | PartType::Html(_) => (false, true),
             PartType::Multipart(_) => (true, false),
@@ -708,7 +819,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
                 .header_value(&HeaderName::ContentTransferEncoding)
                 .and_then(|ct| ct.as_text().map(|ct| ct.into()));
 
-            fields.body_size_octets = body.as_ref().map(|b| b.len()).unwrap_or(0);
+            fields.body_size_octets = stats.size;
 
             if is_text {
                 if fields.body_subtype.is_none() {
@@ -725,9 +836,9 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
 
         if is_extended {
             if !is_multipart {
-                body_md5 = body
-                    .as_ref()
-                    .map(|b| format!("{:x}", md5::compute(b)).into());
+                body_md5 = stats
+                    .md5
+                    .map(|md5| format!("{:x}", md5::Digest(md5)).into());
             }
 
             extension.body_disposition = part
@@ -784,10 +895,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
                 if is_text {
                     BodyPart::Text {
                         fields,
-                        body_size_lines: body
-                            .as_ref()
-                            .map(|b| b.iter().filter(|&&ch| ch == b'\n').count())
-                            .unwrap_or(0),
+                        body_size_lines: stats.lines,
                         body_md5,
                         extension,
                     }
@@ -814,7 +922,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
         if sections.is_empty() {
             return Some(
                 get_partial_bytes(
-                    self.raw_message.get(part.offset_header..part.offset_end)?,
+                    get_range(&self.raw_message, part.offset_header..part.offset_end)?,
                     partial,
                 )
                 .into(),
@@ -856,9 +964,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
                 Section::Header => {
                     return Some(
                         get_partial_bytes(
-                            message
-                                .raw_message
-                                .get(part.offset_header..part.offset_body)?,
+                            get_range(&message.raw_message, part.offset_header..part.offset_body)?,
                             partial,
                         )
                         .into(),
@@ -892,7 +998,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
                 Section::Text => {
                     return Some(
                         get_partial_bytes(
-                            message.raw_message.get(part.offset_body..part.offset_end)?,
+                            get_range(&message.raw_message, part.offset_body..part.offset_end)?,
                             partial,
                         )
                         .into(),
@@ -930,7 +1036,7 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
 
         Some(
             get_partial_bytes(
-                message.raw_message.get(part.offset_body..part.offset_end)?,
+                get_range(&message.raw_message, part.offset_body..part.offset_end)?,
                 partial,
             )
             .into(),
@@ -1086,12 +1192,113 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
     }
 }
 
+// Messages retrieved using a blob range are truncated after the last requested
+// section, so the end offset is clamped to the available bytes.
+fn get_range(bytes: &[u8], range: Range<usize>) -> Option<&[u8]> {
+    bytes.get(range.start..std::cmp::min(range.end, bytes.len()))
+}
+
+/// Returns the range of the message blob required to serve the requested
+/// attributes, or the entire blob when it cannot be determined.
+fn blob_range(contents: &MessageMetadataContents, attributes: &[Attribute]) -> Range<u32> {
+    let mut blob_end = 0;
+
+    for attribute in attributes {
+        let attribute_end = match attribute {
+            Attribute::Envelope | Attribute::Rfc822Header => Some(contents.root_part().offset_body),
+            Attribute::BodySection {
+                sections, partial, ..
+            } => section_end(contents, sections, *partial),
+            Attribute::Rfc822
+            | Attribute::Rfc822Text
+            | Attribute::Binary { .. }
+            | Attribute::BinarySize { .. } => None,
+            _ => Some(0),
+        };
+
+        if let Some(attribute_end) = attribute_end {
+            blob_end = std::cmp::max(blob_end, attribute_end);
+        } else {
+            return 0..u32::MAX;
+        }
+    }
+
+    0..u32::try_from(blob_end).unwrap_or(u32::MAX)
+}
+
+fn section_end(
+    contents: &MessageMetadataContents,
+    sections: &[Section],
+    partial: Option<(u32, u32)>,
+) -> Option<usize> {
+    let partial_end = |from: usize, to: usize| {
+        if let Some((start, count)) = partial {
+            std::cmp::min(
+                from.saturating_add(start as usize)
+                    .saturating_add(count as usize),
+                to,
+            )
+        } else {
+            to
+        }
+    };
+    let mut contents = contents;
+    let mut part = contents.root_part();
+    if sections.is_empty() {
+        return Some(partial_end(part.offset_header, part.offset_end));
+    }
+
+    let mut sections_iter = sections.iter().enumerate().peekable();
+    while let Some((section_num, section)) = sections_iter.next() {
+        match section {
+            Section::Part { num } => {
+                part = match &part.body {
+                    MetadataPartType::Multipart(sub_part_ids) => sub_part_ids
+                        .get((*num).saturating_sub(1) as usize)
+                        .and_then(|pos| contents.parts.get(*pos)),
+                    MetadataPartType::Message(_) if *num == 1 => Some(part),
+                    _ if *num == 1 && section_num == sections.len() - 1 => Some(part),
+                    _ => None,
+                }?;
+
+                if let (
+                    MetadataPartType::Message(nested_message),
+                    Some((
+                        _,
+                        Section::Part { .. }
+                        | Section::Header
+                        | Section::HeaderFields { .. }
+                        | Section::Text,
+                    )),
+                ) = (&part.body, sections_iter.peek())
+                {
+                    // Offsets of encoded nested messages are relative to the decoded part
+                    if !matches!(part.encoding, Encoding::None) {
+                        return None;
+                    }
+                    contents = nested_message;
+                    part = contents.root_part();
+                }
+            }
+            Section::Header | Section::HeaderFields { .. } | Section::Mime => {
+                return Some(part.offset_body);
+            }
+            Section::Text => {
+                return Some(partial_end(part.offset_body, part.offset_end));
+            }
+        }
+    }
+
+    Some(partial_end(part.offset_body, part.offset_end))
+}
+
 #[inline(always)]
 fn get_partial_bytes(bytes: &[u8], partial: Option<(u32, u32)>) -> &[u8] {
     if let Some((start, end)) = partial {
-        if let Some(bytes) =
-            bytes.get(start as usize..std::cmp::min((start + end) as usize, bytes.len()))
-        {
+        if let Some(bytes) = bytes.get(
+            start as usize
+                ..std::cmp::min((start as usize).saturating_add(end as usize), bytes.len()),
+        ) {
             bytes
         } else {
             &[]
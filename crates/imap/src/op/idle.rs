@@ -37,7 +37,10 @@ use imap_proto::{
 
 use jmap_proto::types::{collection::Collection, type_state::DataType};
 use store::query::log::Query;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::broadcast::error::{RecvError, TryRecvError},
+};
 use utils::map::bitmap::Bitmap;
 
 use crate::core::{SelectedMailbox, Session, SessionData, State};
@@ -56,31 +59,31 @@ impl<T: AsyncRead> Session<T> {
             _ => unreachable!(),
         };
         let is_rev2 = self.version.is_rev2();
+        let is_utf8 = self.is_utf8;
         let is_qresync = self.is_qresync;
 
-        // Register with state manager
-        let mut change_rx = if let Some(change_rx) = self
-            .jmap
-            .subscribe_state_manager(data.account_id, data.account_id, types)
-            .await
-        {
-            change_rx
-        } else {
-            return self
-                .write_bytes(
-                    StatusResponse::no("It was not possible to start IDLE.")
-                        .with_tag(request.tag)
-                        .with_code(ResponseCode::ContactAdmin)
-                        .into_bytes(),
-                )
-                .await;
-        };
+        // Register with the account's shared state broadcaster
+        let mut change_rx =
+            if let Some(change_rx) = self.jmap.subscribe_state_broadcaster(data.account_id).await {
+                change_rx
+            } else {
+                return self
+                    .write_bytes(
+                        StatusResponse::no("It was not possible to start IDLE.")
+                            .with_tag(request.tag)
+                            .with_code(ResponseCode::ContactAdmin)
+                            .into_bytes(),
+                    )
+                    .await;
+            };
 
         // Send continuation response
         self.write_bytes(b"+ Idling, send 'DONE' to stop.\r\n".to_vec())
             .await?;
         tracing::debug!(parent: &self.span, event = "stat", context = "idle", "Starting IDLE.");
         let mut buf = vec![0; 1024];
+        let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut shutdown_at = None;
         loop {
             tokio::select! {
                 result = tokio::time::timeout(self.imap.timeout_idle, self.stream_rx.read(&mut buf)) => {
@@ -110,31 +113,66 @@ impl<T: AsyncRead> Session<T> {
                     }
                 }
                 state_change = change_rx.recv() => {
-                    if let Some(state_change) = state_change {
-                        let mut has_mailbox_changes = false;
-                        let mut has_email_changes = false;
+                    let mut has_mailbox_changes = false;
+                    let mut has_email_changes = false;
+                    let mut state_change = state_change;
 
-                        for (type_state, _) in state_change.types {
-                            match type_state {
-                                DataType::Email | DataType::EmailDelivery => {
-                                    has_email_changes = true;
+                    // Coalesce any queued changes into a single update
+                    loop {
+                        match state_change {
+                            Ok(state_change) => {
+                                for (type_state, _) in state_change.types {
+                                    if types.contains(type_state) {
+                                        match type_state {
+                                            DataType::Email | DataType::EmailDelivery => {
+                                                has_email_changes = true;
+                                            }
+                                            DataType::Mailbox => {
+                                                has_mailbox_changes = true;
+                                            }
+                                            _ => {}
+                                        }
+                                    }
                                 }
-                                DataType::Mailbox => {
-                                    has_mailbox_changes = true;
-                                }
-                                _ => {}
+                            }
+                            Err(RecvError::Lagged(_)) => {
+                                // Some changes were missed, resynchronize everything
+                                has_mailbox_changes = true;
+                                has_email_changes = mailbox.is_some();
+                            }
+                            Err(RecvError::Closed) => {
+                                self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
+                                tracing::debug!(parent: &self.span, "IDLE channel closed.");
+                                return Err(());
                             }
                         }
 
-                        if has_mailbox_changes || has_email_changes {
-                            data.write_changes(&mailbox, has_mailbox_changes, has_email_changes, is_qresync, is_rev2).await;
-                        }
-                    } else {
-                        self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
-                        tracing::debug!(parent: &self.span, "IDLE channel closed.");
-                        return Err(());
+                        state_change = match change_rx.try_recv() {
+                            Ok(state_change) => Ok(state_change),
+                            Err(TryRecvError::Lagged(skipped)) => Err(RecvError::Lagged(skipped)),
+                            Err(TryRecvError::Closed) => Err(RecvError::Closed),
+                            Err(TryRecvError::Empty) => break,
+                        };
+                    }
+
+                    if has_mailbox_changes || has_email_changes {
+                        data.write_changes(&mailbox, has_mailbox_changes, has_email_changes, is_qresync, is_rev2, is_utf8).await;
                     }
                 }
+                _ = data.live.revoke.revoked() => {
+                    self.write_bytes(&b"* BYE Session revoked.\r\n"[..]).await.ok();
+                    tracing::debug!(parent: &self.span, event = "revoked", "IMAP session revoked.");
+                    return Err(());
+                }
+                _ = shutdown_rx.changed(), if shutdown_at.is_none() => {
+                    // Keep sending pending changes during the grace period
+                    shutdown_at = Some(tokio::time::Instant::now() + self.imap.timeout_idle_shutdown);
+                }
+                _ = tokio::time::sleep_until(shutdown_at.unwrap_or_else(tokio::time::Instant::now)), if shutdown_at.is_some() => {
+                    self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
+                    tracing::debug!(parent: &self.span, event = "shutdown", "IMAP server shutting down.");
+                    return Err(());
+                }
             }
         }
     }
@@ -148,12 +186,14 @@ impl SessionData {
         check_emails: bool,
         is_qresync: bool,
         is_rev2: bool,
+        is_utf8: bool,
     ) {
         // Fetch all changed mailboxes
         if check_mailboxes {
             match self.synchronize_mailboxes(true).await {
                 Ok(Some(changes)) => {
                     let mut buf = Vec::with_capacity(64);
+                    let is_utf8 = is_rev2 || is_utf8;
 
                     // List deleted mailboxes
                     for mailbox_name in changes.deleted {
@@ -162,7 +202,7 @@ impl SessionData {
                             attributes: vec![Attribute::NonExistent],
                             tags: vec![],
                         }
-                        .serialize(&mut buf, is_rev2, false);
+                        .serialize(&mut buf, is_utf8, false);
                     }
 
                     // List added mailboxes
@@ -172,7 +212,7 @@ impl SessionData {
                             attributes: vec![],
                             tags: vec![],
                         }
-                        .serialize(&mut buf, is_rev2, false);
+                        .serialize(&mut buf, is_utf8, false);
                     }
                     // Obtain status of changed mailboxes
                     for mailbox_name in changes.changed {
@@ -188,7 +228,7 @@ impl SessionData {
                             )
                             .await
                         {
-                            status.serialize(&mut buf, is_rev2);
+                            status.serialize(&mut buf, is_utf8);
                         }
                     }
 
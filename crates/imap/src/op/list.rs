@@ -41,16 +41,17 @@ impl<T: AsyncRead> Session<T> {
         let command = request.command;
         let is_lsub = command == Command::Lsub;
         match if !is_lsub {
-            request.parse_list(self.version)
+            request.parse_list(self.mailbox_name_version())
         } else {
-            request.parse_lsub()
+            request.parse_lsub(self.mailbox_name_version())
         } {
             Ok(arguments) => {
                 if !arguments.is_separator_query() {
                     let data = self.state.session_data();
                     let version = self.version;
+                    let is_utf8 = self.is_utf8;
                     tokio::spawn(async move {
-                        data.list(arguments, is_lsub, version).await;
+                        data.list(arguments, is_lsub, version, is_utf8).await;
                     });
                     Ok(())
                 } else {
@@ -59,7 +60,7 @@ impl<T: AsyncRead> Session<T> {
                             .with_tag(arguments.unwrap_tag())
                             .serialize(
                                 list::Response {
-                                    is_rev2: self.version.is_rev2(),
+                                    is_rev2: self.mailbox_name_version().is_rev2(),
                                     is_lsub,
                                     list_items: vec![ListItem {
                                         mailbox_name: String::new(),
@@ -80,7 +81,13 @@ impl<T: AsyncRead> Session<T> {
 }
 
 impl SessionData {
-    pub async fn list(&self, arguments: Arguments, is_lsub: bool, version: ProtocolVersion) {
+    pub async fn list(
+        &self,
+        arguments: Arguments,
+        is_lsub: bool,
+        version: ProtocolVersion,
+        is_utf8: bool,
+    ) {
         let (tag, reference_name, mut patterns, selection_options, return_options) = match arguments
         {
             Arguments::Basic {
@@ -281,7 +288,7 @@ impl SessionData {
             .with_tag(tag)
             .serialize(
                 list::Response {
-                    is_rev2: version.is_rev2(),
+                    is_rev2: version.is_rev2() || is_utf8,
                     is_lsub,
                     list_items,
                     status_items,
@@ -31,8 +31,15 @@ impl<T: AsyncRead> Session<T> {
     pub async fn handle_noop(&mut self, request: Request<Command>) -> crate::OpResult {
         match &self.state {
             State::Authenticated { data, .. } => {
-                data.write_changes(&None, true, false, self.is_qresync, self.version.is_rev2())
-                    .await;
+                data.write_changes(
+                    &None,
+                    true,
+                    false,
+                    self.is_qresync,
+                    self.version.is_rev2(),
+                    self.is_utf8,
+                )
+                .await;
             }
             State::Selected { data, mailbox, .. } => {
                 data.write_changes(
@@ -41,6 +48,7 @@ impl<T: AsyncRead> Session<T> {
                     true,
                     self.is_qresync,
                     self.version.is_rev2(),
+                    self.is_utf8,
                 )
                 .await;
             }
@@ -42,7 +42,7 @@ use crate::core::{Session, SessionData};
 
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_rename(&mut self, request: Request<Command>) -> crate::OpResult {
-        match request.parse_rename(self.version) {
+        match request.parse_rename(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
                 tokio::spawn(async move {
@@ -104,6 +104,33 @@ impl SessionData {
             }
         };
 
+        // Validate that the mailbox is not moved into its own subtree and that
+        // its descendants stay within the depth limit
+        let prefix = format!("{}/", arguments.mailbox_name);
+        if params.full_path.starts_with(&prefix) {
+            return StatusResponse::no("Cannot move a mailbox under itself.")
+                .with_tag(arguments.tag)
+                .with_code(ResponseCode::Cannot);
+        }
+        let subtree_height = self
+            .mailboxes
+            .lock()
+            .iter()
+            .find(|account| account.account_id == params.account_id)
+            .and_then(|account| {
+                account
+                    .mailbox_names
+                    .keys()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .map(|name| name.split('/').count())
+                    .max()
+            })
+            .unwrap_or(0);
+        if params.full_path.split('/').count() + subtree_height > self.jmap.config.mailbox_max_depth
+        {
+            return StatusResponse::no("Mailbox path is too deep.").with_tag(arguments.tag);
+        }
+
         // Obtain mailbox
         let mailbox = if let Ok(Some(mailbox)) = self
             .jmap
@@ -253,7 +280,6 @@ impl SessionData {
                     parent_mailbox.has_children = true;
                 }
 
-                let prefix = format!("{}/", arguments.mailbox_name);
                 let mut new_mailbox_names = BTreeMap::new();
                 for (mailbox_name, mailbox_id) in std::mem::take(&mut account.mailbox_names) {
                     if mailbox_name != arguments.mailbox_name {
@@ -635,6 +635,25 @@ impl SessionData {
                             )));
                         }
                     }
+                    search::Filter::SavedBefore(date) => {
+                        filters.push(query::Filter::lt(Property::CreatedAt, date as u64));
+                    }
+                    search::Filter::SavedOn(date) => {
+                        filters.push(query::Filter::And);
+                        filters.push(query::Filter::ge(Property::CreatedAt, date as u64));
+                        filters.push(query::Filter::lt(
+                            Property::CreatedAt,
+                            (date + 86400) as u64,
+                        ));
+                        filters.push(query::Filter::End);
+                    }
+                    search::Filter::SavedSince(date) => {
+                        filters.push(query::Filter::ge(Property::CreatedAt, date as u64));
+                    }
+                    search::Filter::SaveDateSupported => {
+                        // Save dates are available in all mailboxes
+                        filters.push(query::Filter::is_in_set(message_ids.clone()));
+                    }
                     search::Filter::ThreadId(id) => {
                         if let Some(id) = Id::from_bytes(id.as_bytes()) {
                             filters.push(query::Filter::is_in_bitmap(
@@ -40,7 +40,7 @@ impl<T: AsyncRead> Session<T> {
     pub async fn handle_select(&mut self, request: Request<Command>) -> crate::OpResult {
         let is_select = request.command == Command::Select;
         let command = request.command;
-        match request.parse_select(self.version) {
+        match request.parse_select(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
 
@@ -124,11 +124,7 @@ impl<T: AsyncRead> Session<T> {
                                 closed_previous,
                                 is_rev2,
                                 highest_modseq,
-                                mailbox_id: Id::from_parts(
-                                    mailbox.id.account_id,
-                                    mailbox.id.mailbox_id,
-                                )
-                                .to_string(),
+                                mailbox_id: Id::from(mailbox.id.mailbox_id).to_string(),
                             };
 
                             // Update state
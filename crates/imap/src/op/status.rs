@@ -45,9 +45,9 @@ use super::ToModSeq;
 
 impl<T: AsyncRead> Session<T> {
     pub async fn handle_status(&mut self, request: Request<Command>) -> crate::OpResult {
-        match request.parse_status(self.version) {
+        match request.parse_status(self.mailbox_name_version()) {
             Ok(arguments) => {
-                let version = self.version;
+                let version = self.mailbox_name_version();
                 let data = self.state.session_data();
                 tokio::spawn(async move {
                     // Refresh mailboxes
@@ -193,10 +193,7 @@ impl SessionData {
                         Status::MailboxId => {
                             items_response.push((
                                 *item,
-                                StatusItemType::String(
-                                    Id::from_parts(mailbox.account_id, mailbox.mailbox_id)
-                                        .to_string(),
-                                ),
+                                StatusItemType::String(Id::from(mailbox.mailbox_id).to_string()),
                             ));
                         }
                         Status::Recent => {
@@ -218,24 +215,48 @@ impl SessionData {
         if !items_update.is_empty() {
             // Retrieve latest values
             let mut values_update = Vec::with_capacity(items_update.len());
-            let mailbox_message_ids = self
-                .jmap
-                .get_tag(
-                    mailbox.account_id,
-                    Collection::Email,
-                    Property::MailboxIds,
-                    mailbox.mailbox_id,
+            let counters = if items_update
+                .iter()
+                .any(|item| matches!(item, Status::Messages | Status::Unseen | Status::Size))
+            {
+                self.jmap
+                    .mailbox_counters(mailbox.account_id, mailbox.mailbox_id)
+                    .await?
+            } else {
+                None
+            };
+            let (mailbox_message_ids, message_ids) = if counters.is_none()
+                || items_update
+                    .iter()
+                    .any(|item| matches!(item, Status::Deleted))
+            {
+                (
+                    self.jmap
+                        .get_tag(
+                            mailbox.account_id,
+                            Collection::Email,
+                            Property::MailboxIds,
+                            mailbox.mailbox_id,
+                        )
+                        .await?
+                        .map(Arc::new),
+                    self.jmap
+                        .get_document_ids(mailbox.account_id, Collection::Email)
+                        .await?,
                 )
-                .await?
-                .map(Arc::new);
-            let message_ids = self
-                .jmap
-                .get_document_ids(mailbox.account_id, Collection::Email)
-                .await?;
+            } else {
+                (None, None)
+            };
 
             for item in items_update {
                 let result = match item {
-                    Status::Messages => mailbox_message_ids.as_ref().map(|v| v.len()).unwrap_or(0),
+                    Status::Messages => {
+                        if let Some(counters) = &counters {
+                            counters.total
+                        } else {
+                            mailbox_message_ids.as_ref().map(|v| v.len()).unwrap_or(0)
+                        }
+                    }
                     Status::UidNext => {
                         (self
                             .jmap
@@ -269,7 +290,9 @@ impl SessionData {
                             StatusResponse::no("Mailbox unavailable.")
                         })?,
                     Status::Unseen => {
-                        if let (Some(message_ids), Some(mailbox_message_ids)) =
+                        if let Some(counters) = &counters {
+                            counters.unread
+                        } else if let (Some(message_ids), Some(mailbox_message_ids)) =
                             (&message_ids, &mailbox_message_ids)
                         {
                             if let Some(mut seen) = self
@@ -311,7 +334,9 @@ impl SessionData {
                         }
                     }
                     Status::Size => {
-                        if let Some(mailbox_message_ids) = &mailbox_message_ids {
+                        if let Some(counters) = &counters {
+                            counters.size
+                        } else if let Some(mailbox_message_ids) = &mailbox_message_ids {
                             self.calculate_mailbox_size(mailbox.account_id, mailbox_message_ids)
                                 .await? as u64
                         } else {
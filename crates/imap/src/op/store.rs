@@ -33,7 +33,13 @@ use imap_proto::{
     receiver::Request,
     Command, ResponseCode, ResponseType, StatusResponse,
 };
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::set::TagManager,
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox,
+    },
+};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -197,106 +203,145 @@ impl SessionData {
             .collect::<Vec<_>>();
         let mut changelog = ChangeLogBuilder::new();
         let mut changed_mailboxes = AHashSet::new();
-        'outer: for (id, imap_id) in ids {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+
+        // Flag changes are coalesced into batches to avoid issuing one write
+        // per message when clients update large numbers of messages at once.
+        for chunk in ids.chunks(self.imap.store_batch_size) {
             let mut try_count = 0;
             loop {
-                // Obtain current keywords
-                let (mut keywords, thread_id) = if let (Some(keywords), Some(thread_id)) = (
-                    self.jmap
-                        .get_property::<HashedValue<Vec<Keyword>>>(
-                            account_id,
-                            Collection::Email,
-                            id,
-                            Property::Keywords,
-                        )
-                        .await
-                        .map_err(|_| {
-                            StatusResponse::database_failure()
-                                .with_tag(response.tag.as_ref().unwrap())
-                        })?,
-                    self.jmap
-                        .get_property::<u32>(account_id, Collection::Email, id, Property::ThreadId)
-                        .await
-                        .map_err(|_| {
-                            StatusResponse::database_failure()
-                                .with_tag(response.tag.as_ref().unwrap())
-                        })?,
-                ) {
-                    (TagManager::new(keywords), thread_id)
-                } else {
-                    continue 'outer;
-                };
+                // Obtain current keywords, mailboxes and thread ids
+                let document_ids = chunk.iter().map(|(id, _)| *id);
+                let current_keywords = self
+                    .jmap
+                    .get_properties::<HashedValue<Vec<Keyword>>>(
+                        account_id,
+                        Collection::Email,
+                        document_ids.clone(),
+                        Property::Keywords,
+                    )
+                    .await
+                    .map_err(|_| {
+                        StatusResponse::database_failure().with_tag(response.tag.as_ref().unwrap())
+                    })?;
+                let current_mailboxes = self
+                    .jmap
+                    .get_properties::<HashedValue<Vec<UidMailbox>>>(
+                        account_id,
+                        Collection::Email,
+                        document_ids.clone(),
+                        Property::MailboxIds,
+                    )
+                    .await
+                    .map_err(|_| {
+                        StatusResponse::database_failure().with_tag(response.tag.as_ref().unwrap())
+                    })?;
+                let thread_ids = self
+                    .jmap
+                    .get_properties::<u32>(
+                        account_id,
+                        Collection::Email,
+                        document_ids,
+                        Property::ThreadId,
+                    )
+                    .await
+                    .map_err(|_| {
+                        StatusResponse::database_failure().with_tag(response.tag.as_ref().unwrap())
+                    })?;
 
                 // Apply changes
-                match arguments.operation {
-                    Operation::Set => {
-                        keywords.set(set_keywords.clone());
-                    }
-                    Operation::Add => {
-                        for keyword in &set_keywords {
-                            keywords.update(keyword.clone(), true);
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email);
+                let mut updated = Vec::with_capacity(chunk.len());
+                let mut counters = MailboxCounters::new();
+                for (((&(id, imap_id), keywords), mailboxes), thread_id) in chunk
+                    .iter()
+                    .zip(current_keywords)
+                    .zip(current_mailboxes)
+                    .zip(thread_ids)
+                {
+                    let (mut keywords, mailboxes, thread_id) =
+                        if let (Some(keywords), Some(mailboxes), Some(thread_id)) =
+                            (keywords, mailboxes, thread_id)
+                        {
+                            (TagManager::new(keywords), mailboxes, thread_id)
+                        } else {
+                            continue;
+                        };
+
+                    match arguments.operation {
+                        Operation::Set => {
+                            keywords.set(set_keywords.clone());
+                        }
+                        Operation::Add => {
+                            for keyword in &set_keywords {
+                                keywords.update(keyword.clone(), true);
+                            }
+                        }
+                        Operation::Clear => {
+                            for keyword in &set_keywords {
+                                keywords.update(keyword.clone(), false);
+                            }
                         }
                     }
-                    Operation::Clear => {
-                        for keyword in &set_keywords {
-                            keywords.update(keyword.clone(), false);
+
+                    if keywords.has_changes() {
+                        // Convert keywords to flags
+                        let seen_changed = keywords
+                            .changed_tags()
+                            .any(|keyword| keyword == &Keyword::Seen);
+                        let flags = if !arguments.is_silent {
+                            keywords
+                                .current()
+                                .iter()
+                                .cloned()
+                                .map(Flag::from)
+                                .collect::<Vec<_>>()
+                        } else {
+                            vec![]
+                        };
+
+                        if changelog.change_id == u64::MAX {
+                            changelog.change_id =
+                                self.jmap.assign_change_id(account_id).await.map_err(|_| {
+                                    StatusResponse::database_failure()
+                                        .with_tag(response.tag.as_ref().unwrap())
+                                })?
                         }
+                        batch.update_document(id);
+                        let seen_mailboxes = if seen_changed {
+                            // Update the unread counters of the message's mailboxes
+                            let unread = is_unread(keywords.current());
+                            for mailbox_id in &mailboxes.inner {
+                                counters.update_unread(mailbox_id.mailbox_id, unread);
+                            }
+                            batch.assert_value(Property::MailboxIds, &mailboxes);
+                            mailboxes.inner
+                        } else {
+                            vec![]
+                        };
+                        keywords.update_batch(&mut batch, Property::Keywords);
+                        batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                        updated.push((id, imap_id, thread_id, seen_mailboxes, flags));
                     }
                 }
 
-                if keywords.has_changes() {
-                    // Convert keywords to flags
-                    let seen_changed = keywords
-                        .changed_tags()
-                        .any(|keyword| keyword == &Keyword::Seen);
-                    let flags = if !arguments.is_silent {
-                        keywords
-                            .current()
-                            .iter()
-                            .cloned()
-                            .map(Flag::from)
-                            .collect::<Vec<_>>()
-                    } else {
-                        vec![]
-                    };
+                if updated.is_empty() {
+                    break;
+                }
+                if !counters.is_empty() {
+                    batch.custom(counters);
+                }
 
-                    // Write changes
-                    let mut batch = BatchBuilder::new();
-                    batch
-                        .with_account_id(account_id)
-                        .with_collection(Collection::Email)
-                        .update_document(id);
-                    keywords.update_batch(&mut batch, Property::Keywords);
-                    if changelog.change_id == u64::MAX {
-                        changelog.change_id =
-                            self.jmap.assign_change_id(account_id).await.map_err(|_| {
-                                StatusResponse::database_failure()
-                                    .with_tag(response.tag.as_ref().unwrap())
-                            })?
-                    }
-                    batch.value(Property::Cid, changelog.change_id, F_VALUE);
-                    match self.jmap.write_batch(batch).await {
-                        Ok(_) => {
+                // Write changes
+                match self.jmap.write_batch(batch).await {
+                    Ok(_) => {
+                        for (id, imap_id, thread_id, seen_mailboxes, flags) in updated {
                             // Set all current mailboxes as changed if the Seen tag changed
-                            if seen_changed {
-                                if let Some(mailboxes) = self
-                                    .jmap
-                                    .get_property::<Vec<UidMailbox>>(
-                                        account_id,
-                                        Collection::Email,
-                                        id,
-                                        Property::MailboxIds,
-                                    )
-                                    .await
-                                    .map_err(|_| {
-                                        StatusResponse::database_failure()
-                                            .with_tag(response.tag.as_ref().unwrap())
-                                    })?
-                                {
-                                    for mailbox_id in mailboxes {
-                                        changed_mailboxes.insert(mailbox_id.mailbox_id);
-                                    }
-                                }
+                            for mailbox_id in seen_mailboxes {
+                                changed_mailboxes.insert(mailbox_id.mailbox_id);
                             }
                             changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
 
@@ -328,20 +373,22 @@ impl SessionData {
                                 });
                             }
                         }
-                        Err(MethodError::ServerUnavailable) => {
-                            if try_count < MAX_RETRIES {
-                                try_count += 1;
-                                continue;
-                            } else {
-                                response.rtype = ResponseType::No;
-                                response.message = "Some messages could not be updated.".into();
-                            }
-                        }
-                        Err(_) => {
-                            return Err(StatusResponse::database_failure()
-                                .with_tag(response.tag.as_ref().unwrap()));
+                    }
+                    Err(MethodError::ServerUnavailable) => {
+                        // Another process modified the keywords of at least one
+                        // message in this batch, reload and try again.
+                        if try_count < MAX_RETRIES {
+                            try_count += 1;
+                            continue;
+                        } else {
+                            response.rtype = ResponseType::No;
+                            response.message = "Some messages could not be updated.".into();
                         }
                     }
+                    Err(_) => {
+                        return Err(StatusResponse::database_failure()
+                            .with_tag(response.tag.as_ref().unwrap()));
+                    }
                 }
                 break;
             }
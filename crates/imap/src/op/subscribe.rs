@@ -42,7 +42,7 @@ impl<T: AsyncRead> Session<T> {
         request: Request<Command>,
         is_subscribe: bool,
     ) -> crate::OpResult {
-        match request.parse_subscribe(self.version) {
+        match request.parse_subscribe(self.mailbox_name_version()) {
             Ok(arguments) => {
                 let data = self.state.session_data();
                 tokio::spawn(async move {
@@ -116,7 +116,14 @@ impl SessionData {
         };
 
         // Subscribe/unsubscribe to mailbox
-        if let Some(value) = mailbox.inner.mailbox_subscribe(account_id, subscribe) {
+        let access_token = match self.get_access_token().await {
+            Ok(access_token) => access_token,
+            Err(response) => return response.with_tag(tag),
+        };
+        if let Some(value) = mailbox
+            .inner
+            .mailbox_subscribe(access_token.primary_id(), subscribe)
+        {
             // Build batch
             let mut changes = match self.jmap.begin_changes(account_id).await {
                 Ok(changes) => changes,
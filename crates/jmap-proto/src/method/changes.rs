@@ -69,6 +69,9 @@ pub enum RequestArguments {
     Identity,
     EmailSubmission,
     Quota,
+    DeliveryStatus,
+    Label,
+    Activity,
 }
 
 impl JsonObjectParser for ChangesRequest {
@@ -84,6 +87,9 @@ impl JsonObjectParser for ChangesRequest {
                 MethodObject::Identity => RequestArguments::Identity,
                 MethodObject::EmailSubmission => RequestArguments::EmailSubmission,
                 MethodObject::Quota => RequestArguments::Quota,
+                MethodObject::DeliveryStatus => RequestArguments::DeliveryStatus,
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::Activity => RequestArguments::Activity,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/changes",
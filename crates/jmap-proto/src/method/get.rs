@@ -53,6 +53,11 @@ pub enum RequestArguments {
     VacationResponse,
     Principal,
     Quota,
+    MaskedEmail,
+    DeliveryStatus,
+    ListFiling,
+    Label,
+    Activity,
     Blob(blob::GetArguments),
 }
 
@@ -89,6 +94,11 @@ impl JsonObjectParser for GetRequest<RequestArguments> {
                 MethodObject::Principal => RequestArguments::Principal,
                 MethodObject::Blob => RequestArguments::Blob(Default::default()),
                 MethodObject::Quota => RequestArguments::Quota,
+                MethodObject::MaskedEmail => RequestArguments::MaskedEmail,
+                MethodObject::DeliveryStatus => RequestArguments::DeliveryStatus,
+                MethodObject::ListFiling => RequestArguments::ListFiling,
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::Activity => RequestArguments::Activity,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/get",
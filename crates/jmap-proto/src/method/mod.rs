@@ -33,6 +33,9 @@ pub mod query;
 pub mod query_changes;
 pub mod search_snippet;
 pub mod set;
+pub mod test;
+pub mod thread;
+pub mod unsubscribe;
 pub mod upload;
 pub mod validate;
 
@@ -71,6 +71,10 @@ pub enum RequestArguments {
     PushSubscription,
     SieveScript(sieve::SetArguments),
     VacationResponse,
+    MaskedEmail,
+    ListFiling,
+    Label,
+    Activity,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize)]
@@ -131,6 +135,10 @@ impl JsonObjectParser for SetRequest<RequestArguments> {
                 MethodObject::PushSubscription => RequestArguments::PushSubscription,
                 MethodObject::VacationResponse => RequestArguments::VacationResponse,
                 MethodObject::SieveScript => RequestArguments::SieveScript(Default::default()),
+                MethodObject::MaskedEmail => RequestArguments::MaskedEmail,
+                MethodObject::ListFiling => RequestArguments::ListFiling,
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::Activity => RequestArguments::Activity,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/set",
@@ -214,13 +222,14 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::ReceivedAt
                     | Property::Expires
                     | Property::FromDate
-                    | Property::ToDate => parser
+                    | Property::ToDate
+                    | Property::LastMessageAt
+                    | Property::CreatedAt => parser
                         .next_token::<UTCDate>()?
                         .unwrap_string_or_null("")?
                         .map(|date| SetValue::Value(Value::Date(date)))
                         .unwrap_or(SetValue::Value(Value::Null)),
                     Property::Subject
-                    | Property::Preview
                     | Property::Name
                     | Property::Description
                     | Property::Timezone
@@ -238,6 +247,13 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::Location
                     | Property::Cid
                     | Property::Role
+                    | Property::State
+                    | Property::ForDomain
+                    | Property::CreatedBy
+                    | Property::EmailPrefix
+                    | Property::MailboxPattern
+                    | Property::Color
+                    | Property::Keyword
                     | Property::PartId => parser
                         .next_token::<String>()?
                         .unwrap_string_or_null("")?
@@ -260,7 +276,8 @@ impl JsonObjectParser for Object<SetValue> {
                     Property::HasAttachment
                     | Property::IsSubscribed
                     | Property::IsEnabled
-                    | Property::IsActive => parser
+                    | Property::IsActive
+                    | Property::Preview => parser
                         .next_token::<String>()?
                         .unwrap_bool_or_null("")?
                         .map(|bool| SetValue::Value(Value::Bool(bool)))
@@ -342,6 +359,7 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::SubParts
                     | Property::To
                     | Property::UndoStatus
+                    | Property::ExcludedListIds
                     | Property::Types => SetValue::Value(Value::parse::<ObjectProperty, String>(
                         parser.next_token()?,
                         parser,
@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+
+use crate::{
+    error::set::SetError,
+    parser::{json::Parser, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::{blob::BlobId, id::Id},
+};
+
+#[derive(Debug, Clone)]
+pub struct TestSieveScriptRequest {
+    pub account_id: Id,
+    pub blob_id: BlobId,
+    pub email_blob_id: BlobId,
+    pub envelope_from: Option<String>,
+    pub envelope_to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestSieveScriptResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+    pub actions: Vec<SieveAction>,
+    pub trace: Vec<String>,
+    pub error: Option<SetError>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum SieveAction {
+    Keep {
+        flags: Vec<String>,
+    },
+    FileInto {
+        mailbox: String,
+        flags: Vec<String>,
+        create: bool,
+    },
+    Discard,
+    Reject {
+        reason: String,
+    },
+    SendMessage {
+        recipients: Vec<String>,
+    },
+    Notify {
+        method: String,
+        message: String,
+    },
+}
+
+impl JsonObjectParser for TestSieveScriptRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = TestSieveScriptRequest {
+            account_id: Id::default(),
+            blob_id: BlobId::default(),
+            email_blob_id: BlobId::default(),
+            envelope_from: None,
+            envelope_to: None,
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x6449_626f_6c62 if !key.is_ref => {
+                    request.blob_id = parser.next_token::<BlobId>()?.unwrap_string("blobId")?;
+                }
+                0x0064_4962_6f6c_426c_6961_6d65 if !key.is_ref => {
+                    request.email_blob_id = parser
+                        .next_token::<BlobId>()?
+                        .unwrap_string("emailBlobId")?;
+                }
+                0x6d6f_7246_6570_6f6c_6576_6e65 if !key.is_ref => {
+                    request.envelope_from = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("envelopeFrom")?;
+                }
+                0x6f54_6570_6f6c_6576_6e65 if !key.is_ref => {
+                    request.envelope_to = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("envelopeTo")?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
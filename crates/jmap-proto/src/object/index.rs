@@ -45,6 +45,14 @@ pub struct ObjectIndexBuilder {
     changes: Option<Object<Value>>,
 }
 
+// Writes the indexes of a stored object again, so that properties added to a
+// schema later on are also indexed for existing objects.
+#[derive(Debug, Clone)]
+pub struct ObjectIndexRefresh {
+    index: &'static [IndexProperty],
+    current: HashedValue<Object<Value>>,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum IndexAs {
     Text {
@@ -177,6 +185,19 @@ impl IntoOperations for ObjectIndexBuilder {
     }
 }
 
+impl ObjectIndexRefresh {
+    pub fn new(index: &'static [IndexProperty], current: HashedValue<Object<Value>>) -> Self {
+        Self { index, current }
+    }
+}
+
+impl IntoOperations for ObjectIndexRefresh {
+    fn build(self, batch: &mut BatchBuilder) {
+        batch.assert_value(Property::Value, &self.current);
+        build_batch(batch, self.index, &self.current.inner, true);
+    }
+}
+
 fn merge_batch(
     batch: &mut BatchBuilder,
     index: &'static [IndexProperty],
@@ -519,6 +540,13 @@ fn build_batch(
                     set,
                 });
             }
+            (Value::Date(date), IndexAs::LongInteger) => {
+                batch.ops.push(Operation::Index {
+                    field: (&item.property).into(),
+                    key: (&(date.timestamp() as u64)).into_index(item.index_as),
+                    set,
+                });
+            }
             (Value::Id(id), IndexAs::Integer | IndexAs::LongInteger) => {
                 batch.ops.push(Operation::Index {
                     field: (&item.property).into(),
@@ -48,6 +48,8 @@ pub enum Capability {
     Blob = 1 << 8,
     #[serde(rename(serialize = "urn:ietf:params:jmap:quota"))]
     Quota = 1 << 9,
+    #[serde(rename(serialize = "https://www.fastmail.com/dev/maskedemail"))]
+    MaskedEmail = 1 << 10,
 }
 
 impl JsonObjectParser for Capability {
@@ -55,7 +57,31 @@ impl JsonObjectParser for Capability {
     where
         Self: Sized,
     {
-        for ch in b"urn:ietf:params:jmap:" {
+        let prefix: &[u8] = match parser
+            .next_unescaped()?
+            .ok_or_else(|| parser.error_capability())?
+        {
+            b'u' => b"rn:ietf:params:jmap:",
+            b'h' => {
+                for ch in b"ttps://www.fastmail.com/dev/maskedemail" {
+                    if parser
+                        .next_unescaped()?
+                        .ok_or_else(|| parser.error_capability())?
+                        != *ch
+                    {
+                        return Err(parser.error_capability());
+                    }
+                }
+                return if parser.next_unescaped()?.is_none() {
+                    Ok(Capability::MaskedEmail)
+                } else {
+                    Err(parser.error_capability())
+                };
+            }
+            _ => return Err(parser.error_capability()),
+        };
+
+        for ch in prefix {
             if parser
                 .next_unescaped()?
                 .ok_or_else(|| parser.error_capability())?
@@ -46,6 +46,11 @@ pub enum MethodObject {
     SieveScript,
     Principal,
     Quota,
+    MaskedEmail,
+    DeliveryStatus,
+    ListFiling,
+    Label,
+    Activity,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,9 +64,13 @@ pub enum MethodFunction {
     Import,
     Parse,
     Validate,
+    Test,
+    Unsubscribe,
     Lookup,
     Upload,
     Echo,
+    Split,
+    Merge,
 }
 
 impl JsonObjectParser for MethodName {
@@ -113,6 +122,11 @@ impl JsonObjectParser for MethodName {
                 0x0074_7069_7263_5365_7665_6953 => MethodObject::SieveScript,
                 0x006c_6170_6963_6e69_7250 => MethodObject::Principal,
                 0x0061_746f_7551 => MethodObject::Quota,
+                0x006c_6961_6d45_6465_6b73_614d => MethodObject::MaskedEmail,
+                0x7375_7461_7453_7972_6576_696c_6544 => MethodObject::DeliveryStatus,
+                0x676e_696c_6946_7473_694c => MethodObject::ListFiling,
+                0x006c_6562_614c => MethodObject::Label,
+                0x7974_6976_6974_6341 => MethodObject::Activity,
                 0x6572_6f43 => MethodObject::Core,
                 _ => return Err(parser.error_value()),
             },
@@ -126,9 +140,13 @@ impl JsonObjectParser for MethodName {
                 0x7472_6f70_6d69 => MethodFunction::Import,
                 0x0065_7372_6170 => MethodFunction::Parse,
                 0x6574_6164_696c_6176 => MethodFunction::Validate,
+                0x7473_6574 => MethodFunction::Test,
+                0x0065_6269_7263_7362_7573_6e75 => MethodFunction::Unsubscribe,
                 0x7075_6b6f_6f6c => MethodFunction::Lookup,
                 0x6461_6f6c_7075 => MethodFunction::Upload,
                 0x6f68_6365 => MethodFunction::Echo,
+                0x0074_696c_7073 => MethodFunction::Split,
+                0x0065_6772_656d => MethodFunction::Merge,
                 _ => return Err(parser.error_value()),
             },
         })
@@ -166,6 +184,8 @@ impl MethodName {
 
             (MethodFunction::Get, MethodObject::Thread) => "Thread/get",
             (MethodFunction::Changes, MethodObject::Thread) => "Thread/changes",
+            (MethodFunction::Split, MethodObject::Thread) => "Thread/split",
+            (MethodFunction::Merge, MethodObject::Thread) => "Thread/merge",
 
             (MethodFunction::Get, MethodObject::Email) => "Email/get",
             (MethodFunction::Changes, MethodObject::Email) => "Email/changes",
@@ -175,6 +195,7 @@ impl MethodName {
             (MethodFunction::Copy, MethodObject::Email) => "Email/copy",
             (MethodFunction::Import, MethodObject::Email) => "Email/import",
             (MethodFunction::Parse, MethodObject::Email) => "Email/parse",
+            (MethodFunction::Unsubscribe, MethodObject::Email) => "Email/unsubscribe",
 
             (MethodFunction::Get, MethodObject::SearchSnippet) => "SearchSnippet/get",
 
@@ -197,6 +218,7 @@ impl MethodName {
             (MethodFunction::Set, MethodObject::SieveScript) => "SieveScript/set",
             (MethodFunction::Query, MethodObject::SieveScript) => "SieveScript/query",
             (MethodFunction::Validate, MethodObject::SieveScript) => "SieveScript/validate",
+            (MethodFunction::Test, MethodObject::SieveScript) => "SieveScript/test",
 
             (MethodFunction::Get, MethodObject::Principal) => "Principal/get",
             (MethodFunction::Set, MethodObject::Principal) => "Principal/set",
@@ -207,6 +229,25 @@ impl MethodName {
             (MethodFunction::Query, MethodObject::Quota) => "Quota/query",
             (MethodFunction::QueryChanges, MethodObject::Quota) => "Quota/queryChanges",
 
+            (MethodFunction::Get, MethodObject::MaskedEmail) => "MaskedEmail/get",
+            (MethodFunction::Set, MethodObject::MaskedEmail) => "MaskedEmail/set",
+
+            (MethodFunction::Get, MethodObject::DeliveryStatus) => "DeliveryStatus/get",
+            (MethodFunction::Changes, MethodObject::DeliveryStatus) => "DeliveryStatus/changes",
+
+            (MethodFunction::Get, MethodObject::ListFiling) => "ListFiling/get",
+            (MethodFunction::Set, MethodObject::ListFiling) => "ListFiling/set",
+
+            (MethodFunction::Get, MethodObject::Label) => "Label/get",
+            (MethodFunction::Set, MethodObject::Label) => "Label/set",
+            (MethodFunction::Changes, MethodObject::Label) => "Label/changes",
+
+            (MethodFunction::Get, MethodObject::Activity) => "Activity/get",
+            (MethodFunction::Set, MethodObject::Activity) => "Activity/set",
+            (MethodFunction::Query, MethodObject::Activity) => "Activity/query",
+            (MethodFunction::QueryChanges, MethodObject::Activity) => "Activity/queryChanges",
+            (MethodFunction::Changes, MethodObject::Activity) => "Activity/changes",
+
             (MethodFunction::Get, MethodObject::Blob) => "Blob/get",
             (MethodFunction::Copy, MethodObject::Blob) => "Blob/copy",
             (MethodFunction::Lookup, MethodObject::Blob) => "Blob/lookup",
@@ -234,6 +275,11 @@ impl Display for MethodObject {
             MethodObject::Thread => "Thread",
             MethodObject::Email => "Email",
             MethodObject::Quota => "Quota",
+            MethodObject::MaskedEmail => "MaskedEmail",
+            MethodObject::DeliveryStatus => "DeliveryStatus",
+            MethodObject::ListFiling => "ListFiling",
+            MethodObject::Label => "Label",
+            MethodObject::Activity => "Activity",
         })
     }
 }
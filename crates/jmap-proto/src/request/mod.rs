@@ -46,6 +46,9 @@ use crate::{
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::{self, SetRequest},
+        test::TestSieveScriptRequest,
+        thread::{MergeThreadRequest, SplitThreadRequest},
+        unsubscribe::UnsubscribeEmailRequest,
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
     },
@@ -84,10 +87,14 @@ pub enum RequestMethod {
     CopyBlob(CopyBlobRequest),
     ImportEmail(ImportEmailRequest),
     ParseEmail(ParseEmailRequest),
+    UnsubscribeEmail(UnsubscribeEmailRequest),
+    SplitThread(SplitThreadRequest),
+    MergeThread(MergeThreadRequest),
     QueryChanges(QueryChangesRequest),
     Query(QueryRequest<query::RequestArguments>),
     SearchSnippet(GetSearchSnippetRequest),
     ValidateScript(ValidateSieveScriptRequest),
+    TestScript(TestSieveScriptRequest),
     LookupBlob(BlobLookupRequest),
     UploadBlob(BlobUploadRequest),
     Echo(Echo),
@@ -39,6 +39,9 @@ use crate::{
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::SetRequest,
+        test::TestSieveScriptRequest,
+        thread::{MergeThreadRequest, SplitThreadRequest},
+        unsubscribe::UnsubscribeEmailRequest,
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
     },
@@ -143,6 +146,11 @@ impl Request {
                                 | MethodObject::SieveScript
                                 | MethodObject::Principal
                                 | MethodObject::Quota
+                                | MethodObject::MaskedEmail
+                                | MethodObject::DeliveryStatus
+                                | MethodObject::ListFiling
+                                | MethodObject::Label
+                                | MethodObject::Activity
                                 | MethodObject::Blob,
                             ) => GetRequest::parse(parser).map(RequestMethod::Get),
                             (MethodFunction::Get, MethodObject::SearchSnippet) => {
@@ -179,10 +187,23 @@ impl Request {
                             (MethodFunction::Parse, MethodObject::Email) => {
                                 ParseEmailRequest::parse(parser).map(RequestMethod::ParseEmail)
                             }
+                            (MethodFunction::Unsubscribe, MethodObject::Email) => {
+                                UnsubscribeEmailRequest::parse(parser)
+                                    .map(RequestMethod::UnsubscribeEmail)
+                            }
+                            (MethodFunction::Split, MethodObject::Thread) => {
+                                SplitThreadRequest::parse(parser).map(RequestMethod::SplitThread)
+                            }
+                            (MethodFunction::Merge, MethodObject::Thread) => {
+                                MergeThreadRequest::parse(parser).map(RequestMethod::MergeThread)
+                            }
                             (MethodFunction::Validate, MethodObject::SieveScript) => {
                                 ValidateSieveScriptRequest::parse(parser)
                                     .map(RequestMethod::ValidateScript)
                             }
+                            (MethodFunction::Test, MethodObject::SieveScript) => {
+                                TestSieveScriptRequest::parse(parser).map(RequestMethod::TestScript)
+                            }
                             (MethodFunction::Echo, MethodObject::Core) => {
                                 Echo::parse(parser).map(RequestMethod::Echo)
                             }
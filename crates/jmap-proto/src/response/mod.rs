@@ -39,6 +39,9 @@ use crate::{
         query_changes::QueryChangesResponse,
         search_snippet::GetSearchSnippetResponse,
         set::SetResponse,
+        test::TestSieveScriptResponse,
+        thread::{MergeThreadResponse, SplitThreadResponse},
+        unsubscribe::UnsubscribeEmailResponse,
         upload::BlobUploadResponse,
         validate::ValidateSieveScriptResponse,
     },
@@ -58,10 +61,14 @@ pub enum ResponseMethod {
     CopyBlob(CopyBlobResponse),
     ImportEmail(ImportEmailResponse),
     ParseEmail(ParseEmailResponse),
+    UnsubscribeEmail(UnsubscribeEmailResponse),
+    SplitThread(SplitThreadResponse),
+    MergeThread(MergeThreadResponse),
     QueryChanges(QueryChangesResponse),
     Query(QueryResponse),
     SearchSnippet(GetSearchSnippetResponse),
     ValidateScript(ValidateSieveScriptResponse),
+    TestScript(TestSieveScriptResponse),
     LookupBlob(BlobLookupResponse),
     UploadBlob(BlobUploadResponse),
     Echo(Echo),
@@ -189,12 +196,36 @@ impl From<GetSearchSnippetResponse> for ResponseMethod {
     }
 }
 
+impl From<UnsubscribeEmailResponse> for ResponseMethod {
+    fn from(unsubscribe_email: UnsubscribeEmailResponse) -> Self {
+        ResponseMethod::UnsubscribeEmail(unsubscribe_email)
+    }
+}
+
+impl From<SplitThreadResponse> for ResponseMethod {
+    fn from(split_thread: SplitThreadResponse) -> Self {
+        ResponseMethod::SplitThread(split_thread)
+    }
+}
+
+impl From<MergeThreadResponse> for ResponseMethod {
+    fn from(merge_thread: MergeThreadResponse) -> Self {
+        ResponseMethod::MergeThread(merge_thread)
+    }
+}
+
 impl From<ValidateSieveScriptResponse> for ResponseMethod {
     fn from(validate_script: ValidateSieveScriptResponse) -> Self {
         ResponseMethod::ValidateScript(validate_script)
     }
 }
 
+impl From<TestSieveScriptResponse> for ResponseMethod {
+    fn from(test_script: TestSieveScriptResponse) -> Self {
+        ResponseMethod::TestScript(test_script)
+    }
+}
+
 impl From<BlobUploadResponse> for ResponseMethod {
     fn from(upload_blob: BlobUploadResponse) -> Self {
         ResponseMethod::UploadBlob(upload_blob)
@@ -38,7 +38,12 @@ pub enum Collection {
     SieveScript = 5,
     PushSubscription = 6,
     Principal = 7,
-    None = 8,
+    MaskedEmail = 8,
+    DeliveryStatus = 9,
+    ListFiling = 10,
+    Label = 11,
+    Activity = 12,
+    None = 13,
 }
 
 impl From<u8> for Collection {
@@ -52,6 +57,11 @@ impl From<u8> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::MaskedEmail,
+            9 => Collection::DeliveryStatus,
+            10 => Collection::ListFiling,
+            11 => Collection::Label,
+            12 => Collection::Activity,
             _ => Collection::None,
         }
     }
@@ -68,6 +78,11 @@ impl From<u64> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::MaskedEmail,
+            9 => Collection::DeliveryStatus,
+            10 => Collection::ListFiling,
+            11 => Collection::Label,
+            12 => Collection::Activity,
             _ => Collection::None,
         }
     }
@@ -97,6 +112,10 @@ impl TryFrom<Collection> for DataType {
             Collection::EmailSubmission => Ok(DataType::EmailSubmission),
             Collection::SieveScript => Ok(DataType::SieveScript),
             Collection::PushSubscription => Ok(DataType::PushSubscription),
+            Collection::MaskedEmail => Ok(DataType::MaskedEmail),
+            Collection::DeliveryStatus => Ok(DataType::DeliveryStatus),
+            Collection::Label => Ok(DataType::Label),
+            Collection::Activity => Ok(DataType::Activity),
             _ => Err(()),
         }
     }
@@ -113,6 +132,11 @@ impl Display for Collection {
             Collection::EmailSubmission => write!(f, "emailSubmission"),
             Collection::SieveScript => write!(f, "sieveScript"),
             Collection::Principal => write!(f, "principal"),
+            Collection::MaskedEmail => write!(f, "maskedEmail"),
+            Collection::DeliveryStatus => write!(f, "deliveryStatus"),
+            Collection::ListFiling => write!(f, "listFiling"),
+            Collection::Label => write!(f, "label"),
+            Collection::Activity => write!(f, "activity"),
             Collection::None => write!(f, ""),
         }
     }
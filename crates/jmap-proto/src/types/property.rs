@@ -136,6 +136,27 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    BodyStats,
+    State,
+    ForDomain,
+    LastMessageAt,
+    CreatedAt,
+    CreatedBy,
+    EmailPrefix,
+    EmailSubmissionId,
+    Status,
+    UpdatedAt,
+    MailboxPattern,
+    ExcludedListIds,
+    Color,
+    Keyword,
+    DeliveryStats,
+    LastSuccessAt,
+    LastFailureAt,
+    LastFailureReason,
+    DeliveredCount,
+    FailedCount,
+    StorageTier,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -366,10 +387,14 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x63 => Property::Cc,
             0x7465_7372_6168 => Property::Charset,
             0x6469 => Property::Cid,
+            0x7441_6465_7461_6572 => Property::CreatedAt,
+            0x7942_6465_7461_6572 => Property::CreatedBy,
+            0x726f_6c6f => Property::Color,
             _ => return None,
         },
         b'd' => match hash {
             0x0073_7574_6174_5379_7265_7669_6c65 => Property::DeliveryStatus,
+            0x7374_6174_5379_7265_7669_6c65 => Property::DeliveryStats,
             0x6e6f_6974_7069_7263_7365 => Property::Description,
             0x0064_4974_6e65_696c_4365_6369_7665 => Property::DeviceClientId,
             0x6e6f_6974_6973_6f70_7369 => Property::Disposition,
@@ -383,11 +408,15 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0073_6449_6c69_616d => Property::EmailIds,
             0x0065_706f_6c65_766e => Property::Envelope,
             0x7365_7269_7078 => Property::Expires,
+            0x7869_6665_7250_6c69_616d => Property::EmailPrefix,
+            0x6449_6e6f_6973_7369_6d62_7553_6c69_616d => Property::EmailSubmissionId,
+            0x7364_4974_7369_4c64_6564_756c_6378 => Property::ExcludedListIds,
             _ => return None,
         },
         b'f' => match hash {
             0x006d_6f72 => Property::From,
             0x0065_7461_446d_6f72 => Property::FromDate,
+            0x6e69_616d_6f44_726f => Property::ForDomain,
             _ => return None,
         },
         b'h' => match hash {
@@ -409,11 +438,13 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
         b'k' => match hash {
             0x0073_7965 => Property::Keys,
             0x0073_6472_6f77_7965 => Property::Keywords,
+            0x6472_6f77_7965 => Property::Keyword,
             _ => return None,
         },
         b'l' => match hash {
             0x0065_6761_7567_6e61 => Property::Language,
             0x006e_6f69_7461_636f => Property::Location,
+            0x7441_6567_6173_7365_4d74_7361 => Property::LastMessageAt,
             _ => return None,
         },
         b'm' => match hash {
@@ -423,6 +454,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x7372_6562_6d65 => Property::Members,
             0x6449_6567_6173_7365 => Property::MessageId,
             0x0073_7468_6769_5279 => Property::MyRights,
+            0x006e_7265_7474_6150_786f_626c_6961 => Property::MailboxPattern,
             _ => return None,
         },
         b'n' => match hash {
@@ -456,6 +488,9 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x7265_6472_4f74_726f => Property::SortOrder,
             0x7463_656a_6275 => Property::Subject,
             0x7374_7261_5062_7573 => Property::SubParts,
+            0x6574_6174 => Property::State,
+            0x0073_7574_6174 => Property::Status,
+            0x7265_6954_6567_6172_6f74 => Property::StorageTier,
             _ => return None,
         },
         b't' => match hash {
@@ -476,6 +511,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0073_6c69_616d_4564_6165_726e => Property::UnreadEmails,
             0x7364_6165_7268_5464_6165_726e => Property::UnreadThreads,
             0x6c72 => Property::Url,
+            0x7441_6465_7461_6470 => Property::UpdatedAt,
             _ => return None,
         },
         b'v' => match hash {
@@ -898,6 +934,27 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::BodyStats => write!(f, "bodyStats"),
+            Property::State => write!(f, "state"),
+            Property::ForDomain => write!(f, "forDomain"),
+            Property::LastMessageAt => write!(f, "lastMessageAt"),
+            Property::CreatedAt => write!(f, "createdAt"),
+            Property::CreatedBy => write!(f, "createdBy"),
+            Property::EmailPrefix => write!(f, "emailPrefix"),
+            Property::EmailSubmissionId => write!(f, "emailSubmissionId"),
+            Property::Status => write!(f, "status"),
+            Property::UpdatedAt => write!(f, "updatedAt"),
+            Property::MailboxPattern => write!(f, "mailboxPattern"),
+            Property::ExcludedListIds => write!(f, "excludedListIds"),
+            Property::Color => write!(f, "color"),
+            Property::Keyword => write!(f, "keyword"),
+            Property::DeliveryStats => write!(f, "deliveryStats"),
+            Property::LastSuccessAt => write!(f, "lastSuccessAt"),
+            Property::LastFailureAt => write!(f, "lastFailureAt"),
+            Property::LastFailureReason => write!(f, "lastFailureReason"),
+            Property::DeliveredCount => write!(f, "deliveredCount"),
+            Property::FailedCount => write!(f, "failedCount"),
+            Property::StorageTier => write!(f, "storageTier"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -1080,6 +1137,27 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::BodyStats => 104,
+            Property::State => 105,
+            Property::ForDomain => 106,
+            Property::LastMessageAt => 107,
+            Property::CreatedAt => 108,
+            Property::CreatedBy => 109,
+            Property::EmailPrefix => 110,
+            Property::EmailSubmissionId => 111,
+            Property::Status => 112,
+            Property::UpdatedAt => 113,
+            Property::MailboxPattern => 114,
+            Property::ExcludedListIds => 115,
+            Property::Color => 116,
+            Property::Keyword => 117,
+            Property::DeliveryStats => 118,
+            Property::LastSuccessAt => 119,
+            Property::LastFailureAt => 120,
+            Property::LastFailureReason => 121,
+            Property::DeliveredCount => 122,
+            Property::FailedCount => 123,
+            Property::StorageTier => 124,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1222,6 +1300,27 @@ impl SerializeInto for Property {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::BodyStats => 104,
+            Property::State => 105,
+            Property::ForDomain => 106,
+            Property::LastMessageAt => 107,
+            Property::CreatedAt => 108,
+            Property::CreatedBy => 109,
+            Property::EmailPrefix => 110,
+            Property::EmailSubmissionId => 111,
+            Property::Status => 112,
+            Property::UpdatedAt => 113,
+            Property::MailboxPattern => 114,
+            Property::ExcludedListIds => 115,
+            Property::Color => 116,
+            Property::Keyword => 117,
+            Property::DeliveryStats => 118,
+            Property::LastSuccessAt => 119,
+            Property::LastFailureAt => 120,
+            Property::LastFailureReason => 121,
+            Property::DeliveredCount => 122,
+            Property::FailedCount => 123,
+            Property::StorageTier => 124,
             Property::Digest(_) | Property::Data(_) => {
                 unreachable!("Property::Digest and Property::Data are not serializable")
             }
@@ -1340,6 +1439,27 @@ impl DeserializeFrom for Property {
             101 => Some(Property::WarnLimit),
             102 => Some(Property::SoftLimit),
             103 => Some(Property::Scope),
+            104 => Some(Property::BodyStats),
+            105 => Some(Property::State),
+            106 => Some(Property::ForDomain),
+            107 => Some(Property::LastMessageAt),
+            108 => Some(Property::CreatedAt),
+            109 => Some(Property::CreatedBy),
+            110 => Some(Property::EmailPrefix),
+            111 => Some(Property::EmailSubmissionId),
+            112 => Some(Property::Status),
+            113 => Some(Property::UpdatedAt),
+            114 => Some(Property::MailboxPattern),
+            115 => Some(Property::ExcludedListIds),
+            116 => Some(Property::Color),
+            117 => Some(Property::Keyword),
+            118 => Some(Property::DeliveryStats),
+            119 => Some(Property::LastSuccessAt),
+            120 => Some(Property::LastFailureAt),
+            121 => Some(Property::LastFailureReason),
+            122 => Some(Property::DeliveredCount),
+            123 => Some(Property::FailedCount),
+            124 => Some(Property::StorageTier),
             _ => None,
         }
     }
@@ -58,7 +58,15 @@ pub enum DataType {
     Quota = 11,
     #[serde(rename = "SieveScript")]
     SieveScript = 12,
-    None = 13,
+    #[serde(rename = "MaskedEmail")]
+    MaskedEmail = 13,
+    #[serde(rename = "DeliveryStatus")]
+    DeliveryStatus = 14,
+    #[serde(rename = "Label")]
+    Label = 15,
+    #[serde(rename = "Activity")]
+    Activity = 16,
+    None = 17,
 }
 
 impl BitmapItem for DataType {
@@ -87,6 +95,10 @@ impl From<u64> for DataType {
             10 => DataType::Mdn,
             11 => DataType::Quota,
             12 => DataType::SieveScript,
+            13 => DataType::MaskedEmail,
+            14 => DataType::DeliveryStatus,
+            15 => DataType::Label,
+            16 => DataType::Activity,
             _ => {
                 debug_assert!(false, "Invalid type_state value: {}", value);
                 DataType::None
@@ -132,6 +144,10 @@ impl JsonObjectParser for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006c_6961_6d45_6465_6b73_614d => Ok(DataType::MaskedEmail),
+            0x7375_7461_7453_7972_6576_696c_6544 => Ok(DataType::DeliveryStatus),
+            0x006c_6562_614c => Ok(DataType::Label),
+            0x7974_6976_6974_6341 => Ok(DataType::Activity),
             _ => Err(parser.error_value()),
         }
     }
@@ -167,6 +183,10 @@ impl TryFrom<&str> for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006c_6961_6d45_6465_6b73_614d => Ok(DataType::MaskedEmail),
+            0x7375_7461_7453_7972_6576_696c_6544 => Ok(DataType::DeliveryStatus),
+            0x006c_6562_614c => Ok(DataType::Label),
+            0x7974_6976_6974_6341 => Ok(DataType::Activity),
             _ => Err(()),
         }
     }
@@ -188,6 +208,10 @@ impl DataType {
             DataType::Mdn => "MDN",
             DataType::Quota => "Quota",
             DataType::SieveScript => "SieveScript",
+            DataType::MaskedEmail => "MaskedEmail",
+            DataType::DeliveryStatus => "DeliveryStatus",
+            DataType::Label => "Label",
+            DataType::Activity => "Activity",
             DataType::None => "",
         }
     }
@@ -221,6 +245,10 @@ impl DeserializeFrom for DataType {
             10 => Some(DataType::Mdn),
             11 => Some(DataType::Quota),
             12 => Some(DataType::SieveScript),
+            13 => Some(DataType::MaskedEmail),
+            14 => Some(DataType::DeliveryStatus),
+            15 => Some(DataType::Label),
+            16 => Some(DataType::Activity),
             _ => None,
         }
     }
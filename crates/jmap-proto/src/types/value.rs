@@ -338,6 +338,7 @@ impl Value {
             Value::UnsignedInt(u) => Some(*u),
             Value::Id(id) => Some(id.id()),
             Value::Bool(b) => Some(*b as u64),
+            Value::Date(d) => Some(d.timestamp() as u64),
             _ => None,
         }
     }
@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::method::MethodError,
+    object::{
+        index::{IndexAs, IndexProperty, ObjectIndexBuilder},
+        Object,
+    },
+    types::{
+        collection::Collection, date::UTCDate, id::Id, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use mail_parser::{Message, MimeHeaders};
+use store::{
+    query::{sort::Pagination, Comparator, ResultSet},
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder},
+};
+use utils::config::Config;
+
+use crate::JMAP;
+
+pub mod get;
+pub mod query;
+pub mod set;
+
+pub static SCHEMA: &[IndexProperty] = &[
+    IndexProperty::new(Property::Type)
+        .index_as(IndexAs::Text {
+            tokenize: false,
+            index: true,
+        })
+        .max_size(255)
+        .required(),
+    IndexProperty::new(Property::CreatedAt)
+        .index_as(IndexAs::LongInteger)
+        .required(),
+    IndexProperty::new(Property::Description).max_size(MAX_DESCRIPTION_LEN),
+];
+
+// Feed size used when recording activity with the feed disabled in the configuration
+const DEFAULT_MAX_ENTRIES: usize = 100;
+const MAX_DESCRIPTION_LEN: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct ActivityConfig {
+    pub max_entries: usize,
+    pub large_attachment: usize,
+    pub quota_warning: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityType {
+    NewDevice,
+    FilterCreated,
+    LargeAttachment,
+    QuotaWarning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Activity {
+    pub typ: ActivityType,
+    pub description: String,
+    pub email_id: Option<Id>,
+}
+
+impl ActivityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityType::NewDevice => "newDevice",
+            ActivityType::FilterCreated => "filterCreated",
+            ActivityType::LargeAttachment => "largeAttachment",
+            ActivityType::QuotaWarning => "quotaWarning",
+        }
+    }
+}
+
+impl Activity {
+    pub fn new(typ: ActivityType, description: impl Into<String>) -> Self {
+        let mut description = description.into();
+        if description.len() > MAX_DESCRIPTION_LEN {
+            let mut end = MAX_DESCRIPTION_LEN;
+            while !description.is_char_boundary(end) {
+                end -= 1;
+            }
+            description.truncate(end);
+        }
+
+        Activity {
+            typ,
+            description,
+            email_id: None,
+        }
+    }
+
+    pub fn with_email_id(mut self, email_id: Id) -> Self {
+        self.email_id = email_id.into();
+        self
+    }
+}
+
+impl JMAP {
+    // Adds an entry to the account's activity feed, once the feed grows past
+    // its maximum size the oldest entries are removed.
+    pub async fn activity_record(
+        &self,
+        account_id: u32,
+        activity: Activity,
+    ) -> Result<Id, MethodError> {
+        let mut object = Object::with_capacity(4)
+            .with_property(Property::Type, activity.typ.as_str())
+            .with_property(
+                Property::CreatedAt,
+                Value::Date(UTCDate::from_timestamp(now() as i64)),
+            )
+            .with_property(Property::Description, activity.description);
+        if let Some(email_id) = activity.email_id {
+            object.set(Property::EmailId, Value::Id(email_id));
+        }
+        let builder = ObjectIndexBuilder::new(SCHEMA)
+            .with_changes(object)
+            .validate()
+            .map_err(|err| {
+                tracing::debug!(
+                    event = "error",
+                    context = "activity",
+                    account_id = account_id,
+                    error = ?err,
+                    "Invalid activity entry."
+                );
+                MethodError::ServerPartialFail
+            })?;
+
+        // Insert entry
+        let document_id = self
+            .assign_document_id(account_id, Collection::Activity)
+            .await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Activity)
+            .create_document(document_id)
+            .custom(builder);
+        self.write_batch(batch).await?;
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_insert(Collection::Activity, document_id);
+
+        // Remove the oldest entries
+        let max_entries = self
+            .config
+            .activity
+            .as_ref()
+            .map_or(DEFAULT_MAX_ENTRIES, |config| config.max_entries);
+        self.activity_expunge(account_id, max_entries, &mut changes)
+            .await?;
+        self.activity_commit(account_id, changes).await?;
+
+        Ok(Id::from(document_id))
+    }
+
+    // Records an activity from a background task, failures are logged
+    // but do not affect the operation that generated the event.
+    pub async fn activity_notify(&self, account_id: u32, activity: Activity) {
+        if self.config.activity.is_some() {
+            if let Err(err) = self.activity_record(account_id, activity).await {
+                tracing::warn!(
+                    event = "error",
+                    context = "activity",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to record account activity."
+                );
+            }
+        }
+    }
+
+    // Records large attachments and quota warnings for a delivered message.
+    pub async fn activity_delivery(
+        &self,
+        account_id: u32,
+        email_id: Id,
+        size: usize,
+        attachment: Option<(String, usize)>,
+    ) {
+        let config = if let Some(config) = &self.config.activity {
+            config
+        } else {
+            return;
+        };
+
+        if let Some((name, attachment_size)) = attachment {
+            self.activity_notify(
+                account_id,
+                Activity::new(
+                    ActivityType::LargeAttachment,
+                    format!("Received attachment {name:?} ({attachment_size} bytes)."),
+                )
+                .with_email_id(email_id),
+            )
+            .await;
+        }
+
+        // Warn only once, when this message pushed the used quota over the threshold
+        if config.quota_warning == 0 {
+            return;
+        }
+        let quota = match self.directory.query(QueryBy::Id(account_id), false).await {
+            Ok(Some(principal)) if principal.quota > 0 => principal.quota as i64,
+            _ => return,
+        };
+        let used = if let Ok(used) = self.get_used_quota(account_id).await {
+            used
+        } else {
+            return;
+        };
+        let threshold = quota * config.quota_warning as i64 / 100;
+        if used >= threshold && used - (size as i64) < threshold {
+            self.activity_notify(
+                account_id,
+                Activity::new(
+                    ActivityType::QuotaWarning,
+                    format!("Storage is {}% full.", used * 100 / quota),
+                ),
+            )
+            .await;
+        }
+    }
+
+    pub async fn activity_prune(
+        &self,
+        account_id: u32,
+        max_entries: usize,
+    ) -> Result<(), MethodError> {
+        let mut changes = ChangeLogBuilder::new();
+        self.activity_expunge(account_id, max_entries, &mut changes)
+            .await?;
+        if !changes.is_empty() {
+            self.activity_commit(account_id, changes).await?;
+        }
+        Ok(())
+    }
+
+    async fn activity_expunge(
+        &self,
+        account_id: u32,
+        max_entries: usize,
+        changes: &mut ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Activity)
+            .await?
+            .unwrap_or_default();
+        let total = document_ids.len() as usize;
+        if total <= max_entries {
+            return Ok(());
+        }
+
+        let expired_ids = self
+            .store
+            .sort(
+                ResultSet::new(account_id, Collection::Activity, document_ids),
+                vec![Comparator::ascending(Property::CreatedAt)],
+                Pagination::new(total - max_entries, 0, None, 0),
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(event = "error",
+                                context = "store",
+                                account_id = account_id,
+                                collection = "activity",
+                                error = ?err,
+                                "Activity sort failed");
+                MethodError::ServerPartialFail
+            })?
+            .ids;
+        for document_id in expired_ids {
+            let document_id = document_id as u32;
+            if let Some(activity) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::Activity,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Activity)
+                    .delete_document(document_id)
+                    .custom(ObjectIndexBuilder::new(SCHEMA).with_current(activity));
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::Activity, document_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn activity_commit(
+        &self,
+        account_id: u32,
+        changes: ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::Activity, change_id),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+// Returns the name and size of the largest attachment exceeding the minimum size
+pub fn large_attachment(message: &Message<'_>, min_size: usize) -> Option<(String, usize)> {
+    message
+        .attachments()
+        .filter(|part| part.len() >= min_size)
+        .max_by_key(|part| part.len())
+        .map(|part| {
+            (
+                part.attachment_name().unwrap_or("untitled").to_string(),
+                part.len(),
+            )
+        })
+}
+
+pub fn parse_activity(config: &Config) -> utils::config::Result<Option<ActivityConfig>> {
+    if !config.property_or_static::<bool>("jmap.activity.enable", "false")? {
+        return Ok(None);
+    }
+
+    Ok(Some(ActivityConfig {
+        max_entries: config.property_or_static("jmap.activity.max-entries", "100")?,
+        large_attachment: config
+            .property_or_static("jmap.activity.large-attachment", "10000000")?,
+        quota_warning: config.property_or_static("jmap.activity.quota-warning", "90")?,
+    }))
+}
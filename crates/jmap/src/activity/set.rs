@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::{index::ObjectIndexBuilder, Object},
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder};
+
+use crate::JMAP;
+
+use super::SCHEMA;
+
+impl JMAP {
+    // Activity entries are generated by the server, clients may only
+    // dismiss them.
+    pub async fn activity_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut activity_ids = self
+            .get_document_ids(account_id, Collection::Activity)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::Activity)
+            .await?;
+
+        for (id, _) in request.unwrap_create() {
+            response.not_created.append(
+                id,
+                SetError::forbidden().with_description("Activity entries cannot be created."),
+            );
+        }
+        for (id, _) in request.unwrap_update() {
+            response.not_updated.append(
+                id,
+                SetError::forbidden().with_description("Activity entries cannot be modified."),
+            );
+        }
+
+        // Process deletions
+        let mut changes = ChangeLogBuilder::new();
+        for id in request.unwrap_destroy() {
+            let document_id = id.document_id();
+            if !activity_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+
+            let activity = if let Some(activity) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::Activity,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                activity
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            };
+
+            // Delete record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Activity)
+                .delete_document(document_id)
+                .custom(ObjectIndexBuilder::new(SCHEMA).with_current(activity));
+            self.write_batch(batch).await?;
+            activity_ids.remove(document_id);
+            changes.log_delete(Collection::Activity, document_id);
+            response.destroyed.push(id);
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::Activity, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+}
@@ -29,10 +29,18 @@ use http_body_util::combinators::BoxBody;
 use hyper::{body::Bytes, Method, StatusCode};
 use jmap_proto::error::request::RequestError;
 use serde_json::json;
+use store::write::now;
 
-use crate::JMAP;
+use crate::{
+    auth::{oauth::grant::OAuthGrant, AccessToken},
+    services::{
+        jobs::JobKind,
+        report::{parse_traffic_day, ClientUsage, TrafficScope, TrafficUsage},
+    },
+    JMAP,
+};
 
-use super::{http::ToHttpResponse, HttpRequest, JsonResponse};
+use super::{http::ToHttpResponse, CsvResponse, HttpRequest, JsonResponse};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PrincipalResponse {
@@ -56,6 +64,7 @@ impl JMAP {
         &self,
         req: &HttpRequest,
         body: Option<Vec<u8>>,
+        access_token: &AccessToken,
     ) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
         let mut path = req.uri().path().split('/');
         path.next();
@@ -68,10 +77,13 @@ impl JMAP {
                     body.and_then(|body| serde_json::from_slice::<Principal<String>>(&body).ok())
                 {
                     match self.store.create_account(principal).await {
-                        Ok(account_id) => JsonResponse::new(json!({
-                            "data": account_id,
-                        }))
-                        .into_http_response(),
+                        Ok(account_id) => {
+                            self.directory.invalidate_cache();
+                            JsonResponse::new(json!({
+                                "data": account_id,
+                            }))
+                            .into_http_response()
+                        }
                         Err(err) => map_directory_error(err),
                     }
                 } else {
@@ -196,10 +208,13 @@ impl JMAP {
 
                         // Delete account
                         match self.store.delete_account(QueryBy::Id(account_id)).await {
-                            Ok(_) => JsonResponse::new(json!({
-                                "data": [],
-                            }))
-                            .into_http_response(),
+                            Ok(_) => {
+                                self.directory.invalidate_cache();
+                                JsonResponse::new(json!({
+                                    "data": [],
+                                }))
+                                .into_http_response()
+                            }
                             Err(err) => map_directory_error(err),
                         }
                     }
@@ -212,10 +227,13 @@ impl JMAP {
                                 .update_account(QueryBy::Id(account_id), changes)
                                 .await
                             {
-                                Ok(account_id) => JsonResponse::new(json!({
-                                    "data": account_id,
-                                }))
-                                .into_http_response(),
+                                Ok(account_id) => {
+                                    self.directory.invalidate_cache();
+                                    JsonResponse::new(json!({
+                                        "data": account_id,
+                                    }))
+                                    .into_http_response()
+                                }
                                 Err(err) => map_directory_error(err),
                             }
                         } else {
@@ -260,46 +278,384 @@ impl JMAP {
             ("domain", Some(domain), &Method::POST) => {
                 // Create domain
                 match self.store.create_domain(domain).await {
-                    Ok(_) => JsonResponse::new(json!({
-                        "data": [],
-                    }))
-                    .into_http_response(),
+                    Ok(_) => {
+                        self.directory.invalidate_cache();
+                        JsonResponse::new(json!({
+                            "data": [],
+                        }))
+                        .into_http_response()
+                    }
                     Err(err) => map_directory_error(err),
                 }
             }
             ("domain", Some(domain), &Method::DELETE) => {
                 // Delete domain
                 match self.store.delete_domain(domain).await {
-                    Ok(_) => JsonResponse::new(json!({
-                        "data": [],
-                    }))
-                    .into_http_response(),
+                    Ok(_) => {
+                        self.directory.invalidate_cache();
+                        JsonResponse::new(json!({
+                            "data": [],
+                        }))
+                        .into_http_response()
+                    }
                     Err(err) => map_directory_error(err),
                 }
             }
+            ("directory", Some("cache"), &Method::DELETE) => {
+                // Flush directory cache
+                self.directory.invalidate_cache();
+                JsonResponse::new(json!({
+                    "data": [],
+                }))
+                .into_http_response()
+            }
             ("store", Some("maintenance"), &Method::GET) => {
-                match self.store.purge_blobs(self.blob_store.clone()).await {
-                    Ok(_) => match self.store.purge_bitmaps().await {
-                        Ok(_) => JsonResponse::new(json!({
-                            "data": [],
+                // Purging runs as a background job
+                match self
+                    .job_submit(JobKind::StoreMaintenance, &access_token.name, None)
+                    .await
+                {
+                    Ok(job) => JsonResponse::new(json!({
+                        "data": job.into_json(),
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to submit job",
+                        format!("{err:?}"),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("jobs", _, &Method::GET | &Method::POST | &Method::DELETE) => {
+                self.handle_jobs_request(req, body, access_token).await
+            }
+            ("reports", Some("storage"), &Method::GET) => {
+                // Top accounts and domains by storage usage
+                let mut top: usize = 50;
+                let mut refresh = false;
+
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "top" => {
+                                top = value.parse().unwrap_or(top);
+                            }
+                            "refresh" => {
+                                refresh = value == "true";
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                let result = if refresh {
+                    self.storage_report_build().await
+                } else {
+                    self.storage_report_get().await
+                };
+
+                match result {
+                    Ok(mut report) => {
+                        if top > 0 {
+                            report.accounts.truncate(top);
+                            report.domains.truncate(top);
+                        }
+                        JsonResponse::new(json!({
+                            "data": report,
+                        }))
+                        .into_http_response()
+                    }
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to build storage report",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("reports", Some("key-space"), &Method::GET) => {
+                // Results of the last key space analysis job
+                let mut top: usize = 50;
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        if key == "top" {
+                            top = value.parse().unwrap_or(top);
+                        }
+                    }
+                }
+
+                match self.key_space_analysis_get().await {
+                    Ok(Some(mut analysis)) => {
+                        if top > 0 {
+                            analysis.accounts.truncate(top);
+                        }
+                        JsonResponse::new(json!({
+                            "data": analysis,
                         }))
-                        .into_http_response(),
-                        Err(err) => RequestError::blank(
-                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                            "Purge database failed",
-                            err.to_string(),
+                        .into_http_response()
+                    }
+                    Ok(None) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "No key space analysis is available, submit a key-space-analysis job first.",
+                    )
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to obtain key space analysis",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("reports", Some("traffic"), &Method::GET) => {
+                // Daily message counts and bytes per account or domain
+                let today = (now() / 86400) as u32;
+                let mut from_day = today;
+                let mut to_day = today;
+                let mut scope = TrafficScope::Domain;
+                let mut is_csv = false;
+
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "from" | "to" => {
+                                if let Some(day) = parse_traffic_day(value.as_ref()) {
+                                    if key == "from" {
+                                        from_day = day;
+                                    } else {
+                                        to_day = day;
+                                    }
+                                } else {
+                                    return RequestError::blank(
+                                        StatusCode::BAD_REQUEST.as_u16(),
+                                        "Invalid parameters",
+                                        format!("Invalid date {value:?}, expected YYYY-MM-DD."),
+                                    )
+                                    .into_http_response();
+                                }
+                            }
+                            "type" => {
+                                scope = TrafficScope::parse(value.as_ref()).unwrap_or(scope);
+                            }
+                            "format" => {
+                                is_csv = value == "csv";
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                match self.traffic_report(scope, from_day, to_day).await {
+                    Ok(report) if is_csv => {
+                        CsvResponse::new("traffic.csv", TrafficUsage::to_csv(&report))
+                            .into_http_response()
+                    }
+                    Ok(report) => JsonResponse::new(json!({
+                        "data": report,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to build traffic report",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("reports", Some("clients"), &Method::GET) => {
+                // Sessions per client name and version, as advertised by the client
+                let today = (now() / 86400) as u32;
+                let mut from_day = today.saturating_sub(30);
+                let mut to_day = today;
+                let mut is_csv = false;
+
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "from" | "to" => {
+                                if let Some(day) = parse_traffic_day(value.as_ref()) {
+                                    if key == "from" {
+                                        from_day = day;
+                                    } else {
+                                        to_day = day;
+                                    }
+                                } else {
+                                    return RequestError::blank(
+                                        StatusCode::BAD_REQUEST.as_u16(),
+                                        "Invalid parameters",
+                                        format!("Invalid date {value:?}, expected YYYY-MM-DD."),
+                                    )
+                                    .into_http_response();
+                                }
+                            }
+                            "format" => {
+                                is_csv = value == "csv";
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                match self.client_usage_report(from_day, to_day).await {
+                    Ok(report) if is_csv => {
+                        CsvResponse::new("clients.csv", ClientUsage::to_csv(&report))
+                            .into_http_response()
+                    }
+                    Ok(report) => JsonResponse::new(json!({
+                        "data": report,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to build client usage report",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("oauth", Some(name), method @ (&Method::GET | &Method::DELETE)) => {
+                // List or revoke all OAuth grants of a principal
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                let result = if *method == Method::GET {
+                    self.oauth_grants(account_id).await.map(|grants| {
+                        serde_json::Value::from(
+                            grants
+                                .grants
+                                .into_iter()
+                                .map(OAuthGrant::into_json)
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                } else {
+                    self.oauth_grants_revoke_all(account_id)
+                        .await
+                        .map(serde_json::Value::from)
+                };
+
+                match result {
+                    Ok(data) => JsonResponse::new(json!({
+                        "data": data,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to access OAuth grants",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("push", Some(name), &Method::GET) => {
+                // List push subscriptions and their delivery history
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
                         )
-                        .into_http_response(),
-                    },
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                match self.push_subscription_summaries(account_id).await {
+                    Ok(subscriptions) => JsonResponse::new(json!({
+                        "data": subscriptions,
+                    }))
+                    .into_http_response(),
                     Err(err) => RequestError::blank(
                         StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                        "Purge blob failed",
+                        "Failed to fetch push subscriptions",
                         err.to_string(),
                     )
                     .into_http_response(),
                 }
             }
-            (path_1 @ ("queue" | "report"), Some(path_2), &Method::GET) => {
+            ("export", Some(name), &Method::GET | &Method::POST | &Method::DELETE) => {
+                // Subject access request exports
+                match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => {
+                        self.handle_account_export(req, account_id, name, body, access_token)
+                            .await
+                    }
+                    Ok(None) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response(),
+                    Err(err) => map_directory_error(err),
+                }
+            }
+            ("redact", Some(name), &Method::POST) => {
+                // Permanently purge messages from an account
+                match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => {
+                        self.handle_email_redact(account_id, name, body, access_token)
+                            .await
+                    }
+                    Ok(None) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response(),
+                    Err(err) => map_directory_error(err),
+                }
+            }
+            ("recover", Some(name), &Method::GET | &Method::POST) => {
+                // List or restore deleted messages still within the retention period
+                match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => {
+                        self.handle_email_recover(req, account_id, name, body, access_token)
+                            .await
+                    }
+                    Ok(None) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response(),
+                    Err(err) => map_directory_error(err),
+                }
+            }
+            ("trace", None, &Method::GET) => self.handle_live_trace(req).await,
+            ("dashboard", None, &Method::GET) => self.handle_dashboard_stream(req).await,
+            ("search", None, &Method::POST) => self.handle_account_search(body, access_token).await,
+            (
+                path_1 @ ("queue" | "report" | "suppression" | "quarantine" | "senders"),
+                Some(path_2),
+                &Method::GET,
+            ) => {
+                self.smtp
+                    .handle_manage_request(req.uri(), req.method(), path_1, path_2)
+                    .await
+            }
+            (
+                path_1 @ ("suppression" | "senders"),
+                Some(path_2),
+                &Method::POST | &Method::DELETE,
+            ) => {
                 self.smtp
                     .handle_manage_request(req.uri(), req.method(), path_1, path_2)
                     .await
@@ -26,6 +26,15 @@ use std::{str::FromStr, time::Duration};
 use nlp::language::Language;
 use store::rand::{distributions::Alphanumeric, thread_rng, Rng};
 
+use utils::message::MessageLimits;
+
+use crate::{
+    activity::parse_activity,
+    auth::{devices::parse_login_alert, reset::parse_password_reset},
+    services::{alert::parse_alerts, notify::parse_notify_connectors},
+    sieve::limits::RegexLimits,
+};
+
 use super::session::BaseCapabilities;
 
 impl crate::Config {
@@ -84,15 +93,55 @@ impl crate::Config {
             mail_max_size: settings
                 .property("jmap.email.max-size")?
                 .unwrap_or(75000000),
+            mail_ingest_batch_size: settings
+                .property("jmap.email.ingest.batch-size")?
+                .unwrap_or(50),
+            mail_limits: MessageLimits {
+                max_headers: settings
+                    .property("jmap.email.limits.headers")?
+                    .unwrap_or(500),
+                max_depth: settings
+                    .property("jmap.email.limits.mime-depth")?
+                    .unwrap_or(20),
+                max_parts: settings
+                    .property("jmap.email.limits.mime-parts")?
+                    .unwrap_or(1000),
+                max_addresses: settings
+                    .property("jmap.email.limits.addresses")?
+                    .unwrap_or(1000),
+            },
+            mail_unsubscribe_timeout: settings
+                .property_or_static("jmap.email.unsubscribe.timeout", "10s")?,
             mail_parse_max_items: settings
                 .property("jmap.email.parse.max-items")?
                 .unwrap_or(10),
+            mail_recovery_retention: settings.property("jmap.email.recovery.retention")?,
             sieve_max_script_name: settings
                 .property("sieve.untrusted.limits.name-length")?
                 .unwrap_or(512),
             sieve_max_scripts: settings
                 .property("sieve.untrusted.limits.max-scripts")?
                 .unwrap_or(256),
+            notify_connectors: parse_notify_connectors(settings)?,
+            masked_email_domain: settings
+                .value("jmap.masked-email.domain")
+                .map(|v| v.to_lowercase()),
+            masked_email_max: settings
+                .property("jmap.masked-email.max-addresses")?
+                .unwrap_or(500),
+            masked_email_expiry_notice: settings
+                .property_or_static("jmap.masked-email.expire.notify", "3d")?,
+            sieve_regex: RegexLimits {
+                max_length: settings
+                    .property("sieve.untrusted.limits.regex.max-length")?
+                    .unwrap_or(256),
+                max_patterns: settings
+                    .property("sieve.untrusted.limits.regex.max-patterns")?
+                    .unwrap_or(64),
+                allow_backtracking: settings
+                    .property("sieve.untrusted.limits.regex.allow-backtracking")?
+                    .unwrap_or(false),
+            },
             capabilities: BaseCapabilities::default(),
             session_cache_ttl: settings
                 .property("jmap.session.cache.ttl")?
@@ -129,9 +178,15 @@ impl crate::Config {
             oauth_expiry_refresh_token_renew: settings
                 .property_or_static::<Duration>("oauth.expiry.refresh-token-renew", "4d")?
                 .as_secs(),
+            oauth_refresh_token_rotate: settings
+                .property_or_static("oauth.refresh-token.rotate", "true")?,
             oauth_max_auth_attempts: settings.property_or_static("oauth.auth.max-attempts", "3")?,
             event_source_throttle: settings
                 .property_or_static("jmap.event-source.throttle", "1s")?,
+            event_source_replay_window: settings
+                .property_or_static("jmap.event-source.replay.window", "15m")?,
+            event_source_replay_max: settings
+                .property_or_static("jmap.event-source.replay.max-changes", "100")?,
             web_socket_throttle: settings.property_or_static("jmap.web-socket.throttle", "1s")?,
             web_socket_timeout: settings.property_or_static("jmap.web-socket.timeout", "10m")?,
             web_socket_heartbeat: settings.property_or_static("jmap.web-socket.heartbeat", "1m")?,
@@ -175,6 +230,23 @@ impl crate::Config {
                     }
                 })
                 .collect::<Result<Vec<_>, String>>()?,
+            health_timeout: settings.property_or_static("jmap.health.timeout", "5s")?,
+            health_queue_lag: settings.property_or_static("jmap.health.queue-lag", "30m")?,
+            health_queue_max_overdue: settings
+                .property_or_static("jmap.health.queue-max-overdue", "100")?,
+            alerts: parse_alerts(settings)?,
+            login_alert: parse_login_alert(settings)?,
+            password_reset: parse_password_reset(settings)?,
+            activity: parse_activity(settings)?,
+            export_expiry: settings.property_or_static("jmap.export.expiry", "7d")?,
+            jobs_max_concurrent: settings.property_or_static("jmap.jobs.max-concurrent", "2")?,
+            jobs_retention: settings.property_or_static("jmap.jobs.retention", "7d")?,
+            traffic_retention: settings
+                .property_or_static("jmap.reports.traffic.retention", "366d")?,
+            client_retention: settings
+                .property_or_static("jmap.reports.clients.retention", "90d")?,
+            tiering_older_than: settings.property("jmap.store.tiering.older-than")?,
+            tiering_archive: settings.property_or_static("jmap.store.tiering.archive", "true")?,
         };
         config.add_capabilites(settings);
         Ok(config)
@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use http_body_util::{combinators::BoxBody, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    header, StatusCode,
+};
+use jmap_proto::error::request::RequestError;
+use serde_json::json;
+use utils::{config::utils::ParseValue, stats::ServerStats};
+
+use crate::JMAP;
+
+use super::{http::ToHttpResponse, status::server_status, HttpRequest, HttpResponse};
+
+const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_STREAM_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_STREAM_DURATION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_TOP_DOMAINS: usize = 10;
+const MAX_TOP_DOMAINS: usize = 100;
+
+impl JMAP {
+    pub async fn handle_dashboard_stream(&self, req: &HttpRequest) -> HttpResponse {
+        // Parse query
+        let mut interval = DEFAULT_UPDATE_INTERVAL;
+        let mut duration = DEFAULT_STREAM_DURATION;
+        let mut top_domains = DEFAULT_TOP_DOMAINS;
+
+        for (key, value) in form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        {
+            match key.as_ref() {
+                "interval" => match Duration::parse_value("interval", value.as_ref()) {
+                    Ok(value) => {
+                        interval = value.clamp(MIN_UPDATE_INTERVAL, MAX_UPDATE_INTERVAL);
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                "duration" => match Duration::parse_value("duration", value.as_ref()) {
+                    Ok(value) => {
+                        duration = std::cmp::min(value, MAX_STREAM_DURATION);
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                "top" => match value.parse::<usize>() {
+                    Ok(value) => {
+                        top_domains = std::cmp::min(value, MAX_TOP_DOMAINS);
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                _ => {}
+            }
+        }
+
+        let smtp = self.smtp.clone();
+        let timeout = self.config.health_timeout;
+        let expires = tokio::time::Instant::now() + duration;
+
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(BoxBody::new(StreamBody::new(async_stream::stream! {
+                let mut ticker = tokio::time::interval(interval);
+
+                loop {
+                    ticker.tick().await;
+                    if tokio::time::Instant::now() >= expires {
+                        yield Ok(Frame::data(Bytes::from_static(
+                            b"event: expired\ndata: {}\n\n",
+                        )));
+                        break;
+                    }
+
+                    let stats = ServerStats::get();
+                    let (_, mut status) = server_status(&smtp, timeout).await;
+                    status["messages"] = json!({
+                        "total": stats.messages(),
+                        "lastMinute": stats.messages_last_minute(),
+                    });
+                    status["topSenderDomains"] = stats
+                        .top_sender_domains(top_domains)
+                        .into_iter()
+                        .map(|(domain, messages)| {
+                            json!({
+                                "domain": domain,
+                                "messagesLastMinute": messages,
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                        .into();
+
+                    yield Ok(Frame::data(Bytes::from(format!(
+                        "event: stats\ndata: {status}\n\n"
+                    ))));
+                }
+            })))
+            .unwrap()
+    }
+}
@@ -0,0 +1,341 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use chrono::TimeZone;
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory},
+    QueryBy, Type,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, header, StatusCode};
+use jmap_proto::{
+    error::request::RequestError,
+    method::{
+        get::GetRequest,
+        query::{QueryRequest, RequestArguments},
+    },
+    object::email::{GetArguments, QueryArguments},
+    parser::{json::Parser, JsonObjectParser},
+    request::{method::MethodObject, reference::MaybeReference},
+    types::{any_id::AnyId, collection::Collection, id::Id, property::Property},
+};
+use serde_json::json;
+
+use crate::{auth::AccessToken, email::metadata::MessageMetadata, Bincode, JMAP};
+
+use super::{http::ToHttpResponse, HttpResponse, JsonResponse};
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    accounts: Vec<String>,
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    export: SearchExport,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+enum SearchExport {
+    #[default]
+    #[serde(rename = "metadata")]
+    Metadata,
+    #[serde(rename = "mbox")]
+    Mbox,
+}
+
+impl JMAP {
+    pub async fn handle_account_search(
+        &self,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        // Parse request
+        let body = body.unwrap_or_default();
+        let search = match serde_json::from_slice::<SearchRequest>(&body) {
+            Ok(search) => search,
+            Err(err) => {
+                return RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Invalid parameters",
+                    err.to_string(),
+                )
+                .into_http_response();
+            }
+        };
+        let mut parser = Parser::new(&body);
+        parser.ctx = MethodObject::Email;
+        let query = match QueryRequest::<RequestArguments>::parse(&mut parser) {
+            Ok(mut query) => match query.take_arguments() {
+                RequestArguments::Email(arguments) => query.with_arguments(arguments),
+                _ => unreachable!(),
+            },
+            Err(err) => {
+                return RequestError::from(err).into_http_response();
+            }
+        };
+
+        // Obtain accounts to search
+        let accounts = match self.search_accounts(&search).await {
+            Ok(accounts) if !accounts.is_empty() => accounts,
+            Ok(_) => {
+                return RequestError::blank(
+                    StatusCode::NOT_FOUND.as_u16(),
+                    "Not found",
+                    "No matching accounts found.",
+                )
+                .into_http_response();
+            }
+            Err(response) => return response,
+        };
+
+        // Run query on each account
+        let mut results = Vec::with_capacity(accounts.len());
+        let mut total = 0;
+        for (account_id, account_name) in accounts {
+            if total >= self.config.query_max_results {
+                break;
+            }
+
+            let mut query: QueryRequest<QueryArguments> = query.clone();
+            query.account_id = Id::from(account_id);
+            query.limit = Some(std::cmp::min(
+                query.limit.unwrap_or(self.config.query_max_results),
+                self.config.query_max_results - total,
+            ));
+            let ids = match self.email_query(query, access_token).await {
+                Ok(response) => response.ids,
+                Err(err) => {
+                    return RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Search failed",
+                        err.to_string(),
+                    )
+                    .into_http_response();
+                }
+            };
+            total += ids.len();
+            results.push((account_id, account_name, ids));
+        }
+
+        tracing::info!(
+            context = "audit",
+            event = "search",
+            admin = access_token.name,
+            accounts = ?results.iter().map(|(_, name, _)| name.as_str()).collect::<Vec<_>>(),
+            query = %String::from_utf8_lossy(&body),
+            results = total,
+            "Cross-account search executed."
+        );
+
+        match search.export {
+            SearchExport::Metadata => self.export_metadata(results, access_token).await,
+            SearchExport::Mbox => self.export_mbox(results).await,
+        }
+    }
+
+    async fn search_accounts(
+        &self,
+        search: &SearchRequest,
+    ) -> Result<Vec<(u32, String)>, HttpResponse> {
+        let mut accounts = Vec::new();
+
+        for name in &search.accounts {
+            match self.store.get_account_id(name).await {
+                Ok(Some(account_id)) => {
+                    accounts.push((account_id, name.to_string()));
+                }
+                Ok(None) => {
+                    return Err(RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        format!("Account {name:?} not found."),
+                    )
+                    .into_http_response());
+                }
+                Err(_) => {
+                    return Err(RequestError::internal_server_error().into_http_response());
+                }
+            }
+        }
+
+        if let Some(domain) = &search.domain {
+            let suffix = format!("@{}", domain.to_lowercase());
+            let names = self
+                .store
+                .list_accounts(None, Some(Type::Individual), 0)
+                .await
+                .map_err(|_| RequestError::internal_server_error().into_http_response())?;
+
+            for name in names {
+                let account_id = match self.store.get_account_id(&name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => continue,
+                    Err(_) => {
+                        return Err(RequestError::internal_server_error().into_http_response());
+                    }
+                };
+                if accounts.iter().any(|(id, _)| *id == account_id) {
+                    continue;
+                }
+                let is_match = name.to_lowercase().ends_with(&suffix)
+                    || self
+                        .store
+                        .query(QueryBy::Id(account_id), false)
+                        .await
+                        .map_err(|_| RequestError::internal_server_error().into_http_response())?
+                        .map_or(false, |principal| {
+                            principal
+                                .emails
+                                .iter()
+                                .any(|email| email.to_lowercase().ends_with(&suffix))
+                        });
+                if is_match {
+                    accounts.push((account_id, name));
+                }
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    async fn export_metadata(
+        &self,
+        results: Vec<(u32, String, Vec<Id>)>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        let mut response = Vec::with_capacity(results.len());
+
+        for (account_id, account_name, ids) in results {
+            let mut emails = Vec::with_capacity(ids.len());
+            for ids in ids.chunks(self.config.get_max_objects) {
+                let request = GetRequest {
+                    account_id: Id::from(account_id),
+                    ids: Some(MaybeReference::Value(
+                        ids.iter()
+                            .map(|id| MaybeReference::Value(AnyId::Id(*id)))
+                            .collect(),
+                    )),
+                    properties: Some(MaybeReference::Value(vec![
+                        Property::Id,
+                        Property::BlobId,
+                        Property::MailboxIds,
+                        Property::MessageId,
+                        Property::From,
+                        Property::To,
+                        Property::Cc,
+                        Property::Subject,
+                        Property::SentAt,
+                        Property::ReceivedAt,
+                        Property::Size,
+                    ])),
+                    arguments: GetArguments::default(),
+                };
+                match self.email_get(request, access_token).await {
+                    Ok(result) => emails.extend(result.list),
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "search",
+                            event = "error",
+                            account_id = account_id,
+                            "Failed to fetch email metadata: {}",
+                            err
+                        );
+                        return RequestError::internal_server_error().into_http_response();
+                    }
+                }
+            }
+
+            response.push(json!({
+                "account": account_name,
+                "accountId": Id::from(account_id).to_string(),
+                "emails": emails,
+            }));
+        }
+
+        JsonResponse::new(json!({
+            "data": response,
+        }))
+        .into_http_response()
+    }
+
+    async fn export_mbox(&self, results: Vec<(u32, String, Vec<Id>)>) -> HttpResponse {
+        let mut mbox = Vec::new();
+
+        for (account_id, _, ids) in results {
+            for id in ids {
+                let metadata = match self
+                    .get_property::<Bincode<MessageMetadata>>(
+                        account_id,
+                        Collection::Email,
+                        id.document_id(),
+                        &Property::BodyStructure,
+                    )
+                    .await
+                {
+                    Ok(Some(metadata)) => metadata.inner,
+                    Ok(None) => continue,
+                    Err(_) => return RequestError::internal_server_error().into_http_response(),
+                };
+                let raw_message = match self.get_blob(&metadata.blob_hash, 0..u32::MAX).await {
+                    Ok(Some(raw_message)) => raw_message,
+                    Ok(None) => continue,
+                    Err(_) => return RequestError::internal_server_error().into_http_response(),
+                };
+
+                // Write message in mboxrd format
+                mbox.extend_from_slice(b"From - ");
+                mbox.extend_from_slice(
+                    chrono::Utc
+                        .timestamp_opt(metadata.received_at as i64, 0)
+                        .single()
+                        .unwrap_or_default()
+                        .format("%a %b %e %H:%M:%S %Y\n")
+                        .to_string()
+                        .as_bytes(),
+                );
+                for line in raw_message.split_inclusive(|&ch| ch == b'\n') {
+                    let unquoted = &line[line.iter().take_while(|&&ch| ch == b'>').count()..];
+                    if unquoted.starts_with(b"From ") {
+                        mbox.push(b'>');
+                    }
+                    mbox.extend_from_slice(line);
+                }
+                if !raw_message.ends_with(b"\n") {
+                    mbox.push(b'\n');
+                }
+                mbox.push(b'\n');
+            }
+        }
+
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/mbox")
+            .body(
+                Full::new(Bytes::from(mbox))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
@@ -31,8 +31,11 @@ use hyper::{
     body::{Bytes, Frame},
     header, StatusCode,
 };
-use jmap_proto::{error::request::RequestError, types::type_state::DataType};
-use utils::map::bitmap::Bitmap;
+use jmap_proto::{
+    error::request::RequestError,
+    types::{collection::Collection, type_state::DataType},
+};
+use utils::{listener::limiter::InFlight, map::bitmap::Bitmap};
 
 use crate::{auth::AccessToken, JMAP, LONG_SLUMBER};
 
@@ -49,6 +52,7 @@ impl JMAP {
         &self,
         req: HttpRequest,
         access_token: Arc<AccessToken>,
+        in_flight: InFlight,
     ) -> HttpResponse {
         // Parse query
         let mut ping = 0;
@@ -105,6 +109,11 @@ impl JMAP {
         } else {
             None
         };
+        let last_event_id = req
+            .headers()
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
         let mut response = StateChangeResponse::new();
         let throttle = self.config.event_source_throttle;
 
@@ -118,19 +127,57 @@ impl JMAP {
             return RequestError::internal_server_error().into_http_response();
         };
 
+        // Replay any changes missed since the last event received by the client
+        let mut event_id = last_event_id.unwrap_or_default();
+        if let Some(last_event_id) = last_event_id {
+            if let Some(state_changes) = self
+                .replay_state_changes(access_token.primary_id(), types, last_event_id)
+                .await
+            {
+                for state_change in state_changes {
+                    for (type_state, change_id) in state_change.types {
+                        event_id = std::cmp::max(event_id, change_id);
+                        response
+                            .changed
+                            .get_mut_or_insert(state_change.account_id.into())
+                            .set(type_state, change_id.into());
+                    }
+                }
+            } else {
+                tracing::debug!(
+                    account_id = access_token.primary_id(),
+                    last_event_id = last_event_id,
+                    "Unable to replay state changes, last event id is outside the retention window."
+                );
+
+                // Send the current state of all requested types so the client resyncs
+                event_id = std::cmp::max(
+                    event_id,
+                    self.current_states(&access_token, types, &mut response)
+                        .await,
+                );
+            }
+        }
+
         hyper::Response::builder()
             .status(StatusCode::OK)
             .header(header::CONTENT_TYPE, "text/event-stream")
             .header(header::CACHE_CONTROL, "no-store")
             .body(BoxBody::new(StreamBody::new(async_stream::stream! {
+                // The stream counts towards the account's connections until closed
+                let _in_flight = in_flight;
                 let mut last_message = Instant::now() - throttle;
-                let mut timeout =
-                    ping.as_ref().map(|p| p.interval).unwrap_or(LONG_SLUMBER);
+                let mut timeout = if response.changed.is_empty() {
+                    ping.as_ref().map(|p| p.interval).unwrap_or(LONG_SLUMBER)
+                } else {
+                    Duration::ZERO
+                };
 
                 loop {
                     match tokio::time::timeout(timeout, change_rx.recv()).await {
                         Ok(Some(state_change)) => {
                             for (type_state, change_id) in state_change.types {
+                                event_id = std::cmp::max(event_id, change_id);
                                 response
                                     .changed
                                     .get_mut_or_insert(state_change.account_id.into())
@@ -149,7 +196,8 @@ impl JMAP {
                         if elapsed >= throttle {
                             last_message = Instant::now();
                             yield Ok(Frame::data(Bytes::from(format!(
-                                "event: state\ndata: {}\n\n",
+                                "event: state\nid: {}\ndata: {}\n\n",
+                                event_id,
                                 serde_json::to_string(&response).unwrap()
                             ))));
 
@@ -178,4 +226,57 @@ impl JMAP {
             })))
             .unwrap()
     }
+
+    // Adds the latest state of the requested types in every account the token
+    // has access to, returns the highest change id found.
+    async fn current_states(
+        &self,
+        access_token: &AccessToken,
+        types: Bitmap<DataType>,
+        response: &mut StateChangeResponse,
+    ) -> u64 {
+        let mut event_id = 0;
+
+        for collection in (0..Collection::None as u8).map(Collection::from) {
+            let mut type_states = Vec::with_capacity(2);
+            if let Ok(type_state) = DataType::try_from(collection) {
+                type_states.push(type_state);
+            }
+            if collection == Collection::Email {
+                type_states.push(DataType::EmailDelivery);
+            }
+            type_states.retain(|type_state| types.contains(*type_state));
+            if type_states.is_empty() {
+                continue;
+            }
+
+            for account_id in [access_token.primary_id()]
+                .iter()
+                .chain(access_token.shared_accounts(collection))
+            {
+                match self.store.get_last_change_id(*account_id, collection).await {
+                    Ok(Some(change_id)) => {
+                        event_id = std::cmp::max(event_id, change_id);
+                        let changed = response.changed.get_mut_or_insert((*account_id).into());
+                        for type_state in &type_states {
+                            changed.set(*type_state, change_id.into());
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(err) => {
+                        tracing::error!(
+                            event = "error",
+                            context = "event_source",
+                            account_id = account_id,
+                            collection = ?collection,
+                            error = ?err,
+                            "Failed to obtain state"
+                        );
+                    }
+                }
+            }
+        }
+
+        event_id
+    }
 }
@@ -0,0 +1,196 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Instant;
+
+use hyper::StatusCode;
+use serde_json::{json, Map, Value};
+use smtp::{core::management::QueueRequest, queue};
+use store::write::DirectoryClass;
+use tokio::sync::oneshot;
+
+use crate::{services::state, JMAP};
+
+use super::{http::ToHttpResponse, HttpResponse, JsonResponse};
+
+const HEALTH_BLOB_KEY: &[u8] = b"healthz";
+
+impl JMAP {
+    pub async fn handle_health_request(&self, is_readiness: bool) -> HttpResponse {
+        let mut checks = Map::new();
+        let mut is_healthy = true;
+
+        // Liveness only verifies that the delivery queue manager is responsive,
+        // readiness also verifies that all external dependencies are reachable.
+        if is_readiness {
+            for (name, result) in [
+                ("store", self.check_store().await),
+                ("blobStore", self.check_blob_store().await),
+                ("directory", self.check_directory().await),
+            ] {
+                is_healthy &= result.is_ok();
+                checks.insert(name.to_string(), result.unwrap_or_else(|value| value));
+            }
+        }
+        let result = self.check_queue().await;
+        is_healthy &= result.is_ok();
+        checks.insert("queue".to_string(), result.unwrap_or_else(|value| value));
+
+        JsonResponse::with_status(
+            if is_healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            json!({
+                "status": if is_healthy { "ok" } else { "error" },
+                "checks": checks,
+            }),
+        )
+        .into_http_response()
+    }
+
+    // Used by the systemd watchdog, verifies that the main event loops are responsive
+    pub async fn is_alive(&self) -> bool {
+        let (tx, rx) = oneshot::channel();
+
+        self.smtp.is_alive(self.config.health_timeout).await
+            && self.state_tx.send(state::Event::Ping { tx }).await.is_ok()
+            && matches!(
+                tokio::time::timeout(self.config.health_timeout, rx).await,
+                Ok(Ok(_))
+            )
+    }
+
+    pub(crate) async fn check_store(&self) -> Result<Value, Value> {
+        let started = Instant::now();
+        match tokio::time::timeout(
+            self.config.health_timeout,
+            self.store.get_counter(DirectoryClass::UsedQuota(u32::MAX)),
+        )
+        .await
+        {
+            Ok(Ok(_)) => Ok(check_ok(started)),
+            Ok(Err(err)) => Err(check_error(started, format!("{err:?}"))),
+            Err(_) => Err(check_timeout(started)),
+        }
+    }
+
+    pub(crate) async fn check_blob_store(&self) -> Result<Value, Value> {
+        let started = Instant::now();
+        match tokio::time::timeout(
+            self.config.health_timeout,
+            self.blob_store.get_blob(HEALTH_BLOB_KEY, 0..1),
+        )
+        .await
+        {
+            Ok(Ok(_)) => Ok(check_ok(started)),
+            Ok(Err(err)) => Err(check_error(started, format!("{err:?}"))),
+            Err(_) => Err(check_timeout(started)),
+        }
+    }
+
+    pub(crate) async fn check_directory(&self) -> Result<Value, Value> {
+        let started = Instant::now();
+        match tokio::time::timeout(
+            self.config.health_timeout,
+            self.directory.is_local_domain("healthz.invalid"),
+        )
+        .await
+        {
+            Ok(Ok(_)) => Ok(check_ok(started)),
+            Ok(Err(err)) => Err(check_error(started, format!("{err:?}"))),
+            Err(_) => Err(check_timeout(started)),
+        }
+    }
+
+    pub(crate) async fn check_queue(&self) -> Result<Value, Value> {
+        let started = Instant::now();
+
+        // Count messages whose delivery is overdue by more than the configured lag
+        let (result_tx, result_rx) = oneshot::channel();
+        let before = started
+            .checked_sub(self.config.health_queue_lag)
+            .unwrap_or(started);
+        if self
+            .smtp
+            .queue
+            .tx
+            .send(queue::Event::Manage(QueueRequest::Overdue {
+                before,
+                result_tx,
+            }))
+            .await
+            .is_err()
+        {
+            return Err(check_error(
+                started,
+                "Queue manager is not running.".to_string(),
+            ));
+        }
+
+        match tokio::time::timeout(self.config.health_timeout, result_rx).await {
+            Ok(Ok(overdue)) if overdue <= self.config.health_queue_max_overdue => {
+                let mut result = check_ok(started);
+                result["overdue"] = overdue.into();
+                Ok(result)
+            }
+            Ok(Ok(overdue)) => {
+                let mut result = check_error(
+                    started,
+                    format!("{overdue} messages are overdue for delivery."),
+                );
+                result["overdue"] = overdue.into();
+                Err(result)
+            }
+            Ok(Err(_)) => Err(check_error(
+                started,
+                "Queue manager dropped the request.".to_string(),
+            )),
+            Err(_) => Err(check_timeout(started)),
+        }
+    }
+}
+
+fn check_ok(started: Instant) -> Value {
+    json!({
+        "status": "ok",
+        "latencyMs": elapsed_ms(started),
+    })
+}
+
+fn check_error(started: Instant, error: String) -> Value {
+    json!({
+        "status": "error",
+        "latencyMs": elapsed_ms(started),
+        "error": error,
+    })
+}
+
+fn check_timeout(started: Instant) -> Value {
+    check_error(started, "Timed out.".to_string())
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
@@ -53,7 +53,8 @@ use crate::{
 };
 
 use super::{
-    session::Session, HtmlResponse, HttpRequest, HttpResponse, JmapSessionManager, JsonResponse,
+    session::Session, CsvResponse, HtmlResponse, HttpRequest, HttpResponse, JmapSessionManager,
+    JsonResponse,
 };
 
 pub async fn parse_jmap_request(
@@ -81,6 +82,17 @@ pub async fn parse_jmap_request(
                 Err(err) => return err.into_http_response(),
             };
 
+            // Enforce concurrency limits shared with other protocols
+            let in_flight_account = if let Some(in_flight) = instance
+                .connections
+                .is_account_allowed(access_token.primary_id(), instance.max_connections_account)
+            {
+                in_flight
+            } else {
+                return RequestError::limit(RequestLimitError::ConcurrentRequest)
+                    .into_http_response();
+            };
+
             match (path.next().unwrap_or(""), req.method()) {
                 ("", &Method::POST) => {
                     return match fetch_body(&mut req, jmap.config.request_max_size, &access_token)
@@ -163,11 +175,19 @@ pub async fn parse_jmap_request(
                     }
                 }
                 ("eventsource", &Method::GET) => {
-                    return jmap.handle_event_source(req, access_token).await
+                    return jmap
+                        .handle_event_source(req, access_token, in_flight_account)
+                        .await
                 }
                 ("ws", &Method::GET) => {
-                    return upgrade_websocket_connection(jmap, req, access_token, instance.clone())
-                        .await;
+                    return upgrade_websocket_connection(
+                        jmap,
+                        req,
+                        access_token,
+                        instance.clone(),
+                        in_flight_account,
+                    )
+                    .await;
                 }
                 (_, &Method::OPTIONS) => {
                     return ().into_http_response();
@@ -193,7 +213,7 @@ pub async fn parse_jmap_request(
             ("oauth-authorization-server", &Method::GET) => {
                 let remote_addr = jmap.build_remote_addr(&req, remote_ip);
                 // Limit anonymous requests
-                return match jmap.is_anonymous_allowed(&remote_addr) {
+                return match jmap.is_anonymous_allowed(&remote_addr).await {
                     Ok(_) => {
                         JsonResponse::new(OAuthMetadata::new(&instance.data)).into_http_response()
                     }
@@ -210,13 +230,13 @@ pub async fn parse_jmap_request(
 
             match (path.next().unwrap_or(""), req.method()) {
                 ("", &Method::GET) => {
-                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                    return match jmap.is_anonymous_allowed(&remote_addr).await {
                         Ok(_) => jmap.handle_user_device_auth(&mut req).await,
                         Err(err) => err.into_http_response(),
                     }
                 }
                 ("", &Method::POST) => {
-                    return match jmap.is_auth_allowed_soft(&remote_addr) {
+                    return match jmap.is_auth_allowed_soft(&remote_addr).await {
                         Ok(_) => {
                             jmap.handle_user_device_auth_post(&mut req, &remote_addr)
                                 .await
@@ -225,13 +245,13 @@ pub async fn parse_jmap_request(
                     }
                 }
                 ("code", &Method::GET) => {
-                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                    return match jmap.is_anonymous_allowed(&remote_addr).await {
                         Ok(_) => jmap.handle_user_code_auth(&mut req).await,
                         Err(err) => err.into_http_response(),
                     }
                 }
                 ("code", &Method::POST) => {
-                    return match jmap.is_auth_allowed_soft(&remote_addr) {
+                    return match jmap.is_auth_allowed_soft(&remote_addr).await {
                         Ok(_) => {
                             jmap.handle_user_code_auth_post(&mut req, &remote_addr)
                                 .await
@@ -240,23 +260,49 @@ pub async fn parse_jmap_request(
                     }
                 }
                 ("device", &Method::POST) => {
-                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                    return match jmap.is_anonymous_allowed(&remote_addr).await {
                         Ok(_) => jmap.handle_device_auth(&mut req, instance).await,
                         Err(err) => err.into_http_response(),
                     }
                 }
                 ("token", &Method::POST) => {
-                    return match jmap.is_anonymous_allowed(&remote_addr) {
-                        Ok(_) => jmap.handle_token_request(&mut req).await,
+                    return match jmap.is_anonymous_allowed(&remote_addr).await {
+                        Ok(_) => jmap.handle_token_request(&mut req, &remote_addr).await,
                         Err(err) => err.into_http_response(),
                     }
                 }
+                ("reset", &Method::POST) => {
+                    let action = path.next().unwrap_or("").to_string();
+                    return match jmap.is_auth_allowed_hard(&remote_addr).await {
+                        Ok(_) => {
+                            jmap.handle_password_reset(&mut req, &action, &remote_addr)
+                                .await
+                        }
+                        Err(err) => err.into_http_response(),
+                    };
+                }
                 (_, &Method::OPTIONS) => {
                     return ().into_http_response();
                 }
                 _ => (),
             }
         }
+        endpoint @ ("healthz" | "readyz") if req.method() == Method::GET => {
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+            return match jmap.is_anonymous_allowed(&remote_addr).await {
+                Ok(_) => jmap.handle_health_request(endpoint == "readyz").await,
+                Err(err) => err.into_http_response(),
+            };
+        }
+        "status" if req.method() == Method::GET => {
+            return match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) if access_token.is_super_user() => {
+                    jmap.handle_status_request().await
+                }
+                Ok(_) => RequestError::unauthorized().into_http_response(),
+                Err(err) => err.into_http_response(),
+            };
+        }
         "crypto" if jmap.config.encrypt => {
             let remote_addr = jmap.build_remote_addr(&req, remote_ip);
 
@@ -265,7 +311,7 @@ pub async fn parse_jmap_request(
                     return jmap.handle_crypto_update(&mut req, &remote_addr).await;
                 }
                 Method::POST => {
-                    return match jmap.is_auth_allowed_soft(&remote_addr) {
+                    return match jmap.is_auth_allowed_soft(&remote_addr).await {
                         Ok(_) => jmap.handle_crypto_update(&mut req, &remote_addr).await,
                         Err(err) => err.into_http_response(),
                     }
@@ -273,17 +319,57 @@ pub async fn parse_jmap_request(
                 _ => (),
             }
         }
+        "devices" => {
+            return match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => {
+                    jmap.handle_devices_request(&req, &access_token).await
+                }
+                Ok(None) => RequestError::unauthorized().into_http_response(),
+                Err(err) => err.into_http_response(),
+            };
+        }
+        "send-as" => {
+            return match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => {
+                    jmap.handle_send_as_request(&req, &access_token).await
+                }
+                Ok(None) => RequestError::unauthorized().into_http_response(),
+                Err(err) => err.into_http_response(),
+            };
+        }
+        "recovery" => {
+            return match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => {
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    jmap.handle_recovery_request(&mut req, &access_token, &remote_addr)
+                        .await
+                }
+                Ok(None) => RequestError::unauthorized().into_http_response(),
+                Err(err) => err.into_http_response(),
+            };
+        }
+        "sessions" => {
+            return match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => {
+                    jmap.handle_sessions_request(&req, path, &access_token)
+                        .await
+                }
+                Ok(None) => RequestError::unauthorized().into_http_response(),
+                Err(err) => err.into_http_response(),
+            };
+        }
         "admin" => {
             // Make sure the user is a superuser
-            let body = match jmap.authenticate_headers(&req, remote_ip).await {
-                Ok(Some((_, access_token))) if access_token.is_super_user() => {
-                    fetch_body(&mut req, 8192, &access_token).await
-                }
+            let (body, access_token) = match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) if access_token.is_super_user() => (
+                    fetch_body(&mut req, 8192, &access_token).await,
+                    access_token,
+                ),
                 Ok(_) => return RequestError::unauthorized().into_http_response(),
                 Err(err) => return err.into_http_response(),
             };
 
-            return jmap.handle_manage_request(&req, body).await;
+            return jmap.handle_manage_request(&req, body, &access_token).await;
         }
         _ => (),
     }
@@ -343,8 +429,9 @@ async fn handle_request<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
 ) {
     let span = session.span;
     let _in_flight = session.in_flight;
+    let mut shutdown_rx = session.instance.shutdown_rx.clone();
 
-    if let Err(http_err) = http1::Builder::new()
+    let conn = http1::Builder::new()
         .keep_alive(true)
         .serve_connection(
             TokioIo::new(session.stream),
@@ -377,9 +464,19 @@ async fn handle_request<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
                 }
             }),
         )
-        .with_upgrades()
-        .await
-    {
+        .with_upgrades();
+    tokio::pin!(conn);
+
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+        _ = shutdown_rx.changed() => {
+            // Finish the request in progress and close the connection
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+
+    if let Err(http_err) = result {
         tracing::debug!(
             parent: &span,
             event = "error",
@@ -525,6 +622,36 @@ impl ToHttpResponse for HtmlResponse {
     }
 }
 
+impl CsvResponse {
+    pub fn new(filename: impl Into<String>, body: String) -> Self {
+        CsvResponse {
+            filename: filename.into(),
+            body,
+        }
+    }
+}
+
+impl ToHttpResponse for CsvResponse {
+    fn into_http_response(self) -> HttpResponse {
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}\"",
+                    self.filename.replace('\"', "\\\"")
+                ),
+            )
+            .body(
+                Full::new(Bytes::from(self.body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
+
 impl ToHttpResponse for () {
     fn into_http_response(self) -> HttpResponse {
         hyper::Response::builder()
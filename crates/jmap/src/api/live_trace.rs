@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use http_body_util::{combinators::BoxBody, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    header, StatusCode,
+};
+use jmap_proto::error::request::RequestError;
+use utils::{
+    config::utils::ParseValue,
+    live_trace::{LiveTracer, TraceFilter},
+};
+
+use crate::JMAP;
+
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse};
+
+const DEFAULT_TRACE_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_TRACE_DURATION: Duration = Duration::from_secs(60 * 60);
+const TRACE_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+impl JMAP {
+    pub async fn handle_live_trace(&self, req: &HttpRequest) -> HttpResponse {
+        // Parse query
+        let mut filter = None;
+        let mut duration = DEFAULT_TRACE_DURATION;
+
+        for (key, value) in form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        {
+            match key.as_ref() {
+                "ip" => match value.parse() {
+                    Ok(ip) => {
+                        filter = TraceFilter::RemoteIp(ip).into();
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                "account" if !value.is_empty() => {
+                    filter = TraceFilter::Account(value.into_owned()).into();
+                }
+                "queue-id" => match value.parse() {
+                    Ok(id) => {
+                        filter = TraceFilter::QueueId(id).into();
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                "duration" => match Duration::parse_value("duration", value.as_ref()) {
+                    Ok(value) => {
+                        duration = std::cmp::min(value, MAX_TRACE_DURATION);
+                    }
+                    Err(_) => return RequestError::invalid_parameters().into_http_response(),
+                },
+                _ => {}
+            }
+        }
+
+        let filter = if let Some(filter) = filter {
+            filter
+        } else {
+            return RequestError::blank(
+                StatusCode::BAD_REQUEST.as_u16(),
+                "Invalid parameters",
+                "One of 'ip', 'account' or 'queue-id' is required.",
+            )
+            .into_http_response();
+        };
+
+        tracing::info!(
+            context = "live-trace",
+            event = "start",
+            filter = ?filter,
+            duration = duration.as_secs(),
+            "Live trace started."
+        );
+
+        let mut trace = LiveTracer::get().subscribe(filter, duration);
+
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(BoxBody::new(StreamBody::new(async_stream::stream! {
+                let expires = tokio::time::Instant::from_std(trace.expires);
+
+                loop {
+                    let timeout = std::cmp::min(
+                        expires + Duration::from_millis(1),
+                        tokio::time::Instant::now() + TRACE_PING_INTERVAL,
+                    );
+                    match tokio::time::timeout_at(timeout, trace.rx.recv()).await {
+                        Ok(Some(event)) => {
+                            yield Ok(Frame::data(Bytes::from(format!(
+                                "event: trace\ndata: {event}\n\n"
+                            ))));
+                        }
+                        Ok(None) => break,
+                        Err(_) => {
+                            if tokio::time::Instant::now() >= expires {
+                                yield Ok(Frame::data(Bytes::from_static(
+                                    b"event: expired\ndata: {}\n\n",
+                                )));
+                                break;
+                            } else {
+                                yield Ok(Frame::data(Bytes::from_static(
+                                    b"event: ping\ndata: {}\n\n",
+                                )));
+                            }
+                        }
+                    }
+                }
+            })))
+            .unwrap()
+    }
+}
@@ -28,14 +28,20 @@ use jmap_proto::types::{id::Id, state::State, type_state::DataType};
 use serde::Serialize;
 use utils::map::vec_map::VecMap;
 
-use crate::JMAP;
+use crate::{push::PushPreview, JMAP};
 
 pub mod admin;
 pub mod config;
+pub mod dashboard;
+pub mod discovery;
 pub mod event_source;
+pub mod health;
 pub mod http;
+pub mod live_trace;
+pub mod redact;
 pub mod request;
 pub mod session;
+pub mod status;
 
 #[derive(Clone)]
 pub struct JmapSessionManager {
@@ -58,6 +64,11 @@ pub struct HtmlResponse {
     body: String,
 }
 
+pub struct CsvResponse {
+    filename: String,
+    body: String,
+}
+
 pub type HttpRequest = hyper::Request<hyper::body::Incoming>;
 pub type HttpResponse =
     hyper::Response<http_body_util::combinators::BoxBody<hyper::body::Bytes, hyper::Error>>;
@@ -72,6 +83,8 @@ pub struct StateChangeResponse {
     #[serde(rename = "@type")]
     pub type_: StateChangeType,
     pub changed: VecMap<Id, VecMap<DataType, State>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previews: Vec<PushPreview>,
 }
 
 impl StateChangeResponse {
@@ -79,6 +92,7 @@ impl StateChangeResponse {
         Self {
             type_: StateChangeType::StateChange,
             changed: VecMap::new(),
+            previews: Vec::new(),
         }
     }
 }
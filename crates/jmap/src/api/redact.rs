@@ -0,0 +1,492 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use hyper::StatusCode;
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    method::query::{QueryRequest, RequestArguments},
+    object::email::QueryArguments,
+    parser::{json::Parser, JsonObjectParser},
+    request::method::MethodObject,
+    types::{
+        collection::Collection, id::Id, property::Property, state::StateChange,
+        type_state::DataType,
+    },
+};
+use mail_parser::{HeaderName, HeaderValue};
+use store::{
+    ahash::AHashSet,
+    blake3,
+    write::{log::ChangeLogBuilder, now},
+    BlobHash,
+};
+
+use crate::{
+    auth::AccessToken,
+    email::{
+        index::{AddressElement, VisitValues},
+        metadata::MessageMetadata,
+    },
+    Bincode, JMAP,
+};
+
+use super::{http::ToHttpResponse, HttpResponse, JsonResponse};
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct RedactRequest {
+    #[serde(default)]
+    ids: Vec<Id>,
+    #[serde(default)]
+    filter: Option<serde_json::Value>,
+    #[serde(default, rename = "keepEnvelope")]
+    keep_envelope: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RedactionReport {
+    pub id: String,
+    pub account: String,
+    #[serde(rename = "accountId")]
+    pub account_id: u32,
+    #[serde(rename = "requestedBy")]
+    pub requested_by: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    #[serde(
+        default,
+        rename = "filterMatches",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub filter_matches: Option<usize>,
+    pub messages: Vec<RedactedMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RedactedMessage {
+    pub id: Id,
+    pub status: RedactionStatus,
+    #[serde(default, rename = "blobHash", skip_serializing_if = "Option::is_none")]
+    pub blob_hash: Option<String>,
+    #[serde(default, rename = "blobPurged")]
+    pub blob_purged: bool,
+    #[serde(default, rename = "ftsPurged")]
+    pub fts_purged: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<RedactedEnvelope>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RedactionStatus {
+    #[serde(rename = "redacted")]
+    Redacted,
+    #[serde(rename = "notFound")]
+    NotFound,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct RedactedEnvelope {
+    pub from: Vec<String>,
+    pub to: Vec<String>,
+    #[serde(default, rename = "messageId", skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(default, rename = "sentAt", skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<i64>,
+    #[serde(rename = "receivedAt")]
+    pub received_at: u64,
+    pub size: usize,
+}
+
+impl JMAP {
+    pub async fn handle_email_redact(
+        &self,
+        account_id: u32,
+        account_name: &str,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        // Parse request
+        let body = body.unwrap_or_default();
+        let request = match serde_json::from_slice::<RedactRequest>(&body) {
+            Ok(request) => request,
+            Err(err) => {
+                return RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Invalid parameters",
+                    err.to_string(),
+                )
+                .into_http_response();
+            }
+        };
+
+        // Messages can be selected by id, by an Email/query filter or both
+        let mut ids = request.ids;
+        let mut filter_matches = None;
+        if request.filter.is_some() {
+            let mut parser = Parser::new(&body);
+            parser.ctx = MethodObject::Email;
+            let mut query: QueryRequest<QueryArguments> =
+                match QueryRequest::<RequestArguments>::parse(&mut parser) {
+                    Ok(mut query) => match query.take_arguments() {
+                        RequestArguments::Email(arguments) => query.with_arguments(arguments),
+                        _ => unreachable!(),
+                    },
+                    Err(err) => {
+                        return RequestError::from(err).into_http_response();
+                    }
+                };
+            query.account_id = Id::from(account_id);
+            query.limit = Some(self.config.query_max_results);
+            query.calculate_total = Some(true);
+
+            // Page through the results, a single query is capped at query_max_results
+            let mut position = 0;
+            loop {
+                query.position = Some(position as i32);
+                match self.email_query(query.clone(), access_token).await {
+                    Ok(response) => {
+                        let total = response.total.unwrap_or_default();
+                        let page_len = response.ids.len();
+                        for id in response.ids {
+                            if !ids.iter().any(|i| i.document_id() == id.document_id()) {
+                                ids.push(id);
+                            }
+                        }
+                        position += page_len;
+                        filter_matches = Some(total);
+                        if page_len == 0 || position >= total {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Query failed",
+                            err.to_string(),
+                        )
+                        .into_http_response();
+                    }
+                }
+            }
+        }
+        if ids.is_empty() {
+            return RequestError::blank(
+                StatusCode::BAD_REQUEST.as_u16(),
+                "Invalid parameters",
+                "No messages were selected for redaction.",
+            )
+            .into_http_response();
+        }
+
+        match self
+            .email_redact(
+                account_id,
+                account_name,
+                ids,
+                filter_matches,
+                request.keep_envelope,
+                &access_token.name,
+            )
+            .await
+        {
+            Ok(report) => JsonResponse::new(serde_json::json!({
+                "data": report,
+            }))
+            .into_http_response(),
+            Err(err) => RequestError::blank(
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                "Redaction failed",
+                err.to_string(),
+            )
+            .into_http_response(),
+        }
+    }
+
+    /// Permanently removes the selected messages, their full-text index
+    /// entries and, once no other message references them, their blobs.
+    /// The change log only records ids so no message contents remain there.
+    /// Messages that fail are reported as such without aborting the others.
+    pub async fn email_redact(
+        &self,
+        account_id: u32,
+        account_name: &str,
+        ids: Vec<Id>,
+        filter_matches: Option<usize>,
+        keep_envelope: bool,
+        requested_by: &str,
+    ) -> Result<RedactionReport, MethodError> {
+        let mut changes = ChangeLogBuilder::new();
+        let mut messages = Vec::with_capacity(ids.len());
+        let mut purged_hashes = AHashSet::new();
+
+        for id in ids {
+            let document_id = id.document_id();
+            let mut message = RedactedMessage {
+                id,
+                status: RedactionStatus::NotFound,
+                blob_hash: None,
+                blob_purged: false,
+                fts_purged: false,
+                envelope: None,
+            };
+
+            // Obtain metadata before it is removed
+            let metadata = match self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::BodyStructure,
+                )
+                .await
+            {
+                Ok(Some(metadata)) => metadata.inner,
+                Ok(None) => {
+                    messages.push(message);
+                    continue;
+                }
+                Err(_) => {
+                    message.status = RedactionStatus::Failed;
+                    messages.push(message);
+                    continue;
+                }
+            };
+            if keep_envelope {
+                message.envelope = RedactedEnvelope::from(&metadata).into();
+            }
+            message.blob_hash = blob_hash_to_hex(&metadata.blob_hash).into();
+
+            // Delete message, redacted messages are never recoverable
+            match self
+                .email_delete_with_retention(account_id, document_id, None)
+                .await
+            {
+                Ok(Ok(change)) => {
+                    changes.merge(change);
+                    message.status = RedactionStatus::Redacted;
+                }
+                Ok(Err(_)) | Err(_) => {
+                    message.status = RedactionStatus::Failed;
+                    messages.push(message);
+                    continue;
+                }
+            }
+
+            // Remove message from the FTS index without waiting for the indexer
+            match self
+                .fts_store
+                .remove(account_id, Collection::Email.into(), document_id)
+                .await
+            {
+                Ok(_) => {
+                    message.fts_purged = true;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "redact",
+                        event = "error",
+                        account_id = account_id,
+                        document_id = document_id,
+                        reason = ?err,
+                        "Failed to remove message from FTS index"
+                    );
+                }
+            }
+
+            // Delete blob unless other messages still reference it
+            if purged_hashes.contains(&metadata.blob_hash) {
+                message.blob_purged = true;
+            } else {
+                // Pending uploads of the same contents by this account are dropped as well
+                let result = match self
+                    .store
+                    .blob_hash_unreserve(account_id, &metadata.blob_hash)
+                    .await
+                {
+                    Ok(_) => {
+                        self.store
+                            .blob_hash_purge(&self.blob_store, &metadata.blob_hash)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok(purged) => {
+                        if purged {
+                            purged_hashes.insert(metadata.blob_hash.clone());
+                        }
+                        message.blob_purged = purged;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "redact",
+                            event = "error",
+                            account_id = account_id,
+                            document_id = document_id,
+                            reason = ?err,
+                            "Failed to purge blob"
+                        );
+                    }
+                }
+            }
+
+            messages.push(message);
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id)
+                    .with_change(DataType::Email, change_id)
+                    .with_change(DataType::Mailbox, change_id)
+                    .with_change(DataType::Thread, change_id),
+            )
+            .await;
+        }
+
+        // Build signed report
+        let mut report = RedactionReport {
+            id: self.generate_snowflake_id()?.to_string(),
+            account: account_name.to_string(),
+            account_id,
+            requested_by: requested_by.to_string(),
+            created_at: now(),
+            filter_matches,
+            messages,
+            signature: None,
+        };
+        report.signature = self.redaction_report_signature(&report).into();
+
+        // The report holds the details, only counts are logged so that the
+        // erased data does not end up in the logs
+        let count = |status: RedactionStatus| {
+            report
+                .messages
+                .iter()
+                .filter(|m| m.status == status)
+                .count()
+        };
+        tracing::info!(
+            context = "audit",
+            event = "redact",
+            report_id = %report.id,
+            redacted = count(RedactionStatus::Redacted),
+            not_found = count(RedactionStatus::NotFound),
+            failed = count(RedactionStatus::Failed),
+            blobs_purged = report.messages.iter().filter(|m| m.blob_purged).count(),
+            "Messages redacted."
+        );
+
+        Ok(report)
+    }
+
+    /// Verifies that a deletion report was issued by this server and has not
+    /// been modified since.
+    pub fn redaction_report_verify(&self, report: &RedactionReport) -> bool {
+        report
+            .signature
+            .as_ref()
+            .and_then(|signature| blake3::Hash::from_hex(signature).ok())
+            .is_some_and(|signature| signature == self.redaction_report_hash(report))
+    }
+
+    fn redaction_report_signature(&self, report: &RedactionReport) -> String {
+        self.redaction_report_hash(report).to_hex().to_string()
+    }
+
+    fn redaction_report_hash(&self, report: &RedactionReport) -> blake3::Hash {
+        // The signature covers the report serialized with sorted keys
+        // and without the signature itself
+        let mut contents = serde_json::to_value(report).unwrap_or_default();
+        if let Some(contents) = contents.as_object_mut() {
+            contents.remove("signature");
+        }
+
+        blake3::keyed_hash(
+            &blake3::derive_key(
+                "Stalwart Mail Server redaction report",
+                self.config.oauth_key.as_bytes(),
+            ),
+            &serde_json::to_vec(&contents).unwrap_or_default(),
+        )
+    }
+}
+
+impl From<&MessageMetadata<'_>> for RedactedEnvelope {
+    fn from(metadata: &MessageMetadata<'_>) -> Self {
+        let mut envelope = RedactedEnvelope {
+            received_at: metadata.received_at,
+            size: metadata.size,
+            ..Default::default()
+        };
+
+        for header in &metadata.contents.root_part().headers {
+            match &header.name {
+                HeaderName::From | HeaderName::To => {
+                    let addresses = if header.name == HeaderName::From {
+                        &mut envelope.from
+                    } else {
+                        &mut envelope.to
+                    };
+                    if addresses.is_empty() {
+                        header.value.visit_addresses(|element, value| {
+                            if element == AddressElement::Address {
+                                addresses.push(value.to_string());
+                            }
+                        });
+                    }
+                }
+                HeaderName::MessageId if envelope.message_id.is_none() => {
+                    header.value.visit_text(|id| {
+                        if envelope.message_id.is_none() {
+                            envelope.message_id = id.to_string().into();
+                        }
+                    });
+                }
+                HeaderName::Date if envelope.sent_at.is_none() => {
+                    if let HeaderValue::DateTime(datetime) = &header.value {
+                        envelope.sent_at = datetime.to_timestamp().into();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        envelope
+    }
+}
+
+fn blob_hash_to_hex(hash: &BlobHash) -> String {
+    use std::fmt::Write;
+
+    let hash: &[u8] = hash.as_ref();
+    hash.iter()
+        .fold(String::with_capacity(hash.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
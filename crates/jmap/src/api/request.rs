@@ -92,6 +92,18 @@ impl JMAP {
                                     self.broadcast_state_change(state_change).await;
                                 }
                             }
+                            ResponseMethod::SplitThread(split_response) => {
+                                // Publish state changes
+                                if let Some(state_change) = split_response.state_change.take() {
+                                    self.broadcast_state_change(state_change).await;
+                                }
+                            }
+                            ResponseMethod::MergeThread(merge_response) => {
+                                // Publish state changes
+                                if let Some(state_change) = merge_response.state_change.take() {
+                                    self.broadcast_state_change(state_change).await;
+                                }
+                            }
                             ResponseMethod::UploadBlob(upload_response) => {
                                 // Add created blobIds
                                 upload_response.update_created_ids(&mut response);
@@ -186,6 +198,31 @@ impl JMAP {
 
                     self.quota_get(req, access_token).await?.into()
                 }
+                get::RequestArguments::MaskedEmail => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.masked_email_get(req).await?.into()
+                }
+                get::RequestArguments::DeliveryStatus => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.delivery_status_get(req).await?.into()
+                }
+                get::RequestArguments::ListFiling => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.list_filing_get(req).await?.into()
+                }
+                get::RequestArguments::Label => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.label_get(req).await?.into()
+                }
+                get::RequestArguments::Activity => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.activity_get(req).await?.into()
+                }
                 get::RequestArguments::Blob(arguments) => {
                     access_token.assert_is_member(req.account_id)?;
 
@@ -233,6 +270,11 @@ impl JMAP {
 
                     self.quota_query(req, access_token).await?.into()
                 }
+                query::RequestArguments::Activity => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.activity_query(req).await?.into()
+                }
             },
             RequestMethod::Set(mut req) => match req.take_arguments() {
                 set::RequestArguments::Email => {
@@ -274,6 +316,26 @@ impl JMAP {
 
                     self.vacation_response_set(req).await?.into()
                 }
+                set::RequestArguments::MaskedEmail => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.masked_email_set(req, access_token).await?.into()
+                }
+                set::RequestArguments::ListFiling => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.list_filing_set(req).await?.into()
+                }
+                set::RequestArguments::Label => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.label_set(req).await?.into()
+                }
+                set::RequestArguments::Activity => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.activity_set(req).await?.into()
+                }
             },
             RequestMethod::Changes(req) => self.changes(req, access_token).await?.into(),
             RequestMethod::Copy(req) => {
@@ -288,6 +350,21 @@ impl JMAP {
 
                 self.email_import(req, access_token).await?.into()
             }
+            RequestMethod::UnsubscribeEmail(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.email_unsubscribe(req, next_call).await?.into()
+            }
+            RequestMethod::SplitThread(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.thread_split(req).await?.into()
+            }
+            RequestMethod::MergeThread(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.thread_merge(req).await?.into()
+            }
             RequestMethod::ParseEmail(req) => {
                 access_token.assert_has_access(req.account_id, Collection::Email)?;
 
@@ -304,6 +381,11 @@ impl JMAP {
 
                 self.sieve_script_validate(req, access_token).await?.into()
             }
+            RequestMethod::TestScript(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.sieve_script_test(req, access_token).await?.into()
+            }
             RequestMethod::CopyBlob(req) => {
                 access_token.assert_is_member(req.account_id)?;
 
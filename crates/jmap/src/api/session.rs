@@ -307,6 +307,16 @@ impl crate::Config {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add masked e-mail capabilities
+        self.capabilities.session.append(
+            Capability::MaskedEmail,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::MaskedEmail,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
     }
 }
 
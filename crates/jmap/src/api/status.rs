@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
+};
+
+use hyper::StatusCode;
+use serde_json::{json, Map, Value};
+use smtp::{
+    core::{management::QueueRequest, SMTP},
+    queue,
+};
+use tokio::sync::oneshot;
+use utils::{config::ServerProtocol, stats::ServerStats};
+
+use crate::JMAP;
+
+use super::{http::ToHttpResponse, HttpResponse, JsonResponse};
+
+// Increase when fields are removed or change meaning, new fields may be added at any time
+const STATUS_SCHEMA_VERSION: u32 = 1;
+
+impl JMAP {
+    pub async fn handle_status_request(&self) -> HttpResponse {
+        let (is_available, status) = server_status(&self.smtp, self.config.health_timeout).await;
+
+        JsonResponse::with_status(
+            if is_available {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            status,
+        )
+        .into_http_response()
+    }
+}
+
+pub(crate) async fn server_status(smtp: &SMTP, timeout: Duration) -> (bool, Value) {
+    let stats = ServerStats::get();
+
+    // Sessions by protocol, all protocols are always listed
+    let mut sessions = Map::new();
+    for protocol in [
+        ServerProtocol::Smtp,
+        ServerProtocol::Lmtp,
+        ServerProtocol::Imap,
+        ServerProtocol::Jmap,
+        ServerProtocol::Http,
+        ServerProtocol::ManageSieve,
+    ] {
+        sessions.insert(protocol.to_string(), 0.into());
+    }
+    for (protocol, concurrent) in stats.sessions() {
+        sessions.insert(protocol.to_string(), concurrent.into());
+    }
+
+    // Queue depth by state
+    let (result_tx, result_rx) = oneshot::channel();
+    let queue_stats = if smtp
+        .queue
+        .tx
+        .send(queue::Event::Manage(QueueRequest::Stats { result_tx }))
+        .await
+        .is_ok()
+    {
+        tokio::time::timeout(timeout, result_rx)
+            .await
+            .ok()
+            .and_then(|result| result.ok())
+    } else {
+        None
+    };
+    let queue = if let Some(queue_stats) = &queue_stats {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        json!({
+            "scheduled": queue_stats.scheduled,
+            "deferred": queue_stats.deferred,
+            "onHold": queue_stats.on_hold,
+            "inFlight": smtp.queue.workers.concurrent.load(Ordering::Relaxed),
+            "total": queue_stats.scheduled + queue_stats.deferred + queue_stats.on_hold,
+            "oldestAgeSecs": queue_stats
+                .oldest_created
+                .map(|created| now.saturating_sub(created)),
+        })
+    } else {
+        Value::Null
+    };
+
+    let tls_handshakes = stats.tls_handshakes();
+    let tls_resumed = stats.tls_resumed();
+
+    (
+        queue_stats.is_some(),
+        json!({
+            "schemaVersion": STATUS_SCHEMA_VERSION,
+            "server": {
+                "version": env!("CARGO_PKG_VERSION"),
+                "uptimeSecs": stats.uptime(),
+            },
+            "queue": queue,
+            "sessions": sessions,
+            "auth": {
+                "failures": stats.auth_failures(),
+                "failuresLastMinute": stats.auth_failures_last_minute(),
+            },
+            "outboundTls": {
+                "handshakes": tls_handshakes,
+                "resumed": tls_resumed,
+                "resumptionRate": (tls_handshakes > 0)
+                    .then(|| tls_resumed as f64 / tls_handshakes as f64),
+            },
+        }),
+    )
+}
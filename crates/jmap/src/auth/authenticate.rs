@@ -32,7 +32,7 @@ use hyper::header;
 use jmap_proto::error::request::RequestError;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::{listener::limiter::InFlight, map::ttl_dashmap::TtlMap};
+use utils::{listener::limiter::InFlight, map::ttl_dashmap::TtlMap, stats::ServerStats};
 
 use crate::JMAP;
 
@@ -50,13 +50,22 @@ impl JMAP {
             .and_then(|h| h.to_str().ok())
             .and_then(|h| h.split_once(' ').map(|(l, t)| (l, t.trim().to_string())))
         {
-            let session = if let Some(account_id) = self.sessions.get_with_ttl(&token) {
+            let cached_id = match self.sessions.get_with_ttl(&token) {
+                Some(account_id) if self.is_session_revoked(account_id).await => {
+                    // Revoked on another node, validate the token again
+                    self.sessions.remove(&token);
+                    self.access_tokens.remove(&account_id);
+                    None
+                }
+                cached_id => cached_id,
+            };
+            let session = if let Some(account_id) = cached_id {
                 self.get_cached_access_token(account_id).await
             } else {
                 let addr = self.build_remote_addr(req, remote_ip);
                 if mechanism.eq_ignore_ascii_case("basic") {
                     // Enforce rate limit for authentication requests
-                    self.is_auth_allowed_soft(&addr)?;
+                    self.is_auth_allowed_soft(&addr).await?;
 
                     // Decode the base64 encoded credentials
                     if let Some((account, secret)) = base64_decode(token.as_bytes())
@@ -67,7 +76,13 @@ impl JMAP {
                             })
                         })
                     {
-                        self.authenticate_plain(&account, &secret, &addr).await
+                        let client = req
+                            .headers()
+                            .get(header::USER_AGENT)
+                            .and_then(|h| h.to_str().ok())
+                            .unwrap_or("JMAP");
+                        self.authenticate_plain(&account, &secret, &addr, client)
+                            .await
                     } else {
                         tracing::debug!(
                             context = "authenticate_headers",
@@ -78,10 +93,10 @@ impl JMAP {
                     }
                 } else if mechanism.eq_ignore_ascii_case("bearer") {
                     // Enforce anonymous rate limit for bearer auth requests
-                    self.is_anonymous_allowed(&addr)?;
+                    self.is_anonymous_allowed(&addr).await?;
 
                     match self.validate_access_token("access_token", &token).await {
-                        Ok((account_id, _, _)) => self.get_access_token(account_id).await,
+                        Ok(token) => self.get_access_token(token.account_id).await,
                         Err(err) => {
                             tracing::debug!(
                                 context = "authenticate_headers",
@@ -93,7 +108,7 @@ impl JMAP {
                     }
                 } else {
                     // Enforce anonymous rate limit
-                    self.is_anonymous_allowed(&addr)?;
+                    self.is_anonymous_allowed(&addr).await?;
                     None
                 }
                 .map(|access_token| {
@@ -106,13 +121,14 @@ impl JMAP {
 
             if let Some(session) = session {
                 // Enforce authenticated rate limit
-                Ok(Some((self.is_account_allowed(&session)?, session)))
+                Ok(Some((self.is_account_allowed(&session).await?, session)))
             } else {
                 Ok(None)
             }
         } else {
             // Enforce anonymous rate limit
-            self.is_anonymous_allowed(&self.build_remote_addr(req, remote_ip))?;
+            self.is_anonymous_allowed(&self.build_remote_addr(req, remote_ip))
+                .await?;
 
             Ok(None)
         }
@@ -171,6 +187,7 @@ impl JMAP {
         username: &str,
         secret: &str,
         remote_addr: &RemoteAddress,
+        client: &str,
     ) -> Option<AccessToken> {
         match self
             .directory
@@ -183,9 +200,13 @@ impl JMAP {
             )
             .await
         {
-            Ok(Some(principal)) => AccessToken::new(principal).into(),
+            Ok(Some(principal)) => {
+                self.track_login(&principal, remote_addr, client).await;
+                AccessToken::new(principal).into()
+            }
             Ok(None) => {
-                let _ = self.is_auth_allowed_hard(remote_addr);
+                ServerStats::get().record_auth_failure();
+                let _ = self.is_auth_allowed_hard(remote_addr).await;
                 None
             }
             Err(_) => None,
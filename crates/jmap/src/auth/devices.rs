@@ -0,0 +1,443 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, time::Duration};
+
+use directory::Principal;
+use hyper::{Method, StatusCode};
+use jmap_proto::error::request::RequestError;
+use mail_builder::{headers::date::Date, MessageBuilder};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use smtp::core::{NullIo, Session, SessionAddress};
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, ValueClass},
+    LookupValue, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+use utils::config::Config;
+
+use crate::{
+    activity::{Activity, ActivityType},
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    Bincode, JMAP,
+};
+
+use super::{rate_limit::RemoteAddress, AccessToken};
+
+const DEVICES_KEY_PREFIX: &[u8] = b"auth.devices.";
+
+// Known devices are only refreshed once a day to avoid a write on every login
+const DEVICE_REFRESH_INTERVAL: u64 = 86400;
+
+#[derive(Debug, Clone)]
+pub struct LoginAlertConfig {
+    pub from: String,
+    pub max_devices: usize,
+    pub push: Option<LoginAlertPush>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginAlertPush {
+    pub url: String,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDevices {
+    pub opt_out: bool,
+    pub devices: Vec<TrustedDevice>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDevice {
+    pub network: String,
+    pub client: String,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceStatus {
+    // First device ever seen for the account, nothing to compare against
+    First,
+    Known,
+    New,
+}
+
+impl JMAP {
+    // Records the device used to log in and notifies the user when it has
+    // not been seen before.
+    pub async fn track_login(
+        &self,
+        principal: &Principal<u32>,
+        remote_addr: &RemoteAddress,
+        client: &str,
+    ) {
+        let config = if let Some(config) = &self.config.login_alert {
+            config
+        } else {
+            return;
+        };
+        let remote_ip = match remote_addr {
+            RemoteAddress::IpAddress(ip) => *ip,
+            RemoteAddress::IpAddressFwd(ip) => match ip.trim().parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => return,
+            },
+        };
+
+        match self
+            .trusted_device_seen(principal.id, remote_ip, client, config.max_devices)
+            .await
+        {
+            Ok((DeviceStatus::New, opt_out)) => {
+                tracing::info!(
+                    context = "auth",
+                    event = "new-device",
+                    account = principal.name,
+                    remote_ip = remote_ip.to_string(),
+                    client = client,
+                    "Login from a new device or location."
+                );
+
+                self.activity_notify(
+                    principal.id,
+                    Activity::new(
+                        ActivityType::NewDevice,
+                        format!("Login from {client} at {remote_ip}."),
+                    ),
+                )
+                .await;
+                if !opt_out {
+                    self.send_login_alert(config, principal, remote_ip, client)
+                        .await;
+                }
+            }
+            Ok(_) => (),
+            Err(err) => {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = ?err,
+                    "Failed to update trusted devices."
+                );
+            }
+        }
+    }
+
+    // Adds or refreshes a device, returns its status and whether the account
+    // has opted out of login alerts.
+    pub async fn trusted_device_seen(
+        &self,
+        account_id: u32,
+        remote_ip: IpAddr,
+        client: &str,
+        max_devices: usize,
+    ) -> store::Result<(DeviceStatus, bool)> {
+        let mut devices = self.trusted_devices(account_id).await?;
+        let network = device_network(remote_ip);
+        let now = now();
+
+        let status = if let Some(device) = devices
+            .devices
+            .iter_mut()
+            .find(|device| device.network == network && device.client == client)
+        {
+            if now.saturating_sub(device.last_seen) < DEVICE_REFRESH_INTERVAL {
+                return Ok((DeviceStatus::Known, devices.opt_out));
+            }
+            device.last_seen = now;
+            DeviceStatus::Known
+        } else {
+            let status = if devices.devices.is_empty() {
+                DeviceStatus::First
+            } else {
+                DeviceStatus::New
+            };
+            devices.devices.push(TrustedDevice {
+                network,
+                client: client.to_string(),
+                first_seen: now,
+                last_seen: now,
+            });
+            if devices.devices.len() > max_devices {
+                devices
+                    .devices
+                    .sort_unstable_by(|a, b| b.last_seen.cmp(&a.last_seen));
+                devices.devices.truncate(max_devices);
+            }
+            status
+        };
+
+        self.trusted_devices_set(account_id, &devices).await?;
+        Ok((status, devices.opt_out))
+    }
+
+    // Lets users list their trusted devices, opt out of login alerts with
+    // "opt-out=true" or forget all known devices with "forget=true".
+    pub async fn handle_devices_request(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        let account_id = access_token.primary_id();
+        let result = match *req.method() {
+            Method::GET => self.trusted_devices(account_id).await,
+            Method::POST => {
+                let mut opt_out = None;
+                let mut forget = false;
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "opt-out" => {
+                                opt_out = (value == "true").into();
+                            }
+                            "forget" => {
+                                forget = value == "true";
+                            }
+                            _ => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    format!("Invalid parameter {key:?}."),
+                                )
+                                .into_http_response();
+                            }
+                        }
+                    }
+                }
+
+                match self.trusted_devices(account_id).await {
+                    Ok(mut devices) => {
+                        if let Some(opt_out) = opt_out {
+                            devices.opt_out = opt_out;
+                        }
+                        if forget {
+                            devices.devices.clear();
+                        }
+                        self.trusted_devices_set(account_id, &devices)
+                            .await
+                            .map(|_| devices)
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            _ => return RequestError::not_found().into_http_response(),
+        };
+
+        match result {
+            Ok(devices) => JsonResponse::new(json!({
+                "data": devices,
+            }))
+            .into_http_response(),
+            Err(err) => RequestError::blank(
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                "Failed to access trusted devices",
+                err.to_string(),
+            )
+            .into_http_response(),
+        }
+    }
+
+    pub async fn trusted_devices(&self, account_id: u32) -> store::Result<TrustedDevices> {
+        if let Some(LookupValue::Value { value, .. }) = self
+            .store
+            .get_value::<LookupValue<Bincode<TrustedDevices>>>(ValueKey::from(ValueClass::Key(
+                devices_key(account_id),
+            )))
+            .await?
+        {
+            Ok(value.inner)
+        } else {
+            Ok(TrustedDevices::default())
+        }
+    }
+
+    pub async fn trusted_devices_set(
+        &self,
+        account_id: u32,
+        devices: &TrustedDevices,
+    ) -> store::Result<()> {
+        // Values are prefixed with an expiration timestamp as the key space
+        // is shared with the lookup store, which purges expired keys.
+        let value = Bincode::new(devices.clone()).serialize();
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Key(devices_key(account_id)),
+            KeySerializer::new(value.len() + U64_LEN)
+                .write(u64::MAX)
+                .write(value.as_slice())
+                .finalize(),
+        );
+        self.store.write(batch.build()).await
+    }
+
+    pub async fn trusted_devices_clear(&self, account_id: u32) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Key(devices_key(account_id)));
+        self.store.write(batch.build()).await
+    }
+
+    async fn send_login_alert(
+        &self,
+        config: &LoginAlertConfig,
+        principal: &Principal<u32>,
+        remote_ip: IpAddr,
+        client: &str,
+    ) {
+        if let Some(to) = principal.emails.first() {
+            let raw_message = MessageBuilder::new()
+                .from(config.from.as_str())
+                .to(to.as_str())
+                .subject("New sign-in to your account")
+                .text_body(format!(
+                    concat!(
+                        "Your account {} was just accessed from a new device or location.\r\n\r\n",
+                        "Time: {}\r\nIP address: {}\r\nClient: {}\r\n\r\n",
+                        "If this was you, no further action is needed. Otherwise, please ",
+                        "change your password and contact your administrator.\r\n"
+                    ),
+                    principal.name,
+                    Date::now().to_rfc822(),
+                    remote_ip,
+                    client
+                ))
+                .write_to_vec()
+                .unwrap_or_default();
+
+            let result = Session::<NullIo>::sieve(
+                self.smtp.clone(),
+                SessionAddress::new(config.from.clone()),
+                vec![SessionAddress::new(to.clone())],
+                raw_message,
+            )
+            .queue_message()
+            .await;
+
+            if !result.starts_with(b"2") {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = String::from_utf8_lossy(&result).trim(),
+                    "Failed to send login alert e-mail."
+                );
+            }
+        }
+
+        if let Some(push) = &config.push {
+            if let Err(err) = push.send(principal, remote_ip, client).await {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = err.as_str(),
+                    "Failed to send login alert push notification."
+                );
+            }
+        }
+    }
+}
+
+impl LoginAlertPush {
+    pub async fn send(
+        &self,
+        principal: &Principal<u32>,
+        remote_ip: IpAddr,
+        client: &str,
+    ) -> Result<(), String> {
+        let client_builder = reqwest::Client::builder().timeout(self.timeout);
+
+        #[cfg(feature = "test_mode")]
+        let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+        match client_builder
+            .build()
+            .unwrap_or_default()
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(
+                json!({
+                    "event": "new-login",
+                    "account": principal.name,
+                    "emails": principal.emails,
+                    "remoteIp": remote_ip.to_string(),
+                    "client": client,
+                    "timestamp": now(),
+                })
+                .to_string(),
+            )
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!("Server responded with {}", response.status())),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+pub fn parse_login_alert(config: &Config) -> utils::config::Result<Option<LoginAlertConfig>> {
+    if !config.property_or_static::<bool>("jmap.login-alert.enable", "false")? {
+        return Ok(None);
+    }
+
+    Ok(Some(LoginAlertConfig {
+        from: config.value_require("jmap.login-alert.from")?.to_string(),
+        max_devices: config.property_or_static("jmap.login-alert.max-devices", "20")?,
+        push: if let Some(url) = config.value("jmap.login-alert.push.url") {
+            LoginAlertPush {
+                url: url.to_string(),
+                timeout: config.property_or_static("jmap.login-alert.push.timeout", "10s")?,
+            }
+            .into()
+        } else {
+            None
+        },
+    }))
+}
+
+// Logins from the same /24 IPv4 or /48 IPv6 network are considered to
+// originate from the same location.
+pub fn device_network(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+    }
+}
+
+fn devices_key(account_id: u32) -> Vec<u8> {
+    KeySerializer::new(DEVICES_KEY_PREFIX.len() + U32_LEN)
+        .write(DEVICES_KEY_PREFIX)
+        .write(account_id)
+        .finalize()
+}
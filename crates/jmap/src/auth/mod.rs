@@ -41,8 +41,11 @@ use utils::map::bitmap::Bitmap;
 
 pub mod acl;
 pub mod authenticate;
+pub mod devices;
 pub mod oauth;
 pub mod rate_limit;
+pub mod reset;
+pub mod sessions;
 
 #[derive(Debug, Clone, Default)]
 pub struct AccessToken {
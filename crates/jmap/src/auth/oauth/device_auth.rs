@@ -177,7 +177,10 @@ impl JMAP {
             {
                 if let (Some(email), Some(password)) = (fields.get("email"), fields.get("password"))
                 {
-                    if let Some(id) = self.authenticate_plain(email, password, remote_addr).await {
+                    if let Some(id) = self
+                        .authenticate_plain(email, password, remote_addr, "OAuth")
+                        .await
+                    {
                         oauth
                             .account_id
                             .store(id.primary_id(), atomic::Ordering::Relaxed);
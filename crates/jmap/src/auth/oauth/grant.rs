@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use rand::Rng;
+use serde_json::json;
+use store::{
+    write::{assert::HashedValue, key::KeySerializer, now, BatchBuilder, ValueClass},
+    LookupValue, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+
+use crate::{auth::rate_limit::RemoteAddress, Bincode, JMAP};
+
+const GRANTS_KEY_PREFIX: &[u8] = b"oauth.grants.";
+const MAX_RETRIES: u32 = 10;
+
+// An authorization given by the user to an OAuth client, access and refresh
+// tokens issued under it stop working as soon as it is revoked.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OAuthGrants {
+    pub grants: Vec<OAuthGrant>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OAuthGrant {
+    pub id: u64,
+    pub client_id: String,
+    pub remote_ip: String,
+    pub user_agent: String,
+    pub issued: u64,
+    pub last_used: u64,
+    pub expires: u64,
+    // Incremented every time a new refresh token is issued, only the
+    // latest refresh token of a grant is accepted.
+    #[serde(default)]
+    pub sequence: u32,
+}
+
+impl JMAP {
+    pub async fn oauth_grant_create(
+        &self,
+        account_id: u32,
+        client_id: &str,
+        remote_addr: &RemoteAddress,
+        user_agent: &str,
+    ) -> store::Result<u64> {
+        let id = self.snowflake_id.generate().unwrap_or_else(now);
+        let now = now();
+        let grant = OAuthGrant {
+            id,
+            client_id: client_id.to_string(),
+            remote_ip: remote_addr.to_string(),
+            user_agent: user_agent.to_string(),
+            issued: now,
+            last_used: now,
+            expires: now + self.config.oauth_expiry_refresh_token,
+            sequence: 0,
+        };
+        self.oauth_grants_update(account_id, |grants| {
+            grants.grants.push(grant.clone());
+        })
+        .await?;
+
+        Ok(id)
+    }
+
+    // Updates the last use of a grant and, when a new refresh token is
+    // issued, extends its expiration. Returns the sequence number to issue
+    // tokens with, or None if the grant is gone or the refresh token was
+    // already used, in which case the whole grant is revoked.
+    pub async fn oauth_grant_refresh(
+        &self,
+        account_id: u32,
+        grant_id: u64,
+        sequence: u32,
+        renew: bool,
+    ) -> store::Result<Option<u32>> {
+        let expiry = self.config.oauth_expiry_refresh_token;
+        let result = self
+            .oauth_grants_update(account_id, |grants| {
+                let pos = grants
+                    .grants
+                    .iter()
+                    .position(|grant| grant.id == grant_id)?;
+                let grant = &mut grants.grants[pos];
+
+                if grant.sequence != sequence {
+                    // A rotated refresh token is being replayed, it might have leaked
+                    // so revoke everything issued under the grant.
+                    let grant = grants.grants.remove(pos);
+                    return Some(Err(grant.client_id));
+                }
+
+                let now = now();
+                grant.last_used = now;
+                if renew {
+                    grant.sequence += 1;
+                    grant.expires = now + expiry;
+                }
+                Some(Ok(grant.sequence))
+            })
+            .await?;
+
+        match result {
+            Some(Ok(sequence)) => Ok(Some(sequence)),
+            Some(Err(client_id)) => {
+                tracing::warn!(
+                    context = "oauth",
+                    event = "token-reuse",
+                    account_id = account_id,
+                    client_id = client_id,
+                    "Refresh token reuse detected, revoking grant."
+                );
+                self.oauth_sessions_revoke(account_id).await;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn oauth_grant_exists(&self, account_id: u32, grant_id: u64) -> store::Result<bool> {
+        self.oauth_grants(account_id)
+            .await
+            .map(|grants| grants.grants.iter().any(|grant| grant.id == grant_id))
+    }
+
+    pub async fn oauth_grant_revoke(&self, account_id: u32, grant_id: u64) -> store::Result<bool> {
+        let revoked = self
+            .oauth_grants_update(account_id, |grants| {
+                let num_grants = grants.grants.len();
+                grants.grants.retain(|grant| grant.id != grant_id);
+                grants.grants.len() != num_grants
+            })
+            .await?;
+        if revoked {
+            self.oauth_sessions_revoke(account_id).await;
+        }
+
+        Ok(revoked)
+    }
+
+    // Revokes all tokens issued to an account, returns the number of grants
+    pub async fn oauth_grants_revoke_all(&self, account_id: u32) -> store::Result<usize> {
+        let num_grants = self
+            .oauth_grants_update(account_id, |grants| {
+                std::mem::take(&mut grants.grants).len()
+            })
+            .await?;
+        self.oauth_sessions_revoke(account_id).await;
+
+        Ok(num_grants)
+    }
+
+    // Returns the unexpired grants of an account
+    pub async fn oauth_grants(&self, account_id: u32) -> store::Result<OAuthGrants> {
+        self.oauth_grants_get(account_id)
+            .await
+            .map(|(grants, _)| grants)
+    }
+
+    // Applies a change to the grants of an account, the write is asserted
+    // against the value that was read and retried if another request
+    // modified the grants in the meantime.
+    async fn oauth_grants_update<T>(
+        &self,
+        account_id: u32,
+        mut update: impl FnMut(&mut OAuthGrants) -> T,
+    ) -> store::Result<T> {
+        let mut try_count = 0;
+
+        loop {
+            let (mut grants, current) = self.oauth_grants_get(account_id).await?;
+            let result = update(&mut grants);
+
+            let mut batch = BatchBuilder::new();
+            let class = ValueClass::Key(grants_key(account_id));
+            if let Some(current) = &current {
+                batch.assert_value(class.clone(), current);
+            } else {
+                batch.assert_value(class.clone(), ());
+            }
+            if !grants.grants.is_empty() {
+                // Expire the key together with the last grant
+                let expires = grants
+                    .grants
+                    .iter()
+                    .map(|grant| grant.expires)
+                    .max()
+                    .unwrap_or(u64::MAX);
+                let value = Bincode::new(grants).serialize();
+                batch.set(
+                    class,
+                    KeySerializer::new(value.len() + U64_LEN)
+                        .write(expires)
+                        .write(value.as_slice())
+                        .finalize(),
+                );
+            } else if current.is_some() {
+                batch.clear(class);
+            } else {
+                return Ok(result);
+            }
+
+            match self.store.write(batch.build()).await {
+                Ok(_) => return Ok(result),
+                Err(store::Error::AssertValueFailed) if try_count < MAX_RETRIES => {
+                    let backoff = rand::thread_rng().gen_range(50..=300);
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                    try_count += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn oauth_grants_get(
+        &self,
+        account_id: u32,
+    ) -> store::Result<(
+        OAuthGrants,
+        Option<HashedValue<LookupValue<Bincode<OAuthGrants>>>>,
+    )> {
+        let mut current = self
+            .store
+            .get_value::<HashedValue<LookupValue<Bincode<OAuthGrants>>>>(ValueKey::from(
+                ValueClass::Key(grants_key(account_id)),
+            ))
+            .await?;
+        let grants = match current.as_mut().map(|value| &mut value.inner) {
+            Some(LookupValue::Value { value, .. }) => {
+                let mut grants = std::mem::take(&mut value.inner);
+                let now = now();
+                grants.grants.retain(|grant| grant.expires > now);
+                grants
+            }
+            _ => OAuthGrants::default(),
+        };
+
+        Ok((grants, current))
+    }
+
+    async fn oauth_sessions_revoke(&self, account_id: u32) {
+        // Bearer tokens are cached for a short while, drop them so the
+        // revoked token is validated again on its next use.
+        self.sessions.retain(|_, entry| *entry.item() != account_id);
+        self.publish_session_revocation(account_id).await;
+    }
+}
+
+impl OAuthGrant {
+    // Ids are returned as strings as they do not fit in a JavaScript number
+    pub fn into_json(self) -> serde_json::Value {
+        json!({
+            "id": self.id.to_string(),
+            "clientId": self.client_id,
+            "remoteIp": self.remote_ip,
+            "userAgent": self.user_agent,
+            "issued": self.issued,
+            "lastUsed": self.last_used,
+            "expires": self.expires,
+        })
+    }
+}
+
+fn grants_key(account_id: u32) -> Vec<u8> {
+    KeySerializer::new(GRANTS_KEY_PREFIX.len() + U32_LEN)
+        .write(GRANTS_KEY_PREFIX)
+        .write(account_id)
+        .finalize()
+}
@@ -30,6 +30,7 @@ use serde::{Deserialize, Serialize};
 use crate::api::{http::ToHttpResponse, HtmlResponse, HttpRequest, HttpResponse};
 
 pub mod device_auth;
+pub mod grant;
 pub mod token;
 pub mod user_code;
 
@@ -73,6 +74,15 @@ pub struct OAuth {
     pub metadata: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub account_id: u32,
+    pub client_id: String,
+    pub grant_id: u64,
+    pub sequence: u32,
+    pub expires_in: u64,
+}
+
 pub struct OAuthCode {
     pub status: AtomicU32,
     pub account_id: AtomicU32,
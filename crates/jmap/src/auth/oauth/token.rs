@@ -24,7 +24,7 @@
 use std::{sync::atomic, time::SystemTime};
 
 use directory::QueryBy;
-use hyper::StatusCode;
+use hyper::{header, StatusCode};
 use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use store::{
@@ -38,18 +38,29 @@ use utils::{
 
 use crate::{
     api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
-    auth::SymmetricEncrypt,
+    auth::{rate_limit::RemoteAddress, SymmetricEncrypt},
     JMAP,
 };
 
 use super::{
-    ErrorType, FormData, TokenResponse, CLIENT_ID_MAX_LEN, MAX_POST_LEN, RANDOM_CODE_LEN,
-    STATUS_AUTHORIZED, STATUS_PENDING, STATUS_TOKEN_ISSUED,
+    ErrorType, FormData, TokenInfo, TokenResponse, CLIENT_ID_MAX_LEN, MAX_POST_LEN,
+    RANDOM_CODE_LEN, STATUS_AUTHORIZED, STATUS_PENDING, STATUS_TOKEN_ISSUED,
 };
 
 impl JMAP {
     // Token endpoint
-    pub async fn handle_token_request(&self, req: &mut HttpRequest) -> HttpResponse {
+    pub async fn handle_token_request(
+        &self,
+        req: &mut HttpRequest,
+        remote_addr: &RemoteAddress,
+    ) -> HttpResponse {
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
         // Parse form
         let params = match FormData::from_request(req, MAX_POST_LEN).await {
             Ok(params) => params,
@@ -77,10 +88,11 @@ impl JMAP {
                             .store(STATUS_TOKEN_ISSUED, atomic::Ordering::Relaxed);
 
                         // Issue token
-                        self.issue_token(
+                        self.issue_new_token(
                             oauth.account_id.load(atomic::Ordering::Relaxed),
                             &oauth.client_id,
-                            true,
+                            remote_addr,
+                            &user_agent,
                         )
                         .await
                         .unwrap_or_else(|err| {
@@ -116,10 +128,11 @@ impl JMAP {
                                 .store(STATUS_TOKEN_ISSUED, atomic::Ordering::Relaxed);
 
                             // Issue token
-                            self.issue_token(
+                            self.issue_new_token(
                                 oauth.account_id.load(atomic::Ordering::Relaxed),
                                 &oauth.client_id,
-                                true,
+                                remote_addr,
+                                &user_agent,
                             )
                             .await
                             .unwrap_or_else(|err| {
@@ -141,22 +154,41 @@ impl JMAP {
             }
         } else if grant_type.eq_ignore_ascii_case("refresh_token") {
             if let Some(refresh_token) = params.get("refresh_token") {
-                if let Ok((account_id, client_id, time_left)) = self
+                if let Ok(token) = self
                     .validate_access_token("refresh_token", refresh_token)
                     .await
                 {
-                    // TODO: implement revoking client ids
-                    response = self
-                        .issue_token(
-                            account_id,
-                            &client_id,
-                            time_left <= self.config.oauth_expiry_refresh_token_renew,
+                    // Refresh tokens are single use when rotation is enabled
+                    let renew = self.config.oauth_refresh_token_rotate
+                        || token.expires_in <= self.config.oauth_expiry_refresh_token_renew;
+                    response = match self
+                        .oauth_grant_refresh(
+                            token.account_id,
+                            token.grant_id,
+                            token.sequence,
+                            renew,
                         )
                         .await
-                        .unwrap_or_else(|err| {
-                            tracing::debug!("Failed to refresh OAuth token: {}", err);
-                            TokenResponse::error(ErrorType::InvalidGrant)
-                        });
+                    {
+                        Ok(Some(sequence)) => self
+                            .issue_token(
+                                token.account_id,
+                                &token.client_id,
+                                token.grant_id,
+                                sequence,
+                                renew,
+                            )
+                            .await
+                            .unwrap_or_else(|err| {
+                                tracing::debug!("Failed to refresh OAuth token: {}", err);
+                                TokenResponse::error(ErrorType::InvalidGrant)
+                            }),
+                        Ok(None) => TokenResponse::error(ErrorType::InvalidGrant),
+                        Err(err) => {
+                            tracing::error!("Failed to update OAuth grant: {}", err);
+                            TokenResponse::error(ErrorType::InvalidRequest)
+                        }
+                    };
                 }
             } else {
                 response = TokenResponse::error(ErrorType::InvalidRequest);
@@ -174,10 +206,31 @@ impl JMAP {
         .into_http_response()
     }
 
+    async fn issue_new_token(
+        &self,
+        account_id: u32,
+        client_id: &str,
+        remote_addr: &RemoteAddress,
+        user_agent: &str,
+    ) -> Result<TokenResponse, &'static str> {
+        if client_id.len() > CLIENT_ID_MAX_LEN {
+            return Err("ClientId is too long");
+        }
+        let grant_id = self
+            .oauth_grant_create(account_id, client_id, remote_addr, user_agent)
+            .await
+            .map_err(|_| "Failed to store OAuth grant")?;
+
+        self.issue_token(account_id, client_id, grant_id, 0, true)
+            .await
+    }
+
     async fn issue_token(
         &self,
         account_id: u32,
         client_id: &str,
+        grant_id: u64,
+        sequence: u32,
         with_refresh_token: bool,
     ) -> Result<TokenResponse, &'static str> {
         let password_hash = self
@@ -197,6 +250,8 @@ impl JMAP {
                 account_id,
                 &password_hash,
                 client_id,
+                grant_id,
+                sequence,
                 self.config.oauth_expiry_token,
             )?,
             token_type: "bearer".to_string(),
@@ -207,6 +262,8 @@ impl JMAP {
                     account_id,
                     &password_hash,
                     client_id,
+                    grant_id,
+                    sequence,
                     self.config.oauth_expiry_refresh_token,
                 )?
                 .into()
@@ -223,6 +280,8 @@ impl JMAP {
         account_id: u32,
         password_hash: &str,
         client_id: &str,
+        grant_id: u64,
+        sequence: u32,
         expiry_in: u64,
     ) -> Result<String, &'static str> {
         // Build context
@@ -231,8 +290,8 @@ impl JMAP {
         }
         let key = self.config.oauth_key.clone();
         let context = format!(
-            "{} {} {} {}",
-            grant_type, client_id, account_id, password_hash
+            "{} {} {} {} {} {}",
+            grant_type, client_id, account_id, grant_id, sequence, password_hash
         );
         let context_nonce = format!("{} nonce {}", grant_type, password_hash);
 
@@ -262,6 +321,8 @@ impl JMAP {
             .map_err(|_| "Failed to encrypt token.")?;
         token.push_leb128(account_id);
         token.push_leb128(expiry);
+        token.push_leb128(grant_id);
+        token.push_leb128(sequence);
         token.extend_from_slice(client_id.as_bytes());
 
         Ok(String::from_utf8(base64_encode(&token).unwrap_or_default()).unwrap())
@@ -271,16 +332,18 @@ impl JMAP {
         &self,
         grant_type: &str,
         token: &str,
-    ) -> Result<(u32, String, u64), &'static str> {
+    ) -> Result<TokenInfo, &'static str> {
         // Base64 decode token
         let token = base64_decode(token.as_bytes()).ok_or("Failed to decode.")?;
-        let (account_id, expiry, client_id) = token
+        let (account_id, expiry, grant_id, sequence, client_id) = token
             .get((RANDOM_CODE_LEN + SymmetricEncrypt::ENCRYPT_TAG_LEN)..)
             .and_then(|bytes| {
                 let mut bytes = bytes.iter();
                 (
                     bytes.next_leb128()?,
                     bytes.next_leb128::<u64>()?,
+                    bytes.next_leb128::<u64>()?,
+                    bytes.next_leb128::<u32>()?,
                     bytes.copied().map(char::from).collect::<String>(),
                 )
                     .into()
@@ -313,8 +376,8 @@ impl JMAP {
         // Build context
         let key = self.config.oauth_key.clone();
         let context = format!(
-            "{} {} {} {}",
-            grant_type, client_id, account_id, password_hash
+            "{} {} {} {} {} {}",
+            grant_type, client_id, account_id, grant_id, sequence, password_hash
         );
         let context_nonce = format!("{} nonce {}", grant_type, password_hash);
 
@@ -338,7 +401,22 @@ impl JMAP {
             )
             .map_err(|_| "Failed to decrypt token.")?;
 
+        // Make sure the grant has not been revoked
+        if !self
+            .oauth_grant_exists(account_id, grant_id)
+            .await
+            .map_err(|_| "Temporary lookup error")?
+        {
+            return Err("Token revoked.");
+        }
+
         // Success
-        Ok((account_id, client_id, expiry - now))
+        Ok(TokenInfo {
+            account_id,
+            client_id,
+            grant_id,
+            sequence,
+            expires_in: expiry - now,
+        })
     }
 }
@@ -137,7 +137,9 @@ impl JMAP {
 
         // Authenticate user
         if let (Some(email), Some(password)) = (params.get("email"), params.get("password")) {
-            if let Some(access_token) = self.authenticate_plain(email, password, remote_addr).await
+            if let Some(access_token) = self
+                .authenticate_plain(email, password, remote_addr, "OAuth")
+                .await
             {
                 // Generate client code
                 let client_code = thread_rng()
@@ -21,11 +21,14 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{fmt::Display, net::IpAddr, sync::Arc};
 
 use jmap_proto::error::request::{RequestError, RequestLimitError};
 use store::parking_lot::Mutex;
-use utils::listener::limiter::{ConcurrencyLimiter, InFlight, RateLimiter};
+use utils::{
+    config::Rate,
+    listener::limiter::{ConcurrencyLimiter, InFlight, RateLimiter},
+};
 
 use crate::JMAP;
 
@@ -92,12 +95,34 @@ impl JMAP {
             })
     }
 
-    pub fn is_account_allowed(&self, access_token: &AccessToken) -> Result<InFlight, RequestError> {
-        let limiter_ = self.get_authenticated_limiter(access_token.primary_id());
-        let mut limiter = limiter_.lock();
+    pub async fn is_account_allowed(
+        &self,
+        access_token: &AccessToken,
+    ) -> Result<InFlight, RequestError> {
+        let account_id = access_token.primary_id();
+        let is_allowed = match self
+            .is_shared_rate_allowed(
+                format!("jmap:a:{account_id}"),
+                &self.config.rate_authenticated,
+                false,
+            )
+            .await
+        {
+            Some(is_allowed) => is_allowed,
+            None => self
+                .get_authenticated_limiter(account_id)
+                .lock()
+                .request_limiter
+                .is_allowed(),
+        };
 
-        if limiter.request_limiter.is_allowed() {
-            if let Some(in_flight_request) = limiter.concurrent_requests.is_allowed() {
+        if is_allowed {
+            if let Some(in_flight_request) = self
+                .get_authenticated_limiter(account_id)
+                .lock()
+                .concurrent_requests
+                .is_allowed()
+            {
                 Ok(in_flight_request)
             } else if access_token.is_super_user() {
                 Ok(InFlight::default())
@@ -111,13 +136,20 @@ impl JMAP {
         }
     }
 
-    pub fn is_anonymous_allowed(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
-        if self
-            .get_anonymous_limiter(addr)
-            .lock()
-            .request_limiter
-            .is_allowed()
+    pub async fn is_anonymous_allowed(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
+        let is_allowed = match self
+            .is_shared_rate_allowed(format!("jmap:r:{addr}"), &self.config.rate_anonymous, false)
+            .await
         {
+            Some(is_allowed) => is_allowed,
+            None => self
+                .get_anonymous_limiter(addr)
+                .lock()
+                .request_limiter
+                .is_allowed(),
+        };
+
+        if is_allowed {
             Ok(())
         } else {
             Err(RequestError::too_many_requests())
@@ -139,27 +171,88 @@ impl JMAP {
         }
     }
 
-    pub fn is_auth_allowed_soft(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
-        match self.rate_limit_unauth.get(addr) {
-            Some(limiter) if !limiter.lock().auth_limiter.is_allowed_soft() => {
-                Err(RequestError::too_many_auth_attempts())
-            }
-            _ => Ok(()),
+    pub async fn is_auth_allowed_soft(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
+        let is_allowed = match self
+            .is_shared_rate_allowed(
+                format!("jmap:l:{addr}"),
+                &self.config.rate_authenticate_req,
+                true,
+            )
+            .await
+        {
+            Some(is_allowed) => is_allowed,
+            None => self.rate_limit_unauth.get(addr).map_or(true, |limiter| {
+                limiter.lock().auth_limiter.is_allowed_soft()
+            }),
+        };
+
+        if is_allowed {
+            Ok(())
+        } else {
+            Err(RequestError::too_many_auth_attempts())
         }
     }
 
-    pub fn is_auth_allowed_hard(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
-        if self
-            .get_anonymous_limiter(addr)
-            .lock()
-            .auth_limiter
-            .is_allowed()
+    pub async fn is_auth_allowed_hard(&self, addr: &RemoteAddress) -> Result<(), RequestError> {
+        let is_allowed = match self
+            .is_shared_rate_allowed(
+                format!("jmap:l:{addr}"),
+                &self.config.rate_authenticate_req,
+                false,
+            )
+            .await
         {
+            Some(is_allowed) => is_allowed,
+            None => self
+                .get_anonymous_limiter(addr)
+                .lock()
+                .auth_limiter
+                .is_allowed(),
+        };
+
+        if is_allowed {
             Ok(())
         } else {
             Err(RequestError::too_many_auth_attempts())
         }
     }
+
+    // Rate limits are shared by all nodes when a lookup store is configured,
+    // the local limiters are used when it is not or it cannot be reached.
+    async fn is_shared_rate_allowed(
+        &self,
+        key: String,
+        rate: &Rate,
+        soft_check: bool,
+    ) -> Option<bool> {
+        match self
+            .rate_limit_store
+            .as_ref()?
+            .is_rate_allowed(key.as_bytes(), rate, soft_check)
+            .await
+        {
+            Ok(retry_after) => Some(retry_after.is_none()),
+            Err(err) => {
+                tracing::warn!(
+                    context = "rate_limit",
+                    event = "error",
+                    key = key,
+                    reason = %err,
+                    "Failed to check distributed rate limit, using local limiter."
+                );
+                None
+            }
+        }
+    }
+}
+
+impl Display for RemoteAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteAddress::IpAddress(ip) => ip.fmt(f),
+            RemoteAddress::IpAddressFwd(ip) => f.write_str(ip),
+        }
+    }
 }
 
 impl AuthenticatedLimiter {
@@ -0,0 +1,789 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::{
+    backend::internal::{manage::ManageDirectory, PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal, QueryBy,
+};
+use hyper::{Method, StatusCode};
+use jmap_proto::error::request::RequestError;
+use mail_builder::{encoders::base64::base64_encode, headers::date::Date, MessageBuilder};
+use mail_parser::decoders::base64::base64_decode;
+use pwhash::sha512_crypt;
+use serde_json::json;
+use smtp::core::{NullIo, Session, SessionAddress};
+use store::{
+    blake3,
+    rand::{thread_rng, Rng},
+    write::{key::KeySerializer, now, BatchBuilder, ValueClass},
+    LookupStore, ValueKey, U32_LEN,
+};
+use utils::{
+    codec::leb128::{Leb128Iterator, Leb128Vec},
+    config::{Config, Rate},
+    stats::ServerStats,
+};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    JMAP,
+};
+
+use super::{oauth::FormData, rate_limit::RemoteAddress, AccessToken, SymmetricEncrypt};
+
+const RECOVERY_KEY_PREFIX: &[u8] = b"auth.recovery.";
+const RESET_THROTTLE_KEY_PREFIX: &[u8] = b"auth.reset.";
+const RESET_CODE_LEN: usize = 16;
+const MAX_POST_LEN: usize = 2048;
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    pub from: String,
+    pub url: String,
+    pub token_expiry: u64,
+    pub throttle: u64,
+    pub min_length: usize,
+}
+
+impl JMAP {
+    // Accepts reset requests ("account=<name>") and their confirmations
+    // ("token=<token>&password=<new password>") for internally managed accounts.
+    pub async fn handle_password_reset(
+        &self,
+        req: &mut HttpRequest,
+        action: &str,
+        remote_addr: &RemoteAddress,
+    ) -> HttpResponse {
+        let config = match &self.config.password_reset {
+            Some(config) if self.directory.internal_store().is_some() => config,
+            _ => return RequestError::not_found().into_http_response(),
+        };
+        let params = match FormData::from_request(req, MAX_POST_LEN).await {
+            Ok(params) => params,
+            Err(err) => return err,
+        };
+
+        match action {
+            "" => {
+                if let Some(account) = params.get("account") {
+                    self.password_reset_request(config, account, remote_addr)
+                        .await;
+                }
+
+                // Do not disclose whether the account exists
+                JsonResponse::new(json!({
+                    "data": "If the account has a recovery address, a reset link has been sent to it.",
+                }))
+                .into_http_response()
+            }
+            "confirm" => {
+                let (token, password) = match (params.get("token"), params.get("password")) {
+                    (Some(token), Some(password)) => (token, password),
+                    _ => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Both a reset token and a new password are required.",
+                        )
+                        .into_http_response()
+                    }
+                };
+                if password.chars().count() < config.min_length {
+                    return RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid password",
+                        format!(
+                            "Passwords must be at least {} characters long.",
+                            config.min_length
+                        ),
+                    )
+                    .into_http_response();
+                }
+
+                match self.password_reset_confirm(token, password).await {
+                    Ok(principal) => {
+                        tracing::info!(
+                            context = "auth",
+                            event = "password-reset",
+                            account = principal.name,
+                            remote_ip = remote_addr.to_string(),
+                            "Password was reset using a recovery token."
+                        );
+
+                        JsonResponse::new(json!({
+                            "data": "Password updated.",
+                        }))
+                        .into_http_response()
+                    }
+                    Err(reason) => {
+                        tracing::info!(
+                            context = "auth",
+                            event = "password-reset-failed",
+                            remote_ip = remote_addr.to_string(),
+                            reason = reason,
+                            "Password reset attempt rejected."
+                        );
+
+                        RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Password reset failed",
+                            reason,
+                        )
+                        .into_http_response()
+                    }
+                }
+            }
+            "recovery" => match self
+                .recovery_address_confirm(params.get("token").unwrap_or_default())
+                .await
+            {
+                Ok(address) => {
+                    tracing::info!(
+                        context = "auth",
+                        event = "recovery-address-changed",
+                        remote_ip = remote_addr.to_string(),
+                        "Recovery address was updated."
+                    );
+
+                    JsonResponse::new(json!({
+                        "data": {
+                            "address": address,
+                        },
+                    }))
+                    .into_http_response()
+                }
+                Err(reason) => RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Recovery address confirmation failed",
+                    reason,
+                )
+                .into_http_response(),
+            },
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    // Lets users view their recovery address or change it with
+    // "address=<address>&password=<current password>", an empty address
+    // removes it. New addresses only take effect once they are confirmed.
+    pub async fn handle_recovery_request(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+        remote_addr: &RemoteAddress,
+    ) -> HttpResponse {
+        let account_id = access_token.primary_id();
+        let result = match *req.method() {
+            Method::GET => self
+                .recovery_address(account_id)
+                .await
+                .map(|address| json!({ "address": address })),
+            Method::POST => {
+                let config = match &self.config.password_reset {
+                    Some(config) => config,
+                    None => return RequestError::not_found().into_http_response(),
+                };
+                let params = match FormData::from_request(req, MAX_POST_LEN).await {
+                    Ok(params) => params,
+                    Err(err) => return err,
+                };
+                let address = match params
+                    .get("address")
+                    .map(|value| value.trim().to_lowercase())
+                {
+                    Some(address) if address.is_empty() => None,
+                    Some(address) if is_valid_address(&address) => Some(address),
+                    _ => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "A valid recovery address is required.",
+                        )
+                        .into_http_response();
+                    }
+                };
+
+                // Changes require the current password, a stolen access token
+                // is not enough to take over the account through a reset
+                let principal = match self.directory.query(QueryBy::Id(account_id), false).await {
+                    Ok(Some(principal)) => principal,
+                    Ok(None) => return RequestError::not_found().into_http_response(),
+                    Err(_) => {
+                        return RequestError::internal_server_error().into_http_response();
+                    }
+                };
+                if !principal
+                    .verify_secret(params.get("password").unwrap_or_default())
+                    .await
+                {
+                    tracing::info!(
+                        context = "auth",
+                        event = "recovery-address-rejected",
+                        account = access_token.name,
+                        remote_ip = remote_addr.to_string(),
+                        "Recovery address change rejected, the current password is invalid."
+                    );
+                    ServerStats::get().record_auth_failure();
+                    return match self.is_auth_allowed_hard(remote_addr).await {
+                        Ok(_) => RequestError::blank(
+                            StatusCode::FORBIDDEN.as_u16(),
+                            "Invalid password",
+                            "The current password is required to change the recovery address.",
+                        )
+                        .into_http_response(),
+                        Err(err) => err.into_http_response(),
+                    };
+                }
+
+                self.recovery_address_change(config, &principal, address, remote_addr)
+                    .await
+            }
+            _ => return RequestError::not_found().into_http_response(),
+        };
+
+        match result {
+            Ok(data) => JsonResponse::new(json!({
+                "data": data,
+            }))
+            .into_http_response(),
+            Err(err) => RequestError::blank(
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                "Failed to access recovery address",
+                err.to_string(),
+            )
+            .into_http_response(),
+        }
+    }
+
+    // Removals take effect right away while new addresses are sent a
+    // confirmation token, the current address is notified in both cases.
+    async fn recovery_address_change(
+        &self,
+        config: &PasswordResetConfig,
+        principal: &Principal<u32>,
+        address: Option<String>,
+        remote_addr: &RemoteAddress,
+    ) -> store::Result<serde_json::Value> {
+        let current = self.recovery_address(principal.id).await?;
+
+        let result = if let Some(address) = &address {
+            let token = self
+                .recovery_address_token(
+                    principal.id,
+                    current.as_deref(),
+                    address,
+                    config.token_expiry,
+                )
+                .map_err(|err| store::Error::InternalError(err.to_string()))?;
+
+            tracing::info!(
+                context = "auth",
+                event = "recovery-address-requested",
+                account = principal.name,
+                remote_ip = remote_addr.to_string(),
+                "Recovery address confirmation sent."
+            );
+
+            self.send_account_notice(
+                config,
+                &principal.name,
+                address,
+                "Confirm your recovery address",
+                format!(
+                    concat!(
+                        "The address {} was set as the recovery address of the account {} ",
+                        "on {} from {}.\r\n\r\n",
+                        "To confirm it, submit the following token to {}/recovery:\r\n\r\n",
+                        "{}\r\n\r\n",
+                        "This token expires in {} minutes. If you did not request this ",
+                        "change, you can safely ignore this message.\r\n"
+                    ),
+                    address,
+                    principal.name,
+                    Date::now().to_rfc822(),
+                    remote_addr,
+                    config.url.trim_end_matches('/'),
+                    token,
+                    config.token_expiry / 60
+                ),
+            )
+            .await;
+
+            json!({
+                "address": current,
+                "pending": address,
+            })
+        } else {
+            self.recovery_address_set(principal.id, None).await?;
+
+            tracing::info!(
+                context = "auth",
+                event = "recovery-address-changed",
+                account = principal.name,
+                remote_ip = remote_addr.to_string(),
+                "Recovery address was removed."
+            );
+
+            json!({
+                "address": null,
+            })
+        };
+
+        if let Some(current) = &current {
+            self.send_account_notice(
+                config,
+                &principal.name,
+                current,
+                "Recovery address change",
+                format!(
+                    concat!(
+                        "{} of the account {} was requested on {} from {}.\r\n\r\n",
+                        "If you did not request this change, change your password ",
+                        "right away.\r\n"
+                    ),
+                    if let Some(address) = &address {
+                        format!("Changing the recovery address to {address}")
+                    } else {
+                        "Removing the recovery address".to_string()
+                    },
+                    principal.name,
+                    Date::now().to_rfc822(),
+                    remote_addr,
+                ),
+            )
+            .await;
+        }
+
+        Ok(result)
+    }
+
+    async fn password_reset_request(
+        &self,
+        config: &PasswordResetConfig,
+        account: &str,
+        remote_addr: &RemoteAddress,
+    ) {
+        let principal = match self.directory.query(QueryBy::Name(account), false).await {
+            Ok(Some(principal)) => principal,
+            Ok(None) => {
+                tracing::info!(
+                    context = "auth",
+                    event = "password-reset-request",
+                    account = account,
+                    remote_ip = remote_addr.to_string(),
+                    "Password reset requested for an unknown account."
+                );
+                return;
+            }
+            Err(_) => {
+                return;
+            }
+        };
+        let recovery_address = match self.recovery_address(principal.id).await {
+            Ok(Some(address)) => address,
+            Ok(None) => {
+                tracing::info!(
+                    context = "auth",
+                    event = "password-reset-request",
+                    account = principal.name,
+                    remote_ip = remote_addr.to_string(),
+                    "Password reset requested for an account without a recovery address."
+                );
+                return;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = ?err,
+                    "Failed to obtain recovery address."
+                );
+                return;
+            }
+        };
+
+        // Send at most one reset e-mail per account during the throttle period
+        match self
+            .password_reset_throttle(principal.id, config.throttle)
+            .await
+        {
+            Ok(true) => (),
+            Ok(false) => {
+                tracing::info!(
+                    context = "auth",
+                    event = "password-reset-throttled",
+                    account = principal.name,
+                    remote_ip = remote_addr.to_string(),
+                    "Password reset request ignored, a reset link was sent recently."
+                );
+                return;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = ?err,
+                    "Failed to update password reset throttle."
+                );
+                return;
+            }
+        }
+
+        let token = match self.password_reset_token(&principal, config.token_expiry) {
+            Ok(token) => token,
+            Err(reason) => {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account = principal.name,
+                    reason = reason,
+                    "Failed to generate password reset token."
+                );
+                return;
+            }
+        };
+
+        tracing::info!(
+            context = "auth",
+            event = "password-reset-request",
+            account = principal.name,
+            remote_ip = remote_addr.to_string(),
+            "Password reset link sent to the recovery address."
+        );
+
+        self.send_account_notice(
+            config,
+            &principal.name,
+            &recovery_address,
+            "Password reset request",
+            format!(
+                concat!(
+                    "A password reset was requested for your account {} on {} from {}.\r\n\r\n",
+                    "To choose a new password, submit the following token together with your ",
+                    "new password to {}/confirm:\r\n\r\n{}\r\n\r\n",
+                    "This token expires in {} minutes and can only be used once. If you did ",
+                    "not request a password reset, you can safely ignore this message.\r\n"
+                ),
+                principal.name,
+                Date::now().to_rfc822(),
+                remote_addr,
+                config.url.trim_end_matches('/'),
+                token,
+                config.token_expiry / 60
+            ),
+        )
+        .await;
+    }
+
+    async fn send_account_notice(
+        &self,
+        config: &PasswordResetConfig,
+        account: &str,
+        to: &str,
+        subject: &str,
+        body: String,
+    ) {
+        let raw_message = MessageBuilder::new()
+            .from(config.from.as_str())
+            .to(to)
+            .subject(subject)
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        let result = Session::<NullIo>::sieve(
+            self.smtp.clone(),
+            SessionAddress::new(config.from.clone()),
+            vec![SessionAddress::new(to.to_string())],
+            raw_message,
+        )
+        .queue_message()
+        .await;
+
+        if !result.starts_with(b"2") {
+            tracing::warn!(
+                context = "auth",
+                event = "error",
+                account = account,
+                reason = String::from_utf8_lossy(&result).trim(),
+                "Failed to send {:?} e-mail.",
+                subject
+            );
+        }
+    }
+
+    async fn password_reset_confirm(
+        &self,
+        token: &str,
+        password: &str,
+    ) -> Result<Principal<u32>, &'static str> {
+        let store = self
+            .directory
+            .internal_store()
+            .ok_or("Accounts are not managed internally")?;
+        let principal = self.validate_password_reset_token(token).await?;
+        let secret = sha512_crypt::hash(password).map_err(|_| "Failed to hash password")?;
+
+        // Changing the secret invalidates the token as well as any issued OAuth tokens
+        store
+            .update_account(
+                QueryBy::Id(principal.id),
+                vec![PrincipalUpdate::set(
+                    PrincipalField::Secrets,
+                    PrincipalValue::StringList(vec![secret]),
+                )],
+            )
+            .await
+            .map_err(|_| "Failed to update password")?;
+        self.directory.invalidate_cache();
+
+        Ok(principal)
+    }
+
+    // Reset tokens are bound to the current password hash, which makes them
+    // single use as they stop validating once the password is changed.
+    pub fn password_reset_token(
+        &self,
+        principal: &Principal<u32>,
+        expiry_in: u64,
+    ) -> Result<String, &'static str> {
+        let password_hash = principal.secrets.first().map(|s| s.as_str()).unwrap_or("");
+        let expiry = now() + expiry_in;
+
+        let mut token = SymmetricEncrypt::new(
+            self.config.oauth_key.as_bytes(),
+            &reset_context(principal.id, password_hash),
+        )
+        .encrypt(
+            &thread_rng().gen::<[u8; RESET_CODE_LEN]>(),
+            &reset_nonce(password_hash, expiry),
+        )
+        .map_err(|_| "Failed to encrypt token")?;
+        token.push_leb128(principal.id);
+        token.push_leb128(expiry);
+
+        Ok(String::from_utf8(base64_encode(&token).unwrap_or_default()).unwrap())
+    }
+
+    pub async fn validate_password_reset_token(
+        &self,
+        token: &str,
+    ) -> Result<Principal<u32>, &'static str> {
+        let token = base64_decode(token.trim().as_bytes()).ok_or("Failed to decode token")?;
+        let (account_id, expiry) = token
+            .get((RESET_CODE_LEN + SymmetricEncrypt::ENCRYPT_TAG_LEN)..)
+            .and_then(|bytes| {
+                let mut bytes = bytes.iter();
+                (bytes.next_leb128::<u32>()?, bytes.next_leb128::<u64>()?).into()
+            })
+            .ok_or("Failed to decode token")?;
+        if expiry <= now() {
+            return Err("Token expired");
+        }
+
+        let principal = self
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .map_err(|_| "Temporary lookup error")?
+            .ok_or("Account no longer exists")?;
+        let password_hash = principal.secrets.first().map(|s| s.as_str()).unwrap_or("");
+
+        SymmetricEncrypt::new(
+            self.config.oauth_key.as_bytes(),
+            &reset_context(account_id, password_hash),
+        )
+        .decrypt(
+            &token[..RESET_CODE_LEN + SymmetricEncrypt::ENCRYPT_TAG_LEN],
+            &reset_nonce(password_hash, expiry),
+        )
+        .map_err(|_| "Invalid or already used token")?;
+
+        Ok(principal)
+    }
+
+    pub async fn recovery_address(&self, account_id: u32) -> store::Result<Option<String>> {
+        self.store
+            .get_value::<String>(ValueKey::from(ValueClass::Persistent(account_key(
+                RECOVERY_KEY_PREFIX,
+                account_id,
+            ))))
+            .await
+    }
+
+    pub async fn recovery_address_set(
+        &self,
+        account_id: u32,
+        address: Option<&str>,
+    ) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        let key = ValueClass::Persistent(account_key(RECOVERY_KEY_PREFIX, account_id));
+        if let Some(address) = address {
+            batch.set(key, address.as_bytes());
+        } else {
+            batch.clear(key);
+        }
+        self.store.write(batch.build()).await
+    }
+
+    // Returns false if a reset e-mail was already sent during the throttle period,
+    // the counter is incremented atomically so concurrent requests send one e-mail
+    async fn password_reset_throttle(&self, account_id: u32, throttle: u64) -> store::Result<bool> {
+        LookupStore::from(self.store.clone())
+            .is_rate_allowed(
+                &account_key(RESET_THROTTLE_KEY_PREFIX, account_id),
+                &Rate {
+                    requests: 1,
+                    period: Duration::from_secs(throttle),
+                },
+                false,
+            )
+            .await
+            .map(|retry_after| retry_after.is_none())
+    }
+
+    // Confirmation tokens carry the new address and are bound to the current
+    // one, so they stop validating once the recovery address changes.
+    pub fn recovery_address_token(
+        &self,
+        account_id: u32,
+        current: Option<&str>,
+        address: &str,
+        expiry_in: u64,
+    ) -> Result<String, &'static str> {
+        let expiry = now() + expiry_in;
+        let mut token = Vec::with_capacity(address.len() + 32);
+        token.push_leb128(account_id);
+        token.push_leb128(expiry);
+        token.extend(
+            SymmetricEncrypt::new(
+                self.config.oauth_key.as_bytes(),
+                &recovery_context(account_id, current.unwrap_or_default()),
+            )
+            .encrypt(address.as_bytes(), &recovery_nonce(expiry))
+            .map_err(|_| "Failed to encrypt token")?,
+        );
+
+        Ok(String::from_utf8(base64_encode(&token).unwrap_or_default()).unwrap())
+    }
+
+    pub async fn recovery_address_confirm(&self, token: &str) -> Result<String, &'static str> {
+        let token = base64_decode(token.trim().as_bytes()).ok_or("Failed to decode token")?;
+        let mut bytes = token.iter();
+        let (account_id, expiry) = (bytes.next_leb128::<u32>(), bytes.next_leb128::<u64>());
+        let (account_id, expiry) = account_id.zip(expiry).ok_or("Failed to decode token")?;
+        if expiry <= now() {
+            return Err("Token expired");
+        }
+
+        let current = self
+            .recovery_address(account_id)
+            .await
+            .map_err(|_| "Temporary lookup error")?;
+        let address = SymmetricEncrypt::new(
+            self.config.oauth_key.as_bytes(),
+            &recovery_context(account_id, current.as_deref().unwrap_or_default()),
+        )
+        .decrypt(bytes.as_slice(), &recovery_nonce(expiry))
+        .ok()
+        .and_then(|address| String::from_utf8(address).ok())
+        .ok_or("Invalid or already used token")?;
+
+        self.recovery_address_set(account_id, Some(&address))
+            .await
+            .map_err(|_| "Failed to update recovery address")?;
+
+        Ok(address)
+    }
+}
+
+pub fn parse_password_reset(config: &Config) -> utils::config::Result<Option<PasswordResetConfig>> {
+    if !config.property_or_static::<bool>("jmap.password-reset.enable", "false")? {
+        return Ok(None);
+    }
+
+    Ok(Some(PasswordResetConfig {
+        from: config
+            .value_require("jmap.password-reset.from")?
+            .to_string(),
+        url: config.value_require("jmap.password-reset.url")?.to_string(),
+        token_expiry: config
+            .property_or_static::<Duration>("jmap.password-reset.token-expiry", "1h")?
+            .as_secs(),
+        throttle: config
+            .property_or_static::<Duration>("jmap.password-reset.throttle", "15m")?
+            .as_secs(),
+        min_length: config.property_or_static("jmap.password-reset.min-length", "8")?,
+    }))
+}
+
+fn reset_context(account_id: u32, password_hash: &str) -> String {
+    format!("password_reset {account_id} {password_hash}")
+}
+
+fn reset_nonce(password_hash: &str, expiry: u64) -> Vec<u8> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(format!("password_reset nonce {password_hash}").as_bytes());
+    hasher.update(expiry.to_be_bytes().as_slice());
+    hasher
+        .finalize()
+        .as_bytes()
+        .iter()
+        .take(SymmetricEncrypt::NONCE_LEN)
+        .copied()
+        .collect()
+}
+
+fn recovery_context(account_id: u32, current: &str) -> String {
+    format!("recovery_address {account_id} {current}")
+}
+
+fn recovery_nonce(expiry: u64) -> Vec<u8> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"recovery_address nonce");
+    hasher.update(expiry.to_be_bytes().as_slice());
+    hasher
+        .finalize()
+        .as_bytes()
+        .iter()
+        .take(SymmetricEncrypt::NONCE_LEN)
+        .copied()
+        .collect()
+}
+
+fn is_valid_address(address: &str) -> bool {
+    address.rsplit_once('@').map_or(false, |(local, domain)| {
+        !local.is_empty() && domain.contains('.') && !address.contains(char::is_whitespace)
+    })
+}
+
+fn account_key(prefix: &[u8], account_id: u32) -> Vec<u8> {
+    KeySerializer::new(prefix.len() + U32_LEN)
+        .write(prefix)
+        .write(account_id)
+        .finalize()
+}
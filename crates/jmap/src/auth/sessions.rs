@@ -0,0 +1,298 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use hyper::Method;
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    types::{collection::Collection, id::Id, property::Property},
+};
+use serde_json::json;
+use store::{
+    write::{now, BatchBuilder, F_CLEAR, F_VALUE},
+    LookupKey, LookupValue,
+};
+use tokio::sync::Notify;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    JMAP,
+};
+
+use super::{oauth::grant::OAuthGrant, rate_limit::RemoteAddress, AccessToken};
+
+// A connection authenticated as an account that can be terminated remotely
+pub struct LiveSession {
+    pub account_id: u32,
+    pub protocol: &'static str,
+    pub remote_ip: String,
+    pub client: String,
+    pub started: u64,
+    pub revoke: Arc<SessionRevoke>,
+}
+
+#[derive(Default)]
+pub struct SessionRevoke {
+    revoked: AtomicBool,
+    notify: Notify,
+}
+
+// Keeps a session registered for as long as it is alive
+pub struct LiveSessionGuard {
+    jmap: Arc<JMAP>,
+    pub id: u64,
+    pub revoke: Arc<SessionRevoke>,
+}
+
+impl LiveSessionGuard {
+    pub fn new(
+        jmap: Arc<JMAP>,
+        account_id: u32,
+        protocol: &'static str,
+        remote_addr: &RemoteAddress,
+        client: impl Into<String>,
+    ) -> Self {
+        let id = jmap.snowflake_id.generate().unwrap_or_else(now);
+        let revoke = Arc::new(SessionRevoke::default());
+        jmap.live_sessions.insert(
+            id,
+            LiveSession {
+                account_id,
+                protocol,
+                remote_ip: remote_addr.to_string(),
+                client: client.into(),
+                started: now(),
+                revoke: revoke.clone(),
+            },
+        );
+
+        LiveSessionGuard { jmap, id, revoke }
+    }
+}
+
+impl Drop for LiveSessionGuard {
+    fn drop(&mut self) {
+        self.jmap.live_sessions.remove(&self.id);
+    }
+}
+
+impl SessionRevoke {
+    pub fn revoke(&self) {
+        self.revoked.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked.load(Ordering::Relaxed)
+    }
+
+    // Completes once the session has been revoked
+    pub async fn revoked(&self) {
+        if !self.is_revoked() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl JMAP {
+    // Lets users list their OAuth grants, live sessions and push subscriptions
+    // with "GET /sessions" and revoke them with "DELETE /sessions/{type}/{id}".
+    pub async fn handle_sessions_request(
+        &self,
+        req: &HttpRequest,
+        mut path: std::str::Split<'_, char>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        let account_id = access_token.primary_id();
+        let result = match (req.method(), path.next().unwrap_or_default()) {
+            (&Method::GET, "") => self.list_sessions(account_id).await.map(|sessions| {
+                JsonResponse::new(json!({
+                    "data": sessions,
+                }))
+            }),
+            (&Method::DELETE, typ @ ("oauth" | "live" | "push")) => {
+                let id = path.next().unwrap_or_default();
+                let result = match typ {
+                    "oauth" => match id.parse::<u64>() {
+                        Ok(id) => self
+                            .oauth_grant_revoke(account_id, id)
+                            .await
+                            .map_err(|_| MethodError::ServerPartialFail),
+                        Err(_) => Ok(false),
+                    },
+                    "live" => Ok(id
+                        .parse::<u64>()
+                        .ok()
+                        .map_or(false, |id| self.revoke_live_session(account_id, id))),
+                    _ => match Id::from_bytes(id.as_bytes()) {
+                        Some(id) => self.revoke_push_subscription(account_id, id).await,
+                        None => Ok(false),
+                    },
+                };
+
+                match result {
+                    Ok(true) => Ok(JsonResponse::new(json!({
+                        "data": true,
+                    }))),
+                    Ok(false) => return RequestError::not_found().into_http_response(),
+                    Err(err) => Err(err),
+                }
+            }
+            _ => return RequestError::not_found().into_http_response(),
+        };
+
+        match result {
+            Ok(response) => response.into_http_response(),
+            Err(_) => RequestError::internal_server_error().into_http_response(),
+        }
+    }
+
+    async fn list_sessions(&self, account_id: u32) -> Result<serde_json::Value, MethodError> {
+        let oauth = self
+            .oauth_grants(account_id)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?
+            .grants
+            .into_iter()
+            .map(OAuthGrant::into_json)
+            .collect::<Vec<_>>();
+
+        let live = self
+            .live_sessions
+            .iter()
+            .filter(|session| session.account_id == account_id)
+            .map(|session| {
+                json!({
+                    "id": session.key().to_string(),
+                    "protocol": session.protocol,
+                    "remoteIp": session.remote_ip,
+                    "client": session.client,
+                    "started": session.started,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let push = self.push_subscription_summaries(account_id).await?;
+
+        Ok(json!({
+            "oauth": oauth,
+            "live": live,
+            "push": push,
+        }))
+    }
+
+    pub fn revoke_live_session(&self, account_id: u32, id: u64) -> bool {
+        if let Some(session) = self
+            .live_sessions
+            .get(&id)
+            .filter(|session| session.account_id == account_id)
+        {
+            session.revoke.revoke();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Revocations are published to a shared store so that the bearer tokens
+    // cached by other nodes are validated again on their next use.
+    pub async fn publish_session_revocation(&self, account_id: u32) {
+        if let Some(store) = &self.revocation_store {
+            if let Err(err) = store
+                .key_set(
+                    revocation_key(account_id),
+                    LookupValue::Value {
+                        value: now().to_string().into_bytes(),
+                        expires: self.config.session_cache_ttl.as_secs().max(1),
+                    },
+                )
+                .await
+            {
+                tracing::warn!(
+                    context = "session",
+                    event = "error",
+                    account_id = account_id,
+                    reason = %err,
+                    "Failed to publish session revocation."
+                );
+            }
+        }
+    }
+
+    pub async fn is_session_revoked(&self, account_id: u32) -> bool {
+        if let Some(store) = &self.revocation_store {
+            match store
+                .key_get::<String>(LookupKey::Key(revocation_key(account_id)))
+                .await
+            {
+                Ok(LookupValue::Value { .. }) => true,
+                Ok(_) => false,
+                Err(err) => {
+                    tracing::debug!(
+                        context = "session",
+                        event = "error",
+                        account_id = account_id,
+                        reason = %err,
+                        "Failed to read session revocation list."
+                    );
+                    false
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    async fn revoke_push_subscription(&self, account_id: u32, id: Id) -> Result<bool, MethodError> {
+        let document_id = id.document_id();
+        if !self
+            .get_document_ids(account_id, Collection::PushSubscription)
+            .await?
+            .map_or(false, |ids| ids.contains(document_id))
+        {
+            return Ok(false);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::PushSubscription)
+            .delete_document(document_id)
+            .value(Property::Value, (), F_VALUE | F_CLEAR)
+            .value(Property::DeliveryStats, (), F_VALUE | F_CLEAR);
+        self.write_batch(batch).await?;
+
+        // Stop sending notifications to the subscription right away
+        self.update_push_subscriptions(account_id).await;
+
+        Ok(true)
+    }
+}
+
+fn revocation_key(account_id: u32) -> Vec<u8> {
+    format!("jmap:rv:{account_id}").into_bytes()
+}
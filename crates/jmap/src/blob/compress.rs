@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{services::jobs::JobContext, JMAP};
+
+impl JMAP {
+    // Blobs written before compression was enabled are rewritten in place
+    pub async fn blob_compression(&self, ctx: &JobContext) -> Result<(), String> {
+        let hashes = self
+            .store
+            .blob_hash_list()
+            .await
+            .map_err(|err| format!("Failed to list blobs: {err}"))?;
+
+        let total = hashes.len().max(1);
+        let mut compressed = 0;
+        for (pos, hash) in hashes.into_iter().enumerate() {
+            if ctx.is_cancelled() {
+                return Ok(());
+            }
+            if self
+                .blob_store
+                .compress_blob(hash.as_ref())
+                .await
+                .map_err(|err| format!("Failed to compress blob {hash:?}: {err}"))?
+            {
+                compressed += 1;
+            }
+            if pos % 1000 == 999 {
+                self.job_progress(ctx, ((pos + 1) * 100 / total) as u8)
+                    .await;
+            }
+        }
+
+        tracing::info!(
+            context = "blob_compression",
+            event = "finish",
+            compressed = compressed,
+            "Compressed existing blobs."
+        );
+
+        Ok(())
+    }
+}
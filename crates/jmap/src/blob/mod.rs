@@ -23,9 +23,11 @@
 
 use jmap_proto::types::{blob::BlobId, id::Id};
 
+pub mod compress;
 pub mod copy;
 pub mod download;
 pub mod get;
+pub mod tiering;
 pub mod upload;
 
 #[derive(Debug, serde::Serialize)]
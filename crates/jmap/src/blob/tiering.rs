@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::{
+    error::method::MethodError,
+    types::{collection::Collection, property::Property},
+};
+use store::{query::Filter, roaring::RoaringBitmap, write::now, BlobHash};
+
+use crate::{email::metadata::MessageMetadata, services::jobs::JobContext, Bincode, JMAP};
+
+impl JMAP {
+    pub async fn blob_tiering(
+        &self,
+        account_id: Option<u32>,
+        ctx: &JobContext,
+    ) -> Result<(), String> {
+        if !self.blob_store.is_tiered() {
+            return Err("The blob store is not tiered.".to_string());
+        }
+
+        let account_ids = if let Some(account_id) = account_id {
+            vec![account_id]
+        } else {
+            let mut account_ids = Vec::new();
+            for name in self
+                .store
+                .list_accounts(None, None, 0)
+                .await
+                .map_err(|err| format!("Failed to list accounts: {err:?}"))?
+            {
+                if let Ok(Some(account_id)) = self.store.get_account_id(&name).await {
+                    account_ids.push(account_id);
+                }
+            }
+            account_ids
+        };
+
+        let total = account_ids.len().max(1);
+        let mut demoted = 0;
+        for (pos, account_id) in account_ids.into_iter().enumerate() {
+            let document_ids = self
+                .blob_tiering_candidates(account_id)
+                .await
+                .map_err(|err| {
+                    format!("Failed to obtain messages for account {account_id}: {err:?}")
+                })?;
+
+            for document_id in document_ids {
+                if ctx.is_cancelled() {
+                    return Ok(());
+                }
+                let blob_hash = match self
+                    .get_property::<Bincode<MessageMetadata>>(
+                        account_id,
+                        Collection::Email,
+                        document_id,
+                        Property::BodyStructure,
+                    )
+                    .await
+                {
+                    Ok(Some(metadata)) => metadata.inner.blob_hash,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        return Err(format!(
+                            "Failed to obtain metadata for account {account_id}: {err:?}"
+                        ))
+                    }
+                };
+                if self
+                    .blob_store
+                    .demote_blob(blob_hash.as_ref())
+                    .await
+                    .map_err(|err| format!("Failed to move blob {blob_hash:?}: {err}"))?
+                {
+                    demoted += 1;
+                }
+            }
+
+            self.job_progress(ctx, ((pos + 1) * 100 / total) as u8)
+                .await;
+        }
+
+        tracing::info!(
+            context = "blob_tiering",
+            event = "finish",
+            demoted = demoted,
+            "Moved blobs to the cold storage tier."
+        );
+
+        Ok(())
+    }
+
+    async fn blob_tiering_candidates(&self, account_id: u32) -> Result<RoaringBitmap, MethodError> {
+        let mut document_ids = RoaringBitmap::new();
+
+        if let Some(older_than) = self.config.tiering_older_than {
+            document_ids |= self
+                .filter(
+                    account_id,
+                    Collection::Email,
+                    vec![Filter::lt(
+                        Property::ReceivedAt,
+                        now().saturating_sub(older_than.as_secs()),
+                    )],
+                )
+                .await?
+                .results;
+        }
+
+        if self.config.tiering_archive {
+            if let Some(mailbox_id) = self.mailbox_get_by_role(account_id, "archive").await? {
+                if let Some(archived_ids) = self
+                    .get_tag(
+                        account_id,
+                        Collection::Email,
+                        Property::MailboxIds,
+                        mailbox_id,
+                    )
+                    .await?
+                {
+                    document_ids |= archived_ids;
+                }
+            }
+        }
+
+        Ok(document_ids)
+    }
+
+    pub async fn blob_is_cold(&self, hash: &BlobHash) -> Result<bool, MethodError> {
+        self.blob_store.is_cold(hash.as_ref()).await.map_err(|err| {
+            tracing::error!(event = "error",
+                            context = "blob_store",
+                            blob_id = ?hash,
+                            error = ?err,
+                            "Failed to obtain blob storage tier");
+            MethodError::ServerPartialFail
+        })
+    }
+}
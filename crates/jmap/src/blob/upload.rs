@@ -257,17 +257,32 @@ impl JMAP {
         })
     }
 
-    #[allow(clippy::blocks_in_if_conditions)]
     pub async fn put_blob(
         &self,
         account_id: u32,
         data: &[u8],
         set_quota: bool,
+    ) -> Result<BlobId, MethodError> {
+        self.put_blob_until(
+            account_id,
+            data,
+            set_quota,
+            now() + self.config.upload_tmp_ttl,
+        )
+        .await
+    }
+
+    #[allow(clippy::blocks_in_if_conditions)]
+    pub async fn put_blob_until(
+        &self,
+        account_id: u32,
+        data: &[u8],
+        set_quota: bool,
+        until: u64,
     ) -> Result<BlobId, MethodError> {
         // First reserve the hash
         let hash = BlobHash::from(data);
         let mut batch = BatchBuilder::new();
-        let until = now() + self.config.upload_tmp_ttl;
 
         batch.with_account_id(account_id).set(
             BlobOp::Reserve {
@@ -62,6 +62,21 @@ impl JMAP {
 
                 Collection::EmailSubmission
             }
+            RequestArguments::DeliveryStatus => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::DeliveryStatus
+            }
+            RequestArguments::Label => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::Label
+            }
+            RequestArguments::Activity => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::Activity
+            }
             RequestArguments::Quota => {
                 access_token.assert_is_member(request.account_id)?;
 
@@ -48,12 +48,20 @@ use jmap_proto::{
 };
 use mail_parser::{parsers::fields::thread::thread_name, HeaderName, HeaderValue};
 use store::{
-    write::{BatchBuilder, ValueClass, F_BITMAP, F_VALUE},
+    write::{now, BatchBuilder, ValueClass, F_BITMAP, F_INDEX, F_VALUE},
     BlobClass,
 };
 use utils::map::vec_map::VecMap;
 
-use crate::{auth::AccessToken, mailbox::UidMailbox, services::housekeeper::Event, Bincode, JMAP};
+use crate::{
+    auth::AccessToken,
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox,
+    },
+    services::housekeeper::Event,
+    Bincode, JMAP,
+};
 
 use super::{
     index::{EmailIndexBuilder, TrimTextValue, VisitValues, MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH},
@@ -401,6 +409,13 @@ impl JMAP {
             changes.log_child_update(Collection::Mailbox, *mailbox_id);
         }
 
+        // Update mailbox counters
+        let mut counters = MailboxCounters::new();
+        let is_unread = is_unread(&keywords);
+        for mailbox_id in &mailboxes {
+            counters.add_message(*mailbox_id, is_unread, metadata.size as u64);
+        }
+
         // Build batch
         batch
             .with_collection(Collection::Email)
@@ -416,12 +431,14 @@ impl JMAP {
             )
             .value(Property::Keywords, keywords, F_VALUE | F_BITMAP)
             .value(Property::Cid, changes.change_id, F_VALUE)
+            .value(Property::CreatedAt, now(), F_VALUE | F_INDEX)
             .set(
                 ValueClass::IndexEmail(self.generate_snowflake_id()?),
                 metadata.blob_hash.clone(),
             )
             .custom(EmailIndexBuilder::set(metadata))
-            .custom(changes);
+            .custom(changes)
+            .custom(counters);
 
         self.store.write(batch.build()).await.map_err(|err| {
             tracing::error!(
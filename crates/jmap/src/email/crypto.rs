@@ -687,7 +687,7 @@ impl JMAP {
 
             // Authenticate
             let token = self
-                .authenticate_plain(email, password, remote_addr)
+                .authenticate_plain(email, password, remote_addr, "Web")
                 .await
                 .ok_or_else(|| Cow::from("Invalid login or password"))?;
             if encryption != "disable" {
@@ -296,6 +296,17 @@ impl JMAP {
                     Property::HasAttachment => {
                         email.append(Property::HasAttachment, metadata.has_attachments);
                     }
+                    Property::StorageTier => {
+                        // Hints clients that fetching the message body may be slow
+                        email.append(
+                            Property::StorageTier,
+                            if self.blob_is_cold(&metadata.blob_hash).await? {
+                                "cold"
+                            } else {
+                                "hot"
+                            },
+                        );
+                    }
                     Property::Subject => {
                         email.append(
                             Property::Subject,
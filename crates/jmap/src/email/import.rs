@@ -41,7 +41,7 @@ use utils::map::vec_map::VecMap;
 
 use crate::{auth::AccessToken, IngestError, JMAP};
 
-use super::ingest::IngestEmail;
+use super::ingest::{IngestBatch, IngestEmail};
 
 impl JMAP {
     pub async fn email_import(
@@ -76,6 +76,9 @@ impl JMAP {
             state_change: None,
         };
 
+        let mut batch = IngestBatch::new(self.config.mail_ingest_batch_size);
+        let mut batch_ids = Vec::new();
+
         'outer: for (id, email) in request.emails {
             // Validate mailboxIds
             let mailbox_ids = email
@@ -132,21 +135,38 @@ impl JMAP {
 
             // Import message
             match self
-                .email_ingest(IngestEmail {
-                    raw_message: &raw_message,
-                    message: MessageParser::new().parse(&raw_message),
-                    account_id,
-                    account_quota,
-                    mailbox_ids,
-                    keywords: email.keywords,
-                    received_at: email.received_at.map(|r| r.into()),
-                    skip_duplicates: true,
-                    encrypt: self.config.encrypt && self.config.encrypt_append,
-                })
+                .email_ingest_batched(
+                    &mut batch,
+                    IngestEmail {
+                        raw_message: &raw_message,
+                        message: MessageParser::new().parse(&raw_message),
+                        account_id,
+                        account_quota,
+                        mailbox_ids,
+                        keywords: email.keywords,
+                        received_at: email.received_at.map(|r| r.into()),
+                        skip_duplicates: true,
+                        encrypt: self.config.encrypt && self.config.encrypt_append,
+                    },
+                )
                 .await
             {
                 Ok(email) => {
+                    batch_ids.push(id.clone());
                     response.created.append(id, email.into());
+
+                    // Flush batch
+                    if batch.is_full() {
+                        self.email_import_commit(
+                            std::mem::replace(
+                                &mut batch,
+                                IngestBatch::new(self.config.mail_ingest_batch_size),
+                            ),
+                            &mut batch_ids,
+                            &mut response,
+                        )
+                        .await;
+                    }
                 }
                 Err(IngestError::Permanent { reason, .. }) => {
                     response.not_created.append(
@@ -162,11 +182,15 @@ impl JMAP {
                     );
                 }
                 Err(IngestError::Temporary) => {
-                    return Err(MethodError::ServerPartialFail);
+                    response.not_created.append(id, temporary_failure());
                 }
             }
         }
 
+        // Write any pending messages
+        self.email_import_commit(batch, &mut batch_ids, &mut response)
+            .await;
+
         // Update state
         if !response.created.is_empty() {
             response.new_state = self.get_state(account_id, Collection::Email).await?;
@@ -181,4 +205,26 @@ impl JMAP {
 
         Ok(response)
     }
+
+    async fn email_import_commit(
+        &self,
+        batch: IngestBatch,
+        batch_ids: &mut Vec<String>,
+        response: &mut ImportEmailResponse,
+    ) {
+        // Messages in a batch that could not be written are reported as not created,
+        // previously committed batches remain in the created list
+        if self.email_ingest_commit(batch).await.is_err() {
+            for id in batch_ids.drain(..) {
+                response.created.remove(&id);
+                response.not_created.append(id, temporary_failure());
+            }
+        } else {
+            batch_ids.clear();
+        }
+    }
+}
+
+fn temporary_failure() -> SetError {
+    SetError::forbidden().with_description("Temporary server failure, try again later.")
 }
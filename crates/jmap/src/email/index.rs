@@ -42,7 +42,7 @@ use store::{
 
 use crate::{mailbox::UidMailbox, Bincode};
 
-use super::metadata::MessageMetadata;
+use super::metadata::{MessageMetadata, MessagePartStats};
 
 pub const MAX_MESSAGE_PARTS: usize = 1000;
 pub const MAX_ID_LENGTH: usize = 100;
@@ -159,6 +159,13 @@ impl IndexMessage for BatchBuilder {
             Vec::new(),
         );
 
+        // Store part statistics
+        self.value(
+            Property::BodyStats,
+            Bincode::new(MessagePartStats::new(&message)),
+            F_VALUE,
+        );
+
         // Store message metadata
         self.value(
             Property::BodyStructure,
@@ -416,7 +423,9 @@ impl<'x> IntoOperations for EmailIndexBuilder<'x> {
             0
         } else {
             // Delete metadata
-            batch.value(Property::BodyStructure, (), F_VALUE | F_CLEAR);
+            batch
+                .value(Property::BodyStructure, (), F_VALUE | F_CLEAR)
+                .value(Property::BodyStats, (), F_VALUE | F_CLEAR);
             F_CLEAR
         };
         let metadata = &self.inner.inner;
@@ -461,6 +470,31 @@ impl<'x> IntoOperations for EmailIndexBuilder<'x> {
     }
 }
 
+/// Rewrites the property indexes of an existing message without
+/// touching its metadata, quota usage or blob links.
+pub struct EmailIndexRefresh<'x> {
+    inner: MessageMetadata<'x>,
+}
+
+impl<'x> EmailIndexRefresh<'x> {
+    pub fn new(inner: MessageMetadata<'x>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'x> IntoOperations for EmailIndexRefresh<'x> {
+    fn build(self, batch: &mut BatchBuilder) {
+        let metadata = &self.inner;
+        batch
+            .value(Property::Size, metadata.size as u32, F_INDEX)
+            .value(Property::ReceivedAt, metadata.received_at, F_INDEX);
+        if metadata.has_attachments {
+            batch.tag(Property::HasAttachment, (), 0);
+        }
+        batch.index_headers(&metadata.contents.parts[0].headers, 0);
+    }
+}
+
 impl SortedAddressBuilder {
     pub fn new() -> Self {
         Self {
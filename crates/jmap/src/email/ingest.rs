@@ -36,19 +36,22 @@ use mail_parser::{
 
 use rand::Rng;
 use store::{
-    ahash::AHashSet,
+    ahash::{AHashMap, AHashSet},
     query::Filter,
     write::{
         log::ChangeLogBuilder, now, BatchBuilder, BitmapClass, TagValue, ValueClass, F_BITMAP,
-        F_CLEAR, F_VALUE,
+        F_CLEAR, F_INDEX, F_VALUE,
     },
-    BitmapKey, BlobClass, ValueKey,
+    BitmapKey, BlobClass, BlobHash, ValueKey,
 };
 use utils::map::vec_map::VecMap;
 
 use crate::{
     email::index::{IndexMessage, VisitValues, MAX_ID_LENGTH},
-    mailbox::{UidMailbox, INBOX_ID, JUNK_ID},
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox, INBOX_ID, JUNK_ID,
+    },
     services::housekeeper::Event,
     IngestError, JMAP,
 };
@@ -78,12 +81,37 @@ pub struct IngestEmail<'x> {
     pub encrypt: bool,
 }
 
+/// Accumulates the index, bitmap and change log updates of multiple
+/// ingested messages so they can be committed using a single write batch.
+pub struct IngestBatch {
+    batch: BatchBuilder,
+    changes: VecMap<u32, ChangeLogBuilder>,
+    used_quota: VecMap<u32, i64>,
+    counters: VecMap<u32, MailboxCounters>,
+    thread_ids: AHashMap<(u32, String), u32>,
+    message_ids: AHashSet<(u32, String)>,
+    blob_hashes: AHashSet<BlobHash>,
+    num_messages: usize,
+    max_messages: usize,
+}
+
 const MAX_RETRIES: u32 = 10;
 
 impl JMAP {
-    #[allow(clippy::blocks_in_if_conditions)]
     pub async fn email_ingest(
         &self,
+        params: IngestEmail<'_>,
+    ) -> Result<IngestedEmail, IngestError> {
+        let mut batch = IngestBatch::new(1);
+        let result = self.email_ingest_batched(&mut batch, params).await?;
+        self.email_ingest_commit(batch).await?;
+        Ok(result)
+    }
+
+    #[allow(clippy::blocks_in_if_conditions)]
+    pub async fn email_ingest_batched(
+        &self,
+        ingest_batch: &mut IngestBatch,
         mut params: IngestEmail<'_>,
     ) -> Result<IngestedEmail, IngestError> {
         // Check quota
@@ -94,6 +122,11 @@ impl JMAP {
                     .get_used_quota(params.account_id)
                     .await
                     .map_err(|_| IngestError::Temporary)?
+                + ingest_batch
+                    .used_quota
+                    .get(&params.account_id)
+                    .copied()
+                    .unwrap_or_default()
                 > params.account_quota
         {
             return Err(IngestError::OverQuota);
@@ -106,6 +139,22 @@ impl JMAP {
             reason: "Failed to parse e-mail message.".to_string(),
         })?;
 
+        // Verify message structure limits
+        if let Err(err) = self.config.mail_limits.check(&message) {
+            tracing::info!(
+                event = "limit-exceeded",
+                context = "email_ingest",
+                account_id = params.account_id,
+                limit = err.as_str(),
+                "{}",
+                err.reason()
+            );
+            return Err(IngestError::Permanent {
+                code: err.code(),
+                reason: err.reason().to_string(),
+            });
+        }
+
         // Check for Spam headers
         if let Some((header_name, header_value)) = &self.config.spam_header {
             if params.mailbox_ids == [INBOX_ID]
@@ -122,7 +171,7 @@ impl JMAP {
         }
 
         // Obtain message references and thread name
-        let thread_id = {
+        let (thread_id, references, message_id) = {
             let mut references = Vec::with_capacity(5);
             let mut subject = "";
             let mut message_id = "";
@@ -162,24 +211,27 @@ impl JMAP {
             // Check for duplicates
             if params.skip_duplicates
                 && !message_id.is_empty()
-                && !self
-                    .store
-                    .filter(
-                        params.account_id,
-                        Collection::Email,
-                        vec![Filter::eq(Property::MessageId, message_id)],
-                    )
-                    .await
-                    .map_err(|err| {
-                        tracing::error!(
+                && (ingest_batch
+                    .message_ids
+                    .contains(&(params.account_id, message_id.to_string()))
+                    || !self
+                        .store
+                        .filter(
+                            params.account_id,
+                            Collection::Email,
+                            vec![Filter::eq(Property::MessageId, message_id)],
+                        )
+                        .await
+                        .map_err(|err| {
+                            tracing::error!(
                             event = "error",
                             context = "find_duplicates",
                             error = ?err,
                             "Duplicate message search failed.");
-                        IngestError::Temporary
-                    })?
-                    .results
-                    .is_empty()
+                            IngestError::Temporary
+                        })?
+                        .results
+                        .is_empty())
             {
                 tracing::debug!(
                     context = "email_ingest",
@@ -196,13 +248,26 @@ impl JMAP {
                     size: 0,
                 });
             }
-
-            if !references.is_empty() {
-                self.find_or_merge_thread(params.account_id, subject, &references)
+            let thread_id = if !references.is_empty() {
+                match self
+                    .find_or_merge_thread(params.account_id, subject, &references)
                     .await?
+                {
+                    Some(thread_id) => Some(thread_id),
+                    None => ingest_batch.pending_thread_id(params.account_id, &references),
+                }
             } else {
                 None
-            }
+            };
+
+            (
+                thread_id,
+                references
+                    .into_iter()
+                    .map(|reference| reference.to_string())
+                    .collect::<Vec<_>>(),
+                message_id.to_string(),
+            )
         };
 
         // Encrypt message
@@ -264,37 +329,66 @@ impl JMAP {
                     "Failed to assign documentId.");
                 IngestError::Temporary
             })?;
-        let change_id = self
-            .assign_change_id(params.account_id)
-            .await
-            .map_err(|_| {
-                tracing::error!(
+        let change_id = if let Some(changes) = ingest_batch.changes.get(&params.account_id) {
+            changes.change_id
+        } else {
+            let change_id = self
+                .assign_change_id(params.account_id)
+                .await
+                .map_err(|_| {
+                    tracing::error!(
+                        event = "error",
+                        context = "email_ingest",
+                        "Failed to assign changeId."
+                    );
+                    IngestError::Temporary
+                })?;
+            ingest_batch.changes.append(
+                params.account_id,
+                ChangeLogBuilder::with_change_id(change_id),
+            );
+            change_id
+        };
+
+        // Store blob, copies of the same message delivered to multiple
+        // local recipients share the blob stored by the first copy
+        let blob_hash = BlobHash::from(raw_message.as_ref());
+        let blob_id = if ingest_batch.blob_hashes.contains(&blob_hash) {
+            BlobId::new(
+                blob_hash,
+                BlobClass::Linked {
+                    account_id: params.account_id,
+                    collection: Collection::Email.into(),
+                    document_id,
+                },
+            )
+        } else {
+            let blob_id = self
+                .put_blob(params.account_id, raw_message.as_ref(), false)
+                .await
+                .map_err(|err| {
+                    tracing::error!(
                     event = "error",
                     context = "email_ingest",
-                    "Failed to assign changeId."
-                );
-                IngestError::Temporary
-            })?;
+                    error = ?err,
+                    "Failed to write blob.");
+                    IngestError::Temporary
+                })?;
+            ingest_batch.blob_hashes.insert(blob_hash);
+            blob_id
+        };
 
-        // Store blob
-        let blob_id = self
-            .put_blob(params.account_id, raw_message.as_ref(), false)
-            .await
-            .map_err(|err| {
-                tracing::error!(
-                event = "error",
-                context = "email_ingest",
-                error = ?err,
-                "Failed to write blob.");
-                IngestError::Temporary
-            })?;
+        // Obtain a snowflake id for the FTS index queue
+        let index_id = self
+            .generate_snowflake_id()
+            .map_err(|_| IngestError::Temporary)?;
 
         // Prepare batch
-        let mut batch = BatchBuilder::new();
+        let batch = &mut ingest_batch.batch;
         batch.with_account_id(params.account_id);
 
         // Build change log
-        let mut changes = ChangeLogBuilder::with_change_id(change_id);
+        let changes = ingest_batch.changes.get_mut(&params.account_id).unwrap();
         let thread_id = if let Some(thread_id) = thread_id {
             changes.log_child_update(Collection::Thread, thread_id);
             thread_id
@@ -322,6 +416,18 @@ impl JMAP {
         for mailbox_id in &params.mailbox_ids {
             changes.log_child_update(Collection::Mailbox, *mailbox_id);
         }
+        for reference in references {
+            ingest_batch
+                .thread_ids
+                .insert((params.account_id, reference), thread_id);
+        }
+
+        // Update mailbox counters
+        let counters = ingest_batch.counters.get_mut_or_insert(params.account_id);
+        let is_unread = is_unread(&params.keywords);
+        for mailbox_id in &params.mailbox_ids {
+            counters.add_message(*mailbox_id, is_unread, raw_message_len as u64);
+        }
 
         // Build write batch
         batch
@@ -340,25 +446,15 @@ impl JMAP {
             )
             .value(Property::Cid, change_id, F_VALUE)
             .value(Property::ThreadId, thread_id, F_VALUE | F_BITMAP)
-            .custom(changes)
-            .set(
-                ValueClass::IndexEmail(
-                    self.generate_snowflake_id()
-                        .map_err(|_| IngestError::Temporary)?,
-                ),
-                blob_id.hash.clone(),
-            );
-        self.store.write(batch.build()).await.map_err(|err| {
-            tracing::error!(
-                event = "error",
-                context = "email_ingest",
-                error = ?err,
-                "Failed to write message to database.");
-            IngestError::Temporary
-        })?;
-
-        // Request FTS index
-        let _ = self.housekeeper_tx.send(Event::IndexStart).await;
+            .value(Property::CreatedAt, now(), F_VALUE | F_INDEX)
+            .set(ValueClass::IndexEmail(index_id), blob_id.hash.clone());
+        *ingest_batch.used_quota.get_mut_or_insert(params.account_id) += raw_message_len;
+        if !message_id.is_empty() {
+            ingest_batch
+                .message_ids
+                .insert((params.account_id, message_id));
+        }
+        ingest_batch.num_messages += 1;
 
         tracing::debug!(
             context = "email_ingest",
@@ -387,6 +483,37 @@ impl JMAP {
         })
     }
 
+    pub async fn email_ingest_commit(&self, ingest_batch: IngestBatch) -> Result<(), IngestError> {
+        if ingest_batch.num_messages == 0 {
+            return Ok(());
+        }
+
+        // Write change log entries, one per account
+        let num_messages = ingest_batch.num_messages;
+        let mut batch = ingest_batch.batch;
+        for (account_id, changes) in ingest_batch.changes {
+            batch.with_account_id(account_id).custom(changes);
+        }
+        for (account_id, counters) in ingest_batch.counters {
+            batch.with_account_id(account_id).custom(counters);
+        }
+
+        self.store.write(batch.build()).await.map_err(|err| {
+            tracing::error!(
+                event = "error",
+                context = "email_ingest",
+                error = ?err,
+                num_messages = num_messages,
+                "Failed to write messages to database.");
+            IngestError::Temporary
+        })?;
+
+        // Request FTS index
+        let _ = self.housekeeper_tx.send(Event::IndexStart).await;
+
+        Ok(())
+    }
+
     pub async fn find_or_merge_thread(
         &self,
         account_id: u32,
@@ -558,6 +685,43 @@ impl JMAP {
     }
 }
 
+impl IngestBatch {
+    pub fn new(max_messages: usize) -> Self {
+        IngestBatch {
+            batch: BatchBuilder::new(),
+            changes: VecMap::new(),
+            used_quota: VecMap::new(),
+            counters: VecMap::new(),
+            thread_ids: AHashMap::new(),
+            message_ids: AHashSet::new(),
+            blob_hashes: AHashSet::new(),
+            num_messages: 0,
+            max_messages: std::cmp::max(max_messages, 1),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.num_messages >= self.max_messages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_messages == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_messages
+    }
+
+    fn pending_thread_id(&self, account_id: u32, references: &[&str]) -> Option<u32> {
+        // Messages staged in this batch are not yet visible in the store
+        references.iter().find_map(|reference| {
+            self.thread_ids
+                .get(&(account_id, reference.to_string()))
+                .copied()
+        })
+    }
+}
+
 impl From<IngestedEmail> for Object<Value> {
     fn from(email: IngestedEmail) -> Self {
         Object::with_capacity(3)
@@ -64,6 +64,22 @@ pub struct MessageMetadataPart<'x> {
     pub offset_end: usize,
 }
 
+/// Size, line count and MD5 digest of each MIME part, listed in the depth-first
+/// order in which IMAP body structures are built. Stored alongside the message
+/// metadata so that BODY and BODYSTRUCTURE can be served without fetching the
+/// message blob.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MessagePartStats {
+    pub parts: Vec<PartStats>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct PartStats {
+    pub size: usize,
+    pub lines: usize,
+    pub md5: Option<[u8; 16]>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum MetadataPartType<'x> {
     Text,
@@ -121,11 +137,82 @@ impl<'x> MessageMetadataContents<'x> {
         }
     }
 
+    /// Returns a message containing only the MIME structure and headers,
+    /// the contents of all parts are left empty.
+    pub fn into_message_structure(self) -> Message<'x> {
+        Message {
+            html_body: self.html_body,
+            text_body: self.text_body,
+            attachments: self.attachments,
+            parts: self
+                .parts
+                .into_iter()
+                .map(|part| MessagePart {
+                    body: match part.body {
+                        MetadataPartType::Text => PartType::Text("".into()),
+                        MetadataPartType::Html => PartType::Html("".into()),
+                        MetadataPartType::Binary => PartType::Binary(Cow::Borrowed(&[])),
+                        MetadataPartType::InlineBinary => {
+                            PartType::InlineBinary(Cow::Borrowed(&[]))
+                        }
+                        MetadataPartType::Message(message) => {
+                            PartType::Message(message.into_message_structure())
+                        }
+                        MetadataPartType::Multipart(parts) => PartType::Multipart(parts),
+                    },
+                    headers: part.headers,
+                    is_encoding_problem: part.is_encoding_problem,
+                    encoding: part.encoding,
+                    offset_header: part.offset_header,
+                    offset_body: part.offset_body,
+                    offset_end: part.offset_end,
+                })
+                .collect(),
+            raw_message: Cow::Borrowed(&[]),
+        }
+    }
+
     pub fn root_part(&self) -> &MessageMetadataPart<'x> {
         &self.parts[0]
     }
 }
 
+impl MessagePartStats {
+    pub fn new(message: &Message<'_>) -> Self {
+        let mut stats = MessagePartStats::default();
+        stats.add_part(message, 0);
+        stats
+    }
+
+    fn add_part(&mut self, message: &Message<'_>, part_id: usize) {
+        let part = if let Some(part) = message.parts.get(part_id) {
+            part
+        } else {
+            return;
+        };
+        let body = message.raw_message.get(part.offset_body..part.offset_end);
+        self.parts.push(PartStats {
+            size: body.map_or(0, |body| body.len()),
+            lines: body.map_or(0, |body| body.iter().filter(|&&ch| ch == b'\n').count()),
+            md5: body
+                .filter(|_| !matches!(part.body, PartType::Multipart(_)))
+                .map(|body| md5::compute(body).0),
+        });
+
+        match &part.body {
+            PartType::Message(nested_message) => {
+                self.add_part(nested_message, 0);
+            }
+            PartType::Multipart(sub_parts) => {
+                for sub_part_id in sub_parts {
+                    self.add_part(message, *sub_part_id);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
 impl<'x> MessageMetadataPart<'x> {
     pub fn contents<'y>(&self, raw_message: &'y [u8]) -> Cow<'y, [u8]> {
         let bytes = raw_message
@@ -34,3 +34,5 @@ pub mod parse;
 pub mod query;
 pub mod set;
 pub mod snippet;
+pub mod tombstone;
+pub mod unsubscribe;
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, time::Duration};
 
 use jmap_proto::{
     error::{
@@ -54,14 +54,19 @@ use store::{
     ahash::AHashSet,
     write::{
         assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, DeserializeFrom, SerializeInto,
-        ToBitmaps, ValueClass, F_BITMAP, F_CLEAR, F_VALUE,
+        ToBitmaps, ValueClass, F_BITMAP, F_CLEAR, F_INDEX, F_VALUE,
     },
     Serialize,
 };
 
 use crate::{
-    auth::AccessToken, mailbox::UidMailbox, services::housekeeper::Event, Bincode, IngestError,
-    JMAP,
+    auth::AccessToken,
+    mailbox::{
+        counters::{is_unread, MailboxCounters},
+        UidMailbox,
+    },
+    services::housekeeper::Event,
+    Bincode, IngestError, JMAP,
 };
 
 use super::{
@@ -69,6 +74,7 @@ use super::{
     index::EmailIndexBuilder,
     ingest::IngestEmail,
     metadata::MessageMetadata,
+    tombstone::Tombstone,
 };
 
 impl JMAP {
@@ -864,6 +870,25 @@ impl JMAP {
             let mut changed_mailboxes = AHashSet::new();
             changes.log_update(Collection::Email, id);
 
+            // Update mailbox counters
+            let mut counters = MailboxCounters::new();
+            counters.update_message(
+                mailboxes.previous().map(|m| m.mailbox_id),
+                is_unread(keywords.previous()),
+                mailboxes.current().iter().map(|m| m.mailbox_id),
+                is_unread(keywords.current()),
+                if mailboxes.has_changes() {
+                    self.email_size(account_id, document_id).await?
+                } else {
+                    0
+                },
+            );
+            if !counters.is_empty() {
+                // Counters depend on both the mailboxes and the keywords
+                mailboxes.assert_batch(&mut batch, Property::MailboxIds);
+                keywords.assert_batch(&mut batch, Property::Keywords);
+            }
+
             // Process keywords
             if keywords.has_changes() {
                 // Verify permissions on shared accounts
@@ -969,6 +994,9 @@ impl JMAP {
 
             // Write changes
             if !batch.is_empty() {
+                if !counters.is_empty() {
+                    batch.custom(counters);
+                }
                 match self.store.write(batch.build()).await {
                     Ok(_) => {
                         // Add to updated list
@@ -1062,6 +1090,20 @@ impl JMAP {
         &self,
         account_id: u32,
         document_id: u32,
+    ) -> Result<Result<ChangeLogBuilder, SetError>, MethodError> {
+        self.email_delete_with_retention(
+            account_id,
+            document_id,
+            self.config.mail_recovery_retention,
+        )
+        .await
+    }
+
+    pub async fn email_delete_with_retention(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        retention: Option<Duration>,
     ) -> Result<Result<ChangeLogBuilder, SetError>, MethodError> {
         // Create batch
         let mut batch = BatchBuilder::new();
@@ -1080,6 +1122,19 @@ impl JMAP {
         // Remove last changeId
         batch.value(Property::Cid, (), F_VALUE | F_CLEAR);
 
+        // Remove save date
+        if let Some(created_at) = self
+            .get_property::<u64>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::CreatedAt,
+            )
+            .await?
+        {
+            batch.value(Property::CreatedAt, created_at, F_VALUE | F_INDEX | F_CLEAR);
+        }
+
         // Remove mailboxes
         let mailboxes = if let Some(mailboxes) = self
             .get_property::<HashedValue<Vec<UidMailbox>>>(
@@ -1104,6 +1159,11 @@ impl JMAP {
         for mailbox_id in &mailboxes.inner {
             changes.log_child_update(Collection::Mailbox, mailbox_id.mailbox_id);
         }
+        let mailbox_ids = mailboxes
+            .inner
+            .iter()
+            .map(|m| m.mailbox_id)
+            .collect::<Vec<_>>();
         batch.assert_value(Property::MailboxIds, &mailboxes).value(
             Property::MailboxIds,
             mailboxes.inner,
@@ -1111,7 +1171,7 @@ impl JMAP {
         );
 
         // Remove keywords
-        if let Some(keywords) = self
+        let (keywords, was_unread) = if let Some(keywords) = self
             .get_property::<HashedValue<Vec<Keyword>>>(
                 account_id,
                 Collection::Email,
@@ -1120,11 +1180,18 @@ impl JMAP {
             )
             .await?
         {
+            let keyword_names = keywords
+                .inner
+                .iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>();
+            let was_unread = is_unread(&keywords.inner);
             batch.assert_value(Property::Keywords, &keywords).value(
                 Property::Keywords,
                 keywords.inner,
                 F_VALUE | F_BITMAP | F_CLEAR,
             );
+            (keyword_names, was_unread)
         } else {
             tracing::debug!(
                 event = "error",
@@ -1193,6 +1260,7 @@ impl JMAP {
         }
 
         // Remove message metadata
+        let mut counters = MailboxCounters::new();
         if let Some(metadata) = self
             .get_property::<Bincode<MessageMetadata>>(
                 account_id,
@@ -1202,6 +1270,23 @@ impl JMAP {
             )
             .await?
         {
+            // Update mailbox counters
+            for mailbox_id in &mailbox_ids {
+                counters.remove_message(*mailbox_id, was_unread, metadata.inner.size as u64);
+            }
+
+            // Keep the message recoverable for the retention period
+            if let Some(retention) = retention {
+                let tombstone = Tombstone::new(
+                    self.generate_snowflake_id()?,
+                    account_id,
+                    &metadata.inner,
+                    mailbox_ids,
+                    keywords,
+                    retention,
+                );
+                tombstone.write(&mut batch);
+            }
             batch.custom(EmailIndexBuilder::clear(metadata.inner));
         } else {
             tracing::debug!(
@@ -1220,6 +1305,7 @@ impl JMAP {
                 .with_collection(Collection::Thread)
                 .delete_document(thread_id);
         }
+        batch.custom(counters);
 
         // Commit batch
         match self.store.write(batch.build()).await {
@@ -1323,6 +1409,14 @@ impl<
         &self.current.inner
     }
 
+    pub fn previous(&self) -> impl Iterator<Item = &T> {
+        self.current
+            .inner
+            .iter()
+            .filter(|tag| !self.added.contains(tag))
+            .chain(self.removed.iter())
+    }
+
     pub fn changed_tags(&self) -> impl Iterator<Item = &T> {
         self.added.iter().chain(self.removed.iter())
     }
@@ -1335,6 +1429,10 @@ impl<
         !self.added.is_empty() || !self.removed.is_empty()
     }
 
+    pub fn assert_batch(&self, batch: &mut BatchBuilder, property: Property) {
+        batch.assert_value(ValueClass::Property(property.into()), &self.current);
+    }
+
     pub fn update_batch(self, batch: &mut BatchBuilder, property: Property) {
         let property = u8::from(property);
 
@@ -0,0 +1,449 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::QueryBy;
+use hyper::{Method, StatusCode};
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    types::{id::Id, keyword::Keyword, state::StateChange, type_state::DataType},
+};
+use mail_parser::{HeaderName, MessageParser};
+use serde_json::json;
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, BlobOp, ValueClass},
+    BlobHash, Deserialize, IterateParams, LookupValue, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    auth::AccessToken,
+    mailbox::INBOX_ID,
+    Bincode, IngestError, JMAP,
+};
+
+use super::{
+    index::{AddressElement, VisitValues},
+    ingest::IngestEmail,
+    metadata::MessageMetadata,
+};
+
+const TOMBSTONE_KEY_PREFIX: &[u8] = b"tombstone.";
+
+/// Deleted message that can still be recovered until the retention period
+/// expires. The message blob is kept alive by a reservation that expires
+/// together with the tombstone.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tombstone {
+    pub id: u64,
+    pub account_id: u32,
+    pub blob_hash: BlobHash,
+    pub mailbox_ids: Vec<u32>,
+    pub keywords: Vec<String>,
+    pub received_at: u64,
+    pub size: usize,
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub preview: String,
+    pub deleted_at: u64,
+    pub expires: u64,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct RecoverRequest {
+    #[serde(default)]
+    ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecoveredItem {
+    pub id: String,
+    pub status: RecoveryStatus,
+    #[serde(rename = "emailId", skip_serializing_if = "Option::is_none")]
+    pub email_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum RecoveryStatus {
+    #[serde(rename = "recovered")]
+    Recovered,
+    #[serde(rename = "notFound")]
+    NotFound,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+impl JMAP {
+    pub async fn handle_email_recover(
+        &self,
+        req: &HttpRequest,
+        account_id: u32,
+        account_name: &str,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        match req.method() {
+            &Method::GET => match self.email_tombstones(account_id).await {
+                Ok(tombstones) => JsonResponse::new(json!({
+                    "data": tombstones
+                        .into_iter()
+                        .map(Tombstone::into_json)
+                        .collect::<Vec<_>>(),
+                }))
+                .into_http_response(),
+                Err(err) => RequestError::blank(
+                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    "Failed to list deleted items",
+                    err.to_string(),
+                )
+                .into_http_response(),
+            },
+            &Method::POST => {
+                let ids = match serde_json::from_slice::<RecoverRequest>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| err.to_string())
+                .and_then(|request| {
+                    request
+                        .ids
+                        .iter()
+                        .map(|id| {
+                            id.parse::<u64>()
+                                .map_err(|_| format!("Invalid deleted item id {id:?}."))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                }) {
+                    Ok(ids) if !ids.is_empty() => ids,
+                    Ok(_) => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "No deleted items were selected for recovery.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            err,
+                        )
+                        .into_http_response();
+                    }
+                };
+
+                match self
+                    .email_recover(account_id, account_name, ids, &access_token.name)
+                    .await
+                {
+                    Ok(items) => JsonResponse::new(json!({
+                        "data": items,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Recovery failed",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    pub async fn email_tombstones(&self, account_id: u32) -> store::Result<Vec<Tombstone>> {
+        let mut tombstones = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Key(tombstone_key(account_id, 0))),
+                    ValueKey::from(ValueClass::Key(tombstone_key(account_id, u64::MAX))),
+                ),
+                |_, value| {
+                    if let LookupValue::Value { value, .. } =
+                        LookupValue::<Bincode<Tombstone>>::deserialize(value)?
+                    {
+                        tombstones.push(value.inner);
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        // Most recently deleted first
+        tombstones.reverse();
+
+        Ok(tombstones)
+    }
+
+    pub async fn email_tombstone(
+        &self,
+        account_id: u32,
+        tombstone_id: u64,
+    ) -> store::Result<Option<Tombstone>> {
+        if let Some(LookupValue::Value { value, .. }) = self
+            .store
+            .get_value::<LookupValue<Bincode<Tombstone>>>(ValueKey::from(ValueClass::Key(
+                tombstone_key(account_id, tombstone_id),
+            )))
+            .await?
+        {
+            Ok(Some(value.inner))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn email_recover(
+        &self,
+        account_id: u32,
+        account_name: &str,
+        ids: Vec<u64>,
+        requested_by: &str,
+    ) -> Result<Vec<RecoveredItem>, MethodError> {
+        let account_quota = match self.directory.query(QueryBy::Id(account_id), false).await {
+            Ok(Some(principal)) => principal.quota as i64,
+            Ok(None) => 0,
+            Err(_) => return Err(MethodError::ServerPartialFail),
+        };
+        let valid_mailbox_ids = self.mailbox_get_or_create(account_id).await?;
+        let mut last_change_id = None;
+        let mut items = Vec::with_capacity(ids.len());
+
+        for tombstone_id in ids {
+            let mut item = RecoveredItem {
+                id: tombstone_id.to_string(),
+                status: RecoveryStatus::Failed,
+                email_id: None,
+                reason: None,
+            };
+            let tombstone = match self
+                .email_tombstone(account_id, tombstone_id)
+                .await
+                .map_err(|_| MethodError::ServerPartialFail)?
+            {
+                Some(tombstone) => tombstone,
+                None => {
+                    item.status = RecoveryStatus::NotFound;
+                    items.push(item);
+                    continue;
+                }
+            };
+            let raw_message = if let Some(raw_message) =
+                self.get_blob(&tombstone.blob_hash, 0..u32::MAX).await?
+            {
+                raw_message
+            } else {
+                item.reason = "Message contents are no longer available."
+                    .to_string()
+                    .into();
+                items.push(item);
+                continue;
+            };
+
+            // Restore the message into the mailboxes that still exist
+            let mut mailbox_ids = tombstone
+                .mailbox_ids
+                .iter()
+                .filter(|mailbox_id| valid_mailbox_ids.contains(**mailbox_id))
+                .copied()
+                .collect::<Vec<_>>();
+            if mailbox_ids.is_empty() {
+                mailbox_ids.push(INBOX_ID);
+            }
+
+            match self
+                .email_ingest(IngestEmail {
+                    raw_message: &raw_message,
+                    message: MessageParser::new().parse(&raw_message),
+                    account_id,
+                    account_quota,
+                    mailbox_ids,
+                    keywords: tombstone
+                        .keywords
+                        .iter()
+                        .map(|keyword| Keyword::from(keyword.clone()))
+                        .collect(),
+                    received_at: tombstone.received_at.into(),
+                    skip_duplicates: false,
+                    encrypt: false,
+                })
+                .await
+            {
+                Ok(email) => {
+                    last_change_id = email.change_id.into();
+                    item.status = RecoveryStatus::Recovered;
+                    item.email_id = email.id.into();
+
+                    // The blob reservation is left to expire on its own
+                    let mut batch = BatchBuilder::new();
+                    batch.clear(ValueClass::Key(tombstone_key(account_id, tombstone_id)));
+                    if let Err(err) = self.store.write(batch.build()).await {
+                        tracing::warn!(
+                            context = "recover",
+                            event = "error",
+                            account_id = account_id,
+                            tombstone_id = tombstone_id,
+                            reason = ?err,
+                            "Failed to remove tombstone"
+                        );
+                    }
+                }
+                Err(IngestError::OverQuota) => {
+                    item.reason = "Account is over quota.".to_string().into();
+                }
+                Err(IngestError::Permanent { reason, .. }) => {
+                    item.reason = reason.into();
+                }
+                Err(IngestError::Temporary) => {
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+            items.push(item);
+        }
+
+        if let Some(change_id) = last_change_id {
+            self.broadcast_state_change(
+                StateChange::new(account_id)
+                    .with_change(DataType::Email, change_id)
+                    .with_change(DataType::Mailbox, change_id)
+                    .with_change(DataType::Thread, change_id),
+            )
+            .await;
+        }
+
+        tracing::info!(
+            context = "audit",
+            event = "recover",
+            admin = requested_by,
+            account = account_name,
+            recovered = items
+                .iter()
+                .filter(|item| item.status == RecoveryStatus::Recovered)
+                .count(),
+            "Deleted messages recovered."
+        );
+
+        Ok(items)
+    }
+}
+
+impl Tombstone {
+    pub fn new(
+        id: u64,
+        account_id: u32,
+        metadata: &MessageMetadata<'_>,
+        mailbox_ids: Vec<u32>,
+        keywords: Vec<String>,
+        retention: Duration,
+    ) -> Self {
+        let deleted_at = now();
+        let mut tombstone = Tombstone {
+            id,
+            account_id,
+            blob_hash: metadata.blob_hash.clone(),
+            mailbox_ids,
+            keywords,
+            received_at: metadata.received_at,
+            size: metadata.size,
+            subject: None,
+            from: None,
+            preview: metadata.preview.clone(),
+            deleted_at,
+            expires: deleted_at + retention.as_secs(),
+        };
+
+        for header in &metadata.contents.root_part().headers {
+            match &header.name {
+                HeaderName::Subject if tombstone.subject.is_none() => {
+                    header.value.visit_text(|subject| {
+                        if tombstone.subject.is_none() {
+                            tombstone.subject = subject.to_string().into();
+                        }
+                    });
+                }
+                HeaderName::From if tombstone.from.is_none() => {
+                    header.value.visit_addresses(|element, value| {
+                        if tombstone.from.is_none() && element == AddressElement::Address {
+                            tombstone.from = value.to_string().into();
+                        }
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        tombstone
+    }
+
+    pub fn write(&self, batch: &mut BatchBuilder) {
+        // Tombstones and their blob reservations expire at the same time
+        let value = Bincode::new(self.clone()).serialize();
+        batch
+            .set(
+                ValueClass::Key(tombstone_key(self.account_id, self.id)),
+                KeySerializer::new(value.len() + U64_LEN)
+                    .write(self.expires)
+                    .write(value.as_slice())
+                    .finalize(),
+            )
+            .set(
+                BlobOp::Reserve {
+                    hash: self.blob_hash.clone(),
+                    until: self.expires,
+                },
+                0u32.serialize(),
+            );
+    }
+
+    pub fn into_json(self) -> serde_json::Value {
+        json!({
+            "id": self.id.to_string(),
+            "mailboxIds": self
+                .mailbox_ids
+                .into_iter()
+                .map(|mailbox_id| Id::from(mailbox_id).to_string())
+                .collect::<Vec<_>>(),
+            "keywords": self.keywords,
+            "receivedAt": self.received_at,
+            "deletedAt": self.deleted_at,
+            "expires": self.expires,
+            "size": self.size,
+            "subject": self.subject,
+            "from": self.from,
+            "preview": self.preview,
+        })
+    }
+}
+
+fn tombstone_key(account_id: u32, tombstone_id: u64) -> Vec<u8> {
+    KeySerializer::new(TOMBSTONE_KEY_PREFIX.len() + U32_LEN + U64_LEN)
+        .write(TOMBSTONE_KEY_PREFIX)
+        .write(account_id)
+        .write(tombstone_id)
+        .finalize()
+}
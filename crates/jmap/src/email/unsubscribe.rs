@@ -0,0 +1,422 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::{IpAddr, SocketAddr};
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::{
+        set::{self, SetRequest},
+        unsubscribe::{UnsubscribeEmailRequest, UnsubscribeEmailResponse},
+    },
+    object::Object,
+    request::{
+        method::{MethodFunction, MethodName, MethodObject},
+        Call, RequestMethod,
+    },
+    types::{
+        collection::Collection,
+        keyword::Keyword,
+        property::Property,
+        value::{SetValue, Value},
+    },
+};
+use mail_builder::MessageBuilder;
+use reqwest::{header::CONTENT_TYPE, redirect, Url};
+use smtp::core::{NullIo, Session, SessionAddress};
+use utils::map::vec_map::VecMap;
+
+use crate::{identity::set::sanitize_email, Bincode, JMAP};
+
+use super::metadata::MessageMetadata;
+
+pub const UNSUBSCRIBED_KEYWORD: &str = "$unsubscribed";
+
+#[derive(Debug, PartialEq, Eq)]
+enum UnsubscribeAction {
+    OneClick(Url),
+    Mailto {
+        address: String,
+        subject: String,
+        body: String,
+    },
+}
+
+impl JMAP {
+    pub async fn email_unsubscribe(
+        &self,
+        request: UnsubscribeEmailRequest,
+        next_call: &mut Option<Call<RequestMethod>>,
+    ) -> Result<UnsubscribeEmailResponse, MethodError> {
+        if request.email_ids.len() > self.config.set_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+
+        let account_id = request.account_id.document_id();
+        let mut response = UnsubscribeEmailResponse {
+            account_id: request.account_id,
+            unsubscribed: Vec::with_capacity(request.email_ids.len()),
+            not_unsubscribed: VecMap::new(),
+        };
+        let mut mail_from = None;
+
+        for id in request.email_ids {
+            // Obtain the message headers
+            let metadata = if let Some(metadata) = self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    id.document_id(),
+                    &Property::BodyStructure,
+                )
+                .await?
+            {
+                metadata.inner
+            } else {
+                response.not_unsubscribed.append(id, SetError::not_found());
+                continue;
+            };
+            let raw_headers = if let Some(raw_headers) = self
+                .get_blob(
+                    &metadata.blob_hash,
+                    0..metadata.contents.parts[0].offset_body as u32,
+                )
+                .await?
+            {
+                raw_headers
+            } else {
+                response.not_unsubscribed.append(
+                    id,
+                    SetError::not_found().with_description("Message blob not found."),
+                );
+                continue;
+            };
+
+            let mut list_unsubscribe = None;
+            let mut one_click = false;
+            for header in &metadata.contents.parts[0].headers {
+                let name = header.name.as_str();
+                if name.eq_ignore_ascii_case("List-Unsubscribe") {
+                    list_unsubscribe = raw_headers.get(header.offset_start..header.offset_end);
+                } else if name.eq_ignore_ascii_case("List-Unsubscribe-Post") {
+                    one_click = raw_headers
+                        .get(header.offset_start..header.offset_end)
+                        .and_then(|value| std::str::from_utf8(value).ok())
+                        .map_or(false, |value| {
+                            value
+                                .trim()
+                                .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+                        });
+                }
+            }
+            let action = if let Some(action) =
+                list_unsubscribe.and_then(|value| UnsubscribeAction::parse(value, one_click))
+            {
+                action
+            } else {
+                response.not_unsubscribed.append(
+                    id,
+                    SetError::new(SetErrorType::InvalidEmail).with_description(
+                        "Message does not contain a usable List-Unsubscribe header.",
+                    ),
+                );
+                continue;
+            };
+
+            // Execute the action
+            let result = match &action {
+                UnsubscribeAction::OneClick(url) => self.unsubscribe_one_click(url).await,
+                UnsubscribeAction::Mailto {
+                    address,
+                    subject,
+                    body,
+                } => {
+                    if mail_from.is_none() {
+                        mail_from = Some(
+                            self.directory
+                                .query(QueryBy::Id(account_id), false)
+                                .await
+                                .map_err(|_| MethodError::ServerPartialFail)?
+                                .and_then(|p| p.emails.into_iter().next()),
+                        );
+                    }
+                    if let Some(Some(mail_from)) = &mail_from {
+                        self.unsubscribe_mailto(mail_from, address, subject, body)
+                            .await
+                    } else {
+                        Err(SetError::new(SetErrorType::ForbiddenMailFrom)
+                            .with_description("Account does not have an e-mail address."))
+                    }
+                }
+            };
+
+            match result {
+                Ok(_) => {
+                    tracing::info!(
+                        context = "email_unsubscribe",
+                        event = "unsubscribe",
+                        account_id = account_id,
+                        document_id = id.document_id(),
+                        method = action.as_str(),
+                        target = action.target(),
+                        "Executed List-Unsubscribe action."
+                    );
+                    response.unsubscribed.push(id);
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        context = "email_unsubscribe",
+                        event = "error",
+                        account_id = account_id,
+                        document_id = id.document_id(),
+                        method = action.as_str(),
+                        target = action.target(),
+                        reason = ?err.description,
+                        "Failed to execute List-Unsubscribe action."
+                    );
+                    response.not_unsubscribed.append(id, err);
+                }
+            }
+        }
+
+        // Flag the unsubscribed messages
+        if !response.unsubscribed.is_empty() {
+            *next_call = Call {
+                id: String::new(),
+                name: MethodName::new(MethodObject::Email, MethodFunction::Set),
+                method: RequestMethod::Set(SetRequest {
+                    account_id: request.account_id,
+                    if_in_state: None,
+                    create: None,
+                    update: response
+                        .unsubscribed
+                        .iter()
+                        .map(|id| {
+                            let mut properties = VecMap::with_capacity(1);
+                            properties.append(
+                                Property::Keywords,
+                                SetValue::Patch(vec![
+                                    Value::Keyword(Keyword::from(UNSUBSCRIBED_KEYWORD.to_string())),
+                                    Value::Bool(true),
+                                ]),
+                            );
+                            (*id, Object { properties })
+                        })
+                        .collect::<VecMap<_, _>>()
+                        .into(),
+                    destroy: None,
+                    arguments: set::RequestArguments::Email,
+                }),
+            }
+            .into();
+        }
+
+        Ok(response)
+    }
+
+    async fn unsubscribe_one_click(&self, url: &Url) -> Result<(), SetError> {
+        // Resolve the host and refuse to connect to internal addresses
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(443);
+        let remote_ip = if let Ok(ip) = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            ip
+        } else {
+            match self.smtp.resolvers.ipv4_lookup(host).await {
+                Ok(ips) if !ips.is_empty() => IpAddr::from(ips[0]),
+                _ => match self.smtp.resolvers.ipv6_lookup(host).await {
+                    Ok(ips) if !ips.is_empty() => IpAddr::from(ips[0]),
+                    _ => {
+                        return Err(SetError::new(SetErrorType::InvalidEmail)
+                            .with_description(format!("Failed to resolve host {host:?}.")))
+                    }
+                },
+            }
+        };
+        if !is_public_ip(&remote_ip) {
+            return Err(SetError::forbidden()
+                .with_description("Unsubscribe URL points to a non-public address."));
+        }
+
+        let client_builder = reqwest::Client::builder()
+            .timeout(self.config.mail_unsubscribe_timeout)
+            .redirect(redirect::Policy::none())
+            .resolve(host, SocketAddr::new(remote_ip, port));
+
+        #[cfg(feature = "test_mode")]
+        let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+        let client = client_builder.build().map_err(|err| {
+            SetError::forbidden().with_description(format!("Failed to create HTTP client: {err}"))
+        })?;
+
+        match client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(SetError::forbidden().with_description(format!(
+                "Unsubscribe request failed with status {}.",
+                response.status()
+            ))),
+            Err(err) => Err(SetError::forbidden()
+                .with_description(format!("Unsubscribe request failed: {err}"))),
+        }
+    }
+
+    async fn unsubscribe_mailto(
+        &self,
+        mail_from: &str,
+        address: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), SetError> {
+        let raw_message = MessageBuilder::new()
+            .from(mail_from)
+            .to(address)
+            .subject(subject)
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        let result = Session::<NullIo>::sieve(
+            self.smtp.clone(),
+            SessionAddress::new(mail_from.to_string()),
+            vec![SessionAddress::new(address.to_string())],
+            raw_message,
+        )
+        .queue_message()
+        .await;
+
+        if result.starts_with(b"2") {
+            Ok(())
+        } else {
+            Err(
+                SetError::new(SetErrorType::ForbiddenToSend).with_description(format!(
+                    "Failed to send unsubscribe message: {}",
+                    String::from_utf8_lossy(&result).trim()
+                )),
+            )
+        }
+    }
+}
+
+impl UnsubscribeAction {
+    // Picks the action to execute from a raw List-Unsubscribe header value,
+    // HTTPS one-click (RFC 8058) is preferred over mailto
+    fn parse(value: &[u8], one_click: bool) -> Option<Self> {
+        let value = std::str::from_utf8(value).ok()?;
+        let mut mailto = None;
+
+        for uri in value.split('<').skip(1) {
+            let uri = uri
+                .split_once('>')?
+                .0
+                .chars()
+                .filter(|ch| !ch.is_whitespace())
+                .collect::<String>();
+            let url = if let Ok(url) = Url::parse(&uri) {
+                url
+            } else {
+                continue;
+            };
+
+            match url.scheme() {
+                "https" if one_click && url.host_str().is_some() && url.username().is_empty() => {
+                    return Some(UnsubscribeAction::OneClick(url));
+                }
+                "mailto" if mailto.is_none() => {
+                    if let Some(address) = sanitize_email(url.path()) {
+                        let mut subject = None;
+                        let mut body = None;
+                        for (name, value) in url.query_pairs() {
+                            if name.eq_ignore_ascii_case("subject") {
+                                subject = value.into_owned().into();
+                            } else if name.eq_ignore_ascii_case("body") {
+                                body = value.into_owned().into();
+                            }
+                        }
+                        mailto = UnsubscribeAction::Mailto {
+                            address,
+                            subject: subject.unwrap_or_else(|| "unsubscribe".to_string()),
+                            body: body.unwrap_or_else(|| "unsubscribe".to_string()),
+                        }
+                        .into();
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        mailto
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            UnsubscribeAction::OneClick(_) => "one-click",
+            UnsubscribeAction::Mailto { .. } => "mailto",
+        }
+    }
+
+    fn target(&self) -> &str {
+        match self {
+            UnsubscribeAction::OneClick(url) => url.as_str(),
+            UnsubscribeAction::Mailto { address, .. } => address.as_str(),
+        }
+    }
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.octets()[0] == 0
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ip));
+            }
+            let segment = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (segment & 0xfe00) == 0xfc00
+                || (segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
@@ -0,0 +1,497 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use hyper::{Method, StatusCode};
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    object::Object,
+    types::{collection::Collection, property::Property, state::StateChange, type_state::DataType},
+};
+use serde_json::json;
+use store::{
+    write::{
+        key::KeySerializer, log::ChangeLogBuilder, BatchBuilder, ValueClass, F_CLEAR, F_VALUE,
+    },
+    LookupValue, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+use utils::ipc::SendAsAddress;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    auth::AccessToken,
+    Bincode, JMAP,
+};
+
+use super::set::sanitize_email;
+
+// Accounts allowed to send as the key's account
+const SEND_AS_GRANTS_PREFIX: &[u8] = b"identity.send-as.";
+// Accounts the key's account is allowed to send as
+const SEND_AS_DELEGATIONS_PREFIX: &[u8] = b"identity.delegated.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendAsGrant {
+    // The delegate in the grantor's list and the grantor in the delegate's list
+    pub account_id: u32,
+    pub mode: SendAsMode,
+    // Identity created in the delegate's account for the grantor's address
+    pub identity_id: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SendAsMode {
+    SendAs,
+    OnBehalf,
+}
+
+impl SendAsMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "send-as" => Some(SendAsMode::SendAs),
+            "on-behalf" => Some(SendAsMode::OnBehalf),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SendAsMode::SendAs => "send-as",
+            SendAsMode::OnBehalf => "on-behalf",
+        }
+    }
+}
+
+impl JMAP {
+    // Lets users list who can send as them and whom they can send as, grant
+    // access with "grant=<account>&mode=send-as|on-behalf" or remove it with
+    // "revoke=<account>".
+    pub async fn handle_send_as_request(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        let account_id = access_token.primary_id();
+
+        if *req.method() == Method::POST {
+            let mut grant = None;
+            let mut revoke = None;
+            let mut mode = SendAsMode::SendAs;
+            if let Some(query) = req.uri().query() {
+                for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                    match key.as_ref() {
+                        "grant" => {
+                            grant = value.into_owned().into();
+                        }
+                        "revoke" => {
+                            revoke = value.into_owned().into();
+                        }
+                        "mode" => {
+                            if let Some(value) = SendAsMode::parse(value.as_ref()) {
+                                mode = value;
+                            } else {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    format!("Invalid mode {value:?}."),
+                                )
+                                .into_http_response();
+                            }
+                        }
+                        _ => {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                format!("Invalid parameter {key:?}."),
+                            )
+                            .into_http_response();
+                        }
+                    }
+                }
+            }
+
+            let (name, is_grant) = match (grant, revoke) {
+                (Some(name), None) => (name, true),
+                (None, Some(name)) => (name, false),
+                _ => {
+                    return RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid parameters",
+                        "Either grant or revoke must be specified.",
+                    )
+                    .into_http_response();
+                }
+            };
+            let delegate_id = match self.directory.query(QueryBy::Name(&name), false).await {
+                Ok(Some(principal)) if principal.id != account_id => principal.id,
+                Ok(_) => {
+                    return RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Account not found",
+                        format!("Account {name:?} does not exist."),
+                    )
+                    .into_http_response();
+                }
+                Err(err) => {
+                    return RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Directory lookup failed",
+                        format!("{err:?}"),
+                    )
+                    .into_http_response();
+                }
+            };
+
+            let result = if is_grant {
+                self.send_as_grant(account_id, delegate_id, mode)
+                    .await
+                    .map(|_| {
+                        tracing::info!(
+                            context = "audit",
+                            event = "send-as-grant",
+                            account = access_token.name,
+                            delegate = name,
+                            mode = mode.as_str(),
+                        );
+                    })
+            } else {
+                self.send_as_revoke(account_id, delegate_id).await.map(|_| {
+                    tracing::info!(
+                        context = "audit",
+                        event = "send-as-revoke",
+                        account = access_token.name,
+                        delegate = name,
+                    );
+                })
+            };
+            if let Err(err) = result {
+                return RequestError::blank(
+                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    "Failed to update delegations",
+                    err.to_string(),
+                )
+                .into_http_response();
+            }
+        } else if *req.method() != Method::GET {
+            return RequestError::not_found().into_http_response();
+        }
+
+        match (
+            self.send_as_grants(account_id).await,
+            self.send_as_delegations(account_id).await,
+        ) {
+            (Ok(grants), Ok(delegations)) => JsonResponse::new(json!({
+                "data": {
+                    "grants": self.send_as_describe(grants).await,
+                    "delegations": self.send_as_describe(delegations).await,
+                },
+            }))
+            .into_http_response(),
+            (Err(err), _) | (_, Err(err)) => RequestError::blank(
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                "Failed to obtain delegations",
+                err.to_string(),
+            )
+            .into_http_response(),
+        }
+    }
+
+    // Allows the delegate to send as the grantor, creating an identity in the
+    // delegate's account for the grantor's primary address.
+    pub async fn send_as_grant(
+        &self,
+        grantor_id: u32,
+        delegate_id: u32,
+        mode: SendAsMode,
+    ) -> Result<SendAsGrant, MethodError> {
+        let mut grants = self.send_as_grants(grantor_id).await?;
+        let mut delegations = self.send_as_delegations(delegate_id).await?;
+        let mut batch = BatchBuilder::new();
+
+        if let Some(grant) = grants
+            .iter_mut()
+            .find(|grant| grant.account_id == delegate_id)
+        {
+            // Only the sending mode changes for existing grants
+            grant.mode = mode;
+            let grant = *grant;
+            for delegation in delegations
+                .iter_mut()
+                .filter(|delegation| delegation.account_id == grantor_id)
+            {
+                delegation.mode = mode;
+            }
+            set_send_as(&mut batch, SEND_AS_GRANTS_PREFIX, grantor_id, &grants);
+            set_send_as(
+                &mut batch,
+                SEND_AS_DELEGATIONS_PREFIX,
+                delegate_id,
+                &delegations,
+            );
+            self.write_batch(batch).await?;
+            return Ok(grant);
+        }
+
+        // Obtain the grantor's primary address
+        let principal = self
+            .directory
+            .query(QueryBy::Id(grantor_id), false)
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    event = "error",
+                    context = "send_as_grant",
+                    error = ?err,
+                    "Failed to query directory.");
+                MethodError::ServerPartialFail
+            })?
+            .unwrap_or_default();
+        let email = principal
+            .emails
+            .first()
+            .and_then(|email| sanitize_email(email))
+            .ok_or_else(|| {
+                MethodError::InvalidArguments(
+                    "No e-mail address configured for this account.".to_string(),
+                )
+            })?;
+        let name = principal
+            .description
+            .unwrap_or(principal.name)
+            .trim()
+            .to_string();
+
+        // Make sure the delegate's own identities exist before adding the
+        // grantor's, otherwise they would never be created.
+        self.identity_get_or_create(delegate_id).await?;
+        let identity_id = self
+            .assign_document_id(delegate_id, Collection::Identity)
+            .await?;
+        let grant = SendAsGrant {
+            account_id: delegate_id,
+            mode,
+            identity_id,
+        };
+        grants.push(grant);
+        delegations.push(SendAsGrant {
+            account_id: grantor_id,
+            mode,
+            identity_id,
+        });
+
+        batch
+            .with_account_id(delegate_id)
+            .with_collection(Collection::Identity)
+            .create_document(identity_id)
+            .value(
+                Property::Value,
+                Object::with_capacity(2)
+                    .with_property(
+                        Property::Name,
+                        if name.is_empty() { email.clone() } else { name },
+                    )
+                    .with_property(Property::Email, email),
+                F_VALUE,
+            );
+        set_send_as(&mut batch, SEND_AS_GRANTS_PREFIX, grantor_id, &grants);
+        set_send_as(
+            &mut batch,
+            SEND_AS_DELEGATIONS_PREFIX,
+            delegate_id,
+            &delegations,
+        );
+        self.write_batch(batch).await?;
+        self.send_as_commit(delegate_id, identity_id, true).await?;
+
+        Ok(grant)
+    }
+
+    // Removes a grant and the identity it created in the delegate's account.
+    pub async fn send_as_revoke(
+        &self,
+        grantor_id: u32,
+        delegate_id: u32,
+    ) -> Result<bool, MethodError> {
+        let mut grants = self.send_as_grants(grantor_id).await?;
+        let grant = if let Some(pos) = grants
+            .iter()
+            .position(|grant| grant.account_id == delegate_id)
+        {
+            grants.swap_remove(pos)
+        } else {
+            return Ok(false);
+        };
+        let mut delegations = self.send_as_delegations(delegate_id).await?;
+        delegations.retain(|delegation| delegation.account_id != grantor_id);
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(delegate_id)
+            .with_collection(Collection::Identity)
+            .delete_document(grant.identity_id)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        set_send_as(&mut batch, SEND_AS_GRANTS_PREFIX, grantor_id, &grants);
+        set_send_as(
+            &mut batch,
+            SEND_AS_DELEGATIONS_PREFIX,
+            delegate_id,
+            &delegations,
+        );
+        self.write_batch(batch).await?;
+        self.send_as_commit(delegate_id, grant.identity_id, false)
+            .await?;
+
+        Ok(true)
+    }
+
+    // Accounts allowed to send as this account
+    pub async fn send_as_grants(&self, account_id: u32) -> Result<Vec<SendAsGrant>, MethodError> {
+        self.get_send_as(SEND_AS_GRANTS_PREFIX, account_id).await
+    }
+
+    // Accounts this account is allowed to send as
+    pub async fn send_as_delegations(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<SendAsGrant>, MethodError> {
+        self.get_send_as(SEND_AS_DELEGATIONS_PREFIX, account_id)
+            .await
+    }
+
+    // Addresses an SMTP session authenticated as this account may send from
+    // on behalf of other accounts.
+    pub async fn send_as_addresses(&self, account_id: u32) -> Vec<SendAsAddress> {
+        let mut addresses = Vec::new();
+        for delegation in self
+            .send_as_delegations(account_id)
+            .await
+            .unwrap_or_default()
+        {
+            if let Ok(Some(principal)) = self
+                .directory
+                .query(QueryBy::Id(delegation.account_id), false)
+                .await
+            {
+                for email in principal.emails {
+                    addresses.push(SendAsAddress {
+                        address: email.to_lowercase(),
+                        on_behalf: delegation.mode == SendAsMode::OnBehalf,
+                    });
+                }
+            }
+        }
+        addresses
+    }
+
+    async fn send_as_describe(&self, grants: Vec<SendAsGrant>) -> Vec<serde_json::Value> {
+        let mut result = Vec::with_capacity(grants.len());
+        for grant in grants {
+            let name = self
+                .directory
+                .query(QueryBy::Id(grant.account_id), false)
+                .await
+                .ok()
+                .flatten()
+                .map(|principal| principal.name)
+                .unwrap_or_default();
+            result.push(json!({
+                "account": name,
+                "mode": grant.mode,
+            }));
+        }
+        result
+    }
+
+    async fn send_as_commit(
+        &self,
+        account_id: u32,
+        identity_id: u32,
+        is_insert: bool,
+    ) -> Result<(), MethodError> {
+        let mut changes = ChangeLogBuilder::new();
+        if is_insert {
+            changes.log_insert(Collection::Identity, identity_id);
+        } else {
+            changes.log_delete(Collection::Identity, identity_id);
+        }
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::Identity, change_id),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn get_send_as(
+        &self,
+        prefix: &[u8],
+        account_id: u32,
+    ) -> Result<Vec<SendAsGrant>, MethodError> {
+        match self
+            .store
+            .get_value::<LookupValue<Bincode<Vec<SendAsGrant>>>>(ValueKey::from(ValueClass::Key(
+                send_as_key(prefix, account_id),
+            )))
+            .await
+        {
+            Ok(Some(LookupValue::Value { value, .. })) => Ok(value.inner),
+            Ok(_) => Ok(Vec::new()),
+            Err(err) => {
+                tracing::error!(
+                    event = "error",
+                    context = "send_as",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to obtain delegations.");
+                Err(MethodError::ServerPartialFail)
+            }
+        }
+    }
+}
+
+fn set_send_as(batch: &mut BatchBuilder, prefix: &[u8], account_id: u32, grants: &[SendAsGrant]) {
+    let key = ValueClass::Key(send_as_key(prefix, account_id));
+    if !grants.is_empty() {
+        // Values are prefixed with an expiration timestamp as the key space
+        // is shared with the lookup store.
+        let value = Bincode::new(grants.to_vec()).serialize();
+        batch.set(
+            key,
+            KeySerializer::new(value.len() + U64_LEN)
+                .write(u64::MAX)
+                .write(value.as_slice())
+                .finalize(),
+        );
+    } else {
+        batch.clear(key);
+    }
+}
+
+fn send_as_key(prefix: &[u8], account_id: u32) -> Vec<u8> {
+    KeySerializer::new(prefix.len() + U32_LEN)
+        .write(prefix)
+        .write(account_id)
+        .finalize()
+}
@@ -74,6 +74,17 @@ impl JMAP {
             not_found: vec![],
         };
 
+        // Identities created by send-as grants can only be removed by the grantor
+        let delegated_ids = if properties.contains(&Property::MayDelete) {
+            self.send_as_delegations(account_id)
+                .await?
+                .into_iter()
+                .map(|delegation| delegation.identity_id)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+
         for id in ids {
             // Obtain the identity object
             let document_id = id.document_id();
@@ -102,7 +113,10 @@ impl JMAP {
                         result.append(Property::Id, Value::Id(id));
                     }
                     Property::MayDelete => {
-                        result.append(Property::MayDelete, Value::Bool(true));
+                        result.append(
+                            Property::MayDelete,
+                            Value::Bool(!delegated_ids.contains(&document_id)),
+                        );
                     }
                     property => {
                         result.append(property.clone(), push.remove(property));
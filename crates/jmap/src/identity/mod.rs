@@ -21,5 +21,6 @@
  * for more details.
 */
 
+pub mod delegate;
 pub mod get;
 pub mod set;
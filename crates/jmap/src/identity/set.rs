@@ -174,9 +174,25 @@ impl JMAP {
         }
 
         // Process deletions
+        let delegated_ids = if !will_destroy.is_empty() {
+            self.send_as_delegations(account_id)
+                .await?
+                .into_iter()
+                .map(|delegation| delegation.identity_id)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
         for id in will_destroy {
             let document_id = id.document_id();
-            if identity_ids.contains(document_id) {
+            if delegated_ids.contains(&document_id) {
+                response.not_destroyed.append(
+                    id,
+                    SetError::forbidden().with_description(
+                        "Delegated identities can only be revoked by their owner.",
+                    ),
+                );
+            } else if identity_ids.contains(document_id) {
                 // Update record
                 let mut batch = BatchBuilder::new();
                 batch
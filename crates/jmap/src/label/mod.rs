@@ -0,0 +1,162 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::{
+        index::{IndexAs, IndexProperty},
+        Object,
+    },
+    types::{collection::Collection, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+pub mod get;
+pub mod set;
+
+pub static SCHEMA: &[IndexProperty] = &[
+    IndexProperty::new(Property::Name).max_size(255).required(),
+    IndexProperty::new(Property::Keyword)
+        .index_as(IndexAs::Text {
+            tokenize: false,
+            index: true,
+        })
+        .max_size(255)
+        .required(),
+    IndexProperty::new(Property::ParentId).index_as(IndexAs::Integer),
+];
+
+pub const MAX_LABEL_DEPTH: usize = 10;
+
+// Builds the IMAP keyword a label is stored as on messages. Labels are
+// mapped to plain keywords so that IMAP clients see the same tags, any
+// characters that are not valid in an IMAP atom are replaced with '_'.
+pub fn label_keyword(path: &[String]) -> String {
+    let mut keyword = String::with_capacity(path.iter().map(|n| n.len() + 1).sum());
+    for (pos, name) in path.iter().enumerate() {
+        if pos > 0 {
+            keyword.push('/');
+        }
+        for ch in name.chars() {
+            keyword.push(if is_atom_char(ch) { ch } else { '_' });
+        }
+    }
+    // Keywords starting with '$' are reserved for system flags
+    if keyword.starts_with('$') {
+        keyword.replace_range(0..1, "_");
+    }
+    keyword
+}
+
+pub fn is_valid_keyword(keyword: &str) -> bool {
+    !keyword.is_empty() && keyword.len() <= 255 && keyword.chars().all(is_atom_char)
+}
+
+fn is_atom_char(ch: char) -> bool {
+    ch.is_ascii_graphic() && !matches!(ch, '(' | ')' | '{' | '%' | '*' | '"' | '\\' | ']')
+}
+
+pub fn is_valid_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color.bytes().skip(1).all(|ch| ch.is_ascii_hexdigit())
+}
+
+impl JMAP {
+    // Returns the names of a label and its ancestors, starting from the top-level label
+    pub async fn label_path(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<Option<Vec<String>>, MethodError> {
+        let mut path = Vec::new();
+        let mut next_id = Some(document_id);
+        while let Some(document_id) = next_id {
+            if path.len() >= MAX_LABEL_DEPTH {
+                return Ok(None);
+            }
+            let label = if let Some(label) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Label,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                label
+            } else {
+                return Ok(None);
+            };
+            path.push(
+                label
+                    .properties
+                    .get(&Property::Name)
+                    .and_then(|v| v.as_string())
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+            next_id = label_parent_id(&label);
+        }
+        path.reverse();
+
+        Ok(Some(path))
+    }
+
+    // Returns true if the label is an ancestor of (or the same as) another label
+    pub async fn label_is_ancestor(
+        &self,
+        account_id: u32,
+        ancestor_id: u32,
+        document_id: u32,
+    ) -> Result<bool, MethodError> {
+        let mut next_id = Some(document_id);
+        let mut depth = 0;
+        while let Some(document_id) = next_id {
+            if document_id == ancestor_id || depth > MAX_LABEL_DEPTH {
+                return Ok(true);
+            }
+            next_id = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Label,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .and_then(|label| label_parent_id(&label));
+            depth += 1;
+        }
+
+        Ok(false)
+    }
+}
+
+pub(crate) fn label_parent_id(label: &Object<Value>) -> Option<u32> {
+    label
+        .properties
+        .get(&Property::ParentId)
+        .and_then(|v| v.as_id())
+        .map(|id| id.document_id())
+}
@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::{index::ObjectIndexBuilder, Object},
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+};
+
+use crate::JMAP;
+
+use super::{is_valid_color, is_valid_keyword, label_keyword, label_parent_id, SCHEMA};
+
+impl JMAP {
+    pub async fn label_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut label_ids = self
+            .get_document_ids(account_id, Collection::Label)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::Label)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            let mut label = Object::with_capacity(object.properties.len() + 1);
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_label_value(&property, value, true))
+                {
+                    Ok(Value::Null) => (),
+                    Ok(value) => {
+                        label.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            // Validate parent and build the label path
+            let name = label
+                .properties
+                .get(&Property::Name)
+                .and_then(|v| v.as_string())
+                .unwrap_or_default()
+                .to_string();
+            let mut path = Vec::new();
+            if let Some(parent_id) = label_parent_id(&label) {
+                match (
+                    label_ids.contains(parent_id),
+                    self.label_path(account_id, parent_id).await?,
+                ) {
+                    (true, Some(parent_path)) => {
+                        path = parent_path;
+                    }
+                    _ => {
+                        response.not_created.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(Property::ParentId)
+                                .with_description("Parent label not found or too deeply nested."),
+                        );
+                        continue 'create;
+                    }
+                }
+            }
+            path.push(name);
+
+            // Keywords are assigned on creation and never change, so renaming
+            // a label does not require retagging its messages.
+            let keyword = if let Some(keyword) = label
+                .properties
+                .get(&Property::Keyword)
+                .and_then(|v| v.as_string())
+            {
+                keyword.to_string()
+            } else {
+                label_keyword(&path)
+            };
+            if let Some(existing_id) = self
+                .filter(
+                    account_id,
+                    Collection::Label,
+                    vec![Filter::eq(Property::Keyword, &keyword)],
+                )
+                .await?
+                .results
+                .min()
+            {
+                response.not_created.append(
+                    id,
+                    SetError::already_exists()
+                        .with_existing_id(existing_id.into())
+                        .with_description(format!(
+                            "A label mapped to keyword {keyword:?} already exists."
+                        )),
+                );
+                continue 'create;
+            }
+            label.set(Property::Keyword, Value::Text(keyword.clone()));
+
+            // Validate fields
+            let builder = match ObjectIndexBuilder::new(SCHEMA)
+                .with_changes(label)
+                .validate()
+            {
+                Ok(builder) => builder,
+                Err(err) => {
+                    response.not_created.append(id, err);
+                    continue 'create;
+                }
+            };
+
+            // Insert record
+            let document_id = self
+                .assign_document_id(account_id, Collection::Label)
+                .await?;
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .create_document(document_id)
+                .custom(builder);
+            self.write_batch(batch).await?;
+            label_ids.insert(document_id);
+            changes.log_insert(Collection::Label, document_id);
+            response.created.insert(
+                id,
+                Object::with_capacity(2)
+                    .with_property(Property::Id, Value::Id(document_id.into()))
+                    .with_property(Property::Keyword, keyword),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain label
+            let document_id = id.document_id();
+            let label = if let Some(label) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::Label,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                label
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            let mut update = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_label_value(&property, value, false))
+                {
+                    Ok(value) => {
+                        update.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            // Make sure the new parent exists and is not a descendant of this label
+            if let Some(parent_id) = label_parent_id(&update) {
+                if !label_ids.contains(parent_id)
+                    || self
+                        .label_is_ancestor(account_id, document_id, parent_id)
+                        .await?
+                    || self.label_path(account_id, parent_id).await?.is_none()
+                {
+                    response.not_updated.append(
+                        id,
+                        SetError::invalid_properties()
+                            .with_property(Property::ParentId)
+                            .with_description("Invalid parent label."),
+                    );
+                    continue 'update;
+                }
+            }
+
+            // Validate fields
+            let builder = match ObjectIndexBuilder::new(SCHEMA)
+                .with_current(label)
+                .with_changes(update)
+                .validate()
+            {
+                Ok(builder) => builder,
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                    continue 'update;
+                }
+            };
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .update_document(document_id)
+                .custom(builder);
+            match self.store.write(batch.build()).await {
+                Ok(_) => (),
+                Err(store::Error::AssertValueFailed) => {
+                    response.not_updated.append(
+                        id,
+                        SetError::forbidden().with_description(
+                            "Another process modified this label, please try again.",
+                        ),
+                    );
+                    continue 'update;
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "label_set",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to update label.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+            changes.log_update(Collection::Label, document_id);
+            response.updated.append(id, None);
+        }
+
+        // Process deletions, messages keep the keyword so that destroying a
+        // label does not remove information visible to IMAP clients.
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if !label_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+
+            // Verify that this label does not have children
+            if !self
+                .filter(
+                    account_id,
+                    Collection::Label,
+                    vec![Filter::eq(Property::ParentId, document_id)],
+                )
+                .await?
+                .results
+                .is_empty()
+            {
+                response.not_destroyed.append(
+                    id,
+                    SetError::forbidden().with_description("Label has at least one child label."),
+                );
+                continue;
+            }
+
+            let label = if let Some(label) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::Label,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                label
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            };
+
+            // Delete record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .delete_document(document_id)
+                .custom(ObjectIndexBuilder::new(SCHEMA).with_current(label));
+            self.write_batch(batch).await?;
+            label_ids.remove(document_id);
+            changes.log_delete(Collection::Label, document_id);
+            response.destroyed.push(id);
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            response.new_state = Some(self.commit_changes(account_id, changes).await?.into());
+        }
+
+        Ok(response)
+    }
+}
+
+fn validate_label_value(
+    property: &Property,
+    value: MaybePatchValue,
+    is_create: bool,
+) -> Result<Value, SetError> {
+    Ok(match (property, value) {
+        (Property::Name, MaybePatchValue::Value(Value::Text(value)))
+            if !value.trim().is_empty() && value.len() <= 255 && !value.contains('/') =>
+        {
+            Value::Text(value)
+        }
+        (Property::Color, MaybePatchValue::Value(Value::Text(value))) if is_valid_color(&value) => {
+            Value::Text(value.to_ascii_lowercase())
+        }
+        (Property::Keyword, MaybePatchValue::Value(Value::Text(value)))
+            if is_create && is_valid_keyword(&value) =>
+        {
+            Value::Text(value)
+        }
+        (Property::ParentId, MaybePatchValue::Value(Value::Id(value))) => Value::Id(value),
+        (Property::Color | Property::ParentId, MaybePatchValue::Value(Value::Null)) => Value::Null,
+
+        (property, _) => {
+            return Err(SetError::invalid_properties()
+                .with_property(property.clone())
+                .with_description("Field could not be set."));
+        }
+    })
+}
@@ -21,16 +21,22 @@
  * for more details.
 */
 
-use std::{collections::hash_map::RandomState, fmt::Display, sync::Arc, time::Duration};
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 
 use ::sieve::{Compiler, Runtime};
 use api::session::BaseCapabilities;
 use auth::{
     oauth::OAuthCode,
     rate_limit::{AnonymousLimiter, AuthenticatedLimiter, RemoteAddress},
+    sessions::LiveSession,
     AccessToken,
 };
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use directory::{Directories, Directory, QueryBy};
 use jmap_proto::{
     error::method::MethodError,
@@ -43,35 +49,49 @@ use jmap_proto::{
 use mail_parser::HeaderName;
 use nlp::language::Language;
 use services::{
-    delivery::spawn_delivery_manager,
+    alert::spawn_alert_manager,
+    delivery::{spawn_delivery_manager, spawn_event_manager},
     housekeeper::{self, init_housekeeper, spawn_housekeeper},
     state::{self, init_state_manager, spawn_state_manager},
 };
 use smtp::core::SMTP;
 use store::{
+    ahash::AHashMap,
     fts::FtsFilter,
     parking_lot::Mutex,
     query::{sort::Pagination, Comparator, Filter, ResultSet, SortedResultSet},
     roaring::RoaringBitmap,
     write::{BatchBuilder, BitmapClass, DirectoryClass, TagValue, ToBitmaps, ValueClass},
-    BitmapKey, BlobStore, Deserialize, FtsStore, Serialize, Store, Stores, ValueKey,
+    BitmapKey, BlobStore, Deserialize, FtsStore, LookupStore, Serialize, Store, Stores, ValueKey,
 };
 use tokio::sync::mpsc;
 use utils::{
     config::Rate,
     ipc::DeliveryEvent,
     map::ttl_dashmap::{TtlDashMap, TtlMap},
+    message::MessageLimits,
     snowflake::SnowflakeIdGenerator,
     UnwrapFailure,
 };
 
+use crate::{
+    activity::ActivityConfig,
+    auth::{devices::LoginAlertConfig, reset::PasswordResetConfig},
+    services::{alert::AlertConfig, notify::NotifyConnector, rules::DeliveryRules},
+    sieve::limits::RegexLimits,
+};
+
+pub mod activity;
 pub mod api;
 pub mod auth;
 pub mod blob;
 pub mod changes;
 pub mod email;
 pub mod identity;
+pub mod label;
+pub mod list_filing;
 pub mod mailbox;
+pub mod masked_email;
 pub mod principal;
 pub mod push;
 pub mod quota;
@@ -97,8 +117,15 @@ pub struct JMAP {
 
     pub rate_limit_auth: DashMap<u32, Arc<Mutex<AuthenticatedLimiter>>>,
     pub rate_limit_unauth: DashMap<RemoteAddress, Arc<Mutex<AnonymousLimiter>>>,
+    pub rate_limit_store: Option<LookupStore>,
+    pub revocation_store: Option<LookupStore>,
+    pub delivery_rules: Option<DeliveryRules>,
 
     pub oauth_codes: TtlDashMap<String, Arc<OAuthCode>>,
+    pub live_sessions: DashMap<u64, LiveSession>,
+    pub running_jobs: DashMap<u64, Arc<AtomicBool>>,
+    pub push_previews: DashSet<u32>,
+    pub mailbox_counters_ready: AtomicBool,
 
     pub state_tx: mpsc::Sender<state::Event>,
     pub housekeeper_tx: mpsc::Sender<housekeeper::Event>,
@@ -133,9 +160,20 @@ pub struct Config {
     pub mail_attachments_max_size: usize,
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
+    pub mail_ingest_batch_size: usize,
+    pub mail_limits: MessageLimits,
+    pub mail_unsubscribe_timeout: Duration,
+    pub mail_recovery_retention: Option<Duration>,
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
+    pub sieve_regex: RegexLimits,
+
+    pub notify_connectors: AHashMap<String, NotifyConnector>,
+
+    pub masked_email_domain: Option<String>,
+    pub masked_email_max: usize,
+    pub masked_email_expiry_notice: Duration,
 
     pub session_cache_ttl: Duration,
     pub rate_authenticated: Rate,
@@ -144,6 +182,8 @@ pub struct Config {
     pub rate_use_forwarded: bool,
 
     pub event_source_throttle: Duration,
+    pub event_source_replay_window: Duration,
+    pub event_source_replay_max: usize,
     pub push_max_total: usize,
 
     pub web_socket_throttle: Duration,
@@ -156,12 +196,29 @@ pub struct Config {
     pub oauth_expiry_token: u64,
     pub oauth_expiry_refresh_token: u64,
     pub oauth_expiry_refresh_token_renew: u64,
+    pub oauth_refresh_token_rotate: bool,
     pub oauth_max_auth_attempts: u32,
 
     pub spam_header: Option<(HeaderName<'static>, String)>,
 
     pub http_headers: Vec<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
 
+    pub health_timeout: Duration,
+    pub health_queue_lag: Duration,
+    pub health_queue_max_overdue: usize,
+
+    pub alerts: Option<AlertConfig>,
+    pub login_alert: Option<LoginAlertConfig>,
+    pub password_reset: Option<PasswordResetConfig>,
+    pub activity: Option<ActivityConfig>,
+    pub export_expiry: Duration,
+    pub jobs_max_concurrent: usize,
+    pub jobs_retention: Duration,
+    pub traffic_retention: Duration,
+    pub client_retention: Duration,
+    pub tiering_older_than: Option<Duration>,
+    pub tiering_archive: bool,
+
     pub encrypt: bool,
     pub encrypt_append: bool,
 
@@ -182,11 +239,39 @@ pub enum IngestError {
 }
 
 impl JMAP {
+    pub fn check_config(
+        config: &utils::config::Config,
+        stores: &Stores,
+        directories: &Directories,
+    ) -> Result<(), String> {
+        let id = config.value_require("jmap.directory")?;
+        if !directories.directories.contains_key(id) {
+            return Err(format!("Unable to find directory '{id}'"));
+        }
+        let id = config.value_require("jmap.store.data")?;
+        if !stores.stores.contains_key(id) {
+            return Err(format!("Unable to find data store '{id}'"));
+        }
+        let id = config.value_require("jmap.store.fts")?;
+        if !stores.fts_stores.contains_key(id) {
+            return Err(format!("Unable to find full text store '{id}'"));
+        }
+        let id = config.value_require("jmap.store.blob")?;
+        if !stores.blob_stores.contains_key(id) {
+            return Err(format!("Unable to find blob store '{id}'"));
+        }
+        stores.get_lookup_store(config, "jmap.rate-limit.store")?;
+        stores.get_lookup_store(config, "jmap.session.revocation.store")?;
+        DeliveryRules::parse(config, stores, directories)?;
+        Config::new(config).map(|_| ())
+    }
+
     pub async fn init(
         config: &utils::config::Config,
         stores: &Stores,
         directories: &Directories,
         delivery_rx: mpsc::Receiver<DeliveryEvent>,
+        event_rx: mpsc::Receiver<DeliveryEvent>,
         smtp: Arc<SMTP>,
     ) -> Result<Arc<Self>, String> {
         // Init state manager and housekeeper
@@ -257,10 +342,25 @@ impl JMAP {
                 RandomState::default(),
                 shard_amount,
             ),
+            rate_limit_store: stores.get_lookup_store(config, "jmap.rate-limit.store")?,
+            revocation_store: stores.get_lookup_store(config, "jmap.session.revocation.store")?,
+            delivery_rules: DeliveryRules::parse(config, stores, directories)?,
             oauth_codes: TtlDashMap::with_capacity(
                 config.property("oauth.cache.size")?.unwrap_or(128),
                 shard_amount,
             ),
+            live_sessions: DashMap::with_capacity_and_hasher_and_shard_amount(
+                128,
+                RandomState::default(),
+                shard_amount,
+            ),
+            running_jobs: DashMap::with_capacity_and_hasher_and_shard_amount(
+                16,
+                RandomState::default(),
+                shard_amount,
+            ),
+            push_previews: DashSet::default(),
+            mailbox_counters_ready: AtomicBool::new(false),
             state_tx,
             housekeeper_tx,
             smtp,
@@ -392,6 +492,13 @@ impl JMAP {
                             "Original-From".to_string(),
                             "Received".to_string(),
                             "Auto-Submitted".to_string(),
+                            "DKIM-Signature".to_string(),
+                            "ARC-Seal".to_string(),
+                            "ARC-Message-Signature".to_string(),
+                            "ARC-Authentication-Results".to_string(),
+                            "Authentication-Results".to_string(),
+                            "Received-SPF".to_string(),
+                            "Return-Path".to_string(),
                         ]
                     }
                 })
@@ -413,8 +520,9 @@ impl JMAP {
                 .with_env_variable("phase", "during"),
         });
 
-        // Spawn delivery manager
+        // Spawn delivery and event managers
         spawn_delivery_manager(jmap_server.clone(), delivery_rx);
+        spawn_event_manager(jmap_server.clone(), event_rx);
 
         // Spawn state manager
         spawn_state_manager(jmap_server.clone(), config, state_rx);
@@ -422,6 +530,9 @@ impl JMAP {
         // Spawn housekeeper
         spawn_housekeeper(jmap_server.clone(), config, housekeeper_rx);
 
+        // Spawn alert manager
+        spawn_alert_manager(jmap_server.clone());
+
         Ok(jmap_server)
     }
 
@@ -0,0 +1,133 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    request::reference::MaybeReference,
+    types::{any_id::AnyId, collection::Collection, id::Id, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+use super::DEFAULT_MAILBOX_PATTERN;
+
+impl JMAP {
+    pub async fn list_filing_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let properties = request.unwrap_properties(&[
+            Property::Id,
+            Property::IsEnabled,
+            Property::MailboxPattern,
+            Property::ExcludedListIds,
+        ]);
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::ListFiling)
+                .await?
+                .into(),
+            list: Vec::with_capacity(1),
+            not_found: vec![],
+        };
+
+        let do_get = if let Some(MaybeReference::Value(ids)) = request.ids {
+            let mut do_get = false;
+            for id in ids {
+                match id.try_unwrap() {
+                    Some(AnyId::Id(id)) if id.is_singleton() => {
+                        do_get = true;
+                    }
+                    Some(id) => {
+                        response.not_found.push(id);
+                    }
+                    _ => {}
+                }
+            }
+            do_get
+        } else {
+            true
+        };
+        if do_get {
+            // Accounts without settings get the defaults
+            let mut obj = if let Some(document_id) = self.list_filing_id(account_id).await? {
+                self.get_property::<Object<Value>>(
+                    account_id,
+                    Collection::ListFiling,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .unwrap_or_default()
+            } else {
+                Object::with_capacity(0)
+            };
+
+            let mut result = Object::with_capacity(properties.len());
+            for property in &properties {
+                match property {
+                    Property::Id => {
+                        result.append(Property::Id, Value::Id(Id::singleton()));
+                    }
+                    Property::IsEnabled => {
+                        result.append(
+                            Property::IsEnabled,
+                            match obj.remove(property) {
+                                Value::Null => Value::Bool(false),
+                                value => value,
+                            },
+                        );
+                    }
+                    Property::MailboxPattern => {
+                        result.append(
+                            Property::MailboxPattern,
+                            match obj.remove(property) {
+                                Value::Null => Value::Text(DEFAULT_MAILBOX_PATTERN.to_string()),
+                                value => value,
+                            },
+                        );
+                    }
+                    Property::ExcludedListIds => {
+                        result.append(
+                            Property::ExcludedListIds,
+                            match obj.remove(property) {
+                                Value::Null => Value::List(vec![]),
+                                value => value,
+                            },
+                        );
+                    }
+                    property => {
+                        result.append(property.clone(), Value::Null);
+                    }
+                }
+            }
+            response.list.push(result);
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,255 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::{index::IndexProperty, Object},
+    types::{collection::Collection, property::Property, value::Value},
+};
+use mail_parser::Message;
+
+use crate::JMAP;
+
+pub mod get;
+pub mod set;
+
+pub static SCHEMA: &[IndexProperty] = &[];
+
+pub const DEFAULT_MAILBOX_PATTERN: &str = "Lists/{name}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListFiling {
+    pub mailbox_pattern: String,
+    pub excluded_list_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailingList {
+    pub id: String,
+    pub name: String,
+}
+
+impl JMAP {
+    pub async fn list_filing_id(&self, account_id: u32) -> Result<Option<u32>, MethodError> {
+        self.get_document_ids(account_id, Collection::ListFiling)
+            .await
+            .map(|ids| ids.and_then(|ids| ids.min()))
+    }
+
+    // Returns the list filing settings of an account, if enabled
+    pub async fn list_filing_get_enabled(
+        &self,
+        account_id: u32,
+    ) -> Result<Option<ListFiling>, MethodError> {
+        let document_id = if let Some(document_id) = self.list_filing_id(account_id).await? {
+            document_id
+        } else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .get_property::<Object<Value>>(
+                account_id,
+                Collection::ListFiling,
+                document_id,
+                Property::Value,
+            )
+            .await?
+            .and_then(|obj| ListFiling::from_object(&obj)))
+    }
+
+    // Obtains the mailbox a mailing list message should be filed into,
+    // creating the mailbox hierarchy if it does not exist
+    pub async fn list_filing_mailbox(
+        &self,
+        account_id: u32,
+        raw_message: &[u8],
+        message: &Message<'_>,
+    ) -> Result<Option<(u32, Option<u64>)>, MethodError> {
+        let list = if let Some(list) = MailingList::parse(raw_message, message) {
+            list
+        } else {
+            return Ok(None);
+        };
+        let settings = match self.list_filing_get_enabled(account_id).await? {
+            Some(settings) if !settings.is_excluded(&list.id) => settings,
+            _ => return Ok(None),
+        };
+
+        // Make sure the default mailboxes exist before creating any others
+        self.mailbox_get_or_create(account_id).await?;
+        self.mailbox_create_path(account_id, &settings.mailbox_path(&list))
+            .await
+    }
+}
+
+impl ListFiling {
+    pub fn from_object(obj: &Object<Value>) -> Option<Self> {
+        if !matches!(
+            obj.properties.get(&Property::IsEnabled),
+            Some(Value::Bool(true))
+        ) {
+            return None;
+        }
+
+        Some(ListFiling {
+            mailbox_pattern: obj
+                .properties
+                .get(&Property::MailboxPattern)
+                .and_then(|v| v.as_string())
+                .unwrap_or(DEFAULT_MAILBOX_PATTERN)
+                .to_string(),
+            excluded_list_ids: if let Some(Value::List(ids)) =
+                obj.properties.get(&Property::ExcludedListIds)
+            {
+                ids.iter()
+                    .filter_map(|id| id.as_string())
+                    .map(|id| id.to_lowercase())
+                    .collect()
+            } else {
+                vec![]
+            },
+        })
+    }
+
+    // Exclusions match a list id exactly or, when prefixed with "*.", any list id
+    // under that domain
+    pub fn is_excluded(&self, list_id: &str) -> bool {
+        self.excluded_list_ids.iter().any(|excluded| {
+            if let Some(domain) = excluded.strip_prefix("*.") {
+                list_id
+                    .strip_suffix(domain)
+                    .map_or(false, |prefix| prefix.is_empty() || prefix.ends_with('.'))
+            } else {
+                excluded == list_id
+            }
+        })
+    }
+
+    pub fn mailbox_path(&self, list: &MailingList) -> String {
+        let domain = list.id.split_once('.').map_or("", |(_, domain)| domain);
+        let mut path = String::with_capacity(self.mailbox_pattern.len() + list.name.len());
+        let mut pattern = self.mailbox_pattern.as_str();
+
+        while let Some((before, after)) = pattern.split_once('{') {
+            path.push_str(before);
+            if let Some((variable, after)) = after.split_once('}') {
+                path.push_str(&sanitize_mailbox_name(match variable {
+                    "name" => list.name.as_str(),
+                    "id" => list.id.as_str(),
+                    "domain" => domain,
+                    _ => "",
+                }));
+                pattern = after;
+            } else {
+                path.push('{');
+                pattern = after;
+            }
+        }
+        path.push_str(pattern);
+
+        path
+    }
+}
+
+impl MailingList {
+    // Identifies the mailing list a message was sent through using its
+    // List-Id header (RFC 2919) or, failing that, its List-Post address
+    pub fn parse(raw_message: &[u8], message: &Message<'_>) -> Option<Self> {
+        let mut list_post = None;
+
+        for header in message.root_part().headers() {
+            let name = header.name.as_str();
+            let value = raw_message
+                .get(header.offset_start..header.offset_end)
+                .and_then(|value| std::str::from_utf8(value).ok())
+                .map(unfold)
+                .unwrap_or_default();
+
+            if name.eq_ignore_ascii_case("List-Id") {
+                let (description, id) = match value.rsplit_once('<') {
+                    Some((description, id)) => (
+                        description.trim().trim_matches('"').trim(),
+                        id.split_once('>').map_or(id, |(id, _)| id).trim(),
+                    ),
+                    None => ("", value.trim()),
+                };
+                if !id.is_empty() && id.contains('.') {
+                    let id = id.to_lowercase();
+                    return Some(MailingList {
+                        name: if !description.is_empty() {
+                            description.to_string()
+                        } else {
+                            id.split_once('.')
+                                .map_or(id.as_str(), |(name, _)| name)
+                                .to_string()
+                        },
+                        id,
+                    });
+                }
+            } else if name.eq_ignore_ascii_case("List-Post") && list_post.is_none() {
+                list_post = value
+                    .split_once('<')
+                    .and_then(|(_, uri)| uri.split_once('>'))
+                    .and_then(|(uri, _)| {
+                        let uri = uri.trim();
+                        uri.get(..7)
+                            .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+                            .map(|_| &uri[7..])
+                    })
+                    .map(|address| address.split_once('?').map_or(address, |(addr, _)| addr))
+                    .and_then(|address| address.split_once('@'))
+                    .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+                    .map(|(local, domain)| MailingList {
+                        id: format!("{local}.{domain}").to_lowercase(),
+                        name: local.to_string(),
+                    });
+            }
+        }
+
+        list_post
+    }
+}
+
+fn unfold(value: &str) -> String {
+    value
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn sanitize_mailbox_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch == '/' || ch.is_control() {
+                '-'
+            } else {
+                ch
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
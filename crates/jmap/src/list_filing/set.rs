@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::{index::ObjectIndexBuilder, Object},
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder};
+
+use crate::JMAP;
+
+use super::SCHEMA;
+
+const MAX_EXCLUDED_LIST_IDS: usize = 100;
+
+impl JMAP {
+    pub async fn list_filing_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut response = self
+            .prepare_set_response(&request, Collection::ListFiling)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+        let mut document_id = self.list_filing_id(account_id).await?;
+        let mut changes = ChangeLogBuilder::new();
+
+        // List filing settings always exist, they can only be updated
+        for (id, _) in request.unwrap_create() {
+            response.not_created.append(
+                id,
+                SetError::forbidden()
+                    .with_description("ListFiling is a singleton, use update instead."),
+            );
+        }
+
+        'update: for (id, object) in request.unwrap_update() {
+            if !id.is_singleton() {
+                response.not_updated.append(id, SetError::not_found());
+                continue;
+            } else if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue;
+            }
+
+            let mut update = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_list_filing_value(&property, value))
+                {
+                    Ok(value) => {
+                        update.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                }
+            }
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ListFiling);
+            if let Some(document_id) = document_id {
+                let current = self
+                    .get_property::<HashedValue<Object<Value>>>(
+                        account_id,
+                        Collection::ListFiling,
+                        document_id,
+                        Property::Value,
+                    )
+                    .await?
+                    .ok_or(MethodError::ServerPartialFail)?;
+                batch.update_document(document_id).custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(current)
+                        .with_changes(update),
+                );
+                changes.log_update(Collection::ListFiling, document_id);
+            } else {
+                let document_id_ = self
+                    .assign_document_id(account_id, Collection::ListFiling)
+                    .await?;
+                batch
+                    .create_document(document_id_)
+                    .custom(ObjectIndexBuilder::new(SCHEMA).with_changes(update));
+                changes.log_insert(Collection::ListFiling, document_id_);
+                document_id = document_id_.into();
+            }
+            self.write_batch(batch).await?;
+            response.updated.append(id, None);
+        }
+
+        // Destroying the settings restores the defaults
+        for id in will_destroy {
+            if !id.is_singleton() {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+            if let Some(document_id) = document_id.take() {
+                if let Some(current) = self
+                    .get_property::<HashedValue<Object<Value>>>(
+                        account_id,
+                        Collection::ListFiling,
+                        document_id,
+                        Property::Value,
+                    )
+                    .await?
+                {
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::ListFiling)
+                        .delete_document(document_id)
+                        .custom(ObjectIndexBuilder::new(SCHEMA).with_current(current));
+                    self.write_batch(batch).await?;
+                    changes.log_delete(Collection::ListFiling, document_id);
+                }
+            }
+            response.destroyed.push(id);
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            response.new_state = Some(self.commit_changes(account_id, changes).await?.into());
+        }
+
+        Ok(response)
+    }
+}
+
+fn validate_list_filing_value(
+    property: &Property,
+    value: MaybePatchValue,
+) -> Result<Value, SetError> {
+    Ok(match (property, value) {
+        (Property::IsEnabled, MaybePatchValue::Value(value @ Value::Bool(_))) => value,
+        (Property::MailboxPattern, MaybePatchValue::Value(Value::Text(value)))
+            if !value.trim().is_empty() && value.len() <= 255 =>
+        {
+            Value::Text(value)
+        }
+        (Property::ExcludedListIds, MaybePatchValue::Value(Value::List(values)))
+            if values.len() <= MAX_EXCLUDED_LIST_IDS
+                && values.iter().all(|value| {
+                    value
+                        .as_string()
+                        .map_or(false, |value| !value.is_empty() && value.len() <= 255)
+                }) =>
+        {
+            Value::List(
+                values
+                    .into_iter()
+                    .filter_map(|value| value.as_string().map(|value| value.to_lowercase()))
+                    .map(Value::Text)
+                    .collect(),
+            )
+        }
+        (
+            Property::IsEnabled | Property::MailboxPattern | Property::ExcludedListIds,
+            MaybePatchValue::Value(Value::Null),
+        ) => Value::Null,
+        (property, _) => {
+            return Err(SetError::invalid_properties()
+                .with_property(property.clone())
+                .with_description("Field could not be set."));
+        }
+    })
+}
@@ -0,0 +1,420 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::atomic::Ordering;
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::{
+    error::method::MethodError,
+    types::{collection::Collection, keyword::Keyword, property::Property},
+};
+use store::{
+    ahash::AHashMap,
+    write::{key::DeserializeBigEndian, BatchBuilder, IntoOperations, ValueClass},
+    Deserialize, IndexKeyPrefix, IterateParams, Serialize, ValueKey, U32_LEN,
+};
+use utils::map::vec_map::VecMap;
+
+use crate::{email::metadata::MessageMetadata, services::jobs::JobContext, Bincode, JMAP};
+
+const TOTAL: usize = 0;
+const UNREAD: usize = 1;
+const SIZE: usize = 2;
+
+const COUNTER_PROPERTIES: [Property; 3] = [
+    Property::TotalEmails,
+    Property::UnreadEmails,
+    Property::Size,
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxCount {
+    pub total: u64,
+    pub unread: u64,
+    pub size: u64,
+}
+
+/// Changes to the message, unread and size counters of the mailboxes
+/// modified by a write batch. Counters are updated in the same batch as the
+/// message tags, so they are only applied if the batch commits.
+#[derive(Default)]
+pub struct MailboxCounters {
+    deltas: VecMap<u32, [i64; 3]>,
+}
+
+impl MailboxCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_message(&mut self, mailbox_id: u32, is_unread: bool, size: u64) {
+        let delta = self.deltas.get_mut_or_insert(mailbox_id);
+        delta[TOTAL] += 1;
+        delta[UNREAD] += is_unread as i64;
+        delta[SIZE] += size as i64;
+    }
+
+    pub fn remove_message(&mut self, mailbox_id: u32, is_unread: bool, size: u64) {
+        let delta = self.deltas.get_mut_or_insert(mailbox_id);
+        delta[TOTAL] -= 1;
+        delta[UNREAD] -= is_unread as i64;
+        delta[SIZE] -= size as i64;
+    }
+
+    pub fn update_unread(&mut self, mailbox_id: u32, is_unread: bool) {
+        self.deltas.get_mut_or_insert(mailbox_id)[UNREAD] += if is_unread { 1 } else { -1 };
+    }
+
+    // Moves a message from its previous mailboxes and read state to the new ones,
+    // the size is only used for mailboxes the message was added to or removed from.
+    pub fn update_message(
+        &mut self,
+        old_mailboxes: impl IntoIterator<Item = u32>,
+        was_unread: bool,
+        new_mailboxes: impl IntoIterator<Item = u32>,
+        is_unread: bool,
+        size: u64,
+    ) {
+        for mailbox_id in old_mailboxes {
+            self.remove_message(mailbox_id, was_unread, size);
+        }
+        for mailbox_id in new_mailboxes {
+            self.add_message(mailbox_id, is_unread, size);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas
+            .values()
+            .all(|delta| delta.iter().all(|value| *value == 0))
+    }
+}
+
+impl IntoOperations for MailboxCounters {
+    fn build(self, batch: &mut BatchBuilder) {
+        batch.with_collection(Collection::Mailbox);
+        for (mailbox_id, delta) in self.deltas {
+            if delta.iter().all(|value| *value == 0) {
+                continue;
+            }
+            batch.update_document(mailbox_id);
+            for (property, value) in COUNTER_PROPERTIES.into_iter().zip(delta) {
+                if value != 0 {
+                    batch.add(ValueClass::Property(property.into()), value);
+                }
+            }
+        }
+    }
+}
+
+pub fn is_unread<'x>(keywords: impl IntoIterator<Item = &'x Keyword>) -> bool {
+    !keywords
+        .into_iter()
+        .any(|keyword| keyword == &Keyword::Seen)
+}
+
+impl JMAP {
+    /// Returns the materialized counters of a mailbox, or `None` while the
+    /// counters of existing mailboxes have not been built yet.
+    pub async fn mailbox_counters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> Result<Option<MailboxCount>, MethodError> {
+        if self.mailbox_counters_ready.load(Ordering::Relaxed) {
+            self.mailbox_counters_get(account_id, mailbox_id)
+                .await
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn mailbox_counters_get(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> Result<MailboxCount, MethodError> {
+        let mut values = [0u64; 3];
+        for (property, value) in COUNTER_PROPERTIES.into_iter().zip(values.iter_mut()) {
+            *value = self
+                .store
+                .get_counter(ValueKey::<ValueClass>::property(
+                    account_id,
+                    Collection::Mailbox,
+                    mailbox_id,
+                    property,
+                ))
+                .await
+                .map_err(|err| {
+                    tracing::error!(
+                        event = "error",
+                        context = "mailbox_counters",
+                        account_id = account_id,
+                        mailbox_id = mailbox_id,
+                        error = ?err,
+                        "Failed to obtain mailbox counters.");
+                    MethodError::ServerPartialFail
+                })?
+                .max(0) as u64;
+        }
+
+        Ok(MailboxCount {
+            total: values[TOTAL],
+            unread: values[UNREAD],
+            size: values[SIZE],
+        })
+    }
+
+    /// Computes the counters of all mailboxes in an account from the message bitmaps.
+    pub async fn mailbox_counters_build(
+        &self,
+        account_id: u32,
+    ) -> Result<VecMap<u32, MailboxCount>, MethodError> {
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut counters = VecMap::with_capacity(mailbox_ids.len() as usize);
+        if mailbox_ids.is_empty() {
+            return Ok(counters);
+        }
+        let seen = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::Keywords,
+                Keyword::Seen,
+            )
+            .await?
+            .unwrap_or_default();
+        let sizes = self.email_sizes(account_id).await?;
+
+        for mailbox_id in mailbox_ids {
+            let message_ids = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    mailbox_id,
+                )
+                .await?
+                .unwrap_or_default();
+            counters.append(
+                mailbox_id,
+                MailboxCount {
+                    total: message_ids.len(),
+                    unread: (&message_ids - &seen).len(),
+                    size: message_ids
+                        .iter()
+                        .map(|id| sizes.get(&id).copied().unwrap_or(0) as u64)
+                        .sum(),
+                },
+            );
+        }
+
+        Ok(counters)
+    }
+
+    /// Rebuilds the counters of an account, or of all accounts if none is specified.
+    pub async fn mailbox_counters_rebuild(
+        &self,
+        account_id: Option<u32>,
+        ctx: &JobContext,
+    ) -> Result<(), String> {
+        let account_ids = if let Some(account_id) = account_id {
+            vec![account_id]
+        } else {
+            let mut account_ids = Vec::new();
+            for name in self
+                .store
+                .list_accounts(None, None, 0)
+                .await
+                .map_err(|err| format!("Failed to list accounts: {err:?}"))?
+            {
+                if let Ok(Some(account_id)) = self.store.get_account_id(&name).await {
+                    account_ids.push(account_id);
+                }
+            }
+            account_ids
+        };
+
+        let total = account_ids.len().max(1);
+        for (pos, account_id) in account_ids.into_iter().enumerate() {
+            if ctx.is_cancelled() {
+                break;
+            }
+            let counters = self
+                .mailbox_counters_build(account_id)
+                .await
+                .map_err(|err| {
+                    format!("Failed to build counters for account {account_id}: {err:?}")
+                })?;
+            if !counters.is_empty() {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Mailbox);
+                for (mailbox_id, count) in counters {
+                    batch.update_document(mailbox_id);
+                    for (property, value) in
+                        COUNTER_PROPERTIES
+                            .into_iter()
+                            .zip([count.total, count.unread, count.size])
+                    {
+                        let class = ValueClass::Property(property.into());
+                        batch.clear_counter(class.clone());
+                        if value != 0 {
+                            batch.add(class, value as i64);
+                        }
+                    }
+                }
+                self.store.write(batch.build()).await.map_err(|err| {
+                    format!("Failed to write counters for account {account_id}: {err:?}")
+                })?;
+            }
+            self.job_progress(ctx, ((pos + 1) * 100 / total) as u8)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    // Counters are built once for mailboxes created before they were
+    // introduced. Until then, readers compute the values from the bitmaps.
+    // Messages modified while the counters are being built can leave them
+    // off, in which case the `mailbox-counters` job can be run again.
+    pub async fn mailbox_counters_init(&self) {
+        let marker = ValueKey::<ValueClass>::property(
+            u32::MAX,
+            Collection::Mailbox,
+            u32::MAX,
+            Property::TotalEmails,
+        );
+        match self.store.get_value::<u64>(marker.clone()).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                tracing::info!(
+                    context = "mailbox_counters",
+                    event = "start",
+                    "Building mailbox counters."
+                );
+                if let Err(err) = self
+                    .mailbox_counters_rebuild(None, &JobContext::new(0))
+                    .await
+                {
+                    tracing::warn!(
+                        context = "mailbox_counters",
+                        event = "error",
+                        reason = %err,
+                        "Failed to build mailbox counters."
+                    );
+                    return;
+                }
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(u32::MAX)
+                    .with_collection(Collection::Mailbox)
+                    .update_document(u32::MAX)
+                    .set(marker.class, store::write::now().serialize());
+                if let Err(err) = self.store.write(batch.build()).await {
+                    tracing::warn!(
+                        context = "mailbox_counters",
+                        event = "error",
+                        reason = ?err,
+                        "Failed to write mailbox counters marker."
+                    );
+                    return;
+                }
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "mailbox_counters",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to obtain mailbox counters marker."
+                );
+                return;
+            }
+        }
+
+        self.mailbox_counters_ready.store(true, Ordering::Relaxed);
+    }
+
+    pub async fn email_size(&self, account_id: u32, document_id: u32) -> Result<u64, MethodError> {
+        Ok(self
+            .get_property::<Bincode<MessageMetadata>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await?
+            .map_or(0, |metadata| metadata.inner.size as u64))
+    }
+
+    async fn email_sizes(&self, account_id: u32) -> Result<AHashMap<u32, u32>, MethodError> {
+        let mut sizes = AHashMap::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    IndexKeyPrefix {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        field: Property::Size.into(),
+                    },
+                    IndexKeyPrefix {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        field: u8::from(Property::Size) + 1,
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    let id_pos = key.len() - U32_LEN;
+                    let document_id = key.deserialize_be_u32(id_pos)?;
+                    let size = key
+                        .get(IndexKeyPrefix::len()..id_pos)
+                        .ok_or_else(|| {
+                            store::Error::InternalError("Invalid key length".to_string())
+                        })
+                        .and_then(u32::deserialize)?;
+                    sizes.insert(document_id, size);
+                    Ok(true)
+                },
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    event = "error",
+                    context = "mailbox_counters",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to obtain message sizes.");
+                MethodError::ServerPartialFail
+            })?;
+
+        Ok(sizes)
+    }
+}
@@ -29,6 +29,7 @@ use store::{
 };
 use utils::codec::leb128::{Leb128Iterator, Leb128Vec};
 
+pub mod counters;
 pub mod get;
 pub mod query;
 pub mod set;
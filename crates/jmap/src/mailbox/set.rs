@@ -37,6 +37,7 @@ use jmap_proto::{
         acl::Acl,
         collection::Collection,
         id::Id,
+        keyword::Keyword,
         property::Property,
         state::StateChange,
         type_state::DataType,
@@ -58,7 +59,10 @@ use crate::{
     JMAP,
 };
 
-use super::{UidMailbox, INBOX_ID, JUNK_ID, TRASH_ID};
+use super::{
+    counters::{is_unread, MailboxCounters},
+    UidMailbox, INBOX_ID, JUNK_ID, TRASH_ID,
+};
 
 struct SetContext<'x> {
     account_id: u32,
@@ -189,8 +193,19 @@ impl JMAP {
             {
                 // Validate ACL
                 if ctx.is_shared {
+                    // Subscribing only requires read access to the mailbox
                     let acl = mailbox.inner.effective_acl(access_token);
-                    if !acl.contains(Acl::Modify) {
+                    if !acl.contains(
+                        if object
+                            .properties
+                            .keys()
+                            .all(|property| property == &Property::IsSubscribed)
+                        {
+                            Acl::Read
+                        } else {
+                            Acl::Modify
+                        },
+                    ) {
                         ctx.response.not_updated.append(
                             id,
                             SetError::forbidden()
@@ -292,17 +307,18 @@ impl JMAP {
 
         // Write changes
         if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
             let state_change =
-                StateChange::new(account_id).with_change(DataType::Mailbox, changes.change_id);
+                StateChange::new(account_id).with_change(DataType::Mailbox, change_id);
             ctx.response.state_change = if did_remove_emails {
                 state_change
-                    .with_change(DataType::Email, changes.change_id)
-                    .with_change(DataType::Thread, changes.change_id)
+                    .with_change(DataType::Email, change_id)
+                    .with_change(DataType::Thread, change_id)
             } else {
                 state_change
             }
             .into();
-            ctx.response.new_state = Some(self.commit_changes(account_id, changes).await?.into());
+            ctx.response.new_state = Some(change_id.into());
         }
 
         Ok(ctx.response)
@@ -375,25 +391,40 @@ impl JMAP {
                         })
                     {
                         if !mailbox_ids.inner.is_empty() {
-                            // Obtain threadId
-                            if let Some(thread_id) = self
-                                .get_property::<u32>(
+                            // Obtain threadId and keywords
+                            if let (Some(thread_id), Some(keywords)) = (
+                                self.get_property::<u32>(
                                     account_id,
                                     Collection::Email,
                                     message_id,
                                     Property::ThreadId,
                                 )
-                                .await?
-                            {
+                                .await?,
+                                self.get_property::<HashedValue<Vec<Keyword>>>(
+                                    account_id,
+                                    Collection::Email,
+                                    message_id,
+                                    Property::Keywords,
+                                )
+                                .await?,
+                            ) {
                                 // Untag message from mailbox
+                                let mut counters = MailboxCounters::new();
+                                counters.remove_message(
+                                    document_id,
+                                    is_unread(&keywords.inner),
+                                    self.email_size(account_id, message_id).await?,
+                                );
                                 let mut batch = BatchBuilder::new();
                                 batch
                                     .with_account_id(account_id)
                                     .with_collection(Collection::Email)
                                     .update_document(message_id)
                                     .assert_value(Property::MailboxIds, &mailbox_ids)
+                                    .assert_value(Property::Keywords, &keywords)
                                     .value(Property::MailboxIds, mailbox_ids.inner, F_VALUE)
-                                    .value(Property::MailboxIds, document_id, F_BITMAP | F_CLEAR);
+                                    .value(Property::MailboxIds, document_id, F_BITMAP | F_CLEAR)
+                                    .custom(counters);
                                 match self.store.write(batch.build()).await {
                                     Ok(_) => changes.log_update(
                                         Collection::Email,
@@ -426,7 +457,7 @@ impl JMAP {
                                     account_id = account_id,
                                     mailbox_id = document_id,
                                     message_id = message_id,
-                                    "Message does not have a threadId, skipping."
+                                    "Message does not have a threadId or keywords, skipping."
                                 );
                             }
                         } else {
@@ -486,6 +517,9 @@ impl JMAP {
                 .with_collection(Collection::Mailbox)
                 .delete_document(document_id)
                 .value(Property::EmailIds, (), F_VALUE | F_CLEAR)
+                .clear_counter(Property::TotalEmails)
+                .clear_counter(Property::UnreadEmails)
+                .clear_counter(Property::Size)
                 .custom(ObjectIndexBuilder::new(SCHEMA).with_current(mailbox));
 
             match self.store.write(batch.build()).await {
@@ -624,6 +658,7 @@ impl JMAP {
                 .map_or(u32::MAX, |(mailbox_id, _)| *mailbox_id + 1);
             let mut mailbox_parent_id = mailbox_parent_id.document_id();
             let mut success = false;
+            let mut parent_depth = 0;
             for depth in 0..self.config.mailbox_max_depth {
                 if mailbox_parent_id == current_mailbox_id {
                     return Ok(Err(SetError::invalid_properties()
@@ -635,6 +670,7 @@ impl JMAP {
                             .with_description("You are not allowed to create root folders.")));
                     }
                     success = true;
+                    parent_depth = depth;
                     break;
                 }
                 let parent_document_id = mailbox_parent_id - 1;
@@ -667,6 +703,7 @@ impl JMAP {
                 } else if ctx.mailbox_ids.contains(parent_document_id) {
                     // Parent mailbox is probably created within the same request
                     success = true;
+                    parent_depth = depth;
                     break;
                 } else {
                     return Ok(Err(SetError::invalid_properties()
@@ -682,6 +719,23 @@ impl JMAP {
                         "Mailbox parent-child relationship is too deep.",
                     )));
             }
+
+            // Make sure the descendants of a moved mailbox stay within the depth limit
+            if let Some((document_id, _)) = &update {
+                if parent_depth
+                    + 1
+                    + self
+                        .mailbox_subtree_height(ctx.account_id, *document_id)
+                        .await?
+                    > self.config.mailbox_max_depth
+                {
+                    return Ok(Err(SetError::invalid_properties()
+                        .with_property(Property::ParentId)
+                        .with_description(
+                            "Mailbox parent-child relationship is too deep.",
+                        )));
+                }
+            }
         } else if update.is_none() {
             // Set parentId if the field is missing
             changes.append(Property::ParentId, Value::Id(0u64.into()));
@@ -801,6 +855,41 @@ impl JMAP {
             .validate())
     }
 
+    pub async fn mailbox_subtree_height(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<usize, MethodError> {
+        let mut height = 0;
+        let mut seen_ids = RoaringBitmap::new();
+        let mut parent_ids = vec![document_id];
+
+        while !parent_ids.is_empty() && height <= self.config.mailbox_max_depth {
+            let mut child_ids = Vec::new();
+            for parent_id in parent_ids {
+                for child_id in self
+                    .filter(
+                        account_id,
+                        Collection::Mailbox,
+                        vec![Filter::eq(Property::ParentId, parent_id + 1)],
+                    )
+                    .await?
+                    .results
+                {
+                    if seen_ids.insert(child_id) {
+                        child_ids.push(child_id);
+                    }
+                }
+            }
+            if !child_ids.is_empty() {
+                height += 1;
+            }
+            parent_ids = child_ids;
+        }
+
+        Ok(height)
+    }
+
     pub async fn mailbox_get_or_create(
         &self,
         account_id: u32,
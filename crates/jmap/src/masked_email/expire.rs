@@ -0,0 +1,253 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::method::MethodError,
+    object::{index::ObjectIndexBuilder, Object},
+    types::{collection::Collection, date::UTCDate, property::Property, value::Value},
+};
+use mail_builder::MessageBuilder;
+use mail_parser::MessageParser;
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder},
+};
+
+use crate::{email::ingest::IngestEmail, mailbox::INBOX_ID, JMAP};
+
+use super::{expires_at, expiry_notified, MaskedEmailState, SCHEMA};
+
+impl JMAP {
+    pub async fn masked_email_expire_all(&self) {
+        let account_ids = match self.get_document_ids(u32::MAX, Collection::Principal).await {
+            Ok(Some(account_ids)) => account_ids,
+            Ok(None) => return,
+            Err(_) => {
+                tracing::warn!(
+                    context = "masked_email_expire",
+                    event = "error",
+                    "Failed to obtain account ids."
+                );
+                return;
+            }
+        };
+
+        for account_id in account_ids {
+            if self.masked_email_expire(account_id).await.is_err() {
+                tracing::warn!(
+                    context = "masked_email_expire",
+                    event = "error",
+                    account_id = account_id,
+                    "Failed to expire masked e-mails."
+                );
+            }
+        }
+    }
+
+    // Stops routing expired masked addresses and notifies the owner of
+    // addresses that are about to expire
+    pub async fn masked_email_expire(&self, account_id: u32) -> Result<(), MethodError> {
+        let mask_ids = self
+            .filter(
+                account_id,
+                Collection::MaskedEmail,
+                vec![Filter::is_in_bitmap(Property::Expires, ())],
+            )
+            .await?
+            .results;
+        if mask_ids.is_empty() {
+            return Ok(());
+        }
+
+        let now = now() as i64;
+        let notify_before = self.config.masked_email_expiry_notice.as_secs() as i64;
+        let mut changes = ChangeLogBuilder::new();
+        let mut notifications = Vec::new();
+
+        for document_id in mask_ids {
+            let mask = if let Some(mask) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::MaskedEmail,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                mask
+            } else {
+                continue;
+            };
+            let expires = match expires_at(&mask.inner) {
+                Some(expires) if MaskedEmailState::from(&mask.inner).is_routable() => expires,
+                _ => continue,
+            };
+            let email = mask
+                .inner
+                .properties
+                .get(&Property::Email)
+                .and_then(|v| v.as_string())
+                .unwrap_or_default()
+                .to_string();
+
+            let mut update = Object::with_capacity(1);
+            let mut notification = None;
+            if expires <= now {
+                // Remove the address from the directory, RCPT will fail from now on
+                if let Err(err) = self.masked_email_route(account_id, &email, false).await? {
+                    tracing::debug!(
+                        context = "masked_email_expire",
+                        event = "error",
+                        account_id = account_id,
+                        reason = ?err.description,
+                        "Failed to remove expired masked e-mail from directory."
+                    );
+                    continue;
+                }
+                update.set(
+                    Property::State,
+                    Value::Text(MaskedEmailState::Deleted.as_str().to_string()),
+                );
+            } else if notify_before > 0
+                && expires - notify_before <= now
+                && !matches!(
+                    mask.inner.properties.get(&expiry_notified()),
+                    Some(Value::Bool(true))
+                )
+            {
+                notification = Some((
+                    email,
+                    expires,
+                    mask.inner
+                        .properties
+                        .get(&Property::ForDomain)
+                        .and_then(|v| v.as_string())
+                        .map(|v| v.to_string()),
+                ));
+                update.set(expiry_notified(), Value::Bool(true));
+            } else {
+                continue;
+            }
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::MaskedEmail)
+                .update_document(document_id)
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(mask)
+                        .with_changes(update),
+                );
+            match self.store.write(batch.build()).await {
+                Ok(_) => {
+                    changes.log_update(Collection::MaskedEmail, document_id);
+                    notifications.extend(notification);
+                }
+                Err(store::Error::AssertValueFailed) => {
+                    // Modified concurrently, retry on the next run
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "masked_email_expire",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to update masked e-mail.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            self.commit_changes(account_id, changes).await?;
+        }
+
+        // Notify the owner about upcoming expirations
+        if !notifications.is_empty() {
+            let account_quota = self
+                .directory
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .map_err(|_| MethodError::ServerPartialFail)?
+                .map_or(0, |p| p.quota as i64);
+
+            for (email, expires, for_domain) in notifications {
+                let domain = email.rsplit_once('@').map_or("localhost", |(_, d)| d);
+                let raw_message = MessageBuilder::new()
+                    .from(("Postmaster".to_string(), format!("postmaster@{domain}")))
+                    .to(email.as_str())
+                    .subject(format!("Masked e-mail address {email} expires soon"))
+                    .text_body(format!(
+                        concat!(
+                            "The masked e-mail address {}{} will expire on {}.\r\n\r\n",
+                            "Messages sent to this address after that date will be rejected. ",
+                            "To keep receiving them, change or remove the expiry date ",
+                            "of this address."
+                        ),
+                        email,
+                        for_domain
+                            .map(|d| format!(" (used for {d})"))
+                            .unwrap_or_default(),
+                        UTCDate::from_timestamp(expires)
+                    ))
+                    .write_to_vec()
+                    .unwrap_or_default();
+
+                match self
+                    .email_ingest(IngestEmail {
+                        raw_message: &raw_message,
+                        message: MessageParser::new().parse(&raw_message),
+                        account_id,
+                        account_quota,
+                        mailbox_ids: vec![INBOX_ID],
+                        keywords: vec![],
+                        received_at: None,
+                        skip_duplicates: false,
+                        encrypt: self.config.encrypt,
+                    })
+                    .await
+                {
+                    Ok(ingested) => {
+                        if ingested.change_id != u64::MAX {
+                            self.broadcast_delivery(account_id, ingested.change_id)
+                                .await;
+                        }
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "masked_email_expire",
+                            event = "error",
+                            account_id = account_id,
+                            reason = ?err,
+                            "Failed to deliver expiry notification."
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
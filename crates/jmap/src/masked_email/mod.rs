@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::{
+        index::{IndexAs, IndexProperty, ObjectIndexBuilder},
+        Object,
+    },
+    types::{collection::Collection, date::UTCDate, property::Property, value::Value},
+};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder},
+};
+
+use utils::ipc::DeliveryResult;
+
+use crate::JMAP;
+
+pub mod expire;
+pub mod get;
+pub mod set;
+
+pub static SCHEMA: &[IndexProperty] = &[
+    IndexProperty::new(Property::Email)
+        .index_as(IndexAs::Text {
+            tokenize: false,
+            index: true,
+        })
+        .max_size(255)
+        .required(),
+    IndexProperty::new(Property::Expires).index_as(IndexAs::HasProperty),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskedEmailState {
+    Pending,
+    Enabled,
+    Disabled,
+    Deleted,
+}
+
+impl MaskedEmailState {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(MaskedEmailState::Pending),
+            "enabled" => Some(MaskedEmailState::Enabled),
+            "disabled" => Some(MaskedEmailState::Disabled),
+            "deleted" => Some(MaskedEmailState::Deleted),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaskedEmailState::Pending => "pending",
+            MaskedEmailState::Enabled => "enabled",
+            MaskedEmailState::Disabled => "disabled",
+            MaskedEmailState::Deleted => "deleted",
+        }
+    }
+
+    pub fn is_routable(&self) -> bool {
+        !matches!(self, MaskedEmailState::Deleted)
+    }
+}
+
+impl From<&Object<Value>> for MaskedEmailState {
+    fn from(object: &Object<Value>) -> Self {
+        object
+            .properties
+            .get(&Property::State)
+            .and_then(|v| v.as_string())
+            .and_then(MaskedEmailState::parse)
+            .unwrap_or(MaskedEmailState::Pending)
+    }
+}
+
+// Internal property recording that the owner was notified about an upcoming expiry
+pub(crate) fn expiry_notified() -> Property {
+    Property::_T("expiryNotified".to_string())
+}
+
+pub(crate) fn expires_at(object: &Object<Value>) -> Option<i64> {
+    object
+        .properties
+        .get(&Property::Expires)
+        .and_then(|v| v.as_date())
+        .map(|d| d.timestamp())
+}
+
+impl JMAP {
+    // Records a delivery to a masked address. Returns the final delivery result
+    // when the message has to be discarded (disabled mask) or rejected (expired mask).
+    pub async fn masked_email_deliver(
+        &self,
+        account_id: u32,
+        rcpt: &str,
+    ) -> Result<Option<DeliveryResult>, MethodError> {
+        let rcpt = rcpt.to_lowercase();
+        let document_id = if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::MaskedEmail,
+                vec![Filter::eq(Property::Email, &rcpt)],
+            )
+            .await?
+            .results
+            .min()
+        {
+            document_id
+        } else {
+            return Ok(None);
+        };
+        let mask = if let Some(mask) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::MaskedEmail,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            mask
+        } else {
+            return Ok(None);
+        };
+
+        let state = MaskedEmailState::from(&mask.inner);
+        if expires_at(&mask.inner).map_or(false, |expires| expires <= now() as i64)
+            || state == MaskedEmailState::Deleted
+        {
+            return Ok(Some(DeliveryResult::PermanentFailure {
+                code: [5, 1, 1],
+                reason: "Mailbox unavailable, this address has expired.".into(),
+            }));
+        } else if state == MaskedEmailState::Disabled {
+            return Ok(Some(DeliveryResult::Success));
+        }
+
+        // Update last received timestamp, pending masks are enabled on first use
+        let mut changes = Object::with_capacity(2).with_property(
+            Property::LastMessageAt,
+            Value::Date(UTCDate::from_timestamp(now() as i64)),
+        );
+        if state == MaskedEmailState::Pending {
+            changes.set(
+                Property::State,
+                Value::Text(MaskedEmailState::Enabled.as_str().to_string()),
+            );
+        }
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::MaskedEmail)
+            .update_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new(SCHEMA)
+                    .with_current(mask)
+                    .with_changes(changes),
+            );
+        match self.store.write(batch.build()).await {
+            Ok(_) => {
+                let mut changes = ChangeLogBuilder::new();
+                changes.log_update(Collection::MaskedEmail, document_id);
+                self.commit_changes(account_id, changes).await?;
+            }
+            Err(store::Error::AssertValueFailed) => {
+                // A concurrent delivery already updated the mask
+            }
+            Err(err) => {
+                tracing::error!(
+                    event = "error",
+                    context = "masked_email_deliver",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to update masked e-mail.");
+                return Err(MethodError::ServerPartialFail);
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,491 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::{
+    backend::internal::manage::ManageDirectory, DirectoryError, ManagementError, QueryBy,
+};
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::{index::ObjectIndexBuilder, Object},
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        date::UTCDate,
+        property::Property,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder},
+};
+
+use crate::{auth::AccessToken, JMAP};
+
+use super::{expiry_notified, MaskedEmailState, SCHEMA};
+
+impl JMAP {
+    pub async fn masked_email_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+        access_token: &AccessToken,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut mask_ids = self
+            .get_document_ids(account_id, Collection::MaskedEmail)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::MaskedEmail)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            if mask_ids.len() as usize >= self.config.masked_email_max {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many masked e-mails, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            let mut mask = Object::with_capacity(object.properties.len() + 4);
+            let mut email_prefix = None;
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_mask_value(&property, value, true))
+                {
+                    Ok(Value::Null) => (),
+                    Ok(Value::Text(prefix)) if property == Property::EmailPrefix => {
+                        email_prefix = prefix.into();
+                    }
+                    Ok(value) => {
+                        mask.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+            let state = MaskedEmailState::from(&mask);
+
+            // Obtain the domain masked addresses are generated at
+            let domain = if let Some(domain) = &self.config.masked_email_domain {
+                domain.clone()
+            } else if let Some(domain) = self
+                .directory
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .map_err(|err| {
+                    tracing::error!(
+                        event = "error",
+                        context = "masked_email_set",
+                        error = ?err,
+                        "Failed to query directory.");
+                    MethodError::ServerPartialFail
+                })?
+                .and_then(|p| {
+                    p.emails
+                        .first()
+                        .and_then(|email| email.rsplit_once('@'))
+                        .map(|(_, domain)| domain.to_lowercase())
+                })
+            {
+                domain
+            } else {
+                response.not_created.append(
+                    id,
+                    SetError::forbidden()
+                        .with_description("No e-mail domain is configured for this account."),
+                );
+                continue 'create;
+            };
+
+            // Generate a unique address, retrying on collisions
+            let mut email = String::new();
+            for _ in 0..5 {
+                let candidate = generate_address(email_prefix.as_deref(), &domain);
+                if state.is_routable() {
+                    match self
+                        .masked_email_route(account_id, &candidate, true)
+                        .await?
+                    {
+                        Ok(_) => (),
+                        Err(err) if err.type_ == SetErrorType::AlreadyExists => continue,
+                        Err(err) => {
+                            response.not_created.append(id, err);
+                            continue 'create;
+                        }
+                    }
+                }
+                email = candidate;
+                break;
+            }
+            if email.is_empty() {
+                response.not_created.append(
+                    id,
+                    SetError::forbidden()
+                        .with_description("Failed to generate a unique masked e-mail address."),
+                );
+                continue 'create;
+            }
+
+            // Insert record
+            mask.set(Property::Email, Value::Text(email.clone()));
+            mask.set(Property::State, Value::Text(state.as_str().to_string()));
+            mask.set(
+                Property::CreatedAt,
+                Value::Date(UTCDate::from_timestamp(now() as i64)),
+            );
+            mask.set(Property::CreatedBy, Value::Text(access_token.name.clone()));
+            let document_id = self
+                .assign_document_id(account_id, Collection::MaskedEmail)
+                .await?;
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::MaskedEmail)
+                .create_document(document_id)
+                .custom(ObjectIndexBuilder::new(SCHEMA).with_changes(mask));
+            self.write_batch(batch).await?;
+            mask_ids.insert(document_id);
+            changes.log_insert(Collection::MaskedEmail, document_id);
+            response.created.insert(
+                id,
+                Object::with_capacity(3)
+                    .with_property(Property::Id, Value::Id(document_id.into()))
+                    .with_property(Property::Email, email)
+                    .with_property(Property::State, state.as_str().to_string()),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain masked email
+            let document_id = id.document_id();
+            let mask = if let Some(mask) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::MaskedEmail,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                mask
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            let mut update = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_mask_value(&property, value, false))
+                {
+                    Ok(value) => {
+                        update.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            // Validate state transition
+            let current_state = MaskedEmailState::from(&mask.inner);
+            let new_state = update
+                .properties
+                .get(&Property::State)
+                .and_then(|v| v.as_string())
+                .and_then(MaskedEmailState::parse)
+                .unwrap_or(current_state);
+            if new_state == MaskedEmailState::Pending && current_state != new_state {
+                response.not_updated.append(
+                    id,
+                    SetError::invalid_properties()
+                        .with_property(Property::State)
+                        .with_description("Masked e-mails cannot be set back to pending."),
+                );
+                continue 'update;
+            }
+
+            // Expired masks cannot be reactivated without a new expiry date
+            if let Some(expires) = update.properties.get(&Property::Expires) {
+                if expires
+                    != mask
+                        .inner
+                        .properties
+                        .get(&Property::Expires)
+                        .unwrap_or(&Value::Null)
+                {
+                    update.set(expiry_notified(), Value::Null);
+                }
+            }
+            if new_state.is_routable()
+                && update
+                    .properties
+                    .get(&Property::Expires)
+                    .or_else(|| mask.inner.properties.get(&Property::Expires))
+                    .and_then(|v| v.as_date())
+                    .map_or(false, |d| d.timestamp() <= now() as i64)
+            {
+                response.not_updated.append(
+                    id,
+                    SetError::invalid_properties()
+                        .with_property(Property::Expires)
+                        .with_description("This masked e-mail has expired."),
+                );
+                continue 'update;
+            }
+
+            // Add or remove the address from the directory
+            let email = mask
+                .inner
+                .properties
+                .get(&Property::Email)
+                .and_then(|v| v.as_string())
+                .unwrap_or_default()
+                .to_string();
+            let reroute = current_state.is_routable() != new_state.is_routable();
+            if reroute {
+                if let Err(err) = self
+                    .masked_email_route(account_id, &email, new_state.is_routable())
+                    .await?
+                {
+                    response.not_updated.append(id, err);
+                    continue 'update;
+                }
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::MaskedEmail)
+                .update_document(document_id)
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(mask)
+                        .with_changes(update),
+                );
+            match self.store.write(batch.build()).await {
+                Ok(_) => (),
+                Err(store::Error::AssertValueFailed) => {
+                    if reroute {
+                        self.masked_email_route(account_id, &email, current_state.is_routable())
+                            .await?
+                            .ok();
+                    }
+                    response.not_updated.append(
+                        id,
+                        SetError::forbidden().with_description(
+                            "Another process modified this masked e-mail, please try again.",
+                        ),
+                    );
+                    continue 'update;
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "masked_email_set",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to update masked e-mail.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+            changes.log_update(Collection::MaskedEmail, document_id);
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if !mask_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+            let mask = if let Some(mask) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::MaskedEmail,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                mask
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            };
+
+            // Stop routing the address
+            if MaskedEmailState::from(&mask.inner).is_routable() {
+                if let Some(email) = mask
+                    .inner
+                    .properties
+                    .get(&Property::Email)
+                    .and_then(|v| v.as_string())
+                {
+                    self.masked_email_route(account_id, email, false)
+                        .await?
+                        .ok();
+                }
+            }
+
+            // Delete record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::MaskedEmail)
+                .delete_document(document_id)
+                .custom(ObjectIndexBuilder::new(SCHEMA).with_current(mask));
+            self.write_batch(batch).await?;
+            changes.log_delete(Collection::MaskedEmail, document_id);
+            response.destroyed.push(id);
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            response.new_state = Some(self.commit_changes(account_id, changes).await?.into());
+        }
+
+        Ok(response)
+    }
+
+    pub(crate) async fn masked_email_route(
+        &self,
+        account_id: u32,
+        email: &str,
+        enable: bool,
+    ) -> Result<Result<(), SetError>, MethodError> {
+        let result = if enable {
+            self.store.add_masked_email(account_id, email).await
+        } else {
+            self.store.remove_masked_email(account_id, email).await
+        };
+
+        match result {
+            Ok(_) => Ok(Ok(())),
+            Err(DirectoryError::Management(ManagementError::AlreadyExists { .. })) => Ok(Err(
+                SetError::already_exists()
+                    .with_description(format!("The address {email} is already in use.")),
+            )),
+            Err(DirectoryError::Management(ManagementError::NotFound(item))) => {
+                Ok(Err(SetError::forbidden().with_description(format!(
+                    "Failed to route {email}: '{item}' does not exist in the internal directory."
+                ))))
+            }
+            Err(err) => {
+                tracing::error!(
+                    event = "error",
+                    context = "masked_email_route",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to update masked e-mail address in directory.");
+                Err(MethodError::ServerPartialFail)
+            }
+        }
+    }
+}
+
+fn validate_mask_value(
+    property: &Property,
+    value: MaybePatchValue,
+    is_create: bool,
+) -> Result<Value, SetError> {
+    Ok(match (property, value) {
+        (Property::State, MaybePatchValue::Value(Value::Text(value)))
+            if MaskedEmailState::parse(&value).is_some() =>
+        {
+            Value::Text(value)
+        }
+        (
+            Property::ForDomain | Property::Description | Property::Url,
+            MaybePatchValue::Value(Value::Text(value)),
+        ) if value.len() < 1024 => Value::Text(value),
+        (Property::Expires, MaybePatchValue::Value(Value::Date(value)))
+            if value.timestamp() > now() as i64 =>
+        {
+            Value::Date(value)
+        }
+        (Property::EmailPrefix, MaybePatchValue::Value(Value::Text(value)))
+            if is_create
+                && !value.is_empty()
+                && value.len() <= 64
+                && value
+                    .bytes()
+                    .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == b'_') =>
+        {
+            Value::Text(value)
+        }
+        (
+            Property::ForDomain
+            | Property::Description
+            | Property::Url
+            | Property::EmailPrefix
+            | Property::Expires,
+            MaybePatchValue::Value(Value::Null),
+        ) => Value::Null,
+
+        (property, _) => {
+            return Err(SetError::invalid_properties()
+                .with_property(property.clone())
+                .with_description("Field could not be set."));
+        }
+    })
+}
+
+fn generate_address(prefix: Option<&str>, domain: &str) -> String {
+    let local = thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(if prefix.is_some() { 8 } else { 12 })
+        .map(|ch| char::from(ch).to_ascii_lowercase())
+        .collect::<String>();
+
+    if let Some(prefix) = prefix {
+        format!("{prefix}.{local}@{domain}")
+    } else {
+        format!("{local}@{domain}")
+    }
+}
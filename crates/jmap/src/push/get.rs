@@ -105,6 +105,12 @@ impl JMAP {
                             "The 'url' and 'keys' properties are not readable".to_string(),
                         ));
                     }
+                    Property::DeliveryStats => {
+                        result.append(
+                            Property::DeliveryStats,
+                            Value::from(self.push_stats(account_id, document_id).await?),
+                        );
+                    }
                     property => {
                         result.append(property.clone(), push.remove(property));
                     }
@@ -128,6 +134,7 @@ impl JMAP {
             .unwrap_or_default();
 
         let current_time = now();
+        let mut has_previews = false;
 
         for document_id in document_ids {
             let mut subscription = self
@@ -231,6 +238,14 @@ impl JMAP {
                         Bitmap::all()
                     };
 
+                    // Previews are only sent encrypted
+                    let preview = keys.is_some()
+                        && matches!(
+                            subscription.properties.get(&Property::Preview),
+                            Some(Value::Bool(true))
+                        );
+                    has_previews |= preview;
+
                     // Add verified subscription
                     subscriptions.push(UpdateSubscription::Verified(PushSubscription {
                         id: document_id,
@@ -238,6 +253,7 @@ impl JMAP {
                         expires,
                         types,
                         keys,
+                        preview,
                     }));
                 } else {
                     // Add unverified subscription
@@ -251,6 +267,13 @@ impl JMAP {
             }
         }
 
+        // Deliveries only build previews for accounts that requested them
+        if has_previews {
+            self.push_previews.insert(account_id);
+        } else {
+            self.push_previews.remove(&account_id);
+        }
+
         Ok(state::Event::UpdateSubscriptions {
             account_id,
             subscriptions,
@@ -25,19 +25,29 @@ use base64::{engine::general_purpose, Engine};
 use jmap_proto::types::id::Id;
 use store::ahash::{AHashMap, AHashSet};
 use tokio::sync::mpsc;
-use utils::{config::Config, UnwrapFailure};
+use utils::{config::Config, failed, UnwrapFailure};
 
-use crate::{api::StateChangeResponse, services::IPC_CHANNEL_BUFFER, LONG_SLUMBER};
+use crate::{api::StateChangeResponse, services::IPC_CHANNEL_BUFFER, JMAP, LONG_SLUMBER};
 
 use super::{ece::ece_encrypt, EncryptionKeys, Event, PushServer, PushUpdate};
 
 use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use std::{
     collections::hash_map::Entry,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
-pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
+// Maximum number of previews waiting to be pushed to a subscription
+const MAX_PENDING_PREVIEWS: usize = 10;
+
+enum PushError {
+    // Encryption failures are permanent and not reattempted
+    Encryption(String),
+    Request(String),
+}
+
+pub fn spawn_push_manager(core: Arc<JMAP>, settings: &Config) -> mpsc::Sender<Event> {
     let (push_tx_, mut push_rx) = mpsc::channel::<Event>(IPC_CHANNEL_BUFFER);
     let push_tx = push_tx_.clone();
 
@@ -59,9 +69,35 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
     let push_throttle: Duration = settings
         .property_or_static("jmap.push.throttle", "1s")
         .failed("Invalid configuration");
+    let push_batch_window: Duration = settings
+        .property("jmap.push.batch.window")
+        .failed("Invalid configuration")
+        .unwrap_or_default();
+    let push_topic = settings
+        .value("jmap.push.topic")
+        .filter(|topic| !topic.is_empty())
+        .map(|topic| {
+            // RFC 8030 topics are limited to 32 characters from the URL-safe base64 alphabet
+            if topic.len() <= 32
+                && topic
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+            {
+                topic.to_string()
+            } else {
+                failed(&format!("Invalid push topic {topic:?}."))
+            }
+        });
+
+    // Pending batches are checked at least as often as the batch window
+    let push_retry_interval = if !push_batch_window.is_zero() {
+        push_retry_interval.min(push_batch_window)
+    } else {
+        push_retry_interval
+    };
 
     tokio::spawn(async move {
-        let mut subscriptions = AHashMap::default();
+        let mut subscriptions: AHashMap<Id, PushServer> = AHashMap::default();
         let mut last_verify: AHashMap<u32, Instant> = AHashMap::default();
         let mut last_retry = Instant::now();
         let mut retry_timeout = LONG_SLUMBER;
@@ -98,9 +134,11 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                         })
                                         .unwrap_or(true)
                                     {
+                                        let core = core.clone();
                                         tokio::spawn(async move {
-                                            http_request(
+                                            if let Err(err) = http_request(
                                                 url,
+                                                None,
                                                 format!(
                                                     concat!(
                                                         "{{\"@type\":\"PushVerification\",",
@@ -113,7 +151,20 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                                 keys,
                                                 push_timeout,
                                             )
-                                            .await;
+                                            .await
+                                            {
+                                                core.push_stats_update(
+                                                    account_id,
+                                                    id,
+                                                    format!(
+                                                        "Verification failed: {}",
+                                                        err.into_reason()
+                                                    )
+                                                    .into(),
+                                                )
+                                                .await
+                                                .ok();
+                                            }
                                         });
 
                                         last_verify.insert(account_id, current_time);
@@ -128,19 +179,31 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                                         continue;
                                     }
                                 }
-                                PushUpdate::Register { id, url, keys } => {
-                                    if let Entry::Vacant(entry) = subscriptions.entry(id) {
+                                PushUpdate::Register {
+                                    id,
+                                    url,
+                                    keys,
+                                    preview,
+                                } => match subscriptions.entry(id) {
+                                    Entry::Occupied(mut entry) => {
+                                        entry.get_mut().preview = preview;
+                                    }
+                                    Entry::Vacant(entry) => {
                                         entry.insert(PushServer {
                                             url,
                                             keys,
                                             num_attempts: 0,
                                             last_request: Instant::now()
                                                 - (push_throttle + Duration::from_millis(1)),
+                                            first_change: Instant::now(),
                                             state_changes: Vec::new(),
+                                            known_states: Vec::new(),
+                                            preview,
+                                            previews: Vec::new(),
                                             in_flight: false,
                                         });
                                     }
-                                }
+                                },
                                 PushUpdate::Unregister { id } => {
                                     subscriptions.remove(&id);
                                 }
@@ -150,17 +213,28 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                     Event::Push { ids, state_change } => {
                         for id in ids {
                             if let Some(subscription) = subscriptions.get_mut(&id) {
+                                if subscription.state_changes.is_empty() {
+                                    subscription.first_change = Instant::now();
+                                }
                                 subscription.state_changes.push(state_change.clone());
                                 let last_request = subscription.last_request.elapsed();
 
                                 if !subscription.in_flight
                                     && ((subscription.num_attempts == 0
-                                        && last_request > push_throttle)
+                                        && last_request > push_throttle
+                                        && subscription.first_change.elapsed()
+                                            >= push_batch_window)
                                         || ((1..push_attempts_max)
                                             .contains(&subscription.num_attempts)
                                             && last_request > push_attempt_interval))
                                 {
-                                    subscription.send(id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        id,
+                                        core.clone(),
+                                        push_tx.clone(),
+                                        push_timeout,
+                                        push_topic.clone(),
+                                    );
                                     retry_ids.remove(&id);
                                 } else {
                                     retry_ids.insert(id);
@@ -173,6 +247,21 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
                     Event::Reset => {
                         subscriptions.clear();
                     }
+                    Event::Preview { ids, preview } => {
+                        // Previews are sent along with the state change that follows them
+                        for id in ids {
+                            if let Some(subscription) =
+                                subscriptions.get_mut(&id).filter(|subscription| {
+                                    subscription.preview && subscription.keys.is_some()
+                                })
+                            {
+                                if subscription.previews.len() >= MAX_PENDING_PREVIEWS {
+                                    subscription.previews.remove(0);
+                                }
+                                subscription.previews.push(preview.clone());
+                            }
+                        }
+                    }
                     Event::DeliverySuccess { id } => {
                         if let Some(subscription) = subscriptions.get_mut(&id) {
                             subscription.num_attempts = 0;
@@ -208,12 +297,19 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
 
                             if !subscription.in_flight
                                 && ((subscription.num_attempts == 0
-                                    && last_request >= push_throttle)
+                                    && last_request >= push_throttle
+                                    && subscription.first_change.elapsed() >= push_batch_window)
                                     || (subscription.num_attempts > 0
                                         && last_request >= push_attempt_interval))
                             {
                                 if subscription.num_attempts < push_attempts_max {
-                                    subscription.send(*retry_id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        *retry_id,
+                                        core.clone(),
+                                        push_tx.clone(),
+                                        push_timeout,
+                                        push_topic.clone(),
+                                    );
                                 } else {
                                     tracing::debug!(
                                         concat!(
@@ -255,17 +351,59 @@ pub fn spawn_push_manager(settings: &Config) -> mpsc::Sender<Event> {
 }
 
 impl PushServer {
-    fn send(&mut self, id: Id, push_tx: mpsc::Sender<Event>, push_timeout: Duration) {
+    fn send(
+        &mut self,
+        id: Id,
+        core: Arc<JMAP>,
+        push_tx: mpsc::Sender<Event>,
+        push_timeout: Duration,
+        topic: Option<String>,
+    ) {
         let url = self.url.clone();
         let keys = self.keys.clone();
         let state_changes = std::mem::take(&mut self.state_changes);
 
+        // Push services replace undelivered messages that share a topic, so
+        // each payload has to carry the latest state of every type seen so far.
+        let payload = if topic.is_some() {
+            for state_change in &state_changes {
+                if let Some(known_state) = self
+                    .known_states
+                    .iter_mut()
+                    .find(|known_state| known_state.account_id == state_change.account_id)
+                {
+                    for (type_state, change_id) in &state_change.types {
+                        if let Some((_, known_id)) = known_state
+                            .types
+                            .iter_mut()
+                            .find(|(known_type, _)| known_type == type_state)
+                        {
+                            *known_id = *change_id;
+                        } else {
+                            known_state.types.push((*type_state, *change_id));
+                        }
+                    }
+                } else {
+                    self.known_states.push(state_change.clone());
+                }
+            }
+            self.known_states.clone()
+        } else {
+            state_changes.clone()
+        };
+
+        // Previews are not reattempted and, as they are unique to each
+        // payload, must not be collapsed by the push service.
+        let previews = std::mem::take(&mut self.previews);
+        let topic = topic.filter(|_| previews.is_empty());
+
         self.in_flight = true;
         self.last_request = Instant::now();
 
         tokio::spawn(async move {
             let mut response = StateChangeResponse::new();
-            for state_change in &state_changes {
+            response.previews = previews;
+            for state_change in &payload {
                 for (type_state, change_id) in &state_change.types {
                     response
                         .changed
@@ -274,33 +412,45 @@ impl PushServer {
                 }
             }
 
-            push_tx
-                .send(
-                    if http_request(
-                        url,
-                        serde_json::to_string(&response).unwrap(),
-                        keys,
-                        push_timeout,
-                    )
-                    .await
-                    {
-                        Event::DeliverySuccess { id }
-                    } else {
-                        Event::DeliveryFailure { id, state_changes }
-                    },
-                )
+            let (event, failure) = match http_request(
+                url,
+                topic,
+                serde_json::to_string(&response).unwrap(),
+                keys,
+                push_timeout,
+            )
+            .await
+            {
+                Ok(()) => (Event::DeliverySuccess { id }, None),
+                Err(PushError::Encryption(reason)) => {
+                    // Do not reattempt if encryption fails.
+                    (Event::DeliverySuccess { id }, Some(reason))
+                }
+                Err(PushError::Request(reason)) => {
+                    (Event::DeliveryFailure { id, state_changes }, Some(reason))
+                }
+            };
+
+            // Stats are written before the next request can be scheduled
+            if let Err(err) = core
+                .push_stats_update(id.prefix_id(), id.document_id(), failure)
                 .await
-                .ok();
+            {
+                tracing::debug!("Failed to update push subscription stats: {}", err);
+            }
+
+            push_tx.send(event).await.ok();
         });
     }
 }
 
 async fn http_request(
     url: String,
+    topic: Option<String>,
     mut body: String,
     keys: Option<EncryptionKeys>,
     push_timeout: Duration,
-) -> bool {
+) -> Result<(), PushError> {
     let client_builder = reqwest::Client::builder().timeout(push_timeout);
 
     #[cfg(feature = "test_mode")]
@@ -312,6 +462,9 @@ async fn http_request(
         .post(&url)
         .header(CONTENT_TYPE, "application/json")
         .header("TTL", "86400");
+    if let Some(topic) = topic {
+        client = client.header("Topic", topic);
+    }
 
     if let Some(keys) = keys {
         match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes())
@@ -322,18 +475,29 @@ async fn http_request(
                 client = client.header(CONTENT_ENCODING, "aes128gcm");
             }
             Err(err) => {
-                // Do not reattempt if encryption fails.
                 tracing::debug!("Failed to encrypt push subscription to {}: {}", url, err);
-                return true;
+                return Err(PushError::Encryption(format!("Encryption failed: {err}")));
             }
         }
     }
 
     match client.body(body).send().await {
-        Ok(response) => response.status().is_success(),
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(PushError::Request(format!(
+            "Push service responded with {}",
+            response.status()
+        ))),
         Err(err) => {
             tracing::debug!("HTTP post to {} failed with: {}", url, err);
-            false
+            Err(PushError::Request(err.to_string()))
+        }
+    }
+}
+
+impl PushError {
+    fn into_reason(self) -> String {
+        match self {
+            PushError::Encryption(reason) | PushError::Request(reason) => reason,
         }
     }
 }
@@ -25,12 +25,16 @@ pub mod ece;
 pub mod get;
 pub mod manager;
 pub mod set;
+pub mod stats;
 
 use std::time::Instant;
 
 use jmap_proto::types::{id::Id, state::StateChange, type_state::DataType};
+use mail_parser::Message;
 use utils::map::bitmap::Bitmap;
 
+const PREVIEW_MAX_LEN: usize = 256;
+
 #[derive(Debug)]
 pub enum UpdateSubscription {
     Unverified {
@@ -49,6 +53,7 @@ pub struct PushSubscription {
     pub expires: u64,
     pub types: Bitmap<DataType>,
     pub keys: Option<EncryptionKeys>,
+    pub preview: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +71,10 @@ pub enum Event {
         ids: Vec<Id>,
         state_change: StateChange,
     },
+    Preview {
+        ids: Vec<Id>,
+        preview: PushPreview,
+    },
     DeliverySuccess {
         id: Id,
     },
@@ -89,6 +98,7 @@ pub enum PushUpdate {
         id: Id,
         url: String,
         keys: Option<EncryptionKeys>,
+        preview: bool,
     },
     Unregister {
         id: Id,
@@ -101,6 +111,61 @@ pub struct PushServer {
     keys: Option<EncryptionKeys>,
     num_attempts: u32,
     last_request: Instant,
+    first_change: Instant,
     state_changes: Vec<StateChange>,
+    known_states: Vec<StateChange>,
+    preview: bool,
+    previews: Vec<PushPreview>,
     in_flight: bool,
 }
+
+// Sender and subject of a delivered message, only pushed to subscriptions
+// with encryption keys that opted in with the "preview" property.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushPreview {
+    pub account_id: Id,
+    pub email_id: Id,
+    pub from: String,
+    pub subject: String,
+}
+
+impl PushPreview {
+    pub fn new(message: &Message<'_>, sender: &str) -> Self {
+        let from = message
+            .from()
+            .and_then(|from| from.first())
+            .map(|addr| match (addr.name(), addr.address()) {
+                (Some(name), Some(address)) => format!("{name} <{address}>"),
+                (None, Some(address)) => address.to_string(),
+                (Some(name), None) => name.to_string(),
+                (None, None) => sender.to_string(),
+            })
+            .unwrap_or_else(|| sender.to_string());
+
+        PushPreview {
+            account_id: Id::default(),
+            email_id: Id::default(),
+            from: truncate_preview(from),
+            subject: truncate_preview(message.subject().unwrap_or_default().to_string()),
+        }
+    }
+
+    pub fn with_email_id(mut self, account_id: u32, email_id: Id) -> Self {
+        self.account_id = Id::from(account_id);
+        self.email_id = email_id;
+        self
+    }
+}
+
+fn truncate_preview(mut text: String) -> String {
+    if text.len() > PREVIEW_MAX_LEN {
+        let mut end = PREVIEW_MAX_LEN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push('…');
+    }
+    text
+}
@@ -97,6 +97,10 @@ impl JMAP {
                 );
                 continue 'create;
             }
+            if let Err(err) = validate_push_preview(&push) {
+                response.not_created.append(id, err);
+                continue 'create;
+            }
 
             // Add expiry time if missing
             let expires = if let Some(expires) = push.properties.get(&Property::Expires) {
@@ -182,6 +186,10 @@ impl JMAP {
                     }
                 };
             }
+            if let Err(err) = validate_push_preview(&push) {
+                response.not_updated.append(id, err);
+                continue 'update;
+            }
 
             // Update record
             let mut batch = BatchBuilder::new();
@@ -204,7 +212,8 @@ impl JMAP {
                     .with_account_id(account_id)
                     .with_collection(Collection::PushSubscription)
                     .delete_document(document_id)
-                    .value(Property::Value, (), F_VALUE | F_CLEAR);
+                    .value(Property::Value, (), F_VALUE | F_CLEAR)
+                    .value(Property::DeliveryStats, (), F_VALUE | F_CLEAR);
                 self.write_batch(batch).await?;
                 response.destroyed.push(id);
             } else {
@@ -288,8 +297,9 @@ fn validate_push_value(
                     .with_description("Verification code does not match.".to_string()));
             }
         }
+        (Property::Preview, MaybePatchValue::Value(Value::Bool(value))) => Value::Bool(value),
         (
-            Property::Keys | Property::Types | Property::VerificationCode,
+            Property::Keys | Property::Types | Property::VerificationCode | Property::Preview,
             MaybePatchValue::Value(Value::Null),
         ) => Value::Null,
         (property, _) => {
@@ -299,3 +309,16 @@ fn validate_push_value(
         }
     })
 }
+
+// Previews include message contents, so they are only pushed encrypted
+fn validate_push_preview(push: &Object<Value>) -> Result<(), SetError> {
+    if matches!(push.get(&Property::Preview), Value::Bool(true))
+        && !push.properties.contains_key(&Property::Keys)
+    {
+        Err(SetError::invalid_properties()
+            .with_property(Property::Preview)
+            .with_description("Previews require encryption keys."))
+    } else {
+        Ok(())
+    }
+}
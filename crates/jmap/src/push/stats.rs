@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{collection::Collection, date::UTCDate, id::Id, property::Property, value::Value},
+};
+use serde_json::json;
+use store::write::{now, BatchBuilder, F_VALUE};
+
+use crate::{Bincode, JMAP};
+
+// Delivery history of a push subscription, kept apart from the subscription
+// object so that recording an attempt never races with PushSubscription/set.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PushStats {
+    pub delivered: u64,
+    pub failed: u64,
+    pub last_success: Option<u64>,
+    pub last_failure: Option<u64>,
+    pub last_failure_reason: Option<String>,
+}
+
+impl JMAP {
+    pub async fn push_stats(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<PushStats, MethodError> {
+        self.get_property::<Bincode<PushStats>>(
+            account_id,
+            Collection::PushSubscription,
+            document_id,
+            Property::DeliveryStats,
+        )
+        .await
+        .map(|stats| stats.map(|stats| stats.inner).unwrap_or_default())
+    }
+
+    // Records the outcome of a push request, failures include the reason.
+    pub async fn push_stats_update(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        failure: Option<String>,
+    ) -> Result<(), MethodError> {
+        // The subscription might have been destroyed while the request was in flight
+        if !self
+            .get_document_ids(account_id, Collection::PushSubscription)
+            .await?
+            .map_or(false, |ids| ids.contains(document_id))
+        {
+            return Ok(());
+        }
+
+        let mut stats = self.push_stats(account_id, document_id).await?;
+        if let Some(reason) = failure {
+            stats.failed += 1;
+            stats.last_failure = now().into();
+            stats.last_failure_reason = reason.into();
+        } else {
+            stats.delivered += 1;
+            stats.last_success = now().into();
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::PushSubscription)
+            .update_document(document_id)
+            .value(Property::DeliveryStats, Bincode::new(stats), F_VALUE);
+        self.write_batch(batch).await
+    }
+
+    // Summarizes the push subscriptions of an account, used by the sessions
+    // and management endpoints.
+    pub async fn push_subscription_summaries(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<serde_json::Value>, MethodError> {
+        let mut summaries = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::PushSubscription)
+            .await?
+            .unwrap_or_default()
+        {
+            if let Some(mut subscription) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::PushSubscription,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                let stats = self.push_stats(account_id, document_id).await?;
+
+                // Only the host is disclosed as push URLs act as credentials
+                let host = subscription
+                    .properties
+                    .get(&Property::Url)
+                    .and_then(|value| value.as_string())
+                    .and_then(|url| reqwest::Url::parse(url).ok())
+                    .and_then(|url| url.host_str().map(|host| host.to_string()))
+                    .unwrap_or_default();
+
+                summaries.push(json!({
+                    "id": Id::from(document_id).to_string(),
+                    "deviceClientId": subscription
+                        .properties
+                        .remove(&Property::DeviceClientId)
+                        .and_then(|value| value.try_unwrap_string())
+                        .unwrap_or_default(),
+                    "host": host,
+                    "expires": subscription
+                        .properties
+                        .get(&Property::Expires)
+                        .and_then(|value| value.as_date())
+                        .map_or(0, |date| date.timestamp() as u64),
+                    "verified": subscription
+                        .properties
+                        .contains_key(&Property::VerificationCode),
+                    "delivered": stats.delivered,
+                    "failed": stats.failed,
+                    "lastSuccess": stats.last_success,
+                    "lastFailure": stats.last_failure,
+                    "lastFailureReason": stats.last_failure_reason,
+                }));
+            }
+        }
+
+        Ok(summaries)
+    }
+}
+
+impl From<PushStats> for Value {
+    fn from(stats: PushStats) -> Self {
+        Value::Object(
+            Object::with_capacity(5)
+                .with_property(Property::DeliveredCount, stats.delivered)
+                .with_property(Property::FailedCount, stats.failed)
+                .with_property(
+                    Property::LastSuccessAt,
+                    stats.last_success.map_or(Value::Null, |timestamp| {
+                        Value::Date(UTCDate::from_timestamp(timestamp as i64))
+                    }),
+                )
+                .with_property(
+                    Property::LastFailureAt,
+                    stats.last_failure.map_or(Value::Null, |timestamp| {
+                        Value::Date(UTCDate::from_timestamp(timestamp as i64))
+                    }),
+                )
+                .with_property(
+                    Property::LastFailureReason,
+                    stats.last_failure_reason.map_or(Value::Null, Value::Text),
+                ),
+        )
+    }
+}
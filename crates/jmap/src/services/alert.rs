@@ -0,0 +1,459 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use mail_builder::MessageBuilder;
+use mail_send::{
+    smtp::{message::Message, tls::build_tls_connector},
+    Credentials, SmtpClientBuilder,
+};
+use serde_json::json;
+use smtp::core::webhook::Webhook;
+use store::{ahash::AHashMap, write::now};
+use utils::config::Config;
+
+use crate::JMAP;
+
+pub struct AlertConfig {
+    pub interval: Duration,
+    pub source: String,
+    pub certificate_warn_before: Duration,
+    // Certificates are read again on every check so that renewals are noticed
+    pub certificates: Config,
+    pub email: Option<AlertEmail>,
+    pub webhook: Option<AlertWebhook>,
+}
+
+// Alert e-mails are sent directly to a relay rather than through the local
+// queue, which might be the very component that is failing.
+pub struct AlertEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub relay: SmtpClientBuilder<String>,
+    pub tls: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertWebhook {
+    pub url: String,
+    pub routing_key: String,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Critical,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub severity: Severity,
+    pub summary: String,
+    pub since: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub key: String,
+    pub alert: Alert,
+    pub is_resolved: bool,
+}
+
+// Alerts are only notified when an incident starts and when it is resolved,
+// incidents that persist across checks are not notified again.
+#[derive(Debug, Default)]
+pub struct Incidents {
+    active: AHashMap<String, Alert>,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+pub fn spawn_alert_manager(core: Arc<JMAP>) {
+    if core.config.alerts.is_none() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        tracing::debug!("Alert manager started.");
+
+        let mut incidents = Incidents::default();
+        let config = core.config.alerts.as_ref().unwrap();
+
+        loop {
+            tokio::time::sleep(config.interval).await;
+
+            for notification in incidents.update(core.check_alerts(config).await) {
+                config.dispatch(&notification).await;
+            }
+        }
+    });
+}
+
+impl Incidents {
+    // Returns the notifications to send for the incidents that started or
+    // were resolved since the previous check
+    pub fn update(&mut self, failing: AHashMap<String, Alert>) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        for (key, alert) in std::mem::take(&mut self.active) {
+            if !failing.contains_key(&key) {
+                notifications.push(Notification {
+                    key,
+                    alert,
+                    is_resolved: true,
+                });
+            } else {
+                self.active.insert(key, alert);
+            }
+        }
+        for (key, alert) in failing {
+            if !self.active.contains_key(&key) {
+                notifications.push(Notification {
+                    key: key.clone(),
+                    alert: alert.clone(),
+                    is_resolved: false,
+                });
+                self.active.insert(key, alert);
+            }
+        }
+        notifications
+    }
+
+    pub fn is_active(&self, key: &str) -> bool {
+        self.active.contains_key(key)
+    }
+}
+
+impl JMAP {
+    pub async fn check_alerts(&self, config: &AlertConfig) -> AHashMap<String, Alert> {
+        let mut failing = AHashMap::new();
+        let since = now();
+
+        for (key, name, result) in [
+            ("store", "Data store", self.check_store().await),
+            ("blob-store", "Blob store", self.check_blob_store().await),
+            ("directory", "Directory", self.check_directory().await),
+            ("queue", "Queue", self.check_queue().await),
+        ] {
+            if let Err(result) = result {
+                failing.insert(
+                    key.to_string(),
+                    Alert {
+                        severity: Severity::Critical,
+                        summary: format!(
+                            "{name} is unhealthy: {}",
+                            result["error"].as_str().unwrap_or("unknown error")
+                        ),
+                        since,
+                    },
+                );
+            }
+        }
+
+        failing.extend(config.check_certificates(since).await);
+
+        failing
+    }
+}
+
+impl AlertConfig {
+    pub async fn check_certificates(&self, now: u64) -> Vec<(String, Alert)> {
+        let certificates = self.certificates.clone();
+        let expirations = tokio::task::spawn_blocking(move || {
+            certificates
+                .sub_keys("certificate")
+                .map(|id| {
+                    (
+                        id.to_string(),
+                        certificates.rustls_certificate(id).and_then(|certs| {
+                            certs
+                                .first()
+                                .and_then(|cert| {
+                                    rasn::der::decode::<rasn_pkix::Certificate>(cert.as_ref()).ok()
+                                })
+                                .map(|cert| match cert.tbs_certificate.validity.not_after {
+                                    rasn_pkix::Time::Utc(time) => time.timestamp(),
+                                    rasn_pkix::Time::General(time) => time.timestamp(),
+                                })
+                                .ok_or_else(|| "Failed to decode certificate.".to_string())
+                        }),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        let warn_after = now as i64 + self.certificate_warn_before.as_secs() as i64;
+        let mut alerts = Vec::new();
+        for (id, expires) in expirations {
+            let summary = match expires {
+                Ok(expires) if expires > warn_after => continue,
+                Ok(expires) if expires >= now as i64 => {
+                    format!(
+                        "Certificate {id:?} expires in {} day(s).",
+                        (expires - now as i64) / 86400
+                    )
+                }
+                Ok(_) => format!("Certificate {id:?} has expired."),
+                Err(err) => format!("Certificate {id:?} could not be read: {err}"),
+            };
+            alerts.push((
+                format!("certificate.{id}"),
+                Alert {
+                    severity: Severity::Warning,
+                    summary,
+                    since: now,
+                },
+            ));
+        }
+
+        alerts
+    }
+
+    pub async fn dispatch(&self, notification: &Notification) {
+        let key = notification.key.as_str();
+        let alert = &notification.alert;
+
+        if notification.is_resolved {
+            tracing::info!(
+                context = "alert",
+                event = "resolve",
+                alert = key,
+                summary = alert.summary.as_str(),
+                "Incident resolved."
+            );
+        } else {
+            tracing::warn!(
+                context = "alert",
+                event = "trigger",
+                alert = key,
+                severity = alert.severity.as_str(),
+                summary = alert.summary.as_str(),
+                "Incident detected."
+            );
+        }
+
+        if let Some(email) = &self.email {
+            if let Err(err) = email.send(&self.source, notification).await {
+                tracing::warn!(
+                    context = "alert",
+                    event = "error",
+                    alert = key,
+                    reason = err.as_str(),
+                    "Failed to send alert e-mail."
+                );
+            }
+        }
+
+        if let Some(webhook) = &self.webhook {
+            if let Err(err) = webhook.send(&self.source, notification).await {
+                tracing::warn!(
+                    context = "alert",
+                    event = "error",
+                    alert = key,
+                    reason = err.as_str(),
+                    "Failed to send alert webhook."
+                );
+            }
+        }
+    }
+}
+
+impl AlertEmail {
+    pub async fn send(&self, source: &str, notification: &Notification) -> Result<(), String> {
+        let key = notification.key.as_str();
+        let alert = &notification.alert;
+        let (subject, body) = if notification.is_resolved {
+            (
+                format!("[RESOLVED] {}: {}", source, alert.summary),
+                format!(
+                    "The following incident on {} has been resolved:\r\n\r\n{}\r\n\r\nAlert: {key}\r\nDuration: {} second(s)\r\n",
+                    source,
+                    alert.summary,
+                    now().saturating_sub(alert.since)
+                ),
+            )
+        } else {
+            (
+                format!(
+                    "[{}] {}: {}",
+                    alert.severity.as_str().to_uppercase(),
+                    source,
+                    alert.summary
+                ),
+                format!(
+                    "The following incident was detected on {}:\r\n\r\n{}\r\n\r\nAlert: {key}\r\nSeverity: {}\r\n",
+                    source,
+                    alert.summary,
+                    alert.severity.as_str()
+                ),
+            )
+        };
+        let message = Message::new(
+            self.from.as_str(),
+            self.to.iter().map(|addr| addr.as_str()),
+            MessageBuilder::new()
+                .from(self.from.as_str())
+                .to(self.to.iter().map(|addr| addr.as_str()).collect::<Vec<_>>())
+                .subject(subject)
+                .text_body(body)
+                .write_to_vec()
+                .map_err(|err| err.to_string())?,
+        );
+
+        if self.tls {
+            let mut client = self.relay.connect().await.map_err(|err| err.to_string())?;
+            client.send(message).await.map_err(|err| err.to_string())?;
+            client.quit().await
+        } else {
+            let mut client = self
+                .relay
+                .connect_plain()
+                .await
+                .map_err(|err| err.to_string())?;
+            client.send(message).await.map_err(|err| err.to_string())?;
+            client.quit().await
+        }
+        .map_err(|err| err.to_string())
+    }
+}
+
+impl AlertWebhook {
+    // Posts a PagerDuty Events API v2 compatible payload, the alert key is used
+    // as deduplication key so that the resolve event closes the right incident.
+    pub async fn send(&self, source: &str, notification: &Notification) -> Result<(), String> {
+        Webhook::post(&self.url)
+            .with_timeout(self.timeout)
+            .send(
+                json!({
+                    "routing_key": self.routing_key,
+                    "event_action": if notification.is_resolved { "resolve" } else { "trigger" },
+                    "dedup_key": format!("{}/{}", source, notification.key),
+                    "payload": {
+                        "summary": notification.alert.summary,
+                        "source": source,
+                        "severity": notification.alert.severity.as_str(),
+                        "component": notification.key,
+                    },
+                })
+                .to_string(),
+            )
+            .await
+    }
+}
+
+pub fn parse_alerts(config: &Config) -> utils::config::Result<Option<AlertConfig>> {
+    let to = config
+        .values("jmap.alerts.email.to")
+        .map(|(_, addr)| addr.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    let hostname = config
+        .value("server.hostname")
+        .unwrap_or("localhost")
+        .to_string();
+    let email = if !to.is_empty() {
+        let address = config.value_require("jmap.alerts.email.relay.address")?;
+        let tls_implicit: bool =
+            config.property_or_static("jmap.alerts.email.relay.tls.implicit", "false")?;
+        let port: u16 = config.property_or_static(
+            "jmap.alerts.email.relay.port",
+            if tls_implicit { "465" } else { "25" },
+        )?;
+        let credentials = config
+            .value("jmap.alerts.email.relay.auth.username")
+            .map(|username| {
+                config
+                    .value_require("jmap.alerts.email.relay.auth.secret")
+                    .map(|secret| Credentials::Plain {
+                        username: username.to_string(),
+                        secret: secret.to_string(),
+                    })
+            })
+            .transpose()?;
+
+        AlertEmail {
+            from: config.value_require("jmap.alerts.email.from")?.to_string(),
+            to,
+            relay: SmtpClientBuilder {
+                addr: format!("{address}:{port}"),
+                timeout: config.property_or_static("jmap.alerts.email.relay.timeout", "30s")?,
+                tls_connector: build_tls_connector(config.property_or_static(
+                    "jmap.alerts.email.relay.tls.allow-invalid-certs",
+                    "false",
+                )?),
+                tls_hostname: address.to_string(),
+                tls_implicit,
+                is_lmtp: false,
+                credentials,
+                local_host: hostname.clone(),
+            },
+            tls: config.property_or_static("jmap.alerts.email.relay.tls.enable", "true")?,
+        }
+        .into()
+    } else {
+        None
+    };
+    let webhook = if let Some(url) = config.value("jmap.alerts.webhook.url") {
+        AlertWebhook {
+            url: url.to_string(),
+            routing_key: config
+                .value("jmap.alerts.webhook.routing-key")
+                .unwrap_or_default()
+                .to_string(),
+            timeout: config.property_or_static("jmap.alerts.webhook.timeout", "10s")?,
+        }
+        .into()
+    } else {
+        None
+    };
+    if email.is_none() && webhook.is_none() {
+        return Ok(None);
+    }
+
+    // Keep the certificate sources only, they are read again on every check
+    let mut certificates = Config::default();
+    for (key, value) in config.keys.iter() {
+        if key.starts_with("certificate.") && key.ends_with(".cert") {
+            certificates.keys.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    Ok(Some(AlertConfig {
+        interval: config.property_or_static("jmap.alerts.interval", "1m")?,
+        source: hostname,
+        certificate_warn_before: config
+            .property_or_static("jmap.alerts.certificate.warn-before", "14d")?,
+        certificates,
+        email,
+        webhook,
+    }))
+}
@@ -21,12 +21,19 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc, time::Instant};
 
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
 use tokio::sync::mpsc;
-use utils::ipc::DeliveryEvent;
+use utils::ipc::{DeliveryEvent, RecipientStatus};
 
-use crate::JMAP;
+use crate::{
+    auth::rate_limit::RemoteAddress,
+    submission::status::{STATUS_MAX_RETRIES, STATUS_RETRY_DELAY},
+    JMAP,
+};
+
+use super::report::TrafficDirection;
 
 pub fn spawn_delivery_manager(core: Arc<JMAP>, mut delivery_rx: mpsc::Receiver<DeliveryEvent>) {
     tokio::spawn(async move {
@@ -35,8 +42,169 @@ pub fn spawn_delivery_manager(core: Arc<JMAP>, mut delivery_rx: mpsc::Receiver<D
                 DeliveryEvent::Ingest { message, result_tx } => {
                     result_tx.send(core.deliver_message(message).await).ok();
                 }
-                DeliveryEvent::Stop => break,
+                DeliveryEvent::Stop => {
+                    // Finish any queued deliveries before stopping
+                    delivery_rx.close();
+                }
+                event => {
+                    tracing::debug!(
+                        context = "delivery",
+                        event = "error",
+                        "Unexpected event {event:?} on the delivery channel."
+                    );
+                }
+            }
+        }
+    });
+}
+
+// Status updates whose submission could not be found yet, retried with
+// exponential backoff while keeping the updates of a message in order.
+struct PendingStatus {
+    queue_id: u64,
+    return_path: String,
+    updates: VecDeque<Vec<RecipientStatus>>,
+    attempt: u32,
+    due: Instant,
+}
+
+pub fn spawn_event_manager(core: Arc<JMAP>, mut event_rx: mpsc::Receiver<DeliveryEvent>) {
+    tokio::spawn(async move {
+        let mut pending: Vec<PendingStatus> = Vec::new();
+
+        loop {
+            let event = if let Some(due) = pending.iter().map(|status| status.due).min() {
+                match tokio::time::timeout_at(due.into(), event_rx.recv()).await {
+                    Ok(Some(event)) => Some(event),
+                    Ok(None) => break,
+                    Err(_) => None,
+                }
+            } else if let Some(event) = event_rx.recv().await {
+                Some(event)
+            } else {
+                break;
+            };
+
+            match event {
+                Some(DeliveryEvent::Status {
+                    queue_id,
+                    return_path,
+                    recipients,
+                }) => {
+                    if let Some(status) = pending
+                        .iter_mut()
+                        .find(|status| status.queue_id == queue_id)
+                    {
+                        status.updates.push_back(recipients);
+                    } else if !core
+                        .delivery_status_update(queue_id, &return_path, &recipients)
+                        .await
+                    {
+                        // The submission might not have been stored yet
+                        pending.push(PendingStatus {
+                            queue_id,
+                            return_path,
+                            updates: VecDeque::from([recipients]),
+                            attempt: 0,
+                            due: Instant::now() + STATUS_RETRY_DELAY,
+                        });
+                    }
+                }
+                Some(DeliveryEvent::Submission {
+                    account,
+                    domain,
+                    size,
+                }) => {
+                    // Messages submitted over SMTP by authenticated users
+                    if let Ok(Some(account_id)) = core.store.get_account_id(&account).await {
+                        core.traffic_record(TrafficDirection::Outbound, account_id, &domain, size)
+                            .await;
+                    }
+                }
+                Some(DeliveryEvent::Login {
+                    account_id,
+                    remote_ip,
+                }) => {
+                    // Logins over SMTP AUTH, tracked in the background to avoid
+                    // delaying other events
+                    let core = core.clone();
+                    tokio::spawn(async move {
+                        if let Ok(Some(principal)) =
+                            core.directory.query(QueryBy::Id(account_id), false).await
+                        {
+                            core.track_login(
+                                &principal,
+                                &RemoteAddress::IpAddress(remote_ip),
+                                "SMTP",
+                            )
+                            .await;
+                        }
+                    });
+                }
+                Some(DeliveryEvent::SendAs {
+                    account_id,
+                    result_tx,
+                }) => {
+                    let core = core.clone();
+                    tokio::spawn(async move {
+                        result_tx
+                            .send(core.send_as_addresses(account_id).await)
+                            .ok();
+                    });
+                }
+                Some(DeliveryEvent::Ingest { message, result_tx }) => {
+                    result_tx.send(core.deliver_message(message).await).ok();
+                }
+                Some(DeliveryEvent::Stop) => {
+                    event_rx.close();
+                }
+                None => {
+                    // Retry the status updates that are due
+                    let now = Instant::now();
+                    let mut idx = 0;
+                    while idx < pending.len() {
+                        if pending[idx].due <= now && pending[idx].retry(&core).await {
+                            pending.swap_remove(idx);
+                        } else {
+                            idx += 1;
+                        }
+                    }
+                }
             }
         }
     });
 }
+
+impl PendingStatus {
+    // Returns true once all updates were applied or the retries are exhausted
+    async fn retry(&mut self, core: &JMAP) -> bool {
+        while let Some(recipients) = self.updates.front() {
+            if core
+                .delivery_status_update(self.queue_id, &self.return_path, recipients)
+                .await
+            {
+                self.updates.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.updates.is_empty() {
+            return true;
+        }
+
+        self.attempt += 1;
+        if self.attempt < STATUS_MAX_RETRIES {
+            self.due = Instant::now() + STATUS_RETRY_DELAY * 2u32.pow(self.attempt);
+            false
+        } else {
+            tracing::debug!(
+                context = "delivery_status",
+                event = "error",
+                queue_id = self.queue_id,
+                return_path = self.return_path,
+                "No submission found for delivery status update."
+            );
+            true
+        }
+    }
+}
@@ -0,0 +1,980 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{io::Write, sync::Arc};
+
+use directory::QueryBy;
+use http_body_util::{combinators::BoxBody, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    header, Method, StatusCode,
+};
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    method::get::{GetRequest, RequestArguments},
+    object::Object,
+    request::reference::MaybeReference,
+    types::{
+        any_id::AnyId, collection::Collection, id::Id, keyword::Keyword, property::Property,
+        value::Value,
+    },
+};
+use openpgp::{serialize::stream, types::SymmetricAlgorithm};
+use sequoia_openpgp as openpgp;
+use serde_json::json;
+use store::{
+    blake3,
+    parking_lot::Mutex,
+    write::{key::KeySerializer, now, BatchBuilder, BlobOp, ValueClass},
+    BlobHash, LookupValue, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    auth::{oauth::grant::OAuthGrant, AccessToken},
+    email::metadata::MessageMetadata,
+    mailbox::UidMailbox,
+    Bincode, JMAP,
+};
+
+use super::jobs::{JobContext, JobKind};
+
+const EXPORT_KEY_PREFIX: &[u8] = b"export.";
+
+// The encrypted archive is stored as a sequence of blobs of this size
+const EXPORT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccountExport {
+    pub id: u64,
+    pub account_id: u32,
+    pub account: String,
+    pub requested_by: String,
+    pub created_at: u64,
+    pub expires: u64,
+    pub status: ExportStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ExportStatus {
+    Running { progress: u8 },
+    Completed { parts: Vec<BlobHash>, size: usize },
+    Failed { reason: String },
+}
+
+#[derive(Debug)]
+pub struct ExportFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Destination of the files collected by `account_export_files`.
+#[async_trait::async_trait]
+pub trait ExportTarget: Send {
+    async fn add_file(&mut self, path: String, contents: Vec<u8>) -> Result<(), MethodError>;
+}
+
+/// Encrypted tar archive written to the blob store while it is being built.
+pub struct ExportArchive<'x> {
+    archive: tar::Builder<stream::Message<'static>>,
+    mtime: u64,
+    parts: ExportParts<'x>,
+}
+
+// Blobs holding the encrypted output written so far
+struct ExportParts<'x> {
+    jmap: &'x JMAP,
+    account_id: u32,
+    expires: u64,
+    output: Arc<Mutex<Vec<u8>>>,
+    hashes: Vec<BlobHash>,
+    size: usize,
+}
+
+struct ExportSink(Arc<Mutex<Vec<u8>>>);
+
+struct ExportFiles<'x, T: ExportTarget> {
+    target: &'x mut T,
+    manifest: Vec<serde_json::Value>,
+}
+
+impl JMAP {
+    pub async fn handle_account_export(
+        &self,
+        req: &HttpRequest,
+        account_id: u32,
+        account_name: &str,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        // Path is /admin/export/{account}/{export_id}/{action}
+        let method = req.method();
+        let mut path = req.uri().path().split('/').skip(4);
+        let export_id = path.next().filter(|id| !id.is_empty());
+        let action = path.next();
+        let export_id = match export_id.map(|id| id.parse::<u64>()) {
+            Some(Ok(export_id)) => Some(export_id),
+            None => None,
+            Some(Err(_)) => return RequestError::not_found().into_http_response(),
+        };
+
+        match (method, export_id, action) {
+            (&Method::POST, None, None) => {
+                // The archive is always encrypted with an admin supplied password
+                let password = match serde_json::from_slice::<serde_json::Value>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .ok()
+                .and_then(|body| body.get("password")?.as_str().map(|p| p.to_string()))
+                {
+                    Some(password) if !password.is_empty() => password,
+                    _ => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "A password is required to encrypt the export.",
+                        )
+                        .into_http_response();
+                    }
+                };
+
+                match self
+                    .account_export_start(account_id, account_name, password, &access_token.name)
+                    .await
+                {
+                    Ok(export) => JsonResponse::new(json!({
+                        "data": export.into_json(),
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to start export",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            (&Method::GET | &Method::DELETE, Some(export_id), action) => {
+                let export = match self.account_export(account_id, export_id).await {
+                    Ok(Some(export)) => export,
+                    Ok(None) => return RequestError::not_found().into_http_response(),
+                    Err(err) => {
+                        return RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Failed to obtain export",
+                            err.to_string(),
+                        )
+                        .into_http_response();
+                    }
+                };
+
+                match (method, action) {
+                    (&Method::GET, None) => JsonResponse::new(json!({
+                        "data": export.into_json(),
+                    }))
+                    .into_http_response(),
+                    (&Method::GET, Some("archive")) => {
+                        let (parts, size) = match export.status {
+                            ExportStatus::Completed { parts, size } => (parts, size),
+                            _ => {
+                                return RequestError::blank(
+                                    StatusCode::CONFLICT.as_u16(),
+                                    "Export not ready",
+                                    "The export has not completed yet.",
+                                )
+                                .into_http_response();
+                            }
+                        };
+
+                        // Parts are fetched one at a time while the response is sent
+                        let blob_store = self.blob_store.clone();
+                        let filename = format!("{}-{}.tar.pgp", export.account, export.id);
+                        let (tx, mut rx) = tokio::sync::mpsc::channel::<Bytes>(1);
+                        tokio::spawn(async move {
+                            for hash in parts {
+                                match blob_store.get_blob(hash.as_ref(), 0..u32::MAX).await {
+                                    Ok(Some(part)) => {
+                                        if tx.send(Bytes::from(part)).await.is_err() {
+                                            break;
+                                        }
+                                    }
+                                    result => {
+                                        tracing::warn!(
+                                            context = "export",
+                                            event = "error",
+                                            account = %export.account,
+                                            export_id = export.id,
+                                            reason = ?result.err(),
+                                            "Failed to read export archive part."
+                                        );
+                                        break;
+                                    }
+                                }
+                            }
+                        });
+
+                        hyper::Response::builder()
+                            .status(StatusCode::OK)
+                            .header(header::CONTENT_TYPE, "application/pgp-encrypted")
+                            .header(header::CONTENT_LENGTH, size)
+                            .header(
+                                header::CONTENT_DISPOSITION,
+                                format!("attachment; filename=\"{filename}\""),
+                            )
+                            .body(BoxBody::new(StreamBody::new(async_stream::stream! {
+                                while let Some(part) = rx.recv().await {
+                                    yield Ok(Frame::data(part));
+                                }
+                            })))
+                            .unwrap()
+                    }
+                    (&Method::DELETE, None) => {
+                        if matches!(export.status, ExportStatus::Running { .. }) {
+                            self.account_export_cancel(&export, &access_token.name)
+                                .await;
+                        }
+                        match self.account_export_delete(&export).await {
+                            Ok(_) => JsonResponse::new(json!({
+                                "data": (),
+                            }))
+                            .into_http_response(),
+                            Err(err) => RequestError::blank(
+                                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                                "Failed to delete export",
+                                err.to_string(),
+                            )
+                            .into_http_response(),
+                        }
+                    }
+                    _ => RequestError::not_found().into_http_response(),
+                }
+            }
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    pub async fn account_export_start(
+        &self,
+        account_id: u32,
+        account_name: &str,
+        password: String,
+        requested_by: &str,
+    ) -> Result<AccountExport, MethodError> {
+        let created_at = now();
+        let export = AccountExport {
+            id: self.generate_snowflake_id()?,
+            account_id,
+            account: account_name.to_string(),
+            requested_by: requested_by.to_string(),
+            created_at,
+            expires: created_at + self.config.export_expiry.as_secs(),
+            status: ExportStatus::Running { progress: 0 },
+        };
+        self.account_export_set(&export)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?;
+
+        tracing::info!(
+            context = "audit",
+            event = "export-start",
+            admin = requested_by,
+            account = account_name,
+            export_id = export.id,
+            "Account export started."
+        );
+
+        // The archive is assembled by a background job
+        self.job_submit(
+            JobKind::AccountExport {
+                account_id,
+                export_id: export.id,
+                password: self.job_secret_encrypt(&password)?,
+            },
+            requested_by,
+            None,
+        )
+        .await?;
+
+        Ok(export)
+    }
+
+    pub async fn account_export_run(
+        &self,
+        account_id: u32,
+        export_id: u64,
+        password: String,
+        ctx: &JobContext,
+    ) -> Result<(), String> {
+        let mut export = match self.account_export(account_id, export_id).await {
+            Ok(Some(export)) => export,
+            Ok(None) => return Err("Export no longer exists.".to_string()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let result = match ExportArchive::new(self, &export, &password) {
+            Ok(mut archive) => match self
+                .account_export_files(&mut export, ctx, &mut archive)
+                .await
+            {
+                Ok(_) => archive
+                    .finish()
+                    .await
+                    .map(|(parts, size)| ExportStatus::Completed { parts, size })
+                    .map_err(|err| err.to_string()),
+                Err(err) => {
+                    archive.discard().await;
+                    if ctx.is_cancelled() {
+                        Err("Export was cancelled.".to_string())
+                    } else {
+                        Err(err.to_string())
+                    }
+                }
+            },
+            Err(err) => Err(err),
+        };
+
+        export.status = match &result {
+            Ok(status) => {
+                tracing::info!(
+                    context = "audit",
+                    event = "export-done",
+                    account = %export.account,
+                    export_id = export.id,
+                    "Account export completed."
+                );
+                status.clone()
+            }
+            Err(reason) => {
+                tracing::warn!(
+                    context = "export",
+                    event = "error",
+                    account = %export.account,
+                    export_id = export.id,
+                    reason = %reason,
+                    "Account export failed."
+                );
+                ExportStatus::Failed {
+                    reason: reason.clone(),
+                }
+            }
+        };
+        // Exports deleted while running are not written back
+        if matches!(self.account_export(account_id, export_id).await, Ok(None)) {
+            return result.map(|_| ());
+        }
+        if let Err(err) = self.account_export_set(&export).await {
+            tracing::warn!(
+                context = "export",
+                event = "error",
+                account = %export.account,
+                export_id = export.id,
+                reason = ?err,
+                "Failed to update export status."
+            );
+        }
+
+        result.map(|_| ())
+    }
+
+    /// Passes everything stored for the account to the target one file at a
+    /// time, updating the export and job progress as messages are added.
+    pub async fn account_export_files(
+        &self,
+        export: &mut AccountExport,
+        ctx: &JobContext,
+        target: &mut impl ExportTarget,
+    ) -> Result<(), MethodError> {
+        let account_id = export.account_id;
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .ok_or(MethodError::ServerPartialFail)?;
+        let mut files = ExportFiles {
+            target,
+            manifest: Vec::new(),
+        };
+
+        // Account details, trusted devices, sign-in grants and recovery settings
+        let principal = self
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?
+            .ok_or(MethodError::ServerPartialFail)?;
+        let devices = self
+            .trusted_devices(account_id)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?;
+        let grants = self
+            .oauth_grants(account_id)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?;
+        files
+            .add_json(
+                "account.json",
+                &json!({
+                    "id": account_id,
+                    "name": principal.name,
+                    "description": principal.description,
+                    "emails": principal.emails,
+                    "quota": principal.quota,
+                    "recoveryAddress": self.recovery_address(account_id).await.unwrap_or_default(),
+                }),
+            )
+            .await?;
+        files
+            .add_json(
+                "login_history.json",
+                &json!({
+                    "trustedDevices": devices,
+                    "oauthGrants": grants
+                        .grants
+                        .into_iter()
+                        .map(OAuthGrant::into_json)
+                        .collect::<Vec<_>>(),
+                }),
+            )
+            .await?;
+
+        // JMAP objects other than emails
+        for (collection, name) in [
+            (Collection::Mailbox, "mailboxes.json"),
+            (Collection::Identity, "identities.json"),
+            (Collection::EmailSubmission, "submissions.json"),
+            (Collection::SieveScript, "sieve_scripts.json"),
+            (Collection::PushSubscription, "push_subscriptions.json"),
+            (Collection::MaskedEmail, "masked_emails.json"),
+            (Collection::ListFiling, "list_filing.json"),
+            (Collection::Label, "labels.json"),
+        ] {
+            let objects = self
+                .account_export_objects(account_id, collection, &access_token)
+                .await?;
+
+            // Include the source of each Sieve script
+            if collection == Collection::SieveScript {
+                for object in &objects {
+                    if let (Some(Value::Id(id)), Some(Value::BlobId(blob_id))) = (
+                        object.properties.get(&Property::Id),
+                        object.properties.get(&Property::BlobId),
+                    ) {
+                        let script = if let Some(section) = &blob_id.section {
+                            self.get_blob_section(&blob_id.hash, section).await?
+                        } else {
+                            self.get_blob(&blob_id.hash, 0..u32::MAX).await?
+                        };
+                        if let Some(script) = script {
+                            files.add(format!("sieve/{id}.sieve"), script).await?;
+                        }
+                    }
+                }
+            }
+
+            files.add_json(name, &objects).await?;
+        }
+        let vacation = self
+            .vacation_response_get(GetRequest {
+                account_id: Id::from(account_id),
+                ids: None,
+                properties: None,
+                arguments: RequestArguments::VacationResponse,
+            })
+            .await?;
+        files
+            .add_json("vacation_response.json", &vacation.list)
+            .await?;
+
+        // Messages
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .unwrap_or_default();
+        let total = document_ids.len().max(1);
+        let mut messages = Vec::with_capacity(document_ids.len() as usize);
+        for (pos, document_id) in document_ids.into_iter().enumerate() {
+            if ctx.is_cancelled() {
+                return Err(MethodError::ServerPartialFail);
+            }
+            let metadata = if let Some(metadata) = self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::BodyStructure,
+                )
+                .await?
+            {
+                metadata.inner
+            } else {
+                continue;
+            };
+            let (thread_id, mailboxes, keywords) = match (
+                self.get_property::<u32>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::ThreadId,
+                )
+                .await?,
+                self.get_property::<Vec<UidMailbox>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::MailboxIds,
+                )
+                .await?,
+                self.get_property::<Vec<Keyword>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::Keywords,
+                )
+                .await?,
+            ) {
+                (Some(thread_id), Some(mailboxes), keywords) => {
+                    (thread_id, mailboxes, keywords.unwrap_or_default())
+                }
+                _ => continue,
+            };
+            let raw_message =
+                if let Some(raw_message) = self.get_blob(&metadata.blob_hash, 0..u32::MAX).await? {
+                    raw_message
+                } else {
+                    continue;
+                };
+
+            let id = Id::from_parts(thread_id, document_id);
+            let path = format!("messages/{id}.eml");
+            messages.push(json!({
+                "id": id,
+                "threadId": Id::from(thread_id),
+                "mailboxIds": mailboxes
+                    .iter()
+                    .map(|m| Id::from(m.mailbox_id))
+                    .collect::<Vec<_>>(),
+                "keywords": keywords
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect::<Vec<_>>(),
+                "receivedAt": metadata.received_at,
+                "size": metadata.size,
+                "file": path,
+            }));
+            files.add(path, raw_message).await?;
+
+            // Report progress in 5% steps
+            let progress = ((pos + 1) * 100 / total as usize).min(99) as u8;
+            if matches!(export.status, ExportStatus::Running { progress: last } if progress >= last + 5)
+            {
+                export.status = ExportStatus::Running { progress };
+                self.account_export_set(export)
+                    .await
+                    .map_err(|_| MethodError::ServerPartialFail)?;
+                self.job_progress(ctx, progress).await;
+            }
+        }
+        files.add_json("messages.json", &messages).await?;
+
+        // Manifest listing every other file with its size and BLAKE3 digest,
+        // written last as it is only complete once all files were added
+        let manifest = json!({
+            "version": 1,
+            "exportId": export.id.to_string(),
+            "account": export.account,
+            "accountId": account_id,
+            "createdAt": export.created_at,
+            "messages": messages.len(),
+            "notSupported": ["contacts", "calendars"],
+            "files": files.manifest,
+        });
+        files
+            .target
+            .add_file(
+                "manifest.json".to_string(),
+                serde_json::to_vec_pretty(&manifest).unwrap_or_default(),
+            )
+            .await
+    }
+
+    async fn account_export_objects(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        access_token: &AccessToken,
+    ) -> Result<Vec<Object<Value>>, MethodError> {
+        let document_ids = self
+            .get_document_ids(account_id, collection)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut objects = Vec::with_capacity(document_ids.len());
+
+        for ids in document_ids.chunks(self.config.get_max_objects) {
+            let request = |arguments| GetRequest {
+                account_id: Id::from(account_id),
+                ids: Some(MaybeReference::Value(
+                    ids.iter()
+                        .map(|id| MaybeReference::Value(AnyId::Id(Id::from(*id))))
+                        .collect(),
+                )),
+                properties: None,
+                arguments,
+            };
+            let response = match collection {
+                Collection::Mailbox => {
+                    self.mailbox_get(request(RequestArguments::Mailbox), access_token)
+                        .await?
+                }
+                Collection::Identity => {
+                    self.identity_get(request(RequestArguments::Identity))
+                        .await?
+                }
+                Collection::EmailSubmission => {
+                    self.email_submission_get(request(RequestArguments::EmailSubmission))
+                        .await?
+                }
+                Collection::SieveScript => {
+                    self.sieve_script_get(request(RequestArguments::SieveScript))
+                        .await?
+                }
+                Collection::PushSubscription => {
+                    self.push_subscription_get(
+                        request(RequestArguments::PushSubscription),
+                        access_token,
+                    )
+                    .await?
+                }
+                Collection::MaskedEmail => {
+                    self.masked_email_get(request(RequestArguments::MaskedEmail))
+                        .await?
+                }
+                Collection::ListFiling => {
+                    self.list_filing_get(request(RequestArguments::ListFiling))
+                        .await?
+                }
+                Collection::Label => self.label_get(request(RequestArguments::Label)).await?,
+                _ => unreachable!(),
+            };
+            objects.extend(response.list);
+        }
+
+        Ok(objects)
+    }
+
+    pub async fn account_export(
+        &self,
+        account_id: u32,
+        export_id: u64,
+    ) -> store::Result<Option<AccountExport>> {
+        if let Some(LookupValue::Value { value, .. }) = self
+            .store
+            .get_value::<LookupValue<Bincode<AccountExport>>>(ValueKey::from(ValueClass::Key(
+                export_key(account_id, export_id),
+            )))
+            .await?
+        {
+            Ok(Some(value.inner))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn account_export_set(&self, export: &AccountExport) -> store::Result<()> {
+        // Export records expire together with their archive
+        let value = Bincode::new(export.clone()).serialize();
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Key(export_key(export.account_id, export.id)),
+            KeySerializer::new(value.len() + U64_LEN)
+                .write(export.expires)
+                .write(value.as_slice())
+                .finalize(),
+        );
+        self.store.write(batch.build()).await
+    }
+
+    async fn account_export_cancel(&self, export: &AccountExport, requested_by: &str) {
+        // Stop the job building the archive
+        for job in self.job_list().await.unwrap_or_default() {
+            if matches!(job.kind, JobKind::AccountExport { export_id, .. } if export_id == export.id)
+            {
+                if let Err(err) = self.job_cancel(job.id, requested_by).await {
+                    tracing::warn!(
+                        context = "export",
+                        event = "error",
+                        account = %export.account,
+                        export_id = export.id,
+                        reason = ?err,
+                        "Failed to cancel export job."
+                    );
+                }
+            }
+        }
+    }
+
+    pub async fn account_export_delete(&self, export: &AccountExport) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Key(export_key(export.account_id, export.id)));
+        if let ExportStatus::Completed { parts, .. } = &export.status {
+            // Release the archive so it is removed on the next blob purge
+            batch.with_account_id(export.account_id);
+            for hash in parts {
+                batch.clear(BlobOp::Reserve {
+                    hash: hash.clone(),
+                    until: export.expires,
+                });
+            }
+        }
+        self.store.write(batch.build()).await
+    }
+
+    async fn account_export_release(&self, account_id: u32, expires: u64, parts: &[BlobHash]) {
+        if parts.is_empty() {
+            return;
+        }
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(account_id);
+        for hash in parts {
+            batch.clear(BlobOp::Reserve {
+                hash: hash.clone(),
+                until: expires,
+            });
+        }
+        if let Err(err) = self.store.write(batch.build()).await {
+            tracing::warn!(
+                context = "export",
+                event = "error",
+                account_id = account_id,
+                reason = ?err,
+                "Failed to release export archive parts."
+            );
+        }
+    }
+}
+
+impl AccountExport {
+    pub fn into_json(self) -> serde_json::Value {
+        let mut result = json!({
+            "id": self.id.to_string(),
+            "account": self.account,
+            "requestedBy": self.requested_by,
+            "createdAt": self.created_at,
+            "expires": self.expires,
+        });
+        let status = match self.status {
+            ExportStatus::Running { progress } => json!({
+                "status": "running",
+                "progress": progress,
+            }),
+            ExportStatus::Completed { size, .. } => json!({
+                "status": "completed",
+                "progress": 100,
+                "size": size,
+            }),
+            ExportStatus::Failed { reason } => json!({
+                "status": "failed",
+                "reason": reason,
+            }),
+        };
+        if let (Some(result), serde_json::Value::Object(status)) = (result.as_object_mut(), status)
+        {
+            result.extend(status);
+        }
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl ExportTarget for Vec<ExportFile> {
+    async fn add_file(&mut self, path: String, contents: Vec<u8>) -> Result<(), MethodError> {
+        self.push(ExportFile { path, contents });
+        Ok(())
+    }
+}
+
+impl<'x, T: ExportTarget> ExportFiles<'x, T> {
+    async fn add(&mut self, path: String, contents: Vec<u8>) -> Result<(), MethodError> {
+        self.manifest.push(json!({
+            "path": path,
+            "size": contents.len(),
+            "blake3": blake3::hash(&contents).to_hex().to_string(),
+        }));
+        self.target.add_file(path, contents).await
+    }
+
+    async fn add_json(
+        &mut self,
+        path: impl Into<String>,
+        value: &impl serde::Serialize,
+    ) -> Result<(), MethodError> {
+        self.add(
+            path.into(),
+            serde_json::to_vec_pretty(value).unwrap_or_default(),
+        )
+        .await
+    }
+}
+
+impl<'x> ExportArchive<'x> {
+    pub fn new(jmap: &'x JMAP, export: &AccountExport, password: &str) -> Result<Self, String> {
+        // Password protected OpenPGP message, can be opened with `gpg --decrypt`
+        let output = Arc::new(Mutex::new(Vec::with_capacity(EXPORT_PART_SIZE)));
+        let message = stream::Message::new(ExportSink(output.clone()));
+        let message = stream::Encryptor2::with_passwords(message, Some(password))
+            .symmetric_algo(SymmetricAlgorithm::AES256)
+            .build()
+            .map_err(|err| format!("Failed to build encryptor: {}", err))?;
+        let message = stream::LiteralWriter::new(message)
+            .build()
+            .map_err(|err| format!("Failed to create literal writer: {}", err))?;
+
+        Ok(ExportArchive {
+            archive: tar::Builder::new(message),
+            mtime: now(),
+            parts: ExportParts {
+                jmap,
+                account_id: export.account_id,
+                expires: export.expires,
+                output,
+                hashes: Vec::new(),
+                size: 0,
+            },
+        })
+    }
+
+    /// Completes the archive and returns its parts and total size.
+    pub async fn finish(self) -> Result<(Vec<BlobHash>, usize), MethodError> {
+        let mut parts = self.parts;
+        let result = match self
+            .archive
+            .into_inner()
+            .map_err(|err| format!("Failed to build archive: {}", err))
+            .and_then(|message| {
+                message
+                    .finalize()
+                    .map_err(|err| format!("Failed to finalize archive: {}", err))
+            }) {
+            Ok(_) => parts.write(true).await,
+            Err(reason) => {
+                tracing::warn!(
+                    context = "export",
+                    event = "error",
+                    account_id = parts.account_id,
+                    reason = %reason,
+                    "Failed to complete export archive."
+                );
+                Err(MethodError::ServerPartialFail)
+            }
+        };
+
+        match result {
+            Ok(_) => Ok((parts.hashes, parts.size)),
+            Err(err) => {
+                parts.release().await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Releases the parts written so far.
+    pub async fn discard(self) {
+        self.parts.release().await;
+    }
+}
+
+impl ExportParts<'_> {
+    async fn write(&mut self, is_last: bool) -> Result<(), MethodError> {
+        loop {
+            let part = {
+                let mut output = self.output.lock();
+                if output.len() >= EXPORT_PART_SIZE || (is_last && !output.is_empty()) {
+                    let part_len = std::cmp::min(output.len(), EXPORT_PART_SIZE);
+                    let remaining = output.split_off(part_len);
+                    std::mem::replace(&mut *output, remaining)
+                } else {
+                    break;
+                }
+            };
+            let blob_id = self
+                .jmap
+                .put_blob_until(self.account_id, &part, false, self.expires)
+                .await?;
+            self.hashes.push(blob_id.hash);
+            self.size += part.len();
+        }
+
+        Ok(())
+    }
+
+    async fn release(&self) {
+        self.jmap
+            .account_export_release(self.account_id, self.expires, &self.hashes)
+            .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl ExportTarget for ExportArchive<'_> {
+    async fn add_file(&mut self, path: String, contents: Vec<u8>) -> Result<(), MethodError> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(self.mtime);
+        if let Err(err) = self
+            .archive
+            .append_data(&mut header, &path, contents.as_slice())
+        {
+            tracing::warn!(
+                context = "export",
+                event = "error",
+                account_id = self.parts.account_id,
+                reason = %err,
+                "Failed to add {:?} to archive.", path
+            );
+            return Err(MethodError::ServerPartialFail);
+        }
+
+        // Store the encrypted output as soon as a full part is available
+        self.parts.write(false).await
+    }
+}
+
+impl Write for ExportSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn export_key(account_id: u32, export_id: u64) -> Vec<u8> {
+    KeySerializer::new(EXPORT_KEY_PREFIX.len() + U32_LEN + U64_LEN)
+        .write(EXPORT_KEY_PREFIX)
+        .write(account_id)
+        .write(export_id)
+        .finalize()
+}
@@ -21,8 +21,9 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use store::write::now;
 use tokio::sync::mpsc;
 use utils::{
     config::{cron::SimpleCron, Config},
@@ -30,14 +31,18 @@ use utils::{
     UnwrapFailure,
 };
 
-use crate::JMAP;
+use crate::{JMAP, LONG_SLUMBER};
 
-use super::IPC_CHANNEL_BUFFER;
+use super::{
+    jobs::{jobs_dispatch, JobKind},
+    IPC_CHANNEL_BUFFER,
+};
 
 pub enum Event {
     PurgeSessions,
     IndexStart,
     IndexDone,
+    JobsDispatch,
     #[cfg(feature = "test_mode")]
     IndexIsActive(tokio::sync::oneshot::Sender<bool>),
     Exit,
@@ -47,6 +52,16 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
     let purge_cache = settings
         .property_or_static::<SimpleCron>("jmap.session.purge.frequency", "15 * *")
         .failed("Initialize housekeeper");
+    let expire_masks = settings
+        .property_or_static::<SimpleCron>("jmap.masked-email.expire.frequency", "50 * *")
+        .failed("Initialize housekeeper");
+    let storage_report = settings
+        .property_or_static::<SimpleCron>("jmap.reports.storage.frequency", "0 3 *")
+        .failed("Initialize housekeeper");
+    let blob_tiering = settings
+        .property_or_static::<SimpleCron>("jmap.store.tiering.frequency", "0 4 *")
+        .failed("Initialize housekeeper");
+    let is_tiered = core.blob_store.is_tiered();
 
     tokio::spawn(async move {
         tracing::debug!("Housekeeper task started.");
@@ -60,9 +75,38 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
             core_.fts_index_queued().await;
         });
 
+        // Build mailbox counters on first start
+        let core_ = core.clone();
+        tokio::spawn(async move {
+            core_.mailbox_counters_init().await;
+        });
+
+        // Resume jobs interrupted by a restart
+        core.jobs_recover().await;
+        let mut next_job = jobs_dispatch(&core).await;
+
         loop {
-            let time_to_next = purge_cache.time_to_next();
+            let time_to_purge = purge_cache.time_to_next();
+            let time_to_expire = expire_masks.time_to_next();
+            let time_to_report = storage_report.time_to_next();
+            let time_to_tiering = if is_tiered {
+                blob_tiering.time_to_next()
+            } else {
+                LONG_SLUMBER
+            };
+            let time_to_job = next_job
+                .map(|run_at| Duration::from_secs(run_at.saturating_sub(now())))
+                .unwrap_or(LONG_SLUMBER);
+            let time_to_next = time_to_purge
+                .min(time_to_expire)
+                .min(time_to_report)
+                .min(time_to_tiering)
+                .min(time_to_job);
             let mut do_purge = false;
+            let mut do_expire = false;
+            let mut do_report = false;
+            let mut do_tiering = false;
+            let mut do_jobs = false;
 
             match tokio::time::timeout(time_to_next, rx.recv()).await {
                 Ok(Some(event)) => match event {
@@ -91,6 +135,9 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                             index_busy = false;
                         }
                     }
+                    Event::JobsDispatch => {
+                        do_jobs = true;
+                    }
                     #[cfg(feature = "test_mode")]
                     Event::IndexIsActive(tx) => {
                         tx.send(index_busy).ok();
@@ -104,7 +151,35 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                     tracing::debug!("Housekeeper task exiting.");
                     return;
                 }
-                Err(_) => (),
+                Err(_) => {
+                    do_expire = time_to_expire == time_to_next;
+                    do_report = time_to_report == time_to_next;
+                    do_tiering = time_to_tiering == time_to_next;
+                    do_jobs = time_to_job == time_to_next;
+                }
+            }
+
+            if do_tiering {
+                // Submitted as a job so it can be tracked and cancelled
+                if let Err(err) = core
+                    .job_submit(
+                        JobKind::BlobTiering { account_id: None },
+                        "housekeeper",
+                        None,
+                    )
+                    .await
+                {
+                    tracing::warn!(
+                        context = "blob_tiering",
+                        event = "error",
+                        reason = ?err,
+                        "Failed to submit blob tiering job."
+                    );
+                }
+            }
+
+            if do_jobs {
+                next_job = jobs_dispatch(&core).await;
             }
 
             if do_purge {
@@ -120,6 +195,53 @@ pub fn spawn_housekeeper(core: Arc<JMAP>, settings: &Config, mut rx: mpsc::Recei
                         .retain(|_, limiter| limiter.lock().is_active());
                 });
             }
+
+            if do_expire {
+                let core = core.clone();
+                tokio::spawn(async move {
+                    tracing::debug!("Expiring masked e-mail addresses.");
+                    core.masked_email_expire_all().await;
+                });
+            }
+
+            if do_report {
+                let core = core.clone();
+                tokio::spawn(async move {
+                    tracing::debug!("Building storage usage report.");
+                    if let Err(err) = core.storage_report_build().await {
+                        tracing::warn!(
+                            context = "report",
+                            event = "error",
+                            reason = ?err,
+                            "Failed to build storage usage report."
+                        );
+                    }
+
+                    // Remove traffic counters past the retention period
+                    let before_day = (now().saturating_sub(core.config.traffic_retention.as_secs())
+                        / 86400) as u32;
+                    if let Err(err) = core.traffic_purge(before_day).await {
+                        tracing::warn!(
+                            context = "report",
+                            event = "error",
+                            reason = ?err,
+                            "Failed to purge traffic counters."
+                        );
+                    }
+
+                    // Remove client usage counters past the retention period
+                    let before_day = (now().saturating_sub(core.config.client_retention.as_secs())
+                        / 86400) as u32;
+                    if let Err(err) = core.client_usage_purge(before_day).await {
+                        tracing::warn!(
+                            context = "report",
+                            event = "error",
+                            reason = ?err,
+                            "Failed to purge client usage counters."
+                        );
+                    }
+                });
+            }
         }
     });
 }
@@ -21,19 +21,31 @@
  * for more details.
 */
 
-use jmap_proto::types::{collection::Collection, property::Property};
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::{
+    error::method::MethodError,
+    object::{
+        index::{IndexProperty, ObjectIndexRefresh},
+        Object,
+    },
+    types::{collection::Collection, property::Property, value::Value},
+};
 use store::{
     fts::index::FtsDocument,
-    write::{key::DeserializeBigEndian, BatchBuilder, ValueClass},
+    write::{assert::HashedValue, key::DeserializeBigEndian, BatchBuilder, ValueClass},
     Deserialize, IterateParams, ValueKey, U32_LEN, U64_LEN,
 };
 
 use crate::{
-    email::{index::IndexMessageText, metadata::MessageMetadata},
+    email::{
+        index::{EmailIndexRefresh, IndexMessageText},
+        metadata::MessageMetadata,
+    },
+    submission::set::SCHEMA as SUBMISSION_SCHEMA,
     Bincode, JMAP,
 };
 
-use super::housekeeper::Event;
+use super::{housekeeper::Event, jobs::JobContext};
 
 #[derive(Debug)]
 struct IndexEmail {
@@ -214,6 +226,185 @@ impl JMAP {
             tracing::warn!("Failed to send index done event to housekeeper: {}", err);
         }
     }
+
+    // Rebuilds the indexes of one or all accounts while they stay online.
+    // Property indexes are rewritten in place and every message is queued for
+    // full-text indexing, which replaces its previous terms once the new ones
+    // are written. Messages changed in the meantime are queued by the change
+    // itself, so the index queue keeps both in order.
+    pub async fn reindex(&self, account_id: Option<u32>, ctx: &JobContext) -> Result<(), String> {
+        let account_ids = if let Some(account_id) = account_id {
+            vec![account_id]
+        } else {
+            let mut account_ids = Vec::new();
+            for name in self
+                .store
+                .list_accounts(None, None, 0)
+                .await
+                .map_err(|err| format!("Failed to list accounts: {err:?}"))?
+            {
+                if let Ok(Some(account_id)) = self.store.get_account_id(&name).await {
+                    account_ids.push(account_id);
+                }
+            }
+            account_ids
+        };
+
+        let total = account_ids.len().max(1);
+        for (pos, account_id) in account_ids.into_iter().enumerate() {
+            if ctx.is_cancelled() {
+                break;
+            }
+            self.reindex_account(account_id, ctx, total == 1)
+                .await
+                .map_err(|err| format!("Failed to reindex account {account_id}: {err:?}"))?;
+            if total > 1 {
+                self.job_progress(ctx, ((pos + 1) * 100 / total) as u8)
+                    .await;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn reindex_account(
+        &self,
+        account_id: u32,
+        ctx: &JobContext,
+        report_progress: bool,
+    ) -> Result<(), MethodError> {
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .unwrap_or_default();
+        let total = document_ids.len().max(1) as usize;
+        let mut last_progress = 0;
+
+        for (pos, document_id) in document_ids.into_iter().enumerate() {
+            if ctx.is_cancelled() {
+                break;
+            }
+
+            let metadata = if let Some(metadata) = self
+                .get_property::<HashedValue<Bincode<MessageMetadata>>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::BodyStructure,
+                )
+                .await?
+            {
+                metadata
+            } else {
+                continue;
+            };
+
+            // Skip the message if it was modified or deleted since it was read
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .update_document(document_id)
+                .assert_value(Property::BodyStructure, &metadata)
+                .set(
+                    ValueClass::IndexEmail(self.generate_snowflake_id()?),
+                    metadata.inner.inner.blob_hash.clone(),
+                )
+                .custom(EmailIndexRefresh::new(metadata.inner.inner));
+            match self.store.write(batch.build()).await {
+                Ok(_) | Err(store::Error::AssertValueFailed) => (),
+                Err(err) => {
+                    tracing::error!(
+                        context = "reindex",
+                        event = "error",
+                        account_id = account_id,
+                        document_id = document_id,
+                        reason = ?err,
+                        "Failed to write batch."
+                    );
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+
+            // Index queued messages in batches
+            if (pos + 1) % 100 == 0 {
+                self.housekeeper_tx.send(Event::IndexStart).await.ok();
+            }
+
+            let progress = ((pos + 1) * 100 / total) as u8;
+            if report_progress && progress >= last_progress + 5 {
+                last_progress = progress;
+                self.job_progress(ctx, progress).await;
+            }
+        }
+        self.housekeeper_tx.send(Event::IndexStart).await.ok();
+
+        // Submissions stored before the queue id was indexed
+        if !ctx.is_cancelled() {
+            self.reindex_objects(account_id, Collection::EmailSubmission, SUBMISSION_SCHEMA)
+                .await?;
+        }
+
+        tracing::info!(
+            context = "reindex",
+            event = "done",
+            account_id = account_id,
+            "Account reindexed."
+        );
+
+        Ok(())
+    }
+
+    async fn reindex_objects(
+        &self,
+        account_id: u32,
+        collection: Collection,
+        schema: &'static [IndexProperty],
+    ) -> Result<(), MethodError> {
+        for document_id in self
+            .get_document_ids(account_id, collection)
+            .await?
+            .unwrap_or_default()
+        {
+            let current = if let Some(current) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    collection,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                current
+            } else {
+                continue;
+            };
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(collection)
+                .update_document(document_id)
+                .custom(ObjectIndexRefresh::new(schema, current));
+            match self.store.write(batch.build()).await {
+                Ok(_) | Err(store::Error::AssertValueFailed) => (),
+                Err(err) => {
+                    tracing::error!(
+                        context = "reindex",
+                        event = "error",
+                        account_id = account_id,
+                        collection = ?collection,
+                        document_id = document_id,
+                        reason = ?err,
+                        "Failed to write batch."
+                    );
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Deserialize for IndexEmail {
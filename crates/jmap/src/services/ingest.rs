@@ -21,13 +21,27 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use directory::QueryBy;
 use jmap_proto::types::{state::StateChange, type_state::DataType};
 use mail_parser::MessageParser;
+use smtp::{
+    scripts::{ScriptModification, ScriptResult},
+    wasm::{PluginParameters, PluginStage},
+};
 use store::ahash::AHashMap;
 use utils::ipc::{DeliveryResult, IngestMessage};
 
-use crate::{email::ingest::IngestEmail, mailbox::INBOX_ID, IngestError, JMAP};
+use crate::{
+    activity::large_attachment,
+    email::ingest::{IngestBatch, IngestEmail},
+    mailbox::INBOX_ID,
+    push::PushPreview,
+    IngestError, JMAP,
+};
+
+use super::{notify::notify_summary, report::TrafficDirection, rules::RuleOutput};
 
 impl JMAP {
     pub async fn deliver_message(&self, message: IngestMessage) -> Vec<DeliveryResult> {
@@ -54,17 +68,129 @@ impl JMAP {
             recipients.push(uids);
         }
 
-        // Deliver to each recipient
+        // Parse the message once when delivery rules are configured or when
+        // it is delivered to multiple local recipients
+        let parsed_message = if self.delivery_rules.is_some() || deliver_names.len() > 1 {
+            MessageParser::new().parse(&raw_message)
+        } else {
+            None
+        };
+
+        // Share the message with ingest plugins, if any
+        let plugin_message = self
+            .smtp
+            .plugins
+            .has_stage(PluginStage::Ingest)
+            .then(|| Arc::new(raw_message.clone()));
+
+        // Build a push preview if any recipient has subscriptions requesting them
+        let preview = if deliver_names
+            .keys()
+            .any(|uid| self.push_previews.contains(uid))
+        {
+            match &parsed_message {
+                Some(parsed_message) => {
+                    PushPreview::new(parsed_message, &message.sender_address).into()
+                }
+                None => MessageParser::new()
+                    .parse_headers(&raw_message)
+                    .map(|headers| PushPreview::new(&headers, &message.sender_address)),
+            }
+        } else {
+            None
+        };
+
+        // Deliver to each recipient, messages that are not filtered by Sieve
+        // are written to the store in batches
+        let mut batch = IngestBatch::new(self.config.mail_ingest_batch_size);
+        let mut batch_changes = Vec::new();
+        let mut failed_uids = Vec::new();
+        let mut notifications = Vec::new();
+        let mut activities = Vec::new();
         for (uid, (status, rcpt)) in &mut deliver_names {
+            // Track deliveries to masked addresses, disabled masks discard the message
+            // and expired ones are rejected
+            match self.masked_email_deliver(*uid, rcpt).await {
+                Ok(None) => (),
+                Ok(Some(result)) => {
+                    *status = result;
+                    continue;
+                }
+                Err(_) => {
+                    *status = DeliveryResult::TemporaryFailure {
+                        reason: "Transient server failure.".into(),
+                    };
+                    continue;
+                }
+            }
+
+            // Run WebAssembly plugins
+            let mut plugin_headers = Vec::new();
+            if let Some(plugin_message) = &plugin_message {
+                let params = PluginParameters::new(PluginStage::Ingest)
+                    .with_variable("stage", PluginStage::Ingest.as_str())
+                    .with_variable("sender", message.sender_address.to_lowercase())
+                    .with_variable("recipient", rcpt.to_lowercase())
+                    .with_recipients(vec![rcpt.to_lowercase()])
+                    .with_message(plugin_message.clone());
+                match self
+                    .smtp
+                    .run_plugins(params, tracing::Span::current())
+                    .await
+                {
+                    ScriptResult::Accept { modifications } => {
+                        for modification in modifications {
+                            if let ScriptModification::AddHeader { name, value } = modification {
+                                plugin_headers.extend_from_slice(name.as_bytes());
+                                plugin_headers.extend_from_slice(b": ");
+                                plugin_headers.extend_from_slice(value.as_bytes());
+                                plugin_headers.extend_from_slice(b"\r\n");
+                            }
+                        }
+                    }
+                    ScriptResult::Reject(reply) => {
+                        *status = plugin_delivery_result(&reply);
+                        continue;
+                    }
+                    ScriptResult::Discard => {
+                        continue;
+                    }
+                    ScriptResult::Replace { .. } => (),
+                }
+            }
+
+            // Apply delivery rules
+            let rules = if let Some(parsed_message) = &parsed_message {
+                self.apply_delivery_rules(
+                    &raw_message,
+                    parsed_message,
+                    &message.sender_address,
+                    rcpt,
+                )
+                .await
+            } else {
+                RuleOutput::default()
+            };
+            let raw_message = rules.raw_message.as_deref().unwrap_or(&raw_message);
+            let plugin_raw_message;
+            let raw_message = if !plugin_headers.is_empty() {
+                plugin_raw_message = [plugin_headers.as_slice(), raw_message].concat();
+                plugin_raw_message.as_slice()
+            } else {
+                raw_message
+            };
+
             // Check if there is an active sieve script
+            let mut is_batched = false;
             let result = match self.sieve_script_get_active(*uid).await {
                 Ok(Some(active_script)) => {
                     self.sieve_script_ingest(
-                        &raw_message,
+                        raw_message,
                         &message.sender_address,
                         rcpt,
                         *uid,
                         active_script,
+                        &rules,
                     )
                     .await
                 }
@@ -80,17 +206,49 @@ impl JMAP {
                         }
                     };
 
-                    self.email_ingest(IngestEmail {
-                        raw_message: &raw_message,
-                        message: MessageParser::new().parse(&raw_message),
-                        account_id: *uid,
-                        account_quota,
-                        mailbox_ids: vec![INBOX_ID],
-                        keywords: vec![],
-                        received_at: None,
-                        skip_duplicates: true,
-                        encrypt: self.config.encrypt,
-                    })
+                    let mut keywords = vec![];
+                    rules.apply_keywords(&mut keywords);
+
+                    // Reuse the parsed message unless the delivery rules or plugins rewrote it
+                    let message = match (&rules.raw_message, &parsed_message) {
+                        (None, Some(parsed_message)) if plugin_headers.is_empty() => {
+                            Some(parsed_message.clone())
+                        }
+                        _ => MessageParser::new().parse(raw_message),
+                    };
+
+                    // File mailing list messages into their own mailbox
+                    let mailbox_id = match &message {
+                        Some(message) => {
+                            match self.list_filing_mailbox(*uid, raw_message, message).await {
+                                Ok(Some((mailbox_id, _))) => mailbox_id,
+                                Ok(None) => INBOX_ID,
+                                Err(_) => {
+                                    *status = DeliveryResult::TemporaryFailure {
+                                        reason: "Transient server failure.".into(),
+                                    };
+                                    continue;
+                                }
+                            }
+                        }
+                        None => INBOX_ID,
+                    };
+
+                    is_batched = true;
+                    self.email_ingest_batched(
+                        &mut batch,
+                        IngestEmail {
+                            raw_message,
+                            message,
+                            account_id: *uid,
+                            account_quota,
+                            mailbox_ids: vec![mailbox_id],
+                            keywords,
+                            received_at: None,
+                            skip_duplicates: true,
+                            encrypt: self.config.encrypt,
+                        },
+                    )
                     .await
                 }
                 Err(_) => {
@@ -103,23 +261,63 @@ impl JMAP {
 
             match result {
                 Ok(ingested_message) => {
+                    // Queue chat notifications requested by the delivery rules
+                    if let Some(parsed_message) =
+                        parsed_message.as_ref().filter(|_| !rules.notify.is_empty())
+                    {
+                        notifications.push((
+                            *uid,
+                            rules.notify.clone(),
+                            notify_summary(parsed_message, &message.sender_address, rcpt),
+                        ));
+                    }
+
                     // Notify state change
                     if ingested_message.change_id != u64::MAX {
-                        self.broadcast_state_change(
-                            StateChange::new(*uid)
-                                .with_change(DataType::EmailDelivery, ingested_message.change_id)
-                                .with_change(DataType::Email, ingested_message.change_id)
-                                .with_change(DataType::Mailbox, ingested_message.change_id)
-                                .with_change(DataType::Thread, ingested_message.change_id),
-                        )
-                        .await;
+                        // Look for large attachments to add to the activity feed
+                        if let Some(config) = &self.config.activity {
+                            let attachment = if raw_message.len() >= config.large_attachment {
+                                match &parsed_message {
+                                    Some(parsed_message) => {
+                                        large_attachment(parsed_message, config.large_attachment)
+                                    }
+                                    None => MessageParser::new().parse(raw_message).and_then(
+                                        |message| {
+                                            large_attachment(&message, config.large_attachment)
+                                        },
+                                    ),
+                                }
+                            } else {
+                                None
+                            };
+                            activities.push((
+                                *uid,
+                                ingested_message.id,
+                                ingested_message.size,
+                                attachment,
+                            ));
+                        }
+
+                        let preview = preview
+                            .as_ref()
+                            .filter(|_| self.push_previews.contains(uid))
+                            .map(|preview| {
+                                preview.clone().with_email_id(*uid, ingested_message.id)
+                            });
+                        if is_batched {
+                            batch_changes.push((*uid, ingested_message.change_id, preview));
+                        } else {
+                            if let Some(preview) = preview {
+                                self.broadcast_push_preview(*uid, preview).await;
+                            }
+                            self.broadcast_delivery(*uid, ingested_message.change_id)
+                                .await;
+                        }
                     }
                 }
                 Err(err) => match err {
                     IngestError::OverQuota => {
-                        *status = DeliveryResult::TemporaryFailure {
-                            reason: "Mailbox over quota.".into(),
-                        }
+                        *status = DeliveryResult::OverQuota;
                     }
                     IngestError::Temporary => {
                         *status = DeliveryResult::TemporaryFailure {
@@ -134,6 +332,55 @@ impl JMAP {
                     }
                 },
             }
+
+            // Flush the batch once it is full
+            if batch.is_full() {
+                let full_batch = std::mem::replace(
+                    &mut batch,
+                    IngestBatch::new(self.config.mail_ingest_batch_size),
+                );
+                self.deliver_batch(full_batch, &mut batch_changes, &mut failed_uids)
+                    .await;
+            }
+        }
+
+        // Write any remaining batched messages
+        self.deliver_batch(batch, &mut batch_changes, &mut failed_uids)
+            .await;
+        for uid in failed_uids {
+            if let Some((status, _)) = deliver_names.get_mut(&uid) {
+                *status = DeliveryResult::TemporaryFailure {
+                    reason: "Transient server failure.".into(),
+                };
+            }
+        }
+
+        // Update traffic counters for successful deliveries
+        for (uid, (status, rcpt)) in &deliver_names {
+            if matches!(status, DeliveryResult::Success) {
+                self.traffic_record(
+                    TrafficDirection::Inbound,
+                    *uid,
+                    rcpt.rsplit_once('@').map_or("", |(_, domain)| domain),
+                    raw_message.len(),
+                )
+                .await;
+            }
+        }
+
+        // Send chat notifications for successful deliveries
+        for (uid, connectors, summary) in notifications {
+            if matches!(deliver_names.get(&uid), Some((DeliveryResult::Success, _))) {
+                self.notify_delivery(&connectors, summary);
+            }
+        }
+
+        // Record account activity for successful deliveries
+        for (uid, email_id, size, attachment) in activities {
+            if matches!(deliver_names.get(&uid), Some((DeliveryResult::Success, _))) {
+                self.activity_delivery(uid, email_id, size, attachment)
+                    .await;
+            }
         }
 
         // Build result
@@ -158,7 +405,8 @@ impl JMAP {
                         for uid in names {
                             match deliver_names.get(&uid).unwrap().0 {
                                 DeliveryResult::Success => success += 1,
-                                DeliveryResult::TemporaryFailure { .. } => temp_failures += 1,
+                                DeliveryResult::TemporaryFailure { .. }
+                                | DeliveryResult::OverQuota => temp_failures += 1,
                                 DeliveryResult::PermanentFailure { .. } => {}
                             }
                         }
@@ -180,4 +428,64 @@ impl JMAP {
             })
             .collect()
     }
+
+    async fn deliver_batch(
+        &self,
+        batch: IngestBatch,
+        batch_changes: &mut Vec<(u32, u64, Option<PushPreview>)>,
+        failed_uids: &mut Vec<u32>,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+
+        if self.email_ingest_commit(batch).await.is_ok() {
+            for (uid, change_id, preview) in batch_changes.drain(..) {
+                if let Some(preview) = preview {
+                    self.broadcast_push_preview(uid, preview).await;
+                }
+                self.broadcast_delivery(uid, change_id).await;
+            }
+        } else {
+            failed_uids.extend(batch_changes.drain(..).map(|(uid, _, _)| uid));
+        }
+    }
+
+    pub(crate) async fn broadcast_delivery(&self, account_id: u32, change_id: u64) {
+        self.broadcast_state_change(
+            StateChange::new(account_id)
+                .with_change(DataType::EmailDelivery, change_id)
+                .with_change(DataType::Email, change_id)
+                .with_change(DataType::Mailbox, change_id)
+                .with_change(DataType::Thread, change_id),
+        )
+        .await;
+    }
+}
+
+// Maps a plugin rejection such as "550 5.7.1 Rejected" to a delivery result
+fn plugin_delivery_result(reply: &str) -> DeliveryResult {
+    let reply = reply.trim();
+    let (code, text) = reply.split_once(' ').unwrap_or((reply, ""));
+    let (status, reason) = match text.split_once(' ') {
+        Some((status, reason)) if status.contains('.') => (status, reason),
+        _ => ("5.7.1", text),
+    };
+    let mut status_code = [5, 7, 1];
+    for (pos, value) in status.split('.').take(3).enumerate() {
+        if let Ok(value) = value.parse() {
+            status_code[pos] = value;
+        }
+    }
+
+    if code.starts_with('4') {
+        DeliveryResult::TemporaryFailure {
+            reason: reason.to_string().into(),
+        }
+    } else {
+        DeliveryResult::PermanentFailure {
+            code: status_code,
+            reason: reason.to_string().into(),
+        }
+    }
 }
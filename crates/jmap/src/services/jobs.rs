@@ -0,0 +1,681 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use directory::backend::internal::manage::ManageDirectory;
+use hyper::{Method, StatusCode};
+use jmap_proto::error::{method::MethodError, request::RequestError};
+use rand::{thread_rng, Rng};
+use serde_json::json;
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, ValueClass},
+    Deserialize, IterateParams, LookupValue, Serialize, ValueKey, U64_LEN,
+};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    auth::{AccessToken, SymmetricEncrypt},
+    Bincode, JMAP,
+};
+
+use super::housekeeper::Event;
+
+const JOB_KEY_PREFIX: &[u8] = b"job.";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub requested_by: String,
+    pub created_at: u64,
+    pub run_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub status: JobStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum JobKind {
+    AccountExport {
+        account_id: u32,
+        export_id: u64,
+        // Encrypted with a key derived from the OAuth key
+        password: Vec<u8>,
+    },
+    StoreMaintenance,
+    Reindex {
+        account_id: Option<u32>,
+    },
+    KeySpaceAnalysis,
+    MailboxCounters {
+        account_id: Option<u32>,
+    },
+    BlobTiering {
+        account_id: Option<u32>,
+    },
+    BlobCompression,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum JobStatus {
+    Queued,
+    Running { progress: u8 },
+    Completed,
+    Failed { reason: String },
+    Cancelled,
+}
+
+pub struct JobContext {
+    pub id: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+impl JMAP {
+    pub async fn handle_jobs_request(
+        &self,
+        req: &HttpRequest,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> HttpResponse {
+        // Path is /admin/jobs/{job_id}
+        let job_id = match req
+            .uri()
+            .path()
+            .split('/')
+            .nth(3)
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse::<u64>())
+        {
+            Some(Ok(job_id)) => Some(job_id),
+            None => None,
+            Some(Err(_)) => return RequestError::not_found().into_http_response(),
+        };
+
+        match (req.method(), job_id) {
+            (&Method::GET, None) => match self.job_list().await {
+                Ok(jobs) => JsonResponse::new(json!({
+                    "data": jobs.into_iter().map(Job::into_json).collect::<Vec<_>>(),
+                }))
+                .into_http_response(),
+                Err(err) => RequestError::blank(
+                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    "Failed to list jobs",
+                    err.to_string(),
+                )
+                .into_http_response(),
+            },
+            (&Method::POST, None) => {
+                // Only jobs that do not carry secrets can be submitted directly
+                let request = serde_json::from_slice::<serde_json::Value>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .unwrap_or_default();
+                let kind = match request.get("type").and_then(|t| t.as_str()) {
+                    Some("store-maintenance") => JobKind::StoreMaintenance,
+                    Some("key-space-analysis") => JobKind::KeySpaceAnalysis,
+                    Some("blob-compression") => JobKind::BlobCompression,
+                    Some(job_type @ ("reindex" | "mailbox-counters" | "blob-tiering")) => {
+                        // Process a single account or all of them
+                        let account_id = match request.get("account").and_then(|a| a.as_str()) {
+                            Some(name) => match self.store.get_account_id(name).await {
+                                Ok(Some(account_id)) => Some(account_id),
+                                Ok(None) => {
+                                    return RequestError::blank(
+                                        StatusCode::NOT_FOUND.as_u16(),
+                                        "Not found",
+                                        "Account not found.",
+                                    )
+                                    .into_http_response();
+                                }
+                                Err(err) => {
+                                    return RequestError::blank(
+                                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                                        "Failed to obtain account",
+                                        format!("{err:?}"),
+                                    )
+                                    .into_http_response();
+                                }
+                            },
+                            None => None,
+                        };
+                        match job_type {
+                            "reindex" => JobKind::Reindex { account_id },
+                            "mailbox-counters" => JobKind::MailboxCounters { account_id },
+                            _ => JobKind::BlobTiering { account_id },
+                        }
+                    }
+                    _ => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Unknown or missing job type.",
+                        )
+                        .into_http_response();
+                    }
+                };
+                let run_at = request.get("runAt").and_then(|r| r.as_u64());
+
+                match self.job_submit(kind, &access_token.name, run_at).await {
+                    Ok(job) => JsonResponse::new(json!({
+                        "data": job.into_json(),
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to submit job",
+                        format!("{err:?}"),
+                    )
+                    .into_http_response(),
+                }
+            }
+            (&Method::GET, Some(job_id)) => match self.job_get(job_id).await {
+                Ok(Some(job)) => JsonResponse::new(json!({
+                    "data": job.into_json(),
+                }))
+                .into_http_response(),
+                Ok(None) => RequestError::not_found().into_http_response(),
+                Err(err) => RequestError::blank(
+                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    "Failed to obtain job",
+                    err.to_string(),
+                )
+                .into_http_response(),
+            },
+            (&Method::DELETE, Some(job_id)) => {
+                // Cancels pending jobs and removes finished ones
+                match self.job_cancel(job_id, &access_token.name).await {
+                    Ok(Some(job)) => JsonResponse::new(json!({
+                        "data": job.into_json(),
+                    }))
+                    .into_http_response(),
+                    Ok(None) => RequestError::not_found().into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Failed to cancel job",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    pub async fn job_submit(
+        &self,
+        kind: JobKind,
+        requested_by: &str,
+        run_at: Option<u64>,
+    ) -> Result<Job, MethodError> {
+        let created_at = now();
+        let job = Job {
+            id: self.generate_snowflake_id()?,
+            kind,
+            requested_by: requested_by.to_string(),
+            created_at,
+            run_at: run_at.unwrap_or(created_at),
+            started_at: None,
+            finished_at: None,
+            status: JobStatus::Queued,
+        };
+        self.job_set(&job)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?;
+
+        tracing::info!(
+            context = "audit",
+            event = "job-submit",
+            admin = requested_by,
+            job_id = job.id,
+            kind = job.kind.as_str(),
+            run_at = job.run_at,
+            "Background job submitted."
+        );
+
+        self.housekeeper_tx
+            .send(Event::JobsDispatch)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?;
+
+        Ok(job)
+    }
+
+    pub async fn job_get(&self, job_id: u64) -> store::Result<Option<Job>> {
+        if let Some(LookupValue::Value { value, .. }) = self
+            .store
+            .get_value::<LookupValue<Bincode<Job>>>(ValueKey::from(ValueClass::Key(job_key(
+                job_id,
+            ))))
+            .await?
+        {
+            Ok(Some(value.inner))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn job_list(&self) -> store::Result<Vec<Job>> {
+        let mut jobs = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Key(job_key(0))),
+                    ValueKey::from(ValueClass::Key(job_key(u64::MAX))),
+                ),
+                |_, value| {
+                    if let LookupValue::Value { value, .. } =
+                        LookupValue::<Bincode<Job>>::deserialize(value)?
+                    {
+                        jobs.push(value.inner);
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        Ok(jobs)
+    }
+
+    async fn job_set(&self, job: &Job) -> store::Result<()> {
+        // Finished jobs are kept around for the retention period
+        let expires = job
+            .finished_at
+            .map(|finished_at| finished_at + self.config.jobs_retention.as_secs())
+            .unwrap_or(u64::MAX);
+        let value = Bincode::new(job.clone()).serialize();
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Key(job_key(job.id)),
+            KeySerializer::new(value.len() + U64_LEN)
+                .write(expires)
+                .write(value.as_slice())
+                .finalize(),
+        );
+        self.store.write(batch.build()).await
+    }
+
+    pub async fn job_cancel(&self, job_id: u64, requested_by: &str) -> store::Result<Option<Job>> {
+        let mut job = if let Some(job) = self.job_get(job_id).await? {
+            job
+        } else {
+            return Ok(None);
+        };
+
+        match &job.status {
+            JobStatus::Queued | JobStatus::Running { .. } => {
+                if let Some(cancel) = self.running_jobs.get(&job_id) {
+                    // The job stops at its next checkpoint
+                    cancel.store(true, Ordering::Relaxed);
+                } else {
+                    job.status = JobStatus::Cancelled;
+                    job.finished_at = Some(now());
+                    self.job_set(&job).await?;
+
+                    // The job could have been dispatched in the meantime
+                    if let Some(cancel) = self.running_jobs.get(&job_id) {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                }
+                tracing::info!(
+                    context = "audit",
+                    event = "job-cancel",
+                    admin = requested_by,
+                    job_id = job_id,
+                    "Background job cancelled."
+                );
+            }
+            _ => {
+                let mut batch = BatchBuilder::new();
+                batch.clear(ValueClass::Key(job_key(job_id)));
+                self.store.write(batch.build()).await?;
+            }
+        }
+
+        Ok(Some(job))
+    }
+
+    pub async fn job_progress(&self, ctx: &JobContext, progress: u8) {
+        match self.job_get(ctx.id).await {
+            Ok(Some(mut job)) if matches!(job.status, JobStatus::Running { .. }) => {
+                job.status = JobStatus::Running {
+                    progress: progress.min(99),
+                };
+                if let Err(err) = self.job_set(&job).await {
+                    tracing::debug!(
+                        context = "jobs",
+                        event = "error",
+                        job_id = ctx.id,
+                        reason = ?err,
+                        "Failed to update job progress."
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Jobs left running by a previous process are started again
+    pub async fn jobs_recover(&self) {
+        let jobs = match self.job_list().await {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                tracing::warn!(
+                    context = "jobs",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to list jobs."
+                );
+                return;
+            }
+        };
+
+        for mut job in jobs {
+            if matches!(job.status, JobStatus::Running { .. })
+                && !self.running_jobs.contains_key(&job.id)
+            {
+                job.status = JobStatus::Queued;
+                job.started_at = None;
+                if let Err(err) = self.job_set(&job).await {
+                    tracing::warn!(
+                        context = "jobs",
+                        event = "error",
+                        job_id = job.id,
+                        reason = ?err,
+                        "Failed to requeue job."
+                    );
+                }
+            }
+        }
+    }
+
+    async fn job_run(&self, mut job: Job, ctx: JobContext) {
+        tracing::info!(
+            context = "jobs",
+            event = "start",
+            job_id = job.id,
+            kind = job.kind.as_str(),
+            "Starting background job."
+        );
+
+        let result = match &job.kind {
+            JobKind::AccountExport {
+                account_id,
+                export_id,
+                password,
+            } => match self.job_secret_decrypt(password) {
+                Ok(password) => {
+                    self.account_export_run(*account_id, *export_id, password, &ctx)
+                        .await
+                }
+                Err(err) => Err(err),
+            },
+            JobKind::StoreMaintenance => {
+                match self.store.purge_blobs(self.blob_store.clone()).await {
+                    Ok(_) if !ctx.is_cancelled() => {
+                        self.job_progress(&ctx, 50).await;
+                        self.store
+                            .purge_bitmaps()
+                            .await
+                            .map_err(|err| format!("Purge database failed: {err}"))
+                    }
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(format!("Purge blob failed: {err}")),
+                }
+            }
+            JobKind::Reindex { account_id } => self.reindex(*account_id, &ctx).await,
+            JobKind::MailboxCounters { account_id } => {
+                self.mailbox_counters_rebuild(*account_id, &ctx).await
+            }
+            JobKind::BlobTiering { account_id } => self.blob_tiering(*account_id, &ctx).await,
+            JobKind::BlobCompression => self.blob_compression(&ctx).await,
+            JobKind::KeySpaceAnalysis => self
+                .key_space_analysis_build()
+                .await
+                .map(|_| ())
+                .map_err(|err| format!("Key space analysis failed: {err}")),
+        };
+        self.running_jobs.remove(&job.id);
+
+        job.finished_at = Some(now());
+        job.status = if ctx.is_cancelled() {
+            JobStatus::Cancelled
+        } else {
+            match result {
+                Ok(_) => JobStatus::Completed,
+                Err(reason) => JobStatus::Failed { reason },
+            }
+        };
+        match &job.status {
+            JobStatus::Failed { reason } => {
+                tracing::warn!(
+                    context = "jobs",
+                    event = "error",
+                    job_id = job.id,
+                    kind = job.kind.as_str(),
+                    reason = %reason,
+                    "Background job failed."
+                );
+            }
+            status => {
+                tracing::info!(
+                    context = "jobs",
+                    event = "finish",
+                    job_id = job.id,
+                    kind = job.kind.as_str(),
+                    status = ?status,
+                    "Background job finished."
+                );
+            }
+        }
+        if let Err(err) = self.job_set(&job).await {
+            tracing::warn!(
+                context = "jobs",
+                event = "error",
+                job_id = job.id,
+                reason = ?err,
+                "Failed to update job status."
+            );
+        }
+
+        // Start any jobs waiting for a free slot
+        self.housekeeper_tx.send(Event::JobsDispatch).await.ok();
+    }
+
+    // Secrets are stored as the random nonce followed by the ciphertext
+    pub fn job_secret_encrypt(&self, secret: &str) -> Result<Vec<u8>, MethodError> {
+        let nonce = thread_rng().gen::<[u8; SymmetricEncrypt::NONCE_LEN]>();
+        SymmetricEncrypt::new(self.config.oauth_key.as_bytes(), "job secret")
+            .encrypt(secret.as_bytes(), &nonce)
+            .map(|secret| {
+                let mut result = nonce.to_vec();
+                result.extend(secret);
+                result
+            })
+            .map_err(|_| MethodError::ServerPartialFail)
+    }
+
+    fn job_secret_decrypt(&self, secret: &[u8]) -> Result<String, String> {
+        if secret.len() <= SymmetricEncrypt::NONCE_LEN {
+            return Err("Invalid job secret".to_string());
+        }
+        let (nonce, secret) = secret.split_at(SymmetricEncrypt::NONCE_LEN);
+        SymmetricEncrypt::new(self.config.oauth_key.as_bytes(), "job secret")
+            .decrypt(secret, nonce)
+            .and_then(|secret| String::from_utf8(secret).map_err(|err| err.to_string()))
+            .map_err(|err| format!("Failed to decrypt job secret: {err}"))
+    }
+}
+
+// Starts queued jobs that are due, up to the concurrency limit, and returns
+// the time at which the next scheduled job becomes due.
+pub async fn jobs_dispatch(core: &Arc<JMAP>) -> Option<u64> {
+    let mut jobs = match core.job_list().await {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            tracing::warn!(
+                context = "jobs",
+                event = "error",
+                reason = ?err,
+                "Failed to list jobs."
+            );
+            return None;
+        }
+    };
+    jobs.retain(|job| job.status == JobStatus::Queued);
+    jobs.sort_unstable_by_key(|job| (job.run_at, job.id));
+
+    let now = now();
+    for mut job in jobs {
+        if job.run_at > now {
+            return Some(job.run_at);
+        } else if core.running_jobs.len() >= core.config.jobs_max_concurrent {
+            break;
+        }
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        core.running_jobs.insert(job.id, cancel.clone());
+        job.status = JobStatus::Running { progress: 0 };
+        job.started_at = Some(now);
+        if let Err(err) = core.job_set(&job).await {
+            core.running_jobs.remove(&job.id);
+            tracing::warn!(
+                context = "jobs",
+                event = "error",
+                job_id = job.id,
+                reason = ?err,
+                "Failed to start job."
+            );
+            continue;
+        }
+
+        let core = core.clone();
+        tokio::spawn(async move {
+            let ctx = JobContext { id: job.id, cancel };
+            core.job_run(job, ctx).await;
+        });
+    }
+
+    None
+}
+
+impl JobContext {
+    pub fn new(id: u64) -> Self {
+        JobContext {
+            id,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::AccountExport { .. } => "account-export",
+            JobKind::StoreMaintenance => "store-maintenance",
+            JobKind::Reindex { .. } => "reindex",
+            JobKind::KeySpaceAnalysis => "key-space-analysis",
+            JobKind::MailboxCounters { .. } => "mailbox-counters",
+            JobKind::BlobTiering { .. } => "blob-tiering",
+            JobKind::BlobCompression => "blob-compression",
+        }
+    }
+}
+
+impl Job {
+    pub fn into_json(self) -> serde_json::Value {
+        let mut result = json!({
+            "id": self.id.to_string(),
+            "type": self.kind.as_str(),
+            "requestedBy": self.requested_by,
+            "createdAt": self.created_at,
+            "runAt": self.run_at,
+            "startedAt": self.started_at,
+            "finishedAt": self.finished_at,
+        });
+        let status = match self.status {
+            JobStatus::Queued => json!({
+                "status": "queued",
+                "progress": 0,
+            }),
+            JobStatus::Running { progress } => json!({
+                "status": "running",
+                "progress": progress,
+            }),
+            JobStatus::Completed => json!({
+                "status": "completed",
+                "progress": 100,
+            }),
+            JobStatus::Failed { reason } => json!({
+                "status": "failed",
+                "reason": reason,
+            }),
+            JobStatus::Cancelled => json!({
+                "status": "cancelled",
+            }),
+        };
+        if let (Some(result), serde_json::Value::Object(status)) = (result.as_object_mut(), status)
+        {
+            result.extend(status);
+        }
+        if let Some(result) = result.as_object_mut() {
+            match self.kind {
+                JobKind::AccountExport {
+                    account_id,
+                    export_id,
+                    ..
+                } => {
+                    result.insert("accountId".to_string(), account_id.into());
+                    result.insert("exportId".to_string(), export_id.to_string().into());
+                }
+                JobKind::Reindex {
+                    account_id: Some(account_id),
+                }
+                | JobKind::MailboxCounters {
+                    account_id: Some(account_id),
+                }
+                | JobKind::BlobTiering {
+                    account_id: Some(account_id),
+                } => {
+                    result.insert("accountId".to_string(), account_id.into());
+                }
+                _ => (),
+            }
+        }
+        result
+    }
+}
+
+fn job_key(job_id: u64) -> Vec<u8> {
+    KeySerializer::new(JOB_KEY_PREFIX.len() + U64_LEN)
+        .write(JOB_KEY_PREFIX)
+        .write(job_id)
+        .finalize()
+}
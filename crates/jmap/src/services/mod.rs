@@ -21,10 +21,16 @@
  * for more details.
 */
 
+pub mod alert;
 pub mod delivery;
+pub mod export;
 pub mod housekeeper;
 pub mod index;
 pub mod ingest;
+pub mod jobs;
+pub mod notify;
+pub mod report;
+pub mod rules;
 pub mod state;
 
 pub const IPC_CHANNEL_BUFFER: usize = 1024;
@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use mail_parser::Message;
+use reqwest::Method;
+use serde_json::json;
+use smtp::core::webhook::Webhook;
+use store::ahash::AHashMap;
+use utils::config::{utils::AsKey, Config};
+
+use crate::JMAP;
+
+const MAX_SUMMARY_LEN: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct NotifyConnector {
+    pub protocol: NotifyProtocol,
+    pub url: String,
+    pub token: String,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotifyProtocol {
+    // Matrix client-server API, messages are posted to a room
+    Matrix { room: String },
+    // XMPP over HTTP (Prosody mod_rest), messages are sent to a JID
+    Xmpp { jid: String },
+}
+
+impl JMAP {
+    // Sends a chat notification summarizing a delivered message to each of the
+    // connectors matched by the delivery rules
+    pub fn notify_delivery(&self, connectors: &[String], summary: String) {
+        for id in connectors {
+            if let Some(connector) = self.config.notify_connectors.get(id) {
+                let connector = connector.clone();
+                let id = id.clone();
+                let summary = summary.clone();
+                let txn_id = self.snowflake_id.generate().unwrap_or_else(rand::random);
+                tokio::spawn(async move {
+                    if let Err(err) = connector.send(&summary, txn_id).await {
+                        tracing::debug!(
+                            context = "notify",
+                            event = "error",
+                            connector = id.as_str(),
+                            reason = err.as_str(),
+                            "Failed to send chat notification."
+                        );
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl NotifyConnector {
+    pub async fn send(&self, text: &str, txn_id: u64) -> Result<(), String> {
+        let (url, method, body) = match &self.protocol {
+            NotifyProtocol::Matrix { room } => (
+                format!(
+                    "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+                    self.url.trim_end_matches('/'),
+                    form_urlencoded::byte_serialize(room.as_bytes()).collect::<String>(),
+                    txn_id
+                ),
+                Method::PUT,
+                json!({
+                    "msgtype": "m.text",
+                    "body": text,
+                }),
+            ),
+            NotifyProtocol::Xmpp { jid } => (
+                self.url.clone(),
+                Method::POST,
+                json!({
+                    "kind": "message",
+                    "type": "chat",
+                    "to": jid,
+                    "body": text,
+                }),
+            ),
+        };
+
+        Webhook::post(&url)
+            .with_method(method)
+            .with_token(&self.token)
+            .with_timeout(self.timeout)
+            .send(body.to_string())
+            .await
+    }
+}
+
+pub fn notify_summary(message: &Message<'_>, sender: &str, rcpt: &str) -> String {
+    let from = message
+        .from()
+        .and_then(|from| from.first())
+        .map(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => format!("{name} <{address}>"),
+            (None, Some(address)) => address.to_string(),
+            (Some(name), None) => name.to_string(),
+            (None, None) => sender.to_string(),
+        })
+        .unwrap_or_else(|| sender.to_string());
+    let mut summary = format!(
+        "New message for {rcpt} from {from}: {}",
+        message.subject().unwrap_or("(no subject)")
+    );
+    if summary.len() > MAX_SUMMARY_LEN {
+        let mut end = MAX_SUMMARY_LEN;
+        while !summary.is_char_boundary(end) {
+            end -= 1;
+        }
+        summary.truncate(end);
+        summary.push('…');
+    }
+    summary
+}
+
+pub fn parse_notify_connectors(
+    config: &Config,
+) -> utils::config::Result<AHashMap<String, NotifyConnector>> {
+    let mut connectors = AHashMap::new();
+
+    for id in config.sub_keys("jmap.notify") {
+        let prefix = ("jmap.notify", id).as_key();
+        let protocol = match config.value_require((prefix.as_str(), "type"))? {
+            "matrix" => NotifyProtocol::Matrix {
+                room: config.value_require((prefix.as_str(), "room"))?.to_string(),
+            },
+            "xmpp" => NotifyProtocol::Xmpp {
+                jid: config.value_require((prefix.as_str(), "jid"))?.to_string(),
+            },
+            other => {
+                return Err(format!(
+                    "Invalid notification type {other:?} for property {prefix:?}."
+                ))
+            }
+        };
+        connectors.insert(
+            id.to_string(),
+            NotifyConnector {
+                protocol,
+                url: config.value_require((prefix.as_str(), "url"))?.to_string(),
+                token: config
+                    .value((prefix.as_str(), "token"))
+                    .unwrap_or_default()
+                    .to_string(),
+                timeout: config.property_or_static((prefix.as_str(), "timeout"), "10s")?,
+            },
+        );
+    }
+
+    Ok(connectors)
+}
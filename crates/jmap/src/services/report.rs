@@ -0,0 +1,737 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::collections::BTreeMap;
+
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory},
+    QueryBy,
+};
+use jmap_proto::types::{collection::Collection, date::UTCDate};
+use store::{
+    ahash::AHashMap,
+    dispatch::analyze::{KeySpace, KeySpaceUsage},
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        now, AnyKey, BatchBuilder, DirectoryClass, ValueClass,
+    },
+    IterateParams, Key, Serialize, ValueKey, SUBSPACE_COUNTERS, U32_LEN,
+};
+
+use crate::{Bincode, JMAP};
+
+const STORAGE_REPORT_KEY: &[u8] = b"report.storage";
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StorageReport {
+    pub generated: u64,
+    pub accounts: Vec<AccountUsage>,
+    pub domains: Vec<DomainUsage>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccountUsage {
+    pub name: String,
+    pub quota: u32,
+    #[serde(rename = "usedBytes")]
+    pub used_bytes: i64,
+    pub messages: u64,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DomainUsage {
+    pub name: String,
+    pub accounts: u64,
+    #[serde(rename = "usedBytes")]
+    pub used_bytes: i64,
+    pub messages: u64,
+}
+
+impl JMAP {
+    // Walks all known accounts and persists a storage rollup sorted by usage,
+    // so that reports can be served without scanning every account.
+    pub async fn storage_report_build(&self) -> store::Result<StorageReport> {
+        let mut accounts = Vec::new();
+        let mut domains: AHashMap<String, DomainUsage> = AHashMap::new();
+
+        for name in self
+            .store
+            .list_accounts(None, None, 0)
+            .await
+            .map_err(|err| store::Error::InternalError(format!("{err:?}")))?
+        {
+            let account_id = match self.store.get_account_id(&name).await {
+                Ok(Some(account_id)) => account_id,
+                _ => continue,
+            };
+            let used_bytes = self
+                .store
+                .get_counter(DirectoryClass::UsedQuota(account_id))
+                .await?;
+            let messages = self
+                .get_document_ids(account_id, Collection::Email)
+                .await
+                .ok()
+                .flatten()
+                .map_or(0, |ids| ids.len());
+            if used_bytes == 0 && messages == 0 {
+                continue;
+            }
+            let principal = self
+                .store
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .ok()
+                .flatten();
+            let domain = principal
+                .as_ref()
+                .and_then(|p| p.emails.first())
+                .map(|email| email.as_str())
+                .unwrap_or(name.as_str())
+                .rsplit_once('@')
+                .map(|(_, domain)| domain.to_lowercase());
+
+            if let Some(domain) = domain {
+                let usage = domains
+                    .entry(domain)
+                    .or_insert_with_key(|name| DomainUsage {
+                        name: name.clone(),
+                        ..Default::default()
+                    });
+                usage.accounts += 1;
+                usage.used_bytes += used_bytes;
+                usage.messages += messages;
+            }
+            accounts.push(AccountUsage {
+                quota: principal.map_or(0, |p| p.quota),
+                name,
+                used_bytes,
+                messages,
+            });
+        }
+
+        accounts.sort_unstable_by(|a, b| b.used_bytes.cmp(&a.used_bytes));
+        let mut domains = domains.into_values().collect::<Vec<_>>();
+        domains.sort_unstable_by(|a, b| b.used_bytes.cmp(&a.used_bytes));
+        let report = StorageReport {
+            generated: now(),
+            accounts,
+            domains,
+        };
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Persistent(STORAGE_REPORT_KEY.to_vec()),
+            Bincode::new(report.clone()).serialize(),
+        );
+        self.store.write(batch.build()).await?;
+
+        Ok(report)
+    }
+
+    pub async fn storage_report_clear(&self) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Persistent(STORAGE_REPORT_KEY.to_vec()));
+        self.store.write(batch.build()).await
+    }
+
+    pub async fn storage_report_get(&self) -> store::Result<StorageReport> {
+        if let Some(report) = self
+            .store
+            .get_value::<Bincode<StorageReport>>(ValueKey::from(ValueClass::Persistent(
+                STORAGE_REPORT_KEY.to_vec(),
+            )))
+            .await?
+        {
+            Ok(report.inner)
+        } else {
+            self.storage_report_build().await
+        }
+    }
+}
+
+const KEY_SPACE_REPORT_KEY: &[u8] = b"report.key-space";
+
+// Thresholds used to flag accounts in the key space analysis
+const CHANGE_LOG_MIN_ENTRIES: u64 = 10_000;
+const CHANGE_LOG_MAX_RATIO: u64 = 4;
+const INDEX_QUEUE_MAX_ENTRIES: u64 = 1_000;
+const BLOB_RESERVATIONS_MAX_ENTRIES: u64 = 1_000;
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KeySpaceAnalysis {
+    pub generated: u64,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    pub total: AHashMap<KeySpace, KeySpaceUsage>,
+    pub accounts: Vec<AccountKeySpace>,
+    pub anomalies: Vec<KeySpaceAnomaly>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccountKeySpace {
+    #[serde(rename = "accountId")]
+    pub account_id: u32,
+    pub name: Option<String>,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    pub usage: AHashMap<KeySpace, KeySpaceUsage>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KeySpaceAnomaly {
+    #[serde(rename = "accountId")]
+    pub account_id: Option<u32>,
+    pub name: Option<String>,
+    #[serde(rename = "keySpace")]
+    pub key_space: Option<KeySpace>,
+    pub keys: u64,
+    pub reason: String,
+}
+
+impl JMAP {
+    // Scans the whole key space, which can take a while on large stores,
+    // so the analysis runs as a background job and the result is kept
+    // until the next run.
+    pub async fn key_space_analysis_build(&self) -> store::Result<KeySpaceAnalysis> {
+        let report = self.store.analyze_key_space().await?;
+        let mut accounts = Vec::with_capacity(report.accounts.len());
+        let mut anomalies = Vec::new();
+
+        if let Some(usage) = report
+            .total
+            .get(&KeySpace::IndexQueue)
+            .filter(|usage| usage.keys > INDEX_QUEUE_MAX_ENTRIES)
+        {
+            anomalies.push(KeySpaceAnomaly {
+                account_id: None,
+                name: None,
+                key_space: Some(KeySpace::IndexQueue),
+                keys: usage.keys,
+                reason: "Full-text indexing is falling behind.".to_string(),
+            });
+        }
+
+        for (account_id, usage) in report.accounts {
+            let name = self
+                .directory
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .ok()
+                .flatten()
+                .map(|p| p.name);
+            let keys = |key_space: KeySpace| usage.get(&key_space).map_or(0, |usage| usage.keys);
+            let mut anomaly = |key_space: Option<KeySpace>, keys: u64, reason: &str| {
+                anomalies.push(KeySpaceAnomaly {
+                    account_id: Some(account_id),
+                    name: name.clone(),
+                    key_space,
+                    keys,
+                    reason: reason.to_string(),
+                })
+            };
+
+            if name.is_none() {
+                anomaly(
+                    None,
+                    usage.values().map(|usage| usage.keys).sum(),
+                    "Data belongs to an account that no longer exists.",
+                );
+            }
+            let changes = keys(KeySpace::ChangeLogs);
+            if changes > CHANGE_LOG_MIN_ENTRIES
+                && changes > keys(KeySpace::Properties) * CHANGE_LOG_MAX_RATIO
+            {
+                anomaly(
+                    Some(KeySpace::ChangeLogs),
+                    changes,
+                    "Change log is much larger than the stored objects.",
+                );
+            }
+            let reservations = keys(KeySpace::BlobReservations);
+            if reservations > BLOB_RESERVATIONS_MAX_ENTRIES {
+                anomaly(
+                    Some(KeySpace::BlobReservations),
+                    reservations,
+                    "Too many temporary blob reservations.",
+                );
+            }
+
+            accounts.push(AccountKeySpace {
+                account_id,
+                total_bytes: usage.values().map(|usage| usage.total_bytes()).sum(),
+                name,
+                usage,
+            });
+        }
+        accounts.sort_unstable_by(|a, b| b.total_bytes.cmp(&a.total_bytes));
+
+        let analysis = KeySpaceAnalysis {
+            generated: now(),
+            total_bytes: report.total.values().map(|usage| usage.total_bytes()).sum(),
+            total: report.total,
+            accounts,
+            anomalies,
+        };
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Persistent(KEY_SPACE_REPORT_KEY.to_vec()),
+            Bincode::new(analysis.clone()).serialize(),
+        );
+        self.store.write(batch.build()).await?;
+
+        Ok(analysis)
+    }
+
+    pub async fn key_space_analysis_get(&self) -> store::Result<Option<KeySpaceAnalysis>> {
+        self.store
+            .get_value::<Bincode<KeySpaceAnalysis>>(ValueKey::from(ValueClass::Persistent(
+                KEY_SPACE_REPORT_KEY.to_vec(),
+            )))
+            .await
+            .map(|analysis| analysis.map(|analysis| analysis.inner))
+    }
+
+    pub async fn key_space_analysis_clear(&self) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Persistent(KEY_SPACE_REPORT_KEY.to_vec()));
+        self.store.write(batch.build()).await
+    }
+}
+
+const TRAFFIC_KEY_PREFIX: &[u8] = b"report.traffic.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrafficDirection {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrafficScope {
+    Account,
+    Domain,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct TrafficUsage {
+    pub day: String,
+    pub name: String,
+    #[serde(rename = "messagesIn")]
+    pub messages_in: u64,
+    #[serde(rename = "bytesIn")]
+    pub bytes_in: u64,
+    #[serde(rename = "messagesOut")]
+    pub messages_out: u64,
+    #[serde(rename = "bytesOut")]
+    pub bytes_out: u64,
+}
+
+// Each daily counter is stored under its own key, ending with the position
+// of the counter: messages and bytes received, then messages and bytes sent.
+const TRAFFIC_MESSAGES_IN: u8 = 0;
+const TRAFFIC_BYTES_IN: u8 = 1;
+const TRAFFIC_MESSAGES_OUT: u8 = 2;
+const TRAFFIC_BYTES_OUT: u8 = 3;
+
+impl TrafficScope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "account" => Some(TrafficScope::Account),
+            "domain" => Some(TrafficScope::Domain),
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            TrafficScope::Account => 0,
+            TrafficScope::Domain => 1,
+        }
+    }
+}
+
+impl JMAP {
+    // Adds a message to the daily traffic counters of an account and its domain
+    pub async fn traffic_record(
+        &self,
+        direction: TrafficDirection,
+        account_id: u32,
+        domain: &str,
+        size: usize,
+    ) {
+        let day = (now() / 86400) as u32;
+        let mut keys = vec![traffic_key(
+            day,
+            TrafficScope::Account,
+            &account_id.to_be_bytes(),
+        )];
+        if !domain.is_empty() {
+            keys.push(traffic_key(
+                day,
+                TrafficScope::Domain,
+                domain.to_lowercase().as_bytes(),
+            ));
+        }
+        let (messages, bytes) = match direction {
+            TrafficDirection::Inbound => (TRAFFIC_MESSAGES_IN, TRAFFIC_BYTES_IN),
+            TrafficDirection::Outbound => (TRAFFIC_MESSAGES_OUT, TRAFFIC_BYTES_OUT),
+        };
+
+        let mut batch = BatchBuilder::new();
+        for key in keys {
+            batch
+                .add(
+                    ValueClass::Persistent(traffic_counter_key(&key, messages)),
+                    1,
+                )
+                .add(
+                    ValueClass::Persistent(traffic_counter_key(&key, bytes)),
+                    size as i64,
+                );
+        }
+        if let Err(err) = self.store.write(batch.build()).await {
+            tracing::warn!(
+                context = "report",
+                event = "error",
+                account_id = account_id,
+                reason = ?err,
+                "Failed to update traffic counters."
+            );
+        }
+    }
+
+    pub async fn traffic_report(
+        &self,
+        scope: TrafficScope,
+        from_day: u32,
+        to_day: u32,
+    ) -> store::Result<Vec<TrafficUsage>> {
+        let mut entries: BTreeMap<(u32, Vec<u8>), [u64; 4]> = BTreeMap::new();
+        let mut end_key = traffic_key(to_day, scope, &[]);
+        end_key.push(u8::MAX);
+        for key in self
+            .counter_keys(traffic_key(from_day, scope, &[]), end_key)
+            .await?
+        {
+            let name = key.get(TRAFFIC_KEY_PREFIX.len()..).unwrap_or_default();
+            if name.get(U32_LEN) != Some(&scope.as_u8()) {
+                continue;
+            }
+            let (field, name) = match name.split_last() {
+                Some((field, name)) if *field <= TRAFFIC_BYTES_OUT => (*field, name),
+                _ => continue,
+            };
+            let value = self
+                .store
+                .get_counter(ValueKey::from(ValueClass::Persistent(key.clone())))
+                .await?;
+            entries
+                .entry((
+                    name.deserialize_be_u32(0)?,
+                    name.get(U32_LEN + 1..).unwrap_or_default().to_vec(),
+                ))
+                .or_default()[field as usize] = value.max(0) as u64;
+        }
+
+        let mut report = Vec::with_capacity(entries.len());
+        for ((day, name), counters) in entries {
+            let name = match scope {
+                TrafficScope::Account => {
+                    let account_id = name.as_slice().deserialize_be_u32(0)?;
+                    self.directory
+                        .query(QueryBy::Id(account_id), false)
+                        .await
+                        .ok()
+                        .flatten()
+                        .map(|p| p.name)
+                        .unwrap_or_else(|| account_id.to_string())
+                }
+                TrafficScope::Domain => String::from_utf8_lossy(&name).into_owned(),
+            };
+            report.push(TrafficUsage {
+                day: format_day(day),
+                name,
+                messages_in: counters[TRAFFIC_MESSAGES_IN as usize],
+                bytes_in: counters[TRAFFIC_BYTES_IN as usize],
+                messages_out: counters[TRAFFIC_MESSAGES_OUT as usize],
+                bytes_out: counters[TRAFFIC_BYTES_OUT as usize],
+            });
+        }
+
+        Ok(report)
+    }
+
+    // Removes the traffic counters of all days before the given one
+    pub async fn traffic_purge(&self, before_day: u32) -> store::Result<()> {
+        let keys = self
+            .counter_keys(
+                traffic_key(0, TrafficScope::Account, &[]),
+                traffic_key(before_day, TrafficScope::Account, &[]),
+            )
+            .await?;
+        for keys in keys.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for key in keys {
+                batch.clear_counter(ValueClass::Persistent(key.clone()));
+            }
+            self.store.write(batch.build()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TrafficUsage {
+    pub fn to_csv(report: &[TrafficUsage]) -> String {
+        let mut csv = String::from("day,name,messages_in,bytes_in,messages_out,bytes_out\r\n");
+        for usage in report {
+            csv.push_str(&format!(
+                "{},\"{}\",{},{},{},{}\r\n",
+                usage.day,
+                usage.name.replace('"', "\"\""),
+                usage.messages_in,
+                usage.bytes_in,
+                usage.messages_out,
+                usage.bytes_out
+            ));
+        }
+        csv
+    }
+}
+
+// Parses a YYYY-MM-DD date into a day number
+pub fn parse_traffic_day(value: &str) -> Option<u32> {
+    let mut parts = value.splitn(3, '-');
+    let date = UTCDate {
+        year: parts.next()?.parse().ok()?,
+        month: parts.next()?.parse().ok()?,
+        day: parts.next()?.parse().ok()?,
+        ..Default::default()
+    };
+    if date.is_valid() {
+        u32::try_from(date.timestamp() / 86400).ok()
+    } else {
+        None
+    }
+}
+
+fn traffic_counter_key(key: &[u8], field: u8) -> Vec<u8> {
+    let mut counter_key = Vec::with_capacity(key.len() + 1);
+    counter_key.extend_from_slice(key);
+    counter_key.push(field);
+    counter_key
+}
+
+fn traffic_key(day: u32, scope: TrafficScope, name: &[u8]) -> Vec<u8> {
+    KeySerializer::new(TRAFFIC_KEY_PREFIX.len() + U32_LEN + 1 + name.len())
+        .write(TRAFFIC_KEY_PREFIX)
+        .write(day)
+        .write(scope.as_u8())
+        .write(name)
+        .finalize()
+}
+
+const CLIENT_KEY_PREFIX: &[u8] = b"report.clients.";
+const CLIENT_MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ClientUsage {
+    pub protocol: String,
+    pub name: String,
+    pub version: String,
+    pub sessions: u64,
+    #[serde(rename = "firstSeen")]
+    pub first_seen: String,
+    #[serde(rename = "lastSeen")]
+    pub last_seen: String,
+}
+
+impl JMAP {
+    // Counts a session by a client that identified itself, used to find out
+    // which clients and versions are in use on this server.
+    pub async fn client_usage_record(&self, protocol: &str, name: &str, version: &str) {
+        let mut batch = BatchBuilder::new();
+        batch.add(
+            ValueClass::Persistent(client_key(
+                (now() / 86400) as u32,
+                &[protocol, name, version],
+            )),
+            1,
+        );
+        if let Err(err) = self.store.write(batch.build()).await {
+            tracing::warn!(
+                context = "report",
+                event = "error",
+                reason = ?err,
+                "Failed to update client usage counters."
+            );
+        }
+    }
+
+    // Adds up the daily client counters between two days, most used clients first
+    pub async fn client_usage_report(
+        &self,
+        from_day: u32,
+        to_day: u32,
+    ) -> store::Result<Vec<ClientUsage>> {
+        let mut clients: AHashMap<Vec<u8>, (u64, u32, u32)> = AHashMap::new();
+        for key in self
+            .counter_keys(
+                client_key(from_day, &[]),
+                client_key(to_day.saturating_add(1), &[]),
+            )
+            .await?
+        {
+            let sessions = self
+                .store
+                .get_counter(ValueKey::from(ValueClass::Persistent(key.clone())))
+                .await?;
+            let key = key.get(CLIENT_KEY_PREFIX.len()..).unwrap_or_default();
+            let day = key.deserialize_be_u32(0)?;
+            let entry = clients
+                .entry(key.get(U32_LEN..).unwrap_or_default().to_vec())
+                .or_insert((0, day, day));
+            entry.0 += sessions.max(0) as u64;
+            entry.1 = entry.1.min(day);
+            entry.2 = entry.2.max(day);
+        }
+
+        let mut report = clients
+            .into_iter()
+            .map(|(name, (sessions, first_seen, last_seen))| {
+                let mut parts = name.split(|&ch| ch == 0).map(String::from_utf8_lossy);
+                ClientUsage {
+                    protocol: parts.next().unwrap_or_default().into_owned(),
+                    name: parts.next().unwrap_or_default().into_owned(),
+                    version: parts.next().unwrap_or_default().into_owned(),
+                    sessions,
+                    first_seen: format_day(first_seen),
+                    last_seen: format_day(last_seen),
+                }
+            })
+            .collect::<Vec<_>>();
+        report.sort_unstable_by(|a, b| {
+            b.sessions
+                .cmp(&a.sessions)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.version.cmp(&b.version))
+        });
+
+        Ok(report)
+    }
+
+    // Removes the client counters of all days before the given one
+    pub async fn client_usage_purge(&self, before_day: u32) -> store::Result<()> {
+        let keys = self
+            .counter_keys(client_key(0, &[]), client_key(before_day, &[]))
+            .await?;
+        for keys in keys.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for key in keys {
+                batch.clear_counter(ValueClass::Persistent(key.clone()));
+            }
+            self.store.write(batch.build()).await?;
+        }
+
+        Ok(())
+    }
+
+    // Lists the persistent counters within a key range. Only keys are read,
+    // as not every store returns counter values as bytes when iterating.
+    async fn counter_keys(
+        &self,
+        from_key: Vec<u8>,
+        to_key: Vec<u8>,
+    ) -> store::Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    AnyKey {
+                        subspace: SUBSPACE_COUNTERS,
+                        key: ValueKey::from(ValueClass::Persistent(from_key)).serialize(0),
+                    },
+                    AnyKey {
+                        subspace: SUBSPACE_COUNTERS,
+                        key: ValueKey::from(ValueClass::Persistent(to_key)).serialize(0),
+                    },
+                )
+                .no_values(),
+                |key, _| {
+                    keys.push(key.get(1..).unwrap_or_default().to_vec());
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        Ok(keys)
+    }
+}
+
+impl ClientUsage {
+    pub fn to_csv(report: &[ClientUsage]) -> String {
+        let mut csv = String::from("protocol,name,version,sessions,first_seen,last_seen\r\n");
+        for usage in report {
+            csv.push_str(&format!(
+                "{},\"{}\",\"{}\",{},{},{}\r\n",
+                usage.protocol,
+                usage.name.replace('"', "\"\""),
+                usage.version.replace('"', "\"\""),
+                usage.sessions,
+                usage.first_seen,
+                usage.last_seen
+            ));
+        }
+        csv
+    }
+}
+
+fn format_day(day: u32) -> String {
+    let date = UTCDate::from_timestamp(day as i64 * 86400);
+    format!("{:04}-{:02}-{:02}", date.year, date.month, date.day)
+}
+
+// Client fields are NUL separated, control characters are removed and long
+// values truncated as they are supplied by the client.
+fn client_key(day: u32, fields: &[&str]) -> Vec<u8> {
+    let mut key = KeySerializer::new(CLIENT_KEY_PREFIX.len() + U32_LEN + 3 * CLIENT_MAX_NAME_LEN)
+        .write(CLIENT_KEY_PREFIX)
+        .write(day);
+    for (pos, field) in fields.iter().enumerate() {
+        if pos > 0 {
+            key = key.write(0u8);
+        }
+        key = key.write(
+            field
+                .trim()
+                .chars()
+                .filter(|ch| !ch.is_control())
+                .take(CLIENT_MAX_NAME_LEN)
+                .collect::<String>()
+                .as_bytes(),
+        );
+    }
+    key.finalize()
+}
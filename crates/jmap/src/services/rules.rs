@@ -0,0 +1,295 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use directory::Directories;
+use jmap_proto::types::keyword::Keyword;
+use mail_parser::{HeaderName, Message};
+use smtp::config::{if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock, IfThen};
+use store::Stores;
+use utils::config::{Config, KeyLookup};
+
+use crate::JMAP;
+
+pub struct DeliveryRules {
+    pub add_keywords: IfBlock<Vec<Keyword>>,
+    pub remove_keywords: IfBlock<Vec<Keyword>>,
+    pub subject_prefix: IfBlock<Option<String>>,
+    pub add_headers: IfBlock<Vec<String>>,
+    pub notify: IfBlock<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct RuleOutput {
+    pub raw_message: Option<Vec<u8>>,
+    pub add_keywords: Vec<Keyword>,
+    pub remove_keywords: Vec<Keyword>,
+    pub notify: Vec<String>,
+}
+
+struct DeliveryEnvelope<'x> {
+    sender: &'x str,
+    rcpt: &'x str,
+}
+
+impl JMAP {
+    /// Evaluates the delivery rules for a recipient and applies the matching
+    /// header modifications to the message before it is indexed.
+    pub async fn apply_delivery_rules(
+        &self,
+        raw_message: &[u8],
+        message: &Message<'_>,
+        sender: &str,
+        rcpt: &str,
+    ) -> RuleOutput {
+        let rules = if let Some(rules) = &self.delivery_rules {
+            rules
+        } else {
+            return RuleOutput::default();
+        };
+        let envelope = DeliveryEnvelope { sender, rcpt };
+        let mut output = RuleOutput {
+            raw_message: None,
+            add_keywords: rules.add_keywords.eval(&envelope).await.clone(),
+            remove_keywords: rules.remove_keywords.eval(&envelope).await.clone(),
+            notify: rules.notify.eval(&envelope).await.clone(),
+        };
+        let subject_prefix = rules
+            .subject_prefix
+            .eval(&envelope)
+            .await
+            .as_deref()
+            .filter(|prefix| {
+                !message
+                    .subject()
+                    .map_or(false, |subject| subject.trim_start().starts_with(prefix))
+            });
+        let headers = rules.add_headers.eval(&envelope).await;
+
+        if subject_prefix.is_some() || !headers.is_empty() {
+            let mut new_message = Vec::with_capacity(
+                raw_message.len()
+                    + headers.iter().map(|h| h.len() + 2).sum::<usize>()
+                    + subject_prefix.map_or(0, |p| p.len())
+                    + 12,
+            );
+            for header in headers {
+                new_message.extend_from_slice(header.as_bytes());
+                new_message.extend_from_slice(b"\r\n");
+            }
+
+            if let Some(subject_prefix) = subject_prefix {
+                if let Some(header) = message
+                    .root_part()
+                    .headers()
+                    .iter()
+                    .find(|h| h.name == HeaderName::Subject)
+                {
+                    let value = raw_message
+                        .get(header.offset_start..header.offset_end)
+                        .unwrap_or_default();
+                    new_message.extend_from_slice(&raw_message[..header.offset_start]);
+                    new_message.push(b' ');
+                    new_message.extend_from_slice(subject_prefix.as_bytes());
+                    new_message.push(b' ');
+                    new_message.extend_from_slice(
+                        value
+                            .iter()
+                            .position(|ch| !matches!(ch, b' ' | b'\t'))
+                            .map_or(value, |pos| &value[pos..]),
+                    );
+                    new_message.extend_from_slice(&raw_message[header.offset_end..]);
+                } else {
+                    new_message.extend_from_slice(b"Subject: ");
+                    new_message.extend_from_slice(subject_prefix.as_bytes());
+                    new_message.extend_from_slice(b"\r\n");
+                    new_message.extend_from_slice(raw_message);
+                }
+            } else {
+                new_message.extend_from_slice(raw_message);
+            }
+
+            output.raw_message = new_message.into();
+        }
+
+        output
+    }
+}
+
+impl RuleOutput {
+    pub fn apply_keywords(&self, keywords: &mut Vec<Keyword>) {
+        keywords.retain(|k| !self.remove_keywords.contains(k));
+        for keyword in &self.add_keywords {
+            if !keywords.contains(keyword) {
+                keywords.push(keyword.clone());
+            }
+        }
+    }
+}
+
+impl DeliveryRules {
+    pub fn parse(
+        config: &Config,
+        stores: &Stores,
+        directories: &Directories,
+    ) -> utils::config::Result<Option<Self>> {
+        let ctx = ConfigContext {
+            stores: stores.clone(),
+            directory: directories.clone(),
+            ..Default::default()
+        };
+        let available_keys = [
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+        ];
+        let add_keywords = config.parse_if_block::<Vec<String>>(
+            "jmap.delivery.add-keywords",
+            &ctx,
+            &available_keys,
+        )?;
+        let remove_keywords = config.parse_if_block::<Vec<String>>(
+            "jmap.delivery.remove-keywords",
+            &ctx,
+            &available_keys,
+        )?;
+        let subject_prefix = config.parse_if_block::<Option<String>>(
+            "jmap.delivery.subject-prefix",
+            &ctx,
+            &available_keys,
+        )?;
+        let add_headers = config.parse_if_block::<Vec<String>>(
+            "jmap.delivery.add-headers",
+            &ctx,
+            &available_keys,
+        )?;
+        let notify =
+            config.parse_if_block::<Vec<String>>("jmap.delivery.notify", &ctx, &available_keys)?;
+
+        if add_keywords.is_none()
+            && remove_keywords.is_none()
+            && subject_prefix.is_none()
+            && add_headers.is_none()
+            && notify.is_none()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(DeliveryRules {
+            add_keywords: map_if_block(add_keywords.unwrap_or_default(), |values| {
+                Ok(values.into_iter().map(Keyword::from).collect())
+            })?,
+            remove_keywords: map_if_block(remove_keywords.unwrap_or_default(), |values| {
+                Ok(values.into_iter().map(Keyword::from).collect())
+            })?,
+            subject_prefix: map_if_block(subject_prefix.unwrap_or_default(), |value| {
+                Ok(value
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty()))
+            })?,
+            add_headers: map_if_block(add_headers.unwrap_or_default(), |values| {
+                values
+                    .into_iter()
+                    .map(|v| match v.split_once(':') {
+                        Some((name, value))
+                            if !name.trim().is_empty()
+                                && name
+                                    .trim()
+                                    .bytes()
+                                    .all(|c| c.is_ascii_graphic() && c != b':') =>
+                        {
+                            Ok(format!("{}: {}", name.trim(), value.trim()))
+                        }
+                        _ => Err(format!(
+                            "Invalid header {v:?} for property \"jmap.delivery.add-headers\"."
+                        )),
+                    })
+                    .collect()
+            })?,
+            notify: map_if_block(notify.unwrap_or_default(), |values| {
+                for v in &values {
+                    if !config.contains_key(("jmap.notify", v.as_str(), "type")) {
+                        return Err(format!(
+                            "Notification connector {v:?} not found for property \"jmap.delivery.notify\"."
+                        ));
+                    }
+                }
+                Ok(values)
+            })?,
+        }))
+    }
+}
+
+fn map_if_block<T: Default, U: Default>(
+    if_block: IfBlock<T>,
+    mut map: impl FnMut(T) -> utils::config::Result<U>,
+) -> utils::config::Result<IfBlock<U>> {
+    Ok(IfBlock {
+        if_then: if_block
+            .if_then
+            .into_iter()
+            .map(|if_then| {
+                Ok(IfThen {
+                    conditions: if_then.conditions,
+                    then: map(if_then.then)?,
+                })
+            })
+            .collect::<utils::config::Result<Vec<_>>>()?,
+        default: map(if_block.default)?,
+    })
+}
+
+impl<'x> KeyLookup for DeliveryEnvelope<'x> {
+    type Key = EnvelopeKey;
+
+    fn key(&self, key: &Self::Key) -> Cow<'_, str> {
+        match key {
+            EnvelopeKey::Sender => self.sender.into(),
+            EnvelopeKey::SenderDomain => self
+                .sender
+                .rsplit_once('@')
+                .map_or("", |(_, domain)| domain)
+                .into(),
+            EnvelopeKey::Recipient => self.rcpt.into(),
+            EnvelopeKey::RecipientDomain => self
+                .rcpt
+                .rsplit_once('@')
+                .map_or("", |(_, domain)| domain)
+                .into(),
+            _ => "".into(),
+        }
+    }
+
+    fn key_as_int(&self, _: &Self::Key) -> i32 {
+        0
+    }
+
+    fn key_as_ip(&self, _: &Self::Key) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))
+    }
+}
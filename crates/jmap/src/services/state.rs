@@ -22,17 +22,18 @@
 */
 
 use std::{
+    collections::VecDeque,
     sync::Arc,
     time::{Duration, Instant, SystemTime},
 };
 
 use jmap_proto::types::{id::Id, state::StateChange, type_state::DataType};
 use store::ahash::AHashMap;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use utils::{config::Config, map::bitmap::Bitmap};
 
 use crate::{
-    push::{manager::spawn_push_manager, UpdateSubscription},
+    push::{manager::spawn_push_manager, PushPreview, UpdateSubscription},
     JMAP,
 };
 
@@ -46,9 +47,23 @@ pub enum Event {
         types: Bitmap<DataType>,
         tx: mpsc::Sender<StateChange>,
     },
+    SubscribeShared {
+        account_id: u32,
+        tx: oneshot::Sender<broadcast::Receiver<StateChange>>,
+    },
     Publish {
         state_change: StateChange,
     },
+    Preview {
+        account_id: u32,
+        preview: PushPreview,
+    },
+    Replay {
+        account_id: u32,
+        types: Bitmap<DataType>,
+        since: u64,
+        tx: oneshot::Sender<Option<Vec<StateChange>>>,
+    },
     UpdateSharedAccounts {
         account_id: u32,
     },
@@ -56,6 +71,9 @@ pub enum Event {
         account_id: u32,
         subscriptions: Vec<UpdateSubscription>,
     },
+    Ping {
+        tx: oneshot::Sender<()>,
+    },
     Stop,
 }
 
@@ -71,6 +89,13 @@ pub enum SubscriberType {
     Push { expires: u64 },
 }
 
+#[derive(Debug)]
+struct ChangeJournal {
+    changes: VecDeque<(Instant, StateChange)>,
+    // Changes with an id above this value are guaranteed to be in the journal
+    valid_from: u64,
+}
+
 impl Subscriber {
     fn is_valid(&self, current_time: u64) -> bool {
         match &self.subscription {
@@ -82,6 +107,10 @@ impl Subscriber {
 
 const PURGE_EVERY_SECS: u64 = 3600;
 const SEND_TIMEOUT_MS: u64 = 500;
+const BROADCAST_CHANNEL_BUFFER: usize = 32;
+
+type SharedAccounts = AHashMap<u32, Vec<u32>>;
+type SharedAccountsMap = AHashMap<u32, AHashMap<u32, Bitmap<DataType>>>;
 
 pub fn init_state_manager() -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
     mpsc::channel::<Event>(IPC_CHANNEL_BUFFER)
@@ -92,13 +121,18 @@ pub fn spawn_state_manager(
     settings: &Config,
     mut change_rx: mpsc::Receiver<Event>,
 ) {
-    let push_tx = spawn_push_manager(settings);
+    let push_tx = spawn_push_manager(core.clone(), settings);
 
     tokio::spawn(async move {
         let mut subscribers: AHashMap<u32, AHashMap<u32, Subscriber>> = AHashMap::default();
-        let mut shared_accounts: AHashMap<u32, Vec<u32>> = AHashMap::default();
-        let mut shared_accounts_map: AHashMap<u32, AHashMap<u32, Bitmap<DataType>>> =
-            AHashMap::default();
+        let mut broadcasters: AHashMap<u32, broadcast::Sender<StateChange>> = AHashMap::default();
+        let mut shared_accounts: SharedAccounts = AHashMap::default();
+        let mut shared_accounts_map: SharedAccountsMap = AHashMap::default();
+
+        let mut journals: AHashMap<u32, ChangeJournal> = AHashMap::default();
+        let mut journal_start = core.generate_snowflake_id().unwrap_or(u64::MAX);
+        let replay_window = core.config.event_source_replay_window;
+        let replay_max = core.config.event_source_replay_max;
 
         let mut last_purge = Instant::now();
 
@@ -107,72 +141,20 @@ pub fn spawn_state_manager(
 
             match event {
                 Event::Stop => {
-                    if let Err(err) = push_tx.send(crate::push::Event::Reset).await {
-                        tracing::debug!("Error sending push reset: {}", err);
-                    }
-                    break;
+                    // Flush any pending state changes before stopping
+                    change_rx.close();
+                }
+                Event::Ping { tx } => {
+                    let _ = tx.send(());
                 }
                 Event::UpdateSharedAccounts { account_id } => {
-                    // Obtain account membership and shared mailboxes
-                    let acl = match core.get_access_token(account_id).await {
-                        Some(result) => result,
-                        None => {
-                            continue;
-                        }
-                    };
-
-                    // Delete any removed sharings
-                    if let Some(shared_account_ids) = shared_accounts.get(&account_id) {
-                        for shared_account_id in shared_account_ids {
-                            if *shared_account_id != acl.primary_id
-                                && !acl.member_of.contains(shared_account_id)
-                                && !acl
-                                    .access_to
-                                    .iter()
-                                    .any(|(id, _)| *id == *shared_account_id)
-                            {
-                                if let Some(shared_list) =
-                                    shared_accounts_map.get_mut(shared_account_id)
-                                {
-                                    shared_list.remove(&account_id);
-                                    if shared_list.is_empty() {
-                                        shared_accounts_map.remove(shared_account_id);
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    // Update lists
-                    let mut shared_account_ids =
-                        Vec::with_capacity(acl.member_of.len() + 1 + acl.access_to.len());
-                    for member_id in [acl.primary_id].iter().chain(acl.member_of.iter()) {
-                        shared_account_ids.push(*member_id);
-                        shared_accounts_map
-                            .entry(*member_id)
-                            .or_insert_with(AHashMap::new)
-                            .insert(account_id, Bitmap::all());
-                    }
-                    for (shared_account_id, shared_collections) in acl.access_to.iter() {
-                        let mut types: Bitmap<DataType> = Bitmap::new();
-                        for collection in *shared_collections {
-                            if let Ok(type_state) = DataType::try_from(collection) {
-                                types.insert(type_state);
-                                if type_state == DataType::Email {
-                                    types.insert(DataType::EmailDelivery);
-                                    types.insert(DataType::Thread);
-                                }
-                            }
-                        }
-                        if !types.is_empty() {
-                            shared_account_ids.push(*shared_account_id);
-                            shared_accounts_map
-                                .entry(*shared_account_id)
-                                .or_insert_with(AHashMap::new)
-                                .insert(account_id, types);
-                        }
-                    }
-                    shared_accounts.insert(account_id, shared_account_ids);
+                    update_shared_accounts(
+                        &core,
+                        account_id,
+                        &mut shared_accounts,
+                        &mut shared_accounts_map,
+                    )
+                    .await;
                 }
                 Event::Subscribe {
                     id,
@@ -191,9 +173,70 @@ pub fn spawn_state_manager(
                             },
                         );
                 }
+                Event::SubscribeShared { account_id, tx } => {
+                    // All sessions of an account share a single broadcaster, the shared
+                    // account list is only fetched when the first session subscribes
+                    let change_rx = if let Some(broadcaster) = broadcasters.get(&account_id) {
+                        broadcaster.subscribe()
+                    } else {
+                        update_shared_accounts(
+                            &core,
+                            account_id,
+                            &mut shared_accounts,
+                            &mut shared_accounts_map,
+                        )
+                        .await;
+                        let (broadcaster, change_rx) =
+                            broadcast::channel::<StateChange>(BROADCAST_CHANNEL_BUFFER);
+                        broadcasters.insert(account_id, broadcaster);
+                        change_rx
+                    };
+                    if tx.send(change_rx).is_err() {
+                        tracing::debug!("Failed to send shared broadcaster to subscriber.");
+                    }
+                }
                 Event::Publish { state_change } => {
+                    // Record change in the account's journal for EventSource replays
+                    let journal =
+                        journals
+                            .entry(state_change.account_id)
+                            .or_insert_with(|| ChangeJournal {
+                                changes: VecDeque::new(),
+                                valid_from: journal_start,
+                            });
+                    journal
+                        .changes
+                        .push_back((Instant::now(), state_change.clone()));
+                    journal.prune(replay_window, replay_max);
+
                     if let Some(shared_accounts) = shared_accounts_map.get(&state_change.account_id)
                     {
+                        // Notify shared broadcasters, sending to a broadcast channel never blocks
+                        for (owner_account_id, allowed_types) in shared_accounts {
+                            if let Some(broadcaster) = broadcasters.get(owner_account_id) {
+                                if broadcaster.receiver_count() > 0 {
+                                    let types = state_change
+                                        .types
+                                        .iter()
+                                        .filter(|(state_type, _)| {
+                                            allowed_types.contains(*state_type)
+                                        })
+                                        .copied()
+                                        .collect::<Vec<_>>();
+                                    if !types.is_empty() {
+                                        broadcaster
+                                            .send(StateChange {
+                                                account_id: state_change.account_id,
+                                                types,
+                                            })
+                                            .ok();
+                                    }
+                                } else {
+                                    purge_needed = true;
+                                }
+                            }
+                        }
+
                         let current_time = SystemTime::now()
                             .duration_since(SystemTime::UNIX_EPOCH)
                             .map(|d| d.as_secs())
@@ -266,6 +309,97 @@ pub fn spawn_state_manager(
                         }
                     }
                 }
+                Event::Preview {
+                    account_id,
+                    preview,
+                } => {
+                    // Previews are only pushed to the recipient's own subscriptions
+                    let current_time = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    let push_ids = subscribers
+                        .get(&account_id)
+                        .map(|subscribers| {
+                            subscribers
+                                .iter()
+                                .filter(|(_, subscriber)| {
+                                    subscriber.types.contains(DataType::Email)
+                                        && matches!(&subscriber.subscription,
+                                            SubscriberType::Push { expires } if expires > &current_time)
+                                })
+                                .map(|(subscriber_id, _)| Id::from_parts(account_id, *subscriber_id))
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+
+                    if !push_ids.is_empty() {
+                        if let Err(err) = push_tx
+                            .send(crate::push::Event::Preview {
+                                ids: push_ids,
+                                preview,
+                            })
+                            .await
+                        {
+                            tracing::debug!("Error sending push preview: {}", err);
+                        }
+                    }
+                }
+                Event::Replay {
+                    account_id,
+                    types,
+                    since,
+                    tx,
+                } => {
+                    let mut changes = Some(Vec::new());
+
+                    for shared_account_id in shared_accounts
+                        .get(&account_id)
+                        .map(|ids| ids.as_slice())
+                        .unwrap_or_default()
+                    {
+                        let allowed_types = if let Some(allowed_types) = shared_accounts_map
+                            .get(shared_account_id)
+                            .and_then(|map| map.get(&account_id))
+                        {
+                            allowed_types
+                        } else {
+                            continue;
+                        };
+                        let journal = journals.get(shared_account_id);
+
+                        // Changes older than the journal can't be replayed
+                        if since < journal.map_or(journal_start, |journal| journal.valid_from) {
+                            changes = None;
+                            break;
+                        }
+
+                        if let (Some(journal), Some(changes)) = (journal, &mut changes) {
+                            for (_, state_change) in &journal.changes {
+                                let replay_types = state_change
+                                    .types
+                                    .iter()
+                                    .filter(|(state_type, change_id)| {
+                                        *change_id > since
+                                            && types.contains(*state_type)
+                                            && allowed_types.contains(*state_type)
+                                    })
+                                    .copied()
+                                    .collect::<Vec<_>>();
+                                if !replay_types.is_empty() {
+                                    changes.push(StateChange {
+                                        account_id: state_change.account_id,
+                                        types: replay_types,
+                                    });
+                                }
+                            }
+                        }
+                    }
+
+                    if tx.send(changes).is_err() {
+                        tracing::debug!("Failed to send replayed state changes.");
+                    }
+                }
                 Event::UpdateSubscriptions {
                     account_id,
                     subscriptions,
@@ -333,6 +467,7 @@ pub fn spawn_state_manager(
                                     id: Id::from_parts(account_id, verified.id),
                                     url: verified.url,
                                     keys: verified.keys,
+                                    preview: verified.preview,
                                 });
                             }
                         }
@@ -380,12 +515,110 @@ pub fn spawn_state_manager(
                     subscribers.remove(&remove_account_id);
                 }
 
+                // Remove broadcasters without any listening sessions
+                broadcasters.retain(|_, broadcaster| broadcaster.receiver_count() > 0);
+
+                // Remove expired journals, replays older than them are no longer possible
+                journals.retain(|_, journal| {
+                    journal.prune(replay_window, replay_max);
+                    if journal.changes.is_empty() {
+                        journal_start = std::cmp::max(journal_start, journal.valid_from);
+                        false
+                    } else {
+                        true
+                    }
+                });
+
                 last_purge = Instant::now();
             }
         }
+
+        if let Err(err) = push_tx.send(crate::push::Event::Reset).await {
+            tracing::debug!("Error sending push reset: {}", err);
+        }
     });
 }
 
+impl ChangeJournal {
+    fn prune(&mut self, window: Duration, max_changes: usize) {
+        while let Some((added, state_change)) = self.changes.front() {
+            if added.elapsed() > window || self.changes.len() > max_changes {
+                for (_, change_id) in &state_change.types {
+                    self.valid_from = std::cmp::max(self.valid_from, *change_id);
+                }
+                self.changes.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+async fn update_shared_accounts(
+    core: &JMAP,
+    account_id: u32,
+    shared_accounts: &mut SharedAccounts,
+    shared_accounts_map: &mut SharedAccountsMap,
+) {
+    // Obtain account membership and shared mailboxes
+    let acl = match core.get_access_token(account_id).await {
+        Some(result) => result,
+        None => {
+            return;
+        }
+    };
+
+    // Delete any removed sharings
+    if let Some(shared_account_ids) = shared_accounts.get(&account_id) {
+        for shared_account_id in shared_account_ids {
+            if *shared_account_id != acl.primary_id
+                && !acl.member_of.contains(shared_account_id)
+                && !acl
+                    .access_to
+                    .iter()
+                    .any(|(id, _)| *id == *shared_account_id)
+            {
+                if let Some(shared_list) = shared_accounts_map.get_mut(shared_account_id) {
+                    shared_list.remove(&account_id);
+                    if shared_list.is_empty() {
+                        shared_accounts_map.remove(shared_account_id);
+                    }
+                }
+            }
+        }
+    }
+
+    // Update lists
+    let mut shared_account_ids = Vec::with_capacity(acl.member_of.len() + 1 + acl.access_to.len());
+    for member_id in [acl.primary_id].iter().chain(acl.member_of.iter()) {
+        shared_account_ids.push(*member_id);
+        shared_accounts_map
+            .entry(*member_id)
+            .or_insert_with(AHashMap::new)
+            .insert(account_id, Bitmap::all());
+    }
+    for (shared_account_id, shared_collections) in acl.access_to.iter() {
+        let mut types: Bitmap<DataType> = Bitmap::new();
+        for collection in *shared_collections {
+            if let Ok(type_state) = DataType::try_from(collection) {
+                types.insert(type_state);
+                if type_state == DataType::Email {
+                    types.insert(DataType::EmailDelivery);
+                    types.insert(DataType::Thread);
+                }
+            }
+        }
+        if !types.is_empty() {
+            shared_account_ids.push(*shared_account_id);
+            shared_accounts_map
+                .entry(*shared_account_id)
+                .or_insert_with(AHashMap::new)
+                .insert(account_id, types);
+        }
+    }
+    shared_accounts.insert(account_id, shared_account_ids);
+}
+
 impl JMAP {
     pub async fn subscribe_state_manager(
         &self,
@@ -417,6 +650,52 @@ impl JMAP {
         change_rx.into()
     }
 
+    pub async fn subscribe_state_broadcaster(
+        &self,
+        account_id: u32,
+    ) -> Option<broadcast::Receiver<StateChange>> {
+        let (tx, rx) = oneshot::channel();
+        if let Err(err) = self
+            .state_tx
+            .clone()
+            .send(Event::SubscribeShared { account_id, tx })
+            .await
+        {
+            tracing::error!(
+                "Channel failure while subscribing to state broadcaster: {}",
+                err
+            );
+            return None;
+        }
+
+        rx.await.ok()
+    }
+
+    pub async fn replay_state_changes(
+        &self,
+        account_id: u32,
+        types: Bitmap<DataType>,
+        since: u64,
+    ) -> Option<Vec<StateChange>> {
+        let (tx, rx) = oneshot::channel();
+        if let Err(err) = self
+            .state_tx
+            .clone()
+            .send(Event::Replay {
+                account_id,
+                types,
+                since,
+                tx,
+            })
+            .await
+        {
+            tracing::error!("Channel failure while replaying state changes: {}", err);
+            return None;
+        }
+
+        rx.await.ok().flatten()
+    }
+
     pub async fn broadcast_state_change(&self, state_change: StateChange) -> bool {
         match self
             .state_tx
@@ -432,6 +711,26 @@ impl JMAP {
         }
     }
 
+    // Previews have to be published before the state change of the delivery
+    // they describe, as they are pushed along with it.
+    pub async fn broadcast_push_preview(&self, account_id: u32, preview: PushPreview) -> bool {
+        match self
+            .state_tx
+            .clone()
+            .send(Event::Preview {
+                account_id,
+                preview,
+            })
+            .await
+        {
+            Ok(_) => true,
+            Err(err) => {
+                tracing::error!("Channel failure while publishing push preview: {}", err);
+                false
+            }
+        }
+    }
+
     pub async fn update_push_subscriptions(&self, account_id: u32) -> bool {
         let push_subs = match self.fetch_push_subscriptions(account_id).await {
             Ok(push_subs) => push_subs,
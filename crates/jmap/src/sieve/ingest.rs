@@ -36,6 +36,7 @@ use store::{
 use crate::{
     email::ingest::{IngestEmail, IngestedEmail},
     mailbox::{INBOX_ID, TRASH_ID},
+    services::rules::RuleOutput,
     sieve::SeenIdHash,
     Bincode, IngestError, JMAP,
 };
@@ -57,6 +58,7 @@ impl JMAP {
         envelope_to: &str,
         account_id: u32,
         mut active_script: ActiveScript,
+        rules: &RuleOutput,
     ) -> Result<IngestedEmail, IngestError> {
         // Parse message
         let message = if let Some(message) = MessageParser::new().parse(raw_message) {
@@ -430,6 +432,8 @@ impl JMAP {
                 };
 
                 // Deliver message
+                let mut keywords = sieve_message.flags;
+                rules.apply_keywords(&mut keywords);
                 match self
                     .email_ingest(IngestEmail {
                         raw_message: &sieve_message.raw_message,
@@ -437,7 +441,7 @@ impl JMAP {
                         account_id,
                         account_quota,
                         mailbox_ids: sieve_message.file_into,
-                        keywords: sieve_message.flags,
+                        keywords,
                         received_at: None,
                         skip_duplicates: true,
                         encrypt: self.config.encrypt,
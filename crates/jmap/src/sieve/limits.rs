@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use sieve::Sieve;
+
+#[derive(Debug, Clone)]
+pub struct RegexLimits {
+    pub max_length: usize,
+    pub max_patterns: usize,
+    pub allow_backtracking: bool,
+}
+
+// Fields of a compiled test or action holding the keys matched with `:regex`
+const REGEX_KEY_FIELDS: &[&str] = &["key_list", "flags", "value_patterns"];
+
+impl RegexLimits {
+    /// Validates the patterns used by `:regex` match types in a compiled untrusted script.
+    /// Patterns longer than the configured limit are rejected and, unless
+    /// backtracking is allowed, so are backreferences and lookaround assertions.
+    /// Patterns built from variables are compiled at runtime and are rejected as
+    /// they cannot be validated in advance.
+    pub fn validate(&self, script: &Sieve) -> Result<(), String> {
+        let script = serde_json::to_value(script)
+            .map_err(|_| "Failed to inspect compiled script.".to_string())?;
+        let mut num_patterns = 0;
+        self.validate_value(&script, &mut num_patterns)
+    }
+
+    fn validate_value(
+        &self,
+        value: &serde_json::Value,
+        num_patterns: &mut usize,
+    ) -> Result<(), String> {
+        match value {
+            serde_json::Value::Object(map) => {
+                if map
+                    .get("match_type")
+                    .and_then(|match_type| match_type.as_object())
+                    .map_or(false, |match_type| match_type.contains_key("Regex"))
+                {
+                    for keys in REGEX_KEY_FIELDS
+                        .iter()
+                        .filter_map(|field| map.get(*field))
+                        .filter_map(|keys| keys.as_array())
+                    {
+                        for key in keys {
+                            self.validate_key(key, num_patterns)?;
+                        }
+                    }
+                }
+
+                for value in map.values() {
+                    self.validate_value(value, num_patterns)?;
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.validate_value(value, num_patterns)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn validate_key(
+        &self,
+        key: &serde_json::Value,
+        num_patterns: &mut usize,
+    ) -> Result<(), String> {
+        let pattern = match key.get("Regex").and_then(|pattern| pattern.as_str()) {
+            Some(pattern) => pattern,
+            None => {
+                return Err("Regular expressions containing variables are not allowed.".to_string())
+            }
+        };
+
+        *num_patterns += 1;
+        if *num_patterns > self.max_patterns {
+            Err(format!(
+                "Script exceeds the maximum of {} regular expressions.",
+                self.max_patterns
+            ))
+        } else if pattern.len() > self.max_length {
+            Err(format!(
+                "Regular expression exceeds the maximum length of {} bytes.",
+                self.max_length
+            ))
+        } else if !self.allow_backtracking && requires_backtracking(pattern.as_bytes()) {
+            Err(
+                "Backreferences and lookaround assertions are not allowed in regular expressions."
+                    .to_string(),
+            )
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn requires_backtracking(pattern: &[u8]) -> bool {
+    let mut iter = pattern.iter().enumerate();
+
+    while let Some((pos, ch)) = iter.next() {
+        match ch {
+            b'\\' => {
+                if let Some((_, ch)) = iter.next() {
+                    if matches!(ch, b'1'..=b'9' | b'k') {
+                        return true;
+                    }
+                }
+            }
+            b'(' => {
+                let rest = &pattern[pos + 1..];
+                if [&b"?="[..], b"?!", b"?<=", b"?<!", b"?>"]
+                    .iter()
+                    .any(|prefix| rest.starts_with(prefix))
+                {
+                    return true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    false
+}
@@ -29,8 +29,10 @@ use store::{ahash::AHashSet, blake3, write::now};
 
 pub mod get;
 pub mod ingest;
+pub mod limits;
 pub mod query;
 pub mod set;
+pub mod test;
 pub mod validate;
 
 pub struct ActiveScript {
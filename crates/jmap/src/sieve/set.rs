@@ -53,7 +53,11 @@ use store::{
     BlobClass,
 };
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{
+    activity::{Activity, ActivityType},
+    auth::AccessToken,
+    JMAP,
+};
 
 struct SetContext<'x> {
     account_id: u32,
@@ -96,6 +100,7 @@ impl JMAP {
 
         // Process creates
         let mut changes = ChangeLogBuilder::new();
+        let mut created_names = Vec::new();
         for (id, object) in request.unwrap_create() {
             if sieve_ids.len() as usize <= self.config.sieve_max_scripts {
                 match self.sieve_set_item(object, None, &ctx).await? {
@@ -115,6 +120,12 @@ impl JMAP {
                         };
                         let script_size = blob_id.section.as_ref().unwrap().size;
                         let blob_id = blob_id.clone();
+                        if let Some(Value::Text(name)) = builder
+                            .changes()
+                            .and_then(|c| c.properties.get(&Property::Name))
+                        {
+                            created_names.push(name.clone());
+                        }
 
                         // Write record
                         let mut batch = BatchBuilder::new();
@@ -348,6 +359,18 @@ impl JMAP {
             ctx.response.new_state = Some(self.commit_changes(account_id, changes).await?.into());
         }
 
+        // Add created filters to the activity feed
+        for name in created_names {
+            self.activity_notify(
+                account_id,
+                Activity::new(
+                    ActivityType::FilterCreated,
+                    format!("Filter {name:?} was created."),
+                ),
+            )
+            .await;
+        }
+
         Ok(ctx.response)
     }
 
@@ -535,6 +558,12 @@ impl JMAP {
                     // Compile script
                     match self.sieve_compiler.compile(&bytes) {
                         Ok(script) => {
+                            // Validate regular expressions
+                            if let Err(reason) = self.config.sieve_regex.validate(&script) {
+                                return Ok(Err(SetError::new(SetErrorType::InvalidScript)
+                                    .with_description(reason)));
+                            }
+
                             changes.set(Property::BlobId, BlobId::default().with_section_size(bytes.len()));
                             bytes.extend(bincode::serialize(&script).unwrap_or_default());
                             bytes.into()
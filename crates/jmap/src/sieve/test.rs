@@ -0,0 +1,261 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::test::{SieveAction, TestSieveScriptRequest, TestSieveScriptResponse},
+    types::{collection::Collection, id::Id},
+};
+use mail_parser::MessageParser;
+use sieve::{Envelope, Event, Input, Mailbox, Recipient};
+
+use crate::{auth::AccessToken, JMAP};
+
+impl JMAP {
+    pub async fn sieve_script_test(
+        &self,
+        request: TestSieveScriptRequest,
+        access_token: &AccessToken,
+    ) -> Result<TestSieveScriptResponse, MethodError> {
+        let mut response = TestSieveScriptResponse {
+            account_id: request.account_id,
+            actions: Vec::new(),
+            trace: Vec::new(),
+            error: None,
+        };
+        let account_id = request.account_id.document_id();
+
+        // Compile script
+        let script = match self.blob_download(&request.blob_id, access_token).await? {
+            Some(bytes) => match self
+                .sieve_compiler
+                .compile(&bytes)
+                .map_err(|err| err.to_string())
+                .and_then(|script| self.config.sieve_regex.validate(&script).map(|_| script))
+            {
+                Ok(script) => script,
+                Err(reason) => {
+                    response.error = SetError::new(SetErrorType::InvalidScript)
+                        .with_description(reason)
+                        .into();
+                    return Ok(response);
+                }
+            },
+            None => {
+                response.error = SetError::new(SetErrorType::BlobNotFound)
+                    .with_description("Script blob does not exist.")
+                    .into();
+                return Ok(response);
+            }
+        };
+
+        // Parse sample message
+        let raw_message = match self
+            .blob_download(&request.email_blob_id, access_token)
+            .await?
+        {
+            Some(raw_message) => raw_message,
+            None => {
+                response.error = SetError::new(SetErrorType::BlobNotFound)
+                    .with_description("Email blob does not exist.")
+                    .into();
+                return Ok(response);
+            }
+        };
+        let message = if let Some(message) = MessageParser::new().parse(&raw_message) {
+            message
+        } else {
+            response.error = SetError::invalid_properties()
+                .with_description("Failed to parse sample message.")
+                .into();
+            return Ok(response);
+        };
+        // Mailboxes are only read, a dry run must not create the default ones
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+
+        // Run the script without side effects
+        let mut instance = self.sieve_runtime.filter_parsed(message);
+        let user_address = match self.directory.query(QueryBy::Id(account_id), false).await {
+            Ok(Some(p)) => {
+                instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
+                p.emails.into_iter().next().unwrap_or_default()
+            }
+            Ok(None) => String::new(),
+            Err(_) => return Err(MethodError::ServerPartialFail),
+        };
+        instance.set_user_address(&user_address);
+        instance.set_envelope(
+            Envelope::From,
+            request.envelope_from.as_deref().unwrap_or_default(),
+        );
+        instance.set_envelope(
+            Envelope::To,
+            request
+                .envelope_to
+                .as_deref()
+                .unwrap_or(user_address.as_str()),
+        );
+        let mut input = Input::script("test", script);
+
+        while let Some(event) = instance.run(input) {
+            match event {
+                Ok(event) => match event {
+                    Event::IncludeScript { name, .. } => {
+                        if let Ok(Some(script)) =
+                            self.sieve_script_get_by_name(account_id, &name).await
+                        {
+                            response.trace.push(format!("include {name:?}"));
+                            input = Input::script(name, script);
+                        } else {
+                            response.trace.push(format!("include {name:?}: not found"));
+                            input = false.into();
+                        }
+                    }
+                    Event::MailboxExists {
+                        mailboxes,
+                        special_use,
+                    } => {
+                        let mut result = true;
+                        for mailbox in &mailboxes {
+                            result = match mailbox {
+                                Mailbox::Name(name) => matches!(
+                                    self.mailbox_get_by_name(account_id, name).await,
+                                    Ok(Some(_))
+                                ),
+                                Mailbox::Id(id) => matches!(
+                                    Id::from_bytes(id.as_bytes()),
+                                    Some(id) if mailbox_ids.contains(id.document_id())
+                                ),
+                            };
+                            if !result {
+                                break;
+                            }
+                        }
+                        if result {
+                            for role in &special_use {
+                                let role = role.to_ascii_lowercase();
+                                if role != "inbox"
+                                    && !matches!(
+                                        self.mailbox_get_by_role(account_id, &role).await,
+                                        Ok(Some(_))
+                                    )
+                                {
+                                    result = false;
+                                    break;
+                                }
+                            }
+                        }
+                        response.trace.push(format!(
+                            "mailboxexists {mailboxes:?} {special_use:?}: {result}"
+                        ));
+                        input = result.into();
+                    }
+                    Event::DuplicateId { id, .. } => {
+                        response.trace.push(format!("duplicate {id:?}: false"));
+                        input = false.into();
+                    }
+                    Event::Discard => {
+                        response.trace.push("discard".to_string());
+                        response.actions.push(SieveAction::Discard);
+                        input = true.into();
+                    }
+                    Event::Reject { reason, .. } => {
+                        response.trace.push(format!("reject {reason:?}"));
+                        response.actions.push(SieveAction::Reject { reason });
+                        input = true.into();
+                    }
+                    Event::Keep { flags, .. } => {
+                        response.trace.push(format!("keep {flags:?}"));
+                        response.actions.push(SieveAction::Keep { flags });
+                        input = true.into();
+                    }
+                    Event::FileInto {
+                        folder,
+                        flags,
+                        create,
+                        ..
+                    } => {
+                        response
+                            .trace
+                            .push(format!("fileinto {folder:?} {flags:?}"));
+                        response.actions.push(SieveAction::FileInto {
+                            mailbox: folder,
+                            flags,
+                            create,
+                        });
+                        input = true.into();
+                    }
+                    Event::SendMessage { recipient, .. } => {
+                        let recipients = match recipient {
+                            Recipient::Address(rcpt) => vec![rcpt],
+                            Recipient::Group(rcpts) => rcpts,
+                            Recipient::List(list) => vec![list],
+                        };
+                        response
+                            .trace
+                            .push(format!("send message to {recipients:?}"));
+                        response
+                            .actions
+                            .push(SieveAction::SendMessage { recipients });
+                        input = true.into();
+                    }
+                    Event::Notify {
+                        method, message, ..
+                    } => {
+                        response.trace.push(format!("notify {method:?}"));
+                        response
+                            .actions
+                            .push(SieveAction::Notify { method, message });
+                        input = true.into();
+                    }
+                    Event::ListContains { .. }
+                    | Event::Function { .. }
+                    | Event::SetEnvelope { .. } => {
+                        // Not allowed
+                        input = false.into();
+                    }
+                    Event::CreatedMessage { .. } => {
+                        response.trace.push("created message".to_string());
+                        input = true.into();
+                    }
+                },
+                Err(err) => {
+                    response.trace.push(format!("error: {err}"));
+                    input = true.into();
+                }
+            }
+        }
+        if instance.has_message_changed() {
+            response.trace.push("message was modified".to_string());
+        }
+
+        Ok(response)
+    }
+}
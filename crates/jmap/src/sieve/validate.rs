@@ -42,11 +42,15 @@ impl JMAP {
             error: match self
                 .blob_download(&request.blob_id, access_token)
                 .await?
-                .map(|bytes| self.sieve_compiler.compile(&bytes))
-            {
+                .map(|bytes| {
+                    self.sieve_compiler
+                        .compile(&bytes)
+                        .map_err(|err| err.to_string())
+                        .and_then(|script| self.config.sieve_regex.validate(&script))
+                }) {
                 Some(Ok(_)) => None,
-                Some(Err(err)) => SetError::new(SetErrorType::InvalidScript)
-                    .with_description(err.to_string())
+                Some(Err(reason)) => SetError::new(SetErrorType::InvalidScript)
+                    .with_description(reason)
                     .into(),
                 None => SetError::new(SetErrorType::BlobNotFound).into(),
             },
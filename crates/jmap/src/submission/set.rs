@@ -23,6 +23,7 @@
 
 use std::{collections::HashMap, sync::Arc};
 
+use directory::QueryBy;
 use jmap_proto::{
     error::{
         method::MethodError,
@@ -57,7 +58,14 @@ use store::write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder
 use tokio::sync::oneshot;
 use utils::{listener::ServerInstance, map::vec_map::VecMap};
 
-use crate::{email::metadata::MessageMetadata, identity::set::sanitize_email, Bincode, JMAP};
+use crate::{
+    email::metadata::MessageMetadata,
+    identity::{delegate::SendAsMode, set::sanitize_email},
+    services::report::TrafficDirection,
+    Bincode, JMAP,
+};
+
+use super::status::initial_delivery_status;
 
 pub static SCHEMA: &[IndexProperty] = &[
     IndexProperty::new(Property::UndoStatus).index_as(IndexAs::Text {
@@ -68,6 +76,7 @@ pub static SCHEMA: &[IndexProperty] = &[
     IndexProperty::new(Property::IdentityId).index_as(IndexAs::Integer),
     IndexProperty::new(Property::ThreadId).index_as(IndexAs::Integer),
     IndexProperty::new(Property::SendAt).index_as(IndexAs::LongInteger),
+    IndexProperty::new(Property::MessageId).index_as(IndexAs::LongInteger),
 ];
 
 impl JMAP {
@@ -101,6 +110,7 @@ impl JMAP {
                     let document_id = self
                         .assign_document_id(account_id, Collection::EmailSubmission)
                         .await?;
+                    let recipients = initial_delivery_status(&submission);
                     batch
                         .with_account_id(account_id)
                         .with_collection(Collection::EmailSubmission)
@@ -109,6 +119,12 @@ impl JMAP {
                     self.write_batch(batch).await?;
                     changes.log_insert(Collection::EmailSubmission, document_id);
                     response.created(id, document_id);
+
+                    // Track the delivery status of each recipient, the message
+                    // has already been queued so failures here are not fatal
+                    self.delivery_status_set(account_id, document_id, &recipients, true)
+                        .await
+                        .ok();
                 }
                 Err(err) => {
                     response.not_created.append(id, err);
@@ -256,6 +272,8 @@ impl JMAP {
                     .custom(ObjectIndexBuilder::new(SCHEMA).with_current(submission));
                 self.write_batch(batch).await?;
                 changes.log_delete(Collection::EmailSubmission, document_id);
+                self.delivery_status_destroy(account_id, document_id, &mut changes)
+                    .await?;
                 response.destroyed.push(id);
             } else {
                 response.not_destroyed.append(id, SetError::not_found());
@@ -518,6 +536,40 @@ impl JMAP {
                 .with_description("Email not found.")));
         };
 
+        // Messages sent on behalf of another account must name the actual sender
+        if self
+            .send_as_delegations(account_id)
+            .await?
+            .into_iter()
+            .any(|delegation| {
+                delegation.identity_id == identity_id && delegation.mode == SendAsMode::OnBehalf
+            })
+        {
+            let emails = self
+                .directory
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .unwrap_or_default()
+                .unwrap_or_default()
+                .emails;
+            let has_sender = metadata.contents.parts[0]
+                .headers
+                .iter()
+                .filter(|header| header.name == HeaderName::Sender)
+                .filter_map(|header| match &header.value {
+                    HeaderValue::Address(addr) => Some(addr),
+                    _ => None,
+                })
+                .flat_map(|addr| addr.iter())
+                .filter_map(|addr| addr.address())
+                .any(|addr| emails.iter().any(|email| email.eq_ignore_ascii_case(addr)));
+            if !has_sender {
+                return Ok(Err(SetError::new(SetErrorType::ForbiddenFrom).with_description(
+                    "Sending on behalf of this identity requires a Sender header with your address.",
+                )));
+            }
+        }
+
         // Add recipients to envelope if missing
         if rcpt_to.is_empty() {
             let mut envelope_values = Vec::new();
@@ -591,6 +643,11 @@ impl JMAP {
         };
 
         // Begin local SMTP session
+        let sender_domain = mail_from
+            .address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_string())
+            .unwrap_or_default();
         let mut session =
             Session::<NullIo>::local(self.smtp.clone(), instance.clone(), SessionData::default());
 
@@ -604,6 +661,11 @@ impl JMAP {
                 ))));
         }
 
+        // Request delivery status updates from the queue
+        if let Some(mail_from) = &mut session.data.mail_from {
+            mail_from.flags |= queue::MAIL_NOTIFY_STATUS;
+        }
+
         // RCPT TO
         let mut responses = Vec::new();
         let mut has_success = false;
@@ -619,10 +681,18 @@ impl JMAP {
 
         // DATA
         if has_success {
+            let size = message.len();
             session.data.message = message;
             let response = session.queue_message().await;
             if let State::Accepted(queue_id) = session.state {
                 submission.append(Property::MessageId, queue_id);
+                self.traffic_record(
+                    TrafficDirection::Outbound,
+                    account_id,
+                    sender_domain.as_str(),
+                    size,
+                )
+                .await;
             } else {
                 return Ok(Err(SetError::new(SetErrorType::ForbiddenToSend)
                     .with_description(format!(
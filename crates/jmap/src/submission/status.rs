@@ -0,0 +1,356 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::{
+        index::{IndexAs, IndexProperty, ObjectIndexBuilder},
+        Object,
+    },
+    types::{
+        collection::Collection, date::UTCDate, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder},
+};
+use utils::ipc::{DeliveryStatus, RecipientStatus};
+
+use crate::JMAP;
+
+pub static SCHEMA: &[IndexProperty] = &[
+    IndexProperty::new(Property::EmailSubmissionId)
+        .index_as(IndexAs::Integer)
+        .required(),
+    IndexProperty::new(Property::Email)
+        .index_as(IndexAs::Text {
+            tokenize: false,
+            index: true,
+        })
+        .max_size(255)
+        .required(),
+];
+
+// Status updates can arrive before the submission that queued the message is
+// stored, they are retried after 1s, 2s, 4s... up to STATUS_MAX_RETRIES times
+pub const STATUS_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const STATUS_MAX_RETRIES: u32 = 7;
+
+impl JMAP {
+    pub async fn delivery_status_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let properties = request.unwrap_properties(&[
+            Property::Id,
+            Property::EmailSubmissionId,
+            Property::Email,
+            Property::Status,
+            Property::SmtpReply,
+            Property::UpdatedAt,
+        ]);
+        let account_id = request.account_id.document_id();
+        let status_ids = self
+            .get_document_ids(account_id, Collection::DeliveryStatus)
+            .await?
+            .unwrap_or_default();
+        let ids = if let Some(ids) = ids {
+            ids
+        } else {
+            status_ids
+                .iter()
+                .take(self.config.get_max_objects)
+                .map(Into::into)
+                .collect::<Vec<_>>()
+        };
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::DeliveryStatus)
+                .await?
+                .into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            // Obtain the delivery status object
+            let document_id = id.document_id();
+            if !status_ids.contains(document_id) {
+                response.not_found.push(id.into());
+                continue;
+            }
+            let mut status = if let Some(status) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::DeliveryStatus,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                status
+            } else {
+                response.not_found.push(id.into());
+                continue;
+            };
+            let mut result = Object::with_capacity(properties.len());
+            for property in &properties {
+                match property {
+                    Property::Id => {
+                        result.append(Property::Id, Value::Id(id));
+                    }
+                    property => {
+                        result.append(property.clone(), status.remove(property));
+                    }
+                }
+            }
+            response.list.push(result);
+        }
+
+        Ok(response)
+    }
+
+    // Applies a status update received from the SMTP queue, returns false
+    // when no submission for the queued message was found.
+    pub async fn delivery_status_update(
+        &self,
+        queue_id: u64,
+        return_path: &str,
+        recipients: &[RecipientStatus],
+    ) -> bool {
+        let account_ids = match self.directory.email_to_ids(return_path).await {
+            Ok(account_ids) => account_ids,
+            Err(err) => {
+                tracing::warn!(
+                    context = "delivery_status",
+                    event = "error",
+                    return_path = return_path,
+                    reason = ?err,
+                    "Failed to lookup sender account."
+                );
+                return true;
+            }
+        };
+
+        for account_id in account_ids {
+            let submission_id = match self
+                .filter(
+                    account_id,
+                    Collection::EmailSubmission,
+                    vec![Filter::eq(Property::MessageId, queue_id)],
+                )
+                .await
+            {
+                Ok(result) => result.results.min(),
+                Err(_) => return true,
+            };
+
+            if let Some(submission_id) = submission_id {
+                if self
+                    .delivery_status_set(account_id, submission_id, recipients, false)
+                    .await
+                    .is_err()
+                {
+                    tracing::warn!(
+                        context = "delivery_status",
+                        event = "error",
+                        account_id = account_id,
+                        queue_id = queue_id,
+                        "Failed to update delivery status."
+                    );
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub async fn delivery_status_set(
+        &self,
+        account_id: u32,
+        submission_id: u32,
+        recipients: &[RecipientStatus],
+        create_only: bool,
+    ) -> Result<(), MethodError> {
+        let mut changes = ChangeLogBuilder::new();
+        let updated_at = Value::Date(UTCDate::from_timestamp(now() as i64));
+
+        for rcpt in recipients {
+            let address = rcpt.address.to_lowercase();
+            let status = Object::with_capacity(3)
+                .with_property(Property::Status, rcpt.status.as_str())
+                .with_property(Property::SmtpReply, rcpt.response.clone())
+                .with_property(Property::UpdatedAt, updated_at.clone());
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::DeliveryStatus);
+
+            if let Some(document_id) = self
+                .filter(
+                    account_id,
+                    Collection::DeliveryStatus,
+                    vec![
+                        Filter::eq(Property::EmailSubmissionId, submission_id),
+                        Filter::eq(Property::Email, &address),
+                    ],
+                )
+                .await?
+                .results
+                .min()
+            {
+                if create_only {
+                    continue;
+                }
+                let current = if let Some(current) = self
+                    .get_property::<HashedValue<Object<Value>>>(
+                        account_id,
+                        Collection::DeliveryStatus,
+                        document_id,
+                        Property::Value,
+                    )
+                    .await?
+                {
+                    current
+                } else {
+                    continue;
+                };
+
+                // Only record actual status changes
+                if [Property::Status, Property::SmtpReply]
+                    .iter()
+                    .all(|property| {
+                        current.inner.properties.get(property) == status.properties.get(property)
+                    })
+                {
+                    continue;
+                }
+
+                batch.update_document(document_id).custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(current)
+                        .with_changes(status),
+                );
+                changes.log_update(Collection::DeliveryStatus, document_id);
+            } else {
+                let document_id = self
+                    .assign_document_id(account_id, Collection::DeliveryStatus)
+                    .await?;
+                batch.create_document(document_id).custom(
+                    ObjectIndexBuilder::new(SCHEMA).with_changes(
+                        status
+                            .with_property(
+                                Property::EmailSubmissionId,
+                                Value::Id(submission_id.into()),
+                            )
+                            .with_property(Property::Email, address),
+                    ),
+                );
+                changes.log_insert(Collection::DeliveryStatus, document_id);
+            }
+
+            self.write_batch(batch).await?;
+        }
+
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id).with_change(DataType::DeliveryStatus, change_id),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
+    pub async fn delivery_status_destroy(
+        &self,
+        account_id: u32,
+        submission_id: u32,
+        changes: &mut ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        for document_id in self
+            .filter(
+                account_id,
+                Collection::DeliveryStatus,
+                vec![Filter::eq(Property::EmailSubmissionId, submission_id)],
+            )
+            .await?
+            .results
+        {
+            if let Some(status) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::DeliveryStatus,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::DeliveryStatus)
+                    .delete_document(document_id)
+                    .custom(ObjectIndexBuilder::new(SCHEMA).with_current(status));
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::DeliveryStatus, document_id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Builds the initial recipient status list from the RCPT TO responses of a new submission
+pub(crate) fn initial_delivery_status(submission: &Object<Value>) -> Vec<RecipientStatus> {
+    let mut recipients = Vec::new();
+    if let Some(Value::Object(status)) = submission.properties.get(&Property::DeliveryStatus) {
+        for (property, value) in status.properties.iter() {
+            if let (Property::_T(address), Value::Object(rcpt)) = (property, value) {
+                recipients.push(RecipientStatus {
+                    address: address.to_string(),
+                    status: if rcpt.get(&Property::Delivered).as_string() == Some("no") {
+                        DeliveryStatus::Failed
+                    } else {
+                        DeliveryStatus::Queued
+                    },
+                    response: rcpt
+                        .get(&Property::SmtpReply)
+                        .as_string()
+                        .unwrap_or_default()
+                        .to_string(),
+                });
+            }
+        }
+    }
+    recipients
+}
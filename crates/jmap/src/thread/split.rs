@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::thread::{
+        MergeThreadRequest, MergeThreadResponse, SplitThreadRequest, SplitThreadResponse,
+    },
+    types::{
+        collection::Collection,
+        id::Id,
+        property::Property,
+        state::{State, StateChange},
+        type_state::DataType,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    write::{log::ChangeLogBuilder, BatchBuilder, F_BITMAP, F_CLEAR, F_VALUE},
+};
+use utils::map::vec_map::VecMap;
+
+use crate::{mailbox::UidMailbox, JMAP};
+
+impl JMAP {
+    pub async fn thread_split(
+        &self,
+        request: SplitThreadRequest,
+    ) -> Result<SplitThreadResponse, MethodError> {
+        if request.email_ids.len() > self.config.set_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+
+        let account_id = request.account_id.document_id();
+        let old_state = self.get_state(account_id, Collection::Thread).await?;
+        let mut response = SplitThreadResponse {
+            account_id: request.account_id,
+            old_state: old_state.clone(),
+            new_state: old_state,
+            thread_id: None,
+            email_ids: Vec::new(),
+            not_split: VecMap::new(),
+            state_change: None,
+        };
+
+        // Obtain the current threadId of each message
+        let mut moves: Vec<(u32, u32)> = Vec::with_capacity(request.email_ids.len());
+        for id in &request.email_ids {
+            let document_id = id.document_id();
+            if moves.iter().any(|(moved_id, _)| *moved_id == document_id) {
+                continue;
+            }
+            if let Some(thread_id) = self
+                .get_property::<u32>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::ThreadId,
+                )
+                .await?
+            {
+                moves.push((document_id, thread_id));
+            } else {
+                response.not_split.append(*id, SetError::not_found());
+            }
+        }
+        if moves.is_empty() {
+            return Ok(response);
+        }
+
+        // Move the messages to a new thread
+        let thread_id = self
+            .assign_document_id(account_id, Collection::Thread)
+            .await?;
+        match self
+            .thread_move_emails(account_id, thread_id, true, moves)
+            .await?
+        {
+            Some((change_id, email_ids)) => {
+                response.thread_id = Id::from(thread_id).into();
+                response.email_ids = email_ids;
+                response.new_state = State::Exact(change_id);
+                response.state_change = thread_state_change(account_id, change_id).into();
+            }
+            None => {
+                for id in request.email_ids {
+                    if !response.not_split.contains_key(&id) {
+                        response.not_split.append(id, thread_modified_error());
+                    }
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    pub async fn thread_merge(
+        &self,
+        request: MergeThreadRequest,
+    ) -> Result<MergeThreadResponse, MethodError> {
+        if request.thread_ids.len() > self.config.set_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+
+        // Threads are merged into the first one listed
+        let account_id = request.account_id.document_id();
+        let target_id = *request.thread_ids.first().ok_or_else(|| {
+            MethodError::InvalidArguments("At least one threadId is required.".to_string())
+        })?;
+        let thread_id = target_id.document_id();
+        let old_state = self.get_state(account_id, Collection::Thread).await?;
+        let mut response = MergeThreadResponse {
+            account_id: request.account_id,
+            old_state: old_state.clone(),
+            new_state: old_state,
+            thread_id: target_id,
+            email_ids: Vec::new(),
+            not_merged: VecMap::new(),
+            state_change: None,
+        };
+        if self
+            .get_tag(account_id, Collection::Email, Property::ThreadId, thread_id)
+            .await?
+            .is_none()
+        {
+            response.not_merged.append(target_id, SetError::not_found());
+            return Ok(response);
+        }
+
+        // Obtain the messages of each thread
+        let mut moves = Vec::new();
+        let mut merged_ids = Vec::with_capacity(request.thread_ids.len());
+        for id in request.thread_ids.iter().skip(1) {
+            let merge_thread_id = id.document_id();
+            if merge_thread_id == thread_id || merged_ids.contains(id) {
+                continue;
+            }
+            if let Some(document_ids) = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::ThreadId,
+                    merge_thread_id,
+                )
+                .await?
+            {
+                moves.extend(
+                    document_ids
+                        .into_iter()
+                        .map(|document_id| (document_id, merge_thread_id)),
+                );
+                merged_ids.push(*id);
+            } else {
+                response.not_merged.append(*id, SetError::not_found());
+            }
+        }
+        if moves.is_empty() {
+            return Ok(response);
+        }
+
+        match self
+            .thread_move_emails(account_id, thread_id, false, moves)
+            .await?
+        {
+            Some((change_id, email_ids)) => {
+                response.email_ids = email_ids;
+                response.new_state = State::Exact(change_id);
+                response.state_change = thread_state_change(account_id, change_id).into();
+            }
+            None => {
+                for id in merged_ids {
+                    response.not_merged.append(id, thread_modified_error());
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    async fn thread_move_emails(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+        is_new_thread: bool,
+        moves: Vec<(u32, u32)>,
+    ) -> Result<Option<(u64, Vec<Id>)>, MethodError> {
+        let change_id = self.assign_change_id(account_id).await?;
+        let mut changes = ChangeLogBuilder::with_change_id(change_id);
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Thread);
+        if is_new_thread {
+            batch.create_document(thread_id);
+            changes.log_insert(Collection::Thread, thread_id);
+        } else {
+            changes.log_child_update(Collection::Thread, thread_id);
+        }
+
+        // Delete the threads that are left without messages
+        let mut moved_count = AHashMap::new();
+        for (_, old_thread_id) in &moves {
+            *moved_count.entry(*old_thread_id).or_insert(0u64) += 1;
+        }
+        for (old_thread_id, moved) in moved_count {
+            let remaining = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::ThreadId,
+                    old_thread_id,
+                )
+                .await?
+                .map_or(0, |document_ids| document_ids.len());
+            if remaining <= moved {
+                batch.delete_document(old_thread_id);
+                changes.log_delete(Collection::Thread, old_thread_id);
+            } else {
+                changes.log_child_update(Collection::Thread, old_thread_id);
+            }
+        }
+
+        // Move messages to the new threadId
+        batch.with_collection(Collection::Email);
+        let mut email_ids = Vec::with_capacity(moves.len());
+        for (document_id, old_thread_id) in moves {
+            // Thread counts of the message mailboxes change as well
+            for mailbox in self
+                .get_property::<Vec<UidMailbox>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::MailboxIds,
+                )
+                .await?
+                .unwrap_or_default()
+            {
+                changes.log_child_update(Collection::Mailbox, mailbox.mailbox_id);
+            }
+
+            let email_id = Id::from_parts(thread_id, document_id);
+            batch
+                .update_document(document_id)
+                .assert_value(Property::ThreadId, old_thread_id)
+                .value(Property::ThreadId, old_thread_id, F_BITMAP | F_CLEAR)
+                .value(Property::ThreadId, thread_id, F_VALUE | F_BITMAP);
+            changes.log_move(
+                Collection::Email,
+                Id::from_parts(old_thread_id, document_id),
+                email_id,
+            );
+            email_ids.push(email_id);
+        }
+        batch.custom(changes);
+
+        match self.store.write(batch.build()).await {
+            Ok(_) => Ok(Some((change_id, email_ids))),
+            Err(store::Error::AssertValueFailed) => Ok(None),
+            Err(err) => {
+                tracing::error!(
+                    event = "error",
+                    context = "thread_move_emails",
+                    error = ?err,
+                    "Failed to write thread batch.");
+                Err(MethodError::ServerPartialFail)
+            }
+        }
+    }
+}
+
+fn thread_state_change(account_id: u32, change_id: u64) -> StateChange {
+    StateChange::new(account_id)
+        .with_change(DataType::Email, change_id)
+        .with_change(DataType::Thread, change_id)
+        .with_change(DataType::Mailbox, change_id)
+}
+
+fn thread_modified_error() -> SetError {
+    SetError::forbidden()
+        .with_description("Another process modified this thread, please try again.")
+}
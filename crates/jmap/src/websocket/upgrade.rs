@@ -29,7 +29,7 @@ use hyper_util::rt::TokioIo;
 use jmap_proto::error::request::RequestError;
 use tokio_tungstenite::WebSocketStream;
 use tungstenite::{handshake::derive_accept_key, protocol::Role};
-use utils::listener::ServerInstance;
+use utils::listener::{limiter::InFlight, ServerInstance};
 
 use crate::{
     api::{http::ToHttpResponse, HttpRequest, HttpResponse},
@@ -42,6 +42,7 @@ pub async fn upgrade_websocket_connection(
     req: HttpRequest,
     access_token: Arc<AccessToken>,
     instance: Arc<ServerInstance>,
+    in_flight: InFlight,
 ) -> HttpResponse {
     let headers = req.headers();
     if headers
@@ -79,8 +80,10 @@ pub async fn upgrade_websocket_connection(
         }
     };
 
-    // Spawn WebSocket connection
+    // Spawn WebSocket connection, it counts towards the account's connections until closed
     tokio::spawn(async move {
+        let _in_flight = in_flight;
+
         // Upgrade connection
         match hyper::upgrade::on(req).await {
             Ok(upgraded) => {
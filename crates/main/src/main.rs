@@ -21,14 +21,14 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use directory::core::config::ConfigDirectory;
 use imap::core::{ImapSessionManager, IMAP};
 use jmap::{api::JmapSessionManager, services::IPC_CHANNEL_BUFFER, JMAP};
 use managesieve::core::ManageSieveSessionManager;
 use smtp::core::{SmtpSessionManager, SMTP};
-use store::config::ConfigStore;
+use store::{config::ConfigStore, write::DirectoryClass};
 use tokio::sync::mpsc;
 use utils::{
     config::{Config, ServerProtocol},
@@ -46,6 +46,11 @@ static GLOBAL: Jemalloc = Jemalloc;
 async fn main() -> std::io::Result<()> {
     let config = Config::init();
 
+    // Validate the configuration without starting any listeners
+    if std::env::args().any(|arg| arg == "check-config") {
+        std::process::exit(if check_config(&config).await { 0 } else { 1 });
+    }
+
     // Enable tracing
     let _tracer = enable_tracing(
         &config,
@@ -57,8 +62,10 @@ async fn main() -> std::io::Result<()> {
     .failed("Failed to enable tracing");
 
     // Bind ports and drop privileges
-    let servers = config.parse_servers().failed("Invalid configuration");
+    let mut servers = config.parse_servers().failed("Invalid configuration");
     servers.bind(&config);
+    let connections = servers.connections.clone();
+    let drain_timeout = servers.drain_timeout;
 
     // Parse stores and directories
     let stores = config.parse_stores().await.failed("Invalid configuration");
@@ -77,12 +84,27 @@ async fn main() -> std::io::Result<()> {
 
     // Init servers
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &stores, &directory, delivery_tx)
-        .await
-        .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &stores, &directory, delivery_rx, smtp.clone())
-        .await
-        .failed("Invalid configuration file");
+    let (event_tx, event_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
+    let smtp = SMTP::init(
+        &config,
+        &servers,
+        &stores,
+        &directory,
+        delivery_tx,
+        event_tx,
+    )
+    .await
+    .failed("Invalid configuration file");
+    let jmap = JMAP::init(
+        &config,
+        &stores,
+        &directory,
+        delivery_rx,
+        event_rx,
+        smtp.clone(),
+    )
+    .await
+    .failed("Invalid configuration file");
     let imap = IMAP::init(&config)
         .await
         .failed("Invalid configuration file");
@@ -115,6 +137,17 @@ async fn main() -> std::io::Result<()> {
         scheduler.spawn(shutdown_rx.clone());
     }
 
+    // Notify systemd
+    #[cfg(unix)]
+    {
+        let jmap = jmap.clone();
+        utils::systemd::spawn_watchdog(move || {
+            let jmap = jmap.clone();
+            async move { jmap.is_alive().await }
+        });
+        utils::systemd::notify("READY=1");
+    }
+
     // Wait for shutdown signal
     wait_for_shutdown(&format!(
         "Shutting down Stalwart Mail Server v{}...",
@@ -122,11 +155,122 @@ async fn main() -> std::io::Result<()> {
     ))
     .await;
 
-    // Stop services
+    // Stop accepting connections and notify sessions
+    #[cfg(unix)]
+    utils::systemd::notify("STOPPING=1");
     let _ = shutdown_tx.send(true);
 
+    // Wait for in-flight sessions to finish
+    let drain_until = Instant::now() + drain_timeout;
+    while connections.num_connections() > 0 && Instant::now() < drain_until {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    if connections.num_connections() > 0 {
+        tracing::warn!(
+            event = "shutdown",
+            connections = connections.num_connections(),
+            "Drain timeout reached, closing remaining connections."
+        );
+    }
+
+    // Wait for in-progress deliveries to be saved to the queue
+    smtp.queue.checkpoint(drain_until).await;
+
     // Wait for services to finish
     tokio::time::sleep(Duration::from_secs(1)).await;
 
     Ok(())
 }
+
+async fn check_config(config: &Config) -> bool {
+    let mut is_valid = true;
+    let mut report = |check: &str, result: Result<(), String>| match result {
+        Ok(()) => println!("[ OK ] {check}"),
+        Err(err) => {
+            println!("[FAIL] {check}: {err}");
+            is_valid = false;
+        }
+    };
+
+    // Parse listeners and TLS certificates
+    let servers = config.parse_servers();
+    report(
+        "Listeners and certificates",
+        servers.as_ref().map(|_| ()).map_err(Clone::clone),
+    );
+
+    // Parse stores and test connectivity
+    let stores = match config.parse_stores().await {
+        Ok(stores) => stores,
+        Err(err) => {
+            report("Stores", Err(err));
+            return false;
+        }
+    };
+    for (id, store) in &stores.stores {
+        report(
+            &format!("Data store {id:?}"),
+            probe(store.get_counter(DirectoryClass::UsedQuota(u32::MAX))).await,
+        );
+    }
+    for (id, store) in &stores.blob_stores {
+        report(
+            &format!("Blob store {id:?}"),
+            probe(store.get_blob(b"check-config", 0..1)).await,
+        );
+    }
+    report(
+        "Purge schedules",
+        config
+            .parse_purge_schedules(
+                &stores,
+                config.value("jmap.store.data"),
+                config.value("jmap.store.blob"),
+            )
+            .await
+            .map(|_| ()),
+    );
+
+    // Parse directories and test connectivity
+    let directory = match config
+        .parse_directory(&stores, config.value("jmap.store.data"))
+        .await
+    {
+        Ok(directory) => directory,
+        Err(err) => {
+            report("Directories", Err(err));
+            return false;
+        }
+    };
+    for (id, directory) in &directory.directories {
+        report(
+            &format!("Directory {id:?}"),
+            probe(directory.is_local_domain("check-config.invalid")).await,
+        );
+    }
+
+    // Parse protocol settings, Sieve scripts and rules
+    if let Ok(servers) = &servers {
+        report(
+            "SMTP settings, Sieve scripts and rules",
+            SMTP::check_config(config, servers, &stores, &directory),
+        );
+    }
+    report(
+        "JMAP settings",
+        JMAP::check_config(config, &stores, &directory),
+    );
+    report("IMAP settings", IMAP::init(config).await.map(|_| ()));
+
+    is_valid
+}
+
+async fn probe<T, E: std::fmt::Debug>(
+    future: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<(), String> {
+    match tokio::time::timeout(Duration::from_secs(10), future).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(format!("{err:?}")),
+        Err(_) => Err("Timed out".to_string()),
+    }
+}
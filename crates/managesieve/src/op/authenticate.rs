@@ -76,7 +76,12 @@ impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
         };
 
         // Throttle authentication requests
-        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
+        if self
+            .jmap
+            .is_auth_allowed_soft(&self.remote_addr)
+            .await
+            .is_err()
+        {
             tracing::debug!(parent: &self.span,
                 event = "disconnect",
                 "Too many authentication attempts, disconnecting.",
@@ -90,7 +95,7 @@ impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
         let access_token = match credentials {
             Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
                 self.jmap
-                    .authenticate_plain(&username, &secret, &self.remote_addr)
+                    .authenticate_plain(&username, &secret, &self.remote_addr, "ManageSieve")
                     .await
             }
             Credentials::OAuthBearer { token } => {
@@ -99,7 +104,7 @@ impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
                     .validate_access_token("access_token", &token)
                     .await
                 {
-                    Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
+                    Ok(token) => self.jmap.get_access_token(token.account_id).await,
                     Err(err) => {
                         tracing::debug!(
                             parent: &self.span,
@@ -120,8 +125,18 @@ impl<T: AsyncRead + AsyncWrite + IsTls> Session<T> {
                 .get_authenticated_limiter(access_token.primary_id())
                 .lock()
                 .concurrent_requests
-                .is_allowed();
+                .is_allowed()
+                .and_then(|mut in_flight| {
+                    // Connections shared with other protocols
+                    in_flight.link(self.instance.connections.is_account_allowed(
+                        access_token.primary_id(),
+                        self.instance.max_connections_account,
+                    )?);
+                    Some(in_flight)
+                });
             if let Some(in_flight) = in_flight {
+                self.span.record("account", access_token.name.as_str());
+
                 // Cache access token
                 let access_token = Arc::new(access_token);
                 self.jmap.cache_access_token(access_token.clone());
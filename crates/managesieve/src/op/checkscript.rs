@@ -32,10 +32,17 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
             return Err(StatusResponse::no("Expected script as a parameter."));
         }
 
-        self.jmap
+        let script = request.tokens.into_iter().next().unwrap().unwrap_bytes();
+        let script = self
+            .jmap
             .sieve_compiler
-            .compile(&request.tokens.into_iter().next().unwrap().unwrap_bytes())
+            .compile(&script)
+            .map_err(|err| StatusResponse::no(err.to_string()))?;
+        self.jmap
+            .config
+            .sieve_regex
+            .validate(&script)
             .map(|_| StatusResponse::ok("Script is valid.").into_bytes())
-            .map_err(|err| StatusResponse::no(err.to_string()))
+            .map_err(StatusResponse::no)
     }
 }
@@ -77,6 +77,13 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
         // Compile script
         match self.jmap.sieve_compiler.compile(&script_bytes) {
             Ok(compiled_script) => {
+                // Validate regular expressions
+                self.jmap
+                    .config
+                    .sieve_regex
+                    .validate(&compiled_script)
+                    .map_err(StatusResponse::no)?;
+
                 script_bytes.extend(bincode::serialize(&compiled_script).unwrap_or_default());
             }
             Err(err) => {
@@ -135,6 +135,7 @@ impl ConfigCondition for Config {
                     Lookup,
                     StartsWith,
                     EndsWith,
+                    Script,
                 }
 
                 let (op, op_is_not) = match op_str {
@@ -149,6 +150,7 @@ impl ConfigCondition for Config {
                     "ends-with" | "not-ends-with" => {
                         (MatchType::EndsWith, op_str == "not-ends-with")
                     }
+                    "script" => (MatchType::Script, false),
                     _ => {
                         return Err(format!("Invalid operation {op_str:?} for key {prefix:?}."));
                     }
@@ -156,6 +158,20 @@ impl ConfigCondition for Config {
 
                 let value_str = self.value_require((&prefix, op_str))?;
                 let value = match (key, &op) {
+                    (_, MatchType::Script) => {
+                        if let Some(script) = ctx.rule_scripts.get(value_str) {
+                            ConditionMatch::Script {
+                                script: script.clone(),
+                                keys: available_keys.to_vec(),
+                            }
+                        } else {
+                            return Err(format!(
+                                "Rule script {:?} not found for property {:?}.",
+                                value_str,
+                                (&prefix, op_str).as_key()
+                            ));
+                        }
+                    }
                     (EnvelopeKey::Listener, MatchType::Equal) => {
                         ConditionMatch::UInt(if value_str != "sieve" {
                             ctx.servers
@@ -214,6 +230,7 @@ impl ConfigCondition for Config {
                                 )
                             })?)
                         }
+                        MatchType::Script => unreachable!(),
                         MatchType::Lookup => {
                             if let Some(lookup) = ctx.directory.lookups.get(value_str) {
                                 ConditionMatch::Lookup(lookup.clone().into())
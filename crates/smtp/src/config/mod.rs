@@ -28,6 +28,7 @@ pub mod queue;
 pub mod remote;
 pub mod report;
 pub mod resolver;
+pub mod rules;
 pub mod scripts;
 pub mod session;
 pub mod throttle;
@@ -50,10 +51,13 @@ use mail_send::Credentials;
 use regex::Regex;
 use sieve::Sieve;
 use smtp_proto::MtPriority;
-use store::Stores;
+use store::{LookupStore, Stores};
 use utils::config::{DynValue, Rate, Server, ServerProtocol};
 
-use crate::{core::Lookup, inbound::milter};
+use crate::{
+    core::Lookup,
+    inbound::{greylist::Greylist, honeypot::Honeypot, milter, rcpt_cache::RcptCache},
+};
 
 #[derive(Debug)]
 pub struct Host {
@@ -99,6 +103,16 @@ pub enum ConditionMatch {
     IpAddrMask(IpAddrMask),
     Lookup(Lookup),
     Regex(Regex),
+    Script {
+        script: Arc<RuleScript>,
+        keys: Vec<EnvelopeKey>,
+    },
+}
+
+pub struct RuleScript {
+    pub id: String,
+    pub engine: Arc<rhai::Engine>,
+    pub ast: rhai::AST,
 }
 
 #[cfg(feature = "test_mode")]
@@ -111,6 +125,7 @@ impl PartialEq for ConditionMatch {
             (Self::IpAddrMask(l0), Self::IpAddrMask(r0)) => l0 == r0,
             (Self::Lookup(l0), Self::Lookup(r0)) => l0 == r0,
             (Self::Regex(_), Self::Regex(_)) => false,
+            (Self::Script { script: l0, .. }, Self::Script { script: r0, .. }) => l0.id == r0.id,
             _ => false,
         }
     }
@@ -125,6 +140,7 @@ impl core::fmt::Debug for ConditionMatch {
             Self::IpAddrMask(arg0) => f.debug_tuple("IpAddrMask").field(arg0).finish(),
             Self::Lookup(_) => f.debug_tuple("Lookup").finish(),
             Self::Regex(arg0) => f.debug_tuple("Regex").field(arg0).finish(),
+            Self::Script { script, .. } => f.debug_tuple("Script").field(&script.id).finish(),
         }
     }
 }
@@ -201,6 +217,9 @@ pub enum IpAddrMask {
 
 pub struct Connect {
     pub script: IfBlock<Option<Arc<Sieve>>>,
+    pub greeting_delay: IfBlock<Option<Duration>>,
+    pub reject_early_talkers: IfBlock<bool>,
+    pub tarpit: IfBlock<bool>,
 }
 
 pub struct Ehlo {
@@ -214,8 +233,8 @@ pub struct Extensions {
     pub chunking: IfBlock<bool>,
     pub requiretls: IfBlock<bool>,
     pub dsn: IfBlock<bool>,
-    pub vrfy: IfBlock<bool>,
-    pub expn: IfBlock<bool>,
+    pub vrfy: IfBlock<AddressDisclosure>,
+    pub expn: IfBlock<AddressDisclosure>,
     pub no_soliciting: IfBlock<Option<String>>,
     pub future_release: IfBlock<Option<Duration>>,
     pub deliver_by: IfBlock<Option<Duration>>,
@@ -229,11 +248,15 @@ pub struct Auth {
     pub allow_plain_text: IfBlock<bool>,
     pub errors_max: IfBlock<usize>,
     pub errors_wait: IfBlock<Duration>,
+    pub must_match_sender: IfBlock<bool>,
+    pub send_as_groups: IfBlock<bool>,
+    pub send_as_delegates: IfBlock<bool>,
 }
 
 pub struct Mail {
     pub script: IfBlock<Option<Arc<Sieve>>>,
     pub rewrite: IfBlock<Option<DynValue<EnvelopeKey>>>,
+    pub tarpit: IfBlock<bool>,
 }
 
 pub struct Rcpt {
@@ -241,6 +264,12 @@ pub struct Rcpt {
     pub relay: IfBlock<bool>,
     pub directory: IfBlock<Option<MaybeDynValue<Directory>>>,
     pub rewrite: IfBlock<Option<DynValue<EnvelopeKey>>>,
+    pub tarpit: IfBlock<bool>,
+    pub require_tls: IfBlock<bool>,
+    pub dictionary: DictionaryAttack,
+    pub cache: Option<RcptCache>,
+    pub greylist: Option<Greylist>,
+    pub honeypot: Option<Honeypot>,
 
     // Errors
     pub errors_max: IfBlock<usize>,
@@ -250,6 +279,14 @@ pub struct Rcpt {
     pub max_recipients: IfBlock<usize>,
 }
 
+pub struct DictionaryAttack {
+    pub max_unknown: IfBlock<Option<usize>>,
+    pub window: Duration,
+    pub cooldown: Duration,
+    pub store: Option<LookupStore>,
+    pub webhook: Option<String>,
+}
+
 pub struct Data {
     pub script: IfBlock<Option<Arc<Sieve>>>,
     pub pipe_commands: Vec<Pipe>,
@@ -259,6 +296,10 @@ pub struct Data {
     pub max_messages: IfBlock<usize>,
     pub max_message_size: IfBlock<usize>,
     pub max_received_headers: IfBlock<usize>,
+    pub max_headers: IfBlock<usize>,
+    pub max_mime_depth: IfBlock<usize>,
+    pub max_mime_parts: IfBlock<usize>,
+    pub max_addresses: IfBlock<usize>,
 
     // Headers
     pub add_received: IfBlock<bool>,
@@ -267,6 +308,131 @@ pub struct Data {
     pub add_auth_results: IfBlock<bool>,
     pub add_message_id: IfBlock<bool>,
     pub add_date: IfBlock<bool>,
+
+    // Transformations
+    pub banner: Banner,
+    pub fixup: SubmissionFixup,
+    pub disclaimer: Disclaimer,
+
+    // Data loss prevention
+    pub dlp: Dlp,
+
+    // Outbound limits for authenticated senders
+    pub send_limits: SendLimits,
+}
+
+pub struct SendLimits {
+    pub messages_hourly: IfBlock<Option<u64>>,
+    pub messages_daily: IfBlock<Option<u64>>,
+    pub recipients_hourly: IfBlock<Option<u64>>,
+    pub recipients_daily: IfBlock<Option<u64>>,
+    pub lock_threshold: IfBlock<Option<u64>>,
+    pub lock_window: Duration,
+    pub webhook: Option<String>,
+    pub store: Option<LookupStore>,
+}
+
+pub struct Banner {
+    pub enable: IfBlock<bool>,
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
+
+pub struct Disclaimer {
+    pub enable: IfBlock<bool>,
+    pub skip_replies: bool,
+    pub templates: Vec<DisclaimerTemplate>,
+}
+
+pub struct DisclaimerTemplate {
+    pub id: String,
+    pub domains: Vec<String>,
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
+
+pub struct SubmissionFixup {
+    pub enable: IfBlock<bool>,
+    pub from: FromPolicy,
+    pub strip_headers: Vec<String>,
+    pub max_header_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FromPolicy {
+    Keep,
+    Rewrite,
+    Reject,
+}
+
+pub struct Dlp {
+    pub enable: IfBlock<bool>,
+    pub rules: Vec<DlpRule>,
+}
+
+pub struct DlpRule {
+    pub id: String,
+    pub matcher: DlpMatcher,
+    pub action: DlpAction,
+    pub domains: Vec<String>,
+}
+
+pub enum DlpMatcher {
+    Regex(Regex),
+    Keywords(Vec<String>),
+    Attachment(Vec<String>),
+    CreditCard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressDisclosure {
+    #[default]
+    Disabled,
+    Ambiguous,
+    Full,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum DlpAction {
+    #[serde(rename = "require-tls")]
+    RequireTls,
+    #[serde(rename = "quarantine")]
+    Quarantine,
+    #[serde(rename = "reject")]
+    Reject,
+}
+
+pub struct PolicyService {
+    pub id: String,
+    pub enable: IfBlock<bool>,
+    pub url: String,
+    pub stages: Vec<PolicyStage>,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub allow_invalid_certs: bool,
+    pub on_error: PolicyAction,
+    pub cache_ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyStage {
+    Connect,
+    Ehlo,
+    Mail,
+    Rcpt,
+    Data,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum PolicyAction {
+    #[serde(rename = "accept", alias = "ok", alias = "dunno")]
+    Accept,
+    #[serde(rename = "reject")]
+    Reject,
+    #[serde(rename = "defer")]
+    Defer,
 }
 
 pub struct Pipe {
@@ -297,6 +463,7 @@ pub struct SessionConfig {
     pub duration: IfBlock<Duration>,
     pub transfer_limit: IfBlock<usize>,
     pub throttle: SessionThrottle,
+    pub tarpit: Tarpit,
 
     pub connect: Connect,
     pub ehlo: Ehlo,
@@ -305,6 +472,14 @@ pub struct SessionConfig {
     pub rcpt: Rcpt,
     pub data: Data,
     pub extensions: Extensions,
+    pub policy: Vec<PolicyService>,
+}
+
+pub struct Tarpit {
+    pub errors: IfBlock<Option<usize>>,
+    pub delay: IfBlock<Duration>,
+    pub max_delay: IfBlock<Duration>,
+    pub disconnect: IfBlock<Option<usize>>,
 }
 
 pub struct SessionThrottle {
@@ -337,6 +512,7 @@ pub struct QueueConfig {
     pub max_mx: IfBlock<usize>,
     pub max_multihomed: IfBlock<usize>,
     pub ip_strategy: IfBlock<IpLookupStrategy>,
+    pub happy_eyeballs: IfBlock<Option<Duration>>,
     pub source_ip: QueueOutboundSourceIp,
     pub tls: QueueOutboundTls,
     pub dsn: Dsn,
@@ -373,6 +549,8 @@ pub struct ReportAnalysis {
     pub forward: bool,
     pub store: Option<PathBuf>,
     pub report_id: AtomicU64,
+    pub role_accounts: Vec<String>,
+    pub role_mailbox: Option<String>,
 }
 
 pub enum AddressMatch {
@@ -419,6 +597,7 @@ pub struct QueueOutboundTls {
     pub mta_sts: IfBlock<RequireOptional>,
     pub start: IfBlock<RequireOptional>,
     pub invalid_certs: IfBlock<bool>,
+    pub mandatory: IfBlock<MandatoryTls>,
 }
 
 pub struct QueueOutboundTimeout {
@@ -468,6 +647,16 @@ pub struct TlsStrategy {
     pub tls: RequireOptional,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MandatoryTls {
+    #[default]
+    Disabled,
+    Verified,
+    Dane,
+    MtaSts,
+    DaneOrMtaSts,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum RequireOptional {
     #[default]
@@ -535,6 +724,7 @@ pub struct ConfigContext<'x> {
     pub servers: &'x [Server],
     pub hosts: AHashMap<String, Host>,
     pub scripts: AHashMap<String, Arc<Sieve>>,
+    pub rule_scripts: AHashMap<String, Arc<RuleScript>>,
     pub directory: Directories,
     pub stores: Stores,
     pub signers: AHashMap<String, Arc<DkimSigner>>,
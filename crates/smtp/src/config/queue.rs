@@ -128,6 +128,9 @@ impl ConfigQueue for Config {
             ip_strategy: self
                 .parse_if_block("queue.outbound.ip-strategy", ctx, &sender_envelope_keys)?
                 .unwrap_or_else(|| IfBlock::new(IpLookupStrategy::Ipv4thenIpv6)),
+            happy_eyeballs: self
+                .parse_if_block("queue.outbound.happy-eyeballs", ctx, &mx_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(Some(Duration::from_millis(250)))),
             source_ip: QueueOutboundSourceIp {
                 ipv4: self
                     .parse_if_block("queue.outbound.source-ip.v4", ctx, &mx_envelope_keys)?
@@ -154,6 +157,9 @@ impl ConfigQueue for Config {
                         &mx_envelope_keys,
                     )?
                     .unwrap_or_else(|| IfBlock::new(false)),
+                mandatory: self
+                    .parse_if_block("queue.outbound.tls.mandatory", ctx, &rcpt_envelope_keys)?
+                    .unwrap_or_default(),
             },
             throttle: self.parse_queue_throttle(ctx)?,
             quota: self.parse_queue_quota(ctx)?,
@@ -453,6 +459,35 @@ impl From<&Host> for RelayHost {
     }
 }
 
+impl ParseValue for MandatoryTls {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "verified" | "true" => Ok(MandatoryTls::Verified),
+            "dane" => Ok(MandatoryTls::Dane),
+            "mta-sts" => Ok(MandatoryTls::MtaSts),
+            "dane-or-mta-sts" => Ok(MandatoryTls::DaneOrMtaSts),
+            "disable" | "disabled" | "none" | "false" => Ok(MandatoryTls::Disabled),
+            _ => Err(format!(
+                "Invalid mandatory TLS value {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl MandatoryTls {
+    pub fn description(&self) -> &'static str {
+        match self {
+            MandatoryTls::Disabled => "no encryption",
+            MandatoryTls::Verified => "TLS with a valid certificate",
+            MandatoryTls::Dane => "TLS authenticated with DANE",
+            MandatoryTls::MtaSts => "TLS authenticated with MTA-STS",
+            MandatoryTls::DaneOrMtaSts => "TLS authenticated with DANE or MTA-STS",
+        }
+    }
+}
+
 impl ParseValue for RequireOptional {
     fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
         match value {
@@ -98,6 +98,13 @@ impl ConfigReport for Config {
                 forward: self.property("report.analysis.forward")?.unwrap_or(false),
                 store: self.property("report.analysis.store")?,
                 report_id: 0.into(),
+                role_accounts: self
+                    .values("report.analysis.role-accounts")
+                    .map(|(_, v)| v.to_lowercase())
+                    .collect(),
+                role_mailbox: self
+                    .value("report.analysis.role-mailbox")
+                    .map(|v| v.to_lowercase()),
             },
         })
     }
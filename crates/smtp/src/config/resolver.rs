@@ -21,28 +21,41 @@
  * for more details.
 */
 
-use std::io::Read;
+use std::{
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
 use mail_auth::{
     common::lru::{DnsCache, LruCache},
     flate2::read::GzDecoder,
     hickory_resolver::{
-        config::{ResolverConfig, ResolverOpts},
+        config::{Protocol, ResolverConfig, ResolverOpts},
         system_conf::read_system_conf,
+        AsyncResolver,
     },
     Resolver,
 };
 
-use crate::{core::Resolvers, outbound::dane::DnssecResolver};
+use crate::{
+    core::Resolvers,
+    outbound::{
+        cache::PersistentCache,
+        dane::{DnssecMode, DnssecResolver},
+    },
+};
 use utils::{config::Config, suffixlist::PublicSuffix};
 
+use super::ConfigContext;
+
 pub trait ConfigResolver {
-    fn build_resolvers(&self) -> super::Result<Resolvers>;
+    fn build_resolvers(&self, ctx: &ConfigContext) -> super::Result<Resolvers>;
     fn parse_public_suffix(&self) -> super::Result<PublicSuffix>;
 }
 
 impl ConfigResolver for Config {
-    fn build_resolvers(&self) -> super::Result<Resolvers> {
+    fn build_resolvers(&self, ctx: &ConfigContext) -> super::Result<Resolvers> {
         let (config, mut opts) = match self.value_require("resolver.type")? {
             "cloudflare" => (ResolverConfig::cloudflare(), ResolverOpts::default()),
             "cloudflare-tls" => (ResolverConfig::cloudflare_tls(), ResolverOpts::default()),
@@ -68,11 +81,52 @@ impl ConfigResolver for Config {
         if let Some(attempts) = self.property("resolver.attempts")? {
             opts.attempts = attempts;
         }
+        if let Some(ttl) = self.property("resolver.cache.positive-ttl.min")? {
+            opts.positive_min_ttl = Some(ttl);
+        }
+        if let Some(ttl) = self.property("resolver.cache.positive-ttl.max")? {
+            opts.positive_max_ttl = Some(ttl);
+        }
+        if let Some(ttl) = self.property("resolver.cache.negative-ttl.min")? {
+            opts.negative_min_ttl = Some(ttl);
+        }
+        if let Some(ttl) = self.property("resolver.cache.negative-ttl.max")? {
+            opts.negative_max_ttl = Some(ttl);
+        }
 
         // Prepare DNSSEC resolver options
         let config_dnssec = config.clone();
         let mut opts_dnssec = opts.clone();
         opts_dnssec.validate = true;
+        let dnssec_mode = match self.value("resolver.dnssec.mode").unwrap_or("disabled") {
+            "disabled" => DnssecMode::Disabled,
+            "validate" => DnssecMode::Validate,
+            "trust-ad" => {
+                let mut upstreams = Vec::new();
+                for (_, upstream) in self.values("resolver.dnssec.upstream") {
+                    upstreams.push(
+                        upstream
+                            .parse::<SocketAddr>()
+                            .or_else(|_| upstream.parse::<IpAddr>().map(|ip| (ip, 53).into()))
+                            .map_err(|_| {
+                                format!("Invalid DNSSEC upstream resolver address {upstream:?}.")
+                            })?,
+                    );
+                }
+                if upstreams.is_empty() {
+                    upstreams.extend(
+                        config.name_servers().iter().filter_map(|ns| {
+                            (ns.protocol == Protocol::Udp).then_some(ns.socket_addr)
+                        }),
+                    );
+                }
+                DnssecMode::TrustAd {
+                    upstreams,
+                    timeout: opts.timeout,
+                }
+            }
+            other => return Err(format!("Unknown DNSSEC mode {other:?}.")),
+        };
 
         let mut capacities = [1024usize; 5];
         for (pos, key) in ["txt", "mx", "ipv4", "ipv6", "ptr"].into_iter().enumerate() {
@@ -81,6 +135,36 @@ impl ConfigResolver for Config {
             }
         }
 
+        // Persistent cache
+        let persist = if let Some(store_id) = self.value("resolver.cache.persist.store") {
+            let store = ctx
+                .stores
+                .lookup_stores
+                .get(store_id)
+                .ok_or_else(|| {
+                    format!(
+                        "Lookup store {store_id:?} not found for key \"resolver.cache.persist.store\"."
+                    )
+                })?
+                .clone();
+            let ttl = self.property_or_static("resolver.cache.persist.ttl", "1d")?;
+            let negative_ttl = self
+                .property::<Duration>("resolver.cache.negative-ttl.max")?
+                .map(|max| max.min(ttl))
+                .unwrap_or(ttl);
+            Some(PersistentCache {
+                store,
+                resolver: AsyncResolver::tokio(config.clone(), opts.clone()),
+                ttl,
+                negative_ttl,
+                mx: LruCache::with_capacity(capacities[1]),
+                ipv4: LruCache::with_capacity(capacities[2]),
+                ipv6: LruCache::with_capacity(capacities[3]),
+            })
+        } else {
+            None
+        };
+
         Ok(Resolvers {
             dns: Resolver::with_capacities(
                 config,
@@ -92,15 +176,19 @@ impl ConfigResolver for Config {
                 capacities[4],
             )
             .map_err(|err| format!("Failed to build DNS resolver: {err}"))?,
-            dnssec: DnssecResolver::with_capacity(config_dnssec, opts_dnssec)
+            dnssec: DnssecResolver::with_capacity(config_dnssec, opts_dnssec, dnssec_mode)
                 .map_err(|err| format!("Failed to build DNSSEC resolver: {err}"))?,
             cache: crate::core::DnsCache {
                 tlsa: LruCache::with_capacity(
                     self.property("resolver.cache.tlsa")?.unwrap_or(1024),
                 ),
+                dnssec: LruCache::with_capacity(
+                    self.property("resolver.cache.dnssec")?.unwrap_or(1024),
+                ),
                 mta_sts: LruCache::with_capacity(
                     self.property("resolver.cache.mta-sts")?.unwrap_or(1024),
                 ),
+                persist,
             },
         })
     }
@@ -0,0 +1,77 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use rhai::{module_resolvers::DummyModuleResolver, Engine};
+use utils::config::Config;
+
+use super::{ConfigContext, RuleScript};
+
+pub trait ConfigRules {
+    fn parse_rule_scripts(&self, ctx: &mut ConfigContext) -> super::Result<()>;
+}
+
+impl ConfigRules for Config {
+    fn parse_rule_scripts(&self, ctx: &mut ConfigContext) -> super::Result<()> {
+        // Scripts are sandboxed: no module imports, no dynamic evaluation and
+        // bounded execution so a faulty rule cannot stall a session.
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_operations(self.property_or_static("rules.limits.operations", "100000")?)
+            .set_max_call_levels(self.property_or_static("rules.limits.call-depth", "32")?)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(self.property_or_static("rules.limits.string-size", "65536")?)
+            .set_max_array_size(self.property_or_static("rules.limits.array-size", "1024")?)
+            .set_max_map_size(self.property_or_static("rules.limits.map-size", "1024")?)
+            .on_print(|message| {
+                tracing::debug!(context = "rules", event = "print", "{}", message);
+            })
+            .on_debug(|message, _, _| {
+                tracing::debug!(context = "rules", event = "debug", "{}", message);
+            });
+        let engine = Arc::new(engine);
+
+        for id in self.sub_keys("rules.scripts") {
+            let script = self
+                .text_file_contents(("rules.scripts", id))?
+                .unwrap_or_default();
+            let ast = engine
+                .compile(&script)
+                .map_err(|err| format!("Failed to compile rule script {id:?}: {err}"))?;
+
+            ctx.rule_scripts.insert(
+                id.to_string(),
+                Arc::new(RuleScript {
+                    id: id.to_string(),
+                    engine: engine.clone(),
+                    ast,
+                }),
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -23,6 +23,7 @@
 
 use std::{net::ToSocketAddrs, time::Duration};
 
+use ahash::AHashSet;
 use smtp_proto::*;
 
 use super::{if_block::ConfigIf, throttle::ConfigThrottle, *};
@@ -41,6 +42,9 @@ pub trait ConfigSession {
     fn parse_session_mail(&self, ctx: &ConfigContext) -> super::Result<Mail>;
     fn parse_session_rcpt(&self, ctx: &ConfigContext) -> super::Result<Rcpt>;
     fn parse_session_data(&self, ctx: &ConfigContext) -> super::Result<Data>;
+    fn parse_dlp_rules(&self) -> super::Result<Vec<DlpRule>>;
+    fn parse_disclaimer_templates(&self) -> super::Result<Vec<DisclaimerTemplate>>;
+    fn parse_honeypot(&self, ctx: &ConfigContext) -> super::Result<Option<Honeypot>>;
     fn parse_pipes(
         &self,
         ctx: &ConfigContext,
@@ -51,6 +55,7 @@ pub trait ConfigSession {
         ctx: &ConfigContext,
         available_keys: &[EnvelopeKey],
     ) -> super::Result<Vec<Milter>>;
+    fn parse_policy_services(&self, ctx: &ConfigContext) -> super::Result<Vec<PolicyService>>;
 }
 
 impl ConfigSession for Config {
@@ -74,6 +79,20 @@ impl ConfigSession for Config {
                 .try_unwrap("session.timeout")
                 .unwrap_or_else(|_| IfBlock::new(Duration::from_secs(5 * 60))),
             throttle: self.parse_session_throttle(ctx)?,
+            tarpit: Tarpit {
+                errors: self
+                    .parse_if_block("session.tarpit.errors", ctx, &available_keys)?
+                    .unwrap_or_default(),
+                delay: self
+                    .parse_if_block("session.tarpit.delay", ctx, &available_keys)?
+                    .unwrap_or_else(|| IfBlock::new(Duration::from_secs(1))),
+                max_delay: self
+                    .parse_if_block("session.tarpit.max-delay", ctx, &available_keys)?
+                    .unwrap_or_else(|| IfBlock::new(Duration::from_secs(30))),
+                disconnect: self
+                    .parse_if_block("session.tarpit.disconnect", ctx, &available_keys)?
+                    .unwrap_or_default(),
+            },
             connect: self.parse_session_connect(ctx)?,
             ehlo: self.parse_session_ehlo(ctx)?,
             auth: self.parse_session_auth(ctx)?,
@@ -81,6 +100,7 @@ impl ConfigSession for Config {
             rcpt: self.parse_session_rcpt(ctx)?,
             data: self.parse_session_data(ctx)?,
             extensions: self.parse_extensions(ctx)?,
+            policy: self.parse_policy_services(ctx)?,
         })
     }
 
@@ -168,6 +188,15 @@ impl ConfigSession for Config {
                 .parse_if_block::<Option<String>>("session.connect.script", ctx, &available_keys)?
                 .unwrap_or_default()
                 .map_if_block(&ctx.scripts, "session.connect.script", "script")?,
+            greeting_delay: self
+                .parse_if_block("session.connect.greeting-delay", ctx, &available_keys)?
+                .unwrap_or_default(),
+            reject_early_talkers: self
+                .parse_if_block("session.connect.reject-early-talkers", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
+            tarpit: self
+                .parse_if_block("session.connect.tarpit", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
         })
     }
 
@@ -190,10 +219,10 @@ impl ConfigSession for Config {
                 .unwrap_or_else(|| IfBlock::new(true)),
             vrfy: self
                 .parse_if_block("session.extensions.vrfy", ctx, &available_keys)?
-                .unwrap_or_else(|| IfBlock::new(true)),
+                .unwrap_or_else(|| IfBlock::new(AddressDisclosure::Full)),
             expn: self
                 .parse_if_block("session.extensions.expn", ctx, &available_keys)?
-                .unwrap_or_else(|| IfBlock::new(true)),
+                .unwrap_or_else(|| IfBlock::new(AddressDisclosure::Full)),
             chunking: self
                 .parse_if_block("session.extensions.chunking", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(true)),
@@ -287,6 +316,15 @@ impl ConfigSession for Config {
             allow_plain_text: self
                 .parse_if_block("session.auth.allow-plain-text", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(false)),
+            must_match_sender: self
+                .parse_if_block("session.auth.must-match-sender", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
+            send_as_groups: self
+                .parse_if_block("session.auth.send-as-groups", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
+            send_as_delegates: self
+                .parse_if_block("session.auth.send-as-delegates", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
         })
     }
 
@@ -312,6 +350,9 @@ impl ConfigSession for Config {
                     &available_keys,
                 )?
                 .unwrap_or_default(),
+            tarpit: self
+                .parse_if_block("session.mail.tarpit", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
         })
     }
 
@@ -365,6 +406,69 @@ impl ConfigSession for Config {
             max_recipients: self
                 .parse_if_block("session.rcpt.max-recipients", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(100)),
+            tarpit: self
+                .parse_if_block("session.rcpt.tarpit", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            require_tls: self
+                .parse_if_block("session.rcpt.require-tls", ctx, &available_keys_full)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            dictionary: DictionaryAttack {
+                max_unknown: self
+                    .parse_if_block(
+                        "session.rcpt.dictionary-attack.max-unknown",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                window: self.property_or_static("session.rcpt.dictionary-attack.window", "10m")?,
+                cooldown: self
+                    .property_or_static("session.rcpt.dictionary-attack.cooldown", "1h")?,
+                store: ctx
+                    .stores
+                    .get_lookup_store(self, "session.rcpt.dictionary-attack.store")?,
+                webhook: self
+                    .value("session.rcpt.dictionary-attack.webhook")
+                    .map(|url| url.to_string()),
+            },
+            cache: if let Some(store_id) = self.value("session.rcpt.cache.store") {
+                Some(RcptCache {
+                    store: ctx
+                        .stores
+                        .lookup_stores
+                        .get(store_id)
+                        .ok_or_else(|| {
+                            format!(
+                                "Lookup store {store_id:?} not found for key \"session.rcpt.cache.store\"."
+                            )
+                        })?
+                        .clone(),
+                    ttl_exists: self.property_or_static("session.rcpt.cache.ttl.exists", "10m")?,
+                    ttl_unknown: self
+                        .property_or_static("session.rcpt.cache.ttl.unknown", "5m")?,
+                    ttl_over_quota: self
+                        .property_or_static("session.rcpt.cache.ttl.over-quota", "15m")?,
+                })
+            } else {
+                None
+            },
+            greylist: if let Some(store) = ctx
+                .stores
+                .get_lookup_store(self, "session.rcpt.greylist.store")?
+            {
+                Some(Greylist {
+                    enable: self
+                        .parse_if_block("session.rcpt.greylist.enable", ctx, &available_keys_full)?
+                        .unwrap_or_else(|| IfBlock::new(true)),
+                    store,
+                    delay: self.property_or_static("session.rcpt.greylist.delay", "5m")?,
+                    retry_window: self
+                        .property_or_static("session.rcpt.greylist.retry-window", "1d")?,
+                    ttl: self.property_or_static("session.rcpt.greylist.ttl", "36d")?,
+                })
+            } else {
+                None
+            },
+            honeypot: self.parse_honeypot(ctx)?,
             rewrite: self
                 .parse_if_block::<Option<DynValue<EnvelopeKey>>>(
                     "session.rcpt.rewrite",
@@ -400,6 +504,18 @@ impl ConfigSession for Config {
             max_received_headers: self
                 .parse_if_block("session.data.limits.received-headers", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(50)),
+            max_headers: self
+                .parse_if_block("session.data.limits.headers", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(500)),
+            max_mime_depth: self
+                .parse_if_block("session.data.limits.mime-depth", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(20)),
+            max_mime_parts: self
+                .parse_if_block("session.data.limits.mime-parts", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(1000)),
+            max_addresses: self
+                .parse_if_block("session.data.limits.addresses", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(1000)),
             add_received: self
                 .parse_if_block("session.data.add-headers.received", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(true)),
@@ -426,11 +542,246 @@ impl ConfigSession for Config {
             add_date: self
                 .parse_if_block("session.data.add-headers.date", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(true)),
+            banner: Banner {
+                enable: self
+                    .parse_if_block(
+                        "session.data.banner.enable",
+                        ctx,
+                        &[
+                            EnvelopeKey::Recipient,
+                            EnvelopeKey::RecipientDomain,
+                            EnvelopeKey::Sender,
+                            EnvelopeKey::SenderDomain,
+                            EnvelopeKey::AuthenticatedAs,
+                            EnvelopeKey::Listener,
+                            EnvelopeKey::RemoteIp,
+                            EnvelopeKey::LocalIp,
+                            EnvelopeKey::Priority,
+                            EnvelopeKey::HeloDomain,
+                        ],
+                    )?
+                    .unwrap_or_default(),
+                text: self
+                    .value("session.data.banner.text")
+                    .map(|s| s.trim_end().to_string()),
+                html: self
+                    .value("session.data.banner.html")
+                    .map(|s| s.trim_end().to_string()),
+            },
+            fixup: SubmissionFixup {
+                enable: self
+                    .parse_if_block("session.data.fixup.enable", ctx, &available_keys)?
+                    .unwrap_or_default(),
+                from: self.property_or_static("session.data.fixup.from", "rewrite")?,
+                strip_headers: {
+                    let headers = self
+                        .values("session.data.fixup.strip-headers")
+                        .map(|(_, value)| value.trim().to_string())
+                        .collect::<Vec<_>>();
+                    if !headers.is_empty() {
+                        headers
+                    } else {
+                        vec!["Received".to_string(), "X-Originating-IP".to_string()]
+                    }
+                },
+                max_header_size: self
+                    .property_or_static("session.data.fixup.max-header-size", "65536")?,
+            },
+            disclaimer: Disclaimer {
+                enable: self
+                    .parse_if_block("session.data.disclaimer.enable", ctx, &available_keys)?
+                    .unwrap_or_default(),
+                skip_replies: self
+                    .property_or_static("session.data.disclaimer.skip-replies", "true")?,
+                templates: self.parse_disclaimer_templates()?,
+            },
+            dlp: Dlp {
+                enable: self
+                    .parse_if_block(
+                        "session.data.dlp.enable",
+                        ctx,
+                        &[
+                            EnvelopeKey::Recipient,
+                            EnvelopeKey::RecipientDomain,
+                            EnvelopeKey::Sender,
+                            EnvelopeKey::SenderDomain,
+                            EnvelopeKey::AuthenticatedAs,
+                            EnvelopeKey::Listener,
+                            EnvelopeKey::RemoteIp,
+                            EnvelopeKey::LocalIp,
+                            EnvelopeKey::Priority,
+                        ],
+                    )?
+                    .unwrap_or_default(),
+                rules: self.parse_dlp_rules()?,
+            },
+            send_limits: SendLimits {
+                messages_hourly: self
+                    .parse_if_block(
+                        "session.data.send-limits.messages.hourly",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                messages_daily: self
+                    .parse_if_block(
+                        "session.data.send-limits.messages.daily",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                recipients_hourly: self
+                    .parse_if_block(
+                        "session.data.send-limits.recipients.hourly",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                recipients_daily: self
+                    .parse_if_block(
+                        "session.data.send-limits.recipients.daily",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                lock_threshold: self
+                    .parse_if_block(
+                        "session.data.send-limits.lock.recipients",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default(),
+                lock_window: self
+                    .property_or_static("session.data.send-limits.lock.window", "10m")?,
+                webhook: self
+                    .value("session.data.send-limits.lock.webhook")
+                    .map(|url| url.to_string()),
+                store: if let Some(store) = ctx
+                    .stores
+                    .get_lookup_store(self, "session.data.send-limits.lock.store")?
+                {
+                    Some(store)
+                } else {
+                    ctx.stores
+                        .get_lookup_store(self, "sieve.trusted.default.store")?
+                },
+            },
             pipe_commands: self.parse_pipes(ctx, &available_keys)?,
             milters: self.parse_milters(ctx, &available_keys)?,
         })
     }
 
+    fn parse_dlp_rules(&self) -> super::Result<Vec<DlpRule>> {
+        let mut rules = Vec::new();
+        for id in self.sub_keys("session.data.dlp.rule") {
+            let values = || {
+                self.values(("session.data.dlp.rule", id, "value"))
+                    .map(|(_, value)| value.to_lowercase())
+                    .collect::<Vec<_>>()
+            };
+            let matcher = match self.value_require(("session.data.dlp.rule", id, "type"))? {
+                "regex" => {
+                    let pattern = self.value_require(("session.data.dlp.rule", id, "value"))?;
+                    DlpMatcher::Regex(
+                        regex::RegexBuilder::new(pattern)
+                            .case_insensitive(true)
+                            .build()
+                            .map_err(|err| {
+                                format!("Invalid regular expression for DLP rule {id:?}: {err}")
+                            })?,
+                    )
+                }
+                "keyword" => DlpMatcher::Keywords(values()),
+                "attachment" => DlpMatcher::Attachment(
+                    values()
+                        .into_iter()
+                        .map(|value| value.trim_start_matches('.').to_string())
+                        .collect(),
+                ),
+                "credit-card" => DlpMatcher::CreditCard,
+                other => return Err(format!("Invalid DLP rule type {other:?} for rule {id:?}.")),
+            };
+            if matches!(&matcher, DlpMatcher::Keywords(v) | DlpMatcher::Attachment(v) if v.is_empty())
+            {
+                return Err(format!("Missing values for DLP rule {id:?}."));
+            }
+
+            rules.push(DlpRule {
+                id: id.to_string(),
+                matcher,
+                action: self.property_require(("session.data.dlp.rule", id, "action"))?,
+                domains: self
+                    .values(("session.data.dlp.rule", id, "domains"))
+                    .map(|(_, value)| value.to_lowercase())
+                    .collect(),
+            });
+        }
+        Ok(rules)
+    }
+
+    fn parse_honeypot(&self, ctx: &ConfigContext) -> super::Result<Option<Honeypot>> {
+        let mut addresses = AHashSet::new();
+        let mut domains = AHashSet::new();
+        for (_, address) in self.values("session.rcpt.honeypot.addresses") {
+            let address = address.trim().to_lowercase();
+            if let Some(domain) = address.strip_prefix('@') {
+                domains.insert(domain.to_string());
+            } else if address.contains('@') {
+                addresses.insert(address);
+            } else {
+                return Err(format!(
+                    "Invalid honeypot address {address:?} for key \"session.rcpt.honeypot.addresses\"."
+                ));
+            }
+        }
+        if addresses.is_empty() && domains.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Honeypot {
+            addresses,
+            domains,
+            store: if let Some(store) = ctx
+                .stores
+                .get_lookup_store(self, "session.rcpt.honeypot.store")?
+            {
+                Some(store)
+            } else {
+                ctx.stores
+                    .get_lookup_store(self, "sieve.trusted.default.store")?
+            },
+            score: self.property_or_static("session.rcpt.honeypot.score", "20.0")?,
+            ttl: self.property_or_static("session.rcpt.honeypot.ttl", "30d")?,
+            webhook: self
+                .value("session.rcpt.honeypot.webhook")
+                .map(|url| url.to_string()),
+        }))
+    }
+
+    fn parse_disclaimer_templates(&self) -> super::Result<Vec<DisclaimerTemplate>> {
+        let mut templates = Vec::new();
+        for id in self.sub_keys("session.data.disclaimer.template") {
+            let template = DisclaimerTemplate {
+                id: id.to_string(),
+                domains: self
+                    .values(("session.data.disclaimer.template", id, "domains"))
+                    .map(|(_, value)| value.to_lowercase())
+                    .collect(),
+                text: self
+                    .value(("session.data.disclaimer.template", id, "text"))
+                    .map(|s| s.trim_end().to_string()),
+                html: self
+                    .value(("session.data.disclaimer.template", id, "html"))
+                    .map(|s| s.trim_end().to_string()),
+            };
+            if template.text.is_none() && template.html.is_none() {
+                return Err(format!("Missing text for disclaimer template {id:?}."));
+            }
+            templates.push(template);
+        }
+        Ok(templates)
+    }
+
     fn parse_pipes(
         &self,
         ctx: &ConfigContext,
@@ -515,6 +866,139 @@ impl ConfigSession for Config {
         }
         Ok(milters)
     }
+
+    fn parse_policy_services(&self, ctx: &ConfigContext) -> super::Result<Vec<PolicyService>> {
+        // Services may be called at any stage, envelope keys that are not
+        // known yet evaluate to empty values
+        let available_keys = [
+            EnvelopeKey::Recipient,
+            EnvelopeKey::RecipientDomain,
+            EnvelopeKey::Sender,
+            EnvelopeKey::SenderDomain,
+            EnvelopeKey::AuthenticatedAs,
+            EnvelopeKey::Listener,
+            EnvelopeKey::RemoteIp,
+            EnvelopeKey::LocalIp,
+            EnvelopeKey::Priority,
+            EnvelopeKey::HeloDomain,
+        ];
+        let mut services = Vec::new();
+        for id in self.sub_keys("session.policy") {
+            let mut headers = Vec::new();
+            for (key, header) in self.values(("session.policy", id, "headers")) {
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                } else {
+                    return Err(format!("Invalid header {header:?} for property {key:?}."));
+                }
+            }
+            let stages = self
+                .properties::<PolicyStage>(("session.policy", id, "stages"))
+                .map(|result| result.map(|(_, stage)| stage))
+                .collect::<super::Result<Vec<_>>>()?;
+
+            services.push(PolicyService {
+                id: id.to_string(),
+                enable: self
+                    .parse_if_block(("session.policy", id, "enable"), ctx, &available_keys)?
+                    .unwrap_or_else(|| IfBlock::new(true)),
+                url: self
+                    .value_require(("session.policy", id, "url"))?
+                    .to_string(),
+                stages: if !stages.is_empty() {
+                    stages
+                } else {
+                    vec![PolicyStage::Rcpt]
+                },
+                headers,
+                timeout: self.property_or_static(("session.policy", id, "timeout"), "10s")?,
+                allow_invalid_certs: self
+                    .property_or_static(("session.policy", id, "allow-invalid-certs"), "false")?,
+                on_error: self.property_or_static(("session.policy", id, "on-error"), "defer")?,
+                cache_ttl: self
+                    .property::<Duration>(("session.policy", id, "cache.ttl"))?
+                    .filter(|ttl| !ttl.is_zero()),
+            });
+        }
+        Ok(services)
+    }
+}
+
+impl ParseValue for PolicyStage {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "connect" => Ok(PolicyStage::Connect),
+            "ehlo" => Ok(PolicyStage::Ehlo),
+            "mail" => Ok(PolicyStage::Mail),
+            "rcpt" => Ok(PolicyStage::Rcpt),
+            "data" => Ok(PolicyStage::Data),
+            _ => Err(format!(
+                "Invalid policy stage {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for PolicyAction {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "accept" => Ok(PolicyAction::Accept),
+            "reject" => Ok(PolicyAction::Reject),
+            "defer" => Ok(PolicyAction::Defer),
+            _ => Err(format!(
+                "Invalid policy action {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for AddressDisclosure {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "full" | "true" => Ok(AddressDisclosure::Full),
+            "ambiguous" => Ok(AddressDisclosure::Ambiguous),
+            "disabled" | "false" => Ok(AddressDisclosure::Disabled),
+            _ => Err(format!(
+                "Invalid VRFY/EXPN mode {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for FromPolicy {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "keep" => Ok(FromPolicy::Keep),
+            "rewrite" => Ok(FromPolicy::Rewrite),
+            "reject" => Ok(FromPolicy::Reject),
+            _ => Err(format!(
+                "Invalid From policy {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for DlpAction {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "reject" => Ok(DlpAction::Reject),
+            "quarantine" => Ok(DlpAction::Quarantine),
+            "require-tls" => Ok(DlpAction::RequireTls),
+            _ => Err(format!(
+                "Invalid DLP action {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
 }
 
 struct Mechanism {
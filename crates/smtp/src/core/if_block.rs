@@ -23,11 +23,12 @@
 
 use std::{borrow::Cow, net::IpAddr, sync::Arc};
 
+use rhai::{Dynamic, Scope};
 use utils::config::{DynValue, KeyLookup};
 
 use crate::config::{
     Condition, ConditionMatch, Conditions, EnvelopeKey, IfBlock, IpAddrMask, MaybeDynValue,
-    StringMatch,
+    RuleScript, StringMatch,
 };
 
 pub struct Captures<'x, T> {
@@ -97,6 +98,9 @@ impl Conditions {
                             }
                         }
                         ConditionMatch::Regex(value) => value.is_match(envelope.key(key).as_ref()),
+                        ConditionMatch::Script { script, keys } => {
+                            script.eval(envelope, key, keys).is_some()
+                        }
                     } ^ not;
                 }
                 Condition::JumpIfTrue { positions } => {
@@ -160,6 +164,16 @@ impl Conditions {
 
                             !regex_capture.is_empty()
                         }
+                        ConditionMatch::Script { script, keys } => {
+                            regex_capture.clear();
+
+                            if let Some(result) = script.eval(envelope, key, keys) {
+                                regex_capture.push(result);
+                                true
+                            } else {
+                                false
+                            }
+                        }
                     } ^ not;
 
                     // Save last capture
@@ -198,6 +212,74 @@ impl Conditions {
     }
 }
 
+impl RuleScript {
+    // Runs the script with the envelope variables in scope. Truthy results are a
+    // match, string results are also returned so they can be used as captures.
+    pub fn eval(
+        &self,
+        envelope: &impl KeyLookup<Key = EnvelopeKey>,
+        key: &EnvelopeKey,
+        keys: &[EnvelopeKey],
+    ) -> Option<String> {
+        let mut scope = Scope::new();
+        for key in keys {
+            if let EnvelopeKey::Priority = key {
+                scope.push_constant(key.variable_name(), envelope.key_as_int(key) as i64);
+            } else {
+                scope.push_constant(key.variable_name(), envelope.key(key).into_owned());
+            }
+        }
+        let value = envelope.key(key).into_owned();
+        scope.push_constant("value", value.clone());
+
+        match self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+        {
+            Ok(result) => {
+                if let Ok(matched) = result.as_bool() {
+                    matched.then_some(value)
+                } else if let Ok(number) = result.as_int() {
+                    (number != 0).then_some(value)
+                } else if result.is_unit() {
+                    None
+                } else {
+                    let result = result.to_string();
+                    (!result.is_empty()).then_some(result)
+                }
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "rules",
+                    event = "error",
+                    script = self.id,
+                    reason = %err,
+                    "Failed to evaluate rule script.",
+                );
+                None
+            }
+        }
+    }
+}
+
+impl EnvelopeKey {
+    pub fn variable_name(&self) -> &'static str {
+        match self {
+            EnvelopeKey::Recipient => "rcpt",
+            EnvelopeKey::RecipientDomain => "rcpt_domain",
+            EnvelopeKey::Sender => "sender",
+            EnvelopeKey::SenderDomain => "sender_domain",
+            EnvelopeKey::Mx => "mx",
+            EnvelopeKey::HeloDomain => "helo_domain",
+            EnvelopeKey::AuthenticatedAs => "authenticated_as",
+            EnvelopeKey::Listener => "listener",
+            EnvelopeKey::RemoteIp => "remote_ip",
+            EnvelopeKey::LocalIp => "local_ip",
+            EnvelopeKey::Priority => "priority",
+        }
+    }
+}
+
 impl IpAddrMask {
     pub fn matches(&self, remote: &IpAddr) -> bool {
         match self {
@@ -44,7 +44,11 @@ use tokio::{
 use utils::listener::{limiter::InFlight, SessionManager};
 
 use crate::{
-    queue::{self, instant_to_timestamp, InstantFromTimestamp, QueueId, Status},
+    inbound::send_limits,
+    queue::{
+        self, instant_to_timestamp, suppression::SuppressionReason, InstantFromTimestamp, QueueId,
+        Status,
+    },
     reporting::{
         self,
         scheduler::{ReportKey, ReportPolicy, ReportType, ReportValue},
@@ -77,6 +81,21 @@ pub enum QueueRequest {
         time: Instant,
         result_tx: oneshot::Sender<Vec<bool>>,
     },
+    Stats {
+        result_tx: oneshot::Sender<QueueStats>,
+    },
+    Overdue {
+        before: Instant,
+        result_tx: oneshot::Sender<usize>,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueueStats {
+    pub scheduled: usize,
+    pub deferred: usize,
+    pub on_hold: usize,
+    pub oldest_created: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -395,6 +414,61 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "queue", "history") => {
+                let mut queue_ids = Vec::new();
+                let mut message_id = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" | "ids" => match value.parse_queue_ids() {
+                                Ok(ids) => {
+                                    queue_ids = ids;
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            "message-id" => {
+                                message_id = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None if !self.queue.history.is_enabled() => (
+                        StatusCode::NOT_FOUND,
+                        "{\"error\": \"not-found\", \"details\": \"Delivery history is disabled.\"}"
+                            .to_string(),
+                    ),
+                    None => {
+                        let mut result = queue_ids
+                            .into_iter()
+                            .filter_map(|queue_id| self.queue.history.get(queue_id))
+                            .collect::<Vec<_>>();
+                        if let Some(message_id) = message_id {
+                            for history in self.queue.history.get_by_message_id(&message_id) {
+                                if !result.iter().any(|h| h.id == history.id) {
+                                    result.push(history);
+                                }
+                            }
+                        }
+
+                        (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: result }).unwrap_or_default(),
+                        )
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
             (&Method::GET, "queue", "status") => {
                 let mut queue_ids = Vec::new();
                 let mut error = None;
@@ -533,6 +607,59 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "queue", "warmup") => {
+                if self.queue.warmup.is_enabled() {
+                    (
+                        StatusCode::OK,
+                        serde_json::to_string(&Response {
+                            data: self.queue.warmup.status(),
+                        })
+                        .unwrap_or_default(),
+                    )
+                } else {
+                    (
+                        StatusCode::NOT_FOUND,
+                        "{\"error\": \"not-found\", \"details\": \"IP warm-up is disabled.\"}"
+                            .to_string(),
+                    )
+                }
+            }
+            (&Method::GET, "queue", "bounces") => {
+                let mut domain = None;
+                let mut clear = false;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "domain" => {
+                                domain = value.to_lowercase().into();
+                            }
+                            "clear" => {
+                                clear = value == "true";
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let stats = self.queue.bounce.stats(domain.as_deref());
+                        if clear {
+                            self.queue.bounce.clear();
+                        }
+                        (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: stats }).unwrap_or_default(),
+                        )
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
             (&Method::GET, "report", "list") => {
                 let mut domain = None;
                 let mut type_ = None;
@@ -658,6 +785,145 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "quarantine", "list") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.queue.quarantine.list(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "quarantine", "incidents") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.queue.quarantine.incidents(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "quarantine", action @ ("release" | "delete")) => {
+                let mut queue_ids = Vec::new();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" | "ids" => match value.parse_queue_ids() {
+                                Ok(ids) => {
+                                    queue_ids = ids;
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let span = tracing::info_span!("quarantine", action = action);
+                        let mut result = Vec::with_capacity(queue_ids.len());
+                        for queue_id in queue_ids {
+                            result.push(if action == "release" {
+                                self.queue.release_quarantined(queue_id, &span).await
+                            } else {
+                                self.queue.quarantine.delete(queue_id).await
+                            });
+                        }
+
+                        (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: result }).unwrap_or_default(),
+                        )
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "suppression", action @ "list")
+            | (&Method::POST, "suppression", action @ "add")
+            | (&Method::DELETE, "suppression", action @ "remove") => {
+                let mut domain = None;
+                let mut address = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "domain" => {
+                                domain = value.to_lowercase().into();
+                            }
+                            "address" => {
+                                address = value.to_lowercase().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                let suppression = &self.queue.suppression;
+                match (action, domain, address, error) {
+                    (_, _, _, None) if !suppression.is_enabled() => (
+                        StatusCode::NOT_FOUND,
+                        "{\"error\": \"not-found\", \"details\": \"Suppression list is disabled.\"}"
+                            .to_string(),
+                    ),
+                    ("list", domain, _, None) => {
+                        suppression.list(domain.as_deref()).await.into_response()
+                    }
+                    ("add", Some(domain), Some(address), None) => suppression
+                        .add(&domain, &address, SuppressionReason::Manual, "")
+                        .await
+                        .into_response(),
+                    ("remove", Some(domain), Some(address), None) => {
+                        suppression.remove(&domain, &address).await.into_response()
+                    }
+                    (_, _, _, Some(error)) => error.into_bad_request(),
+                    _ => "Missing domain or address parameters."
+                        .to_string()
+                        .into_bad_request(),
+                }
+            }
+            (&Method::GET, "senders", action @ "list")
+            | (&Method::POST, "senders", action @ "unlock") => {
+                let mut account = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "account" => {
+                                account = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (action, account, error) {
+                    ("list", account, None) => (
+                        StatusCode::OK,
+                        serde_json::to_string(&Response {
+                            data: send_limits::status(&self.session, account.as_deref()).await,
+                        })
+                        .unwrap_or_default(),
+                    ),
+                    ("unlock", Some(account), None) => send_limits::unlock(&self.session, &account)
+                        .await
+                        .into_response(),
+                    (_, _, Some(error)) => error.into_bad_request(),
+                    _ => "Missing account parameter.".to_string().into_bad_request(),
+                }
+            }
             _ => (
                 StatusCode::NOT_FOUND,
                 format!(
@@ -943,6 +1209,34 @@ impl BadRequest for String {
     }
 }
 
+trait StoreResponse {
+    fn into_response(self) -> (StatusCode, String);
+}
+
+impl<T: Serialize> StoreResponse for store::Result<T> {
+    fn into_response(self) -> (StatusCode, String) {
+        match self {
+            Ok(data) => (
+                StatusCode::OK,
+                serde_json::to_string(&Response { data }).unwrap_or_default(),
+            ),
+            Err(err) => {
+                tracing::warn!(
+                    context = "management",
+                    event = "error",
+                    reason = %err,
+                    "Failed to access store."
+                );
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "{\"error\": \"internal-error\", \"details\": \"Failed to access store.\"}"
+                        .to_string(),
+                )
+            }
+        }
+    }
+}
+
 fn is_zero(num: &i16) -> bool {
     *num == 0
 }
@@ -974,14 +1268,14 @@ where
     }
 }
 
-fn serialize_datetime<S>(value: &DateTime, serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_datetime<S>(value: &DateTime, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
     serializer.serialize_str(&value.to_rfc3339())
 }
 
-fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime, D::Error>
+pub(crate) fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime, D::Error>
 where
     D: Deserializer<'de>,
 {
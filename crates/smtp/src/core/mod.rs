@@ -48,23 +48,37 @@ use tokio::{
 use tokio_rustls::TlsConnector;
 use tracing::Span;
 use utils::{
+    durability::FileSync,
     ipc::DeliveryEvent,
-    listener::{limiter::InFlight, ServerInstance},
+    listener::{
+        limiter::{ConcurrencyLimiter, InFlight},
+        ServerInstance,
+    },
+    map::ttl_dashmap::TtlDashMap,
 };
 
 use crate::{
     config::{
-        scripts::SieveContext, DkimSigner, MailAuthConfig, QueueConfig, ReportConfig,
-        SessionConfig, VerifyStrategy,
+        scripts::SieveContext, AddressDisclosure, DkimSigner, MailAuthConfig, QueueConfig,
+        ReportConfig, SessionConfig, VerifyStrategy,
+    },
+    inbound::{
+        auth::SaslToken, dictionary::UnknownRecipients, policy::PolicyResponse,
+        send_limits::SenderUsage,
     },
-    inbound::auth::SaslToken,
     outbound::{
-        dane::{DnssecResolver, Tlsa},
+        cache::PersistentCache,
+        dane::{DnssecResolver, DnssecStatus, Tlsa},
         mta_sts,
     },
-    queue::{self, DomainPart, QueueId, QuotaLimiter},
+    queue::{
+        self, bounce::BounceClassifier, history::DeliveryHistory, quarantine::Quarantine,
+        shared::SharedQueue, stream::MessageStreams, suppression::SuppressionList,
+        tuning::AutoTune, warmup::WarmUp, DomainPart, QueueId, QuotaLimiter,
+    },
     reporting,
     scripts::plugins::lookup::VariableExists,
+    wasm::Plugins,
 };
 
 use self::throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder};
@@ -73,6 +87,7 @@ pub mod if_block;
 pub mod management;
 pub mod params;
 pub mod throttle;
+pub mod webhook;
 pub mod worker;
 
 #[derive(Clone)]
@@ -105,8 +120,13 @@ pub struct SMTP {
     pub mail_auth: MailAuthConfig,
     pub report: ReportCore,
     pub sieve: SieveCore,
+    pub plugins: Plugins,
     #[cfg(feature = "local_delivery")]
     pub delivery_tx: mpsc::Sender<DeliveryEvent>,
+    // Status updates and other events, kept apart from local deliveries so
+    // that they are not delayed by ingestion
+    #[cfg(feature = "local_delivery")]
+    pub event_tx: mpsc::Sender<DeliveryEvent>,
 }
 
 pub struct SieveCore {
@@ -131,21 +151,38 @@ pub struct Resolvers {
 
 pub struct DnsCache {
     pub tlsa: LruCache<String, Arc<Tlsa>>,
+    pub dnssec: LruCache<String, DnssecStatus>,
     pub mta_sts: LruCache<String, Arc<mta_sts::Policy>>,
+    pub persist: Option<PersistentCache>,
 }
 
 pub struct SessionCore {
     pub config: SessionConfig,
     pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
+    pub throttle_store: Option<LookupStore>,
+    pub dictionary: DashMap<IpAddr, UnknownRecipients>,
+    pub senders: DashMap<String, SenderUsage>,
+    pub policy_cache: TtlDashMap<u64, Arc<PolicyResponse>>,
 }
 
 pub struct QueueCore {
     pub config: QueueConfig,
     pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
+    pub throttle_store: Option<LookupStore>,
     pub quota: DashMap<ThrottleKey, Arc<QuotaLimiter>, ThrottleKeyHasherBuilder>,
     pub tx: mpsc::Sender<queue::Event>,
     pub id_seq: AtomicU32,
     pub connectors: TlsConnectors,
+    pub workers: ConcurrencyLimiter,
+    pub history: DeliveryHistory,
+    pub suppression: SuppressionList,
+    pub warmup: WarmUp,
+    pub bounce: BounceClassifier,
+    pub quarantine: Quarantine,
+    pub streams: MessageStreams,
+    pub durability: FileSync,
+    pub shared: Option<SharedQueue>,
+    pub auto_tune: AutoTune,
 }
 
 pub struct ReportCore {
@@ -198,6 +235,8 @@ pub struct SessionData {
     pub message: Vec<u8>,
 
     pub authenticated_as: String,
+    pub authenticated_emails: Vec<String>,
+    pub on_behalf_emails: Vec<String>,
     pub auth_errors: usize,
 
     pub priority: i16,
@@ -211,7 +250,13 @@ pub struct SessionData {
     pub iprev: Option<IprevOutput>,
     pub spf_ehlo: Option<SpfOutput>,
     pub spf_mail_from: Option<SpfOutput>,
+    pub spf_dnssec: Option<DnssecStatus>,
     pub dnsbl_error: Option<Vec<u8>>,
+
+    pub early_talker: bool,
+    pub pipelining: bool,
+    pub pipelining_violations: usize,
+    pub tarpit_count: usize,
 }
 
 #[derive(Clone)]
@@ -238,14 +283,17 @@ pub struct SessionParameters {
     pub auth_errors_max: usize,
     pub auth_errors_wait: Duration,
     pub auth_plain_text: bool,
+    pub auth_match_sender: bool,
+    pub auth_send_as_groups: bool,
+    pub auth_send_as_delegates: bool,
 
     // Rcpt parameters
     pub rcpt_errors_max: usize,
     pub rcpt_errors_wait: Duration,
     pub rcpt_max: usize,
     pub rcpt_dsn: bool,
-    pub can_expn: bool,
-    pub can_vrfy: bool,
+    pub expn: AddressDisclosure,
+    pub vrfy: AddressDisclosure,
     pub max_message_size: usize,
 
     // Mail authentication parameters
@@ -264,6 +312,8 @@ impl SessionData {
             mail_from: None,
             rcpt_to: Vec::new(),
             authenticated_as: String::new(),
+            authenticated_emails: Vec::new(),
+            on_behalf_emails: Vec::new(),
             priority: 0,
             valid_until: Instant::now(),
             rcpt_errors: 0,
@@ -276,7 +326,12 @@ impl SessionData {
             iprev: None,
             spf_ehlo: None,
             spf_mail_from: None,
+            spf_dnssec: None,
             dnsbl_error: None,
+            early_talker: false,
+            pipelining: false,
+            pipelining_violations: 0,
+            tarpit_count: 0,
         }
     }
 }
@@ -484,6 +539,9 @@ static ref SIEVE: Arc<ServerInstance> = Arc::new(utils::listener::ServerInstance
     tls_acceptor: None,
     is_tls_implicit: true,
     limiter: utils::listener::limiter::ConcurrencyLimiter::new(0),
+    connections: Default::default(),
+    max_connections_ip: u64::MAX,
+    max_connections_account: u64::MAX,
     shutdown_rx: tokio::sync::watch::channel(false).1,
 });
 }
@@ -525,6 +583,9 @@ impl Session<NullIo> {
                 auth_errors_max: Default::default(),
                 auth_errors_wait: Default::default(),
                 auth_plain_text: false,
+                auth_match_sender: false,
+                auth_send_as_groups: false,
+                auth_send_as_delegates: false,
                 rcpt_errors_max: Default::default(),
                 rcpt_errors_wait: Default::default(),
                 rcpt_max: Default::default(),
@@ -533,8 +594,8 @@ impl Session<NullIo> {
                 iprev: crate::config::VerifyStrategy::Disable,
                 spf_ehlo: crate::config::VerifyStrategy::Disable,
                 spf_mail_from: crate::config::VerifyStrategy::Disable,
-                can_expn: false,
-                can_vrfy: false,
+                expn: AddressDisclosure::Disabled,
+                vrfy: AddressDisclosure::Disabled,
             },
             in_flight: vec![],
         }
@@ -585,6 +646,8 @@ impl SessionData {
             rcpt_errors: 0,
             message,
             authenticated_as: "local".into(),
+            authenticated_emails: Vec::new(),
+            on_behalf_emails: Vec::new(),
             auth_errors: 0,
             priority: 0,
             delivery_by: 0,
@@ -595,7 +658,12 @@ impl SessionData {
             iprev: None,
             spf_ehlo: None,
             spf_mail_from: None,
+            spf_dnssec: None,
             dnsbl_error: None,
+            early_talker: false,
+            pipelining: false,
+            pipelining_violations: 0,
+            tarpit_count: 0,
         }
     }
 }
@@ -48,18 +48,21 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
         self.params.auth_errors_max = *ac.errors_max.eval(self).await;
         self.params.auth_errors_wait = *ac.errors_wait.eval(self).await;
         self.params.auth_plain_text = *ac.allow_plain_text.eval(self).await;
+        self.params.auth_match_sender = *ac.must_match_sender.eval(self).await;
+        self.params.auth_send_as_groups = *ac.send_as_groups.eval(self).await;
+        self.params.auth_send_as_delegates = *ac.send_as_delegates.eval(self).await;
 
         // VRFY/EXPN parameters
         let ec = &self.core.session.config.extensions;
-        self.params.can_expn = *ec.expn.eval(self).await;
-        self.params.can_vrfy = *ec.vrfy.eval(self).await;
+        self.params.expn = *ec.expn.eval(self).await;
+        self.params.vrfy = *ec.vrfy.eval(self).await;
     }
 
     pub async fn eval_post_auth_params(&mut self) {
         // Refresh VRFY/EXPN parameters
         let ec = &self.core.session.config.extensions;
-        self.params.can_expn = *ec.expn.eval(self).await;
-        self.params.can_vrfy = *ec.vrfy.eval(self).await;
+        self.params.expn = *ec.expn.eval(self).await;
+        self.params.vrfy = *ec.vrfy.eval(self).await;
     }
 
     pub async fn eval_rcpt_params(&mut self) {
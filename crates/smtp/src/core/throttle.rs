@@ -23,6 +23,7 @@
 
 use ::utils::listener::limiter::{ConcurrencyLimiter, RateLimiter};
 use dashmap::mapref::entry::Entry;
+use store::LookupStore;
 use tokio::io::{AsyncRead, AsyncWrite};
 use utils::config::{KeyLookup, Rate};
 
@@ -85,6 +86,19 @@ impl BuildHasher for ThrottleKeyHasherBuilder {
     }
 }
 
+impl ThrottleKey {
+    /// Checks a rate limit shared across nodes using a fixed window counter
+    /// stored in a lookup store. Returns the number of seconds until the next
+    /// window when the limit has been exceeded.
+    pub async fn is_rate_allowed(
+        &self,
+        store: &LookupStore,
+        rate: &Rate,
+    ) -> store::Result<Option<u64>> {
+        store.is_rate_allowed(&self.hash, rate, false).await
+    }
+}
+
 impl QueueQuota {
     pub fn new_key(&self, e: &impl KeyLookup<Key = EnvelopeKey>) -> ThrottleKey {
         let mut hasher = blake3::Hasher::new();
@@ -236,7 +250,42 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                 }
 
                 // Build throttle key
-                match self.core.session.throttle.entry(t.new_key(self)) {
+                let key = t.new_key(self);
+
+                // Check distributed rate limit
+                let mut is_distributed = false;
+                if let (Some(rate), Some(store)) = (&t.rate, &self.core.session.throttle_store) {
+                    match key.is_rate_allowed(store, rate).await {
+                        Ok(None) => {
+                            is_distributed = true;
+                        }
+                        Ok(Some(_)) => {
+                            tracing::debug!(
+                                parent: &self.span,
+                                context = "throttle",
+                                event = "rate-limit-exceeded",
+                                max_requests = rate.requests,
+                                max_interval = rate.period.as_secs(),
+                                "Rate limit exceeded."
+                            );
+                            return false;
+                        }
+                        Err(err) => {
+                            tracing::warn!(
+                                parent: &self.span,
+                                context = "throttle",
+                                event = "error",
+                                reason = %err,
+                                "Failed to check distributed rate limit, using local limiter."
+                            );
+                        }
+                    }
+                }
+                if is_distributed && t.concurrency.is_none() {
+                    continue;
+                }
+
+                match self.core.session.throttle.entry(key) {
                     Entry::Occupied(mut e) => {
                         let limiter = e.get_mut();
                         if let Some(limiter) = &limiter.concurrency {
@@ -253,7 +302,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                                 return false;
                             }
                         }
-                        if let Some(limiter) = &mut limiter.rate {
+                        if let Some(limiter) = limiter.rate.as_mut().filter(|_| !is_distributed) {
                             if !limiter.is_allowed() {
                                 tracing::debug!(
                                     parent: &self.span,
@@ -275,7 +324,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                             }
                             limiter
                         });
-                        let rate = t.rate.as_ref().map(|rate| {
+                        let rate = t.rate.as_ref().filter(|_| !is_distributed).map(|rate| {
                             let mut r = RateLimiter::new(
                                 rate.requests,
                                 std::cmp::min(rate.period, Duration::from_secs(1)),
@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method,
+};
+use tracing::Span;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Webhook<'x> {
+    pub method: Method,
+    pub url: &'x str,
+    pub token: Option<&'x str>,
+    pub timeout: Duration,
+}
+
+impl<'x> Webhook<'x> {
+    pub fn post(url: &'x str) -> Self {
+        Webhook {
+            method: Method::POST,
+            url,
+            token: None,
+            timeout: WEBHOOK_TIMEOUT,
+        }
+    }
+
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn with_token(mut self, token: &'x str) -> Self {
+        if !token.is_empty() {
+            self.token = Some(token);
+        }
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Sends a JSON payload, any response other than 2xx is an error
+    pub async fn send(&self, body: String) -> Result<(), String> {
+        let client_builder = reqwest::Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .timeout(self.timeout);
+
+        #[cfg(feature = "test_mode")]
+        let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+        let mut request = client_builder
+            .build()
+            .map_err(|err| err.to_string())?
+            .request(self.method.clone(), self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(token) = self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!("Server responded with {}", response.status())),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+// Notifies a webhook in the background, failures are only logged
+pub fn spawn_webhook(url: String, body: String, span: Span) {
+    tokio::spawn(async move {
+        if let Err(err) = Webhook::post(&url).send(body).await {
+            tracing::debug!(
+                parent: &span,
+                context = "webhook",
+                event = "error",
+                url = url,
+                reason = err.as_str(),
+                "Failed to notify webhook."
+            );
+        }
+    });
+}
@@ -25,6 +25,8 @@ use std::sync::{atomic::Ordering, Arc};
 
 use tokio::sync::oneshot;
 
+use crate::inbound::{dictionary, send_limits};
+
 use super::SMTP;
 
 impl SMTP {
@@ -63,6 +65,11 @@ impl SMTP {
                         .map_or(false, |r| r.elapsed() < r.max_interval)
             });
         }
+        dictionary::purge(
+            &self.session.dictionary,
+            self.session.config.rcpt.dictionary.window,
+        );
+        send_limits::purge(&self.session.senders);
         self.queue.quota.retain(|_, v| {
             v.messages.load(Ordering::Relaxed) > 0 || v.size.load(Ordering::Relaxed) > 0
         });
@@ -26,6 +26,7 @@ use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 use smtp_proto::{IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2};
 use tokio::io::{AsyncRead, AsyncWrite};
+use utils::stats::ServerStats;
 
 use crate::core::Session;
 
@@ -175,30 +176,68 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     }
 
     pub async fn authenticate(&mut self, credentials: Credentials<String>) -> Result<bool, ()> {
-        if let Some(lookup) = &self.params.auth_directory {
+        if let Some(lookup) = self.params.auth_directory.clone() {
             let authenticated_as = match &credentials {
                 Credentials::Plain { username, .. }
                 | Credentials::XOauth2 { username, .. }
                 | Credentials::OAuthBearer { token: username } => username.to_string(),
             };
-            if let Ok(is_authenticated) = lookup
-                .query(QueryBy::Credentials(&credentials), false)
+            if let Ok(principal) = lookup
+                .query(
+                    QueryBy::Credentials(&credentials),
+                    self.params.auth_match_sender && self.params.auth_send_as_groups,
+                )
                 .await
-                .map(|r| r.is_some())
             {
                 tracing::debug!(
                     parent: &self.span,
                     context = "auth",
                     event = "authenticate",
-                    result = if is_authenticated {"success"} else {"failed"}
+                    result = if principal.is_some() {"success"} else {"failed"}
                 );
-                return if is_authenticated {
+                return if let Some(principal) = principal {
+                    // Enforce concurrency limits shared with other protocols
+                    if let Some(in_flight) = self
+                        .instance
+                        .connections
+                        .is_account_allowed(principal.id, self.instance.max_connections_account)
+                    {
+                        self.in_flight.push(in_flight);
+                    } else {
+                        self.write(
+                            b"421 4.7.0 Too many concurrent connections for this account.\r\n",
+                        )
+                        .await?;
+                        tracing::debug!(
+                            parent: &self.span,
+                            event = "disconnect",
+                            reason = "too-many-connections",
+                            "Too many concurrent connections for this account."
+                        );
+                        return Err(());
+                    }
+
+                    self.span.record("account", principal.name.as_str());
                     self.data.authenticated_as = authenticated_as;
+
+                    // Report the login for new device notifications
+                    #[cfg(feature = "local_delivery")]
+                    let _ = self
+                        .core
+                        .event_tx
+                        .try_send(utils::ipc::DeliveryEvent::Login {
+                            account_id: principal.id,
+                            remote_ip: self.data.remote_ip,
+                        });
                     self.eval_post_auth_params().await;
+                    if self.params.auth_match_sender {
+                        self.load_sender_identities(&lookup, &principal).await;
+                    }
                     self.write(b"235 2.7.0 Authentication succeeded.\r\n")
                         .await?;
                     Ok(false)
                 } else {
+                    ServerStats::get().record_auth_failure();
                     self.auth_error(b"535 5.7.8 Authentication credentials invalid.\r\n")
                         .await
                 };
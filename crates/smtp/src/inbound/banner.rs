@@ -0,0 +1,185 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_builder::encoders::base64::base64_encode;
+use mail_parser::{HeaderName, MessageParser, PartType};
+
+use crate::config::Banner;
+
+impl Banner {
+    /// Prepends the warning banner to the text and HTML bodies of a message.
+    pub fn apply(&self, raw_message: &[u8]) -> Option<Vec<u8>> {
+        insert_notice(
+            raw_message,
+            self.text.as_deref(),
+            self.html.as_deref(),
+            NoticePosition::Top,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NoticePosition {
+    Top,
+    Bottom,
+}
+
+/// Inserts a notice in the text and HTML bodies of a message. Each modified
+/// part is re-encoded as base64 UTF-8, the remaining MIME structure is left
+/// untouched.
+pub(crate) fn insert_notice(
+    raw_message: &[u8],
+    text: Option<&str>,
+    html: Option<&str>,
+    position: NoticePosition,
+) -> Option<Vec<u8>> {
+    let message = MessageParser::new().parse(raw_message)?;
+    let mut replacements = Vec::new();
+
+    for part_id in message.text_body.iter().chain(message.html_body.iter()) {
+        if replacements.iter().any(|(id, _, _, _)| id == part_id) {
+            continue;
+        }
+        let part = message.parts.get(*part_id)?;
+        let (content_type, contents) = match &part.body {
+            PartType::Text(body) => {
+                if let Some(notice) = text {
+                    let mut contents = String::with_capacity(notice.len() + body.len() + 6);
+                    if position == NoticePosition::Top {
+                        contents.push_str(notice);
+                        contents.push_str("\r\n\r\n");
+                        contents.push_str(body);
+                    } else {
+                        contents.push_str(body.trim_end());
+                        contents.push_str("\r\n\r\n");
+                        contents.push_str(notice);
+                        contents.push_str("\r\n");
+                    }
+                    ("text/plain", contents)
+                } else {
+                    continue;
+                }
+            }
+            PartType::Html(body) => {
+                let notice = if let Some(notice) = html {
+                    notice.to_string()
+                } else if let Some(notice) = text {
+                    format!("<p>{}</p>", html_escape(notice))
+                } else {
+                    continue;
+                };
+                let insert_at = if position == NoticePosition::Top {
+                    find_body_start(body).unwrap_or(0)
+                } else {
+                    find_body_end(body).unwrap_or(body.len())
+                };
+                let mut contents = String::with_capacity(notice.len() + body.len());
+                contents.push_str(&body[..insert_at]);
+                contents.push_str(&notice);
+                contents.push_str(&body[insert_at..]);
+                ("text/html", contents)
+            }
+            _ => continue,
+        };
+
+        // Copy all headers except the ones describing the content encoding
+        let mut part_bytes = Vec::with_capacity(contents.len() * 4 / 3 + 256);
+        for header in &part.headers {
+            if !matches!(
+                header.name,
+                HeaderName::ContentType | HeaderName::ContentTransferEncoding
+            ) {
+                part_bytes.extend_from_slice(header.name.as_str().as_bytes());
+                part_bytes.push(b':');
+                part_bytes.extend_from_slice(
+                    raw_message
+                        .get(header.offset_start..header.offset_end)
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        if *part_id == 0
+            && !part
+                .headers
+                .iter()
+                .any(|h| matches!(h.name, HeaderName::MimeVersion))
+        {
+            part_bytes.extend_from_slice(b"MIME-Version: 1.0\r\n");
+        }
+        part_bytes.extend_from_slice(b"Content-Type: ");
+        part_bytes.extend_from_slice(content_type.as_bytes());
+        part_bytes.extend_from_slice(b"; charset=\"utf-8\"\r\n");
+        part_bytes.extend_from_slice(b"Content-Transfer-Encoding: base64\r\n\r\n");
+        for line in base64_encode(contents.as_bytes()).ok()?.chunks(76) {
+            part_bytes.extend_from_slice(line);
+            part_bytes.extend_from_slice(b"\r\n");
+        }
+
+        replacements.push((*part_id, part.offset_header, part.offset_end, part_bytes));
+    }
+
+    if replacements.is_empty() {
+        return None;
+    }
+
+    // Rebuild the message
+    replacements.sort_unstable_by_key(|(_, start, _, _)| *start);
+    let mut new_message = Vec::with_capacity(raw_message.len() + 1024);
+    let mut last_offset = 0;
+    for (_, start, end, part_bytes) in replacements {
+        if start < last_offset {
+            return None;
+        }
+        new_message.extend_from_slice(raw_message.get(last_offset..start)?);
+        new_message.extend_from_slice(&part_bytes);
+        last_offset = end;
+    }
+    new_message.extend_from_slice(raw_message.get(last_offset..)?);
+
+    Some(new_message)
+}
+
+fn find_body_start(html: &str) -> Option<usize> {
+    let start = html.to_ascii_lowercase().find("<body")?;
+    html[start..].find('>').map(|pos| start + pos + 1)
+}
+
+fn find_body_end(html: &str) -> Option<usize> {
+    html.to_ascii_lowercase().rfind("</body")
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("<br>"),
+            '\r' => (),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -31,26 +31,40 @@ use std::{
 
 use mail_auth::{
     common::{headers::HeaderWriter, verify::VerifySignature},
-    dmarc, AuthenticatedMessage, AuthenticationResults, DkimResult, DmarcResult, ReceivedSpf,
+    dmarc,
+    hickory_resolver::proto::rr::RecordType,
+    AuthenticatedMessage, AuthenticationResults, DkimResult, DmarcResult, ReceivedSpf,
 };
 use mail_builder::headers::{date::Date, message_id::generate_message_id_header};
+use mail_parser::MessageParser;
 use sieve::runtime::Variable;
 use smtp_proto::{
-    MAIL_BY_RETURN, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    MAIL_BY_RETURN, MAIL_REQUIRETLS, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER,
+    RCPT_NOTIFY_SUCCESS,
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     process::Command,
 };
+use utils::{
+    message::{LimitExceeded, MessageLimits},
+    stats::ServerStats,
+};
 
 use crate::{
+    config::{DlpAction, PolicyStage},
     core::{Session, SessionAddress, State},
-    queue::{self, Message, SimpleEnvelope},
+    outbound::dane::DnssecStatus,
+    queue::{
+        self, history::HistoryDetails, quarantine::DlpIncident, suppression::SuppressionAction,
+        Message, SimpleEnvelope,
+    },
     reporting::analysis::AnalyzeReport,
     scripts::{ScriptModification, ScriptResult},
+    wasm::PluginStage,
 };
 
-use super::{AuthResult, IsTls};
+use super::{fixup::FixupResult, honeypot::RCPT_HONEYPOT, AuthResult, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
     pub async fn queue_message(&mut self) -> Cow<'static, [u8]> {
@@ -67,6 +81,16 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             return (&b"550 5.7.7 Failed to parse message.\r\n"[..]).into();
         };
 
+        // Enforce outbound limits for authenticated senders
+        if let Err(response) = self.check_send_limits().await {
+            return response.into();
+        }
+
+        // Reject spoofed From addresses on submission
+        if let Err(response) = self.check_from_alignment(&raw_message) {
+            return response.into();
+        }
+
         // Loop detection
         let dc = &self.core.session.config.data;
         let ac = &self.core.mail_auth;
@@ -82,6 +106,35 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                 .into();
         }
 
+        // Verify message structure limits
+        let limits = MessageLimits {
+            max_headers: *dc.max_headers.eval(self).await,
+            max_depth: *dc.max_mime_depth.eval(self).await,
+            max_parts: *dc.max_mime_parts.eval(self).await,
+            max_addresses: *dc.max_addresses.eval(self).await,
+        };
+        if let Err(err) = limits.check_raw(raw_message.as_slice()) {
+            tracing::info!(parent: &self.span,
+                context = "data",
+                event = "limit-exceeded",
+                limit = err.as_str(),
+                return_path = self.data.mail_from.as_ref().unwrap().address,
+                from = auth_message.from(),
+                "{}", err.reason());
+            let [class, subject, detail] = err.code();
+            return format!(
+                "{} {class}.{subject}.{detail} {}\r\n",
+                if err == LimitExceeded::Parts {
+                    552
+                } else {
+                    550
+                },
+                err.reason()
+            )
+            .into_bytes()
+            .into();
+        }
+
         // Verify DKIM
         let dkim = *ac.dkim.verify.eval(self).await;
         let dmarc = *ac.dmarc.verify.eval(self).await;
@@ -133,6 +186,36 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             vec![]
         };
 
+        // Obtain the DNSSEC validation status of the DKIM key record
+        let dkim_dnssec = match dkim_output
+            .iter()
+            .find(|r| matches!(r.result(), DkimResult::Pass))
+            .and_then(|r| r.signature())
+        {
+            Some(signature) if self.core.resolvers.dnssec.is_enabled() => {
+                let key = format!("{}._domainkey.{}", signature.selector(), signature.domain());
+                let status = self
+                    .core
+                    .resolvers
+                    .dnssec_status(key.as_str(), RecordType::TXT)
+                    .await;
+
+                tracing::debug!(parent: &self.span,
+                    context = "dnssec",
+                    event = "lookup",
+                    identity = "dkim",
+                    domain = key,
+                    status = status.as_str(),
+                );
+
+                if status == DnssecStatus::Bogus && dkim.is_strict() {
+                    return (&b"451 4.7.20 DKIM key failed DNSSEC validation.\r\n"[..]).into();
+                }
+                status.into()
+            }
+            _ => None,
+        };
+
         // Verify ARC
         let arc = *ac.arc.verify.eval(self).await;
         let arc_sealer = ac.arc.seal.eval_and_capture(self).await.into_value(self);
@@ -279,6 +362,33 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             _ => (None, None),
         };
 
+        // Penalize senders of messages addressed to spam traps
+        if self
+            .data
+            .rcpt_to
+            .iter()
+            .any(|rcpt| (rcpt.flags & RCPT_HONEYPOT) != 0)
+        {
+            self.penalize_honeypot_sender(auth_message.from(), dmarc_result.as_ref())
+                .await;
+
+            if self
+                .data
+                .rcpt_to
+                .iter()
+                .all(|rcpt| (rcpt.flags & RCPT_HONEYPOT) != 0)
+            {
+                tracing::info!(parent: &self.span,
+                    context = "honeypot",
+                    event = "reject",
+                    return_path = mail_from.address,
+                    from = auth_message.from(),
+                    size = raw_message.len());
+
+                return (&b"550 5.7.1 Message rejected.\r\n"[..]).into();
+            }
+        }
+
         // Analyze reports
         if self.is_report() {
             self.core.analyze_report(raw_message.clone());
@@ -289,9 +399,11 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         }
 
         // Run Milter filters
+        let mut verdicts = Vec::new();
         let mut edited_message = match self.run_milters(&auth_message).await {
             Ok(modifications) => {
                 if !modifications.is_empty() {
+                    verdicts.push(HistoryDetails::filter("milter", "modified"));
                     tracing::debug!(
                     parent: &self.span,
                     context = "milter",
@@ -439,6 +551,12 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                         })
                         .collect::<Vec<_>>(),
                 )
+                .set_variable(
+                    "dkim.dnssec",
+                    dkim_dnssec
+                        .map(|status: DnssecStatus| status.as_str())
+                        .unwrap_or_default(),
+                )
                 .set_variable(
                     "dmarc.result",
                     dmarc_result
@@ -455,11 +573,15 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                 );
 
             let modifications = match self.run_script(script.clone(), params).await {
-                ScriptResult::Accept { modifications } => modifications,
+                ScriptResult::Accept { modifications } => {
+                    verdicts.push(HistoryDetails::filter("sieve", "accept"));
+                    modifications
+                }
                 ScriptResult::Replace {
                     message,
                     modifications,
                 } => {
+                    verdicts.push(HistoryDetails::filter("sieve", "replace"));
                     edited_message = Arc::new(message).into();
                     modifications
                 }
@@ -494,11 +616,250 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             }
         }
 
+        // WebAssembly plugins
+        if self.core.plugins.has_stage(PluginStage::Data) {
+            let params = self
+                .build_plugin_parameters(PluginStage::Data)
+                .with_message(edited_message.as_ref().unwrap_or(&raw_message).clone());
+            match self.core.run_plugins(params, self.span.clone()).await {
+                ScriptResult::Accept { modifications } => {
+                    verdicts.push(HistoryDetails::filter("wasm", "accept"));
+                    for modification in modifications {
+                        if let ScriptModification::AddHeader { name, value } = modification {
+                            headers.extend_from_slice(name.as_bytes());
+                            headers.extend_from_slice(b": ");
+                            headers.extend_from_slice(value.as_bytes());
+                            headers.extend_from_slice(b"\r\n");
+                        }
+                    }
+                }
+                ScriptResult::Reject(message) => {
+                    tracing::info!(parent: &self.span,
+                        context = "wasm",
+                        event = "reject",
+                        reason = message);
+
+                    return message.into_bytes().into();
+                }
+                ScriptResult::Discard => {
+                    return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
+                }
+                ScriptResult::Replace { .. } => (),
+            }
+        }
+
+        // Policy services
+        match self
+            .run_policy_services(
+                PolicyStage::Data,
+                edited_message.as_ref().unwrap_or(&raw_message).len(),
+            )
+            .await
+        {
+            ScriptResult::Accept { modifications } => {
+                for modification in modifications {
+                    if let ScriptModification::AddHeader { name, value } = modification {
+                        headers.extend_from_slice(name.as_bytes());
+                        headers.extend_from_slice(b": ");
+                        headers.extend_from_slice(value.as_bytes());
+                        headers.extend_from_slice(b"\r\n");
+                    }
+                }
+            }
+            ScriptResult::Reject(message) => {
+                tracing::info!(parent: &self.span,
+                    context = "policy",
+                    event = "reject",
+                    reason = message);
+
+                return message.into_bytes().into();
+            }
+            _ => (),
+        }
+
+        // Add external sender banner
+        if *dc.banner.enable.eval(self).await {
+            if let Some(message) = dc
+                .banner
+                .apply(edited_message.as_ref().unwrap_or(&raw_message))
+            {
+                verdicts.push(HistoryDetails::filter("banner", "added"));
+                edited_message = Arc::new(message).into();
+            }
+        }
+
+        // Apply submission fixups
+        let mut has_date_header = auth_message.has_date_header();
+        let mut has_message_id_header = auth_message.has_message_id_header();
+        if !self.data.authenticated_as.is_empty() && *dc.fixup.enable.eval(self).await {
+            match dc.fixup.apply(
+                edited_message.as_ref().unwrap_or(&raw_message),
+                &self.data.mail_from.as_ref().unwrap().address,
+                &self.instance.hostname,
+            ) {
+                FixupResult::Unchanged => (),
+                FixupResult::Modified(message) => {
+                    verdicts.push(HistoryDetails::filter("fixup", "modified"));
+                    has_date_header = true;
+                    has_message_id_header = true;
+                    edited_message = Arc::new(message).into();
+                }
+                FixupResult::Reject(response) => {
+                    tracing::info!(parent: &self.span,
+                        context = "fixup",
+                        event = "reject",
+                        reason = std::str::from_utf8(response).unwrap_or_default().trim_end());
+
+                    return response.into();
+                }
+            }
+        }
+
+        // Scan submissions for sensitive content
+        let mut dlp_rule = None;
+        if !self.data.authenticated_as.is_empty() && *dc.dlp.enable.eval(self).await {
+            if let Some(rule) = dc.dlp.scan(
+                &self.data.mail_from.as_ref().unwrap().domain,
+                edited_message.as_ref().unwrap_or(&raw_message),
+            ) {
+                let mut action = rule.action;
+                if action == DlpAction::Quarantine && !self.core.queue.quarantine.is_enabled() {
+                    action = DlpAction::Reject;
+                }
+                verdicts.push(HistoryDetails::filter(
+                    "dlp",
+                    format!("{} ({})", action.as_str(), rule.id),
+                ));
+
+                if action == DlpAction::Reject {
+                    self.core.queue.quarantine.record_incident(DlpIncident {
+                        timestamp: mail_parser::DateTime::from_timestamp(
+                            SystemTime::now()
+                                .duration_since(SystemTime::UNIX_EPOCH)
+                                .map_or(0, |d| d.as_secs()) as i64,
+                        ),
+                        rule: rule.id.clone(),
+                        action,
+                        return_path: self.data.mail_from.as_ref().unwrap().address.clone(),
+                        recipients: self
+                            .data
+                            .rcpt_to
+                            .iter()
+                            .map(|rcpt| rcpt.address.clone())
+                            .collect(),
+                        authenticated_as: self.data.authenticated_as.clone(),
+                        queue_id: None,
+                    });
+
+                    return (b"550 5.7.1 Message rejected by data loss prevention policy.\r\n"[..])
+                        .into();
+                }
+                dlp_rule = Some((rule, action));
+            }
+        }
+
+        // Append outbound disclaimer
+        if !self.data.authenticated_as.is_empty() && *dc.disclaimer.enable.eval(self).await {
+            if let Some((template, message)) = dc.disclaimer.apply(
+                &self.data.mail_from.as_ref().unwrap().domain,
+                edited_message.as_ref().unwrap_or(&raw_message),
+            ) {
+                verdicts.push(HistoryDetails::filter("disclaimer", template.id.clone()));
+                edited_message = Arc::new(message).into();
+            }
+        }
+
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
-        let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let mut rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        rcpt_to.retain(|rcpt| (rcpt.flags & RCPT_HONEYPOT) == 0);
+
+        // Drop submissions to suppressed addresses
+        if self.core.queue.suppression.action == SuppressionAction::Drop
+            && !self.data.authenticated_as.is_empty()
+        {
+            let mut allowed_rcpts = Vec::with_capacity(rcpt_to.len());
+            for rcpt in rcpt_to {
+                if self.is_suppressed(&rcpt).await {
+                    tracing::info!(parent: &self.span,
+                        context = "data",
+                        event = "suppressed",
+                        address = &rcpt.address_lcase,
+                        "Dropping recipient on the suppression list.");
+                } else {
+                    allowed_rcpts.push(rcpt);
+                }
+            }
+            if allowed_rcpts.is_empty() {
+                return (b"550 5.1.1 All recipients are suppressed due to previous bounces or complaints.\r\n"[..]).into();
+            }
+            rcpt_to = allowed_rcpts;
+        }
+
         let mut message = self.build_message(mail_from, rcpt_to).await;
 
+        // Record data loss prevention incidents
+        if let Some((rule, action)) = dlp_rule {
+            if action == DlpAction::RequireTls {
+                message.flags |= MAIL_REQUIRETLS;
+            }
+            self.core.queue.quarantine.record_incident(DlpIncident {
+                timestamp: mail_parser::DateTime::from_timestamp(message.created as i64),
+                rule: rule.id.clone(),
+                action,
+                return_path: message.return_path.clone(),
+                recipients: message
+                    .recipients
+                    .iter()
+                    .map(|rcpt| rcpt.address.clone())
+                    .collect(),
+                authenticated_as: self.data.authenticated_as.clone(),
+                queue_id: message.id.into(),
+            });
+        }
+
+        // Record how the message was received and the filter verdicts
+        let history = &self.core.queue.history;
+        if history.is_enabled() {
+            let mut events = vec![HistoryDetails::Received {
+                listener: self.instance.id.clone(),
+                remote_ip: self.data.remote_ip,
+                authenticated_as: self.data.authenticated_as.clone(),
+                return_path: message.return_path.clone(),
+                recipients: message
+                    .recipients
+                    .iter()
+                    .map(|rcpt| rcpt.address.clone())
+                    .collect(),
+            }];
+            if let Some(spf_ehlo) = &self.data.spf_ehlo {
+                events.push(HistoryDetails::filter(
+                    "spf-ehlo",
+                    spf_ehlo.result().to_string(),
+                ));
+            }
+            if let Some(spf_mail_from) = &self.data.spf_mail_from {
+                events.push(HistoryDetails::filter(
+                    "spf",
+                    spf_mail_from.result().to_string(),
+                ));
+            }
+            for output in &dkim_output {
+                events.push(HistoryDetails::filter("dkim", output.result().to_string()));
+            }
+            if let Some(arc_output) = &arc_output {
+                events.push(HistoryDetails::filter(
+                    "arc",
+                    arc_output.result().to_string(),
+                ));
+            }
+            if let Some(dmarc_result) = &dmarc_result {
+                events.push(HistoryDetails::filter("dmarc", dmarc_result.to_string()));
+            }
+            events.extend(verdicts);
+            history.record_all(message.id, events);
+        }
+
         // Add Received header
         if *dc.add_received.eval(self).await {
             self.write_received(&mut headers, message.id)
@@ -543,12 +904,12 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         }
 
         // Add any missing headers
-        if !auth_message.has_date_header() && *dc.add_date.eval(self).await {
+        if !has_date_header && *dc.add_date.eval(self).await {
             headers.extend_from_slice(b"Date: ");
             headers.extend_from_slice(Date::now().to_rfc822().as_bytes());
             headers.extend_from_slice(b"\r\n");
         }
-        if !auth_message.has_message_id_header() && *dc.add_message_id.eval(self).await {
+        if !has_message_id_header && *dc.add_message_id.eval(self).await {
             headers.extend_from_slice(b"Message-ID: ");
             let _ = generate_message_id_header(&mut headers, &self.instance.hostname);
             headers.extend_from_slice(b"\r\n");
@@ -581,9 +942,70 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         // Update size
         message.size = raw_message.len() + headers.len();
 
+        // Index the history by Message-ID
+        if history.is_enabled() {
+            let parser = MessageParser::new();
+            if let Some(message_id) = parser
+                .parse_headers(raw_message.as_slice())
+                .and_then(|m| m.message_id().map(|id| id.to_string()))
+                .or_else(|| {
+                    parser
+                        .parse_headers(headers.as_slice())
+                        .and_then(|m| m.message_id().map(|id| id.to_string()))
+                })
+            {
+                history.set_message_id(message.id, &message_id);
+            }
+        }
+
+        // Hold messages quarantined by data loss prevention rules for review
+        if let Some((rule, DlpAction::Quarantine)) = dlp_rule {
+            return if self
+                .core
+                .queue
+                .quarantine
+                .store(
+                    &message,
+                    &rule.id,
+                    &self.data.authenticated_as,
+                    &headers,
+                    &raw_message,
+                )
+                .await
+            {
+                tracing::info!(parent: &self.span,
+                    context = "dlp",
+                    event = "quarantine",
+                    id = message.id,
+                    rule = &rule.id,
+                    "Message quarantined for review.");
+
+                self.data.messages_sent += 1;
+                (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
+            } else {
+                (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
+            };
+        }
+
         // Verify queue quota
         if self.core.queue.has_quota(&mut message).await {
             let queue_id = message.id;
+            let stream_event = self
+                .core
+                .queue
+                .streams
+                .is_enabled()
+                .then(|| self.build_stream_event(&message, &headers, &raw_message));
+            #[cfg(feature = "local_delivery")]
+            let submission = (!self.data.authenticated_as.is_empty()
+                && self.data.authenticated_as != "local")
+                .then(|| utils::ipc::DeliveryEvent::Submission {
+                    account: self.data.authenticated_as.clone(),
+                    domain: message.return_path_domain.clone(),
+                    size: message.size,
+                });
+            let sender_domain = message.return_path_domain.clone();
+            let num_rcpts = message.recipients.len() as u64;
             if self
                 .core
                 .queue
@@ -592,6 +1014,19 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             {
                 self.state = State::Accepted(queue_id);
                 self.data.messages_sent += 1;
+                self.track_sent_message(num_rcpts).await;
+                ServerStats::get().record_message(&sender_domain);
+
+                // Publish to the archival streams
+                if let Some(stream_event) = stream_event {
+                    self.core.queue.streams.publish(stream_event);
+                }
+
+                // Report submissions by authenticated users for traffic accounting
+                #[cfg(feature = "local_delivery")]
+                if let Some(submission) = submission {
+                    let _ = self.core.event_tx.try_send(submission);
+                }
                 (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
             } else {
                 (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
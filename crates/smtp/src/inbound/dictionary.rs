@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+use store::{write::now, LookupKey, LookupValue};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::{webhook::spawn_webhook, Session};
+
+use super::IsTls;
+
+#[derive(Debug, Default)]
+pub struct UnknownRecipients {
+    pub hits: VecDeque<Instant>,
+    pub blocked_until: Option<Instant>,
+}
+
+impl UnknownRecipients {
+    pub fn is_blocked(&self, now: Instant) -> bool {
+        self.blocked_until.map_or(false, |until| until > now)
+    }
+
+    pub fn is_active(&self, now: Instant, window: Duration) -> bool {
+        self.is_blocked(now)
+            || self
+                .hits
+                .back()
+                .map_or(false, |hit| now.duration_since(*hit) < window)
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn is_dictionary_blocked(&self) -> bool {
+        let network = remote_network(self.data.remote_ip);
+        let now_instant = Instant::now();
+        if self
+            .core
+            .session
+            .dictionary
+            .get(&network)
+            .map_or(false, |entry| entry.is_blocked(now_instant))
+        {
+            return true;
+        }
+
+        // Blocks issued by other nodes or before a restart
+        let store = match &self.core.session.config.rcpt.dictionary.store {
+            Some(store) => store,
+            None => return false,
+        };
+        match store
+            .key_get::<String>(LookupKey::Key(block_key(network)))
+            .await
+        {
+            Ok(LookupValue::Value { expires, .. }) => {
+                self.core
+                    .session
+                    .dictionary
+                    .entry(network)
+                    .or_default()
+                    .blocked_until =
+                    Some(now_instant + Duration::from_secs(expires.saturating_sub(now())));
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "rcpt",
+                    event = "error",
+                    reason = %err,
+                    "Failed to read dictionary attack block."
+                );
+                false
+            }
+        }
+    }
+
+    pub async fn track_unknown_rcpt(&self) {
+        let config = &self.core.session.config.rcpt.dictionary;
+        let max_unknown = match *config.max_unknown.eval(self).await {
+            Some(max_unknown) if max_unknown > 0 => max_unknown,
+            _ => return,
+        };
+        let window = config.window;
+        let cooldown = config.cooldown;
+        let network = remote_network(self.data.remote_ip);
+
+        // Count unknown recipients within the window
+        let now_instant = Instant::now();
+        let count = if let Some(store) = &config.store {
+            // Fixed windows shared by all nodes
+            let period = window.as_secs().max(1);
+            let timestamp = now();
+            let slot = timestamp / period;
+            match store
+                .counter_incr(
+                    counter_key(network, slot),
+                    1,
+                    (slot + 1) * period - timestamp,
+                )
+                .await
+            {
+                Ok(count) => count.max(0) as usize,
+                Err(err) => {
+                    tracing::debug!(
+                        parent: &self.span,
+                        context = "rcpt",
+                        event = "error",
+                        reason = %err,
+                        "Failed to update unknown recipients counter."
+                    );
+                    return;
+                }
+            }
+        } else {
+            let mut entry = self.core.session.dictionary.entry(network).or_default();
+            while entry
+                .hits
+                .front()
+                .map_or(false, |hit| now_instant.duration_since(*hit) >= window)
+            {
+                entry.hits.pop_front();
+            }
+            entry.hits.push_back(now_instant);
+            entry.hits.len()
+        };
+        if count < max_unknown {
+            return;
+        }
+
+        {
+            let mut entry = self.core.session.dictionary.entry(network).or_default();
+            if entry.is_blocked(now_instant) {
+                return;
+            }
+            entry.blocked_until = Some(now_instant + cooldown);
+            entry.hits.clear();
+        }
+        if let Some(store) = &config.store {
+            if let Err(err) = store
+                .key_set(
+                    block_key(network),
+                    LookupValue::Value {
+                        value: count.to_string().into_bytes(),
+                        expires: cooldown.as_secs().max(1),
+                    },
+                )
+                .await
+            {
+                tracing::warn!(
+                    parent: &self.span,
+                    context = "rcpt",
+                    event = "error",
+                    reason = %err,
+                    "Failed to persist dictionary attack block."
+                );
+            }
+        }
+
+        tracing::info!(
+            parent: &self.span,
+            context = "rcpt",
+            event = "dictionary-attack",
+            remote_ip = %self.data.remote_ip,
+            network = %network,
+            count = count,
+            window = window.as_secs(),
+            cooldown = cooldown.as_secs(),
+            "Too many unknown recipients, blocking remote network."
+        );
+
+        // Notify webhook
+        if let Some(url) = &config.webhook {
+            let body = serde_json::json!({
+                "event": "dictionary-attack",
+                "remoteIp": self.data.remote_ip.to_string(),
+                "network": network.to_string(),
+                "count": count,
+                "window": window.as_secs(),
+                "cooldown": cooldown.as_secs(),
+            })
+            .to_string();
+            spawn_webhook(url.clone(), body, self.span.clone());
+        }
+    }
+}
+
+// IPv6 clients usually control a whole /64, so addresses are tracked by prefix
+pub fn remote_network(remote_ip: IpAddr) -> IpAddr {
+    match remote_ip {
+        IpAddr::V4(_) => remote_ip,
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(Ipv6Addr::from(
+                u128::from(ip) & 0xffff_ffff_ffff_ffff_0000_0000_0000_0000,
+            )),
+        },
+    }
+}
+
+fn block_key(network: IpAddr) -> Vec<u8> {
+    format!("dictionary:{network}").into_bytes()
+}
+
+fn counter_key(network: IpAddr, slot: u64) -> Vec<u8> {
+    format!("dictionary:{network}:{slot}").into_bytes()
+}
+
+pub fn purge(entries: &dashmap::DashMap<IpAddr, UnknownRecipients>, window: Duration) {
+    let now = Instant::now();
+    entries.retain(|_, entry| entry.is_active(now, window));
+}
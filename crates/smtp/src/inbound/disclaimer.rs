@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_parser::MessageParser;
+
+use crate::config::{Disclaimer, DisclaimerTemplate};
+
+use super::banner::{insert_notice, NoticePosition};
+
+impl Disclaimer {
+    /// Appends the disclaimer template configured for the sender's domain to the
+    /// text and HTML bodies of a message. Replies within an existing thread are
+    /// left untouched when `skip_replies` is set, as are messages that already
+    /// include the disclaimer.
+    pub fn apply(
+        &self,
+        sender_domain: &str,
+        raw_message: &[u8],
+    ) -> Option<(&DisclaimerTemplate, Vec<u8>)> {
+        let template = self.template(sender_domain)?;
+        let message = MessageParser::new().parse(raw_message)?;
+        if self.skip_replies
+            && (!message.in_reply_to().is_empty() || !message.references().is_empty())
+        {
+            return None;
+        }
+        if let Some(text) = &template.text {
+            if (0..message.text_body.len())
+                .filter_map(|pos| message.body_text(pos))
+                .any(|body| body.contains(text.as_str()))
+            {
+                return None;
+            }
+        }
+
+        insert_notice(
+            raw_message,
+            template.text.as_deref(),
+            template.html.as_deref(),
+            NoticePosition::Bottom,
+        )
+        .map(|message| (template, message))
+    }
+
+    fn template(&self, sender_domain: &str) -> Option<&DisclaimerTemplate> {
+        self.templates
+            .iter()
+            .find(|template| {
+                template
+                    .domains
+                    .iter()
+                    .any(|domain| domain.eq_ignore_ascii_case(sender_domain))
+            })
+            .or_else(|| {
+                self.templates
+                    .iter()
+                    .find(|template| template.domains.is_empty())
+            })
+    }
+}
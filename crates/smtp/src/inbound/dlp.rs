@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_parser::{MessageParser, MimeHeaders, PartType};
+
+use crate::config::{Dlp, DlpAction, DlpMatcher, DlpRule};
+
+impl Dlp {
+    /// Scans a message for sensitive content, returning the matching rule
+    /// with the most restrictive action.
+    pub fn scan(&self, sender_domain: &str, raw_message: &[u8]) -> Option<&DlpRule> {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.domains.is_empty()
+                    || rule
+                        .domains
+                        .iter()
+                        .any(|domain| domain.eq_ignore_ascii_case(sender_domain))
+            })
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return None;
+        }
+        let message = MessageParser::new().parse(raw_message)?;
+
+        // Obtain the text contents and attachment types of the message
+        let mut text = message.subject().unwrap_or_default().to_lowercase();
+        for pos in 0..message.text_body.len() {
+            if let Some(body) = message.body_text(pos) {
+                text.push('\n');
+                text.push_str(&body.to_lowercase());
+            }
+        }
+        let mut attachments = Vec::new();
+        for part in message.attachments() {
+            if let Some(name) = part.attachment_name() {
+                if let Some((_, ext)) = name.rsplit_once('.') {
+                    attachments.push(ext.to_lowercase());
+                }
+            }
+            if let Some(ct) = part.content_type() {
+                attachments.push(if let Some(subtype) = ct.subtype() {
+                    format!("{}/{}", ct.ctype(), subtype).to_lowercase()
+                } else {
+                    ct.ctype().to_lowercase()
+                });
+            }
+            if let PartType::Text(contents) = &part.body {
+                text.push('\n');
+                text.push_str(&contents.to_lowercase());
+            }
+        }
+
+        rules
+            .into_iter()
+            .filter(|rule| match &rule.matcher {
+                DlpMatcher::Regex(regex) => regex.is_match(&text),
+                DlpMatcher::Keywords(keywords) => keywords
+                    .iter()
+                    .any(|keyword| text.contains(keyword.as_str())),
+                DlpMatcher::Attachment(types) => attachments
+                    .iter()
+                    .any(|attachment| types.contains(attachment)),
+                DlpMatcher::CreditCard => has_card_number(&text),
+            })
+            .max_by_key(|rule| rule.action)
+    }
+}
+
+impl DlpAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DlpAction::RequireTls => "require-tls",
+            DlpAction::Quarantine => "quarantine",
+            DlpAction::Reject => "reject",
+        }
+    }
+}
+
+/// Looks for sequences of 13 to 19 digits, optionally separated by spaces
+/// or dashes, that pass the Luhn checksum.
+pub fn has_card_number(text: &str) -> bool {
+    let mut digits = Vec::with_capacity(19);
+    let mut last_separator = false;
+
+    for ch in text.chars().chain([' ', '\n']) {
+        match ch {
+            '0'..='9' => {
+                digits.push(ch as u8 - b'0');
+                last_separator = false;
+            }
+            ' ' | '-' if !digits.is_empty() && !last_separator => {
+                last_separator = true;
+            }
+            _ => {
+                if (13..=19).contains(&digits.len()) && is_luhn_valid(&digits) {
+                    return true;
+                }
+                digits.clear();
+                last_separator = false;
+            }
+        }
+        if digits.len() > 19 {
+            digits.clear();
+        }
+    }
+
+    false
+}
+
+fn is_luhn_valid(digits: &[u8]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(pos, digit)| {
+            let digit = *digit as u32;
+            if pos % 2 == 1 {
+                let digit = digit * 2;
+                if digit > 9 {
+                    digit - 9
+                } else {
+                    digit
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
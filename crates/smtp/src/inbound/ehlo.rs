@@ -23,7 +23,11 @@
 
 use std::time::SystemTime;
 
-use crate::{core::Session, scripts::ScriptResult};
+use crate::{
+    config::{AddressDisclosure, PolicyStage},
+    core::Session,
+    scripts::ScriptResult,
+};
 use mail_auth::spf::verify::HasLabels;
 use smtp_proto::*;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -100,6 +104,22 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
                 }
             }
 
+            // Policy services
+            if let ScriptResult::Reject(message) =
+                self.run_policy_services(PolicyStage::Ehlo, 0).await
+            {
+                tracing::info!(parent: &self.span,
+                    context = "policy",
+                    event = "reject",
+                    domain = &self.data.helo_domain,
+                    reason = message);
+
+                self.data.mail_from = None;
+                self.data.helo_domain = prev_helo_domain;
+                self.data.spf_ehlo = None;
+                return self.write(message.as_bytes()).await;
+            }
+
             tracing::debug!(parent: &self.span,
                 context = "ehlo",
                 event = "ehlo",
@@ -123,7 +143,8 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
         let dc = &self.core.session.config.data;
 
         // Pipelining
-        if *ec.pipelining.eval(self).await {
+        self.data.pipelining = *ec.pipelining.eval(self).await;
+        if self.data.pipelining {
             response.capabilities |= EXT_PIPELINING;
         }
 
@@ -132,13 +153,13 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             response.capabilities |= EXT_CHUNKING;
         }
 
-        // Address Expansion
-        if *ec.expn.eval(self).await {
+        // Address Expansion, only advertised when addresses are disclosed
+        if *ec.expn.eval(self).await == AddressDisclosure::Full {
             response.capabilities |= EXT_EXPN;
         }
 
         // Recipient Verification
-        if *ec.vrfy.eval(self).await {
+        if *ec.vrfy.eval(self).await == AddressDisclosure::Full {
             response.capabilities |= EXT_VRFY;
         }
 
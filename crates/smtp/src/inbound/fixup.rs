@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_builder::headers::{date::Date, message_id::generate_message_id_header};
+use mail_parser::MessageParser;
+
+use crate::config::{FromPolicy, SubmissionFixup};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FixupResult {
+    Unchanged,
+    Modified(Vec<u8>),
+    Reject(&'static [u8]),
+}
+
+impl SubmissionFixup {
+    /// Normalizes the header of a message submitted by an authenticated user:
+    /// strips the configured headers, enforces the header size limit, fixes or
+    /// rejects non-compliant From headers and adds any missing Date and Message-ID.
+    pub fn apply(&self, raw_message: &[u8], return_path: &str, hostname: &str) -> FixupResult {
+        let header_len = header_len(raw_message);
+        if self.max_header_size > 0 && header_len > self.max_header_size {
+            return FixupResult::Reject(b"552 5.3.4 Message header size exceeds limit.\r\n");
+        }
+        let (header, body) = raw_message.split_at(header_len);
+        let headers = split_headers(header);
+
+        // Obtain the headers that need fixing
+        let mut has_date = false;
+        let mut has_message_id = false;
+        let mut has_sender = false;
+        let mut from_count = 0;
+        let mut strip = false;
+        for (name, _) in &headers {
+            if name.eq_ignore_ascii_case("Date") {
+                has_date = true;
+            } else if name.eq_ignore_ascii_case("Message-ID") {
+                has_message_id = true;
+            } else if name.eq_ignore_ascii_case("Sender") {
+                has_sender = true;
+            } else if name.eq_ignore_ascii_case("From") {
+                from_count += 1;
+            }
+            strip |= self.is_stripped(name);
+        }
+        let rewrite_from = from_count != 1
+            || !MessageParser::new()
+                .parse_headers(header)
+                .and_then(|message| {
+                    message.from().and_then(|from| from.as_list()).map(|list| {
+                        !list.is_empty()
+                            && (list.len() == 1 || has_sender)
+                            && list
+                                .iter()
+                                .all(|addr| addr.address().map_or(false, |a| a.contains('@')))
+                    })
+                })
+                .unwrap_or(false);
+        let rewrite_from = match self.from {
+            FromPolicy::Rewrite if rewrite_from => {
+                if return_path.is_empty() {
+                    return FixupResult::Reject(b"550 5.6.0 Missing or invalid From header.\r\n");
+                }
+                true
+            }
+            FromPolicy::Reject if rewrite_from => {
+                return FixupResult::Reject(b"550 5.6.0 Missing or invalid From header.\r\n");
+            }
+            _ => false,
+        };
+
+        if !strip && !rewrite_from && has_date && has_message_id {
+            return FixupResult::Unchanged;
+        }
+
+        // Rebuild the header
+        let mut message = Vec::with_capacity(raw_message.len() + 128);
+        if rewrite_from {
+            message.extend_from_slice(b"From: <");
+            message.extend_from_slice(return_path.as_bytes());
+            message.extend_from_slice(b">\r\n");
+        }
+        if !has_date {
+            message.extend_from_slice(b"Date: ");
+            message.extend_from_slice(Date::now().to_rfc822().as_bytes());
+            message.extend_from_slice(b"\r\n");
+        }
+        if !has_message_id {
+            message.extend_from_slice(b"Message-ID: ");
+            let _ = generate_message_id_header(&mut message, hostname);
+            message.extend_from_slice(b"\r\n");
+        }
+        for (name, raw_header) in headers {
+            if !self.is_stripped(name) && !(rewrite_from && name.eq_ignore_ascii_case("From")) {
+                message.extend_from_slice(raw_header);
+            }
+        }
+        message.extend_from_slice(body);
+
+        FixupResult::Modified(message)
+    }
+
+    fn is_stripped(&self, name: &str) -> bool {
+        self.strip_headers
+            .iter()
+            .any(|strip| strip.eq_ignore_ascii_case(name))
+    }
+}
+
+// Length of the header block, including the line break that ends the last header
+fn header_len(raw_message: &[u8]) -> usize {
+    if raw_message.starts_with(b"\r\n") || raw_message.starts_with(b"\n") {
+        return 0;
+    }
+    raw_message
+        .iter()
+        .enumerate()
+        .find_map(|(pos, &ch)| {
+            (ch == b'\n' && matches!(&raw_message[pos + 1..], [b'\n', ..] | [b'\r', b'\n', ..]))
+                .then_some(pos + 1)
+        })
+        .unwrap_or(raw_message.len())
+}
+
+// Splits the header block into (name, raw header including folded lines)
+fn split_headers(header: &[u8]) -> Vec<(&str, &[u8])> {
+    let mut headers: Vec<(&str, &[u8])> = Vec::new();
+    let mut start = 0;
+    while start < header.len() {
+        let mut end = start;
+        loop {
+            match header[end..].iter().position(|&ch| ch == b'\n') {
+                Some(pos) => {
+                    end += pos + 1;
+                    if !matches!(header.get(end), Some(b' ' | b'\t')) {
+                        break;
+                    }
+                }
+                None => {
+                    end = header.len();
+                    break;
+                }
+            }
+        }
+        let raw_header = &header[start..end];
+        let name = raw_header
+            .iter()
+            .position(|&ch| ch == b':')
+            .and_then(|pos| std::str::from_utf8(&raw_header[..pos]).ok())
+            .map(|name| name.trim())
+            .unwrap_or_default();
+        headers.push((name, raw_header));
+        start = end;
+    }
+    headers
+}
@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, time::Duration};
+
+use store::{write::now, LookupKey, LookupStore, LookupValue};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{config::IfBlock, core::Session};
+
+use super::IsTls;
+
+/// Greylisting of (client network, sender, recipient) triplets. Triplets are
+/// kept in a lookup store so that a retry is recognized by any node.
+pub struct Greylist {
+    pub enable: IfBlock<bool>,
+    pub store: LookupStore,
+    pub delay: Duration,
+    pub retry_window: Duration,
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GreylistStatus {
+    Pass,
+    Defer,
+}
+
+impl Greylist {
+    pub async fn check(&self, remote_ip: IpAddr, sender: &str, rcpt: &str) -> GreylistStatus {
+        let key = Self::key(remote_ip, sender, rcpt);
+        let now = now();
+        let first_seen = match self
+            .store
+            .key_get::<String>(LookupKey::Key(key.clone()))
+            .await
+        {
+            Ok(LookupValue::Value { value, .. }) => value.parse::<u64>().ok(),
+            Ok(_) => None,
+            Err(err) => {
+                // Never block mail because the store is unavailable
+                tracing::debug!(
+                    context = "greylist",
+                    event = "error",
+                    reason = %err,
+                    "Failed to read greylist entry."
+                );
+                return GreylistStatus::Pass;
+            }
+        };
+
+        let (status, first_seen, expires) = match first_seen {
+            Some(first_seen) if first_seen + self.delay.as_secs() <= now => {
+                // Retried after the delay, keep the triplet allowed
+                (GreylistStatus::Pass, first_seen, self.ttl.as_secs())
+            }
+            Some(_) => return GreylistStatus::Defer,
+            None => (GreylistStatus::Defer, now, self.retry_window.as_secs()),
+        };
+
+        if let Err(err) = self
+            .store
+            .key_set(
+                key,
+                LookupValue::Value {
+                    value: first_seen.to_string().into_bytes(),
+                    expires,
+                },
+            )
+            .await
+        {
+            tracing::debug!(
+                context = "greylist",
+                event = "error",
+                reason = %err,
+                "Failed to write greylist entry."
+            );
+        }
+
+        status
+    }
+
+    fn key(remote_ip: IpAddr, sender: &str, rcpt: &str) -> Vec<u8> {
+        // Senders often retry from a different address of the same pool
+        let network = match remote_ip {
+            IpAddr::V4(ip) => {
+                let octets = ip.octets();
+                format!("{}.{}.{}", octets[0], octets[1], octets[2])
+            }
+            IpAddr::V6(ip) => {
+                let segments = ip.segments();
+                format!(
+                    "{:x}:{:x}:{:x}:{:x}",
+                    segments[0], segments[1], segments[2], segments[3]
+                )
+            }
+        };
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(network.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(sender.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(rcpt.as_bytes());
+
+        let mut key = Vec::with_capacity(35);
+        key.extend_from_slice(b"gl:");
+        key.extend_from_slice(hasher.finalize().as_bytes());
+        key
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn is_greylisted(&self) -> bool {
+        if let (Some(greylist), Some(mail_from), Some(rcpt)) = (
+            &self.core.session.config.rcpt.greylist,
+            &self.data.mail_from,
+            self.data.rcpt_to.last(),
+        ) {
+            *greylist.enable.eval(self).await
+                && greylist
+                    .check(
+                        self.data.remote_ip,
+                        &mail_from.address_lcase,
+                        &rcpt.address_lcase,
+                    )
+                    .await
+                    == GreylistStatus::Defer
+        } else {
+            false
+        }
+    }
+}
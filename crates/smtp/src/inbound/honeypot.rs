@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use mail_auth::DmarcResult;
+use sieve::runtime::Variable;
+use store::{LookupKey, LookupStore, LookupValue};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    core::{webhook::spawn_webhook, Session},
+    scripts::plugins::lookup::VariableWrapper,
+};
+
+use super::IsTls;
+
+/// Spam trap addresses. Traps are removed from the recipients of a message and
+/// messages sent only to traps are rejected, while the sender's IP address,
+/// return path and domain are penalized in the reputation store used by the
+/// spam filter.
+pub struct Honeypot {
+    pub addresses: AHashSet<String>,
+    pub domains: AHashSet<String>,
+    pub store: Option<LookupStore>,
+    pub score: f64,
+    pub ttl: Duration,
+    pub webhook: Option<String>,
+}
+
+/// Marks recipients that are spam traps, never stored in the queue.
+pub const RCPT_HONEYPOT: u64 = 1 << 40;
+
+impl Honeypot {
+    pub fn is_trap(&self, address: &str, domain: &str) -> bool {
+        self.addresses.contains(address) || self.domains.contains(domain)
+    }
+
+    /// Updates the reputation tokens using the same formula as the spam filter.
+    pub async fn penalize(&self, tokens: &[String]) {
+        let store = if let Some(store) = &self.store {
+            store
+        } else {
+            return;
+        };
+
+        for token in tokens {
+            let key = token.as_bytes().to_vec();
+            let (token_score, token_count) = match store
+                .key_get::<VariableWrapper>(LookupKey::Key(key.clone()))
+                .await
+            {
+                Ok(LookupValue::Value { value, .. }) => value
+                    .into_inner()
+                    .as_array()
+                    .and_then(|rep| Some((to_float(rep.first()?), rep.get(1)?.to_integer())))
+                    .unwrap_or((0.0, 0)),
+                Ok(_) => (0.0, 0),
+                Err(err) => {
+                    tracing::debug!(
+                        context = "honeypot",
+                        event = "error",
+                        reason = %err,
+                        "Failed to read reputation token."
+                    );
+                    return;
+                }
+            };
+            let updated_score = if token_count > 0 {
+                (token_count + 1) as f64 * (self.score + 0.98 * token_score)
+                    / (0.98 * token_count as f64 + 1.0)
+            } else {
+                self.score
+            };
+            let value = Variable::Array(
+                vec![
+                    Variable::Float(updated_score),
+                    Variable::Integer(token_count + 1),
+                ]
+                .into(),
+            );
+
+            if let Err(err) = store
+                .key_set(
+                    key,
+                    LookupValue::Value {
+                        value: bincode::serialize(&value).unwrap_or_default(),
+                        expires: self.ttl.as_secs(),
+                    },
+                )
+                .await
+            {
+                tracing::debug!(
+                    context = "honeypot",
+                    event = "error",
+                    reason = %err,
+                    "Failed to write reputation token."
+                );
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub fn is_honeypot(&self) -> bool {
+        if let (Some(honeypot), Some(rcpt)) = (
+            &self.core.session.config.rcpt.honeypot,
+            self.data.rcpt_to.last(),
+        ) {
+            self.data.authenticated_as.is_empty()
+                && honeypot.is_trap(&rcpt.address_lcase, &rcpt.domain)
+        } else {
+            false
+        }
+    }
+
+    pub async fn handle_honeypot(&mut self) {
+        let honeypot = self.core.session.config.rcpt.honeypot.as_ref().unwrap();
+        let is_first_hit = !self
+            .data
+            .rcpt_to
+            .iter()
+            .any(|rcpt| (rcpt.flags & RCPT_HONEYPOT) != 0);
+        let rcpt = self.data.rcpt_to.last_mut().unwrap();
+        rcpt.flags |= RCPT_HONEYPOT;
+        let rcpt = self.data.rcpt_to.last().unwrap();
+        let mail_from = self.data.mail_from.as_ref().unwrap();
+
+        tracing::info!(parent: &self.span,
+            context = "honeypot",
+            event = "trap",
+            remote_ip = %self.data.remote_ip,
+            return_path = &mail_from.address_lcase,
+            address = &rcpt.address_lcase,
+            "Message addressed to a spam trap.");
+
+        // Penalize the remote IP once per transaction, the sender address and
+        // domain are penalized once the DMARC result is known
+        if !is_first_hit {
+            return;
+        }
+        honeypot
+            .penalize(&[format!("i:{}", self.data.remote_ip)])
+            .await;
+
+        // Notify webhook
+        if let Some(url) = &honeypot.webhook {
+            let url = url.clone();
+            let body = serde_json::json!({
+                "event": "honeypot",
+                "remoteIp": self.data.remote_ip.to_string(),
+                "heloDomain": self.data.helo_domain,
+                "returnPath": mail_from.address_lcase,
+                "recipient": rcpt.address_lcase,
+            })
+            .to_string();
+            spawn_webhook(url, body, self.span.clone());
+        }
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn penalize_honeypot_sender(&self, from: &str, dmarc_result: Option<&DmarcResult>) {
+        let honeypot = if let Some(honeypot) = &self.core.session.config.rcpt.honeypot {
+            honeypot
+        } else {
+            return;
+        };
+
+        // Use the same tokens as the reputation script of the spam filter
+        let address = match self.data.mail_from.as_ref() {
+            Some(mail_from) if !mail_from.address_lcase.is_empty() => {
+                mail_from.address_lcase.clone()
+            }
+            _ => from.to_lowercase(),
+        };
+        if address.is_empty() {
+            return;
+        }
+        let prefix = if dmarc_result == Some(&DmarcResult::Pass) {
+            ""
+        } else {
+            // Do not penalize forged senders
+            "_"
+        };
+        let mut tokens = vec![format!("f:{prefix}{address}")];
+        if let Some(domain) = address
+            .rsplit_once('@')
+            .and_then(|(_, domain)| self.core.sieve.runtime.context().psl.domain_sld(domain))
+        {
+            tokens.push(format!("d:{prefix}{domain}"));
+        }
+        honeypot.penalize(&tokens).await;
+    }
+}
+
+fn to_float(value: &Variable) -> f64 {
+    match value {
+        Variable::Float(n) => *n,
+        Variable::Integer(n) => *n as f64,
+        _ => 0.0,
+    }
+}
@@ -23,20 +23,27 @@
 
 use std::time::SystemTime;
 
-use mail_auth::{IprevOutput, IprevResult, SpfOutput, SpfResult};
+use mail_auth::{
+    hickory_resolver::proto::rr::RecordType, IprevOutput, IprevResult, SpfOutput, SpfResult,
+};
 use smtp_proto::{MailFrom, MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
+    config::PolicyStage,
     core::{Session, SessionAddress},
+    outbound::dane::DnssecStatus,
     queue::DomainPart,
     scripts::{ScriptModification, ScriptResult},
+    wasm::PluginStage,
 };
 
-use super::IsTls;
+use super::{tarpit::TarpitStage, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
     pub async fn handle_mail_from(&mut self, from: MailFrom<String>) -> Result<(), ()> {
+        self.tarpit(TarpitStage::Mail).await?;
+
         if self.data.helo_domain.is_empty()
             && (self.params.ehlo_require
                 || self.params.spf_ehlo.verify()
@@ -53,6 +60,15 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             return self
                 .write(b"503 5.5.1 You must authenticate first.\r\n")
                 .await;
+        } else if self.is_send_locked().await {
+            tracing::info!(parent: &self.span,
+                context = "mail-from",
+                event = "reject",
+                reason = "send-locked",
+                account = self.data.authenticated_as);
+            return self
+                .write(b"550 5.7.1 Sending has been disabled for this account.\r\n")
+                .await;
         } else if self.data.iprev.is_none() && self.params.iprev.verify() {
             let iprev = self
                 .core
@@ -98,6 +114,20 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
 
         let (address, address_lcase, domain) = if !from.address.is_empty() {
             let address_lcase = from.address.to_lowercase();
+
+            // Authenticated users may only use their own addresses as return path
+            if !self.is_allowed_sender(&address_lcase) {
+                tracing::info!(parent: &self.span,
+                    context = "audit",
+                    event = "spoofed-sender",
+                    account = self.data.authenticated_as,
+                    return_path = address_lcase,
+                    "Rejected MAIL FROM not owned by the authenticated account.");
+                return self
+                    .write(b"501 5.5.4 You are not allowed to send from this address.\r\n")
+                    .await;
+            }
+
             let domain = address_lcase.domain_part().to_string();
             (from.address, address_lcase, domain)
         } else {
@@ -147,6 +177,39 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             }
         }
 
+        // WebAssembly plugins
+        if self.core.plugins.has_stage(PluginStage::Mail) {
+            if let ScriptResult::Reject(message) = self
+                .core
+                .run_plugins(
+                    self.build_plugin_parameters(PluginStage::Mail),
+                    self.span.clone(),
+                )
+                .await
+            {
+                tracing::info!(parent: &self.span,
+                    context = "wasm",
+                    event = "reject",
+                    address = &self.data.mail_from.as_ref().unwrap().address,
+                    reason = message);
+                self.data.mail_from = None;
+                return self.write(message.as_bytes()).await;
+            }
+        }
+
+        // Policy services
+        if let ScriptResult::Reject(message) =
+            self.run_policy_services(PolicyStage::Mail, from.size).await
+        {
+            tracing::info!(parent: &self.span,
+                context = "policy",
+                event = "reject",
+                address = &self.data.mail_from.as_ref().unwrap().address,
+                reason = message);
+            self.data.mail_from = None;
+            return self.write(message.as_bytes()).await;
+        }
+
         // Address rewriting
         if let Some(new_address) = self
             .core
@@ -307,6 +370,37 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
                         result = %spf_output.result(),
                 );
 
+                // Obtain the DNSSEC validation status of the SPF record
+                if self.core.resolvers.dnssec.is_enabled() {
+                    let mail_from = self.data.mail_from.as_ref().unwrap();
+                    let spf_domain = if !mail_from.address.is_empty() {
+                        mail_from.domain.as_str()
+                    } else {
+                        self.data.helo_domain.as_str()
+                    };
+                    let status = self
+                        .core
+                        .resolvers
+                        .dnssec_status(spf_domain, RecordType::TXT)
+                        .await;
+
+                    tracing::debug!(parent: &self.span,
+                        context = "dnssec",
+                        event = "lookup",
+                        identity = "mail-from",
+                        domain = spf_domain,
+                        status = status.as_str(),
+                    );
+
+                    if status == DnssecStatus::Bogus && self.params.spf_mail_from.is_strict() {
+                        self.data.mail_from = None;
+                        return self
+                            .write(b"451 4.7.24 SPF record failed DNSSEC validation.\r\n")
+                            .await;
+                    }
+                    self.data.spf_dnssec = status.into();
+                }
+
                 if self
                     .handle_spf(&spf_output, self.params.spf_mail_from.is_strict())
                     .await?
@@ -31,13 +31,25 @@ use tokio_rustls::server::TlsStream;
 use crate::config::{ArcSealer, DkimSigner};
 
 pub mod auth;
+pub mod banner;
 pub mod data;
+pub mod dictionary;
+pub mod disclaimer;
+pub mod dlp;
 pub mod ehlo;
+pub mod fixup;
+pub mod greylist;
+pub mod honeypot;
 pub mod mail;
 pub mod milter;
+pub mod policy;
 pub mod rcpt;
+pub mod rcpt_cache;
+pub mod send_limits;
+pub mod sender;
 pub mod session;
 pub mod spawn;
+pub mod tarpit;
 pub mod vrfy;
 
 pub trait IsTls {
@@ -197,3 +209,23 @@ impl AuthResult for Policy {
         }
     }
 }
+
+// Builds an SMTP reply from the text returned by an external filter, replies
+// that do not start with a status code are prefixed with the default one.
+pub(crate) fn smtp_reply(code: &str, default_text: &str, reply: Option<String>) -> String {
+    // Line breaks would allow filters to inject additional SMTP responses
+    let reply = reply.unwrap_or_default().replace(['\r', '\n'], " ");
+    let reply = reply.trim();
+    let mut result = if reply.len() > 4
+        && reply.as_bytes()[..3].iter().all(|ch| ch.is_ascii_digit())
+        && reply.as_bytes()[3] == b' '
+    {
+        reply.to_string()
+    } else if !reply.is_empty() {
+        format!("{code} {reply}")
+    } else {
+        format!("{code} {default_text}")
+    };
+    result.push_str("\r\n");
+    result
+}
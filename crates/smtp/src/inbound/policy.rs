@@ -0,0 +1,294 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Instant,
+};
+
+use reqwest::header::CONTENT_TYPE;
+use tokio::io::{AsyncRead, AsyncWrite};
+use utils::map::ttl_dashmap::TtlMap;
+
+use crate::{
+    config::{PolicyAction, PolicyService, PolicyStage},
+    core::Session,
+    scripts::{ScriptModification, ScriptResult},
+};
+
+use super::{smtp_reply, IsTls};
+
+const MAX_CACHE_ENTRIES: usize = 10_000;
+const MAX_HEADERS: usize = 32;
+
+// Request attributes follow the Postfix SMTPD policy delegation protocol
+// so existing policy daemons can be reused behind an HTTP adapter.
+#[derive(Debug, Hash, serde::Serialize)]
+pub struct PolicyRequest<'x> {
+    request: &'static str,
+    protocol_state: &'static str,
+    listener: &'x str,
+    client_address: String,
+    server_address: String,
+    helo_name: &'x str,
+    sender: &'x str,
+    recipient: &'x str,
+    recipient_count: usize,
+    sasl_username: &'x str,
+    encryption_protocol: &'static str,
+    encryption_cipher: &'static str,
+    size: usize,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PolicyResponse {
+    pub action: PolicyAction,
+    #[serde(default)]
+    pub reply: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<PolicyHeader>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PolicyHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    // Queries the policy services enabled for a stage in configuration order,
+    // headers returned by the services are only added at the DATA stage.
+    pub async fn run_policy_services(&self, stage: PolicyStage, size: usize) -> ScriptResult {
+        let mut modifications = Vec::new();
+        let mut request = None;
+
+        for service in &self.core.session.config.policy {
+            if !service.stages.contains(&stage) || !*service.enable.eval(self).await {
+                continue;
+            }
+            let request = request.get_or_insert_with(|| self.build_policy_request(stage, size));
+
+            // Obtain verdict from cache or policy service
+            let cache_key = service.cache_ttl.map(|_| {
+                let mut hasher = ahash::AHasher::default();
+                service.id.hash(&mut hasher);
+                request.hash(&mut hasher);
+                hasher.finish()
+            });
+            let cached = cache_key
+                .and_then(|cache_key| self.core.session.policy_cache.get_with_ttl(&cache_key));
+            let response = if let Some(response) = cached {
+                response
+            } else {
+                let time = Instant::now();
+                match service.query(request).await {
+                    Ok(response) => {
+                        tracing::debug!(parent: &self.span,
+                            context = "policy",
+                            event = "verdict",
+                            service = service.id,
+                            stage = stage.as_str(),
+                            action = ?response.action,
+                            elapsed = time.elapsed().as_millis());
+
+                        let response = Arc::new(response);
+                        if let (Some(cache_key), Some(ttl)) = (cache_key, service.cache_ttl) {
+                            let cache = &self.core.session.policy_cache;
+                            if cache.len() >= MAX_CACHE_ENTRIES {
+                                cache.cleanup();
+                            }
+                            if cache.len() < MAX_CACHE_ENTRIES {
+                                cache.insert_with_ttl(
+                                    cache_key,
+                                    response.clone(),
+                                    Instant::now() + ttl,
+                                );
+                            }
+                        }
+                        response
+                    }
+                    Err(err) => {
+                        tracing::warn!(parent: &self.span,
+                            context = "policy",
+                            event = "error",
+                            service = service.id,
+                            stage = stage.as_str(),
+                            reason = err,
+                            "Policy service request failed.");
+
+                        match service.on_error {
+                            PolicyAction::Accept => continue,
+                            PolicyAction::Defer => {
+                                return ScriptResult::Reject(
+                                    "451 4.3.0 Policy service unavailable, please try again later.\r\n"
+                                        .to_string(),
+                                );
+                            }
+                            PolicyAction::Reject => {
+                                return ScriptResult::Reject(
+                                    "554 5.7.1 Policy service unavailable.\r\n".to_string(),
+                                );
+                            }
+                        }
+                    }
+                }
+            };
+
+            match response.action {
+                PolicyAction::Accept => {
+                    if stage == PolicyStage::Data {
+                        modifications.extend(
+                            response
+                                .headers
+                                .iter()
+                                .filter(|header| header.is_valid())
+                                .take(MAX_HEADERS)
+                                .map(|header| ScriptModification::AddHeader {
+                                    name: header.name.clone().into(),
+                                    value: header.value.clone().into(),
+                                }),
+                        );
+                    }
+                }
+                PolicyAction::Reject => {
+                    return ScriptResult::Reject(smtp_reply(
+                        "550 5.7.1",
+                        "Rejected by policy.",
+                        response.reply.clone(),
+                    ));
+                }
+                PolicyAction::Defer => {
+                    return ScriptResult::Reject(smtp_reply(
+                        "451 4.7.1",
+                        "Temporary failure, please try again later.",
+                        response.reply.clone(),
+                    ));
+                }
+            }
+        }
+
+        ScriptResult::Accept { modifications }
+    }
+
+    fn build_policy_request(&self, stage: PolicyStage, size: usize) -> PolicyRequest<'_> {
+        let (encryption_protocol, encryption_cipher) = self.stream.tls_version_and_cipher();
+        PolicyRequest {
+            request: "smtpd_access_policy",
+            protocol_state: stage.as_protocol_state(),
+            listener: self.instance.id.as_str(),
+            client_address: self.data.remote_ip.to_string(),
+            server_address: self.data.local_ip.to_string(),
+            helo_name: self.data.helo_domain.as_str(),
+            sender: self
+                .data
+                .mail_from
+                .as_ref()
+                .map(|mail_from| mail_from.address.as_str())
+                .unwrap_or_default(),
+            recipient: if stage == PolicyStage::Rcpt {
+                self.data
+                    .rcpt_to
+                    .last()
+                    .map(|rcpt| rcpt.address.as_str())
+                    .unwrap_or_default()
+            } else {
+                ""
+            },
+            recipient_count: self.data.rcpt_to.len(),
+            sasl_username: self.data.authenticated_as.as_str(),
+            encryption_protocol,
+            encryption_cipher,
+            size,
+        }
+    }
+}
+
+impl PolicyService {
+    pub async fn query(&self, request: &PolicyRequest<'_>) -> Result<PolicyResponse, String> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.allow_invalid_certs)
+            .build()
+            .unwrap_or_default()
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json");
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+
+        let response = builder
+            .body(serde_json::to_string(request).unwrap_or_default())
+            .send()
+            .await
+            .map_err(|err| format!("HTTP request to {:?} failed: {err}", self.url))?;
+        if response.status().is_success() {
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(|err| format!("Failed to read response from {:?}: {err}", self.url))?;
+            serde_json::from_slice::<PolicyResponse>(&bytes)
+                .map_err(|err| format!("Invalid response from {:?}: {err}", self.url))
+        } else {
+            Err(format!(
+                "{:?} responded with HTTP status {}",
+                self.url,
+                response.status()
+            ))
+        }
+    }
+}
+
+impl PolicyHeader {
+    fn is_valid(&self) -> bool {
+        !self.name.is_empty()
+            && self
+                .name
+                .bytes()
+                .all(|ch| ch.is_ascii_graphic() && ch != b':')
+            && !self.value.contains(['\r', '\n'])
+    }
+}
+
+impl PolicyStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyStage::Connect => "connect",
+            PolicyStage::Ehlo => "ehlo",
+            PolicyStage::Mail => "mail",
+            PolicyStage::Rcpt => "rcpt",
+            PolicyStage::Data => "data",
+        }
+    }
+
+    pub fn as_protocol_state(&self) -> &'static str {
+        match self {
+            PolicyStage::Connect => "CONNECT",
+            PolicyStage::Ehlo => "EHLO",
+            PolicyStage::Mail => "MAIL",
+            PolicyStage::Rcpt => "RCPT",
+            PolicyStage::Data => "END-OF-MESSAGE",
+        }
+    }
+}
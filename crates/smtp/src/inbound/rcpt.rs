@@ -27,12 +27,14 @@ use smtp_proto::{
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
+    config::PolicyStage,
     core::{Session, SessionAddress},
-    queue::DomainPart,
+    queue::{suppression::SuppressionAction, DomainPart},
     scripts::{ScriptModification, ScriptResult},
+    wasm::PluginStage,
 };
 
-use super::IsTls;
+use super::{rcpt_cache::RcptStatus, tarpit::TarpitStage, IsTls};
 
 impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
     pub async fn handle_rcpt_to(&mut self, to: RcptTo<String>) -> Result<(), ()> {
@@ -45,8 +47,19 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             }
         }
 
+        self.tarpit(TarpitStage::Rcpt).await?;
+
         if self.data.mail_from.is_none() {
             return self.write(b"503 5.5.1 MAIL is required first.\r\n").await;
+        } else if self.is_dictionary_blocked().await {
+            tracing::debug!(parent: &self.span,
+                context = "rcpt",
+                event = "reject",
+                reason = "dictionary-attack",
+                "Remote IP blocked for sending too many unknown recipients.");
+            return self
+                .write(b"451 4.7.1 Too many unknown recipients, try again later.\r\n")
+                .await;
         } else if self.data.rcpt_to.len() >= self.params.rcpt_max {
             return self.write(b"451 4.5.3 Too many recipients.\r\n").await;
         }
@@ -78,6 +91,19 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
         }
         self.data.rcpt_to.push(rcpt);
 
+        // Reject plain-text deliveries to domains that require encryption
+        if !self.stream.is_tls() && *self.core.session.config.rcpt.require_tls.eval(self).await {
+            let rcpt = self.data.rcpt_to.pop().unwrap();
+            tracing::info!(parent: &self.span,
+                context = "rcpt",
+                event = "reject",
+                reason = "require-tls",
+                address = rcpt.address);
+            return self
+                .write(b"530 5.7.0 Must issue a STARTTLS command first.\r\n")
+                .await;
+        }
+
         // Address rewriting and Sieve filtering
         let rcpt_script = self
             .core
@@ -152,6 +178,44 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             }
         }
 
+        // Accept and discard messages to spam traps
+        if self.is_honeypot() {
+            self.handle_honeypot().await;
+            return self.write(b"250 2.1.5 OK\r\n").await;
+        }
+
+        // WebAssembly plugins
+        if self.core.plugins.has_stage(PluginStage::Rcpt) {
+            if let ScriptResult::Reject(message) = self
+                .core
+                .run_plugins(
+                    self.build_plugin_parameters(PluginStage::Rcpt),
+                    self.span.clone(),
+                )
+                .await
+            {
+                tracing::info!(parent: &self.span,
+                    context = "wasm",
+                    event = "reject",
+                    address = self.data.rcpt_to.last().unwrap().address,
+                    reason = message);
+                self.data.rcpt_to.pop();
+                return self.write(message.as_bytes()).await;
+            }
+        }
+
+        // Policy services
+        if let ScriptResult::Reject(message) = self.run_policy_services(PolicyStage::Rcpt, 0).await
+        {
+            tracing::info!(parent: &self.span,
+                context = "policy",
+                event = "reject",
+                address = self.data.rcpt_to.last().unwrap().address,
+                reason = message);
+            self.data.rcpt_to.pop();
+            return self.write(message.as_bytes()).await;
+        }
+
         // Verify address
         let rcpt = self.data.rcpt_to.last().unwrap();
         if let Some(directory) = self
@@ -166,8 +230,41 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
         {
             if let Ok(is_local_domain) = directory.is_local_domain(&rcpt.domain).await {
                 if is_local_domain {
-                    if let Ok(is_local_address) = directory.rcpt(&rcpt.address_lcase).await {
-                        if !is_local_address {
+                    let result = self.verify_rcpt(&directory, &rcpt.address_lcase).await;
+                    if let Ok(RcptStatus::OverQuota) = result {
+                        tracing::debug!(parent: &self.span,
+                                        context = "rcpt",
+                                        event = "error",
+                                        address = &rcpt.address_lcase,
+                                        "Mailbox is over quota.");
+
+                        self.data.rcpt_to.pop();
+                        return self
+                            .write(b"452 4.2.2 Mailbox full, try again later.\r\n")
+                            .await;
+                    }
+
+                    if let Ok(is_local_address) = result.map(|status| status == RcptStatus::Exists)
+                    {
+                        if let (false, Some(mailbox)) = (is_local_address, self.role_mailbox()) {
+                            tracing::debug!(parent: &self.span,
+                                            context = "rcpt",
+                                            event = "rewrite",
+                                            address = &rcpt.address_lcase,
+                                            mailbox = &mailbox,
+                                            "Routing role account to postmaster mailbox.");
+
+                            let rcpt = self.data.rcpt_to.last_mut().unwrap();
+                            rcpt.domain = mailbox.domain_part().to_string();
+                            rcpt.address = mailbox.clone();
+                            rcpt.address_lcase = mailbox;
+
+                            let rcpt = self.data.rcpt_to.last().unwrap();
+                            if self.data.rcpt_to.iter().filter(|r| r == &rcpt).count() > 1 {
+                                self.data.rcpt_to.pop();
+                                return self.write(b"250 2.1.5 OK\r\n").await;
+                            }
+                        } else if !is_local_address {
                             tracing::debug!(parent: &self.span,
                                             context = "rcpt", 
                                             event = "error",
@@ -175,6 +272,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
                                             "Mailbox does not exist.");
 
                             self.data.rcpt_to.pop();
+                            self.track_unknown_rcpt().await;
                             return self
                                 .rcpt_error(b"550 5.1.2 Mailbox does not exist.\r\n")
                                 .await;
@@ -224,6 +322,37 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
             return self.rcpt_error(b"550 5.1.2 Relay not allowed.\r\n").await;
         }
 
+        // Reject submissions to suppressed addresses
+        if self.core.queue.suppression.action == SuppressionAction::Reject
+            && !self.data.authenticated_as.is_empty()
+            && self.is_suppressed(self.data.rcpt_to.last().unwrap()).await
+        {
+            tracing::info!(parent: &self.span,
+                context = "rcpt",
+                event = "suppressed",
+                address = &self.data.rcpt_to.last().unwrap().address_lcase,
+                "Recipient is on the suppression list.");
+
+            self.data.rcpt_to.pop();
+            return self
+                .write(b"550 5.1.1 Recipient address suppressed due to previous bounces or complaints.\r\n")
+                .await;
+        }
+
+        // Greylisting
+        if self.is_greylisted().await {
+            tracing::info!(parent: &self.span,
+                context = "greylist",
+                event = "defer",
+                address = &self.data.rcpt_to.last().unwrap().address_lcase,
+                "Recipient greylisted.");
+
+            self.data.rcpt_to.pop();
+            return self
+                .write(b"451 4.7.1 Greylisted, please try again later.\r\n")
+                .await;
+        }
+
         if self.is_allowed().await {
             tracing::debug!(parent: &self.span,
                     context = "rcpt",
@@ -239,6 +368,19 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
         self.write(b"250 2.1.5 OK\r\n").await
     }
 
+    pub async fn is_suppressed(&self, rcpt: &SessionAddress) -> bool {
+        match &self.data.mail_from {
+            Some(mail_from) => {
+                self.core
+                    .queue
+                    .suppression
+                    .is_suppressed(&mail_from.domain, &rcpt.address_lcase)
+                    .await
+            }
+            None => false,
+        }
+    }
+
     async fn rcpt_error(&mut self, response: &[u8]) -> Result<(), ()> {
         tokio::time::sleep(self.params.rcpt_errors_wait).await;
         self.data.rcpt_errors += 1;
@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::Directory;
+use store::{LookupKey, LookupStore, LookupValue, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::Session;
+
+use super::IsTls;
+
+/// Recipient verification results shared by all nodes through a lookup store,
+/// which shields the directory from dictionary attacks and bursty retries.
+pub struct RcptCache {
+    pub store: LookupStore,
+    pub ttl_exists: Duration,
+    pub ttl_unknown: Duration,
+    pub ttl_over_quota: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RcptStatus {
+    Exists,
+    Unknown,
+    OverQuota,
+}
+
+#[derive(Debug)]
+struct CachedStatus(Option<RcptStatus>);
+
+impl RcptCache {
+    pub async fn get(&self, address: &str) -> Option<RcptStatus> {
+        match self
+            .store
+            .key_get::<CachedStatus>(LookupKey::Key(Self::key(address)))
+            .await
+        {
+            Ok(LookupValue::Value { value, .. }) => value.0,
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(
+                    context = "rcpt-cache",
+                    event = "error",
+                    address = address,
+                    reason = %err,
+                    "Failed to read recipient cache entry."
+                );
+                None
+            }
+        }
+    }
+
+    pub async fn set(&self, address: &str, status: RcptStatus) {
+        let ttl = match status {
+            RcptStatus::Exists => self.ttl_exists,
+            RcptStatus::Unknown => self.ttl_unknown,
+            RcptStatus::OverQuota => self.ttl_over_quota,
+        }
+        .as_secs();
+        if ttl == 0 {
+            return;
+        }
+
+        if let Err(err) = self
+            .store
+            .key_set(
+                Self::key(address),
+                LookupValue::Value {
+                    value: vec![status.as_byte()],
+                    expires: ttl,
+                },
+            )
+            .await
+        {
+            tracing::debug!(
+                context = "rcpt-cache",
+                event = "error",
+                address = address,
+                reason = %err,
+                "Failed to write recipient cache entry."
+            );
+        }
+    }
+
+    fn key(address: &str) -> Vec<u8> {
+        format!("rcpt:{address}").into_bytes()
+    }
+}
+
+impl RcptStatus {
+    fn as_byte(&self) -> u8 {
+        match self {
+            RcptStatus::Exists => b'+',
+            RcptStatus::Unknown => b'-',
+            RcptStatus::OverQuota => b'q',
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'+' => Some(RcptStatus::Exists),
+            b'-' => Some(RcptStatus::Unknown),
+            b'q' => Some(RcptStatus::OverQuota),
+            _ => None,
+        }
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn verify_rcpt(
+        &self,
+        directory: &Directory,
+        address: &str,
+    ) -> directory::Result<RcptStatus> {
+        let cache = self.core.session.config.rcpt.cache.as_ref();
+        if let Some(cache) = cache {
+            if let Some(status) = cache.get(address).await {
+                return Ok(status);
+            }
+        }
+
+        let status = if directory.rcpt(address).await? {
+            RcptStatus::Exists
+        } else {
+            RcptStatus::Unknown
+        };
+        if let Some(cache) = cache {
+            cache.set(address, status).await;
+        }
+
+        Ok(status)
+    }
+}
+
+impl store::Deserialize for CachedStatus {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        Ok(CachedStatus(
+            bytes.first().and_then(|byte| RcptStatus::from_byte(*byte)),
+        ))
+    }
+}
+
+impl From<Value<'static>> for CachedStatus {
+    fn from(value: Value<'static>) -> Self {
+        match value {
+            Value::Blob(bytes) => {
+                CachedStatus(bytes.first().and_then(|b| RcptStatus::from_byte(*b)))
+            }
+            Value::Text(text) => CachedStatus(text.bytes().next().and_then(RcptStatus::from_byte)),
+            _ => CachedStatus(None),
+        }
+    }
+}
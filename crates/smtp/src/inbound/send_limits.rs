@@ -0,0 +1,348 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use store::{LookupKey, LookupValue};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::{webhook::spawn_webhook, Session, SessionCore};
+
+use super::IsTls;
+
+const LOCK_KEY_PREFIX: &str = "send-lock:";
+
+#[derive(Debug, Default)]
+pub struct SenderUsage {
+    pub hour: u64,
+    pub hour_messages: u64,
+    pub hour_recipients: u64,
+    pub day: u64,
+    pub day_messages: u64,
+    pub day_recipients: u64,
+    pub recent: VecDeque<(Instant, u64)>,
+    pub locked_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SenderStatus {
+    pub account: String,
+    pub hourly_messages: u64,
+    pub hourly_recipients: u64,
+    pub daily_messages: u64,
+    pub daily_recipients: u64,
+    pub locked_at: Option<u64>,
+}
+
+impl SenderUsage {
+    fn rotate(&mut self, now: u64) {
+        let (hour, day) = (now / 3600, now / 86400);
+        if self.hour != hour {
+            self.hour = hour;
+            self.hour_messages = 0;
+            self.hour_recipients = 0;
+        }
+        if self.day != day {
+            self.day = day;
+            self.day_messages = 0;
+            self.day_recipients = 0;
+        }
+    }
+
+    fn recent_recipients(&mut self, now: Instant, window: Duration) -> u64 {
+        while self
+            .recent
+            .front()
+            .map_or(false, |(sent, _)| now.duration_since(*sent) >= window)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.iter().map(|(_, rcpts)| rcpts).sum()
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.locked_at.is_some() || self.day == now / 86400
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn is_send_locked(&self) -> bool {
+        if self.data.authenticated_as.is_empty() {
+            return false;
+        }
+        let senders = &self.core.session.senders;
+        if senders
+            .get(&self.data.authenticated_as)
+            .map_or(false, |usage| usage.locked_at.is_some())
+        {
+            return true;
+        }
+
+        // Locks are persisted so they survive restarts
+        if let Some(locked_at) =
+            persisted_lock(&self.core.session, &self.data.authenticated_as).await
+        {
+            senders
+                .entry(self.data.authenticated_as.clone())
+                .or_default()
+                .locked_at = locked_at.into();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub async fn check_send_limits(&self) -> Result<(), &'static [u8]> {
+        if self.data.authenticated_as.is_empty() {
+            return Ok(());
+        }
+        let config = &self.core.session.config.data.send_limits;
+        let limits = [
+            *config.messages_hourly.eval(self).await,
+            *config.recipients_hourly.eval(self).await,
+            *config.messages_daily.eval(self).await,
+            *config.recipients_daily.eval(self).await,
+        ];
+        let rcpts = self.data.rcpt_to.len() as u64;
+
+        let mut usage = self
+            .core
+            .session
+            .senders
+            .entry(self.data.authenticated_as.clone())
+            .or_default();
+        if usage.locked_at.is_some() {
+            return Err(b"550 5.7.1 Sending has been disabled for this account.\r\n");
+        }
+        usage.rotate(now());
+        for (limit, used) in limits.into_iter().zip([
+            usage.hour_messages + 1,
+            usage.hour_recipients + rcpts,
+            usage.day_messages + 1,
+            usage.day_recipients + rcpts,
+        ]) {
+            if limit.map_or(false, |limit| used > limit) {
+                tracing::info!(parent: &self.span,
+                    context = "data",
+                    event = "send-limit-exceeded",
+                    account = self.data.authenticated_as,
+                    hourly_messages = usage.hour_messages,
+                    hourly_recipients = usage.hour_recipients,
+                    daily_messages = usage.day_messages,
+                    daily_recipients = usage.day_recipients,
+                    "Account exceeded its outbound sending limits.");
+                return Err(b"451 4.7.1 Sending limit exceeded, try again later.\r\n");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn track_sent_message(&self, rcpts: u64) {
+        if self.data.authenticated_as.is_empty() {
+            return;
+        }
+        let config = &self.core.session.config.data.send_limits;
+        let lock_threshold = *config.lock_threshold.eval(self).await;
+
+        // Update counters and detect sudden bursts of recipients
+        let now_instant = Instant::now();
+        let (recent, locked_at) = {
+            let mut usage = self
+                .core
+                .session
+                .senders
+                .entry(self.data.authenticated_as.clone())
+                .or_default();
+            let now = now();
+            usage.rotate(now);
+            usage.hour_messages += 1;
+            usage.hour_recipients += rcpts;
+            usage.day_messages += 1;
+            usage.day_recipients += rcpts;
+
+            let lock_threshold = match lock_threshold {
+                Some(lock_threshold) if lock_threshold > 0 && usage.locked_at.is_none() => {
+                    lock_threshold
+                }
+                _ => return,
+            };
+            usage.recent.push_back((now_instant, rcpts));
+            let recent = usage.recent_recipients(now_instant, config.lock_window);
+            if recent < lock_threshold {
+                return;
+            }
+            usage.locked_at = now.into();
+            usage.recent.clear();
+            (recent, now)
+        };
+
+        tracing::warn!(
+            parent: &self.span,
+            context = "data",
+            event = "send-locked",
+            account = self.data.authenticated_as,
+            recipients = recent,
+            window = config.lock_window.as_secs(),
+            "Account sent to an unusual number of recipients, locking outbound mail."
+        );
+        if let Some(store) = &config.store {
+            if let Err(err) = store
+                .key_set(
+                    lock_key(&self.data.authenticated_as),
+                    LookupValue::Value {
+                        value: locked_at.to_string().into_bytes(),
+                        expires: 0,
+                    },
+                )
+                .await
+            {
+                tracing::warn!(
+                    parent: &self.span,
+                    context = "data",
+                    event = "error",
+                    account = self.data.authenticated_as,
+                    reason = %err,
+                    "Failed to persist sending lock."
+                );
+            }
+        }
+
+        // Alert administrators
+        if let Some(url) = &config.webhook {
+            let url = url.clone();
+            let body = serde_json::json!({
+                "event": "send-locked",
+                "account": self.data.authenticated_as,
+                "remoteIp": self.data.remote_ip.to_string(),
+                "recipients": recent,
+                "window": config.lock_window.as_secs(),
+            })
+            .to_string();
+            spawn_webhook(url, body, self.span.clone());
+        }
+    }
+}
+
+pub async fn status(core: &SessionCore, account: Option<&str>) -> Vec<SenderStatus> {
+    let now = now();
+    let mut status = core
+        .senders
+        .iter()
+        .filter(|entry| account.map_or(true, |account| entry.key() == account))
+        .map(|entry| {
+            let usage = entry.value();
+            let (hour, day) = (usage.hour == now / 3600, usage.day == now / 86400);
+            SenderStatus {
+                account: entry.key().clone(),
+                hourly_messages: if hour { usage.hour_messages } else { 0 },
+                hourly_recipients: if hour { usage.hour_recipients } else { 0 },
+                daily_messages: if day { usage.day_messages } else { 0 },
+                daily_recipients: if day { usage.day_recipients } else { 0 },
+                locked_at: usage.locked_at,
+            }
+        })
+        .collect::<Vec<_>>();
+    status.sort_unstable_by(|a, b| a.account.cmp(&b.account));
+
+    // Include locks that have not been loaded since the last restart
+    if let Some(account) = account {
+        if status.iter().all(|status| status.locked_at.is_none()) {
+            if let Some(locked_at) = persisted_lock(core, account).await {
+                if let Some(status) = status.first_mut() {
+                    status.locked_at = locked_at.into();
+                } else {
+                    status.push(SenderStatus {
+                        account: account.to_string(),
+                        hourly_messages: 0,
+                        hourly_recipients: 0,
+                        daily_messages: 0,
+                        daily_recipients: 0,
+                        locked_at: locked_at.into(),
+                    });
+                }
+            }
+        }
+    }
+
+    status
+}
+
+pub async fn unlock(core: &SessionCore, account: &str) -> store::Result<bool> {
+    let mut unlocked = core
+        .senders
+        .get_mut(account)
+        .map_or(false, |mut usage| usage.locked_at.take().is_some());
+    if let Some(store) = &core.config.data.send_limits.store {
+        let key = lock_key(account);
+        if !unlocked {
+            unlocked = matches!(
+                store.key_get::<String>(LookupKey::Key(key.clone())).await?,
+                LookupValue::Value { .. }
+            );
+        }
+        store.key_delete(key).await?;
+    }
+    Ok(unlocked)
+}
+
+async fn persisted_lock(core: &SessionCore, account: &str) -> Option<u64> {
+    let store = core.config.data.send_limits.store.as_ref()?;
+    match store
+        .key_get::<String>(LookupKey::Key(lock_key(account)))
+        .await
+    {
+        Ok(LookupValue::Value { value, .. }) => value.parse().ok(),
+        Ok(_) => None,
+        Err(err) => {
+            tracing::debug!(
+                context = "data",
+                event = "error",
+                account = account,
+                reason = %err,
+                "Failed to read sending lock."
+            );
+            None
+        }
+    }
+}
+
+fn lock_key(account: &str) -> Vec<u8> {
+    format!("{LOCK_KEY_PREFIX}{account}").into_bytes()
+}
+
+pub fn purge(senders: &dashmap::DashMap<String, SenderUsage>) {
+    let now = now();
+    senders.retain(|_, usage| usage.is_active(now));
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::{Directory, Principal, QueryBy};
+use mail_parser::MessageParser;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::Session;
+
+impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+    /// Collects the addresses an authenticated principal is allowed to send from,
+    /// optionally including the addresses of the groups it is a member of and
+    /// of the accounts that delegated sending to it.
+    pub async fn load_sender_identities(
+        &mut self,
+        directory: &Directory,
+        principal: &Principal<u32>,
+    ) {
+        let mut identities = Vec::with_capacity(principal.emails.len() + 1);
+        add_identities(&mut identities, principal);
+
+        if self.params.auth_send_as_groups {
+            for group_id in &principal.member_of {
+                match directory.query(QueryBy::Id(*group_id), false).await {
+                    Ok(Some(group)) => add_identities(&mut identities, &group),
+                    Ok(None) => (),
+                    Err(err) => {
+                        tracing::debug!(
+                            parent: &self.span,
+                            context = "auth",
+                            event = "error",
+                            group_id = group_id,
+                            reason = ?err,
+                            "Failed to obtain group addresses."
+                        );
+                    }
+                }
+            }
+        }
+
+        // Addresses of accounts that granted send-as or on-behalf access
+        #[cfg(feature = "local_delivery")]
+        if self.params.auth_send_as_delegates {
+            let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+            if self
+                .core
+                .event_tx
+                .send(utils::ipc::DeliveryEvent::SendAs {
+                    account_id: principal.id,
+                    result_tx,
+                })
+                .await
+                .is_ok()
+            {
+                for delegated in result_rx.await.unwrap_or_default() {
+                    if identities.contains(&delegated.address) {
+                        continue;
+                    } else if delegated.on_behalf {
+                        if !self.data.on_behalf_emails.contains(&delegated.address) {
+                            self.data.on_behalf_emails.push(delegated.address);
+                        }
+                    } else {
+                        identities.push(delegated.address);
+                    }
+                }
+            }
+        }
+
+        self.data.authenticated_emails = identities;
+    }
+
+    pub fn is_allowed_sender(&self, address_lcase: &str) -> bool {
+        !self.params.auth_match_sender
+            || self.data.authenticated_as.is_empty()
+            || self
+                .data
+                .authenticated_emails
+                .iter()
+                .any(|email| email == address_lcase)
+    }
+
+    /// Verifies that all addresses in the From header belong to the authenticated account.
+    /// Messages sent on behalf of another account must name the sender in a Sender header.
+    pub fn check_from_alignment(&self, raw_message: &[u8]) -> Result<(), &'static [u8]> {
+        if !self.params.auth_match_sender || self.data.authenticated_as.is_empty() {
+            return Ok(());
+        }
+
+        let message = MessageParser::new().parse_headers(raw_message);
+        let mut on_behalf = false;
+        let spoofed = message
+            .as_ref()
+            .and_then(|message| message.from())
+            .into_iter()
+            .flat_map(|from| from.iter())
+            .map(|addr| addr.address().unwrap_or_default().to_lowercase())
+            .find(|address| {
+                if self.is_allowed_sender(address) {
+                    false
+                } else if self.data.on_behalf_emails.contains(address) {
+                    on_behalf = true;
+                    false
+                } else {
+                    true
+                }
+            });
+
+        if spoofed.is_none()
+            && on_behalf
+            && !message
+                .as_ref()
+                .and_then(|message| message.sender())
+                .and_then(|sender| sender.first())
+                .and_then(|addr| addr.address())
+                .map_or(false, |address| {
+                    self.is_allowed_sender(&address.to_lowercase())
+                })
+        {
+            tracing::info!(parent: &self.span,
+                context = "audit",
+                event = "missing-sender",
+                account = self.data.authenticated_as,
+                return_path = self.data.mail_from.as_ref().map_or("", |f| f.address.as_str()),
+                "Rejected message sent on behalf of another account without a valid Sender header.");
+
+            Err(b"550 5.7.1 Sending on behalf of this address requires a valid Sender header.\r\n")
+        } else if let Some(address) = spoofed {
+            tracing::info!(parent: &self.span,
+                context = "audit",
+                event = "spoofed-sender",
+                account = self.data.authenticated_as,
+                return_path = self.data.mail_from.as_ref().map_or("", |f| f.address.as_str()),
+                from = address,
+                "Rejected message with a From address not owned by the authenticated account.");
+
+            Err(b"550 5.7.1 You are not allowed to send from this address.\r\n")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn add_identities(identities: &mut Vec<String>, principal: &Principal<u32>) {
+    for email in principal
+        .emails
+        .iter()
+        .map(|email| email.as_str())
+        .chain(Some(principal.name.as_str()).filter(|name| name.contains('@')))
+    {
+        let email = email.to_lowercase();
+        if !identities.contains(&email) {
+            identities.push(email);
+        }
+    }
+}
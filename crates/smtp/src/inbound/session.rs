@@ -49,145 +49,165 @@ impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
             match &mut state {
                 State::Request(receiver) => loop {
                     match receiver.ingest(&mut iter, bytes) {
-                        Ok(request) => match request {
-                            Request::Rcpt { to } => {
-                                self.handle_rcpt_to(to).await?;
+                        Ok(request) => {
+                            if iter.len() > 0 && !self.is_pipelining_allowed(&request) {
+                                self.data.pipelining_violations += 1;
+                                tracing::debug!(
+                                    parent: &self.span,
+                                    context = "pipelining",
+                                    event = "violation",
+                                    count = self.data.pipelining_violations,
+                                    "Client pipelined a command without waiting for a response."
+                                );
                             }
-                            Request::Mail { from } => {
-                                self.handle_mail_from(from).await?;
-                            }
-                            Request::Ehlo { host } => {
-                                if self.instance.protocol == ServerProtocol::Smtp {
-                                    self.handle_ehlo(host).await?;
-                                } else {
-                                    self.write(b"500 5.5.1 Invalid command.\r\n").await?;
+
+                            match request {
+                                Request::Rcpt { to } => {
+                                    self.handle_rcpt_to(to).await?;
                                 }
-                            }
-                            Request::Data => {
-                                if self.can_send_data().await? {
-                                    self.write(b"354 Start mail input; end with <CRLF>.<CRLF>\r\n")
-                                        .await?;
-                                    self.data.message = Vec::with_capacity(1024);
-                                    state = State::Data(DataReceiver::new());
-                                    continue 'outer;
+                                Request::Mail { from } => {
+                                    self.handle_mail_from(from).await?;
                                 }
-                            }
-                            Request::Bdat {
-                                chunk_size,
-                                is_last,
-                            } => {
-                                state = if chunk_size + self.data.message.len()
-                                    < self.params.max_message_size
-                                {
-                                    if self.data.message.is_empty() {
-                                        self.data.message = Vec::with_capacity(chunk_size);
+                                Request::Ehlo { host } => {
+                                    if self.instance.protocol == ServerProtocol::Smtp {
+                                        self.handle_ehlo(host).await?;
                                     } else {
-                                        self.data.message.reserve(chunk_size);
+                                        self.write(b"500 5.5.1 Invalid command.\r\n").await?;
                                     }
-                                    State::Bdat(BdatReceiver::new(chunk_size, is_last))
-                                } else {
-                                    // Chunk is too large, ignore.
-                                    State::DataTooLarge(DummyDataReceiver::new_bdat(chunk_size))
-                                };
-                                continue 'outer;
-                            }
-                            Request::Auth {
-                                mechanism,
-                                initial_response,
-                            } => {
-                                let auth =
-                                    *self.core.session.config.auth.mechanisms.eval(self).await;
-                                if auth == 0 || self.params.auth_directory.is_none() {
-                                    self.write(b"503 5.5.1 AUTH not allowed.\r\n").await?;
-                                } else if !self.data.authenticated_as.is_empty() {
-                                    self.write(b"503 5.5.1 Already authenticated.\r\n").await?;
-                                } else if mechanism & (AUTH_LOGIN | AUTH_PLAIN) != 0
-                                    && !self.stream.is_tls()
-                                    && !self.params.auth_plain_text
-                                {
-                                    self.write(b"503 5.5.1 Clear text authentication without TLS is forbidden.\r\n").await?;
-                                } else if let Some(mut token) =
-                                    SaslToken::from_mechanism(mechanism & auth)
-                                {
-                                    if self
-                                        .handle_sasl_response(
-                                            &mut token,
-                                            initial_response.as_bytes(),
+                                }
+                                Request::Data => {
+                                    if self.can_send_data().await? {
+                                        self.write(
+                                            b"354 Start mail input; end with <CRLF>.<CRLF>\r\n",
                                         )
-                                        .await?
-                                    {
-                                        state = State::Sasl(LineReceiver::new(token));
+                                        .await?;
+                                        self.data.message = Vec::with_capacity(1024);
+                                        state = State::Data(DataReceiver::new());
                                         continue 'outer;
                                     }
-                                } else {
-                                    self.write(
+                                }
+                                Request::Bdat {
+                                    chunk_size,
+                                    is_last,
+                                } => {
+                                    state = if chunk_size + self.data.message.len()
+                                        < self.params.max_message_size
+                                    {
+                                        if self.data.message.is_empty() {
+                                            self.data.message = Vec::with_capacity(chunk_size);
+                                        } else {
+                                            self.data.message.reserve(chunk_size);
+                                        }
+                                        State::Bdat(BdatReceiver::new(chunk_size, is_last))
+                                    } else {
+                                        // Chunk is too large, ignore.
+                                        State::DataTooLarge(DummyDataReceiver::new_bdat(chunk_size))
+                                    };
+                                    continue 'outer;
+                                }
+                                Request::Auth {
+                                    mechanism,
+                                    initial_response,
+                                } => {
+                                    let auth =
+                                        *self.core.session.config.auth.mechanisms.eval(self).await;
+                                    if auth == 0 || self.params.auth_directory.is_none() {
+                                        self.write(b"503 5.5.1 AUTH not allowed.\r\n").await?;
+                                    } else if !self.data.authenticated_as.is_empty() {
+                                        self.write(b"503 5.5.1 Already authenticated.\r\n").await?;
+                                    } else if mechanism & (AUTH_LOGIN | AUTH_PLAIN) != 0
+                                        && !self.stream.is_tls()
+                                        && !self.params.auth_plain_text
+                                    {
+                                        self.write(b"503 5.5.1 Clear text authentication without TLS is forbidden.\r\n").await?;
+                                    } else if let Some(mut token) =
+                                        SaslToken::from_mechanism(mechanism & auth)
+                                    {
+                                        if self
+                                            .handle_sasl_response(
+                                                &mut token,
+                                                initial_response.as_bytes(),
+                                            )
+                                            .await?
+                                        {
+                                            state = State::Sasl(LineReceiver::new(token));
+                                            continue 'outer;
+                                        }
+                                    } else {
+                                        self.write(
                                         b"554 5.7.8 Authentication mechanism not supported.\r\n",
                                     )
                                     .await?;
+                                    }
                                 }
-                            }
-                            Request::Noop { .. } => {
-                                self.write(b"250 2.0.0 OK\r\n").await?;
-                            }
-                            Request::Vrfy { value } => {
-                                self.handle_vrfy(value).await?;
-                            }
-                            Request::Expn { value } => {
-                                self.handle_expn(value).await?;
-                            }
-                            Request::StartTls => {
-                                if !self.stream.is_tls() {
-                                    self.write(b"220 2.0.0 Ready to start TLS.\r\n").await?;
-                                    #[cfg(any(test, feature = "test_mode"))]
-                                    if self.data.helo_domain.contains("badtls") {
-                                        return Err(());
+                                Request::Noop { .. } => {
+                                    self.write(b"250 2.0.0 OK\r\n").await?;
+                                }
+                                Request::Vrfy { value } => {
+                                    self.handle_vrfy(value).await?;
+                                }
+                                Request::Expn { value } => {
+                                    self.handle_expn(value).await?;
+                                }
+                                Request::StartTls => {
+                                    if !self.stream.is_tls() {
+                                        self.write(b"220 2.0.0 Ready to start TLS.\r\n").await?;
+                                        #[cfg(any(test, feature = "test_mode"))]
+                                        if self.data.helo_domain.contains("badtls") {
+                                            return Err(());
+                                        }
+                                        self.state = State::default();
+                                        return Ok(false);
+                                    } else {
+                                        self.write(b"504 5.7.4 Already in TLS mode.\r\n").await?;
                                     }
-                                    self.state = State::default();
-                                    return Ok(false);
-                                } else {
-                                    self.write(b"504 5.7.4 Already in TLS mode.\r\n").await?;
                                 }
-                            }
-                            Request::Rset => {
-                                self.reset();
-                                self.write(b"250 2.0.0 OK\r\n").await?;
-                            }
-                            Request::Quit => {
-                                self.write(b"221 2.0.0 Bye.\r\n").await?;
-                                return Err(());
-                            }
-                            Request::Help { .. } => {
-                                self.write(
+                                Request::Rset => {
+                                    self.reset();
+                                    self.write(b"250 2.0.0 OK\r\n").await?;
+                                }
+                                Request::Quit => {
+                                    self.write(b"221 2.0.0 Bye.\r\n").await?;
+                                    return Err(());
+                                }
+                                Request::Help { .. } => {
+                                    self.write(
                                     b"250 2.0.0 Help can be found at https://stalw.art/smtp/\r\n",
                                 )
                                 .await?;
-                            }
-                            Request::Helo { host } => {
-                                if self.instance.protocol == ServerProtocol::Smtp
-                                    && self.data.helo_domain.is_empty()
-                                {
-                                    self.data.helo_domain = host;
-                                    self.write(
-                                        format!("250 {} says hello\r\n", self.instance.hostname)
+                                }
+                                Request::Helo { host } => {
+                                    if self.instance.protocol == ServerProtocol::Smtp
+                                        && self.data.helo_domain.is_empty()
+                                    {
+                                        self.data.helo_domain = host;
+                                        self.write(
+                                            format!(
+                                                "250 {} says hello\r\n",
+                                                self.instance.hostname
+                                            )
                                             .as_bytes(),
-                                    )
-                                    .await?;
-                                } else {
-                                    self.write(b"503 5.5.1 Invalid command.\r\n").await?;
+                                        )
+                                        .await?;
+                                    } else {
+                                        self.write(b"503 5.5.1 Invalid command.\r\n").await?;
+                                    }
                                 }
-                            }
-                            Request::Lhlo { host } => {
-                                if self.instance.protocol == ServerProtocol::Lmtp {
-                                    self.handle_ehlo(host).await?;
-                                } else {
-                                    self.write(b"502 5.5.1 Invalid command.\r\n").await?;
+                                Request::Lhlo { host } => {
+                                    if self.instance.protocol == ServerProtocol::Lmtp {
+                                        self.handle_ehlo(host).await?;
+                                    } else {
+                                        self.write(b"502 5.5.1 Invalid command.\r\n").await?;
+                                    }
+                                }
+                                Request::Etrn { .. }
+                                | Request::Atrn { .. }
+                                | Request::Burl { .. } => {
+                                    self.write(b"502 5.5.1 Command not implemented.\r\n")
+                                        .await?;
                                 }
                             }
-                            Request::Etrn { .. } | Request::Atrn { .. } | Request::Burl { .. } => {
-                                self.write(b"502 5.5.1 Command not implemented.\r\n")
-                                    .await?;
-                            }
-                        },
+                        }
                         Err(err) => match err {
                             Error::NeedsMoreData { .. } => break 'outer,
                             Error::UnknownCommand | Error::InvalidResponse { .. } => {
@@ -346,6 +366,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     pub fn reset(&mut self) {
         self.data.mail_from = None;
         self.data.spf_mail_from = None;
+        self.data.spf_dnssec = None;
         self.data.rcpt_to.clear();
         self.data.message = Vec::with_capacity(0);
         self.data.priority = 0;
@@ -354,6 +375,24 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     }
 
     #[inline(always)]
+    fn is_pipelining_allowed<U>(&self, request: &Request<U>) -> bool {
+        // Commands that must be the last in a pipelined group (RFC 2920, section 3.1)
+        self.data.pipelining
+            && !matches!(
+                request,
+                Request::Ehlo { .. }
+                    | Request::Helo { .. }
+                    | Request::Lhlo { .. }
+                    | Request::Data
+                    | Request::Quit
+                    | Request::Noop { .. }
+                    | Request::Vrfy { .. }
+                    | Request::Expn { .. }
+                    | Request::StartTls
+                    | Request::Auth { .. }
+            )
+    }
+
     pub async fn write(&mut self, bytes: &[u8]) -> Result<(), ()> {
         let err = match self.stream.write_all(bytes).await {
             Ok(_) => match self.stream.flush().await {
@@ -31,12 +31,14 @@ use tokio_rustls::server::TlsStream;
 use utils::listener::SessionManager;
 
 use crate::{
+    config::PolicyStage,
     core::{Session, SessionData, SessionParameters, SmtpSessionManager, State},
     queue, reporting,
     scripts::ScriptResult,
+    wasm::PluginStage,
 };
 
-use super::IsTls;
+use super::{tarpit::TarpitStage, IsTls};
 
 impl SessionManager for SmtpSessionManager {
     fn spawn(&self, session: utils::listener::SessionData<TcpStream>) {
@@ -76,6 +78,8 @@ impl SessionManager for SmtpSessionManager {
             let _ = core.report.tx.send(reporting::Event::Stop).await;
             #[cfg(feature = "local_delivery")]
             let _ = core.delivery_tx.send(utils::ipc::DeliveryEvent::Stop).await;
+            #[cfg(feature = "local_delivery")]
+            let _ = core.event_tx.send(utils::ipc::DeliveryEvent::Stop).await;
         });
     }
 }
@@ -114,6 +118,53 @@ impl<T: AsyncRead + AsyncWrite + IsTls + Unpin> Session<T> {
     pub async fn init_conn(&mut self) -> bool {
         self.eval_session_params().await;
 
+        // Delay the greeting, legitimate clients wait for the banner
+        let mut early_data = Vec::new();
+        if let Some(delay) = *self
+            .core
+            .session
+            .config
+            .connect
+            .greeting_delay
+            .eval(self)
+            .await
+        {
+            let mut buf = vec![0; 1024];
+            match tokio::time::timeout(delay, self.read(&mut buf)).await {
+                Ok(Ok(bytes_read)) if bytes_read > 0 => {
+                    self.data.early_talker = true;
+                    if *self
+                        .core
+                        .session
+                        .config
+                        .connect
+                        .reject_early_talkers
+                        .eval(self)
+                        .await
+                    {
+                        tracing::info!(parent: &self.span,
+                            context = "connect",
+                            event = "reject",
+                            reason = "early-talker",
+                            "Client sent data before the greeting.");
+
+                        let _ = self
+                            .write(b"554 5.5.0 Protocol error: data sent before greeting.\r\n")
+                            .await;
+                        return false;
+                    } else {
+                        tracing::debug!(parent: &self.span,
+                            context = "connect",
+                            event = "early-talker",
+                            "Client sent data before the greeting.");
+                        early_data.extend_from_slice(&buf[..bytes_read]);
+                    }
+                }
+                Ok(_) => return false,
+                Err(_) => (),
+            }
+        }
+
         // Sieve filtering
         if let Some(script) = self.core.session.config.connect.script.eval(self).await {
             if let ScriptResult::Reject(message) = self
@@ -130,17 +181,57 @@ impl<T: AsyncRead + AsyncWrite + IsTls + Unpin> Session<T> {
             }
         }
 
+        // WebAssembly plugins
+        if self.core.plugins.has_stage(PluginStage::Connect) {
+            if let ScriptResult::Reject(message) = self
+                .core
+                .run_plugins(
+                    self.build_plugin_parameters(PluginStage::Connect),
+                    self.span.clone(),
+                )
+                .await
+            {
+                tracing::debug!(parent: &self.span,
+                        context = "connect",
+                        event = "plugin-reject",
+                        reason = message);
+
+                let _ = self.write(message.as_bytes()).await;
+                return false;
+            }
+        }
+
+        // Policy services
+        if let ScriptResult::Reject(message) =
+            self.run_policy_services(PolicyStage::Connect, 0).await
+        {
+            tracing::info!(parent: &self.span,
+                context = "policy",
+                event = "reject",
+                reason = message);
+
+            let _ = self.write(message.as_bytes()).await;
+            return false;
+        }
+
+        // Tarpit
+        if self.tarpit(TarpitStage::Connect).await.is_err() {
+            return false;
+        }
+
         let instance = self.instance.clone();
         if self.write(instance.data.as_bytes()).await.is_err() {
             return false;
         }
 
-        true
+        // Process any commands sent before the greeting
+        early_data.is_empty() || matches!(self.ingest(&early_data).await, Ok(true))
     }
 
     pub async fn handle_conn_(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        let mut is_draining = false;
 
         loop {
             tokio::select! {
@@ -153,6 +244,16 @@ impl<T: AsyncRead + AsyncWrite + IsTls + Unpin> Session<T> {
                                     if Instant::now() < self.data.valid_until && bytes_read <= self.data.bytes_left  {
                                         self.data.bytes_left -= bytes_read;
                                         match self.ingest(&buf[..bytes_read]).await {
+                                            Ok(true) if is_draining && self.data.mail_from.is_none() => {
+                                                tracing::debug!(
+                                                    parent: &self.span,
+                                                    event = "disconnect",
+                                                    reason = "shutdown",
+                                                    "Server shutting down, transaction completed."
+                                                );
+                                                self.write(b"421 4.3.0 Server shutting down.\r\n").await.ok();
+                                                break;
+                                            }
                                             Ok(true) => (),
                                             Ok(false) => {
                                                 return true;
@@ -214,7 +315,18 @@ impl<T: AsyncRead + AsyncWrite + IsTls + Unpin> Session<T> {
                             }
                         }
                 },
-                _ = shutdown_rx.changed() => {
+                _ = shutdown_rx.changed(), if !is_draining => {
+                    if self.data.mail_from.is_some() {
+                        // Allow the transaction in progress to complete
+                        tracing::debug!(
+                            parent: &self.span,
+                            event = "drain",
+                            reason = "shutdown",
+                            "Server shutting down, waiting for transaction to complete."
+                        );
+                        is_draining = true;
+                        continue;
+                    }
                     tracing::debug!(
                         parent: &self.span,
                         event = "disconnect",
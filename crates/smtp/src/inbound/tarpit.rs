@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::Session;
+
+use super::IsTls;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarpitStage {
+    Connect,
+    Mail,
+    Rcpt,
+}
+
+impl TarpitStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TarpitStage::Connect => "connect",
+            TarpitStage::Mail => "mail",
+            TarpitStage::Rcpt => "rcpt",
+        }
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub async fn tarpit(&mut self, stage: TarpitStage) -> Result<(), ()> {
+        let config = &self.core.session.config;
+
+        // Clients matching a stage rule are always tarpitted, others only
+        // after exceeding the error threshold
+        let is_match = match stage {
+            TarpitStage::Connect => *config.connect.tarpit.eval(self).await,
+            TarpitStage::Mail => *config.mail.tarpit.eval(self).await,
+            TarpitStage::Rcpt => *config.rcpt.tarpit.eval(self).await,
+        };
+        let is_tarpitted = is_match
+            || (stage != TarpitStage::Connect
+                && config.tarpit.errors.eval(self).await.map_or(false, |max| {
+                    self.data.rcpt_errors + self.data.auth_errors >= max
+                }));
+        if !is_tarpitted {
+            return Ok(());
+        }
+
+        // Drop the connection after too many delayed responses
+        if let Some(max) = *config.tarpit.disconnect.eval(self).await {
+            if self.data.tarpit_count >= max {
+                tracing::info!(
+                    parent: &self.span,
+                    context = "tarpit",
+                    event = "disconnect",
+                    stage = stage.as_str(),
+                    count = self.data.tarpit_count,
+                    "Disconnecting tarpitted client."
+                );
+                self.write(b"421 4.7.0 Too many errors, disconnecting.\r\n")
+                    .await?;
+                return Err(());
+            }
+        }
+
+        // Progressively increase the delay
+        let delay = *config.tarpit.delay.eval(self).await;
+        let max_delay = *config.tarpit.max_delay.eval(self).await;
+        let delay = delay
+            .checked_mul(1u32 << self.data.tarpit_count.min(16))
+            .unwrap_or(max_delay)
+            .min(max_delay);
+        self.data.tarpit_count += 1;
+
+        tracing::debug!(
+            parent: &self.span,
+            context = "tarpit",
+            event = "delay",
+            stage = stage.as_str(),
+            count = self.data.tarpit_count,
+            delay = delay.as_millis() as u64,
+            "Delaying response to tarpitted client."
+        );
+
+        if delay > Duration::ZERO {
+            tokio::time::sleep(delay).await;
+        }
+
+        Ok(())
+    }
+}
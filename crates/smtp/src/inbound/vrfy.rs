@@ -24,11 +24,36 @@
 use directory::DirectoryError;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::core::Session;
+use crate::{config::AddressDisclosure, core::Session};
 use std::fmt::Write;
 
+const AMBIGUOUS_VRFY: &[u8] =
+    b"252 2.5.2 Cannot VRFY user, but will accept message and attempt delivery.\r\n";
+const AMBIGUOUS_EXPN: &[u8] =
+    b"252 2.5.2 Cannot EXPN list, but will accept message and attempt delivery.\r\n";
+
 impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     pub async fn handle_vrfy(&mut self, address: String) -> Result<(), ()> {
+        match self.params.vrfy {
+            AddressDisclosure::Full => (),
+            AddressDisclosure::Ambiguous => {
+                tracing::debug!(parent: &self.span,
+                    context = "vrfy",
+                    event = "ambiguous",
+                    address = &address);
+
+                return self.write(AMBIGUOUS_VRFY).await;
+            }
+            AddressDisclosure::Disabled => {
+                tracing::debug!(parent: &self.span,
+                    context = "vrfy",
+                    event = "forbidden",
+                    address = &address);
+
+                return self.write(b"502 5.5.1 VRFY is disabled.\r\n").await;
+            }
+        }
+
         match self
             .core
             .session
@@ -39,57 +64,75 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             .await
             .into_value(self)
         {
-            Some(address_lookup) if self.params.can_vrfy => {
-                match address_lookup.vrfy(&address.to_lowercase()).await {
-                    Ok(values) if !values.is_empty() => {
-                        let mut result = String::with_capacity(32);
-                        for (pos, value) in values.iter().enumerate() {
-                            let _ = write!(
-                                result,
-                                "250{}{}\r\n",
-                                if pos == values.len() - 1 { " " } else { "-" },
-                                value
-                            );
-                        }
-
-                        tracing::debug!(parent: &self.span,
-                            context = "vrfy",
-                            event = "success",
-                            address = &address);
-
-                        self.write(result.as_bytes()).await
+            Some(address_lookup) => match address_lookup.vrfy(&address.to_lowercase()).await {
+                Ok(values) if !values.is_empty() => {
+                    let mut result = String::with_capacity(32);
+                    for (pos, value) in values.iter().enumerate() {
+                        let _ = write!(
+                            result,
+                            "250{}{}\r\n",
+                            if pos == values.len() - 1 { " " } else { "-" },
+                            value
+                        );
                     }
-                    Ok(_) | Err(DirectoryError::Unsupported) => {
-                        tracing::debug!(parent: &self.span,
-                            context = "vrfy",
-                            event = "not-found",
-                            address = &address);
 
-                        self.write(b"550 5.1.2 Address not found.\r\n").await
-                    }
-                    Err(_) => {
-                        tracing::debug!(parent: &self.span,
-                            context = "vrfy",
-                            event = "temp-fail",
-                            address = &address);
-
-                        self.write(b"252 2.4.3 Unable to verify address at this time.\r\n")
-                            .await
-                    }
+                    tracing::debug!(parent: &self.span,
+                        context = "vrfy",
+                        event = "success",
+                        address = &address);
+
+                    self.write(result.as_bytes()).await
                 }
-            }
-            _ => {
+                Ok(_) | Err(DirectoryError::Unsupported) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "vrfy",
+                        event = "not-found",
+                        address = &address);
+
+                    self.write(b"550 5.1.2 Address not found.\r\n").await
+                }
+                Err(_) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "vrfy",
+                        event = "temp-fail",
+                        address = &address);
+
+                    self.write(b"252 2.4.3 Unable to verify address at this time.\r\n")
+                        .await
+                }
+            },
+            None => {
                 tracing::debug!(parent: &self.span,
                     context = "vrfy",
-                    event = "forbidden",
+                    event = "ambiguous",
                     address = &address);
 
-                self.write(b"252 2.5.1 VRFY is disabled.\r\n").await
+                self.write(AMBIGUOUS_VRFY).await
             }
         }
     }
 
     pub async fn handle_expn(&mut self, address: String) -> Result<(), ()> {
+        match self.params.expn {
+            AddressDisclosure::Full => (),
+            AddressDisclosure::Ambiguous => {
+                tracing::debug!(parent: &self.span,
+                    context = "expn",
+                    event = "ambiguous",
+                    address = &address);
+
+                return self.write(AMBIGUOUS_EXPN).await;
+            }
+            AddressDisclosure::Disabled => {
+                tracing::debug!(parent: &self.span,
+                    context = "expn",
+                    event = "forbidden",
+                    address = &address);
+
+                return self.write(b"502 5.5.1 EXPN is disabled.\r\n").await;
+            }
+        }
+
         match self
             .core
             .session
@@ -100,50 +143,48 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             .await
             .into_value(self)
         {
-            Some(address_lookup) if self.params.can_expn => {
-                match address_lookup.expn(&address.to_lowercase()).await {
-                    Ok(values) if !values.is_empty() => {
-                        let mut result = String::with_capacity(32);
-                        for (pos, value) in values.iter().enumerate() {
-                            let _ = write!(
-                                result,
-                                "250{}{}\r\n",
-                                if pos == values.len() - 1 { " " } else { "-" },
-                                value
-                            );
-                        }
-                        tracing::debug!(parent: &self.span,
-                            context = "expn",
-                            event = "success",
-                            address = &address);
-                        self.write(result.as_bytes()).await
+            Some(address_lookup) => match address_lookup.expn(&address.to_lowercase()).await {
+                Ok(values) if !values.is_empty() => {
+                    let mut result = String::with_capacity(32);
+                    for (pos, value) in values.iter().enumerate() {
+                        let _ = write!(
+                            result,
+                            "250{}{}\r\n",
+                            if pos == values.len() - 1 { " " } else { "-" },
+                            value
+                        );
                     }
-                    Ok(_) | Err(DirectoryError::Unsupported) => {
-                        tracing::debug!(parent: &self.span,
-                            context = "expn",
-                            event = "not-found",
-                            address = &address);
+                    tracing::debug!(parent: &self.span,
+                        context = "expn",
+                        event = "success",
+                        address = &address);
+                    self.write(result.as_bytes()).await
+                }
+                Ok(_) | Err(DirectoryError::Unsupported) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "expn",
+                        event = "not-found",
+                        address = &address);
 
-                        self.write(b"550 5.1.2 Mailing list not found.\r\n").await
-                    }
-                    Err(_) => {
-                        tracing::debug!(parent: &self.span,
-                            context = "expn",
-                            event = "temp-fail",
-                            address = &address);
-
-                        self.write(b"252 2.4.3 Unable to expand mailing list at this time.\r\n")
-                            .await
-                    }
+                    self.write(b"550 5.1.2 Mailing list not found.\r\n").await
                 }
-            }
-            _ => {
+                Err(_) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "expn",
+                        event = "temp-fail",
+                        address = &address);
+
+                    self.write(b"252 2.4.3 Unable to expand mailing list at this time.\r\n")
+                        .await
+                }
+            },
+            None => {
                 tracing::debug!(parent: &self.span,
                     context = "expn",
-                    event = "forbidden",
+                    event = "ambiguous",
                     address = &address);
 
-                self.write(b"252 2.5.1 EXPN is disabled.\r\n").await
+                self.write(AMBIGUOUS_EXPN).await
             }
         }
     }
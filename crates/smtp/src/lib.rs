@@ -24,23 +24,30 @@
 use crate::core::{
     throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, SessionCore, TlsConnectors, SMTP,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use config::{
     auth::ConfigAuth, queue::ConfigQueue, remote::ConfigHost, report::ConfigReport,
-    resolver::ConfigResolver, scripts::ConfigSieve, session::ConfigSession, ConfigContext, Host,
+    resolver::ConfigResolver, rules::ConfigRules, scripts::ConfigSieve, session::ConfigSession,
+    ConfigContext, Host,
 };
 use dashmap::DashMap;
 use directory::Directories;
-use mail_send::smtp::tls::build_tls_connector;
-use queue::manager::SpawnQueue;
+use queue::{
+    bounce::BounceClassifier, history::DeliveryHistory, manager::SpawnQueue,
+    quarantine::Quarantine, shared::SharedQueue, stream::MessageStreams,
+    suppression::SuppressionList, tuning::AutoTune, warmup::WarmUp,
+};
 use reporting::scheduler::SpawnReport;
 use store::Stores;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use utils::{
     config::{Config, ServerProtocol, Servers},
-    UnwrapFailure,
+    durability::FileSync,
+    listener::limiter::ConcurrencyLimiter,
+    map::ttl_dashmap::{TtlDashMap, TtlMap},
 };
+use wasm::Plugins;
 
 pub mod config;
 pub mod core;
@@ -49,6 +56,7 @@ pub mod outbound;
 pub mod queue;
 pub mod reporting;
 pub mod scripts;
+pub mod wasm;
 
 pub static USER_AGENT: &str = concat!("StalwartSMTP/", env!("CARGO_PKG_VERSION"),);
 pub static DAEMON_NAME: &str = concat!("Stalwart SMTP v", env!("CARGO_PKG_VERSION"),);
@@ -60,34 +68,96 @@ impl SMTP {
         stores: &Stores,
         directory: &Directories,
         #[cfg(feature = "local_delivery")] delivery_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
+        #[cfg(feature = "local_delivery")] event_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
     ) -> Result<Arc<Self>, String> {
-        // Read configuration parameters
-        let mut config_ctx = ConfigContext::new(&servers.inner);
-        config_ctx.directory = directory.clone();
-        config_ctx.stores = stores.clone();
+        let (core, queue_rx, report_rx) = Self::build(
+            config,
+            servers,
+            stores,
+            directory,
+            #[cfg(feature = "local_delivery")]
+            delivery_tx,
+            #[cfg(feature = "local_delivery")]
+            event_tx,
+        )?;
+        let core = Arc::new(core);
 
-        // Parse remote hosts
-        config.parse_remote_hosts(&mut config_ctx)?;
+        // Spawn queue manager
+        queue_rx.spawn(core.clone(), core.queue.read_queue().await);
 
-        // Add local delivery host
-        #[cfg(feature = "local_delivery")]
-        {
-            config_ctx.hosts.insert(
-                "local".to_string(),
-                Host {
-                    address: String::new(),
-                    port: 0,
-                    protocol: ServerProtocol::Jmap,
-                    concurrency: Default::default(),
-                    timeout: Default::default(),
-                    tls_implicit: Default::default(),
-                    tls_allow_invalid_certs: Default::default(),
-                    username: Default::default(),
-                    secret: Default::default(),
-                },
-            );
+        // Spawn shared queue lease renewal
+        if core.queue.shared.is_some() {
+            SharedQueue::spawn(core.clone());
         }
 
+        // Spawn report manager
+        report_rx.spawn(core.clone(), core.report.read_reports().await);
+
+        Ok(core)
+    }
+
+    // Returns whether the queue and report managers are processing events
+    pub async fn is_alive(&self, timeout: Duration) -> bool {
+        let (queue_tx, queue_rx) = oneshot::channel();
+        let (report_tx, report_rx) = oneshot::channel();
+
+        self.queue
+            .tx
+            .send(queue::Event::Ping(queue_tx))
+            .await
+            .is_ok()
+            && self
+                .report
+                .tx
+                .send(reporting::Event::Ping(report_tx))
+                .await
+                .is_ok()
+            && matches!(
+                tokio::time::timeout(timeout, async { (queue_rx.await, report_rx.await) }).await,
+                Ok((Ok(_), Ok(_)))
+            )
+    }
+
+    pub fn check_config(
+        config: &Config,
+        servers: &Servers,
+        stores: &Stores,
+        directory: &Directories,
+    ) -> Result<(), String> {
+        Self::build(
+            config,
+            servers,
+            stores,
+            directory,
+            #[cfg(feature = "local_delivery")]
+            mpsc::channel(1).0,
+            #[cfg(feature = "local_delivery")]
+            mpsc::channel(1).0,
+        )
+        .map(|_| ())
+    }
+
+    // Parses the configuration and builds the core without spawning any of
+    // its managers, so that check_config validates exactly what init uses.
+    #[allow(clippy::type_complexity)]
+    fn build(
+        config: &Config,
+        servers: &Servers,
+        stores: &Stores,
+        directory: &Directories,
+        #[cfg(feature = "local_delivery")] delivery_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
+        #[cfg(feature = "local_delivery")] event_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
+    ) -> Result<
+        (
+            Self,
+            mpsc::Receiver<queue::Event>,
+            mpsc::Receiver<reporting::Event>,
+        ),
+        String,
+    > {
+        // Read configuration parameters
+        let mut config_ctx = Self::build_config_context(config, servers, stores, directory)?;
+
         // Parse configuration
         config.parse_signatures(&mut config_ctx)?;
         let sieve_config = config.parse_sieve(&mut config_ctx)?;
@@ -96,10 +166,14 @@ impl SMTP {
         let mail_auth_config = config.parse_mail_auth(&config_ctx)?;
         let report_config = config.parse_reports(&config_ctx)?;
 
+        let throttle_store = config_ctx
+            .stores
+            .get_lookup_store(config, "global.rate-limit.store")?;
+
         // Build core
         let (queue_tx, queue_rx) = mpsc::channel(1024);
         let (report_tx, report_rx) = mpsc::channel(1024);
-        let core = Arc::new(SMTP {
+        let core = SMTP {
             worker_pool: rayon::ThreadPoolBuilder::new()
                 .num_threads(
                     config
@@ -109,7 +183,7 @@ impl SMTP {
                 )
                 .build()
                 .unwrap(),
-            resolvers: config.build_resolvers().failed("Failed to build resolvers"),
+            resolvers: config.build_resolvers(&config_ctx)?,
             session: SessionCore {
                 config: session_config,
                 throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
@@ -120,6 +194,10 @@ impl SMTP {
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
                 ),
+                throttle_store: throttle_store.clone(),
+                dictionary: DashMap::new(),
+                senders: DashMap::new(),
+                policy_cache: TtlDashMap::with_capacity(128, 16),
             },
             queue: QueueCore {
                 config: queue_config,
@@ -131,6 +209,7 @@ impl SMTP {
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
                 ),
+                throttle_store,
                 id_seq: 0.into(),
                 quota: DashMap::with_capacity_and_hasher_and_shard_amount(
                     config.property("global.shared-map.capacity")?.unwrap_or(2),
@@ -141,10 +220,17 @@ impl SMTP {
                         .next_power_of_two() as usize,
                 ),
                 tx: queue_tx,
-                connectors: TlsConnectors {
-                    pki_verify: build_tls_connector(false),
-                    dummy_verify: build_tls_connector(true),
-                },
+                connectors: TlsConnectors::parse(config)?,
+                workers: ConcurrencyLimiter::new(u64::MAX),
+                history: DeliveryHistory::parse(config)?,
+                suppression: SuppressionList::parse(config, stores)?,
+                warmup: WarmUp::parse(config, stores)?,
+                bounce: BounceClassifier::parse(config)?,
+                quarantine: Quarantine::parse(config)?,
+                streams: MessageStreams::parse(config)?,
+                durability: FileSync::parse(config, "queue.durability")?,
+                shared: SharedQueue::parse(config, stores)?,
+                auto_tune: AutoTune::parse(config)?,
             },
             report: ReportCore {
                 tx: report_tx,
@@ -152,16 +238,51 @@ impl SMTP {
             },
             mail_auth: mail_auth_config,
             sieve: sieve_config,
+            plugins: Plugins::parse(config)?,
             #[cfg(feature = "local_delivery")]
             delivery_tx,
-        });
+            #[cfg(feature = "local_delivery")]
+            event_tx,
+        };
 
-        // Spawn queue manager
-        queue_rx.spawn(core.clone(), core.queue.read_queue().await);
+        Ok((core, queue_rx, report_rx))
+    }
 
-        // Spawn report manager
-        report_rx.spawn(core.clone(), core.report.read_reports().await);
+    fn build_config_context<'x>(
+        config: &Config,
+        servers: &'x Servers,
+        stores: &Stores,
+        directory: &Directories,
+    ) -> Result<ConfigContext<'x>, String> {
+        let mut config_ctx = ConfigContext::new(&servers.inner);
+        config_ctx.directory = directory.clone();
+        config_ctx.stores = stores.clone();
 
-        Ok(core)
+        // Parse remote hosts
+        config.parse_remote_hosts(&mut config_ctx)?;
+
+        // Parse rule scripts
+        config.parse_rule_scripts(&mut config_ctx)?;
+
+        // Add local delivery host
+        #[cfg(feature = "local_delivery")]
+        {
+            config_ctx.hosts.insert(
+                "local".to_string(),
+                Host {
+                    address: String::new(),
+                    port: 0,
+                    protocol: ServerProtocol::Jmap,
+                    concurrency: Default::default(),
+                    timeout: Default::default(),
+                    tls_implicit: Default::default(),
+                    tls_allow_invalid_certs: Default::default(),
+                    username: Default::default(),
+                    secret: Default::default(),
+                },
+            );
+        }
+
+        Ok(config_ctx)
     }
 }
@@ -0,0 +1,383 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    future::Future,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mail_auth::{
+    common::lru::{DnsCache, LruCache},
+    hickory_resolver::{
+        error::{ResolveError, ResolveErrorKind},
+        lookup::Lookup,
+        proto::{op::ResponseCode, rr::RecordType},
+        TokioAsyncResolver,
+    },
+    MX,
+};
+use serde::{Deserialize, Serialize};
+use store::{write::now, LookupKey, LookupStore, LookupValue, Value};
+
+use crate::core::Resolvers;
+
+use super::{dane::Tlsa, mta_sts::Policy};
+
+/// Second level DNS cache backed by a lookup store, which allows resolved
+/// records to survive restarts. MX and address lookups are resolved here
+/// rather than through mail-auth, as its cache does not expose the TTL of
+/// the records it holds.
+pub struct PersistentCache {
+    pub store: LookupStore,
+    pub resolver: TokioAsyncResolver,
+    pub ttl: Duration,
+    pub negative_ttl: Duration,
+    pub mx: LruCache<String, Arc<Vec<MX>>>,
+    pub ipv4: LruCache<String, Arc<Vec<Ipv4Addr>>>,
+    pub ipv6: LruCache<String, Arc<Vec<Ipv6Addr>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum CachedRecord {
+    Mx(Vec<(Vec<String>, u16)>),
+    Ipv4(Vec<Ipv4Addr>),
+    Ipv6(Vec<Ipv6Addr>),
+    Tlsa(Tlsa),
+    MtaSts(Policy),
+    NotFound,
+}
+
+#[derive(Debug)]
+struct CachedBytes(Vec<u8>);
+
+trait CachedLookup: Sized {
+    const CLASS: &'static str;
+
+    fn from_lookup(lookup: &Lookup) -> Self;
+    fn from_record(record: CachedRecord) -> Option<Self>;
+    fn to_record(&self) -> CachedRecord;
+}
+
+impl PersistentCache {
+    pub async fn get(&self, class: &str, name: &str) -> Option<(CachedRecord, Instant)> {
+        match self
+            .store
+            .key_get::<CachedBytes>(LookupKey::Key(Self::key(class, name)))
+            .await
+        {
+            Ok(LookupValue::Value { value, expires }) => {
+                let record = bincode::deserialize::<CachedRecord>(&value.0).ok()?;
+                let max_ttl = if matches!(record, CachedRecord::NotFound) {
+                    self.negative_ttl
+                } else {
+                    self.ttl
+                };
+                // Some backends do not report the expiration time
+                let remaining = if expires > 0 {
+                    Duration::from_secs(expires.saturating_sub(now())).min(max_ttl)
+                } else {
+                    max_ttl
+                };
+                Some((record, Instant::now() + remaining))
+            }
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(
+                    context = "dns-cache",
+                    event = "error",
+                    class = class,
+                    name = name,
+                    reason = %err,
+                    "Failed to read persistent DNS cache entry."
+                );
+                None
+            }
+        }
+    }
+
+    pub async fn set(&self, class: &str, name: &str, record: &CachedRecord, ttl: Duration) {
+        let ttl = ttl.min(self.ttl).as_secs();
+        if ttl == 0 {
+            return;
+        }
+        let value = match bincode::serialize(record) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        if let Err(err) = self
+            .store
+            .key_set(
+                Self::key(class, name),
+                LookupValue::Value {
+                    value,
+                    expires: ttl,
+                },
+            )
+            .await
+        {
+            tracing::debug!(
+                context = "dns-cache",
+                event = "error",
+                class = class,
+                name = name,
+                reason = %err,
+                "Failed to write persistent DNS cache entry."
+            );
+        }
+    }
+
+    pub async fn set_until(
+        &self,
+        class: &str,
+        name: &str,
+        record: &CachedRecord,
+        valid_until: Instant,
+    ) {
+        self.set(
+            class,
+            name,
+            record,
+            valid_until.saturating_duration_since(Instant::now()),
+        )
+        .await
+    }
+
+    async fn lookup<T: CachedLookup>(
+        &self,
+        name: &str,
+        cache: &LruCache<String, Arc<T>>,
+        resolve: impl Future<Output = Result<Lookup, ResolveError>>,
+    ) -> mail_auth::Result<Arc<T>> {
+        let name = name.trim_end_matches('.').to_lowercase();
+        if let Some(value) = cache.get(&name) {
+            return Ok(value);
+        }
+        match self.get(T::CLASS, &name).await {
+            Some((CachedRecord::NotFound, _)) => {
+                return Err(mail_auth::Error::DnsRecordNotFound(ResponseCode::NXDomain));
+            }
+            Some((record, valid_until)) => {
+                if let Some(value) = T::from_record(record) {
+                    return Ok(cache.insert(name, Arc::new(value), valid_until));
+                }
+            }
+            None => (),
+        }
+
+        #[cfg(any(test, feature = "test_mode"))]
+        if true {
+            drop(resolve);
+            return Err(mail_auth::Error::DnsRecordNotFound(ResponseCode::NXDomain));
+        }
+
+        match resolve.await {
+            Ok(lookup) => {
+                let value = T::from_lookup(&lookup);
+                self.set_until(T::CLASS, &name, &value.to_record(), lookup.valid_until())
+                    .await;
+                Ok(cache.insert(name, Arc::new(value), lookup.valid_until()))
+            }
+            Err(err) => {
+                if let ResolveErrorKind::NoRecordsFound { negative_ttl, .. } = err.kind() {
+                    self.set(
+                        T::CLASS,
+                        &name,
+                        &CachedRecord::NotFound,
+                        negative_ttl
+                            .map(|ttl| Duration::from_secs(ttl as u64).min(self.negative_ttl))
+                            .unwrap_or(self.negative_ttl),
+                    )
+                    .await;
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    fn key(class: &str, name: &str) -> Vec<u8> {
+        format!("dns:{class}:{}", name.trim_end_matches('.').to_lowercase()).into_bytes()
+    }
+}
+
+impl Resolvers {
+    pub async fn mx_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<MX>>> {
+        match &self.cache.persist {
+            Some(persist) => {
+                persist
+                    .lookup(
+                        domain,
+                        &persist.mx,
+                        persist.resolver.lookup(fqdn(domain), RecordType::MX),
+                    )
+                    .await
+            }
+            None => self.dns.mx_lookup(domain).await,
+        }
+    }
+
+    pub async fn ipv4_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<Ipv4Addr>>> {
+        match &self.cache.persist {
+            Some(persist) => {
+                persist
+                    .lookup(
+                        domain,
+                        &persist.ipv4,
+                        persist.resolver.lookup(fqdn(domain), RecordType::A),
+                    )
+                    .await
+            }
+            None => self.dns.ipv4_lookup(domain).await,
+        }
+    }
+
+    pub async fn ipv6_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<Ipv6Addr>>> {
+        match &self.cache.persist {
+            Some(persist) => {
+                persist
+                    .lookup(
+                        domain,
+                        &persist.ipv6,
+                        persist.resolver.lookup(fqdn(domain), RecordType::AAAA),
+                    )
+                    .await
+            }
+            None => self.dns.ipv6_lookup(domain).await,
+        }
+    }
+}
+
+impl CachedLookup for Vec<MX> {
+    const CLASS: &'static str = "mx";
+
+    fn from_lookup(lookup: &Lookup) -> Self {
+        let mut records: Vec<MX> = Vec::new();
+        for mx in lookup.record_iter().filter_map(|r| r.data()?.as_mx()) {
+            let preference = mx.preference();
+            let exchange = mx.exchange().to_lowercase().to_string();
+
+            if let Some(record) = records.iter_mut().find(|r| r.preference == preference) {
+                record.exchanges.push(exchange);
+            } else {
+                records.push(MX {
+                    exchanges: vec![exchange],
+                    preference,
+                });
+            }
+        }
+        records.sort_unstable_by(|a, b| a.preference.cmp(&b.preference));
+        records
+    }
+
+    fn from_record(record: CachedRecord) -> Option<Self> {
+        match record {
+            CachedRecord::Mx(records) => Some(
+                records
+                    .into_iter()
+                    .map(|(exchanges, preference)| MX {
+                        exchanges,
+                        preference,
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn to_record(&self) -> CachedRecord {
+        CachedRecord::Mx(
+            self.iter()
+                .map(|mx| (mx.exchanges.clone(), mx.preference))
+                .collect(),
+        )
+    }
+}
+
+impl CachedLookup for Vec<Ipv4Addr> {
+    const CLASS: &'static str = "a";
+
+    fn from_lookup(lookup: &Lookup) -> Self {
+        lookup
+            .record_iter()
+            .filter_map(|r| r.data()?.as_a()?.0.into())
+            .collect()
+    }
+
+    fn from_record(record: CachedRecord) -> Option<Self> {
+        match record {
+            CachedRecord::Ipv4(ips) => Some(ips),
+            _ => None,
+        }
+    }
+
+    fn to_record(&self) -> CachedRecord {
+        CachedRecord::Ipv4(self.clone())
+    }
+}
+
+impl CachedLookup for Vec<Ipv6Addr> {
+    const CLASS: &'static str = "aaaa";
+
+    fn from_lookup(lookup: &Lookup) -> Self {
+        lookup
+            .record_iter()
+            .filter_map(|r| r.data()?.as_aaaa()?.0.into())
+            .collect()
+    }
+
+    fn from_record(record: CachedRecord) -> Option<Self> {
+        match record {
+            CachedRecord::Ipv6(ips) => Some(ips),
+            _ => None,
+        }
+    }
+
+    fn to_record(&self) -> CachedRecord {
+        CachedRecord::Ipv6(self.clone())
+    }
+}
+
+fn fqdn(domain: &str) -> String {
+    if domain.ends_with('.') {
+        domain.to_string()
+    } else {
+        format!("{domain}.")
+    }
+}
+
+impl store::Deserialize for CachedBytes {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        Ok(CachedBytes(bytes.to_vec()))
+    }
+}
+
+impl From<Value<'static>> for CachedBytes {
+    fn from(value: Value<'static>) -> Self {
+        match value {
+            Value::Blob(bytes) => CachedBytes(bytes.into_owned()),
+            Value::Text(text) => CachedBytes(text.into_owned().into_bytes()),
+            _ => CachedBytes(Vec::new()),
+        }
+    }
+}
@@ -28,29 +28,195 @@ use mail_auth::{
         error::{ResolveError, ResolveErrorKind},
         proto::{
             error::ProtoErrorKind,
-            rr::rdata::tlsa::{CertUsage, Matching, Selector},
+            op::{Edns, Message, MessageType, OpCode, Query, ResponseCode},
+            rr::{
+                rdata::tlsa::{CertUsage, Matching, Selector},
+                Name, Record, RecordType,
+            },
         },
         AsyncResolver,
     },
 };
-use std::sync::Arc;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
 
-use crate::core::Resolvers;
+use crate::{core::Resolvers, outbound::cache::CachedRecord};
 
-use super::{DnssecResolver, Tlsa, TlsaEntry};
+use super::{DnssecMode, DnssecResolver, DnssecStatus, Tlsa, TlsaEntry};
 
 impl DnssecResolver {
     pub fn with_capacity(
         config: ResolverConfig,
         options: ResolverOpts,
+        mode: DnssecMode,
     ) -> Result<Self, ResolveError> {
         Ok(Self {
             resolver: AsyncResolver::tokio(config, options),
+            mode,
         })
     }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.mode, DnssecMode::Disabled)
+    }
+
+    async fn validated_status(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> (DnssecStatus, Option<Instant>) {
+        match self.resolver.lookup(name, record_type).await {
+            Ok(lookup) => (DnssecStatus::Secure, lookup.valid_until().into()),
+            Err(err) => match err.kind() {
+                ResolveErrorKind::Proto(proto_err)
+                    if matches!(proto_err.kind(), ProtoErrorKind::RrsigsNotPresent { .. }) =>
+                {
+                    (DnssecStatus::Insecure, None)
+                }
+                ResolveErrorKind::NoRecordsFound { .. } => (DnssecStatus::Insecure, None),
+                // Signature validation failures are reported as protocol errors
+                ResolveErrorKind::Proto(_) => (DnssecStatus::Bogus, None),
+                _ => (DnssecStatus::Indeterminate, None),
+            },
+        }
+    }
+
+    async fn upstream_query(
+        &self,
+        upstreams: &[SocketAddr],
+        timeout: Duration,
+        name: &str,
+        record_type: RecordType,
+    ) -> Option<(DnssecStatus, Message)> {
+        let name = Name::from_ascii(name).ok()?;
+        let mut request = Message::new();
+        let mut edns = Edns::new();
+        edns.set_dnssec_ok(true);
+        edns.set_max_payload(1232);
+        request
+            .set_id(rand::random())
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .set_authentic_data(true)
+            .add_query(Query::query(name, record_type))
+            .set_edns(edns);
+        let request_bytes = request.to_vec().ok()?;
+
+        for upstream in upstreams {
+            let bind_addr: SocketAddr = if upstream.is_ipv4() {
+                ([0u8; 4], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            let result = tokio::time::timeout(timeout, async {
+                let socket = UdpSocket::bind(bind_addr).await?;
+                socket.connect(upstream).await?;
+                socket.send(&request_bytes).await?;
+                let mut buf = vec![0u8; 4096];
+                loop {
+                    let len = socket.recv(&mut buf).await?;
+                    if let Ok(response) = Message::from_vec(&buf[..len]) {
+                        if response.id() == request.id() {
+                            return Ok::<_, std::io::Error>(response);
+                        }
+                    }
+                }
+            })
+            .await;
+
+            match result {
+                Ok(Ok(response)) => {
+                    let status = match response.response_code() {
+                        ResponseCode::NoError | ResponseCode::NXDomain
+                            if response.authentic_data() =>
+                        {
+                            DnssecStatus::Secure
+                        }
+                        ResponseCode::NoError | ResponseCode::NXDomain => DnssecStatus::Insecure,
+                        // Validating resolvers return SERVFAIL for bogus responses
+                        ResponseCode::ServFail => DnssecStatus::Bogus,
+                        _ => DnssecStatus::Indeterminate,
+                    };
+                    return Some((status, response));
+                }
+                Ok(Err(err)) => {
+                    tracing::debug!(
+                        context = "dnssec",
+                        event = "error",
+                        upstream = %upstream,
+                        "Failed to query upstream resolver: {}",
+                        err
+                    );
+                }
+                Err(_) => {
+                    tracing::debug!(
+                        context = "dnssec",
+                        event = "timeout",
+                        upstream = %upstream,
+                        "Upstream resolver timed out."
+                    );
+                }
+            }
+        }
+
+        None
+    }
 }
 
 impl Resolvers {
+    pub async fn dnssec_status<'x>(
+        &self,
+        key: impl IntoFqdn<'x>,
+        record_type: RecordType,
+    ) -> DnssecStatus {
+        let key = key.into_fqdn();
+        let cache_key = format!("{record_type}:{key}");
+        if let Some(status) = self.cache.dnssec.get(&cache_key) {
+            return status;
+        }
+
+        #[cfg(any(test, feature = "test_mode"))]
+        if true {
+            return DnssecStatus::Indeterminate;
+        }
+
+        let (status, valid_until) = match &self.dnssec.mode {
+            DnssecMode::Disabled => (DnssecStatus::Indeterminate, None),
+            DnssecMode::Validate => {
+                self.dnssec
+                    .validated_status(key.as_ref(), record_type)
+                    .await
+            }
+            DnssecMode::TrustAd { upstreams, timeout } => self
+                .dnssec
+                .upstream_query(upstreams, *timeout, key.as_ref(), record_type)
+                .await
+                .map_or((DnssecStatus::Indeterminate, None), |(status, response)| {
+                    (
+                        status,
+                        response
+                            .answers()
+                            .iter()
+                            .map(|record| record.ttl())
+                            .min()
+                            .map(|ttl| Instant::now() + Duration::from_secs(ttl as u64)),
+                    )
+                }),
+        };
+
+        // Failures carry no TTL and are looked up again
+        if let Some(valid_until) = valid_until {
+            self.cache.dnssec.insert(cache_key, status, valid_until);
+        }
+
+        status
+    }
+
     pub async fn tlsa_lookup<'x>(
         &self,
         key: impl IntoFqdn<'x>,
@@ -59,13 +225,58 @@ impl Resolvers {
         if let Some(value) = self.cache.tlsa.get(key.as_ref()) {
             return Ok(Some(value));
         }
+        if let Some(persist) = &self.cache.persist {
+            if let Some((CachedRecord::Tlsa(tlsa), valid_until)) =
+                persist.get("tlsa", key.as_ref()).await
+            {
+                return Ok(Some(self.cache.tlsa.insert(
+                    key.into_owned(),
+                    Arc::new(tlsa),
+                    valid_until,
+                )));
+            }
+        }
 
         #[cfg(any(test, feature = "test_mode"))]
         if true {
             return mail_auth::common::resolver::mock_resolve(key.as_ref());
         }
 
-        let mut entries = Vec::new();
+        // Trust the AD bit set by a validating upstream resolver
+        if let DnssecMode::TrustAd { upstreams, timeout } = &self.dnssec.mode {
+            return match self
+                .dnssec
+                .upstream_query(upstreams, *timeout, key.as_ref(), RecordType::TLSA)
+                .await
+            {
+                // Authenticated denial of existence
+                Some((DnssecStatus::Secure, response)) if response.answers().is_empty() => Ok(None),
+                Some((DnssecStatus::Secure, response)) => {
+                    let ttl = response
+                        .answers()
+                        .iter()
+                        .map(|record| record.ttl())
+                        .min()
+                        .unwrap_or(0);
+                    Ok(Some(
+                        self.tlsa_insert(
+                            key.into_owned(),
+                            Tlsa::from_records(response.answers().iter()),
+                            Instant::now() + Duration::from_secs(ttl as u64),
+                        )
+                        .await,
+                    ))
+                }
+                Some((DnssecStatus::Insecure, _)) => Ok(None),
+                Some((DnssecStatus::Bogus, _)) => Err(mail_auth::Error::DnsError(
+                    "TLSA record failed DNSSEC validation".to_string(),
+                )),
+                _ => Err(mail_auth::Error::DnsError(
+                    "Upstream resolver unavailable".to_string(),
+                )),
+            };
+        }
+
         let tlsa_lookup = match self.dnssec.resolver.tlsa_lookup(key.as_ref()).await {
             Ok(tlsa_lookup) => tlsa_lookup,
             Err(err) => {
@@ -80,10 +291,60 @@ impl Resolvers {
             }
         };
 
+        Ok(Some(
+            self.tlsa_insert(
+                key.into_owned(),
+                Tlsa::from_records(tlsa_lookup.as_lookup().record_iter()),
+                tlsa_lookup.valid_until(),
+            )
+            .await,
+        ))
+    }
+
+    async fn tlsa_insert(&self, key: String, tlsa: Tlsa, valid_until: Instant) -> Arc<Tlsa> {
+        if let Some(persist) = &self.cache.persist {
+            persist
+                .set_until("tlsa", &key, &CachedRecord::Tlsa(tlsa.clone()), valid_until)
+                .await;
+        }
+        self.cache.tlsa.insert(key, Arc::new(tlsa), valid_until)
+    }
+
+    #[cfg(feature = "test_mode")]
+    pub fn dnssec_add<'x>(
+        &self,
+        key: impl IntoFqdn<'x>,
+        record_type: RecordType,
+        status: DnssecStatus,
+        valid_until: std::time::Instant,
+    ) {
+        self.cache.dnssec.insert(
+            format!("{record_type}:{}", key.into_fqdn()),
+            status,
+            valid_until,
+        );
+    }
+
+    #[cfg(feature = "test_mode")]
+    pub fn tlsa_add<'x>(
+        &self,
+        key: impl IntoFqdn<'x>,
+        value: impl Into<Arc<Tlsa>>,
+        valid_until: std::time::Instant,
+    ) {
+        self.cache
+            .tlsa
+            .insert(key.into_fqdn().into_owned(), value.into(), valid_until);
+    }
+}
+
+impl Tlsa {
+    fn from_records<'x>(records: impl Iterator<Item = &'x Record>) -> Self {
+        let mut entries = Vec::new();
         let mut has_end_entities = false;
         let mut has_intermediates = false;
 
-        for record in tlsa_lookup.as_lookup().record_iter() {
+        for record in records {
             if let Some(tlsa) = record.data().and_then(|r| r.as_tlsa()) {
                 let is_end_entity = match tlsa.cert_usage() {
                     CertUsage::DomainIssued => true,
@@ -112,26 +373,10 @@ impl Resolvers {
             }
         }
 
-        Ok(Some(self.cache.tlsa.insert(
-            key.into_owned(),
-            Arc::new(Tlsa {
-                entries,
-                has_end_entities,
-                has_intermediates,
-            }),
-            tlsa_lookup.valid_until(),
-        )))
-    }
-
-    #[cfg(feature = "test_mode")]
-    pub fn tlsa_add<'x>(
-        &self,
-        key: impl IntoFqdn<'x>,
-        value: impl Into<Arc<Tlsa>>,
-        valid_until: std::time::Instant,
-    ) {
-        self.cache
-            .tlsa
-            .insert(key.into_fqdn().into_owned(), value.into(), valid_until);
+        Tlsa {
+            entries,
+            has_end_entities,
+            has_intermediates,
+        }
     }
 }
@@ -21,16 +21,49 @@
  * for more details.
 */
 
+use std::{net::SocketAddr, time::Duration};
+
 use mail_auth::hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
 
 pub mod dnssec;
 pub mod verify;
 
 pub struct DnssecResolver {
     pub resolver: TokioAsyncResolver,
+    pub mode: DnssecMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnssecMode {
+    Disabled,
+    Validate,
+    TrustAd {
+        upstreams: Vec<SocketAddr>,
+        timeout: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnssecStatus {
+    Secure,
+    Insecure,
+    Bogus,
+    Indeterminate,
+}
+
+impl DnssecStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnssecStatus::Secure => "secure",
+            DnssecStatus::Insecure => "insecure",
+            DnssecStatus::Bogus => "bogus",
+            DnssecStatus::Indeterminate => "indeterminate",
+        }
+    }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TlsaEntry {
     pub is_end_entity: bool,
     pub is_sha256: bool,
@@ -38,7 +71,7 @@ pub struct TlsaEntry {
     pub data: Vec<u8>,
 }
 
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tlsa {
     pub entries: Vec<TlsaEntry>,
     pub has_end_entities: bool,
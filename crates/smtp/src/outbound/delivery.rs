@@ -22,7 +22,7 @@
 */
 
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -31,30 +31,60 @@ use mail_auth::{
     mta_sts::TlsRpt,
     report::tlsrpt::{FailureDetails, ResultType},
 };
-use mail_send::SmtpClient;
 use smtp_proto::MAIL_REQUIRETLS;
 use utils::config::ServerProtocol;
 
 use crate::{
-    config::{AggregateFrequency, TlsStrategy},
+    config::{AggregateFrequency, MandatoryTls, RequireOptional, TlsStrategy},
     core::SMTP,
     queue::ErrorDetails,
     reporting::{tls::TlsRptOptions, PolicyType, TlsEvent},
 };
 
 use super::{
+    happy_eyeballs,
     lookup::ToNextHop,
     mta_sts,
     session::{read_greeting, say_helo, try_start_tls, SessionParams, StartTlsResult},
+    tls::into_tls,
     NextHop,
 };
 use crate::queue::{
-    manager::Queue, throttle, DeliveryAttempt, Domain, Error, Event, OnHold, QueueEnvelope,
-    Schedule, Status, WorkerResult,
+    history::HistoryDetails, manager::Queue, throttle, tuning::Signal, DeliveryAttempt, Domain,
+    Error, Event, OnHold, QueueEnvelope, Schedule, Status, WorkerResult, RCPT_STATUS_CHANGED,
 };
 
 impl DeliveryAttempt {
     pub async fn try_deliver(mut self, core: Arc<SMTP>, queue: &mut Queue) {
+        // Make sure that no other node took over the message in the meantime
+        match core.queue.is_owner(&self.message).await {
+            Ok(true) => (),
+            Ok(false) => {
+                tracing::info!(
+                    parent: &self.span,
+                    context = "queue",
+                    event = "fenced",
+                    "Message is now owned by another node, removing local copy."
+                );
+                self.message.remove().await;
+                return;
+            }
+            Err(err) => {
+                tracing::error!(
+                    parent: &self.span,
+                    context = "queue",
+                    event = "error",
+                    "Failed to verify shared queue ownership: {}",
+                    err
+                );
+                queue.schedule(Schedule {
+                    due: Instant::now() + Duration::from_secs(60),
+                    inner: self.message,
+                });
+                return;
+            }
+        }
+
         // Check that the message still has recipients to be delivered
         let has_pending_delivery = self.has_pending_delivery();
 
@@ -66,7 +96,7 @@ impl DeliveryAttempt {
             let due = self.message.next_delivery_event();
             if due > Instant::now() {
                 // Save changes to disk
-                self.message.save_changes().await;
+                core.queue.save_message(&mut self.message).await;
 
                 queue.schedule(Schedule {
                     due,
@@ -76,7 +106,14 @@ impl DeliveryAttempt {
             }
         } else {
             // All message recipients expired, do not re-queue. (DSN has been already sent)
-            self.message.remove().await;
+            #[cfg(feature = "local_delivery")]
+            self.message
+                .notify_status(0..self.message.domains.len(), &core.event_tx, &self.span)
+                .await;
+            core.queue
+                .history
+                .record(self.message.id, HistoryDetails::completed(&self.message));
+            core.queue.remove_message(&self.message).await;
             return;
         }
 
@@ -93,7 +130,7 @@ impl DeliveryAttempt {
                 .await
             {
                 // Save changes to disk
-                self.message.save_changes().await;
+                core.queue.save_message(&mut self.message).await;
 
                 match err {
                     throttle::Error::Concurrency { limiter } => {
@@ -114,6 +151,9 @@ impl DeliveryAttempt {
             }
         }
 
+        // Track active workers so the queue can be checkpointed on shutdown
+        self.in_flight.extend(core.queue.workers.is_allowed());
+
         tokio::spawn(async move {
             let queue_config = &core.queue.config;
             let mut on_hold = Vec::new();
@@ -121,6 +161,7 @@ impl DeliveryAttempt {
 
             let mut domains = std::mem::take(&mut self.message.domains);
             let mut recipients = std::mem::take(&mut self.message.recipients);
+            let mut attempted = Vec::new();
             'next_domain: for (domain_idx, domain) in domains.iter_mut().enumerate() {
                 // Only process domains due for delivery
                 if !matches!(&domain.status, Status::Scheduled | Status::TemporaryFailure(_)
@@ -128,6 +169,7 @@ impl DeliveryAttempt {
                 {
                     continue;
                 }
+                attempted.push(domain_idx);
 
                 // Create new span for domain
                 let span = tracing::info_span!(
@@ -170,6 +212,7 @@ impl DeliveryAttempt {
                             .deliver_local(
                                 recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
                                 &core.delivery_tx,
+                                core.session.config.rcpt.cache.as_ref(),
                                 &span,
                             )
                             .await;
@@ -188,11 +231,22 @@ impl DeliveryAttempt {
 
                 // Prepare TLS strategy
                 let mut disable_tls = false;
+                let mandatory_tls = *queue_config.tls.mandatory.eval(&envelope).await;
                 let mut tls_strategy = TlsStrategy {
-                    mta_sts: *queue_config.tls.mta_sts.eval(&envelope).await,
+                    mta_sts: match (
+                        mandatory_tls,
+                        *queue_config.tls.mta_sts.eval(&envelope).await,
+                    ) {
+                        (MandatoryTls::MtaSts, _) => RequireOptional::Require,
+                        (MandatoryTls::DaneOrMtaSts, RequireOptional::Disable) => {
+                            RequireOptional::Optional
+                        }
+                        (_, mta_sts) => mta_sts,
+                    },
                     ..Default::default()
                 };
-                let allow_invalid_certs = *queue_config.tls.invalid_certs.eval(&envelope).await;
+                let allow_invalid_certs = mandatory_tls == MandatoryTls::Disabled
+                    && *queue_config.tls.invalid_certs.eval(&envelope).await;
 
                 // Obtain TLS reporting
                 let tls_report = match core.report.config.tls.send.eval(&envelope).await {
@@ -294,7 +348,11 @@ impl DeliveryAttempt {
                                     "Failed to retrieve MTA-STS policy: {}",
                                     err
                                 );
-                                domain.set_status(err, queue_config.retry.eval(&envelope).await);
+                                domain.set_status(
+                                    Status::from(err)
+                                        .into_mandatory_tls(mandatory_tls, envelope.domain),
+                                    queue_config.retry.eval(&envelope).await,
+                                );
                                 continue 'next_domain;
                             } else {
                                 tracing::debug!(
@@ -317,7 +375,7 @@ impl DeliveryAttempt {
                 let mx_list;
                 if is_smtp && remote_hosts.is_empty() {
                     // Lookup MX
-                    mx_list = match core.resolvers.dns.mx_lookup(&domain.domain).await {
+                    mx_list = match core.resolvers.mx_lookup(&domain.domain).await {
                         Ok(mx) => mx,
                         Err(err) => {
                             tracing::info!(
@@ -355,6 +413,7 @@ impl DeliveryAttempt {
                 // Try delivering message
                 let max_multihomed = *queue_config.max_multihomed.eval(&envelope).await;
                 let mut last_status = Status::Scheduled;
+                let mut warmup_deferred = None;
                 'next_host: for remote_host in &remote_hosts {
                     // Validate MTA-STS
                     envelope.mx = remote_host.hostname();
@@ -413,8 +472,19 @@ impl DeliveryAttempt {
                     };
 
                     // Update TLS strategy
-                    tls_strategy.dane = *queue_config.tls.dane.eval(&envelope).await;
-                    tls_strategy.tls = *queue_config.tls.start.eval(&envelope).await;
+                    tls_strategy.dane =
+                        match (mandatory_tls, *queue_config.tls.dane.eval(&envelope).await) {
+                            (MandatoryTls::Dane, _) => RequireOptional::Require,
+                            (MandatoryTls::DaneOrMtaSts, RequireOptional::Disable) => {
+                                RequireOptional::Optional
+                            }
+                            (_, dane) => dane,
+                        };
+                    tls_strategy.tls = if mandatory_tls != MandatoryTls::Disabled {
+                        RequireOptional::Require
+                    } else {
+                        *queue_config.tls.start.eval(&envelope).await
+                    };
 
                     // Lookup DANE policy
                     let dane_policy = if tls_strategy.try_dane() && is_smtp {
@@ -556,19 +626,96 @@ impl DeliveryAttempt {
                         None
                     };
 
+                    // Enforce mandatory DANE or MTA-STS authentication
+                    if mandatory_tls == MandatoryTls::DaneOrMtaSts
+                        && mta_sts_policy.is_none()
+                        && dane_policy.is_none()
+                    {
+                        tracing::info!(
+                            parent: &span,
+                            context = "tls",
+                            event = "policy-missing",
+                            mx = envelope.mx,
+                            "Neither a DANE nor an MTA-STS policy is available for this host."
+                        );
+
+                        last_status = Status::PermanentFailure(Error::TlsError(ErrorDetails {
+                            entity: envelope.mx.to_string(),
+                            details: "No DANE or MTA-STS policy was found".to_string(),
+                        }));
+                        continue 'next_host;
+                    }
+
+                    // Group addresses into connection attempts, racing both address
+                    // families when Happy Eyeballs is enabled (RFC 8305)
+                    let attempt_delay = *queue_config.happy_eyeballs.eval(&envelope).await;
+                    let candidates = |remote_ips: Vec<IpAddr>| {
+                        remote_ips
+                            .into_iter()
+                            .map(|remote_ip| happy_eyeballs::Candidate {
+                                remote_ip,
+                                source_ip: if remote_ip.is_ipv4() {
+                                    resolve_result.source_ipv4
+                                } else {
+                                    resolve_result.source_ipv6
+                                },
+                            })
+                            .collect::<Vec<_>>()
+                    };
+                    let is_dual_stack = resolve_result.remote_ips.iter().any(|ip| ip.is_ipv4())
+                        && resolve_result.remote_ips.iter().any(|ip| ip.is_ipv6());
+                    let attempts = match attempt_delay {
+                        Some(_) if is_dual_stack => {
+                            vec![candidates(happy_eyeballs::interleave(
+                                resolve_result.remote_ips.clone(),
+                            ))]
+                        }
+                        _ => resolve_result
+                            .remote_ips
+                            .iter()
+                            .map(|ip| candidates(vec![*ip]))
+                            .collect(),
+                    };
+
                     // Try each IP address
-                    'next_ip: for remote_ip in resolve_result.remote_ips {
-                        // Set source IP, if any
-                        let source_ip = if remote_ip.is_ipv4() {
-                            resolve_result.source_ipv4
+                    'next_ip: for candidates in attempts {
+                        // Skip source addresses that reached their daily warm-up limit
+                        let candidates = if core.queue.warmup.is_enabled() {
+                            let mut allowed = Vec::with_capacity(candidates.len());
+                            for candidate in candidates {
+                                if let Some(source_ip) = candidate.source_ip {
+                                    if let Err(err) = core
+                                        .queue
+                                        .warmup
+                                        .is_allowed(source_ip, &domain.domain)
+                                        .await
+                                    {
+                                        tracing::info!(
+                                            parent: &span,
+                                            context = "warm-up",
+                                            event = "skipped",
+                                            domain = domain.domain,
+                                            source_ip = %source_ip,
+                                            "Daily warm-up limit reached for source address."
+                                        );
+                                        warmup_deferred = Some(err);
+                                        continue;
+                                    }
+                                }
+                                allowed.push(candidate);
+                            }
+                            if allowed.is_empty() {
+                                continue 'next_ip;
+                            }
+                            allowed
                         } else {
-                            resolve_result.source_ipv6
+                            candidates
                         };
-                        envelope.local_ip = source_ip.unwrap_or(no_ip);
 
-                        // Throttle remote host
+                        // Throttle remote host, using the preferred address when racing
                         let mut in_flight_host = Vec::new();
-                        envelope.remote_ip = remote_ip;
+                        envelope.remote_ip = candidates[0].remote_ip;
+                        envelope.local_ip = candidates[0].source_ip.unwrap_or(no_ip);
                         for throttle in &queue_config.throttle.host {
                             if let Err(err) = core
                                 .queue
@@ -580,33 +727,52 @@ impl DeliveryAttempt {
                             }
                         }
 
+                        // Adapt to the concurrency and rate tolerated by the remote host
+                        match core.queue.auto_tune.is_allowed(envelope.mx) {
+                            Ok(Some(in_flight)) => in_flight_host.push(in_flight),
+                            Ok(None) => (),
+                            Err(err) => {
+                                tracing::info!(
+                                    parent: &span,
+                                    context = "auto-tune",
+                                    event = "deferred",
+                                    mx = envelope.mx,
+                                    "Delivery deferred by remote host auto-tuning."
+                                );
+                                domain.set_throttle_error(err, &mut on_hold);
+                                continue 'next_domain;
+                            }
+                        }
+
                         // Connect
-                        let mut smtp_client = match if let Some(ip_addr) = source_ip {
-                            SmtpClient::connect_using(
-                                ip_addr,
-                                SocketAddr::new(remote_ip, remote_host.port()),
-                                *queue_config.timeout.connect.eval(&envelope).await,
-                            )
-                            .await
-                        } else {
-                            SmtpClient::connect(
-                                SocketAddr::new(remote_ip, remote_host.port()),
-                                *queue_config.timeout.connect.eval(&envelope).await,
-                            )
-                            .await
-                        } {
-                            Ok(smtp_client) => {
+                        let connect_start = Instant::now();
+                        let (remote_ip, mut smtp_client) = match happy_eyeballs::connect(
+                            &candidates,
+                            remote_host.port(),
+                            attempt_delay.unwrap_or_default(),
+                            *queue_config.timeout.connect.eval(&envelope).await,
+                        )
+                        .await
+                        {
+                            Ok((candidate, smtp_client)) => {
+                                envelope.remote_ip = candidate.remote_ip;
+                                envelope.local_ip = candidate.source_ip.unwrap_or(no_ip);
+                                core.queue
+                                    .auto_tune
+                                    .record_latency(envelope.mx, connect_start.elapsed());
+
                                 tracing::debug!(
                                     parent: &span,
                                     context = "connect",
                                     event = "success",
                                     mx = envelope.mx,
-                                    source_ip = %source_ip.unwrap_or(no_ip),
-                                    remote_ip = %remote_ip,
+                                    source_ip = %envelope.local_ip,
+                                    remote_ip = %candidate.remote_ip,
                                     remote_port = remote_host.port(),
+                                    candidates = candidates.len(),
                                 );
 
-                                smtp_client
+                                (candidate.remote_ip, smtp_client)
                             }
                             Err(err) => {
                                 tracing::info!(
@@ -639,12 +805,34 @@ impl DeliveryAttempt {
                             || (self.message.flags & MAIL_REQUIRETLS) != 0
                             || mta_sts_policy.is_some()
                             || dane_policy.is_some();
-                        let tls_connector =
-                            if allow_invalid_certs || remote_host.allow_invalid_certs() {
-                                &core.queue.connectors.dummy_verify
-                            } else {
-                                &core.queue.connectors.pki_verify
-                            };
+                        let tls_connector = if allow_invalid_certs
+                            || (remote_host.allow_invalid_certs()
+                                && mandatory_tls == MandatoryTls::Disabled)
+                        {
+                            &core.queue.connectors.dummy_verify
+                        } else {
+                            &core.queue.connectors.pki_verify
+                        };
+
+                        // Recipients pending delivery, their change flag is cleared so that
+                        // only the statuses updated by this attempt are classified.
+                        let pending = recipients
+                            .iter_mut()
+                            .map(|rcpt| {
+                                if rcpt.domain_idx == domain_idx
+                                    && !matches!(
+                                        rcpt.status,
+                                        Status::Completed(_) | Status::PermanentFailure(_)
+                                    )
+                                {
+                                    let was_changed = rcpt.has_flag(RCPT_STATUS_CHANGED);
+                                    rcpt.flags &= !RCPT_STATUS_CHANGED;
+                                    Some(was_changed)
+                                } else {
+                                    None
+                                }
+                            })
+                            .collect::<Vec<_>>();
 
                         let delivery_result = if !remote_host.implicit_tls() {
                             // Read greeting
@@ -659,6 +847,9 @@ impl DeliveryAttempt {
                                     mx = envelope.mx,
                                     status = %status,
                                 );
+                                core.queue
+                                    .auto_tune
+                                    .record(envelope.mx, Signal::from_status(&status));
 
                                 last_status = status;
                                 continue 'next_host;
@@ -682,7 +873,9 @@ impl DeliveryAttempt {
                             };
 
                             // Try starting TLS
-                            if tls_strategy.try_start_tls() && !domain.disable_tls {
+                            if tls_strategy.try_start_tls()
+                                && (!domain.disable_tls || mandatory_tls != MandatoryTls::Disabled)
+                            {
                                 smtp_client.timeout =
                                     *queue_config.timeout.tls.eval(&envelope).await;
                                 match try_start_tls(
@@ -875,7 +1068,7 @@ impl DeliveryAttempt {
                             // Start TLS
                             smtp_client.timeout = *queue_config.timeout.tls.eval(&envelope).await;
                             let mut smtp_client =
-                                match smtp_client.into_tls(tls_connector, envelope.mx).await {
+                                match into_tls(smtp_client, tls_connector, envelope.mx).await {
                                     Ok(smtp_client) => smtp_client,
                                     Err(error) => {
                                         tracing::info!(
@@ -903,6 +1096,9 @@ impl DeliveryAttempt {
                                     mx = envelope.mx,
                                     status = %status,
                                 );
+                                core.queue
+                                    .auto_tune
+                                    .record(envelope.mx, Signal::from_status(&status));
 
                                 last_status = status;
                                 continue 'next_host;
@@ -918,6 +1114,62 @@ impl DeliveryAttempt {
                                 .await
                         };
 
+                        // Feed throttling signals back to auto-tuning
+                        if core.queue.auto_tune.is_enabled() {
+                            let signal = recipients
+                                .iter()
+                                .zip(&pending)
+                                .filter(|(_, pending)| pending.is_some())
+                                .map(|(rcpt, _)| Signal::from_rcpt_status(&rcpt.status))
+                                .chain([Signal::from_status(&delivery_result)])
+                                .fold(Signal::Neutral, Signal::merge);
+                            core.queue.auto_tune.record(envelope.mx, signal);
+                        }
+
+                        // Classify the bounces of the recipients updated by this attempt,
+                        // and restore the change flag of those left untouched
+                        let classifier = &core.queue.bounce;
+                        let mut changed = Vec::new();
+                        for (rcpt_idx, (rcpt, was_changed)) in
+                            recipients.iter_mut().zip(pending).enumerate()
+                        {
+                            match was_changed {
+                                Some(_) if rcpt.has_flag(RCPT_STATUS_CHANGED) => {
+                                    if let Some(class) = classifier.classify_status(&rcpt.status) {
+                                        classifier.record(&domain.domain, class);
+                                    }
+                                    changed.push(rcpt_idx);
+                                }
+                                Some(true) => {
+                                    rcpt.flags |= RCPT_STATUS_CHANGED;
+                                }
+                                _ => (),
+                            }
+                        }
+                        if let Some(class) = classifier.classify_status(&delivery_result) {
+                            classifier.record(&domain.domain, class);
+                        }
+
+                        // Count the message towards the warm-up limit of the source address used
+                        if changed.iter().any(|rcpt_idx| {
+                            matches!(recipients[*rcpt_idx].status, Status::Completed(_))
+                        }) {
+                            core.queue
+                                .warmup
+                                .record_sent(envelope.local_ip, &domain.domain)
+                                .await;
+                        }
+
+                        // Suppress recipients that hard bounced
+                        core.queue
+                            .suppression
+                            .add_bounces(
+                                &self.message.return_path_domain,
+                                changed.into_iter().map(|rcpt_idx| &recipients[rcpt_idx]),
+                                classifier,
+                            )
+                            .await;
+
                         // Update status for the current domain and continue with the next one
                         domain
                             .set_status(delivery_result, queue_config.retry.eval(&envelope).await);
@@ -925,13 +1177,46 @@ impl DeliveryAttempt {
                     }
                 }
 
+                // Hold the message until the next day when no source address
+                // was allowed to send to this domain
+                if let (Status::Scheduled, Some(err)) = (&last_status, warmup_deferred) {
+                    domain.set_throttle_error(err, &mut on_hold);
+                    continue 'next_domain;
+                }
+
                 // Update status
+                if let Some(class) = core.queue.bounce.classify_status(&last_status) {
+                    core.queue.bounce.record(&domain.domain, class);
+                }
                 domain.disable_tls = disable_tls;
-                domain.set_status(last_status, queue_config.retry.eval(&envelope).await);
+                domain.set_status(
+                    last_status.into_mandatory_tls(mandatory_tls, envelope.domain),
+                    queue_config.retry.eval(&envelope).await,
+                );
+            }
+
+            // Record delivery attempts
+            if core.queue.history.is_enabled() {
+                core.queue.history.record_all(
+                    self.message.id,
+                    attempted.iter().map(|domain_idx| {
+                        HistoryDetails::attempt(
+                            &domains[*domain_idx],
+                            recipients.iter().filter(|r| r.domain_idx == *domain_idx),
+                            &core.queue.bounce,
+                        )
+                    }),
+                );
             }
             self.message.domains = domains;
             self.message.recipients = recipients;
 
+            // Notify delivery status updates to the JMAP server
+            #[cfg(feature = "local_delivery")]
+            self.message
+                .notify_status(attempted.into_iter(), &core.event_tx, &self.span)
+                .await;
+
             // Send Delivery Status Notifications
             core.queue.send_dsn(&mut self).await;
 
@@ -942,7 +1227,7 @@ impl DeliveryAttempt {
                 self.message.release_quota();
 
                 // Save changes to disk
-                self.message.save_changes().await;
+                core.queue.save_message(&mut self.message).await;
 
                 tracing::info!(
                     parent: &span,
@@ -962,7 +1247,7 @@ impl DeliveryAttempt {
                 self.message.release_quota();
 
                 // Save changes to disk
-                self.message.save_changes().await;
+                core.queue.save_message(&mut self.message).await;
 
                 tracing::info!(
                     parent: &span,
@@ -978,7 +1263,10 @@ impl DeliveryAttempt {
                 })
             } else {
                 // Delete message from queue
-                self.message.remove().await;
+                core.queue
+                    .history
+                    .record(self.message.id, HistoryDetails::completed(&self.message));
+                core.queue.remove_message(&self.message).await;
 
                 tracing::info!(
                     parent: &span,
@@ -0,0 +1,112 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use mail_send::SmtpClient;
+use tokio::{net::TcpStream, task::JoinSet};
+
+/// Connection candidate consisting of a remote address and an optional
+/// source address to bind to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    pub remote_ip: IpAddr,
+    pub source_ip: Option<IpAddr>,
+}
+
+/// Reorders a list of addresses so that address families alternate,
+/// starting with the family of the first (preferred) address (RFC 8305, section 4).
+pub fn interleave(addrs: Vec<IpAddr>) -> Vec<IpAddr> {
+    let preferred_v4 = match addrs.first() {
+        Some(addr) => addr.is_ipv4(),
+        None => return addrs,
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv4() == preferred_v4);
+    if other.is_empty() {
+        return preferred;
+    }
+
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+    loop {
+        match (preferred.next(), other.next()) {
+            (Some(a), Some(b)) => {
+                result.push(a);
+                result.push(b);
+            }
+            (Some(a), None) | (None, Some(a)) => result.push(a),
+            (None, None) => break,
+        }
+    }
+    result
+}
+
+/// Races connection attempts to the candidates in order, starting a new attempt
+/// every `attempt_delay` or as soon as the previous attempt fails. The first
+/// established connection wins and all other pending attempts are aborted.
+pub async fn connect(
+    candidates: &[Candidate],
+    port: u16,
+    attempt_delay: Duration,
+    timeout: Duration,
+) -> Result<(Candidate, SmtpClient<TcpStream>), mail_send::Error> {
+    let mut attempts = JoinSet::new();
+    let mut pending = candidates.iter().copied().peekable();
+    let mut last_err = None;
+
+    loop {
+        if let Some(candidate) = pending.next() {
+            attempts.spawn(async move {
+                let remote_addr = SocketAddr::new(candidate.remote_ip, port);
+                let result = if let Some(source_ip) = candidate.source_ip {
+                    SmtpClient::connect_using(source_ip, remote_addr, timeout).await
+                } else {
+                    SmtpClient::connect(remote_addr, timeout).await
+                };
+                (candidate, result)
+            });
+        } else if attempts.is_empty() {
+            return Err(last_err.unwrap_or(mail_send::Error::Timeout));
+        }
+
+        let has_pending = pending.peek().is_some();
+        tokio::select! {
+            result = attempts.join_next() => match result {
+                Some(Ok((candidate, Ok(smtp_client)))) => {
+                    return Ok((candidate, smtp_client));
+                }
+                Some(Ok((_, Err(err)))) => {
+                    last_err = Some(err);
+                }
+                Some(Err(_)) | None => (),
+            },
+            _ = tokio::time::sleep(attempt_delay), if has_pending => (),
+        }
+    }
+}
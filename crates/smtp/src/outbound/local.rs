@@ -23,10 +23,14 @@
 
 use smtp_proto::Response;
 use tokio::sync::{mpsc, oneshot};
-use utils::ipc::{DeliveryEvent, DeliveryResult, IngestMessage};
+use utils::ipc::{DeliveryEvent, DeliveryResult, DeliveryStatus, IngestMessage, RecipientStatus};
 
-use crate::queue::{
-    Error, ErrorDetails, HostResponse, Message, Recipient, Status, RCPT_STATUS_CHANGED,
+use crate::{
+    inbound::rcpt_cache::{RcptCache, RcptStatus},
+    queue::{
+        Error, ErrorDetails, HostResponse, Message, Recipient, Status, MAIL_NOTIFY_STATUS,
+        RCPT_STATUS_CHANGED,
+    },
 };
 
 impl Message {
@@ -34,6 +38,7 @@ impl Message {
         &self,
         recipients: impl Iterator<Item = &mut Recipient>,
         delivery_tx: &mpsc::Sender<DeliveryEvent>,
+        rcpt_cache: Option<&RcptCache>,
         span: &tracing::Span,
     ) -> Status<(), Error> {
         // Prepare recipients list
@@ -138,6 +143,34 @@ impl Message {
                         },
                     });
                 }
+                DeliveryResult::OverQuota => {
+                    tracing::info!(
+                        parent: span,
+                        context = "deliver_local",
+                        event = "deferred",
+                        rcpt = rcpt.address,
+                        reason = "Mailbox over quota.",
+                    );
+
+                    // Reject further messages for this mailbox at RCPT time
+                    if let Some(rcpt_cache) = rcpt_cache {
+                        rcpt_cache
+                            .set(&rcpt.address_lcase, RcptStatus::OverQuota)
+                            .await;
+                    }
+
+                    rcpt.status = Status::TemporaryFailure(HostResponse {
+                        hostname: ErrorDetails {
+                            entity: "localhost".to_string(),
+                            details: format!("RCPT TO:<{}>", rcpt.address),
+                        },
+                        response: Response {
+                            code: 452,
+                            esc: [4, 2, 2],
+                            message: "Mailbox over quota.".to_string(),
+                        },
+                    });
+                }
                 DeliveryResult::PermanentFailure { code, reason } => {
                     tracing::info!(
                         parent: span,
@@ -168,4 +201,69 @@ impl Message {
             Status::Scheduled
         }
     }
+
+    pub async fn notify_status(
+        &self,
+        domain_idxs: impl Iterator<Item = usize>,
+        delivery_tx: &mpsc::Sender<DeliveryEvent>,
+        span: &tracing::Span,
+    ) {
+        if (self.flags & MAIL_NOTIFY_STATUS) == 0 {
+            return;
+        }
+
+        // Recipients without a final status inherit the status of their domain
+        let mut recipients = Vec::new();
+        for domain_idx in domain_idxs {
+            let domain = &self.domains[domain_idx];
+            for rcpt in self
+                .recipients
+                .iter()
+                .filter(|r| r.domain_idx == domain_idx)
+            {
+                let (status, response) = match (&rcpt.status, &domain.status) {
+                    (Status::Completed(response), _) => {
+                        (DeliveryStatus::Delivered, response.response.to_string())
+                    }
+                    (Status::PermanentFailure(err), _) => {
+                        (DeliveryStatus::Failed, err.response.to_string())
+                    }
+                    (Status::TemporaryFailure(err), _) => {
+                        (DeliveryStatus::Delayed, err.response.to_string())
+                    }
+                    (Status::Scheduled, Status::TemporaryFailure(err)) => {
+                        (DeliveryStatus::Delayed, err.to_string())
+                    }
+                    (Status::Scheduled, Status::PermanentFailure(err)) => {
+                        (DeliveryStatus::Failed, err.to_string())
+                    }
+                    (Status::Scheduled, _) => (DeliveryStatus::Queued, String::new()),
+                };
+                recipients.push(RecipientStatus {
+                    address: rcpt.address_lcase.clone(),
+                    status,
+                    response,
+                });
+            }
+        }
+
+        if !recipients.is_empty()
+            && delivery_tx
+                .send(DeliveryEvent::Status {
+                    queue_id: self.id,
+                    return_path: self.return_path_lcase.clone(),
+                    recipients,
+                })
+                .await
+                .is_err()
+        {
+            tracing::debug!(
+                parent: span,
+                context = "deliver_local",
+                event = "error",
+                reason = "tx channel closed",
+                "Failed to send delivery status update."
+            );
+        }
+    }
 }
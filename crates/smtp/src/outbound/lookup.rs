@@ -55,7 +55,7 @@ impl SMTP {
             IpLookupStrategy::Ipv6thenIpv4 => (true, true, false),
         };
         let ipv4_addrs = if has_ipv4 {
-            match self.resolvers.dns.ipv4_lookup(key).await {
+            match self.resolvers.ipv4_lookup(key).await {
                 Ok(addrs) => addrs,
                 Err(_) if has_ipv6 => Arc::new(Vec::new()),
                 Err(err) => return Err(err),
@@ -65,7 +65,7 @@ impl SMTP {
         };
 
         if has_ipv6 {
-            let ipv6_addrs = match self.resolvers.dns.ipv6_lookup(key).await {
+            let ipv6_addrs = match self.resolvers.ipv6_lookup(key).await {
                 Ok(addrs) => addrs,
                 Err(_) if !ipv4_addrs.is_empty() => Arc::new(Vec::new()),
                 Err(err) => return Err(err),
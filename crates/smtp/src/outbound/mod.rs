@@ -28,17 +28,20 @@ use smtp_proto::{Response, Severity};
 use utils::config::ServerProtocol;
 
 use crate::{
-    config::RelayHost,
+    config::{MandatoryTls, RelayHost},
     queue::{DeliveryAttempt, Error, ErrorDetails, HostResponse, Message, Status},
 };
 
+pub mod cache;
 pub mod dane;
 pub mod delivery;
+pub mod happy_eyeballs;
 #[cfg(feature = "local_delivery")]
 pub mod local;
 pub mod lookup;
 pub mod mta_sts;
 pub mod session;
+pub mod tls;
 
 impl Status<(), Error> {
     pub fn from_smtp_error(hostname: &str, command: &str, err: mail_send::Error) -> Self {
@@ -115,6 +118,47 @@ impl Status<(), Error> {
         }
     }
 
+    /// Rewrites TLS related failures for domains with a mandatory TLS policy so
+    /// that the DSN explains which policy could not be satisfied.
+    pub fn into_mandatory_tls(self, policy: MandatoryTls, domain: &str) -> Self {
+        let (err, is_temporary) = match &self {
+            _ if policy == MandatoryTls::Disabled => return self,
+            Status::TemporaryFailure(err) => (err, true),
+            Status::PermanentFailure(err) => (err, false),
+            Status::Scheduled | Status::Completed(_) => return self,
+        };
+        let (entity, details) = match err {
+            Error::TlsError(details) => (details.entity.clone(), details.details.clone()),
+            Error::DaneError(details) => (
+                details.entity.clone(),
+                format!("DANE authentication failed: {}", details.details),
+            ),
+            Error::MtaStsError(details) => (
+                domain.to_string(),
+                format!("MTA-STS authentication failed: {details}"),
+            ),
+            Error::UnexpectedResponse(response) if response.hostname.details == "STARTTLS" => (
+                response.hostname.entity.clone(),
+                format!("STARTTLS rejected: {}", response.response),
+            ),
+            _ => return self,
+        };
+
+        let err = Error::TlsError(ErrorDetails {
+            entity,
+            details: format!(
+                "Delivery requires {} but it could not be established ({})",
+                policy.description(),
+                details.trim_end_matches('.')
+            ),
+        });
+        if is_temporary {
+            Status::TemporaryFailure(err)
+        } else {
+            Status::PermanentFailure(err)
+        }
+    }
+
     pub fn from_tls_error(hostname: &str, err: mail_send::Error) -> Self {
         match err {
             mail_send::Error::InvalidTLSName => {
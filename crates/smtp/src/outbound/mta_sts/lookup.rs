@@ -32,7 +32,7 @@ pub static STS_TEST_POLICY: parking_lot::Mutex<Vec<u8>> = parking_lot::Mutex::ne
 
 use mail_auth::{common::lru::DnsCache, mta_sts::MtaSts, report::tlsrpt::ResultType};
 
-use crate::core::SMTP;
+use crate::{core::SMTP, outbound::cache::CachedRecord};
 
 use super::{Error, Policy};
 
@@ -55,6 +55,12 @@ impl SMTP {
                 // Return the cached policy in case of failure
                 return if let Some(value) = self.resolvers.cache.mta_sts.get(domain) {
                     Ok(value)
+                } else if let Some((policy, valid_until)) = self.persisted_policy(domain).await {
+                    Ok(self.resolvers.cache.mta_sts.insert(
+                        domain.to_string(),
+                        Arc::new(policy),
+                        valid_until,
+                    ))
                 } else {
                     Err(err.into())
                 };
@@ -66,6 +72,14 @@ impl SMTP {
             if value.id == record.id {
                 return Ok(value);
             }
+        } else if let Some((policy, valid_until)) = self.persisted_policy(domain).await {
+            if policy.id == record.id {
+                return Ok(self.resolvers.cache.mta_sts.insert(
+                    domain.to_string(),
+                    Arc::new(policy),
+                    valid_until,
+                ));
+            }
         }
 
         // Fetch policy
@@ -94,6 +108,16 @@ impl SMTP {
             } else {
                 86400
             });
+        if let Some(persist) = &self.resolvers.cache.persist {
+            persist
+                .set_until(
+                    "mta-sts",
+                    domain,
+                    &CachedRecord::MtaSts(policy.clone()),
+                    valid_until,
+                )
+                .await;
+        }
 
         Ok(self
             .resolvers
@@ -102,6 +126,20 @@ impl SMTP {
             .insert(domain.to_string(), Arc::new(policy), valid_until))
     }
 
+    async fn persisted_policy(&self, domain: &str) -> Option<(Policy, Instant)> {
+        match self
+            .resolvers
+            .cache
+            .persist
+            .as_ref()?
+            .get("mta-sts", domain)
+            .await?
+        {
+            (CachedRecord::MtaSts(policy), valid_until) => Some((policy, valid_until)),
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "test_mode")]
     pub fn policy_add<'x>(
         &self,
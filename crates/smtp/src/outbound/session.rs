@@ -23,9 +23,9 @@
 
 use mail_send::{smtp::AssertReply, Credentials, SmtpClient};
 use smtp_proto::{
-    EhloResponse, Response, Severity, EXT_CHUNKING, EXT_DSN, EXT_REQUIRE_TLS, EXT_SIZE,
-    EXT_SMTP_UTF8, EXT_START_TLS, MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8,
-    RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    EhloResponse, Response, Severity, EXT_CHUNKING, EXT_DSN, EXT_PIPELINING, EXT_REQUIRE_TLS,
+    EXT_SIZE, EXT_SMTP_UTF8, EXT_START_TLS, MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS,
+    MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
 use std::fmt::Write;
 use std::time::Duration;
@@ -38,6 +38,7 @@ use tokio_rustls::{client::TlsStream, TlsConnector};
 
 use crate::{
     config::{RequireOptional, TlsStrategy},
+    outbound::tls::into_tls,
     queue::{ErrorDetails, HostResponse, RCPT_STATUS_CHANGED},
 };
 
@@ -109,104 +110,196 @@ impl Message {
             };
         }
 
-        // MAIL FROM
-        smtp_client.timeout = params.timeout_mail;
-        let cmd = self.build_mail_from(&capabilities);
-        if let Err(err) = smtp_client
-            .cmd(cmd.as_bytes())
-            .await
-            .and_then(|r| r.assert_positive_completion())
-        {
-            tracing::info!(
-                parent: params.span,
-                context = "sender",
-                event = "rejected",
-                mx = &params.hostname,
-                reason = %err,
-            );
-            quit(smtp_client).await;
-            return Status::from_smtp_error(params.hostname, &cmd, err);
-        }
-
-        // RCPT TO
+        // Commands can be grouped into a single write when pipelining is available
+        let bdat_cmd = if capabilities.has_capability(EXT_CHUNKING) {
+            format!("BDAT {} LAST\r\n", self.size).into()
+        } else {
+            None
+        };
         let mut total_rcpt = 0;
         let mut total_completed = 0;
         let mut accepted_rcpts = Vec::new();
-        smtp_client.timeout = params.timeout_rcpt;
-        for rcpt in recipients {
-            total_rcpt += 1;
-            if matches!(
-                &rcpt.status,
-                Status::Completed(_) | Status::PermanentFailure(_)
-            ) {
-                total_completed += 1;
-                continue;
+        let mut is_data_pipelined = false;
+
+        if capabilities.has_capability(EXT_PIPELINING) {
+            let mail_from = self.build_mail_from(&capabilities);
+            let mut rcpts = Vec::new();
+            for rcpt in recipients {
+                total_rcpt += 1;
+                if matches!(
+                    &rcpt.status,
+                    Status::Completed(_) | Status::PermanentFailure(_)
+                ) {
+                    total_completed += 1;
+                    continue;
+                }
+                let cmd = self.build_rcpt_to(rcpt, &capabilities);
+                rcpts.push((rcpt, cmd));
             }
 
-            let cmd = self.build_rcpt_to(rcpt, &capabilities);
-            match smtp_client.cmd(cmd.as_bytes()).await {
-                Ok(response) => match response.severity() {
-                    Severity::PositiveCompletion => {
-                        accepted_rcpts.push((
-                            rcpt,
-                            Status::Completed(HostResponse {
-                                hostname: params.hostname.to_string(),
-                                response,
-                            }),
-                        ));
+            if !rcpts.is_empty() {
+                // DATA is the last command in a group (RFC 2920), BDAT is sent
+                // on its own to avoid transmitting the message to no recipients
+                is_data_pipelined = bdat_cmd.is_none();
+                let mut cmds = Vec::with_capacity(rcpts.len() + 2);
+                cmds.push(mail_from.as_bytes());
+                cmds.extend(rcpts.iter().map(|(_, cmd)| cmd.as_bytes()));
+                if is_data_pipelined {
+                    cmds.push(b"DATA\r\n");
+                }
+
+                smtp_client.timeout = params.timeout_rcpt;
+                let mut responses =
+                    match tokio::time::timeout(params.timeout_mail + params.timeout_rcpt, async {
+                        write_chunks(&mut smtp_client, &cmds).await?;
+                        smtp_client.read_many(cmds.len()).await
+                    })
+                    .await
+                    {
+                        Ok(Ok(responses)) => responses.into_iter(),
+                        Ok(Err(err)) => {
+                            tracing::info!(
+                                parent: params.span,
+                                context = "pipelining",
+                                event = "failed",
+                                mx = &params.hostname,
+                                reason = %err,
+                            );
+                            quit(smtp_client).await;
+                            return Status::from_smtp_error(params.hostname, &mail_from, err);
+                        }
+                        Err(_) => {
+                            quit(smtp_client).await;
+                            return Status::timeout(params.hostname, "reading pipelined responses");
+                        }
+                    };
+
+                // MAIL FROM
+                if let Err(err) = responses
+                    .next()
+                    .ok_or(mail_send::Error::UnparseableReply)
+                    .and_then(|r| r.assert_positive_completion())
+                {
+                    tracing::info!(
+                        parent: params.span,
+                        context = "sender",
+                        event = "rejected",
+                        mx = &params.hostname,
+                        reason = %err,
+                    );
+                    quit(smtp_client).await;
+                    return Status::from_smtp_error(params.hostname, &mail_from, err);
+                }
+
+                // RCPT TO
+                for ((rcpt, cmd), response) in rcpts.into_iter().zip(responses.by_ref()) {
+                    match rcpt_response(rcpt, &cmd, response, &params) {
+                        Some(status) => accepted_rcpts.push((rcpt, status)),
+                        None if matches!(rcpt.status, Status::PermanentFailure(_)) => {
+                            total_completed += 1;
+                        }
+                        None => (),
+                    }
+                }
+
+                // DATA
+                if let Some(response) = responses.next().filter(|_| is_data_pipelined) {
+                    if response.code() != 354 {
+                        if !accepted_rcpts.is_empty() {
+                            tracing::info!(
+                                parent: params.span,
+                                context = "message",
+                                event = "rejected",
+                                mx = &params.hostname,
+                                reason = %response,
+                            );
+                            quit(smtp_client).await;
+                            return Status::from_smtp_error(
+                                params.hostname,
+                                "DATA",
+                                mail_send::Error::UnexpectedReply(response),
+                            );
+                        }
+                    } else if accepted_rcpts.is_empty() {
+                        // Send an empty message, all recipients were rejected
+                        let _ = tokio::time::timeout(params.timeout_data, async {
+                            write_chunks(&mut smtp_client, &[b".\r\n"]).await?;
+                            smtp_client.read().await
+                        })
+                        .await;
                     }
-                    severity => {
+                }
+            }
+        } else {
+            // MAIL FROM
+            smtp_client.timeout = params.timeout_mail;
+            let cmd = self.build_mail_from(&capabilities);
+            if let Err(err) = smtp_client
+                .cmd(cmd.as_bytes())
+                .await
+                .and_then(|r| r.assert_positive_completion())
+            {
+                tracing::info!(
+                    parent: params.span,
+                    context = "sender",
+                    event = "rejected",
+                    mx = &params.hostname,
+                    reason = %err,
+                );
+                quit(smtp_client).await;
+                return Status::from_smtp_error(params.hostname, &cmd, err);
+            }
+
+            // RCPT TO
+            smtp_client.timeout = params.timeout_rcpt;
+            for rcpt in recipients {
+                total_rcpt += 1;
+                if matches!(
+                    &rcpt.status,
+                    Status::Completed(_) | Status::PermanentFailure(_)
+                ) {
+                    total_completed += 1;
+                    continue;
+                }
+
+                let cmd = self.build_rcpt_to(rcpt, &capabilities);
+                match smtp_client.cmd(cmd.as_bytes()).await {
+                    Ok(response) => match rcpt_response(rcpt, &cmd, response, &params) {
+                        Some(status) => accepted_rcpts.push((rcpt, status)),
+                        None if matches!(rcpt.status, Status::PermanentFailure(_)) => {
+                            total_completed += 1;
+                        }
+                        None => (),
+                    },
+                    Err(err) => {
                         tracing::info!(
                             parent: params.span,
                             context = "rcpt",
-                            event = "rejected",
-                            rcpt = rcpt.address,
+                            event = "failed",
                             mx = &params.hostname,
-                            reason = %response,
+                            rcpt = rcpt.address,
+                            reason = %err,
                         );
 
-                        let response = HostResponse {
-                            hostname: ErrorDetails {
-                                entity: params.hostname.to_string(),
-                                details: cmd.trim().to_string(),
-                            },
-                            response,
-                        };
-                        rcpt.flags |= RCPT_STATUS_CHANGED;
-                        rcpt.status = if severity == Severity::PermanentNegativeCompletion {
-                            total_completed += 1;
-                            Status::PermanentFailure(response)
-                        } else {
-                            Status::TemporaryFailure(response)
-                        };
+                        // Something went wrong, abort.
+                        quit(smtp_client).await;
+                        return Status::from_smtp_error(params.hostname, "", err);
                     }
-                },
-                Err(err) => {
-                    tracing::info!(
-                        parent: params.span,
-                        context = "rcpt",
-                        event = "failed",
-                        mx = &params.hostname,
-                        rcpt = rcpt.address,
-                        reason = %err,
-                    );
-
-                    // Something went wrong, abort.
-                    quit(smtp_client).await;
-                    return Status::from_smtp_error(params.hostname, "", err);
                 }
             }
         }
 
         // Send message
         if !accepted_rcpts.is_empty() {
-            let bdat_cmd = if capabilities.has_capability(EXT_CHUNKING) {
-                format!("BDAT {} LAST\r\n", self.size).into()
-            } else {
-                None
-            };
-
-            if let Err(status) = send_message(&mut smtp_client, self, &bdat_cmd, &params).await {
+            if let Err(status) = send_message(
+                &mut smtp_client,
+                self,
+                &bdat_cmd,
+                is_data_pipelined,
+                &params,
+            )
+            .await
+            {
                 tracing::info!(
                     parent: params.span,
                     context = "message",
@@ -426,6 +519,46 @@ impl Recipient {
     }
 }
 
+// Returns the delivery status of accepted recipients, rejected ones are updated in place
+fn rcpt_response(
+    rcpt: &mut Recipient,
+    cmd: &str,
+    response: Response<String>,
+    params: &SessionParams<'_>,
+) -> Option<Status<HostResponse<String>, HostResponse<ErrorDetails>>> {
+    match response.severity() {
+        Severity::PositiveCompletion => Some(Status::Completed(HostResponse {
+            hostname: params.hostname.to_string(),
+            response,
+        })),
+        severity => {
+            tracing::info!(
+                parent: params.span,
+                context = "rcpt",
+                event = "rejected",
+                rcpt = rcpt.address,
+                mx = &params.hostname,
+                reason = %response,
+            );
+
+            let response = HostResponse {
+                hostname: ErrorDetails {
+                    entity: params.hostname.to_string(),
+                    details: cmd.trim().to_string(),
+                },
+                response,
+            };
+            rcpt.flags |= RCPT_STATUS_CHANGED;
+            rcpt.status = if severity == Severity::PermanentNegativeCompletion {
+                Status::PermanentFailure(response)
+            } else {
+                Status::TemporaryFailure(response)
+            };
+            None
+        }
+    }
+}
+
 pub enum StartTlsResult {
     Success {
         smtp_client: SmtpClient<TlsStream<TcpStream>>,
@@ -449,7 +582,7 @@ pub async fn try_start_tls(
         match smtp_client.cmd("STARTTLS\r\n").await {
             Ok(response) => {
                 if response.code() == 220 {
-                    match smtp_client.into_tls(tls_connector, hostname).await {
+                    match into_tls(smtp_client, tls_connector, hostname).await {
                         Ok(smtp_client) => StartTlsResult::Success { smtp_client },
                         Err(error) => StartTlsResult::Error { error },
                     }
@@ -529,6 +662,7 @@ pub async fn send_message<T: AsyncRead + AsyncWrite + Unpin>(
     smtp_client: &mut SmtpClient<T>,
     message: &Message,
     bdat_cmd: &Option<String>,
+    is_data_pipelined: bool,
     params: &SessionParams<'_>,
 ) -> Result<(), Status<(), Error>> {
     let mut raw_message = vec![0u8; message.size];
@@ -555,8 +689,10 @@ pub async fn send_message<T: AsyncRead + AsyncWrite + Unpin>(
         if let Some(bdat_cmd) = bdat_cmd {
             write_chunks(smtp_client, &[bdat_cmd.as_bytes(), &raw_message]).await
         } else {
-            write_chunks(smtp_client, &[b"DATA\r\n"]).await?;
-            smtp_client.read().await?.assert_code(354)?;
+            if !is_data_pipelined {
+                write_chunks(smtp_client, &[b"DATA\r\n"]).await?;
+                smtp_client.read().await?.assert_code(354)?;
+            }
             smtp_client
                 .write_message(&raw_message)
                 .await
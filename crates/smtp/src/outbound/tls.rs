@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use mail_send::SmtpClient;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        Resumption, WebPkiServerVerifier,
+    },
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use rustls_pki_types::{CertificateDer, ServerName, TrustAnchor, UnixTime};
+use tokio::net::TcpStream;
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use utils::{config::Config, stats::ServerStats, DummyVerifier};
+
+use crate::core::TlsConnectors;
+
+tokio::task_local! {
+    // Set when the certificate chain is verified, which only happens on full handshakes
+    static FULL_HANDSHAKE: Arc<AtomicBool>;
+}
+
+impl TlsConnectors {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        Ok(TlsConnectors::new(config.property_or_static::<usize>(
+            "queue.outbound.tls.session-cache",
+            "1024",
+        )?))
+    }
+
+    /// Each connector keeps its own session cache, keyed by hostname, so that
+    /// sessions established without certificate verification are never resumed
+    /// by the verifying connector.
+    pub fn new(session_cache: usize) -> Self {
+        TlsConnectors {
+            pki_verify: build_connector(false, session_cache),
+            dummy_verify: build_connector(true, session_cache),
+        }
+    }
+}
+
+/// Upgrades the connection to TLS, resuming a cached session when available.
+pub async fn into_tls(
+    smtp_client: SmtpClient<TcpStream>,
+    tls_connector: &TlsConnector,
+    hostname: &str,
+) -> Result<SmtpClient<TlsStream<TcpStream>>, mail_send::Error> {
+    let is_full_handshake = Arc::new(AtomicBool::new(false));
+    let smtp_client = FULL_HANDSHAKE
+        .scope(
+            is_full_handshake.clone(),
+            smtp_client.into_tls(tls_connector, hostname),
+        )
+        .await?;
+    let is_resumed = !is_full_handshake.load(Ordering::Relaxed);
+    ServerStats::get().record_tls_handshake(is_resumed);
+    tracing::trace!(
+        context = "tls",
+        event = "handshake",
+        mx = hostname,
+        resumed = is_resumed,
+    );
+
+    Ok(smtp_client)
+}
+
+fn build_connector(allow_invalid_certs: bool, session_cache: usize) -> TlsConnector {
+    let verifier: Arc<dyn ServerCertVerifier> = if !allow_invalid_certs {
+        let mut root_cert_store = RootCertStore::empty();
+        root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| TrustAnchor {
+            subject: ta.subject.clone(),
+            subject_public_key_info: ta.subject_public_key_info.clone(),
+            name_constraints: ta.name_constraints.clone(),
+        }));
+        WebPkiServerVerifier::builder(Arc::new(root_cert_store))
+            .build()
+            .expect("Failed to build certificate verifier")
+    } else {
+        Arc::new(DummyVerifier)
+    };
+
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(HandshakeVerifier { inner: verifier }))
+        .with_no_client_auth();
+    config.resumption = if session_cache > 0 {
+        Resumption::in_memory_sessions(session_cache)
+    } else {
+        Resumption::disabled()
+    };
+
+    TlsConnector::from(Arc::new(config))
+}
+
+#[derive(Debug)]
+struct HandshakeVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+}
+
+impl ServerCertVerifier for HandshakeVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let _ = FULL_HANDSHAKE.try_with(|is_full_handshake| {
+            is_full_handshake.store(true, Ordering::Relaxed);
+        });
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
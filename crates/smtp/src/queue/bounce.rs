@@ -0,0 +1,348 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Display;
+
+use dashmap::DashMap;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use smtp_proto::Response;
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use super::{Error, HostResponse, Status};
+
+pub struct BounceClassifier {
+    rules: Vec<BounceRule>,
+    stats: DashMap<(String, BounceClass), u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BounceClass {
+    #[serde(rename = "user-unknown")]
+    UserUnknown,
+    #[serde(rename = "mailbox-full")]
+    MailboxFull,
+    #[serde(rename = "policy-block")]
+    PolicyBlock,
+    #[serde(rename = "greylisted")]
+    Greylisted,
+    #[serde(rename = "other")]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BounceStats {
+    pub domain: String,
+    pub class: BounceClass,
+    pub count: u64,
+}
+
+struct BounceRule {
+    class: BounceClass,
+    code: Option<[Option<u8>; 3]>,
+    status: Option<[Option<u8>; 3]>,
+    message: Option<Regex>,
+}
+
+impl BounceClassifier {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let mut rules = Vec::new();
+        for id in config.sub_keys("queue.bounce.rule") {
+            let rule = BounceRule {
+                class: config.property_require(("queue.bounce.rule", id, "class"))?,
+                code: config
+                    .value(("queue.bounce.rule", id, "code"))
+                    .map(|code| {
+                        parse_pattern(code, "").ok_or_else(|| {
+                            format!("Invalid reply code pattern {code:?} for bounce rule {id:?}.")
+                        })
+                    })
+                    .transpose()?,
+                status: config
+                    .value(("queue.bounce.rule", id, "status"))
+                    .map(|status| {
+                        parse_pattern(status, ".").ok_or_else(|| {
+                            format!(
+                                "Invalid status code pattern {status:?} for bounce rule {id:?}."
+                            )
+                        })
+                    })
+                    .transpose()?,
+                message: config
+                    .value(("queue.bounce.rule", id, "message"))
+                    .map(|message| {
+                        RegexBuilder::new(message)
+                            .case_insensitive(true)
+                            .build()
+                            .map_err(|err| {
+                                format!("Invalid message pattern for bounce rule {id:?}: {err}")
+                            })
+                    })
+                    .transpose()?,
+            };
+            if rule.code.is_none() && rule.status.is_none() && rule.message.is_none() {
+                return Err(format!(
+                    "Bounce rule {id:?} must specify a code, status or message pattern."
+                ));
+            }
+            rules.push(rule);
+        }
+        rules.extend(default_rules());
+
+        Ok(BounceClassifier {
+            rules,
+            stats: DashMap::new(),
+        })
+    }
+
+    pub fn new() -> Self {
+        BounceClassifier {
+            rules: default_rules(),
+            stats: DashMap::new(),
+        }
+    }
+
+    pub fn classify(&self, response: &Response<String>) -> BounceClass {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(response))
+            .map_or(BounceClass::Other, |rule| rule.class)
+    }
+
+    pub fn classify_status<T, E: AsResponse>(&self, status: &Status<T, E>) -> Option<BounceClass> {
+        match status {
+            Status::TemporaryFailure(err) | Status::PermanentFailure(err) => {
+                err.as_response().map(|response| self.classify(response))
+            }
+            Status::Scheduled | Status::Completed(_) => None,
+        }
+    }
+
+    pub fn record(&self, domain: &str, class: BounceClass) {
+        *self
+            .stats
+            .entry((domain.to_lowercase(), class))
+            .or_insert(0) += 1;
+    }
+
+    pub fn stats(&self, domain: Option<&str>) -> Vec<BounceStats> {
+        let mut stats = self
+            .stats
+            .iter()
+            .filter(|entry| {
+                domain.map_or(true, |domain| entry.key().0.eq_ignore_ascii_case(domain))
+            })
+            .map(|entry| BounceStats {
+                domain: entry.key().0.clone(),
+                class: entry.key().1,
+                count: *entry.value(),
+            })
+            .collect::<Vec<_>>();
+        stats.sort_unstable_by(|a, b| (&a.domain, a.class).cmp(&(&b.domain, b.class)));
+        stats
+    }
+
+    pub fn clear(&self) {
+        self.stats.clear();
+    }
+}
+
+impl Default for BounceClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BounceRule {
+    fn new(
+        class: BounceClass,
+        code: Option<&str>,
+        status: Option<&str>,
+        message: Option<&str>,
+    ) -> Self {
+        BounceRule {
+            class,
+            code: code.and_then(|code| parse_pattern(code, "")),
+            status: status.and_then(|status| parse_pattern(status, ".")),
+            message: message.map(|message| {
+                RegexBuilder::new(message)
+                    .case_insensitive(true)
+                    .build()
+                    .unwrap()
+            }),
+        }
+    }
+
+    fn matches(&self, response: &Response<String>) -> bool {
+        self.code.map_or(true, |code| {
+            let digits = [
+                (response.code / 100) as u8,
+                ((response.code / 10) % 10) as u8,
+                (response.code % 10) as u8,
+            ];
+            pattern_matches(&code, &digits)
+        }) && self
+            .status
+            .map_or(true, |status| pattern_matches(&status, &response.esc))
+            && self
+                .message
+                .as_ref()
+                .map_or(true, |message| message.is_match(&response.message))
+    }
+}
+
+fn default_rules() -> Vec<BounceRule> {
+    vec![
+        // Greylisting is always temporary
+        BounceRule::new(
+            BounceClass::Greylisted,
+            Some("4xx"),
+            None,
+            Some(r"gr[ae]y[ -]?list"),
+        ),
+        // Classify using enhanced status codes
+        BounceRule::new(BounceClass::MailboxFull, None, Some("*.2.2"), None),
+        BounceRule::new(BounceClass::UserUnknown, None, Some("5.1.*"), None),
+        BounceRule::new(BounceClass::UserUnknown, None, Some("5.2.1"), None),
+        BounceRule::new(
+            BounceClass::Greylisted,
+            None,
+            Some("4.7.1"),
+            Some(r"try again|retry"),
+        ),
+        BounceRule::new(BounceClass::PolicyBlock, None, Some("*.7.*"), None),
+        // Classify using the reply text
+        BounceRule::new(
+            BounceClass::MailboxFull,
+            None,
+            None,
+            Some(r"mailbox (is )?full|over ?quota|quota exceeded|insufficient (system )?storage"),
+        ),
+        BounceRule::new(
+            BounceClass::UserUnknown,
+            None,
+            None,
+            Some(concat!(
+                r"user unknown|unknown user|no such (user|recipient|mailbox)|does not exist|",
+                r"invalid (recipient|mailbox)|mailbox (unavailable|not found|disabled)"
+            )),
+        ),
+        BounceRule::new(
+            BounceClass::PolicyBlock,
+            None,
+            None,
+            Some(r"spam|blocked|block ?list|black ?list|reputation|policy|\b(dnsbl|rbl)\b"),
+        ),
+        // Servers that do not support enhanced status codes
+        BounceRule::new(BounceClass::UserUnknown, Some("550"), Some("0.0.0"), None),
+        BounceRule::new(BounceClass::UserUnknown, Some("551"), Some("0.0.0"), None),
+        BounceRule::new(BounceClass::UserUnknown, Some("553"), Some("0.0.0"), None),
+        BounceRule::new(BounceClass::MailboxFull, Some("552"), Some("0.0.0"), None),
+    ]
+}
+
+fn parse_pattern(value: &str, separator: &str) -> Option<[Option<u8>; 3]> {
+    let parts = if separator.is_empty() {
+        value
+            .trim()
+            .chars()
+            .map(|ch| ch.to_string())
+            .collect::<Vec<_>>()
+    } else {
+        value
+            .trim()
+            .split(separator)
+            .map(|part| part.to_string())
+            .collect::<Vec<_>>()
+    };
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let mut pattern = [None; 3];
+    for (pos, part) in parts.iter().enumerate() {
+        pattern[pos] = match part.as_str() {
+            "*" | "x" | "X" => None,
+            part => Some(part.parse::<u8>().ok()?),
+        };
+    }
+    Some(pattern)
+}
+
+fn pattern_matches(pattern: &[Option<u8>; 3], value: &[u8; 3]) -> bool {
+    pattern
+        .iter()
+        .zip(value.iter())
+        .all(|(pattern, value)| pattern.map_or(true, |pattern| pattern == *value))
+}
+
+pub trait AsResponse {
+    fn as_response(&self) -> Option<&Response<String>>;
+}
+
+impl<T> AsResponse for HostResponse<T> {
+    fn as_response(&self) -> Option<&Response<String>> {
+        Some(&self.response)
+    }
+}
+
+impl AsResponse for Error {
+    fn as_response(&self) -> Option<&Response<String>> {
+        match self {
+            Error::UnexpectedResponse(response) => Some(&response.response),
+            _ => None,
+        }
+    }
+}
+
+impl ParseValue for BounceClass {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        match value {
+            "user-unknown" => Ok(BounceClass::UserUnknown),
+            "mailbox-full" => Ok(BounceClass::MailboxFull),
+            "policy-block" => Ok(BounceClass::PolicyBlock),
+            "greylisted" => Ok(BounceClass::Greylisted),
+            "other" => Ok(BounceClass::Other),
+            _ => Err(format!(
+                "Invalid bounce class {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl Display for BounceClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BounceClass::UserUnknown => "user-unknown",
+            BounceClass::MailboxFull => "mailbox-full",
+            BounceClass::PolicyBlock => "policy-block",
+            BounceClass::Greylisted => "greylisted",
+            BounceClass::Other => "other",
+        })
+    }
+}
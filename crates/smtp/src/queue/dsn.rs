@@ -258,7 +258,7 @@ impl DeliveryAttempt {
         let mut dsn_header = String::with_capacity(dsn.len() + 128);
         self.message
             .write_dsn_headers(&mut dsn_header, reporting_mta);
-        let dsn = dsn_header + &dsn;
+        let dsn = dsn_header + dsn.as_str();
 
         // Fetch up to 1024 bytes of message headers
         let headers = match File::open(&self.message.path).await {
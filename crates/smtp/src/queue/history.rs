@@ -0,0 +1,341 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use dashmap::DashMap;
+use mail_parser::DateTime;
+use serde::Serialize;
+use utils::config::Config;
+
+use crate::core::management::serialize_datetime;
+
+use super::{
+    bounce::{BounceClass, BounceClassifier},
+    Domain, Message, QueueId, Recipient, Status,
+};
+
+const PURGE_INTERVAL: u64 = 3600;
+
+/// Delivery history is kept in memory on the node that handled each message
+/// and is lost on restart. It is meant for troubleshooting deliveries, not as
+/// an audit log, and is capped in both messages and events per message.
+pub struct DeliveryHistory {
+    retention: Option<Duration>,
+    max_messages: usize,
+    max_events: usize,
+    entries: DashMap<QueueId, MessageHistory>,
+    message_ids: DashMap<String, Vec<QueueId>>,
+    last_purge: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageHistory {
+    pub id: QueueId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    pub events: Vec<HistoryEvent>,
+    #[serde(skip)]
+    updated: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEvent {
+    #[serde(serialize_with = "serialize_datetime")]
+    pub timestamp: DateTime,
+    #[serde(flatten)]
+    pub details: HistoryDetails,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum HistoryDetails {
+    #[serde(rename = "received")]
+    Received {
+        listener: String,
+        remote_ip: IpAddr,
+        #[serde(skip_serializing_if = "String::is_empty")]
+        authenticated_as: String,
+        return_path: String,
+        recipients: Vec<String>,
+    },
+    #[serde(rename = "filter")]
+    Filter { filter: String, result: String },
+    #[serde(rename = "queued")]
+    Queued { size: usize, recipients: usize },
+    #[serde(rename = "attempt")]
+    Attempt {
+        domain: String,
+        status: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        classification: Option<BounceClass>,
+        recipients: Vec<RecipientAttempt>,
+    },
+    #[serde(rename = "completed")]
+    Completed { disposition: Disposition },
+    #[serde(rename = "complaint")]
+    Complaint {
+        feedback_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reporting_mta: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source_ip: Option<IpAddr>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        recipient: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientAttempt {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classification: Option<BounceClass>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Disposition {
+    #[serde(rename = "delivered")]
+    Delivered,
+    #[serde(rename = "partial")]
+    Partial,
+    #[serde(rename = "failed")]
+    Failed,
+    #[serde(rename = "cancelled")]
+    Cancelled,
+}
+
+impl DeliveryHistory {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let mut history = DeliveryHistory::new(
+            if config.property_or_static::<bool>("queue.history.enable", "true")? {
+                Some(config.property_or_static("queue.history.retention", "7d")?)
+            } else {
+                None
+            },
+        );
+        history.max_messages =
+            config.property_or_static("queue.history.limits.messages", "100000")?;
+        history.max_events = config.property_or_static("queue.history.limits.events", "100")?;
+        Ok(history)
+    }
+
+    pub fn new(retention: Option<Duration>) -> Self {
+        DeliveryHistory {
+            retention,
+            max_messages: 100000,
+            max_events: 100,
+            entries: DashMap::new(),
+            message_ids: DashMap::new(),
+            last_purge: AtomicU64::new(now()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.retention.is_some()
+    }
+
+    pub fn record(&self, queue_id: QueueId, details: HistoryDetails) {
+        self.record_all(queue_id, [details]);
+    }
+
+    pub fn record_all(&self, queue_id: QueueId, details: impl IntoIterator<Item = HistoryDetails>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = now();
+        if !self.entries.contains_key(&queue_id) && self.entries.len() >= self.max_messages {
+            self.evict();
+        }
+
+        let timestamp = DateTime::from_timestamp(now as i64);
+        let mut entry = self
+            .entries
+            .entry(queue_id)
+            .or_insert_with(|| MessageHistory {
+                id: queue_id,
+                message_id: None,
+                events: Vec::new(),
+                updated: now,
+            });
+        entry.updated = now;
+        entry.events.extend(
+            details
+                .into_iter()
+                .map(|details| HistoryEvent { timestamp, details }),
+        );
+
+        // Keep the first event, which records how the message was received
+        if entry.events.len() > self.max_events {
+            let excess = entry.events.len() - self.max_events;
+            entry.events.drain(1..excess + 1);
+        }
+        drop(entry);
+
+        self.purge(now);
+    }
+
+    pub fn set_message_id(&self, queue_id: QueueId, message_id: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let message_id = message_id
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        if !message_id.is_empty() {
+            if let Some(mut entry) = self.entries.get_mut(&queue_id) {
+                entry.message_id = message_id.to_string().into();
+            }
+            self.message_ids
+                .entry(message_id.to_string())
+                .or_default()
+                .push(queue_id);
+        }
+    }
+
+    pub fn get(&self, queue_id: QueueId) -> Option<MessageHistory> {
+        self.entries.get(&queue_id).map(|entry| entry.clone())
+    }
+
+    pub fn get_by_message_id(&self, message_id: &str) -> Vec<MessageHistory> {
+        let message_id = message_id
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        self.message_ids
+            .get(message_id)
+            .map(|ids| ids.iter().filter_map(|id| self.get(*id)).collect())
+            .unwrap_or_default()
+    }
+
+    fn purge(&self, now: u64) {
+        let last_purge = self.last_purge.load(Ordering::Relaxed);
+        if now < last_purge + PURGE_INTERVAL
+            || self
+                .last_purge
+                .compare_exchange(last_purge, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+
+        let retention = self.retention.map_or(0, |r| r.as_secs());
+        self.entries
+            .retain(|_, entry| entry.updated + retention > now);
+        self.purge_message_ids();
+    }
+
+    // Removes the least recently updated tenth of the history
+    fn evict(&self) {
+        let mut entries = self
+            .entries
+            .iter()
+            .map(|entry| (entry.updated, entry.id))
+            .collect::<Vec<_>>();
+        let num_evict = (entries.len() / 10).max(1).min(entries.len());
+        if num_evict > 0 {
+            entries.select_nth_unstable(num_evict - 1);
+            for (_, id) in &entries[..num_evict] {
+                self.entries.remove(id);
+            }
+            self.purge_message_ids();
+        }
+    }
+
+    fn purge_message_ids(&self) {
+        self.message_ids.retain(|_, ids| {
+            ids.retain(|id| self.entries.contains_key(id));
+            !ids.is_empty()
+        });
+    }
+}
+
+impl HistoryDetails {
+    pub fn filter(filter: impl Into<String>, result: impl Into<String>) -> Self {
+        HistoryDetails::Filter {
+            filter: filter.into(),
+            result: result.into(),
+        }
+    }
+
+    pub fn attempt<'x>(
+        domain: &Domain,
+        recipients: impl Iterator<Item = &'x Recipient>,
+        classifier: &BounceClassifier,
+    ) -> Self {
+        HistoryDetails::Attempt {
+            domain: domain.domain.clone(),
+            status: domain.status.to_string(),
+            classification: classifier.classify_status(&domain.status),
+            recipients: recipients
+                .map(|rcpt| RecipientAttempt {
+                    address: rcpt.address.clone(),
+                    host: match &rcpt.status {
+                        Status::Completed(response) => response.hostname.clone().into(),
+                        Status::TemporaryFailure(response) | Status::PermanentFailure(response) => {
+                            response.hostname.entity.clone().into()
+                        }
+                        Status::Scheduled => None,
+                    }
+                    .filter(|host: &String| !host.is_empty()),
+                    status: rcpt.status.to_string(),
+                    classification: classifier.classify_status(&rcpt.status),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn completed(message: &Message) -> Self {
+        let total = message.recipients.len();
+        let delivered = message
+            .recipients
+            .iter()
+            .filter(|rcpt| matches!(rcpt.status, Status::Completed(_)))
+            .count();
+
+        HistoryDetails::Completed {
+            disposition: if delivered == total {
+                Disposition::Delivered
+            } else if delivered > 0 {
+                Disposition::Partial
+            } else {
+                Disposition::Failed
+            },
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
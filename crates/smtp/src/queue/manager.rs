@@ -23,6 +23,7 @@
 
 use std::{
     collections::BinaryHeap,
+    path::Path,
     sync::{atomic::Ordering, Arc},
     time::{Duration, Instant},
 };
@@ -37,6 +38,8 @@ use crate::core::{
 };
 
 use super::{
+    history::{Disposition, HistoryDetails},
+    spool::SPOOL_TEMP_EXT,
     DeliveryAttempt, Event, HostResponse, Message, OnHold, QueueId, Schedule, Status, WorkerResult,
     RCPT_STATUS_CHANGED,
 };
@@ -228,21 +231,39 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                                             | Status::Scheduled
                                                     )
                                                 }) {
-                                                    message.save_changes().await;
+                                                    core.queue.save_message(message).await;
                                                 } else {
-                                                    message.remove().await;
+                                                    core.queue.history.record(
+                                                        *queue_id,
+                                                        HistoryDetails::Completed {
+                                                            disposition: Disposition::Cancelled,
+                                                        },
+                                                    );
+                                                    core.queue.remove_message(message).await;
                                                     queue.messages.remove(queue_id);
                                                 }
                                             }
                                         }
                                     } else if let Some(message) = queue.messages.remove(queue_id) {
-                                        message.remove().await;
+                                        core.queue.history.record(
+                                            *queue_id,
+                                            HistoryDetails::Completed {
+                                                disposition: Disposition::Cancelled,
+                                            },
+                                        );
+                                        core.queue.remove_message(&message).await;
                                         found = true;
                                     }
                                     result.push(found);
                                 }
                                 let _ = result_tx.send(result);
                             }
+                            management::QueueRequest::Stats { result_tx } => {
+                                let _ = result_tx.send(queue.stats());
+                            }
+                            management::QueueRequest::Overdue { before, result_tx } => {
+                                let _ = result_tx.send(queue.overdue(before));
+                            }
                             management::QueueRequest::Retry {
                                 queue_ids,
                                 item,
@@ -272,7 +293,7 @@ impl SpawnQueue for mpsc::Receiver<Event> {
 
                                         if found {
                                             queue.on_hold.retain(|oh| &oh.message != queue_id);
-                                            message.save_changes().await;
+                                            core.queue.save_message(message).await;
                                             if let Some(next_event) = message.next_event() {
                                                 queue.scheduled.push(Schedule {
                                                     due: next_event,
@@ -286,6 +307,9 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                 let _ = result_tx.send(result);
                             }
                         },
+                        Event::Ping(result_tx) => {
+                            let _ = result_tx.send(());
+                        }
                         Event::Stop => break,
                     },
                     Ok(None) => break,
@@ -338,6 +362,57 @@ impl Queue {
             .and_then(|pos| self.messages.remove(&self.on_hold.remove(pos).message))
     }
 
+    pub fn stats(&self) -> management::QueueStats {
+        let mut stats = management::QueueStats::default();
+        for message in self.messages.values() {
+            if self.on_hold.iter().any(|oh| oh.message == message.id) {
+                stats.on_hold += 1;
+            } else if message
+                .domains
+                .iter()
+                .any(|domain| matches!(domain.status, Status::TemporaryFailure(_)))
+            {
+                stats.deferred += 1;
+            } else {
+                stats.scheduled += 1;
+            }
+            if stats
+                .oldest_created
+                .map_or(true, |created| message.created < created)
+            {
+                stats.oldest_created = message.created.into();
+            }
+        }
+        stats
+    }
+
+    // Due messages are delivered as soon as the manager wakes up, so only messages
+    // waiting for a concurrency slot or left behind by a stalled loop can be overdue.
+    pub fn overdue(&self, before: Instant) -> usize {
+        let mut overdue = self
+            .on_hold
+            .iter()
+            .filter(|on_hold| {
+                self.messages
+                    .get(&on_hold.message)
+                    .and_then(|message| message.next_event())
+                    .map_or(false, |due| due < before)
+            })
+            .count();
+        if self
+            .scheduled
+            .peek()
+            .map_or(false, |item| item.due < before)
+        {
+            overdue += self
+                .scheduled
+                .iter()
+                .filter(|item| item.due < before)
+                .count();
+        }
+        overdue
+    }
+
     pub fn wake_up_time(&self) -> Duration {
         self.scheduled
             .peek()
@@ -432,6 +507,23 @@ impl Message {
 }
 
 impl QueueCore {
+    pub async fn checkpoint(&self, deadline: Instant) {
+        // Stop scheduling deliveries and wait for active workers to save their state
+        let _ = self.tx.send(Event::Stop).await;
+        while (!self.tx.is_closed() || self.workers.is_active()) && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        if self.workers.is_active() {
+            tracing::warn!(
+                context = "queue",
+                event = "shutdown",
+                active_workers = self.workers.concurrent.load(Ordering::Relaxed),
+                "Drain timeout reached with deliveries in progress."
+            );
+        }
+    }
+
     pub async fn read_queue(&self) -> Queue {
         let mut queue = Queue::default();
         let mut messages = Vec::new();
@@ -464,6 +556,8 @@ impl QueueCore {
                                                     messages.push(tokio::spawn(
                                                         Message::from_path(file),
                                                     ));
+                                                } else if is_spool_temp(&file) {
+                                                    remove_spool_temp(&file).await;
                                                 }
                                             }
                                             Ok(None) => break,
@@ -488,6 +582,8 @@ impl QueueCore {
                             };
                         } else if file.extension().map_or(false, |e| e == "msg") {
                             messages.push(tokio::spawn(Message::from_path(file)));
+                        } else if is_spool_temp(&file) {
+                            remove_spool_temp(&file).await;
                         }
                     }
                     Ok(None) => {
@@ -509,6 +605,19 @@ impl QueueCore {
         for message in messages {
             match message.await {
                 Ok(Ok(mut message)) => {
+                    // Drop messages that another node took over while this one was down
+                    if let Ok(false) = self.is_owner(&message).await {
+                        tracing::info!(
+                            context = "queue",
+                            event = "fenced",
+                            id = message.id,
+                            "Removing message {} now owned by another node.",
+                            message.path.display()
+                        );
+                        message.remove().await;
+                        continue;
+                    }
+
                     // Reserve quota
                     self.has_quota(&mut message).await;
 
@@ -544,6 +653,34 @@ impl QueueCore {
     }
 }
 
+fn is_spool_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            name.strip_suffix(SPOOL_TEMP_EXT)
+                .map_or(false, |name| name.ends_with('.'))
+        })
+}
+
+// Temporary files are messages that were never acknowledged to the sender
+async fn remove_spool_temp(path: &Path) {
+    tracing::info!(
+        context = "queue",
+        event = "cleanup",
+        "Removing incomplete queue file {}",
+        path.display()
+    );
+    if let Err(err) = tokio::fs::remove_file(path).await {
+        tracing::warn!(
+            context = "queue",
+            event = "error",
+            "Failed to remove incomplete queue file {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
 impl Default for Queue {
     fn default() -> Self {
         Queue {
@@ -31,6 +31,7 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 use smtp_proto::Response;
+use tokio::sync::oneshot;
 use utils::{
     config::KeyLookup,
     listener::limiter::{ConcurrencyLimiter, InFlight},
@@ -38,12 +39,20 @@ use utils::{
 
 use crate::{config::EnvelopeKey, core::management};
 
+pub mod bounce;
 pub mod dsn;
+pub mod history;
 pub mod manager;
+pub mod quarantine;
 pub mod quota;
 pub mod serialize;
+pub mod shared;
 pub mod spool;
+pub mod stream;
+pub mod suppression;
 pub mod throttle;
+pub mod tuning;
+pub mod warmup;
 
 pub type QueueId = u64;
 
@@ -52,6 +61,7 @@ pub enum Event {
     Queue(Schedule<Box<Message>>),
     Manage(management::QueueRequest),
     Done(WorkerResult),
+    Ping(oneshot::Sender<()>),
     Stop,
 }
 
@@ -116,6 +126,7 @@ pub struct Recipient {
     pub orcpt: Option<String>,
 }
 
+pub const MAIL_NOTIFY_STATUS: u64 = 1 << 32;
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
 
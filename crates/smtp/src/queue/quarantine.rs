@@ -0,0 +1,356 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::VecDeque, io::Write, path::PathBuf, time::SystemTime};
+
+use dashmap::DashMap;
+use mail_parser::DateTime;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use utils::config::Config;
+
+use crate::{
+    config::DlpAction,
+    core::{
+        management::{deserialize_datetime, serialize_datetime},
+        QueueCore,
+    },
+};
+
+use super::{DomainPart, Message, QueueId};
+
+pub struct Quarantine {
+    path: Option<PathBuf>,
+    max_incidents: usize,
+    entries: DashMap<QueueId, QuarantineEntry>,
+    incidents: Mutex<VecDeque<DlpIncident>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: QueueId,
+    pub rule: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub authenticated_as: String,
+    pub size: usize,
+    #[serde(serialize_with = "serialize_datetime")]
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub created: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlpIncident {
+    #[serde(serialize_with = "serialize_datetime")]
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub timestamp: DateTime,
+    pub rule: String,
+    pub action: DlpAction,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub authenticated_as: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_id: Option<QueueId>,
+}
+
+impl Quarantine {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let quarantine = Quarantine {
+            path: config.property("queue.quarantine.path")?,
+            max_incidents: config.property_or_static("queue.quarantine.max-incidents", "1000")?,
+            entries: DashMap::new(),
+            incidents: Mutex::new(VecDeque::new()),
+        };
+        quarantine.load();
+        Ok(quarantine)
+    }
+
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Quarantine {
+            path,
+            max_incidents: 1000,
+            entries: DashMap::new(),
+            incidents: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub async fn store(
+        &self,
+        message: &Message,
+        rule: &str,
+        authenticated_as: &str,
+        raw_headers: &[u8],
+        raw_message: &[u8],
+    ) -> bool {
+        let path = if let Some(path) = &self.path {
+            path
+        } else {
+            return false;
+        };
+        let entry = QuarantineEntry {
+            id: message.id,
+            rule: rule.to_string(),
+            return_path: message.return_path.clone(),
+            recipients: message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address.clone())
+                .collect(),
+            authenticated_as: authenticated_as.to_string(),
+            size: message.size,
+            created: DateTime::from_timestamp(now() as i64),
+        };
+
+        let mut contents = Vec::with_capacity(raw_headers.len() + raw_message.len());
+        contents.extend_from_slice(raw_headers);
+        contents.extend_from_slice(raw_message);
+        let result = match serde_json::to_vec(&entry) {
+            Ok(metadata) => match fs::create_dir_all(path).await {
+                Ok(_) => match fs::write(path.join(format!("{}.eml", entry.id)), contents).await {
+                    Ok(_) => fs::write(path.join(format!("{}.json", entry.id)), metadata)
+                        .await
+                        .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                },
+                Err(err) => Err(err.to_string()),
+            },
+            Err(err) => Err(err.to_string()),
+        };
+
+        match result {
+            Ok(_) => {
+                self.entries.insert(entry.id, entry);
+                true
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "quarantine",
+                    event = "error",
+                    path = %path.display(),
+                    "Failed to quarantine message: {}",
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<QuarantineEntry> {
+        let mut entries = self
+            .entries
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|entry| entry.id);
+        entries
+    }
+
+    pub async fn take(&self, id: QueueId) -> Option<(QuarantineEntry, Vec<u8>)> {
+        let path = self.path.as_ref()?;
+        let (_, entry) = self.entries.remove(&id)?;
+        let eml_path = path.join(format!("{id}.eml"));
+        match fs::read(&eml_path).await {
+            Ok(contents) => {
+                self.remove_files(id).await;
+                Some((entry, contents))
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "quarantine",
+                    event = "error",
+                    path = %eml_path.display(),
+                    "Failed to read quarantined message: {}",
+                    err
+                );
+                self.entries.insert(id, entry);
+                None
+            }
+        }
+    }
+
+    pub async fn delete(&self, id: QueueId) -> bool {
+        if self.entries.remove(&id).is_some() {
+            self.remove_files(id).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    async fn remove_files(&self, id: QueueId) {
+        if let Some(path) = &self.path {
+            for ext in ["eml", "json"] {
+                let _ = fs::remove_file(path.join(format!("{id}.{ext}"))).await;
+            }
+        }
+    }
+
+    pub fn record_incident(&self, incident: DlpIncident) {
+        tracing::info!(
+            context = "dlp",
+            event = "incident",
+            rule = &incident.rule,
+            action = ?incident.action,
+            return_path = &incident.return_path,
+            authenticated_as = &incident.authenticated_as,
+            "Outbound message matched a data loss prevention rule."
+        );
+
+        // Append the incident to the log
+        if let Some(path) = &self.path {
+            let result = serde_json::to_vec(&incident)
+                .map_err(|err| err.to_string())
+                .and_then(|mut bytes| {
+                    bytes.push(b'\n');
+                    std::fs::create_dir_all(path)
+                        .and_then(|_| {
+                            std::fs::OpenOptions::new()
+                                .create(true)
+                                .append(true)
+                                .open(path.join("incidents.log"))
+                        })
+                        .and_then(|mut file| file.write_all(&bytes))
+                        .map_err(|err| err.to_string())
+                });
+            if let Err(err) = result {
+                tracing::warn!(
+                    context = "dlp",
+                    event = "error",
+                    path = %path.display(),
+                    "Failed to write incident log: {}",
+                    err
+                );
+            }
+        }
+
+        let mut incidents = self.incidents.lock();
+        incidents.push_back(incident);
+        while incidents.len() > self.max_incidents {
+            incidents.pop_front();
+        }
+    }
+
+    pub fn incidents(&self) -> Vec<DlpIncident> {
+        self.incidents.lock().iter().rev().cloned().collect()
+    }
+
+    fn load(&self) {
+        let path = if let Some(path) = &self.path {
+            path
+        } else {
+            return;
+        };
+        let dir = match std::fs::read_dir(path) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+            Err(err) => {
+                tracing::warn!(
+                    context = "quarantine",
+                    event = "error",
+                    path = %path.display(),
+                    "Failed to read quarantine directory: {}",
+                    err
+                );
+                return;
+            }
+        };
+
+        for file in dir.flatten() {
+            let file = file.path();
+            if file.extension().map_or(false, |ext| ext == "json") {
+                match std::fs::read(&file)
+                    .map_err(|err| err.to_string())
+                    .and_then(|bytes| {
+                        serde_json::from_slice::<QuarantineEntry>(&bytes)
+                            .map_err(|err| err.to_string())
+                    }) {
+                    Ok(entry) => {
+                        self.entries.insert(entry.id, entry);
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "quarantine",
+                            event = "error",
+                            path = %file.display(),
+                            "Failed to load quarantined message: {}",
+                            err
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Ok(log) = std::fs::read_to_string(path.join("incidents.log")) {
+            let mut incidents = self.incidents.lock();
+            for line in log.lines() {
+                if let Ok(incident) = serde_json::from_str::<DlpIncident>(line) {
+                    incidents.push_back(incident);
+                    if incidents.len() > self.max_incidents {
+                        incidents.pop_front();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl QueueCore {
+    pub async fn release_quarantined(&self, id: QueueId, span: &tracing::Span) -> bool {
+        let (entry, contents) = if let Some(result) = self.quarantine.take(id).await {
+            result
+        } else {
+            return false;
+        };
+
+        let return_path_lcase = entry.return_path.to_lowercase();
+        let return_path_domain = return_path_lcase.domain_part().to_string();
+        let mut message =
+            Message::new_boxed(entry.return_path, return_path_lcase, return_path_domain);
+        message.id = entry.id;
+        for rcpt in entry.recipients {
+            message.add_recipient(rcpt, &self.config).await;
+        }
+
+        tracing::info!(
+            parent: span,
+            context = "quarantine",
+            event = "release",
+            id = id,
+            "Releasing quarantined message."
+        );
+        self.queue_message(message, None, &contents, span).await
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
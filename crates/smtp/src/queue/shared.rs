@@ -0,0 +1,410 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use store::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, ValueClass,
+    },
+    Deserialize, IterateParams, Serialize, Store, Stores, ValueKey, U64_LEN,
+};
+use utils::config::Config;
+
+use crate::core::SMTP;
+
+use super::{Event, Message, QueueId, Schedule};
+
+const KEY_BLOB: &[u8] = b"q:b";
+const KEY_MESSAGE: &[u8] = b"q:m";
+const KEY_NODE: &[u8] = b"q:n";
+const KEY_OWNER: &[u8] = b"q:o";
+
+/// Replicates the queue to a store shared by all nodes in a cluster. Each node
+/// holds a lease that it renews periodically, and the messages of a node whose
+/// lease expired are claimed by the first node to notice.
+///
+/// The owner of each message is recorded under its queue id and every write
+/// asserts it, so a node that lost a message to another node can no longer
+/// modify it. Message records are keyed by owner, which allows a node to list
+/// the messages of an expired node without scanning the whole queue.
+pub struct SharedQueue {
+    pub store: Store,
+    pub node_id: u64,
+    pub lease: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueRecord {
+    pub owner: u64,
+    pub size: u64,
+    pub metadata: Vec<u8>,
+}
+
+impl SharedQueue {
+    pub fn parse(config: &Config, stores: &Stores) -> crate::config::Result<Option<Self>> {
+        let store_id = if let Some(store_id) = config.value("queue.shared.store") {
+            store_id
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(SharedQueue {
+            store: stores.stores.get(store_id).cloned().ok_or_else(|| {
+                format!("Store {store_id:?} not found for key \"queue.shared.store\".")
+            })?,
+            node_id: config.property_require("queue.shared.node-id")?,
+            lease: config.property_or_static("queue.shared.lease", "5m")?,
+        }))
+    }
+
+    pub async fn insert(
+        &self,
+        message: &Message,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+    ) -> store::Result<()> {
+        // Fails if another node is already using the same queue id. The lease
+        // is refreshed as well so that the owner can always be found.
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(ValueClass::Persistent(owner_key(message.id)), ())
+            .set(
+                ValueClass::Persistent(owner_key(message.id)),
+                self.node_id.serialize(),
+            )
+            .set(
+                ValueClass::Persistent(message_key(self.node_id, message.id)),
+                serialize_record(message),
+            )
+            .set(
+                ValueClass::Persistent(node_key(self.node_id)),
+                (now() + self.lease.as_secs()).serialize(),
+            );
+        self.store.write(batch.build()).await?;
+
+        let result = if let Some(raw_headers) = raw_headers {
+            let mut raw = Vec::with_capacity(raw_headers.len() + raw_message.len());
+            raw.extend_from_slice(raw_headers);
+            raw.extend_from_slice(raw_message);
+            self.store.put_blob(&blob_key(message.id), &raw).await
+        } else {
+            self.store
+                .put_blob(&blob_key(message.id), raw_message)
+                .await
+        };
+        if result.is_err() {
+            let _ = self.remove(message.id).await;
+        }
+
+        result
+    }
+
+    /// Updates a message, failing with `AssertValueFailed` if the message
+    /// is no longer owned by this node.
+    pub async fn update(&self, message: &Message) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(ValueClass::Persistent(owner_key(message.id)), self.node_id)
+            .set(
+                ValueClass::Persistent(message_key(self.node_id, message.id)),
+                serialize_record(message),
+            );
+        self.store.write(batch.build()).await.map(|_| ())
+    }
+
+    /// Removes a message, failing with `AssertValueFailed` if the message
+    /// is no longer owned by this node.
+    pub async fn remove(&self, id: QueueId) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(ValueClass::Persistent(owner_key(id)), self.node_id)
+            .clear(ValueClass::Persistent(owner_key(id)))
+            .clear(ValueClass::Persistent(message_key(self.node_id, id)));
+        self.store.write(batch.build()).await?;
+        self.store.delete_blob(&blob_key(id)).await.map(|_| ())
+    }
+
+    pub async fn is_owner(&self, id: QueueId) -> store::Result<bool> {
+        self.store
+            .get_value::<u64>(ValueKey::from(ValueClass::Persistent(owner_key(id))))
+            .await
+            .map(|owner| owner == Some(self.node_id))
+    }
+
+    pub async fn renew_lease(&self) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Persistent(node_key(self.node_id)),
+            (now() + self.lease.as_secs()).serialize(),
+        );
+        self.store.write(batch.build()).await.map(|_| ())
+    }
+
+    /// Returns the messages owned by nodes whose lease has expired.
+    pub async fn orphans(&self) -> store::Result<Vec<(QueueId, QueueRecord)>> {
+        // Obtain the nodes whose lease has expired
+        let mut expired = Vec::new();
+        let now = now();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Persistent(node_key(0))),
+                    ValueKey::from(ValueClass::Persistent(node_key(u64::MAX))),
+                ),
+                |key, value| {
+                    let node_id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                    let expires = u64::deserialize(value)?;
+                    if node_id != self.node_id && expires <= now {
+                        expired.push((node_id, expires));
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        let mut orphans = Vec::new();
+        for (owner, expires) in expired {
+            let num_orphans = orphans.len();
+            self.store
+                .iterate(
+                    IterateParams::new(
+                        ValueKey::from(ValueClass::Persistent(message_key(owner, 0))),
+                        ValueKey::from(ValueClass::Persistent(message_key(owner, u64::MAX))),
+                    ),
+                    |key, value| {
+                        let id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                        orphans.push((
+                            id,
+                            QueueRecord {
+                                owner,
+                                size: value.deserialize_be_u64(0)?,
+                                metadata: value.get(U64_LEN..).unwrap_or_default().to_vec(),
+                            },
+                        ));
+                        Ok(true)
+                    },
+                )
+                .await?;
+
+            // Forget nodes that have no messages left, unless they came back
+            if orphans.len() == num_orphans {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .assert_value(ValueClass::Persistent(node_key(owner)), expires)
+                    .clear(ValueClass::Persistent(node_key(owner)));
+                match self.store.write(batch.build()).await {
+                    Ok(_) | Err(store::Error::AssertValueFailed) => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(orphans)
+    }
+
+    /// Takes ownership of a message, returns `None` if another node claimed it first.
+    pub async fn claim(
+        &self,
+        id: QueueId,
+        record: QueueRecord,
+    ) -> store::Result<Option<(Box<Message>, Vec<u8>)>> {
+        let mut message = if let Some(message) = Message::deserialize(&record.metadata) {
+            Box::new(message)
+        } else {
+            return Err(store::Error::InternalError(format!(
+                "Failed to deserialize shared queue message {id}."
+            )));
+        };
+        message.id = id;
+        message.size = record.size as usize;
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(ValueClass::Persistent(owner_key(id)), record.owner)
+            .set(
+                ValueClass::Persistent(owner_key(id)),
+                self.node_id.serialize(),
+            )
+            .clear(ValueClass::Persistent(message_key(record.owner, id)))
+            .set(
+                ValueClass::Persistent(message_key(self.node_id, id)),
+                serialize_record(&message),
+            );
+        match self.store.write(batch.build()).await {
+            Ok(_) => (),
+            Err(store::Error::AssertValueFailed) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+
+        let raw_message = self
+            .store
+            .get_blob(&blob_key(id), 0..u32::MAX)
+            .await?
+            .ok_or_else(|| {
+                store::Error::InternalError(format!("Shared queue message {id} has no contents."))
+            })?;
+
+        Ok(Some((message, raw_message)))
+    }
+
+    pub fn spawn(core: Arc<SMTP>) {
+        tokio::spawn(async move {
+            let shared = if let Some(shared) = &core.queue.shared {
+                shared
+            } else {
+                return;
+            };
+            let interval = shared.lease / 3;
+
+            loop {
+                match shared.renew_lease().await {
+                    Ok(_) => shared.take_over(&core).await,
+                    Err(err) => {
+                        tracing::error!(
+                            context = "queue",
+                            event = "error",
+                            "Failed to renew shared queue lease: {}",
+                            err
+                        );
+                    }
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    async fn take_over(&self, core: &SMTP) {
+        let orphans = match self.orphans().await {
+            Ok(orphans) => orphans,
+            Err(err) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to obtain orphaned messages from shared queue: {}",
+                    err
+                );
+                return;
+            }
+        };
+
+        for (id, record) in orphans {
+            let owner = record.owner;
+            let (mut message, raw_message) = match self.claim(id, record).await {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        id = id,
+                        "Failed to claim message from shared queue: {}",
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            let span = tracing::info_span!(
+                "queue-takeover",
+                "id" = id,
+                "owner" = owner,
+                "node" = self.node_id,
+            );
+            if !core
+                .queue
+                .spool_message(&mut message, None, &raw_message, &span)
+                .await
+            {
+                continue;
+            }
+            core.queue.has_quota(&mut message).await;
+
+            tracing::info!(
+                parent: &span,
+                context = "queue",
+                event = "takeover",
+                "Took over message from node with an expired lease."
+            );
+
+            if let Some(due) = message.next_event() {
+                if core
+                    .queue
+                    .tx
+                    .send(Event::Queue(Schedule {
+                        due,
+                        inner: message,
+                    }))
+                    .await
+                    .is_err()
+                {
+                    tracing::warn!(
+                        parent: &span,
+                        context = "queue",
+                        event = "error",
+                        "Queue channel closed: Message queued but won't be sent until next restart."
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn serialize_record(message: &Message) -> Vec<u8> {
+    let metadata = message.serialize();
+    KeySerializer::new(metadata.len() + U64_LEN)
+        .write(message.size as u64)
+        .write(metadata.as_slice())
+        .finalize()
+}
+
+fn blob_key(id: QueueId) -> Vec<u8> {
+    KeySerializer::new(KEY_BLOB.len() + U64_LEN)
+        .write(KEY_BLOB)
+        .write(id)
+        .finalize()
+}
+
+fn owner_key(id: QueueId) -> Vec<u8> {
+    KeySerializer::new(KEY_OWNER.len() + U64_LEN)
+        .write(KEY_OWNER)
+        .write(id)
+        .finalize()
+}
+
+fn message_key(owner: u64, id: QueueId) -> Vec<u8> {
+    KeySerializer::new(KEY_MESSAGE.len() + U64_LEN * 2)
+        .write(KEY_MESSAGE)
+        .write(owner)
+        .write(id)
+        .finalize()
+}
+
+fn node_key(node_id: u64) -> Vec<u8> {
+    KeySerializer::new(KEY_NODE.len() + U64_LEN)
+        .write(KEY_NODE)
+        .write(node_id)
+        .finalize()
+}
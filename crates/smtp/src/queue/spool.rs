@@ -24,7 +24,7 @@
 use crate::queue::DomainPart;
 use mail_auth::common::base32::Base32Writer;
 use mail_auth::common::headers::Writer;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
 use std::time::Instant;
 use std::time::{Duration, SystemTime};
@@ -34,7 +34,11 @@ use tokio::{fs, io::AsyncWriteExt};
 use crate::config::QueueConfig;
 use crate::core::QueueCore;
 
-use super::{Domain, Event, Message, Recipient, Schedule, SimpleEnvelope, Status};
+use super::{
+    history::HistoryDetails, Domain, Event, Message, Recipient, Schedule, SimpleEnvelope, Status,
+};
+
+pub(crate) const SPOOL_TEMP_EXT: &str = "msg.tmp";
 
 impl QueueCore {
     pub async fn queue_message(
@@ -52,71 +56,40 @@ impl QueueCore {
             message.size = raw_message.len() + raw_headers.as_ref().map_or(0, |h| h.len());
         }
 
-        // Build path
-        message.path = self.config.path.eval(message.as_ref()).await.clone();
-        let hash = *self.config.hash.eval(message.as_ref()).await;
-        if hash > 0 {
-            message.path.push((message.id % hash).to_string());
-        }
-        let _ = fs::create_dir(&message.path).await;
-
-        // Encode file name
-        let mut encoder = Base32Writer::with_capacity(20);
-        encoder.write(&message.id.to_le_bytes()[..]);
-        encoder.write(&(message.size as u32).to_le_bytes()[..]);
-        let mut file = encoder.finalize();
-        file.push_str(".msg");
-        message.path.push(file);
-
-        // Serialize metadata
-        let metadata = message.serialize();
-
-        // Save message
-        let mut file = match fs::File::create(&message.path).await {
-            Ok(file) => file,
-            Err(err) => {
-                tracing::error!(
-                    parent: span,
-                    context = "queue",
-                    event = "error",
-                    "Failed to create file {}: {}",
-                    message.path.display(),
-                    err
-                );
-                return false;
-            }
-        };
-
-        let iter = if let Some(raw_headers) = raw_headers {
-            [raw_headers, raw_message, &metadata].into_iter()
-        } else {
-            [raw_message, &metadata, b""].into_iter()
-        };
-
-        for bytes in iter {
-            if !bytes.is_empty() {
-                if let Err(err) = file.write_all(bytes).await {
-                    tracing::error!(
-                        parent: span,
-                        context = "queue",
-                        event = "error",
-                        "Failed to write to file {}: {}",
-                        message.path.display(),
-                        err
-                    );
-                    return false;
+        // Replicate message to the shared queue, retrying on id collisions
+        if let Some(shared) = &self.shared {
+            let mut attempts = 0;
+            loop {
+                match shared
+                    .insert(message.as_ref(), raw_headers, raw_message)
+                    .await
+                {
+                    Ok(_) => break,
+                    Err(store::Error::AssertValueFailed) if attempts < 3 => {
+                        message.id = self.queue_id();
+                        attempts += 1;
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            parent: span,
+                            context = "queue",
+                            event = "error",
+                            "Failed to write message to shared queue: {}",
+                            err
+                        );
+                        return false;
+                    }
                 }
             }
         }
-        if let Err(err) = file.flush().await {
-            tracing::error!(
-                parent: span,
-                context = "queue",
-                event = "error",
-                "Failed to flush file {}: {}",
-                message.path.display(),
-                err
-            );
+
+        if !self
+            .spool_message(&mut message, raw_headers, raw_message, span)
+            .await
+        {
+            if let Some(shared) = &self.shared {
+                let _ = shared.remove(message.id).await;
+            }
             return false;
         }
 
@@ -134,6 +107,13 @@ impl QueueCore {
             size = message.size,
             "Message queued for delivery."
         );
+        self.history.record(
+            message.id,
+            HistoryDetails::Queued {
+                size: message.size,
+                recipients: message.recipients.len(),
+            },
+        );
 
         // Queue the message
         if self
@@ -156,6 +136,126 @@ impl QueueCore {
         true
     }
 
+    pub(crate) async fn spool_message(
+        &self,
+        message: &mut Message,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        span: &tracing::Span,
+    ) -> bool {
+        // Build path
+        message.path = self.config.path.eval(&*message).await.clone();
+        let hash = *self.config.hash.eval(&*message).await;
+        if hash > 0 {
+            message.path.push((message.id % hash).to_string());
+        }
+        let _ = fs::create_dir(&message.path).await;
+
+        // Encode file name
+        let mut encoder = Base32Writer::with_capacity(20);
+        encoder.write(&message.id.to_le_bytes()[..]);
+        encoder.write(&(message.size as u32).to_le_bytes()[..]);
+        let mut file = encoder.finalize();
+        file.push_str(".msg");
+        message.path.push(file);
+
+        // Serialize metadata
+        let metadata = message.serialize();
+
+        // Save message to a temporary file first, so a crash while writing
+        // never leaves a partial message in the queue
+        let temp_path = message.path.with_extension(SPOOL_TEMP_EXT);
+        if let Err(err) = self
+            .write_spool_file(&temp_path, raw_headers, raw_message, &metadata)
+            .await
+        {
+            tracing::error!(
+                parent: span,
+                context = "queue",
+                event = "error",
+                "Failed to write file {}: {}",
+                temp_path.display(),
+                err
+            );
+            let _ = fs::remove_file(&temp_path).await;
+            return false;
+        }
+
+        // Unless relaxed by the durability policy, the message is only
+        // acknowledged once it has been synced to disk
+        if let Err(err) = fs::rename(&temp_path, &message.path).await {
+            tracing::error!(
+                parent: span,
+                context = "queue",
+                event = "error",
+                "Failed to rename file {} to {}: {}",
+                temp_path.display(),
+                message.path.display(),
+                err
+            );
+            let _ = fs::remove_file(&temp_path).await;
+            return false;
+        }
+        self.durability.sync_path(&message.path).await;
+
+        true
+    }
+
+    pub async fn save_message(&self, message: &mut Message) {
+        message.save_changes().await;
+        if let Some(shared) = &self.shared {
+            match shared.update(message).await {
+                Ok(_) => (),
+                Err(store::Error::AssertValueFailed) => {
+                    // The next delivery attempt drops the local copy
+                    tracing::warn!(
+                        context = "queue",
+                        event = "fenced",
+                        id = message.id,
+                        "Message is now owned by another node, changes were not shared."
+                    );
+                }
+                Err(err) => {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        id = message.id,
+                        "Failed to update shared queue: {}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    pub async fn remove_message(&self, message: &Message) {
+        message.remove().await;
+        if let Some(shared) = &self.shared {
+            match shared.remove(message.id).await {
+                Ok(_) | Err(store::Error::AssertValueFailed) => (),
+                Err(err) => {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        id = message.id,
+                        "Failed to remove message from shared queue: {}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns whether this node may deliver the message, which is always
+    /// the case unless the queue is shared with other nodes.
+    pub async fn is_owner(&self, message: &Message) -> store::Result<bool> {
+        if let Some(shared) = &self.shared {
+            shared.is_owner(message.id).await
+        } else {
+            Ok(true)
+        }
+    }
+
     pub fn queue_id(&self) -> u64 {
         (SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -164,6 +264,23 @@ impl QueueCore {
             & 0xFFFFFFFF)
             | (self.id_seq.fetch_add(1, Ordering::Relaxed) as u64) << 32
     }
+
+    async fn write_spool_file(
+        &self,
+        path: &Path,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        metadata: &[u8],
+    ) -> std::io::Result<()> {
+        let mut file = fs::File::create(path).await?;
+        for bytes in [raw_headers.unwrap_or_default(), raw_message, metadata] {
+            if !bytes.is_empty() {
+                file.write_all(bytes).await?;
+            }
+        }
+        file.flush().await?;
+        self.durability.sync_file(&file).await
+    }
 }
 
 impl Message {
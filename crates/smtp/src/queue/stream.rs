@@ -0,0 +1,452 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use mail_builder::encoders::base64::base64_encode;
+use mail_parser::{DateTime, MessageParser};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
+use utils::config::Config;
+
+use crate::{
+    core::{management::serialize_datetime, Session},
+    inbound::IsTls,
+};
+
+use super::{Message, QueueId};
+
+/// Publishes the metadata of accepted messages to Kafka topics or NATS
+/// JetStream subjects. Events are handed over to a background task through a
+/// bounded channel and dropped when it is full, so a slow or unavailable
+/// broker never delays an SMTP transaction.
+#[derive(Default)]
+pub struct MessageStreams {
+    streams: Vec<MessageStream>,
+}
+
+struct MessageStream {
+    id: String,
+    include_message: bool,
+    tx: mpsc::Sender<Arc<StreamEvent>>,
+    dropped: Arc<AtomicU64>,
+}
+
+struct StreamConfig {
+    id: String,
+    backend: StreamBackend,
+    include_message: bool,
+    max_message_size: usize,
+    timeout: Duration,
+    retry_attempts: u32,
+    retry_delay: Duration,
+    dropped: Arc<AtomicU64>,
+}
+
+enum StreamBackend {
+    Kafka {
+        brokers: Vec<String>,
+        topic: String,
+        partition: i32,
+    },
+    Nats {
+        url: String,
+        subject: String,
+        user: Option<String>,
+        password: Option<String>,
+        token: Option<String>,
+    },
+}
+
+enum Producer {
+    Kafka(rskafka::client::partition::PartitionClient),
+    Nats(async_nats::jetstream::Context),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    #[serde(rename = "inbound")]
+    Inbound,
+    #[serde(rename = "outbound")]
+    Outbound,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamEvent {
+    pub id: QueueId,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub timestamp: DateTime,
+    pub direction: Direction,
+    pub listener: String,
+    pub remote_ip: IpAddr,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub authenticated_as: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(skip)]
+    pub raw_message: Option<Vec<u8>>,
+}
+
+#[derive(Serialize)]
+struct StreamPayload<'x> {
+    #[serde(flatten)]
+    event: &'x StreamEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl MessageStreams {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let mut streams = Vec::new();
+
+        for id in config.sub_keys("queue.stream") {
+            if !config.property_or_static::<bool>(("queue.stream", id, "enable"), "true")? {
+                continue;
+            }
+            let backend = match config.value_require(("queue.stream", id, "type"))? {
+                "kafka" => StreamBackend::Kafka {
+                    brokers: config
+                        .values(("queue.stream", id, "brokers"))
+                        .map(|(_, broker)| broker.to_string())
+                        .collect(),
+                    topic: config
+                        .value_require(("queue.stream", id, "topic"))?
+                        .to_string(),
+                    partition: config.property_or_static(("queue.stream", id, "partition"), "0")?,
+                },
+                "nats" => StreamBackend::Nats {
+                    url: config
+                        .value_require(("queue.stream", id, "url"))?
+                        .to_string(),
+                    subject: config
+                        .value_require(("queue.stream", id, "subject"))?
+                        .to_string(),
+                    user: config.property(("queue.stream", id, "auth.username"))?,
+                    password: config.property(("queue.stream", id, "auth.secret"))?,
+                    token: config.property(("queue.stream", id, "auth.token"))?,
+                },
+                unknown => {
+                    return Err(format!(
+                        "Unknown stream type {unknown:?} for key \"queue.stream.{id}.type\"."
+                    ))
+                }
+            };
+            if matches!(&backend, StreamBackend::Kafka { brokers, .. } if brokers.is_empty()) {
+                return Err(format!("Missing Kafka brokers for stream {id:?}."));
+            }
+
+            let (tx, rx) = mpsc::channel(
+                config
+                    .property_or_static::<usize>(("queue.stream", id, "queue-size"), "10000")?
+                    .max(1),
+            );
+            let dropped = Arc::new(AtomicU64::new(0));
+            let stream = StreamConfig {
+                id: id.to_string(),
+                backend,
+                include_message: config
+                    .property_or_static(("queue.stream", id, "include-message"), "false")?,
+                max_message_size: config
+                    .property_or_static(("queue.stream", id, "max-message-size"), "10485760")?,
+                timeout: config.property_or_static(("queue.stream", id, "timeout"), "30s")?,
+                retry_attempts: config
+                    .property_or_static(("queue.stream", id, "retry.attempts"), "5")?,
+                retry_delay: config
+                    .property_or_static(("queue.stream", id, "retry.delay"), "1s")?,
+                dropped: dropped.clone(),
+            };
+            streams.push(MessageStream {
+                id: id.to_string(),
+                include_message: stream.include_message,
+                tx,
+                dropped,
+            });
+            tokio::spawn(stream.run(rx));
+        }
+
+        Ok(MessageStreams { streams })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    pub fn include_message(&self) -> bool {
+        self.streams.iter().any(|stream| stream.include_message)
+    }
+
+    pub fn publish(&self, event: StreamEvent) {
+        let event = Arc::new(event);
+        for stream in &self.streams {
+            if stream.tx.try_send(event.clone()).is_err() {
+                let dropped = stream.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(
+                    context = "stream",
+                    event = "dropped",
+                    stream = &stream.id,
+                    id = event.id,
+                    dropped = dropped,
+                    "Stream queue is full, dropping event."
+                );
+            }
+        }
+    }
+
+    /// Number of events dropped by each stream since startup
+    pub fn dropped(&self) -> Vec<(String, u64)> {
+        self.streams
+            .iter()
+            .map(|stream| (stream.id.clone(), stream.dropped.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+impl StreamConfig {
+    async fn run(self, mut rx: mpsc::Receiver<Arc<StreamEvent>>) {
+        let mut producer = None;
+
+        while let Some(event) = rx.recv().await {
+            let payload = match serde_json::to_vec(&StreamPayload {
+                event: &event,
+                message: event
+                    .raw_message
+                    .as_ref()
+                    .filter(|raw| self.include_message && raw.len() <= self.max_message_size)
+                    .and_then(|raw| base64_encode(raw).ok())
+                    .map(|raw| String::from_utf8(raw).unwrap_or_default()),
+            }) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    tracing::warn!(
+                        context = "stream",
+                        event = "error",
+                        stream = &self.id,
+                        reason = %err,
+                        "Failed to serialize event."
+                    );
+                    continue;
+                }
+            };
+
+            // Events are retried until acknowledged by the broker, the queue
+            // id is sent along so that consumers can discard duplicates.
+            let mut attempt = 0;
+            loop {
+                let result = match producer.take() {
+                    Some(p) => Ok(p),
+                    None => self.connect().await,
+                };
+                let result: Result<(), String> = match result {
+                    Ok(p) => match self.send(&p, event.id, payload.clone()).await {
+                        Ok(_) => {
+                            producer = Some(p);
+                            break;
+                        }
+                        Err(err) => Err(err),
+                    },
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = result {
+                    attempt += 1;
+                    if attempt < self.retry_attempts {
+                        tracing::debug!(
+                            context = "stream",
+                            event = "retry",
+                            stream = &self.id,
+                            id = event.id,
+                            attempt = attempt,
+                            reason = %err,
+                            "Failed to publish event, retrying."
+                        );
+                        tokio::time::sleep(self.retry_delay * attempt).await;
+                    } else {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(
+                            context = "stream",
+                            event = "error",
+                            stream = &self.id,
+                            id = event.id,
+                            reason = %err,
+                            "Failed to publish event, dropping it."
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Producer, String> {
+        let result = tokio::time::timeout(self.timeout, async {
+            match &self.backend {
+                StreamBackend::Kafka {
+                    brokers,
+                    topic,
+                    partition,
+                } => {
+                    let client = rskafka::client::ClientBuilder::new(brokers.clone())
+                        .build()
+                        .await
+                        .map_err(|err| err.to_string())?;
+                    client
+                        .partition_client(
+                            topic.clone(),
+                            *partition,
+                            rskafka::client::partition::UnknownTopicHandling::Retry,
+                        )
+                        .await
+                        .map(Producer::Kafka)
+                        .map_err(|err| err.to_string())
+                }
+                StreamBackend::Nats {
+                    url,
+                    user,
+                    password,
+                    token,
+                    ..
+                } => {
+                    let mut options = async_nats::ConnectOptions::new().name("stalwart-smtp");
+                    if let (Some(user), Some(password)) = (user, password) {
+                        options = options.user_and_password(user.clone(), password.clone());
+                    } else if let Some(token) = token {
+                        options = options.token(token.clone());
+                    }
+                    options
+                        .connect(url.as_str())
+                        .await
+                        .map(|client| Producer::Nats(async_nats::jetstream::new(client)))
+                        .map_err(|err| err.to_string())
+                }
+            }
+        })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(_) => Err("Connection timed out".to_string()),
+        }
+    }
+
+    async fn send(&self, producer: &Producer, id: QueueId, payload: Vec<u8>) -> Result<(), String> {
+        let result = tokio::time::timeout(self.timeout, async {
+            match (producer, &self.backend) {
+                (Producer::Kafka(client), _) => client
+                    .produce(
+                        vec![rskafka::record::Record {
+                            key: Some(id.to_string().into_bytes()),
+                            value: Some(payload),
+                            headers: BTreeMap::new(),
+                            timestamp: chrono::Utc::now(),
+                        }],
+                        rskafka::client::partition::Compression::NoCompression,
+                    )
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string()),
+                (Producer::Nats(context), StreamBackend::Nats { subject, .. }) => {
+                    let mut headers = async_nats::HeaderMap::new();
+                    headers.insert("Nats-Msg-Id", id.to_string().as_str());
+                    context
+                        .publish_with_headers(subject.clone(), headers, payload.into())
+                        .await
+                        .map_err(|err| err.to_string())?
+                        .await
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                }
+                (Producer::Nats(_), _) => unreachable!(),
+            }
+        })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(_) => Err("Publish timed out".to_string()),
+        }
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + IsTls + Unpin> Session<T> {
+    pub fn build_stream_event(
+        &self,
+        message: &Message,
+        headers: &[u8],
+        raw_message: &[u8],
+    ) -> StreamEvent {
+        let parser = MessageParser::new();
+        StreamEvent {
+            id: message.id,
+            timestamp: DateTime::from_timestamp(now() as i64),
+            direction: if self.data.authenticated_as.is_empty() {
+                Direction::Inbound
+            } else {
+                Direction::Outbound
+            },
+            listener: self.instance.id.clone(),
+            remote_ip: self.data.remote_ip,
+            authenticated_as: self.data.authenticated_as.clone(),
+            return_path: message.return_path.clone(),
+            recipients: message
+                .recipients
+                .iter()
+                .map(|rcpt| rcpt.address.clone())
+                .collect(),
+            size: message.size,
+            message_id: parser
+                .parse_headers(raw_message)
+                .and_then(|m| m.message_id().map(|id| id.to_string()))
+                .or_else(|| {
+                    parser
+                        .parse_headers(headers)
+                        .and_then(|m| m.message_id().map(|id| id.to_string()))
+                }),
+            raw_message: self.core.queue.streams.include_message().then(|| {
+                let mut raw = Vec::with_capacity(headers.len() + raw_message.len());
+                raw.extend_from_slice(headers);
+                raw.extend_from_slice(raw_message);
+                raw
+            }),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,365 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use mail_parser::DateTime;
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, ValueClass},
+    IterateParams, Store, Stores, ValueKey,
+};
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use crate::core::management::{deserialize_datetime, serialize_datetime};
+
+use super::{
+    bounce::{BounceClass, BounceClassifier},
+    HostResponse, Recipient, Status,
+};
+
+const KEY_SUPPRESSION: &[u8] = b"q:s";
+
+pub struct SuppressionList {
+    pub action: SuppressionAction,
+    expire: Option<Duration>,
+    store: Option<Store>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionAction {
+    Disabled,
+    Reject,
+    Drop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuppressionEntry {
+    pub domain: String,
+    pub address: String,
+    pub reason: SuppressionReason,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub details: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classification: Option<BounceClass>,
+    #[serde(serialize_with = "serialize_datetime")]
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub created: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SuppressionReason {
+    #[serde(rename = "bounce")]
+    Bounce,
+    #[serde(rename = "complaint")]
+    Complaint,
+    #[serde(rename = "manual")]
+    Manual,
+}
+
+impl SuppressionList {
+    pub fn parse(config: &Config, stores: &Stores) -> crate::config::Result<Self> {
+        if !config.property_or_static::<bool>("queue.suppression.enable", "false")? {
+            return Ok(SuppressionList::new(SuppressionAction::Disabled, None));
+        }
+
+        let store_id = config.value_require("queue.suppression.store")?;
+        Ok(SuppressionList {
+            action: config.property_or_static("queue.suppression.action", "reject")?,
+            expire: config.property("queue.suppression.expire")?,
+            store: stores
+                .stores
+                .get(store_id)
+                .cloned()
+                .ok_or_else(|| {
+                    format!("Store {store_id:?} not found for key \"queue.suppression.store\".")
+                })?
+                .into(),
+        })
+    }
+
+    pub fn new(action: SuppressionAction, store: Option<Store>) -> Self {
+        SuppressionList {
+            action: if store.is_some() {
+                action
+            } else {
+                SuppressionAction::Disabled
+            },
+            expire: None,
+            store,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.action != SuppressionAction::Disabled
+    }
+
+    pub async fn is_suppressed(&self, domain: &str, address: &str) -> bool {
+        let store = match &self.store {
+            Some(store) if self.is_enabled() => store,
+            _ => return false,
+        };
+
+        match store
+            .get_value::<SuppressionEntry>(ValueKey::from(ValueClass::Persistent(entry_key(
+                &domain.to_lowercase(),
+                &address.to_lowercase(),
+            ))))
+            .await
+        {
+            Ok(entry) => entry.map_or(false, |entry| !entry.is_expired(now())),
+            Err(err) => {
+                tracing::warn!(
+                    context = "suppression",
+                    event = "error",
+                    domain = domain,
+                    address = address,
+                    reason = %err,
+                    "Failed to read suppression list."
+                );
+                false
+            }
+        }
+    }
+
+    pub async fn add(
+        &self,
+        domain: &str,
+        address: &str,
+        reason: SuppressionReason,
+        details: impl Into<String>,
+    ) -> store::Result<bool> {
+        self.insert(domain, address, reason, details.into(), None)
+            .await
+    }
+
+    async fn insert(
+        &self,
+        domain: &str,
+        address: &str,
+        reason: SuppressionReason,
+        details: String,
+        classification: Option<BounceClass>,
+    ) -> store::Result<bool> {
+        let store = match &self.store {
+            Some(store) if self.is_enabled() && !domain.is_empty() && !address.is_empty() => store,
+            _ => return Ok(false),
+        };
+
+        let now = now();
+        let domain = domain.to_lowercase();
+        let address = address.to_lowercase();
+        let entry = SuppressionEntry {
+            domain,
+            address,
+            reason,
+            details,
+            classification,
+            created: DateTime::from_timestamp(now as i64),
+            expires: self.expire.map(|expire| now + expire.as_secs()),
+        };
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Persistent(entry_key(&entry.domain, &entry.address)),
+            serde_json::to_vec(&entry).unwrap_or_default(),
+        );
+        store.write(batch.build()).await?;
+
+        tracing::info!(
+            context = "suppression",
+            event = "add",
+            domain = &entry.domain,
+            address = &entry.address,
+            reason = ?reason,
+            "Address added to suppression list."
+        );
+        Ok(true)
+    }
+
+    pub async fn add_bounces<'x>(
+        &self,
+        domain: &str,
+        recipients: impl Iterator<Item = &'x Recipient>,
+        classifier: &BounceClassifier,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        for rcpt in recipients {
+            if let Status::PermanentFailure(HostResponse { response, .. }) = &rcpt.status {
+                // Only suppress recipients that do not exist
+                let class = classifier.classify(response);
+                if class == BounceClass::UserUnknown
+                    && !self.is_suppressed(domain, &rcpt.address_lcase).await
+                {
+                    if let Err(err) = self
+                        .insert(
+                            domain,
+                            &rcpt.address_lcase,
+                            SuppressionReason::Bounce,
+                            format!("{} {}", response.code, response.message),
+                            class.into(),
+                        )
+                        .await
+                    {
+                        tracing::warn!(
+                            context = "suppression",
+                            event = "error",
+                            domain = domain,
+                            address = &rcpt.address_lcase,
+                            reason = %err,
+                            "Failed to add address to suppression list."
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn remove(&self, domain: &str, address: &str) -> store::Result<bool> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+
+        let key =
+            ValueClass::Persistent(entry_key(&domain.to_lowercase(), &address.to_lowercase()));
+        if store
+            .get_value::<SuppressionEntry>(ValueKey::from(key.clone()))
+            .await?
+            .is_some()
+        {
+            let mut batch = BatchBuilder::new();
+            batch.clear(key);
+            store.write(batch.build()).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub async fn list(&self, domain: Option<&str>) -> store::Result<Vec<SuppressionEntry>> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(Vec::new()),
+        };
+
+        let (from_key, to_key) = match domain {
+            Some(domain) => {
+                let domain = domain.to_lowercase();
+                (domain_key(&domain, 0), domain_key(&domain, 1))
+            }
+            None => (
+                KEY_SUPPRESSION.to_vec(),
+                KeySerializer::new(KEY_SUPPRESSION.len() + 1)
+                    .write(KEY_SUPPRESSION)
+                    .write(u8::MAX)
+                    .finalize(),
+            ),
+        };
+
+        let now = now();
+        let mut entries = Vec::new();
+        let mut expired = Vec::new();
+        store
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Persistent(from_key)),
+                    ValueKey::from(ValueClass::Persistent(to_key)),
+                ),
+                |_, value| {
+                    if let Ok(entry) = serde_json::from_slice::<SuppressionEntry>(value) {
+                        if !entry.is_expired(now) {
+                            entries.push(entry);
+                        } else {
+                            expired.push(entry_key(&entry.domain, &entry.address));
+                        }
+                    }
+                    Ok(true)
+                },
+            )
+            .await?;
+
+        // Purge expired entries
+        if !expired.is_empty() {
+            let mut batch = BatchBuilder::new();
+            for key in expired {
+                batch.clear(ValueClass::Persistent(key));
+            }
+            store.write(batch.build()).await?;
+        }
+
+        Ok(entries)
+    }
+}
+
+impl SuppressionEntry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+}
+
+impl store::Deserialize for SuppressionEntry {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        serde_json::from_slice(bytes).map_err(|err| {
+            store::Error::InternalError(format!("Failed to deserialize suppression entry: {err}"))
+        })
+    }
+}
+
+impl ParseValue for SuppressionAction {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        match value {
+            "reject" => Ok(SuppressionAction::Reject),
+            "drop" => Ok(SuppressionAction::Drop),
+            _ => Err(format!(
+                "Invalid suppression action {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+fn domain_key(domain: &str, separator: u8) -> Vec<u8> {
+    KeySerializer::new(KEY_SUPPRESSION.len() + domain.len() + 1)
+        .write(KEY_SUPPRESSION)
+        .write(domain)
+        .write(separator)
+        .finalize()
+}
+
+fn entry_key(domain: &str, address: &str) -> Vec<u8> {
+    KeySerializer::new(KEY_SUPPRESSION.len() + domain.len() + address.len() + 1)
+        .write(KEY_SUPPRESSION)
+        .write(domain)
+        .write(0u8)
+        .write(address)
+        .finalize()
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use dashmap::mapref::entry::Entry;
 use utils::{
@@ -51,7 +51,44 @@ impl QueueCore {
         span: &tracing::Span,
     ) -> Result<(), Error> {
         if throttle.conditions.conditions.is_empty() || throttle.conditions.eval(envelope).await {
-            match self.throttle.entry(throttle.new_key(envelope)) {
+            let key = throttle.new_key(envelope);
+
+            // Check distributed rate limit
+            let mut is_distributed = false;
+            if let (Some(rate), Some(store)) = (&throttle.rate, &self.throttle_store) {
+                match key.is_rate_allowed(store, rate).await {
+                    Ok(None) => {
+                        is_distributed = true;
+                    }
+                    Ok(Some(retry_in)) => {
+                        tracing::info!(
+                            parent: span,
+                            context = "throttle",
+                            event = "rate-limit-exceeded",
+                            max_requests = rate.requests,
+                            max_interval = rate.period.as_secs(),
+                            "Queue rate limit exceeded."
+                        );
+                        return Err(Error::Rate {
+                            retry_at: Instant::now() + Duration::from_secs(retry_in),
+                        });
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            parent: span,
+                            context = "throttle",
+                            event = "error",
+                            reason = %err,
+                            "Failed to check distributed rate limit, using local limiter."
+                        );
+                    }
+                }
+            }
+            if is_distributed && throttle.concurrency.is_none() {
+                return Ok(());
+            }
+
+            match self.throttle.entry(key) {
                 Entry::Occupied(mut e) => {
                     let limiter = e.get_mut();
                     if let Some(limiter) = &limiter.concurrency {
@@ -70,7 +107,7 @@ impl QueueCore {
                             });
                         }
                     }
-                    if let Some(limiter) = &mut limiter.rate {
+                    if let Some(limiter) = limiter.rate.as_mut().filter(|_| !is_distributed) {
                         if !limiter.is_allowed() {
                             tracing::info!(
                                 parent: span,
@@ -94,11 +131,15 @@ impl QueueCore {
                         }
                         limiter
                     });
-                    let rate = throttle.rate.as_ref().map(|rate| {
-                        let mut r = RateLimiter::new(rate.requests, rate.period);
-                        r.is_allowed();
-                        r
-                    });
+                    let rate = throttle
+                        .rate
+                        .as_ref()
+                        .filter(|_| !is_distributed)
+                        .map(|rate| {
+                            let mut r = RateLimiter::new(rate.requests, rate.period);
+                            r.is_allowed();
+                            r
+                        });
 
                     e.insert(Limiter { rate, concurrency });
                 }
@@ -0,0 +1,260 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use smtp_proto::Response;
+use utils::{
+    config::Config,
+    listener::limiter::{ConcurrencyLimiter, InFlight},
+};
+
+use super::{throttle, Error, ErrorDetails, HostResponse, Status};
+
+/// Adjusts the concurrency and connection rate to each remote host using
+/// additive increase and multiplicative decrease, backing off when the host
+/// signals throttling or responds slowly and recovering after successful deliveries.
+pub struct AutoTune {
+    enabled: bool,
+    min_concurrency: u64,
+    max_concurrency: u64,
+    initial_concurrency: u64,
+    backoff: f64,
+    initial_delay: Duration,
+    max_delay: Duration,
+    latency_threshold: Duration,
+    hosts: DashMap<String, HostState>,
+}
+
+struct HostState {
+    limit: u64,
+    successes: u64,
+    concurrent: Arc<AtomicU64>,
+    delay: Duration,
+    next_connect: Instant,
+    latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Success,
+    Throttled,
+    Neutral,
+}
+
+impl AutoTune {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let min_concurrency = config
+            .property_or_static::<u64>("queue.auto-tune.concurrency.min", "1")?
+            .max(1);
+        let max_concurrency = config
+            .property_or_static::<u64>("queue.auto-tune.concurrency.max", "20")?
+            .max(min_concurrency);
+        let backoff = config.property_or_static::<f64>("queue.auto-tune.backoff", "0.5")?;
+        if !(0.0..1.0).contains(&backoff) {
+            return Err(format!(
+                "Invalid backoff factor {backoff} for property \"queue.auto-tune.backoff\", expected a value between 0 and 1."
+            ));
+        }
+
+        Ok(AutoTune {
+            enabled: config.property_or_static("queue.auto-tune.enable", "false")?,
+            min_concurrency,
+            max_concurrency,
+            initial_concurrency: config
+                .property_or_static::<u64>("queue.auto-tune.concurrency.initial", "5")?
+                .clamp(min_concurrency, max_concurrency),
+            backoff,
+            initial_delay: config.property_or_static("queue.auto-tune.delay.initial", "1s")?,
+            max_delay: config.property_or_static("queue.auto-tune.delay.max", "5m")?,
+            latency_threshold: config
+                .property_or_static("queue.auto-tune.latency.threshold", "5s")?,
+            hosts: DashMap::new(),
+        })
+    }
+
+    pub fn new() -> Self {
+        AutoTune {
+            enabled: false,
+            min_concurrency: 1,
+            max_concurrency: 20,
+            initial_concurrency: 5,
+            backoff: 0.5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+            latency_threshold: Duration::from_secs(5),
+            hosts: DashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_allowed(&self, host: &str) -> Result<Option<InFlight>, throttle::Error> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let mut state = self.state(host);
+        let now = Instant::now();
+        if state.next_connect > now {
+            return Err(throttle::Error::Rate {
+                retry_at: state.next_connect,
+            });
+        }
+
+        let limiter = ConcurrencyLimiter {
+            max_concurrent: state.limit,
+            concurrent: state.concurrent.clone(),
+        };
+        if let Some(in_flight) = limiter.is_allowed() {
+            state.next_connect = now + state.delay;
+            Ok(Some(in_flight))
+        } else {
+            Err(throttle::Error::Concurrency { limiter })
+        }
+    }
+
+    pub fn record_latency(&self, host: &str, latency: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        // Exponentially weighted moving average
+        let latency = {
+            let mut state = self.state(host);
+            let latency = state
+                .latency
+                .map_or(latency, |average| (average * 3 + latency) / 4);
+            state.latency = Some(latency);
+            latency
+        };
+        if latency > self.latency_threshold {
+            self.record(host, Signal::Throttled);
+        }
+    }
+
+    pub fn record(&self, host: &str, signal: Signal) {
+        if !self.enabled || signal == Signal::Neutral {
+            return;
+        }
+
+        let mut state = self.state(host);
+        match signal {
+            Signal::Throttled => {
+                state.limit =
+                    ((state.limit as f64 * self.backoff) as u64).max(self.min_concurrency);
+                state.successes = 0;
+                state.delay = (state.delay * 2).clamp(self.initial_delay, self.max_delay);
+                state.next_connect = Instant::now() + state.delay;
+            }
+            Signal::Success => {
+                state.successes += 1;
+                if state.successes >= state.limit {
+                    state.successes = 0;
+                    state.limit = (state.limit + 1).min(self.max_concurrency);
+                    state.delay = if state.delay > self.initial_delay {
+                        state.delay / 2
+                    } else {
+                        Duration::ZERO
+                    };
+                }
+            }
+            Signal::Neutral => (),
+        }
+    }
+
+    pub fn limit(&self, host: &str) -> Option<(u64, Duration)> {
+        self.hosts.get(host).map(|state| (state.limit, state.delay))
+    }
+
+    fn state(&self, host: &str) -> dashmap::mapref::one::RefMut<'_, String, HostState> {
+        self.hosts
+            .entry(host.to_string())
+            .or_insert_with(|| HostState {
+                limit: self.initial_concurrency,
+                successes: 0,
+                concurrent: Arc::new(0.into()),
+                delay: Duration::ZERO,
+                next_connect: Instant::now(),
+                latency: None,
+            })
+    }
+}
+
+impl Default for AutoTune {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal {
+    pub fn from_status(status: &Status<(), Error>) -> Self {
+        match status {
+            Status::Completed(_) => Signal::Success,
+            Status::TemporaryFailure(Error::UnexpectedResponse(HostResponse {
+                response, ..
+            })) if is_throttle_response(response) => Signal::Throttled,
+            _ => Signal::Neutral,
+        }
+    }
+
+    pub fn from_rcpt_status(
+        status: &Status<HostResponse<String>, HostResponse<ErrorDetails>>,
+    ) -> Self {
+        match status {
+            Status::Completed(_) => Signal::Success,
+            Status::TemporaryFailure(HostResponse { response, .. })
+                if is_throttle_response(response) =>
+            {
+                Signal::Throttled
+            }
+            _ => Signal::Neutral,
+        }
+    }
+
+    // Throttling takes precedence over any successful delivery
+    pub fn merge(self, other: Signal) -> Signal {
+        match (self, other) {
+            (Signal::Throttled, _) | (_, Signal::Throttled) => Signal::Throttled,
+            (Signal::Success, _) | (_, Signal::Success) => Signal::Success,
+            _ => Signal::Neutral,
+        }
+    }
+}
+
+pub fn is_throttle_response(response: &Response<String>) -> bool {
+    matches!(response.code, 421 | 450 | 451 | 452) && {
+        let message = response.message.to_lowercase();
+        message.contains("too many")
+            || message.contains("slow down")
+            || message
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|word| word.starts_with("rate") || word.starts_with("throttl"))
+    }
+}
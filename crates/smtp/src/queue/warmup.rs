@@ -0,0 +1,325 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ahash::AHashMap;
+use dashmap::DashMap;
+use mail_parser::DateTime;
+use serde::Serialize;
+use store::{LookupKey, LookupStore, LookupValue, Stores};
+use utils::config::Config;
+
+use crate::core::management::serialize_datetime;
+
+use super::throttle;
+
+const DAY: u64 = 86400;
+
+pub struct WarmUp {
+    schedule: Vec<u64>,
+    step: u64,
+    addresses: AHashMap<IpAddr, u64>,
+    providers: AHashMap<String, String>,
+    sent: DashMap<(IpAddr, String), SentCounter>,
+    store: Option<LookupStore>,
+}
+
+struct SentCounter {
+    day: u64,
+    count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmUpStatus {
+    pub address: IpAddr,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub start: DateTime,
+    pub stage: usize,
+    pub stages: usize,
+    #[serde(rename = "dailyLimit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_limit: Option<u64>,
+    pub complete: bool,
+    pub providers: Vec<ProviderUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderUsage {
+    pub name: String,
+    pub sent: u64,
+}
+
+impl WarmUp {
+    pub fn parse(config: &Config, stores: &Stores) -> crate::config::Result<Self> {
+        let mut schedule = Vec::new();
+        for (key, value) in config.values("queue.warm-up.schedule") {
+            schedule.push(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid daily limit {value:?} for property {key:?}."))?,
+            );
+        }
+        let step = config
+            .property_or_static::<Duration>("queue.warm-up.step", "1d")?
+            .as_secs()
+            .max(1);
+
+        let mut addresses = AHashMap::new();
+        for id in config.sub_keys("queue.warm-up.ip") {
+            let address = config.property_require::<IpAddr>(("queue.warm-up.ip", id, "address"))?;
+            let start = config.value_require(("queue.warm-up.ip", id, "start"))?;
+            let start = parse_start(start).ok_or_else(|| {
+                format!(
+                    "Invalid start date {start:?} for property \"queue.warm-up.ip.{id}.start\"."
+                )
+            })?;
+            if schedule.is_empty() {
+                return Err(format!(
+                    "Missing \"queue.warm-up.schedule\" for warm-up address {address}."
+                ));
+            }
+            addresses.insert(address, start);
+        }
+
+        let mut providers = AHashMap::new();
+        for name in config.sub_keys("queue.warm-up.provider") {
+            for (_, domain) in config.values(("queue.warm-up.provider", name)) {
+                providers.insert(domain.to_lowercase(), name.to_string());
+            }
+        }
+
+        Ok(WarmUp {
+            schedule,
+            step,
+            addresses,
+            providers,
+            sent: DashMap::new(),
+            store: if let Some(store) = stores.get_lookup_store(config, "queue.warm-up.store")? {
+                Some(store)
+            } else {
+                stores.get_lookup_store(config, "sieve.trusted.default.store")?
+            },
+        })
+    }
+
+    pub fn new() -> Self {
+        WarmUp {
+            schedule: Vec::new(),
+            step: DAY,
+            addresses: AHashMap::new(),
+            providers: AHashMap::new(),
+            sent: DashMap::new(),
+            store: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+
+    // Checks whether a source address can send another message to a provider today,
+    // messages are only counted once delivered by calling `record_sent`.
+    pub async fn is_allowed(&self, address: IpAddr, domain: &str) -> Result<(), throttle::Error> {
+        let now = now();
+        let limit = if let Some(limit) = self.limit(address, now) {
+            limit
+        } else {
+            return Ok(());
+        };
+
+        let provider = self.provider(domain);
+        let today = now / DAY;
+        let count = if let Some(store) = &self.store {
+            // Counters are kept in the store so that they survive restarts
+            // and are shared by all nodes sending from the same address
+            match store
+                .key_get::<String>(LookupKey::Counter(counter_key(address, provider, today)))
+                .await
+            {
+                Ok(LookupValue::Counter { num }) => {
+                    let count = num.max(0) as u64;
+                    self.update_cache(address, provider, today, count);
+                    count
+                }
+                Ok(_) => 0,
+                Err(err) => {
+                    tracing::debug!(
+                        context = "warm-up",
+                        event = "error",
+                        reason = %err,
+                        "Failed to read warm-up counter."
+                    );
+                    self.cached_count(address, provider, today)
+                }
+            }
+        } else {
+            self.cached_count(address, provider, today)
+        };
+
+        if count < limit {
+            Ok(())
+        } else {
+            Err(throttle::Error::Rate {
+                retry_at: Instant::now() + Duration::from_secs(DAY - (now % DAY)),
+            })
+        }
+    }
+
+    // Counts a message delivered from a source address towards its daily limit
+    pub async fn record_sent(&self, address: IpAddr, domain: &str) {
+        let now = now();
+        if self.limit(address, now).is_none() {
+            return;
+        }
+
+        let provider = self.provider(domain);
+        let today = now / DAY;
+        if let Some(store) = &self.store {
+            match store
+                .counter_incr(counter_key(address, provider, today), 1, 2 * DAY)
+                .await
+            {
+                Ok(count) => {
+                    self.update_cache(address, provider, today, count.max(0) as u64);
+                    return;
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        context = "warm-up",
+                        event = "error",
+                        reason = %err,
+                        "Failed to increment warm-up counter."
+                    );
+                }
+            }
+        }
+
+        let mut sent = self
+            .sent
+            .entry((address, provider.to_string()))
+            .or_insert(SentCounter {
+                day: today,
+                count: 0,
+            });
+        if sent.day != today {
+            sent.day = today;
+            sent.count = 0;
+        }
+        sent.count += 1;
+    }
+
+    pub fn status(&self) -> Vec<WarmUpStatus> {
+        let now = now();
+        let today = now / DAY;
+        let mut result = self
+            .addresses
+            .iter()
+            .map(|(address, start)| {
+                let stage = stage(*start, now, self.step);
+                let daily_limit = self.schedule.get(stage).copied();
+                let mut providers = self
+                    .sent
+                    .iter()
+                    .filter(|entry| entry.key().0 == *address && entry.day == today)
+                    .map(|entry| ProviderUsage {
+                        name: entry.key().1.clone(),
+                        sent: entry.count,
+                    })
+                    .collect::<Vec<_>>();
+                providers.sort_unstable_by(|a, b| b.sent.cmp(&a.sent));
+
+                WarmUpStatus {
+                    address: *address,
+                    start: DateTime::from_timestamp(*start as i64),
+                    stage: std::cmp::min(stage, self.schedule.len()),
+                    stages: self.schedule.len(),
+                    daily_limit,
+                    complete: daily_limit.is_none(),
+                    providers,
+                }
+            })
+            .collect::<Vec<_>>();
+        result.sort_unstable_by(|a, b| a.address.cmp(&b.address));
+        result
+    }
+
+    fn limit(&self, address: IpAddr, now: u64) -> Option<u64> {
+        self.addresses
+            .get(&address)
+            .and_then(|start| self.schedule.get(stage(*start, now, self.step)).copied())
+    }
+
+    fn cached_count(&self, address: IpAddr, provider: &str, today: u64) -> u64 {
+        self.sent
+            .get(&(address, provider.to_string()))
+            .filter(|sent| sent.day == today)
+            .map_or(0, |sent| sent.count)
+    }
+
+    fn update_cache(&self, address: IpAddr, provider: &str, today: u64, count: u64) {
+        self.sent.insert(
+            (address, provider.to_string()),
+            SentCounter { day: today, count },
+        );
+    }
+
+    fn provider<'x>(&'x self, domain: &'x str) -> &'x str {
+        self.providers
+            .get(domain)
+            .map(|provider| provider.as_str())
+            .unwrap_or(domain)
+    }
+}
+
+impl Default for WarmUp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn counter_key(address: IpAddr, provider: &str, day: u64) -> Vec<u8> {
+    format!("warm-up:{address}:{provider}:{day}").into_bytes()
+}
+
+fn stage(start: u64, now: u64, step: u64) -> usize {
+    (now.saturating_sub(start) / step) as usize
+}
+
+fn parse_start(value: &str) -> Option<u64> {
+    if value.len() == 10 {
+        DateTime::parse_rfc3339(&format!("{value}T00:00:00Z"))
+    } else {
+        DateTime::parse_rfc3339(value)
+    }
+    .map(|dt| dt.to_timestamp() as u64)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -32,12 +32,16 @@ use std::{
 use ahash::AHashMap;
 use mail_auth::{
     flate2::read::GzDecoder,
-    report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, Report},
+    report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, FeedbackType, Report},
     zip,
 };
-use mail_parser::{DateTime, MessageParser, MimeHeaders, PartType};
+use mail_parser::{DateTime, Message, MessageParser, MimeHeaders, PartType};
+use tokio::runtime::Handle;
 
-use crate::core::SMTP;
+use crate::{
+    core::SMTP,
+    queue::{history::HistoryDetails, suppression::SuppressionReason, DomainPart},
+};
 
 enum Compression {
     None,
@@ -64,6 +68,7 @@ pub trait AnalyzeReport {
 impl AnalyzeReport for Arc<SMTP> {
     fn analyze_report(&self, message: Arc<Vec<u8>>) {
         let core = self.clone();
+        let handle = Handle::current();
         self.worker_pool.spawn(move || {
             let message = if let Some(message) = MessageParser::default().parse(message.as_ref()) {
                 message
@@ -245,6 +250,34 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Arf => match Feedback::parse_arf(&data) {
                         Some(report) => {
                             report.log();
+                            let complaints = core.correlate_feedback(&report, &message);
+                            if !complaints.is_empty() {
+                                let core = core.clone();
+                                handle.spawn(async move {
+                                    for (domain, address, reporting_mta) in complaints {
+                                        if let Err(err) = core
+                                            .queue
+                                            .suppression
+                                            .add(
+                                                &domain,
+                                                &address,
+                                                SuppressionReason::Complaint,
+                                                reporting_mta,
+                                            )
+                                            .await
+                                        {
+                                            tracing::warn!(
+                                                context = "suppression",
+                                                event = "error",
+                                                domain = domain,
+                                                address = address,
+                                                reason = %err,
+                                                "Failed to add address to suppression list."
+                                            );
+                                        }
+                                    }
+                                });
+                            }
                         }
                         None => {
                             tracing::debug!(
@@ -301,6 +334,87 @@ impl AnalyzeReport for Arc<SMTP> {
     }
 }
 
+impl SMTP {
+    // Returns the domain, address and reporting MTA of the recipients that
+    // complained, which are to be added to the suppression list.
+    fn correlate_feedback(
+        &self,
+        feedback: &Feedback<'_>,
+        message: &Message<'_>,
+    ) -> Vec<(String, String, String)> {
+        // Obtain the Message-ID of the original message
+        let message_id = message.parts.iter().find_map(|part| match &part.body {
+            PartType::Message(original) => original.message_id().map(|id| id.to_string()),
+            PartType::Text(headers) if part.is_content_type("text", "rfc822-headers") => {
+                MessageParser::default()
+                    .parse_headers(headers.as_bytes())
+                    .and_then(|headers| headers.message_id().map(|id| id.to_string()))
+            }
+            _ => None,
+        });
+        let history = message_id
+            .as_deref()
+            .map(|message_id| self.queue.history.get_by_message_id(message_id))
+            .unwrap_or_default();
+        let queue_ids = history.iter().map(|history| history.id).collect::<Vec<_>>();
+
+        tracing::info!(
+            context = "arf",
+            event = "complaint",
+            feedback_type = ?feedback.feedback_type(),
+            message_id = message_id.as_deref().unwrap_or_default(),
+            original_rcpt_to = feedback.original_rcpt_to().unwrap_or_default(),
+            queue_ids = ?queue_ids,
+            "Feedback report received for {} queued message(s).",
+            queue_ids.len()
+        );
+
+        for queue_id in queue_ids {
+            self.queue.history.record(
+                queue_id,
+                HistoryDetails::Complaint {
+                    feedback_type: format!("{:?}", feedback.feedback_type()).to_lowercase(),
+                    reporting_mta: feedback.reporting_mta().map(|mta| mta.to_string()),
+                    source_ip: feedback.source_ip(),
+                    recipient: feedback.original_rcpt_to().map(|rcpt| rcpt.to_string()),
+                },
+            );
+        }
+
+        // Suppress further sends to the complaining recipient
+        let mut complaints = Vec::new();
+        if matches!(feedback.feedback_type(), FeedbackType::Abuse) {
+            for history in &history {
+                for event in &history.events {
+                    if let HistoryDetails::Received {
+                        return_path,
+                        recipients,
+                        ..
+                    } = &event.details
+                    {
+                        let recipient = feedback
+                            .original_rcpt_to()
+                            .map(|rcpt| rcpt.trim_matches(|c| c == '<' || c == '>'))
+                            .or_else(|| match recipients.as_slice() {
+                                [recipient] => Some(recipient.as_str()),
+                                _ => None,
+                            });
+                        if let Some(recipient) = recipient {
+                            complaints.push((
+                                return_path.domain_part().to_string(),
+                                recipient.to_string(),
+                                feedback.reporting_mta().unwrap_or_default().to_string(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        complaints
+    }
+}
+
 trait LogReport {
     fn log(&self);
 }
@@ -33,7 +33,10 @@ use mail_auth::{
 };
 use mail_parser::DateTime;
 
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
 
 use crate::{
     config::{AddressMatch, AggregateFrequency, DkimSigner, IfBlock, MaybeDynValue},
@@ -57,6 +60,7 @@ pub enum Event {
     Dmarc(Box<DmarcEvent>),
     Tls(Box<TlsEvent>),
     Manage(management::ReportRequest),
+    Ping(oneshot::Sender<()>),
     Stop,
 }
 
@@ -121,6 +125,21 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
 
         false
     }
+
+    pub fn role_mailbox(&self) -> Option<String> {
+        let analysis = &self.core.report.config.analysis;
+        let mailbox = analysis.role_mailbox.as_ref()?;
+        let rcpt = self.data.rcpt_to.last()?;
+        let (local_part, _) = rcpt.address_lcase.rsplit_once('@')?;
+
+        if &rcpt.address_lcase != mailbox
+            && analysis.role_accounts.iter().any(|role| role == local_part)
+        {
+            Some(mailbox.clone())
+        } else {
+            None
+        }
+    }
 }
 
 impl SMTP {
@@ -156,6 +156,9 @@ impl SpawnReport for mpsc::Receiver<Event> {
                                 let _ = result_tx.send(result);
                             }
                         },
+                        Event::Ping(result_tx) => {
+                            let _ = result_tx.send(());
+                        }
                         Event::Stop => break,
                     },
                     Ok(None) => break,
@@ -71,6 +71,21 @@ impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
                     .map(|r| r.result().as_str())
                     .unwrap_or_default(),
             )
+            .set_variable(
+                "spf.dnssec",
+                self.data
+                    .spf_dnssec
+                    .map(|status| status.as_str())
+                    .unwrap_or_default(),
+            )
+            .set_variable(
+                "early_talker",
+                Variable::Integer(self.data.early_talker as i64),
+            )
+            .set_variable(
+                "pipelining_violations",
+                Variable::Integer(self.data.pipelining_violations as i64),
+            )
             .set_variable("tls.version", tls_version)
             .set_variable("tls.cipher", tls_cipher)
             .set_variable("stage", stage);
@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::Span;
+
+use crate::{
+    core::{Session, SMTP},
+    inbound::IsTls,
+    scripts::ScriptResult,
+};
+
+use super::{PluginParameters, PluginStage};
+
+impl SMTP {
+    pub async fn run_plugins(
+        self: &Arc<Self>,
+        params: PluginParameters,
+        span: Span,
+    ) -> ScriptResult {
+        let core = self.clone();
+        self.spawn_worker(move || core.plugins.run(params, &span))
+            .await
+            .unwrap_or(ScriptResult::Accept {
+                modifications: vec![],
+            })
+    }
+}
+
+impl<T: AsyncWrite + AsyncRead + Unpin + IsTls> Session<T> {
+    pub fn build_plugin_parameters(&self, stage: PluginStage) -> PluginParameters {
+        let (tls_version, tls_cipher) = self.stream.tls_version_and_cipher();
+        let mut params = PluginParameters::new(stage)
+            .with_variable("stage", stage.as_str())
+            .with_variable("listener", self.instance.id.as_str())
+            .with_variable("remote_ip", self.data.remote_ip.to_string())
+            .with_variable("local_ip", self.data.local_ip.to_string())
+            .with_variable("helo_domain", self.data.helo_domain.to_lowercase())
+            .with_variable(
+                "authenticated_as",
+                self.data.authenticated_as.to_lowercase(),
+            )
+            .with_variable("tls.version", tls_version)
+            .with_variable("tls.cipher", tls_cipher);
+        if let Some(mail_from) = &self.data.mail_from {
+            params = params.with_variable("sender", mail_from.address_lcase.as_str());
+        }
+        if let Some(rcpt) = self.data.rcpt_to.last() {
+            params = params.with_variable("recipient", rcpt.address_lcase.as_str());
+        }
+
+        params.with_recipients(
+            self.data
+                .rcpt_to
+                .iter()
+                .map(|rcpt| rcpt.address_lcase.clone())
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,347 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use wasmtime::{Caller, Engine, Extern, Linker, StoreLimits};
+
+use super::{PluginInput, HOST_API_MODULE};
+
+// Return codes of the host functions, non-negative values are lengths
+pub const RESULT_NOT_FOUND: i32 = -1;
+pub const RESULT_INVALID: i32 = -2;
+
+const MAX_STRING_LEN: usize = 64 * 1024;
+const MAX_ADD_HEADERS: usize = 32;
+
+pub struct HostState {
+    pub plugin_id: String,
+    pub(crate) input: Arc<PluginInput>,
+    pub reply: Option<String>,
+    pub add_headers: Vec<(String, String)>,
+    pub limits: StoreLimits,
+}
+
+impl HostState {
+    pub(crate) fn new(plugin_id: String, input: Arc<PluginInput>, limits: StoreLimits) -> Self {
+        HostState {
+            plugin_id,
+            input,
+            reply: None,
+            add_headers: Vec::new(),
+            limits,
+        }
+    }
+}
+
+// Builds the host API available to plugins. Functions that return data copy at
+// most `buf_len` bytes into the guest buffer and return the full length of the
+// value, so plugins can retry with a larger buffer when the result is truncated.
+pub(crate) fn build_linker(engine: &Engine) -> wasmtime::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+
+    // Session and envelope variables, such as "remote_ip" or "sender"
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "get_var",
+        |mut caller: Caller<'_, HostState>,
+         name_ptr: i32,
+         name_len: i32,
+         buf_ptr: i32,
+         buf_len: i32|
+         -> i32 {
+            let name = if let Some(name) = read_string(&mut caller, name_ptr, name_len) {
+                name
+            } else {
+                return RESULT_INVALID;
+            };
+            let input = caller.data().input.clone();
+            if let Some(value) = input.params.variables.get(name.as_str()) {
+                write_bytes(&mut caller, buf_ptr, buf_len, value.as_bytes())
+            } else {
+                RESULT_NOT_FOUND
+            }
+        },
+    )?;
+
+    // Envelope recipients
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "rcpt_count",
+        |caller: Caller<'_, HostState>| -> i32 {
+            caller.data().input.params.recipients.len() as i32
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "get_rcpt",
+        |mut caller: Caller<'_, HostState>, index: i32, buf_ptr: i32, buf_len: i32| -> i32 {
+            let input = caller.data().input.clone();
+            if let Some(rcpt) = usize::try_from(index)
+                .ok()
+                .and_then(|index| input.params.recipients.get(index))
+            {
+                write_bytes(&mut caller, buf_ptr, buf_len, rcpt.as_bytes())
+            } else {
+                RESULT_NOT_FOUND
+            }
+        },
+    )?;
+
+    // Message headers, names are case insensitive
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "header_count",
+        |mut caller: Caller<'_, HostState>, name_ptr: i32, name_len: i32| -> i32 {
+            let name = if let Some(name) = read_string(&mut caller, name_ptr, name_len) {
+                name.to_lowercase()
+            } else {
+                return RESULT_INVALID;
+            };
+            caller
+                .data()
+                .input
+                .headers
+                .iter()
+                .filter(|(header, _, _)| *header == name)
+                .count() as i32
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "get_header",
+        |mut caller: Caller<'_, HostState>,
+         name_ptr: i32,
+         name_len: i32,
+         index: i32,
+         buf_ptr: i32,
+         buf_len: i32|
+         -> i32 {
+            let name = if let Some(name) = read_string(&mut caller, name_ptr, name_len) {
+                name.to_lowercase()
+            } else {
+                return RESULT_INVALID;
+            };
+            let input = caller.data().input.clone();
+            let value = usize::try_from(index).ok().and_then(|index| {
+                input
+                    .headers
+                    .iter()
+                    .filter(|(header, _, _)| *header == name)
+                    .nth(index)
+                    .and_then(|(_, start, end)| input.params.message.as_ref()?.get(*start..*end))
+            });
+            if let Some(value) = value {
+                write_bytes(&mut caller, buf_ptr, buf_len, trim_bytes(value))
+            } else {
+                RESULT_NOT_FOUND
+            }
+        },
+    )?;
+
+    // Raw message access, the body can be streamed by reading it in chunks
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "message_size",
+        |caller: Caller<'_, HostState>| -> i64 {
+            caller
+                .data()
+                .input
+                .params
+                .message
+                .as_ref()
+                .map_or(RESULT_NOT_FOUND as i64, |message| message.len() as i64)
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "body_offset",
+        |caller: Caller<'_, HostState>| -> i64 {
+            let input = &caller.data().input;
+            if input.params.message.is_some() {
+                input.body_offset as i64
+            } else {
+                RESULT_NOT_FOUND as i64
+            }
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "read_message",
+        |mut caller: Caller<'_, HostState>, offset: i64, buf_ptr: i32, buf_len: i32| -> i32 {
+            let input = caller.data().input.clone();
+            let message = if let Some(message) = &input.params.message {
+                message
+            } else {
+                return RESULT_NOT_FOUND;
+            };
+            let (offset, buf_len) = if let (Ok(offset), Ok(len)) =
+                (usize::try_from(offset), usize::try_from(buf_len))
+            {
+                (offset, len)
+            } else {
+                return RESULT_INVALID;
+            };
+            let chunk = message
+                .get(offset..std::cmp::min(offset.saturating_add(buf_len), message.len()))
+                .unwrap_or_default();
+            if chunk.is_empty() || write_bytes(&mut caller, buf_ptr, chunk.len() as i32, chunk) >= 0
+            {
+                chunk.len() as i32
+            } else {
+                RESULT_INVALID
+            }
+        },
+    )?;
+
+    // Actions
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "add_header",
+        |mut caller: Caller<'_, HostState>,
+         name_ptr: i32,
+         name_len: i32,
+         value_ptr: i32,
+         value_len: i32|
+         -> i32 {
+            match (
+                read_string(&mut caller, name_ptr, name_len),
+                read_string(&mut caller, value_ptr, value_len),
+            ) {
+                (Some(name), Some(value))
+                    if !name.is_empty()
+                        && name.bytes().all(|ch| ch.is_ascii_graphic() && ch != b':')
+                        && !value.contains(['\r', '\n'])
+                        && caller.data().add_headers.len() < MAX_ADD_HEADERS =>
+                {
+                    caller.data_mut().add_headers.push((name, value));
+                    0
+                }
+                _ => RESULT_INVALID,
+            }
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "set_reply",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+            if let Some(reply) = read_string(&mut caller, ptr, len) {
+                caller.data_mut().reply = reply.into();
+                0
+            } else {
+                RESULT_INVALID
+            }
+        },
+    )?;
+    linker.func_wrap(
+        HOST_API_MODULE,
+        "log",
+        |mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+            if let Some(message) = read_string(&mut caller, ptr, len) {
+                let plugin = caller.data().plugin_id.as_str();
+                match level {
+                    0 => tracing::error!(
+                        context = "wasm",
+                        event = "log",
+                        plugin = plugin,
+                        "{}",
+                        message
+                    ),
+                    1 => tracing::warn!(
+                        context = "wasm",
+                        event = "log",
+                        plugin = plugin,
+                        "{}",
+                        message
+                    ),
+                    2 => tracing::info!(
+                        context = "wasm",
+                        event = "log",
+                        plugin = plugin,
+                        "{}",
+                        message
+                    ),
+                    _ => tracing::debug!(
+                        context = "wasm",
+                        event = "log",
+                        plugin = plugin,
+                        "{}",
+                        message
+                    ),
+                }
+            }
+        },
+    )?;
+
+    Ok(linker)
+}
+
+fn read_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= MAX_STRING_LEN)?;
+    let mut buf = vec![0u8; len];
+    memory
+        .read(&*caller, u32::try_from(ptr).ok()? as usize, &mut buf)
+        .ok()?;
+    String::from_utf8(buf).ok()
+}
+
+fn write_bytes(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32, bytes: &[u8]) -> i32 {
+    let (memory, ptr, len) = match (
+        caller.get_export("memory").and_then(Extern::into_memory),
+        u32::try_from(ptr),
+        usize::try_from(len),
+    ) {
+        (Some(memory), Ok(ptr), Ok(len)) => (memory, ptr as usize, len),
+        _ => return RESULT_INVALID,
+    };
+    let bytes_len = std::cmp::min(bytes.len(), i32::MAX as usize);
+    if memory
+        .write(&mut *caller, ptr, &bytes[..std::cmp::min(len, bytes_len)])
+        .is_ok()
+    {
+        bytes_len as i32
+    } else {
+        RESULT_INVALID
+    }
+}
+
+fn trim_bytes(mut bytes: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = bytes {
+        if first.is_ascii_whitespace() {
+            bytes = rest;
+        } else {
+            break;
+        }
+    }
+    while let [rest @ .., last] = bytes {
+        if last.is_ascii_whitespace() {
+            bytes = rest;
+        } else {
+            break;
+        }
+    }
+    bytes
+}
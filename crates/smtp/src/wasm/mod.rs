@@ -0,0 +1,392 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ahash::AHashMap;
+use mail_parser::MessageParser;
+use parking_lot::Mutex;
+use tracing::Span;
+use utils::config::Config;
+use wasmtime::{Engine, Linker, Module, Store, StoreLimitsBuilder};
+
+use crate::{
+    inbound::smtp_reply,
+    scripts::{ScriptModification, ScriptResult},
+};
+
+use self::host::HostState;
+
+pub mod exec;
+pub mod host;
+
+// Namespace of the host functions, bumped on incompatible API changes
+pub const HOST_API_MODULE: &str = "stalwart_v1";
+
+pub const VERDICT_ACCEPT: i32 = 0;
+pub const VERDICT_REJECT: i32 = 1;
+pub const VERDICT_DISCARD: i32 = 2;
+pub const VERDICT_TEMPFAIL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStage {
+    Connect,
+    Mail,
+    Rcpt,
+    Data,
+    Ingest,
+}
+
+pub struct Plugins {
+    engine: Engine,
+    linker: Linker<HostState>,
+    plugins: Vec<Plugin>,
+}
+
+pub struct Plugin {
+    pub id: String,
+    pub path: PathBuf,
+    pub fuel: u64,
+    pub max_memory: usize,
+    pub tempfail_on_error: bool,
+    pub reload_interval: Duration,
+    state: Mutex<PluginState>,
+}
+
+struct PluginState {
+    module: Module,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+pub struct PluginParameters {
+    pub stage: PluginStage,
+    pub variables: AHashMap<&'static str, String>,
+    pub recipients: Vec<String>,
+    pub message: Option<Arc<Vec<u8>>>,
+}
+
+// Message contents shared by all plugins invoked for the same stage
+pub(crate) struct PluginInput {
+    pub params: PluginParameters,
+    pub headers: Vec<(String, usize, usize)>,
+    pub body_offset: usize,
+}
+
+impl Plugins {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let mut plugins = Plugins::new()?;
+
+        for id in config.sub_keys("plugin") {
+            if !config.property_or_static::<bool>(("plugin", id, "enable"), "true")? {
+                continue;
+            }
+            let path = config.property_require::<PathBuf>(("plugin", id, "path"))?;
+            let module = plugins.load(&path).map_err(|err| {
+                format!(
+                    "Failed to load WebAssembly plugin {id:?} from {}: {err}",
+                    path.display()
+                )
+            })?;
+
+            plugins.plugins.push(Plugin {
+                id: id.to_string(),
+                fuel: config.property_or_static(("plugin", id, "limits.fuel"), "100000000")?,
+                max_memory: config
+                    .property_or_static(("plugin", id, "limits.memory"), "16777216")?,
+                tempfail_on_error: config
+                    .property_or_static(("plugin", id, "tempfail-on-error"), "false")?,
+                reload_interval: config
+                    .property_or_static(("plugin", id, "reload-interval"), "30s")?,
+                state: Mutex::new(PluginState {
+                    module,
+                    modified: std::fs::metadata(&path).and_then(|m| m.modified()).ok(),
+                    last_check: Instant::now(),
+                }),
+                path,
+            });
+        }
+
+        Ok(plugins)
+    }
+
+    pub fn new() -> crate::config::Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)
+            .map_err(|err| format!("Failed to build WebAssembly engine: {err}"))?;
+        let linker = host::build_linker(&engine)
+            .map_err(|err| format!("Failed to build WebAssembly host API: {err}"))?;
+
+        Ok(Plugins {
+            engine,
+            linker,
+            plugins: Vec::new(),
+        })
+    }
+
+    fn load(&self, path: &PathBuf) -> wasmtime::Result<Module> {
+        let module = Module::from_file(&self.engine, path)?;
+        if module.get_export("memory").is_none() {
+            return Err(wasmtime::Error::msg("Plugin does not export its memory."));
+        }
+        Ok(module)
+    }
+
+    pub fn has_stage(&self, stage: PluginStage) -> bool {
+        self.plugins.iter().any(|plugin| {
+            plugin
+                .state
+                .lock()
+                .module
+                .get_export(stage.as_fnc())
+                .is_some()
+        })
+    }
+
+    // Runs all plugins that implement a stage in configuration order. Plugins are
+    // executed on the worker pool as they may block for up to their fuel limit.
+    pub fn run(&self, params: PluginParameters, span: &Span) -> ScriptResult {
+        let stage = params.stage;
+        let input = Arc::new(PluginInput::new(params));
+        let mut modifications = Vec::new();
+
+        for plugin in &self.plugins {
+            let module = if let Some(module) = self.module(plugin, stage) {
+                module
+            } else {
+                continue;
+            };
+
+            match self.run_plugin(plugin, &module, input.clone()) {
+                Ok((verdict, state)) => {
+                    tracing::debug!(parent: span,
+                        context = "wasm",
+                        event = "verdict",
+                        plugin = plugin.id,
+                        stage = stage.as_str(),
+                        verdict = verdict);
+
+                    match verdict {
+                        VERDICT_REJECT => {
+                            return ScriptResult::Reject(smtp_reply(
+                                "550 5.7.1",
+                                "Message rejected by policy.",
+                                state.reply,
+                            ));
+                        }
+                        VERDICT_TEMPFAIL => {
+                            return ScriptResult::Reject(smtp_reply(
+                                "451 4.7.1",
+                                "Temporary failure, please try again later.",
+                                state.reply,
+                            ));
+                        }
+                        VERDICT_DISCARD
+                            if matches!(stage, PluginStage::Data | PluginStage::Ingest) =>
+                        {
+                            return ScriptResult::Discard;
+                        }
+                        _ => {
+                            modifications.extend(state.add_headers.into_iter().map(
+                                |(name, value)| ScriptModification::AddHeader {
+                                    name: name.into(),
+                                    value: value.into(),
+                                },
+                            ));
+                        }
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(parent: span,
+                        context = "wasm",
+                        event = "error",
+                        plugin = plugin.id,
+                        stage = stage.as_str(),
+                        reason = %err,
+                        "WebAssembly plugin failed.");
+
+                    if plugin.tempfail_on_error {
+                        return ScriptResult::Reject(
+                            "451 4.3.0 Temporary failure, please try again later.\r\n".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        ScriptResult::Accept { modifications }
+    }
+
+    fn run_plugin(
+        &self,
+        plugin: &Plugin,
+        module: &Module,
+        input: Arc<PluginInput>,
+    ) -> wasmtime::Result<(i32, HostState)> {
+        let stage = input.params.stage;
+        let mut store = Store::new(
+            &self.engine,
+            HostState::new(
+                plugin.id.clone(),
+                input,
+                StoreLimitsBuilder::new()
+                    .memory_size(plugin.max_memory)
+                    .instances(1)
+                    .build(),
+            ),
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(plugin.fuel)?;
+
+        let instance = self.linker.instantiate(&mut store, module)?;
+        let verdict = instance
+            .get_typed_func::<(), i32>(&mut store, stage.as_fnc())?
+            .call(&mut store, ())?;
+
+        Ok((verdict, store.into_data()))
+    }
+
+    // Returns the compiled module of a plugin if it implements the stage,
+    // recompiling it first when the file changed on disk.
+    fn module(&self, plugin: &Plugin, stage: PluginStage) -> Option<Module> {
+        let mut state = plugin.state.lock();
+        if !plugin.reload_interval.is_zero() && state.last_check.elapsed() >= plugin.reload_interval
+        {
+            state.last_check = Instant::now();
+            let modified = std::fs::metadata(&plugin.path)
+                .and_then(|m| m.modified())
+                .ok();
+            if modified.is_some() && modified != state.modified {
+                state.modified = modified;
+                match self.load(&plugin.path) {
+                    Ok(module) => {
+                        tracing::info!(
+                            context = "wasm",
+                            event = "reload",
+                            plugin = plugin.id,
+                            path = %plugin.path.display(),
+                            "Reloaded WebAssembly plugin.");
+                        state.module = module;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "wasm",
+                            event = "error",
+                            plugin = plugin.id,
+                            path = %plugin.path.display(),
+                            reason = %err,
+                            "Failed to reload WebAssembly plugin, keeping previous version.");
+                    }
+                }
+            }
+        }
+
+        if state.module.get_export(stage.as_fnc()).is_some() {
+            Some(state.module.clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl PluginStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PluginStage::Connect => "connect",
+            PluginStage::Mail => "mail",
+            PluginStage::Rcpt => "rcpt",
+            PluginStage::Data => "data",
+            PluginStage::Ingest => "ingest",
+        }
+    }
+
+    // Name of the function a plugin exports to handle this stage
+    pub fn as_fnc(&self) -> &'static str {
+        match self {
+            PluginStage::Connect => "on_connect",
+            PluginStage::Mail => "on_mail",
+            PluginStage::Rcpt => "on_rcpt",
+            PluginStage::Data => "on_data",
+            PluginStage::Ingest => "on_ingest",
+        }
+    }
+}
+
+impl PluginParameters {
+    pub fn new(stage: PluginStage) -> Self {
+        PluginParameters {
+            stage,
+            variables: AHashMap::with_capacity(10),
+            recipients: Vec::new(),
+            message: None,
+        }
+    }
+
+    pub fn with_variable(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.variables.insert(name, value.into());
+        self
+    }
+
+    pub fn with_recipients(mut self, recipients: Vec<String>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    pub fn with_message(mut self, message: Arc<Vec<u8>>) -> Self {
+        self.message = message.into();
+        self
+    }
+}
+
+impl PluginInput {
+    fn new(params: PluginParameters) -> Self {
+        let mut headers = Vec::new();
+        let mut body_offset = 0;
+        if let Some(message) = params
+            .message
+            .as_ref()
+            .and_then(|message| MessageParser::new().parse_headers(message.as_slice()))
+        {
+            let root = message.root_part();
+            body_offset = root.offset_body;
+            for header in &root.headers {
+                headers.push((
+                    header.name.as_str().to_lowercase(),
+                    header.offset_start,
+                    header.offset_end,
+                ));
+            }
+        }
+
+        PluginInput {
+            params,
+            headers,
+            body_offset,
+        }
+    }
+}
@@ -0,0 +1,427 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{io::Read, ops::Range, path::PathBuf};
+
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use crate::{BlobStore, U32_LEN};
+
+// Blobs written through this store start with a marker followed by the codec,
+// blobs without it were written before compression was enabled.
+const BLOB_MAGIC: &[u8] = b"\x00\xfbSTWB";
+const HEADER_LEN: usize = BLOB_MAGIC.len() + 1;
+
+// Data is compressed in independent chunks so that ranged reads only fetch and
+// decompress the chunks they need. Chunked blobs are framed as the header, the
+// chunk size, the number of chunks and the compressed length of each chunk.
+const CHUNKED: u8 = 0x80;
+const CHUNK_SIZE: usize = 64 * 1024;
+const FRAME_HEADER_LEN: usize = HEADER_LEN + 2 * U32_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+pub struct CompressedStore {
+    inner: BlobStore,
+    text: Codec,
+    binary: Codec,
+    level: i32,
+    dictionary: Option<Vec<u8>>,
+}
+
+impl CompressedStore {
+    pub fn open(
+        config: &Config,
+        prefix: impl AsKey,
+        inner: BlobStore,
+    ) -> utils::config::Result<Self> {
+        let prefix = prefix.as_key();
+        let dictionary =
+            if let Some(path) = config.property::<PathBuf>((&prefix, "compression.dictionary"))? {
+                Some(std::fs::read(&path).map_err(|err| {
+                    format!("Failed to read compression dictionary {path:?}: {err}")
+                })?)
+            } else {
+                None
+            };
+
+        Ok(CompressedStore {
+            inner,
+            text: config.property_or_static((&prefix, "compression.text"), "zstd")?,
+            binary: config.property_or_static((&prefix, "compression.binary"), "none")?,
+            level: config.property_or_static((&prefix, "compression.level"), "3")?,
+            dictionary,
+        })
+    }
+
+    pub fn is_enabled(config: &Config, prefix: impl AsKey) -> bool {
+        let prefix = prefix.as_key();
+        config
+            .value((&prefix, "compression.text"))
+            .or_else(|| config.value((&prefix, "compression.binary")))
+            .is_some()
+    }
+
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<u32>,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        if range.start == 0 && range.end == u32::MAX {
+            return if let Some(data) = Box::pin(self.inner.get_blob(key, range)).await? {
+                self.decode(data).map(Some)
+            } else {
+                Ok(None)
+            };
+        }
+
+        let header = if let Some(header) =
+            Box::pin(self.inner.get_blob(key, 0..FRAME_HEADER_LEN as u32)).await?
+        {
+            header
+        } else {
+            return Ok(None);
+        };
+        if !header.starts_with(BLOB_MAGIC) || header.len() < HEADER_LEN {
+            // Written before compression was enabled
+            return Box::pin(self.inner.get_blob(key, range)).await;
+        }
+
+        match header[BLOB_MAGIC.len()] {
+            0 => {
+                Box::pin(self.inner.get_blob(
+                    key,
+                    range.start.saturating_add(HEADER_LEN as u32)
+                        ..range.end.saturating_add(HEADER_LEN as u32),
+                ))
+                .await
+            }
+            codec if codec & CHUNKED != 0 && header.len() == FRAME_HEADER_LEN => {
+                self.get_chunked_range(key, codec & !CHUNKED, &header, range)
+                    .await
+            }
+            _ => {
+                // Blobs compressed as a whole have to be read in full
+                let data =
+                    if let Some(data) = Box::pin(self.inner.get_blob(key, 0..u32::MAX)).await? {
+                        self.decode(data)?
+                    } else {
+                        return Ok(None);
+                    };
+                let end = std::cmp::min(range.end as usize, data.len());
+                let start = std::cmp::min(range.start as usize, end);
+                Ok(Some(data[start..end].to_vec()))
+            }
+        }
+    }
+
+    async fn get_chunked_range(
+        &self,
+        key: &[u8],
+        codec: u8,
+        header: &[u8],
+        range: Range<u32>,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        let (chunk_size, num_chunks) = read_frame_header(header)?;
+        let first_chunk = range.start as usize / chunk_size;
+        let last_chunk = std::cmp::min(
+            (range.end as usize).saturating_sub(1) / chunk_size,
+            num_chunks.saturating_sub(1),
+        );
+        if range.start >= range.end || first_chunk >= num_chunks {
+            return Ok(Some(Vec::new()));
+        }
+
+        // Obtain the offsets of the chunks to read from the index
+        let index_len = num_chunks * U32_LEN;
+        let index = if let Some(index) = Box::pin(self.inner.get_blob(
+            key,
+            FRAME_HEADER_LEN as u32..(FRAME_HEADER_LEN + index_len) as u32,
+        ))
+        .await?
+        {
+            index
+        } else {
+            return Ok(None);
+        };
+        if index.len() != index_len {
+            return Err(crate::Error::InternalError(
+                "Truncated compressed blob index".into(),
+            ));
+        }
+        let chunk_lens = index
+            .chunks_exact(U32_LEN)
+            .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize)
+            .collect::<Vec<_>>();
+        let offset = FRAME_HEADER_LEN + index_len + chunk_lens[..first_chunk].iter().sum::<usize>();
+        let len = chunk_lens[first_chunk..=last_chunk].iter().sum::<usize>();
+
+        let compressed = if let Some(compressed) = Box::pin(
+            self.inner
+                .get_blob(key, offset as u32..(offset + len) as u32),
+        )
+        .await?
+        {
+            compressed
+        } else {
+            return Ok(None);
+        };
+        if compressed.len() != len {
+            return Err(crate::Error::InternalError(
+                "Truncated compressed blob".into(),
+            ));
+        }
+
+        let mut data = Vec::with_capacity((last_chunk - first_chunk + 1) * chunk_size);
+        let mut compressed = compressed.as_slice();
+        for chunk_len in &chunk_lens[first_chunk..=last_chunk] {
+            let (chunk, rest) = compressed.split_at(*chunk_len);
+            data.extend_from_slice(&self.decompress_chunk(codec, chunk, chunk_size)?);
+            compressed = rest;
+        }
+
+        let base = first_chunk * chunk_size;
+        let end = std::cmp::min(range.end as usize - base, data.len());
+        let start = std::cmp::min(range.start as usize - base, end);
+        data.truncate(end);
+        data.drain(..start);
+        Ok(Some(data))
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+        Box::pin(self.inner.put_blob(key, &self.encode(data)?)).await
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        Box::pin(self.inner.delete_blob(key)).await
+    }
+
+    pub(crate) async fn compress_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        match Box::pin(self.inner.get_blob(key, 0..u32::MAX)).await? {
+            Some(data) if !data.starts_with(BLOB_MAGIC) => {
+                Box::pin(self.inner.put_blob(key, &self.encode(&data)?)).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let codec = if is_binary(data) {
+            self.binary
+        } else {
+            self.text
+        };
+
+        if codec != Codec::None {
+            let num_chunks = data.len().div_ceil(CHUNK_SIZE);
+            let mut index = Vec::with_capacity(num_chunks * U32_LEN);
+            let mut chunks = Vec::with_capacity(data.len() / 2);
+            for chunk in data.chunks(CHUNK_SIZE) {
+                let compressed = self.compress_chunk(codec, chunk)?;
+                index.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+                chunks.extend_from_slice(&compressed);
+            }
+
+            // Keep the original data when compression does not pay off
+            if FRAME_HEADER_LEN + index.len() + chunks.len() < HEADER_LEN + data.len() {
+                let mut blob = Vec::with_capacity(FRAME_HEADER_LEN + index.len() + chunks.len());
+                blob.extend_from_slice(BLOB_MAGIC);
+                blob.push(codec as u8 | CHUNKED);
+                blob.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+                blob.extend_from_slice(&(num_chunks as u32).to_be_bytes());
+                blob.extend_from_slice(&index);
+                blob.extend_from_slice(&chunks);
+                return Ok(blob);
+            }
+        }
+
+        let mut blob = Vec::with_capacity(HEADER_LEN + data.len());
+        blob.extend_from_slice(BLOB_MAGIC);
+        blob.push(Codec::None as u8);
+        blob.extend_from_slice(data);
+        Ok(blob)
+    }
+
+    fn decode(&self, mut data: Vec<u8>) -> crate::Result<Vec<u8>> {
+        if !data.starts_with(BLOB_MAGIC) || data.len() < HEADER_LEN {
+            return Ok(data);
+        }
+
+        match data[BLOB_MAGIC.len()] {
+            0 => {
+                data.drain(..HEADER_LEN);
+                Ok(data)
+            }
+            1 => lz4_flex::decompress_size_prepended(&data[HEADER_LEN..]).map_err(|err| {
+                crate::Error::InternalError(format!("Lz4 decompression failed: {err}"))
+            }),
+            2 => {
+                let mut result = Vec::with_capacity(data.len() * 2);
+                if let Some(dictionary) = &self.dictionary {
+                    zstd::stream::read::Decoder::with_dictionary(&data[HEADER_LEN..], dictionary)
+                        .and_then(|mut decoder| decoder.read_to_end(&mut result))
+                } else {
+                    zstd::stream::read::Decoder::with_buffer(&data[HEADER_LEN..])
+                        .and_then(|mut decoder| decoder.read_to_end(&mut result))
+                }
+                .map_err(|err| {
+                    crate::Error::InternalError(format!("Zstd decompression failed: {err}"))
+                })?;
+                Ok(result)
+            }
+            codec if codec & CHUNKED != 0 && data.len() >= FRAME_HEADER_LEN => {
+                let (chunk_size, num_chunks) = read_frame_header(&data[..FRAME_HEADER_LEN])?;
+                let index_end = FRAME_HEADER_LEN + num_chunks * U32_LEN;
+                let index = data.get(FRAME_HEADER_LEN..index_end).ok_or_else(|| {
+                    crate::Error::InternalError("Truncated compressed blob index".into())
+                })?;
+                let mut compressed = &data[index_end..];
+                let mut result = Vec::with_capacity(num_chunks * chunk_size);
+                for chunk_len in index.chunks_exact(U32_LEN) {
+                    let chunk_len = u32::from_be_bytes(chunk_len.try_into().unwrap()) as usize;
+                    if chunk_len > compressed.len() {
+                        return Err(crate::Error::InternalError(
+                            "Truncated compressed blob".into(),
+                        ));
+                    }
+                    let (chunk, rest) = compressed.split_at(chunk_len);
+                    result.extend_from_slice(&self.decompress_chunk(
+                        codec & !CHUNKED,
+                        chunk,
+                        chunk_size,
+                    )?);
+                    compressed = rest;
+                }
+                Ok(result)
+            }
+            codec => Err(crate::Error::InternalError(format!(
+                "Unknown blob compression codec {codec}"
+            ))),
+        }
+    }
+
+    fn compress_chunk(&self, codec: Codec, data: &[u8]) -> crate::Result<Vec<u8>> {
+        match codec {
+            Codec::None => Ok(data.to_vec()),
+            Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            Codec::Zstd => if let Some(dictionary) = &self.dictionary {
+                zstd::bulk::Compressor::with_dictionary(self.level, dictionary)
+                    .and_then(|mut compressor| compressor.compress(data))
+            } else {
+                zstd::bulk::compress(data, self.level)
+            }
+            .map_err(|err| crate::Error::InternalError(format!("Zstd compression failed: {err}"))),
+        }
+    }
+
+    fn decompress_chunk(
+        &self,
+        codec: u8,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> crate::Result<Vec<u8>> {
+        match codec {
+            1 => lz4_flex::decompress_size_prepended(data).map_err(|err| {
+                crate::Error::InternalError(format!("Lz4 decompression failed: {err}"))
+            }),
+            2 => if let Some(dictionary) = &self.dictionary {
+                zstd::bulk::Decompressor::with_dictionary(dictionary)
+                    .and_then(|mut decompressor| decompressor.decompress(data, chunk_size))
+            } else {
+                zstd::bulk::decompress(data, chunk_size)
+            }
+            .map_err(|err| {
+                crate::Error::InternalError(format!("Zstd decompression failed: {err}"))
+            }),
+            codec => Err(crate::Error::InternalError(format!(
+                "Unknown blob compression codec {codec}"
+            ))),
+        }
+    }
+}
+
+fn read_frame_header(header: &[u8]) -> crate::Result<(usize, usize)> {
+    let chunk_size =
+        u32::from_be_bytes(header[HEADER_LEN..HEADER_LEN + U32_LEN].try_into().unwrap()) as usize;
+    let num_chunks = u32::from_be_bytes(
+        header[HEADER_LEN + U32_LEN..FRAME_HEADER_LEN]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    if chunk_size > 0 {
+        Ok((chunk_size, num_chunks))
+    } else {
+        Err(crate::Error::InternalError(
+            "Invalid compressed blob chunk size".into(),
+        ))
+    }
+}
+
+// Formats that are already compressed gain nothing from another pass
+fn is_binary(data: &[u8]) -> bool {
+    const SIGNATURES: &[&[u8]] = &[
+        b"\x1f\x8b",           // gzip
+        b"PK\x03\x04",         // zip, docx, xlsx
+        b"\x89PNG",            // png
+        b"\xff\xd8\xff",       // jpeg
+        b"GIF8",               // gif
+        b"%PDF-",              // pdf
+        b"\x28\xb5\x2f\xfd",   // zstd
+        b"\xfd7zXZ\x00",       // xz
+        b"BZh",                // bzip2
+        b"7z\xbc\xaf\x27\x1c", // 7z
+        b"Rar!",               // rar
+        b"OggS",               // ogg
+        b"ID3",                // mp3
+        b"\x1aE\xdf\xa3",      // matroska, webm
+    ];
+
+    SIGNATURES
+        .iter()
+        .any(|signature| data.starts_with(signature))
+        || (data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP")
+        || (data.len() > 8 && &data[4..8] == b"ftyp")
+}
+
+impl ParseValue for Codec {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        match value {
+            "none" | "false" => Ok(Codec::None),
+            "lz4" => Ok(Codec::Lz4),
+            "zstd" => Ok(Codec::Zstd),
+            _ => Err(format!(
+                "Invalid compression codec {value:?} for key {:?}.",
+                key.as_key()
+            )),
+        }
+    }
+}
@@ -116,6 +116,20 @@ impl FdbStore {
 
                         trx.atomic_op(&key, &by.to_le_bytes()[..], MutationType::Add);
                     }
+                    Operation::Value {
+                        class,
+                        op: ValueOp::ClearCounter,
+                    } => {
+                        let key = ValueKey {
+                            account_id,
+                            collection,
+                            document_id,
+                            class,
+                        }
+                        .serialize(WITH_SUBSPACE);
+
+                        trx.clear(&key);
+                    }
                     Operation::Value { class, op } => {
                         let mut key = ValueKey {
                             account_id,
@@ -30,11 +30,13 @@ use tokio::{
 use utils::{
     codec::base32_custom::Base32Writer,
     config::{utils::AsKey, Config},
+    durability::FileSync,
 };
 
 pub struct FsStore {
     path: PathBuf,
     hash_levels: usize,
+    sync: FileSync,
 }
 
 impl FsStore {
@@ -53,6 +55,7 @@ impl FsStore {
         Ok(FsStore {
             path,
             hash_levels: std::cmp::min(config.property_or_static((&prefix, "depth"), "2")?, 5),
+            sync: FileSync::parse(config, (&prefix, "durability"))?,
         })
     }
 
@@ -100,6 +103,8 @@ impl FsStore {
             let mut blob_file = File::create(&blob_path).await?;
             blob_file.write_all(data).await?;
             blob_file.flush().await?;
+            self.sync.sync_file(&blob_file).await?;
+            self.sync.sync_path(&blob_path).await;
         }
 
         Ok(())
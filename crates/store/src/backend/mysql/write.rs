@@ -120,6 +120,21 @@ impl MysqlStore {
                         trx.exec_drop(&s, (by, key)).await?;
                     }
                 }
+                Operation::Value {
+                    class,
+                    op: ValueOp::ClearCounter,
+                } => {
+                    let key = ValueKey {
+                        account_id,
+                        collection,
+                        document_id,
+                        class,
+                    }
+                    .serialize(0);
+
+                    let s = trx.prep("DELETE FROM c WHERE k = ?").await?;
+                    trx.exec_drop(&s, (key,)).await?;
+                }
                 Operation::Value { class, op } => {
                     let key = ValueKey {
                         account_id,
@@ -128,6 +128,21 @@ impl PostgresStore {
                         trx.execute(&s, &[&by, &key]).await?;
                     }
                 }
+                Operation::Value {
+                    class,
+                    op: ValueOp::ClearCounter,
+                } => {
+                    let key = ValueKey {
+                        account_id,
+                        collection,
+                        document_id,
+                        class,
+                    }
+                    .serialize(0);
+
+                    let s = trx.prepare_cached("DELETE FROM c WHERE k = $1").await?;
+                    trx.execute(&s, &[&key]).await?;
+                }
                 Operation::Value { class, op } => {
                     let key = ValueKey {
                         account_id,
@@ -35,6 +35,31 @@ impl RedisStore {
         }
     }
 
+    pub async fn counter_incr(&self, key: Vec<u8>, value: i64, expires: u64) -> crate::Result<i64> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                self.counter_incr_(pool.get().await?.as_mut(), key, value, expires)
+                    .await
+            }
+            RedisPool::Cluster(pool) => {
+                self.counter_incr_(pool.get().await?.as_mut(), key, value, expires)
+                    .await
+            }
+        }
+    }
+
+    pub async fn key_delete(&self, key: Vec<u8>) -> crate::Result<()> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                pool.get().await?.as_mut().del::<_, ()>(key).await?;
+            }
+            RedisPool::Cluster(pool) => {
+                pool.get().await?.as_mut().del::<_, ()>(key).await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn key_get<T: Deserialize + std::fmt::Debug + 'static>(
         &self,
         key: LookupKey,
@@ -67,6 +92,21 @@ impl RedisStore {
         }
     }
 
+    async fn counter_incr_(
+        &self,
+        conn: &mut impl AsyncCommands,
+        key: Vec<u8>,
+        value: i64,
+        expires: u64,
+    ) -> crate::Result<i64> {
+        let mut pipe = redis::pipe();
+        pipe.atomic().incr(&key, value);
+        if expires > 0 {
+            pipe.cmd("EXPIRE").arg(&key).arg(expires).ignore();
+        }
+        Ok(pipe.query_async::<_, (i64,)>(conn).await?.0)
+    }
+
     async fn key_set_(
         &self,
         conn: &mut impl AsyncCommands,
@@ -182,6 +182,20 @@ impl<'x> RocksDBTransaction<'x> {
 
                         txn.merge_cf(&self.cf_counters, &key, &by.to_le_bytes()[..])?;
                     }
+                    Operation::Value {
+                        class,
+                        op: ValueOp::ClearCounter,
+                    } => {
+                        let key = ValueKey {
+                            account_id,
+                            collection,
+                            document_id,
+                            class,
+                        }
+                        .serialize(0);
+
+                        txn.delete_cf(&self.cf_counters, &key)?;
+                    }
                     Operation::Value { class, op } => {
                         let key = ValueKey {
                             account_id,
@@ -323,6 +337,20 @@ impl<'x> RocksDBTransaction<'x> {
 
                         wb.merge_cf(&self.cf_counters, &key, &by.to_le_bytes()[..]);
                     }
+                    Operation::Value {
+                        class,
+                        op: ValueOp::ClearCounter,
+                    } => {
+                        let key = ValueKey {
+                            account_id,
+                            collection,
+                            document_id,
+                            class,
+                        }
+                        .serialize(0);
+
+                        wb.delete_cf(&self.cf_counters, &key);
+                    }
                     Operation::Value { class, op } => {
                         let key = ValueKey {
                             account_id,
@@ -79,6 +79,21 @@ impl SqliteStore {
                                 .execute(params![*by, &key])?;
                         }
                     }
+                    Operation::Value {
+                        class,
+                        op: ValueOp::ClearCounter,
+                    } => {
+                        let key = ValueKey {
+                            account_id,
+                            collection,
+                            document_id,
+                            class,
+                        }
+                        .serialize(0);
+
+                        trx.prepare_cached("DELETE FROM c WHERE k = ?")?
+                            .execute([&key])?;
+                    }
                     Operation::Value { class, op } => {
                         let key = ValueKey {
                             account_id,
@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::ops::Range;
+
+use ahash::AHashMap;
+use utils::config::{utils::AsKey, Config};
+
+use crate::BlobStore;
+
+// Blobs are always written to the hot store and are only moved to the cold
+// store by `demote_blob`, so reads try the hot store first. Calls into the
+// tiers are boxed as `BlobStore` dispatches back into this store.
+pub struct TieredStore {
+    hot: BlobStore,
+    cold: BlobStore,
+}
+
+impl TieredStore {
+    pub fn open(
+        config: &Config,
+        prefix: impl AsKey,
+        blob_stores: &AHashMap<String, BlobStore>,
+    ) -> utils::config::Result<Self> {
+        let prefix = prefix.as_key();
+        let mut tiers = Vec::with_capacity(2);
+        for tier in ["hot", "cold"] {
+            let store_id = config.value_require((&prefix, tier))?;
+            match blob_stores.get(store_id) {
+                Some(BlobStore::Tiered(_)) => {
+                    return Err(format!(
+                        "Blob store {store_id:?} cannot be used as a tier as it is tiered itself."
+                    ));
+                }
+                Some(store) => tiers.push(store.clone()),
+                None => {
+                    return Err(format!(
+                        "Blob store {store_id:?} not found for key {prefix}.{tier}."
+                    ));
+                }
+            }
+        }
+        let cold = tiers.pop().unwrap();
+        let hot = tiers.pop().unwrap();
+
+        Ok(TieredStore { hot, cold })
+    }
+
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<u32>,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        match Box::pin(self.hot.get_blob(key, range.clone())).await? {
+            Some(blob) => Ok(Some(blob)),
+            None => Box::pin(self.cold.get_blob(key, range)).await,
+        }
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+        Box::pin(self.hot.put_blob(key, data)).await
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        let deleted_hot = Box::pin(self.hot.delete_blob(key)).await?;
+        let deleted_cold = Box::pin(self.cold.delete_blob(key)).await?;
+        Ok(deleted_hot || deleted_cold)
+    }
+
+    pub(crate) async fn demote_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        if let Some(data) = Box::pin(self.hot.get_blob(key, 0..u32::MAX)).await? {
+            // Write to the cold store before removing the hot copy so the
+            // blob remains readable at all times
+            Box::pin(self.cold.put_blob(key, &data)).await?;
+            Box::pin(self.hot.delete_blob(key)).await
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub(crate) async fn compress_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        let compressed_hot = Box::pin(self.hot.compress_blob(key)).await?;
+        let compressed_cold = Box::pin(self.cold.compress_blob(key)).await?;
+        Ok(compressed_hot || compressed_cold)
+    }
+
+    pub(crate) async fn is_cold(&self, key: &[u8]) -> crate::Result<bool> {
+        Ok(Box::pin(self.hot.get_blob(key, 0..1)).await?.is_none()
+            && Box::pin(self.cold.get_blob(key, 0..1)).await?.is_some())
+    }
+}
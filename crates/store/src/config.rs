@@ -24,10 +24,10 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use utils::config::{cron::SimpleCron, Config};
+use utils::config::{cron::SimpleCron, utils::AsKey, Config};
 
 use crate::{
-    backend::{fs::FsStore, memory::MemoryStore},
+    backend::{compressed::CompressedStore, fs::FsStore, memory::MemoryStore, tiered::TieredStore},
     write::purge::{PurgeSchedule, PurgeStore},
     LookupStore, QueryStore, Store, Stores,
 };
@@ -183,6 +183,10 @@ impl ConfigStore for Config {
                         .insert(store_id, MemoryStore::open(self, prefix).await?.into());
                     continue;
                 }
+                "tiered" => {
+                    // Tiered stores are built once all other blob stores are available
+                    continue;
+                }
 
                 unknown => {
                     tracing::debug!("Unknown directory type: {unknown:?}");
@@ -211,6 +215,26 @@ impl ConfigStore for Config {
             }
         }
 
+        // Compress blobs on the stores that have it enabled
+        for (id, blob_store) in config.blob_stores.iter_mut() {
+            if CompressedStore::is_enabled(self, ("store", id.as_str())) {
+                *blob_store =
+                    CompressedStore::open(self, ("store", id.as_str()), blob_store.clone())?.into();
+            }
+        }
+
+        // Build tiered blob stores on top of the hot and cold stores
+        for id in self.sub_keys("store") {
+            if !self.property_or_static::<bool>(("store", id, "disable"), "false")?
+                && self
+                    .value_require(("store", id, "type"))?
+                    .eq_ignore_ascii_case("tiered")
+            {
+                let store = TieredStore::open(self, ("store", id), &config.blob_stores)?;
+                config.blob_stores.insert(id.to_string(), store.into());
+            }
+        }
+
         Ok(config)
     }
 
@@ -269,6 +293,27 @@ impl ConfigStore for Config {
     }
 }
 
+impl Stores {
+    /// Returns the lookup store referenced by a configuration key, which allows
+    /// each subsystem to keep its high-churn data in a different store.
+    pub fn get_lookup_store(
+        &self,
+        config: &Config,
+        key: impl AsKey,
+    ) -> utils::config::Result<Option<LookupStore>> {
+        let key = key.as_key();
+        if let Some(store_id) = config.value(key.as_str()) {
+            self.lookup_stores
+                .get(store_id)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| format!("Lookup store {store_id:?} not found for key {key:?}."))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 impl From<crate::Error> for String {
     fn from(err: crate::Error) -> Self {
         match err {
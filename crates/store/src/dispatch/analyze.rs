@@ -0,0 +1,212 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+
+use crate::{
+    write::{key::DeserializeBigEndian, AnyKey},
+    IterateParams, Store, BLOB_HASH_LEN, SUBSPACE_BITMAPS, SUBSPACE_BLOBS, SUBSPACE_COUNTERS,
+    SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_VALUES, U32_LEN, U64_LEN,
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeySpace {
+    Properties,
+    TermIndexes,
+    Acls,
+    ReservedIds,
+    KeyValue,
+    IndexQueue,
+    BlobReservations,
+    BlobLinks,
+    Directory,
+    Bitmaps,
+    FullText,
+    Indexes,
+    ChangeLogs,
+    Blobs,
+    Counters,
+    Other,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KeySpaceUsage {
+    pub keys: u64,
+    #[serde(rename = "keyBytes")]
+    pub key_bytes: u64,
+    #[serde(rename = "valueBytes")]
+    pub value_bytes: u64,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KeySpaceReport {
+    pub total: AHashMap<KeySpace, KeySpaceUsage>,
+    pub accounts: AHashMap<u32, AHashMap<KeySpace, KeySpaceUsage>>,
+}
+
+impl Store {
+    // Walks every subspace and adds up key counts and sizes by subsystem,
+    // attributing them to the owning account whenever the key contains one.
+    pub async fn analyze_key_space(&self) -> crate::Result<KeySpaceReport> {
+        let mut report = KeySpaceReport::default();
+
+        for (subspace, has_values) in [
+            (SUBSPACE_VALUES, true),
+            (SUBSPACE_COUNTERS, false),
+            (SUBSPACE_BLOBS, true),
+            (SUBSPACE_BITMAPS, false),
+            (SUBSPACE_INDEXES, false),
+            (SUBSPACE_LOGS, true),
+        ] {
+            // SQL backends keep bitmaps and indexes in key-only tables and
+            // counters as native integers, so only their keys are read
+            let params = IterateParams::new(
+                AnyKey {
+                    subspace,
+                    key: vec![0u8],
+                },
+                AnyKey {
+                    subspace,
+                    key: vec![u8::MAX; 10],
+                },
+            );
+            self.iterate(
+                if has_values {
+                    params
+                } else {
+                    params.no_values()
+                },
+                |key, value| {
+                    let (key_space, account_id) = classify_key(subspace, key);
+                    let value_len = if subspace == SUBSPACE_COUNTERS {
+                        U64_LEN
+                    } else {
+                        value.len()
+                    };
+                    report.add(key_space, account_id, key.len(), value_len);
+                    Ok(true)
+                },
+            )
+            .await?;
+        }
+
+        Ok(report)
+    }
+}
+
+impl KeySpaceReport {
+    fn add(
+        &mut self,
+        key_space: KeySpace,
+        account_id: Option<u32>,
+        key_len: usize,
+        value_len: usize,
+    ) {
+        self.total
+            .entry(key_space)
+            .or_default()
+            .add(key_len, value_len);
+        if let Some(account_id) = account_id {
+            self.accounts
+                .entry(account_id)
+                .or_default()
+                .entry(key_space)
+                .or_default()
+                .add(key_len, value_len);
+        }
+    }
+}
+
+impl KeySpaceUsage {
+    fn add(&mut self, key_len: usize, value_len: usize) {
+        self.keys += 1;
+        self.key_bytes += key_len as u64;
+        self.value_bytes += value_len as u64;
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.key_bytes + self.value_bytes
+    }
+}
+
+fn classify_key(subspace: u8, key: &[u8]) -> (KeySpace, Option<u32>) {
+    const BM_TEXT: u8 = 1 << 7;
+
+    let account_at = |offset: usize| {
+        key.deserialize_be_u32(offset)
+            .ok()
+            .filter(|account_id| *account_id != u32::MAX)
+    };
+
+    match subspace {
+        SUBSPACE_BITMAPS => {
+            if key.get(U32_LEN + 1).is_some_and(|typ| typ & BM_TEXT != 0) {
+                (KeySpace::FullText, account_at(0))
+            } else {
+                (KeySpace::Bitmaps, account_at(0))
+            }
+        }
+        SUBSPACE_INDEXES => (KeySpace::Indexes, account_at(0)),
+        SUBSPACE_LOGS => (KeySpace::ChangeLogs, account_at(0)),
+        SUBSPACE_BLOBS => (KeySpace::Blobs, None),
+        SUBSPACE_COUNTERS => (KeySpace::Counters, None),
+        _ => match key.first().copied().unwrap_or(u8::MAX) {
+            0 => (KeySpace::Properties, account_at(1)),
+            1 => (KeySpace::TermIndexes, account_at(1)),
+            2 => (KeySpace::Acls, account_at(1 + U32_LEN)),
+            3 => (KeySpace::ReservedIds, account_at(1)),
+            4 | 8 => (KeySpace::KeyValue, None),
+            5 => (KeySpace::IndexQueue, account_at(1 + U64_LEN)),
+            6 => (KeySpace::BlobReservations, account_at(1)),
+            7 => (KeySpace::BlobLinks, account_at(1 + BLOB_HASH_LEN)),
+            20..=26 => (KeySpace::Directory, None),
+            _ => (KeySpace::Other, None),
+        },
+    }
+}
+
+impl KeySpace {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeySpace::Properties => "properties",
+            KeySpace::TermIndexes => "term-indexes",
+            KeySpace::Acls => "acls",
+            KeySpace::ReservedIds => "reserved-ids",
+            KeySpace::KeyValue => "key-value",
+            KeySpace::IndexQueue => "index-queue",
+            KeySpace::BlobReservations => "blob-reservations",
+            KeySpace::BlobLinks => "blob-links",
+            KeySpace::Directory => "directory",
+            KeySpace::Bitmaps => "bitmaps",
+            KeySpace::FullText => "full-text",
+            KeySpace::Indexes => "indexes",
+            KeySpace::ChangeLogs => "change-logs",
+            KeySpace::Blobs => "blobs",
+            KeySpace::Counters => "counters",
+            KeySpace::Other => "other",
+        }
+    }
+}
@@ -43,6 +43,8 @@ impl BlobStore {
             Self::Fs(store) => store.get_blob(key, range).await,
             #[cfg(feature = "s3")]
             Self::S3(store) => store.get_blob(key, range).await,
+            Self::Tiered(store) => store.get_blob(key, range).await,
+            Self::Compressed(store) => store.get_blob(key, range).await,
         }
     }
 
@@ -63,6 +65,8 @@ impl BlobStore {
             Self::Fs(store) => store.put_blob(key, data).await,
             #[cfg(feature = "s3")]
             Self::S3(store) => store.put_blob(key, data).await,
+            Self::Tiered(store) => store.put_blob(key, data).await,
+            Self::Compressed(store) => store.put_blob(key, data).await,
         }
     }
 
@@ -83,6 +87,39 @@ impl BlobStore {
             Self::Fs(store) => store.delete_blob(key).await,
             #[cfg(feature = "s3")]
             Self::S3(store) => store.delete_blob(key).await,
+            Self::Tiered(store) => store.delete_blob(key).await,
+            Self::Compressed(store) => store.delete_blob(key).await,
         }
     }
+
+    /// Moves a blob from the hot to the cold tier, returning `false` if the
+    /// store is not tiered or the blob is not in the hot tier.
+    pub async fn demote_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        match self {
+            Self::Tiered(store) => store.demote_blob(key).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Returns `true` if the blob is only available in the cold tier.
+    pub async fn is_cold(&self, key: &[u8]) -> crate::Result<bool> {
+        match self {
+            Self::Tiered(store) => store.is_cold(key).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Rewrites a blob stored before compression was enabled, returning
+    /// `false` if the store does not compress or the blob is already encoded.
+    pub async fn compress_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        match self {
+            Self::Compressed(store) => store.compress_blob(key).await,
+            Self::Tiered(store) => store.compress_blob(key).await,
+            _ => Ok(false),
+        }
+    }
+
+    pub fn is_tiered(&self) -> bool {
+        matches!(self, Self::Tiered(_))
+    }
 }
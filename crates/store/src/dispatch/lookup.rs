@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use utils::config::Rate;
+
 use crate::{backend::memory::MemoryStore, Row};
 #[allow(unused_imports)]
 use crate::{
@@ -160,6 +162,101 @@ impl LookupStore {
         }
     }
 
+    /// Increments a counter, stores without native key expiration keep an
+    /// expiring marker next to it so the counter is removed by `purge_expired`.
+    pub async fn counter_incr(&self, key: Vec<u8>, value: i64, expires: u64) -> crate::Result<i64> {
+        match self {
+            LookupStore::Store(store) => {
+                let mut batch = BatchBuilder::new();
+                if expires > 0 {
+                    batch.ops.push(Operation::Value {
+                        class: ValueClass::Key(key.clone()),
+                        op: ValueOp::Set(
+                            KeySerializer::new(U64_LEN)
+                                .write(now() + expires)
+                                .finalize(),
+                        ),
+                    });
+                }
+                batch.ops.push(Operation::Value {
+                    class: ValueClass::Key(key.clone()),
+                    op: ValueOp::Add(value),
+                });
+                store.write(batch.build()).await?;
+                store
+                    .get_counter(ValueKey {
+                        account_id: 0,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::Key(key),
+                    })
+                    .await
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => store.counter_incr(key, value, expires).await,
+            LookupStore::Query(_) | LookupStore::Memory(_) => Err(crate::Error::InternalError(
+                "This store does not support counter_incr".into(),
+            )),
+        }
+    }
+
+    pub async fn key_delete(&self, key: Vec<u8>) -> crate::Result<()> {
+        match self {
+            LookupStore::Store(store) => {
+                let mut batch = BatchBuilder::new();
+                batch.ops.push(Operation::Value {
+                    class: ValueClass::Key(key.clone()),
+                    op: ValueOp::Clear,
+                });
+                batch.ops.push(Operation::Value {
+                    class: ValueClass::Key(key),
+                    op: ValueOp::ClearCounter,
+                });
+                store.write(batch.build()).await
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => store.key_delete(key).await,
+            LookupStore::Query(_) | LookupStore::Memory(_) => Err(crate::Error::InternalError(
+                "This store does not support key_delete".into(),
+            )),
+        }
+    }
+
+    /// Checks a rate limit shared across nodes using a fixed window counter.
+    /// Returns the number of seconds until the next window when the limit has
+    /// been exceeded. Soft checks do not count as a request.
+    pub async fn is_rate_allowed(
+        &self,
+        key: &[u8],
+        rate: &Rate,
+        soft_check: bool,
+    ) -> crate::Result<Option<u64>> {
+        let period = rate.period.as_secs().max(1);
+        let now = now();
+        let window = now / period;
+        let exceeded = if !soft_check {
+            // Past windows expire once the window is over
+            let expires = (window + 1) * period - now;
+            let count = self.counter_incr(rate_key(key, window), 1, expires).await?;
+
+            count as u64 > rate.requests
+        } else {
+            match self
+                .key_get::<String>(LookupKey::Counter(rate_key(key, window)))
+                .await?
+            {
+                LookupValue::Counter { num } => num as u64 >= rate.requests,
+                _ => false,
+            }
+        };
+
+        Ok(if exceeded {
+            Some((window + 1) * period - now)
+        } else {
+            None
+        })
+    }
+
     pub async fn purge_expired(&self) -> crate::Result<()> {
         match self {
             LookupStore::Store(store) => {
@@ -189,6 +286,11 @@ impl LookupStore {
                 if !expired_keys.is_empty() {
                     let mut batch = BatchBuilder::new();
                     for key in expired_keys {
+                        // Expiring counters are removed along with their marker
+                        batch.ops.push(Operation::Value {
+                            class: ValueClass::Key(key.clone()),
+                            op: ValueOp::ClearCounter,
+                        });
                         batch.ops.push(Operation::Value {
                             class: ValueClass::Key(key),
                             op: ValueOp::Clear,
@@ -212,6 +314,14 @@ impl LookupStore {
     }
 }
 
+fn rate_key(key: &[u8], window: u64) -> Vec<u8> {
+    let mut rate_key = Vec::with_capacity(key.len() + U64_LEN + 2);
+    rate_key.extend_from_slice(b"t:");
+    rate_key.extend_from_slice(key);
+    rate_key.extend_from_slice(&window.to_be_bytes());
+    rate_key
+}
+
 impl<T: Deserialize> Deserialize for LookupValue<T> {
     fn deserialize(bytes: &[u8]) -> crate::Result<Self> {
         bytes.deserialize_be_u64(0).and_then(|expires| {
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod analyze;
 pub mod blob;
 pub mod fts;
 pub mod lookup;
@@ -308,6 +308,25 @@ impl Store {
             .await?;
         }
 
+        // Delete per-document counters
+        self.delete_range(
+            AnyKey {
+                subspace: crate::SUBSPACE_COUNTERS,
+                key: KeySerializer::new(U32_LEN + 1)
+                    .write(0u8)
+                    .write(account_id)
+                    .finalize(),
+            },
+            AnyKey {
+                subspace: crate::SUBSPACE_COUNTERS,
+                key: KeySerializer::new(U32_LEN + 1)
+                    .write(0u8)
+                    .write(account_id + 1)
+                    .finalize(),
+            },
+        )
+        .await?;
+
         Ok(())
     }
 
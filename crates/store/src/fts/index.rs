@@ -204,7 +204,26 @@ impl Store {
             }
         }
 
+        // Terms from a previous version of the document are removed once the
+        // new ones are written, so it stays searchable while being reindexed
+        let previous_terms = self
+            .get_value::<TermIndex>(ValueKey {
+                account_id: document.account_id,
+                collection: document.collection,
+                document_id: document.document_id,
+                class: ValueClass::TermIndex,
+            })
+            .await?;
+
         if tokens.is_empty() {
+            if previous_terms.is_some() {
+                self.fts_remove(
+                    document.account_id,
+                    document.collection,
+                    document.document_id,
+                )
+                .await?;
+            }
             return Ok(());
         }
 
@@ -219,6 +238,7 @@ impl Store {
         }
 
         // Write index keys
+        let mut current_terms = AHashSet::with_capacity(tokens.len());
         for (hash, fields) in tokens.into_iter() {
             serializer = serializer
                 .write(hash.hash.as_slice())
@@ -226,12 +246,18 @@ impl Store {
                 .write(fields.len() as u8);
             for field in fields.into_iter() {
                 serializer = serializer.write(field);
-                keys.push(Operation::Bitmap {
-                    class: BitmapClass::Text { field, token: hash },
-                    set: true,
-                });
+                let class = BitmapClass::Text { field, token: hash };
+                if previous_terms.is_some() {
+                    current_terms.insert(class.clone());
+                }
+                keys.push(Operation::Bitmap { class, set: true });
             }
         }
+        if let Some(previous_terms) = previous_terms {
+            keys.extend(previous_terms.ops.into_iter().filter(
+                |op| matches!(op, Operation::Bitmap { class, .. } if !current_terms.contains(class)),
+            ));
+        }
 
         // Write term index
         let mut batch = BatchBuilder::new();
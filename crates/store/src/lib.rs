@@ -32,7 +32,7 @@ pub mod write;
 
 pub use ahash;
 use ahash::AHashMap;
-use backend::{fs::FsStore, memory::MemoryStore};
+use backend::{compressed::CompressedStore, fs::FsStore, memory::MemoryStore, tiered::TieredStore};
 pub use blake3;
 pub use parking_lot;
 pub use rand;
@@ -216,6 +216,8 @@ pub enum BlobStore {
     Fs(Arc<FsStore>),
     #[cfg(feature = "s3")]
     S3(Arc<S3Store>),
+    Tiered(Arc<TieredStore>),
+    Compressed(Arc<CompressedStore>),
 }
 
 #[derive(Clone)]
@@ -280,6 +282,18 @@ impl From<FsStore> for BlobStore {
     }
 }
 
+impl From<CompressedStore> for BlobStore {
+    fn from(store: CompressedStore) -> Self {
+        Self::Compressed(Arc::new(store))
+    }
+}
+
+impl From<TieredStore> for BlobStore {
+    fn from(store: TieredStore) -> Self {
+        Self::Tiered(Arc::new(store))
+    }
+}
+
 #[cfg(feature = "s3")]
 impl From<S3Store> for BlobStore {
     fn from(store: S3Store) -> Self {
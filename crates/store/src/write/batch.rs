@@ -166,6 +166,14 @@ impl BatchBuilder {
         self
     }
 
+    pub fn clear_counter(&mut self, class: impl Into<ValueClass>) -> &mut Self {
+        self.ops.push(Operation::Value {
+            class: class.into(),
+            op: ValueOp::ClearCounter,
+        });
+        self
+    }
+
     pub fn custom(&mut self, value: impl IntoOperations) -> &mut Self {
         value.build(self);
         self
@@ -266,6 +266,200 @@ impl Store {
         Ok(())
     }
 
+    /// Returns the hashes of all committed blobs.
+    pub async fn blob_hash_list(&self) -> crate::Result<Vec<BlobHash>> {
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::default(),
+            }),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::new_max(),
+            }),
+        };
+        let mut hashes = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                if key.deserialize_be_u32(key.len() - U32_LEN)? == u32::MAX {
+                    hashes.push(
+                        BlobHash::try_from_hash_slice(key.get(1..1 + BLOB_HASH_LEN).ok_or_else(
+                            || {
+                                crate::Error::InternalError(format!(
+                                    "Invalid key {key:?} in blob hash tables"
+                                ))
+                            },
+                        )?)
+                        .unwrap(),
+                    );
+                }
+                Ok(true)
+            },
+        )
+        .await?;
+
+        Ok(hashes)
+    }
+
+    /// Deletes a blob right away once no document links to it anymore,
+    /// instead of waiting for the next `purge_blobs` run. Returns `false`
+    /// when the blob is still linked or reserved elsewhere and was left in place.
+    pub async fn blob_hash_purge(
+        &self,
+        blob_store: &BlobStore,
+        hash: impl AsRef<BlobHash> + Sync + Send,
+    ) -> crate::Result<bool> {
+        let hash = hash.as_ref();
+        if self.blob_hash_is_linked(hash).await? {
+            return Ok(false);
+        }
+
+        // Keep a copy in case a concurrent write starts using the blob
+        let data = blob_store.get_blob(hash.as_ref(), 0..u32::MAX).await?;
+        let mut batch = BatchBuilder::new();
+        batch.ops.push(Operation::Value {
+            class: ValueClass::Blob(BlobOp::Commit { hash: hash.clone() }),
+            op: ValueOp::Clear,
+        });
+        self.write(batch.build()).await?;
+        blob_store.delete_blob(hash.as_ref()).await?;
+
+        // Writers reserve a blob before checking whether it is committed, so
+        // any write that found the blob before it was deleted is visible now
+        if self.blob_hash_is_linked(hash).await? {
+            if let Some(data) = data {
+                blob_store.put_blob(hash.as_ref(), &data).await?;
+                let mut batch = BatchBuilder::new();
+                batch.set(BlobOp::Commit { hash: hash.clone() }, Vec::new());
+                self.write(batch.build()).await?;
+            }
+            Ok(false)
+        } else {
+            Ok(true)
+        }
+    }
+
+    async fn blob_hash_is_linked(&self, hash: &BlobHash) -> crate::Result<bool> {
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+        };
+        let mut is_linked = false;
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                if key.deserialize_be_u32(key.len() - U32_LEN)? != u32::MAX {
+                    is_linked = true;
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            },
+        )
+        .await?;
+
+        // Blobs reserved by a pending upload or ingest are still in use
+        if !is_linked {
+            let from_key = ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Blob(BlobOp::Reserve {
+                    until: 0,
+                    hash: BlobHash::default(),
+                }),
+            };
+            let to_key = ValueKey {
+                account_id: u32::MAX,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Blob(BlobOp::Reserve {
+                    until: 0,
+                    hash: BlobHash::default(),
+                }),
+            };
+            let now = now();
+            self.iterate(
+                IterateParams::new(from_key, to_key).ascending().no_values(),
+                |key, _| {
+                    if key.get(1 + U32_LEN..1 + U32_LEN + BLOB_HASH_LEN) == Some(hash.as_slice())
+                        && key.deserialize_be_u64(key.len() - U64_LEN)? > now
+                    {
+                        is_linked = true;
+                        Ok(false)
+                    } else {
+                        Ok(true)
+                    }
+                },
+            )
+            .await?;
+        }
+
+        Ok(is_linked)
+    }
+
+    /// Drops all reservations an account holds on a blob, such as pending
+    /// uploads or tombstones, so they no longer prevent it from being purged.
+    pub async fn blob_hash_unreserve(
+        &self,
+        account_id: u32,
+        hash: impl AsRef<BlobHash> + Sync + Send,
+    ) -> crate::Result<()> {
+        let hash = hash.as_ref();
+        let from_key = ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Reserve {
+                hash: hash.clone(),
+                until: 0,
+            }),
+        };
+        let to_key = ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Reserve {
+                hash: hash.clone(),
+                until: u64::MAX,
+            }),
+        };
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(account_id);
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                batch.clear(BlobOp::Reserve {
+                    hash: hash.clone(),
+                    until: key.deserialize_be_u64(key.len() - U64_LEN)?,
+                });
+                Ok(true)
+            },
+        )
+        .await?;
+
+        if !batch.is_empty() {
+            self.write(batch.build()).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn blob_hash_unlink_account(&self, account_id: u32) -> crate::Result<()> {
         // Validate linked blobs
         let from_key = ValueKey {
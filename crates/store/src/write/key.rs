@@ -251,6 +251,7 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
                 .write(self.collection)
                 .write(self.document_id),
             ValueClass::Key(key) => serializer.write(4u8).write(key.as_slice()),
+            ValueClass::Persistent(key) => serializer.write(8u8).write(key.as_slice()),
             ValueClass::IndexEmail(seq) => serializer
                 .write(5u8)
                 .write(*seq)
@@ -278,6 +279,9 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
             ValueClass::Directory(directory) => match directory {
                 DirectoryClass::NameToId(name) => serializer.write(20u8).write(name.as_slice()),
                 DirectoryClass::EmailToId(email) => serializer.write(21u8).write(email.as_slice()),
+                DirectoryClass::MaskedEmailToId(email) => {
+                    serializer.write(27u8).write(email.as_slice())
+                }
                 DirectoryClass::Principal(uid) => serializer.write(22u8).write_leb128(*uid),
                 DirectoryClass::Domain(name) => serializer.write(23u8).write(name.as_slice()),
                 DirectoryClass::UsedQuota(uid) => serializer.write(24u8).write_leb128(*uid),
@@ -424,10 +428,11 @@ impl ValueClass {
                 U32_LEN * 2 + 3
             }
             ValueClass::Acl(_) => U32_LEN * 3 + 2,
-            ValueClass::Key(v) => v.len(),
+            ValueClass::Key(v) | ValueClass::Persistent(v) => v.len(),
             ValueClass::Directory(d) => match d {
                 DirectoryClass::NameToId(v)
                 | DirectoryClass::EmailToId(v)
+                | DirectoryClass::MaskedEmailToId(v)
                 | DirectoryClass::Domain(v) => v.len(),
                 DirectoryClass::Principal(_) | DirectoryClass::UsedQuota(_) => U32_LEN,
                 DirectoryClass::Members { .. } | DirectoryClass::MemberOf { .. } => U32_LEN * 2,
@@ -132,6 +132,8 @@ pub enum ValueClass {
     Property(u8),
     Acl(u32),
     Key(Vec<u8>),
+    // Named values that, unlike lookup keys, have no expiration header
+    Persistent(Vec<u8>),
     TermIndex,
     ReservedId,
     Directory(DirectoryClass),
@@ -143,6 +145,7 @@ pub enum ValueClass {
 pub enum DirectoryClass {
     NameToId(Vec<u8>),
     EmailToId(Vec<u8>),
+    MaskedEmailToId(Vec<u8>),
     MemberOf { principal_id: u32, member_of: u32 },
     Members { principal_id: u32, has_member: u32 },
     Domain(Vec<u8>),
@@ -154,6 +157,7 @@ pub enum DirectoryClass {
 pub enum ValueOp {
     Set(Vec<u8>),
     Add(i64),
+    ClearCounter,
     #[default]
     Clear,
 }
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use rustls::{
     crypto::ring::{
@@ -40,7 +40,7 @@ use rustls::{
 };
 use tokio::net::TcpSocket;
 
-use crate::UnwrapFailure;
+use crate::{listener::limiter::ConnectionLimiter, UnwrapFailure};
 
 use super::{
     certificate::{CertificateResolver, TLS12_VERSION, TLS13_VERSION},
@@ -51,10 +51,15 @@ use super::{
 impl Config {
     pub fn parse_servers(&self) -> super::Result<Servers> {
         let mut servers: Vec<Server> = Vec::new();
+        let connections = Arc::new(ConnectionLimiter::default());
+        let drain_timeout =
+            self.property_or_static::<Duration>("server.shutdown.drain-timeout", "30s")?;
         for (internal_id, id) in self.sub_keys("server.listener").enumerate() {
             let mut server = self.parse_server(id)?;
             if !servers.iter().any(|s| s.id == server.id) {
                 server.internal_id = internal_id as u16;
+                server.connections = connections.clone();
+                server.drain_timeout = drain_timeout;
                 servers.push(server);
             } else {
                 return Err(format!("Duplicate listener id {:?}.", server.id));
@@ -62,7 +67,11 @@ impl Config {
         }
 
         if !servers.is_empty() {
-            Ok(Servers { inner: servers })
+            Ok(Servers {
+                inner: servers,
+                connections,
+                drain_timeout,
+            })
         } else {
             Err("No server directives found in config file.".to_string())
         }
@@ -301,6 +310,20 @@ impl Config {
                     "server.max-connections",
                 )?
                 .unwrap_or(8192),
+            max_connections_ip: self
+                .property_or_default(
+                    ("server.listener", id, "max-connections-per-ip"),
+                    "server.max-connections-per-ip",
+                )?
+                .unwrap_or(u64::MAX),
+            max_connections_account: self
+                .property_or_default(
+                    ("server.listener", id, "max-connections-per-account"),
+                    "server.max-connections-per-account",
+                )?
+                .unwrap_or(u64::MAX),
+            connections: Default::default(),
+            drain_timeout: Default::default(),
             protocol,
             listeners,
             tls,
@@ -33,6 +33,7 @@ use std::{
     collections::BTreeMap,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
@@ -40,7 +41,7 @@ use ahash::{AHashMap, AHashSet};
 use rustls::ServerConfig;
 use tokio::net::TcpSocket;
 
-use crate::{failed, UnwrapFailure};
+use crate::{failed, listener::limiter::ConnectionLimiter, UnwrapFailure};
 
 use self::utils::ParseValue;
 
@@ -60,10 +61,16 @@ pub struct Server {
     pub tls: Option<ServerConfig>,
     pub tls_implicit: bool,
     pub max_connections: u64,
+    pub max_connections_ip: u64,
+    pub max_connections_account: u64,
+    pub connections: Arc<ConnectionLimiter>,
+    pub drain_timeout: Duration,
 }
 
 pub struct Servers {
     pub inner: Vec<Server>,
+    pub connections: Arc<ConnectionLimiter>,
+    pub drain_timeout: Duration,
 }
 
 #[derive(Debug)]
@@ -160,6 +167,8 @@ impl Config {
                 break;
             } else if arg.starts_with("--config") {
                 found_param = true;
+            } else if arg == "check-config" {
+                // Handled by the caller
             } else {
                 failed(&format!("Invalid command line argument: {arg}"));
             }
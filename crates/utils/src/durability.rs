@@ -0,0 +1,240 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use ahash::AHashSet;
+use tokio::fs::File;
+
+use crate::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    // Sync every write before it is acknowledged
+    Always,
+    // Acknowledge writes and sync them in batches every interval
+    Group(Duration),
+    // Leave flushing to the operating system
+    Never,
+}
+
+pub struct FileSync {
+    policy: SyncPolicy,
+    pending: Arc<Mutex<AHashSet<PathBuf>>>,
+    flusher: AtomicBool,
+}
+
+impl FileSync {
+    pub fn new(policy: SyncPolicy) -> Self {
+        FileSync {
+            policy,
+            pending: Arc::new(Mutex::new(AHashSet::new())),
+            flusher: AtomicBool::new(false),
+        }
+    }
+
+    pub fn parse(config: &Config, prefix: impl AsKey) -> crate::config::Result<Self> {
+        let prefix = prefix.as_key();
+        let policy = match config.value((&prefix, "sync")) {
+            Some("group") => {
+                SyncPolicy::Group(config.property_or_static((&prefix, "sync-interval"), "50ms")?)
+            }
+            Some(value) => SyncPolicy::parse_value((&prefix, "sync"), value)?,
+            None => SyncPolicy::Always,
+        };
+
+        Ok(FileSync::new(policy))
+    }
+
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    // Called on an open file before it is made visible or acknowledged
+    pub async fn sync_file(&self, file: &File) -> std::io::Result<()> {
+        if self.policy == SyncPolicy::Always {
+            file.sync_data().await
+        } else {
+            Ok(())
+        }
+    }
+
+    // Called once a file has been created or renamed at its final path
+    pub async fn sync_path(&self, path: &Path) {
+        match self.policy {
+            SyncPolicy::Always => {
+                if let Some(parent) = path.parent() {
+                    sync_dir(parent).await;
+                }
+            }
+            SyncPolicy::Group(interval) => {
+                self.pending.lock().unwrap().insert(path.to_path_buf());
+                if !self.flusher.swap(true, Ordering::Relaxed) {
+                    tokio::spawn(flush_pending(Arc::downgrade(&self.pending), interval));
+                }
+            }
+            SyncPolicy::Never => (),
+        }
+    }
+}
+
+impl Default for FileSync {
+    fn default() -> Self {
+        FileSync::new(SyncPolicy::Always)
+    }
+}
+
+impl ParseValue for SyncPolicy {
+    fn parse_value(key: impl AsKey, value: &str) -> crate::config::Result<Self> {
+        match value {
+            "always" => Ok(SyncPolicy::Always),
+            "never" => Ok(SyncPolicy::Never),
+            _ => Err(format!(
+                "Invalid sync policy {:?} for property {:?}, expected \"always\", \"group\" or \"never\".",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+async fn flush_pending(pending: Weak<Mutex<AHashSet<PathBuf>>>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let paths = match pending.upgrade() {
+            Some(pending) => std::mem::take(&mut *pending.lock().unwrap()),
+            None => break,
+        };
+        if paths.is_empty() {
+            continue;
+        }
+
+        let mut dirs = AHashSet::new();
+        for path in paths {
+            match File::open(&path).await {
+                Ok(file) => {
+                    if let Err(err) = file.sync_data().await {
+                        tracing::warn!(
+                            context = "durability",
+                            event = "error",
+                            "Failed to sync file {}: {}",
+                            path.display(),
+                            err
+                        );
+                    }
+                }
+                // Files removed before the flush no longer need syncing
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => {
+                    tracing::warn!(
+                        context = "durability",
+                        event = "error",
+                        "Failed to open file {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+            if let Some(parent) = path.parent() {
+                dirs.insert(parent.to_path_buf());
+            }
+        }
+        for dir in dirs {
+            sync_dir(&dir).await;
+        }
+    }
+}
+
+// Makes renames and new entries durable, not supported on all platforms
+pub async fn sync_dir(path: &Path) {
+    let result = match File::open(path).await {
+        Ok(dir) => dir.sync_all().await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        tracing::debug!(
+            context = "durability",
+            event = "error",
+            "Failed to sync directory {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::Config;
+
+    use super::{FileSync, SyncPolicy};
+
+    #[test]
+    fn parse_sync_policy() {
+        let config = Config::new(
+            r#"
+[queue.durability]
+sync = "group"
+sync-interval = "10ms"
+
+[store."fs".durability]
+sync = "never"
+
+[store."s3".durability]
+sync = "sometimes"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            FileSync::parse(&config, "queue.durability")
+                .unwrap()
+                .policy(),
+            SyncPolicy::Group(Duration::from_millis(10))
+        );
+        assert_eq!(
+            FileSync::parse(&config, "store.fs.durability")
+                .unwrap()
+                .policy(),
+            SyncPolicy::Never
+        );
+        assert_eq!(
+            FileSync::parse(&config, "store.blob.durability")
+                .unwrap()
+                .policy(),
+            SyncPolicy::Always
+        );
+        assert!(FileSync::parse(&config, "store.s3.durability").is_err());
+    }
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{borrow::Cow, path::PathBuf};
+use std::{borrow::Cow, net::IpAddr, path::PathBuf};
 
 use tokio::{fs, io::AsyncReadExt, sync::oneshot};
 
@@ -31,9 +31,48 @@ pub enum DeliveryEvent {
         message: IngestMessage,
         result_tx: oneshot::Sender<Vec<DeliveryResult>>,
     },
+    Status {
+        queue_id: u64,
+        return_path: String,
+        recipients: Vec<RecipientStatus>,
+    },
+    Submission {
+        account: String,
+        domain: String,
+        size: usize,
+    },
+    Login {
+        account_id: u32,
+        remote_ip: IpAddr,
+    },
+    SendAs {
+        account_id: u32,
+        result_tx: oneshot::Sender<Vec<SendAsAddress>>,
+    },
     Stop,
 }
 
+#[derive(Debug, Clone)]
+pub struct SendAsAddress {
+    pub address: String,
+    pub on_behalf: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecipientStatus {
+    pub address: String,
+    pub status: DeliveryStatus,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Queued,
+    Delayed,
+    Delivered,
+    Failed,
+}
+
 #[derive(Debug)]
 pub struct IngestMessage {
     pub sender_address: String,
@@ -52,6 +91,28 @@ pub enum DeliveryResult {
         code: [u8; 3],
         reason: Cow<'static, str>,
     },
+    OverQuota,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Delayed => "delayed",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "queued" => Some(DeliveryStatus::Queued),
+            "delayed" => Some(DeliveryStatus::Delayed),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
 }
 
 impl IngestMessage {
@@ -27,11 +27,18 @@ use config::Config;
 
 pub mod codec;
 pub mod config;
+pub mod durability;
 pub mod ipc;
 pub mod listener;
+pub mod live_trace;
 pub mod map;
+pub mod message;
 pub mod snowflake;
+pub mod stats;
 pub mod suffixlist;
+pub mod syslog;
+#[cfg(unix)]
+pub mod systemd;
 
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
@@ -46,7 +53,7 @@ use rustls::{
 };
 use rustls_pki_types::TrustAnchor;
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, EnvFilter};
+use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, EnvFilter, Layer};
 
 pub trait UnwrapFailure<T> {
     fn failed(self, action: &str) -> T;
@@ -110,21 +117,27 @@ pub fn enable_tracing(config: &Config, message: &str) -> config::Result<Option<W
 
             let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
             tracing::subscriber::set_global_default(
-                tracing_subscriber::FmtSubscriber::builder()
-                    .with_env_filter(env_filter)
-                    .with_writer(non_blocking)
-                    .with_ansi(config.property_or_static("global.tracing.ansi", "true")?)
-                    .finish(),
+                tracing_subscriber::Registry::default()
+                    .with(
+                        tracing_subscriber::fmt::layer()
+                            .with_writer(non_blocking)
+                            .with_ansi(config.property_or_static("global.tracing.ansi", "true")?)
+                            .with_filter(env_filter),
+                    )
+                    .with(live_trace::layer()),
             )
             .failed("Failed to set subscriber");
             Ok(guard.into())
         }
         "stdout" => {
             tracing::subscriber::set_global_default(
-                tracing_subscriber::FmtSubscriber::builder()
-                    .with_env_filter(env_filter)
-                    .with_ansi(config.property_or_static("global.tracing.ansi", "true")?)
-                    .finish(),
+                tracing_subscriber::Registry::default()
+                    .with(
+                        tracing_subscriber::fmt::layer()
+                            .with_ansi(config.property_or_static("global.tracing.ansi", "true")?)
+                            .with_filter(env_filter),
+                    )
+                    .with(live_trace::layer()),
             )
             .failed("Failed to set subscriber");
 
@@ -179,8 +192,22 @@ pub fn enable_tracing(config: &Config, message: &str) -> config::Result<Option<W
 
             tracing::subscriber::set_global_default(
                 tracing_subscriber::Registry::default()
-                    .with(tracing_opentelemetry::layer().with_tracer(tracer))
-                    .with(env_filter),
+                    .with(
+                        tracing_opentelemetry::layer()
+                            .with_tracer(tracer)
+                            .with_filter(env_filter),
+                    )
+                    .with(live_trace::layer()),
+            )
+            .failed("Failed to set subscriber");
+
+            Ok(None)
+        }
+        "syslog" => {
+            tracing::subscriber::set_global_default(
+                tracing_subscriber::Registry::default()
+                    .with(syslog::layer(config)?.with_filter(env_filter))
+                    .with(live_trace::layer()),
             )
             .failed("Failed to set subscriber");
 
@@ -190,14 +217,26 @@ pub fn enable_tracing(config: &Config, message: &str) -> config::Result<Option<W
         "journal" => {
             tracing::subscriber::set_global_default(
                 tracing_subscriber::Registry::default()
-                    .with(tracing_journald::layer().failed("Failed to configure journal"))
-                    .with(env_filter),
+                    .with(
+                        tracing_journald::layer()
+                            .failed("Failed to configure journal")
+                            .with_filter(env_filter),
+                    )
+                    .with(live_trace::layer()),
+            )
+            .failed("Failed to set subscriber");
+
+            Ok(None)
+        }
+        _ => {
+            // No log output configured, live traces are still available
+            tracing::subscriber::set_global_default(
+                tracing_subscriber::Registry::default().with(live_trace::layer()),
             )
             .failed("Failed to set subscriber");
 
             Ok(None)
         }
-        _ => Ok(None),
     };
 
     tracing::info!(message);
@@ -256,7 +295,7 @@ pub fn rustls_client_config(allow_invalid_certs: bool) -> ClientConfig {
 }
 
 #[derive(Debug)]
-struct DummyVerifier;
+pub struct DummyVerifier;
 
 impl ServerCertVerifier for DummyVerifier {
     fn verify_server_cert(
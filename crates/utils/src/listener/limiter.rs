@@ -21,9 +21,11 @@
  * for more details.
 */
 
+use dashmap::DashMap;
 use std::{
+    net::IpAddr,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     time::{Duration, Instant},
@@ -46,8 +48,20 @@ pub struct ConcurrencyLimiter {
 #[derive(Default)]
 pub struct InFlight {
     concurrent: Arc<AtomicU64>,
+    linked: Vec<InFlight>,
 }
 
+#[derive(Debug, Default)]
+pub struct ConnectionLimiter {
+    by_ip: DashMap<IpAddr, Arc<AtomicU64>>,
+    by_account: DashMap<u32, Arc<AtomicU64>>,
+    ip_acquisitions: AtomicUsize,
+    account_acquisitions: AtomicUsize,
+}
+
+const CLEANUP_THRESHOLD: usize = 4096;
+const CLEANUP_INTERVAL: usize = 1024;
+
 impl Drop for InFlight {
     fn drop(&mut self) {
         self.concurrent.fetch_sub(1, Ordering::Relaxed);
@@ -119,6 +133,7 @@ impl ConcurrencyLimiter {
             self.concurrent.fetch_add(1, Ordering::Relaxed);
             Some(InFlight {
                 concurrent: self.concurrent.clone(),
+                linked: Vec::new(),
             })
         } else {
             None
@@ -138,4 +153,143 @@ impl InFlight {
     pub fn num_concurrent(&self) -> u64 {
         self.concurrent.load(Ordering::Relaxed)
     }
+
+    pub fn link(&mut self, in_flight: InFlight) {
+        self.linked.push(in_flight);
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn is_ip_allowed(&self, ip: IpAddr, max_concurrent: u64) -> Option<InFlight> {
+        Self::cleanup(&self.by_ip, &self.ip_acquisitions);
+        Self::acquire(&self.by_ip.entry(ip).or_default(), max_concurrent)
+    }
+
+    pub fn is_account_allowed(&self, account_id: u32, max_concurrent: u64) -> Option<InFlight> {
+        Self::cleanup(&self.by_account, &self.account_acquisitions);
+        Self::acquire(
+            &self.by_account.entry(account_id).or_default(),
+            max_concurrent,
+        )
+    }
+
+    pub fn ip_connections(&self, ip: &IpAddr) -> u64 {
+        self.by_ip
+            .get(ip)
+            .map_or(0, |concurrent| concurrent.load(Ordering::Relaxed))
+    }
+
+    pub fn account_connections(&self, account_id: u32) -> u64 {
+        self.by_account
+            .get(&account_id)
+            .map_or(0, |concurrent| concurrent.load(Ordering::Relaxed))
+    }
+
+    pub fn num_connections(&self) -> u64 {
+        self.by_ip
+            .iter()
+            .map(|concurrent| concurrent.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    // Removing idle entries locks every shard, so the map size is only
+    // checked once every CLEANUP_INTERVAL acquisitions
+    fn cleanup<K: Eq + std::hash::Hash>(
+        map: &DashMap<K, Arc<AtomicU64>>,
+        acquisitions: &AtomicUsize,
+    ) {
+        if acquisitions.fetch_add(1, Ordering::Relaxed) % CLEANUP_INTERVAL == 0
+            && map.len() > CLEANUP_THRESHOLD
+        {
+            map.retain(|_, concurrent| concurrent.load(Ordering::Relaxed) > 0);
+        }
+    }
+
+    // The counter is incremented while the entry is still locked, otherwise a
+    // concurrent cleanup could remove it before it is in use
+    fn acquire(concurrent: &Arc<AtomicU64>, max_concurrent: u64) -> Option<InFlight> {
+        if concurrent.fetch_add(1, Ordering::Relaxed) < max_concurrent {
+            Some(InFlight {
+                concurrent: concurrent.clone(),
+                linked: Vec::new(),
+            })
+        } else {
+            concurrent.fetch_sub(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+
+    use super::{ConnectionLimiter, CLEANUP_INTERVAL, CLEANUP_THRESHOLD};
+
+    #[test]
+    fn connection_limits() {
+        let limiter = ConnectionLimiter::default();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        // Connections are counted per IP address and per account
+        let first = limiter.is_ip_allowed(ip, 2).unwrap();
+        let second = limiter.is_ip_allowed(ip, 2).unwrap();
+        assert!(limiter.is_ip_allowed(ip, 2).is_none());
+        assert_eq!(limiter.ip_connections(&ip), 2);
+        let account = limiter.is_account_allowed(1, 1).unwrap();
+        assert!(limiter.is_account_allowed(1, 1).is_none());
+        assert!(limiter.is_account_allowed(2, 1).is_some());
+        assert_eq!(limiter.account_connections(1), 1);
+        drop(first);
+        drop(account);
+        assert_eq!(limiter.ip_connections(&ip), 1);
+        assert_eq!(limiter.num_connections(), 1);
+        assert!(limiter.is_account_allowed(1, 1).is_some());
+
+        // Cleaning up idle entries keeps the ones in use
+        for n in 0..(CLEANUP_THRESHOLD + CLEANUP_INTERVAL) as u32 {
+            limiter.is_ip_allowed(IpAddr::V4(n.into()), 1);
+        }
+        assert!(limiter.by_ip.len() < CLEANUP_THRESHOLD);
+        assert!(limiter.is_ip_allowed(ip, 2).is_some());
+        assert!(limiter.is_ip_allowed(ip, 1).is_none());
+        drop(second);
+        assert_eq!(limiter.num_connections(), 0);
+    }
+
+    #[test]
+    fn connection_limits_during_cleanup() {
+        let limiter = Arc::new(ConnectionLimiter::default());
+        let holders = Arc::new(AtomicU64::new(0));
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        // Acquiring a connection must never race with the removal of idle
+        // entries, which would allow the limit to be exceeded
+        let threads = (0..4u32)
+            .map(|thread_id| {
+                let limiter = limiter.clone();
+                let holders = holders.clone();
+                std::thread::spawn(move || {
+                    for n in 0..20_000u32 {
+                        limiter.is_ip_allowed(IpAddr::V4(((thread_id << 24) | n).into()), 1);
+                        if let Some(in_flight) = limiter.is_ip_allowed(ip, 1) {
+                            assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+                            holders.fetch_sub(1, Ordering::SeqCst);
+                            drop(in_flight);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(limiter.num_connections(), 0);
+    }
 }
@@ -21,7 +21,12 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
 use tokio::{
@@ -35,6 +40,7 @@ use crate::{
     config::{Config, Listener, Server, ServerProtocol, Servers},
     failed,
     listener::SessionData,
+    stats::ServerStats,
     UnwrapFailure,
 };
 
@@ -56,8 +62,14 @@ impl Server {
             tls_acceptor: self.tls.map(|config| TlsAcceptor::from(Arc::new(config))),
             is_tls_implicit: self.tls_implicit,
             limiter: ConcurrencyLimiter::new(self.max_connections),
+            connections: self.connections,
+            max_connections_ip: self.max_connections_ip,
+            max_connections_account: self.max_connections_account,
             shutdown_rx,
         });
+        ServerStats::get()
+            .register_listener(instance.protocol, instance.limiter.concurrent.clone());
+        let drain_timeout = self.drain_timeout;
 
         // Spawn listeners
         for listener in self.listeners {
@@ -69,12 +81,12 @@ impl Server {
                 tls = instance.is_tls_implicit,
                 "Starting listener"
             );
-            let local_ip = listener.addr.ip();
-
-            // Obtain TCP options
-            let nodelay = listener.nodelay;
-            let ttl = listener.ttl;
-            let linger = listener.linger;
+            let options = AcceptOptions {
+                local_ip: listener.addr.ip(),
+                nodelay: listener.nodelay,
+                ttl: listener.ttl,
+                linger: listener.linger,
+            };
 
             // Bind socket
             let listener = listener.listen();
@@ -89,79 +101,7 @@ impl Server {
                         stream = listener.accept() => {
                             match stream {
                                 Ok((stream, remote_addr)) => {
-                                    // Convert mapped IPv6 addresses to IPv4
-                                    let remote_ip = match remote_addr.ip() {
-                                        IpAddr::V6(ip) => {
-                                            ip.to_ipv4_mapped()
-                                            .map(IpAddr::V4)
-                                            .unwrap_or(IpAddr::V6(ip))
-                                        }
-                                        remote_ip => remote_ip,
-                                    };
-                                    let remote_port = remote_addr.port();
-
-                                    // Enforce concurrency
-                                    if let Some(in_flight) = instance.limiter.is_allowed() {
-                                        let span = tracing::info_span!(
-                                            "session",
-                                            instance = instance.id,
-                                            protocol = ?instance.protocol,
-                                            remote.ip = remote_ip.to_string(),
-                                            remote.port = remote_port,
-                                        );
-
-                                        // Set TCP options
-                                        if let Err(err) = stream.set_nodelay(nodelay) {
-                                            tracing::warn!(
-                                                context = "tcp",
-                                                event = "error",
-                                                instance = instance.id,
-                                                protocol = ?instance.protocol,
-                                                "Failed to set no-delay: {}", err);
-                                        }
-                                        if let Some(ttl) = ttl {
-                                            if let Err(err) = stream.set_ttl(ttl) {
-                                                tracing::warn!(
-                                                    context = "tcp",
-                                                    event = "error",
-                                                    instance = instance.id,
-                                                    protocol = ?instance.protocol,
-                                                    "Failed to set TTL: {}", err);
-                                            }
-                                        }
-                                        if linger.is_some() {
-                                            if let Err(err) = stream.set_linger(linger) {
-                                                tracing::warn!(
-                                                    context = "tcp",
-                                                    event = "error",
-                                                    instance = instance.id,
-                                                    protocol = ?instance.protocol,
-                                                    "Failed to set linger: {}", err);
-                                            }
-                                        }
-
-                                        // Spawn connection
-                                        manager.spawn(SessionData {
-                                            stream,
-                                            local_ip,
-                                            remote_ip,
-                                            remote_port,
-                                            span,
-                                            in_flight,
-                                            instance: instance.clone(),
-                                        });
-                                    } else {
-                                        tracing::info!(
-                                            context = "throttle",
-                                            event = "too-many-requests",
-                                            instance = instance.id,
-                                            protocol = ?instance.protocol,
-                                            remote.ip = remote_ip.to_string(),
-                                            remote.port = remote_port,
-                                            max_concurrent = instance.limiter.max_concurrent,
-                                            "Too many concurrent connections."
-                                        );
-                                    };
+                                    instance.accept(&manager, stream, remote_addr, options);
                                 }
                                 Err(err) => {
                                     tracing::trace!(context = "io",
@@ -178,21 +118,242 @@ impl Server {
                                 instance = instance.id,
                                 protocol = ?instance.protocol,
                                 "Listener shutting down.");
-                            manager.shutdown();
                             break;
                         }
                     };
                 }
+
+                // Connections waiting in the accept queue would be reset when the socket
+                // is closed, serve them with an instance that is not being shut down.
+                let (handover_tx, handover_rx) = watch::channel(false);
+                let handover = Arc::new(instance.with_shutdown_rx(handover_rx));
+                match listener.into_std() {
+                    Ok(listener) => {
+                        // The socket is non-blocking, accept until the backlog is empty
+                        loop {
+                            match listener.accept().and_then(|(stream, remote_addr)| {
+                                stream.set_nonblocking(true)?;
+                                TcpStream::from_std(stream).map(|stream| (stream, remote_addr))
+                            }) {
+                                Ok((stream, remote_addr)) => {
+                                    handover.accept(&manager, stream, remote_addr, options);
+                                }
+                                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                                Err(err)
+                                    if matches!(
+                                        err.kind(),
+                                        ErrorKind::ConnectionAborted
+                                            | ErrorKind::ConnectionReset
+                                            | ErrorKind::Interrupted
+                                    ) => {}
+                                Err(err) => {
+                                    tracing::debug!(context = "io",
+                                                    event = "error",
+                                                    instance = instance.id,
+                                                    protocol = ?instance.protocol,
+                                                    "Failed to drain accept queue: {}", err);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        tracing::debug!(context = "io",
+                                        event = "error",
+                                        instance = instance.id,
+                                        protocol = ?instance.protocol,
+                                        "Failed to drain accept queue: {}", err);
+                    }
+                }
+
+                // Stop accepting connections and wait for in-flight sessions to finish
+                let drain_until = Instant::now() + drain_timeout;
+                while instance.limiter.is_active() && Instant::now() < drain_until {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                drop(handover_tx);
+                manager.shutdown();
+            });
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AcceptOptions {
+    local_ip: IpAddr,
+    nodelay: bool,
+    ttl: Option<u32>,
+    linger: Option<Duration>,
+}
+
+impl ServerInstance {
+    fn accept(
+        self: &Arc<Self>,
+        manager: &impl SessionManager,
+        stream: TcpStream,
+        remote_addr: SocketAddr,
+        options: AcceptOptions,
+    ) {
+        // Convert mapped IPv6 addresses to IPv4
+        let remote_ip = match remote_addr.ip() {
+            IpAddr::V6(ip) => ip
+                .to_ipv4_mapped()
+                .map(IpAddr::V4)
+                .unwrap_or(IpAddr::V6(ip)),
+            remote_ip => remote_ip,
+        };
+        let remote_port = remote_addr.port();
+
+        // Enforce per-IP concurrency
+        let in_flight_ip = if let Some(in_flight_ip) = self
+            .connections
+            .is_ip_allowed(remote_ip, self.max_connections_ip)
+        {
+            in_flight_ip
+        } else {
+            tracing::info!(
+                context = "throttle",
+                event = "too-many-connections",
+                instance = self.id,
+                protocol = ?self.protocol,
+                remote.ip = remote_ip.to_string(),
+                remote.port = remote_port,
+                max_concurrent = self.max_connections_ip,
+                "Too many concurrent connections from IP address."
+            );
+
+            // Best-effort goodbye, TLS listeners are dropped silently
+            if !self.is_tls_implicit {
+                let _ = stream.try_write(self.protocol.too_many_connections());
+            }
+            return;
+        };
+
+        // Enforce concurrency
+        if let Some(mut in_flight) = self.limiter.is_allowed() {
+            in_flight.link(in_flight_ip);
+            let span = tracing::info_span!(
+                "session",
+                instance = self.id,
+                protocol = ?self.protocol,
+                remote.ip = remote_ip.to_string(),
+                remote.port = remote_port,
+                account = tracing::field::Empty,
+            );
+
+            // Set TCP options
+            if let Err(err) = stream.set_nodelay(options.nodelay) {
+                tracing::warn!(
+                    context = "tcp",
+                    event = "error",
+                    instance = self.id,
+                    protocol = ?self.protocol,
+                    "Failed to set no-delay: {}", err);
+            }
+            if let Some(ttl) = options.ttl {
+                if let Err(err) = stream.set_ttl(ttl) {
+                    tracing::warn!(
+                        context = "tcp",
+                        event = "error",
+                        instance = self.id,
+                        protocol = ?self.protocol,
+                        "Failed to set TTL: {}", err);
+                }
+            }
+            if options.linger.is_some() {
+                if let Err(err) = stream.set_linger(options.linger) {
+                    tracing::warn!(
+                        context = "tcp",
+                        event = "error",
+                        instance = self.id,
+                        protocol = ?self.protocol,
+                        "Failed to set linger: {}", err);
+                }
+            }
+
+            // Spawn connection
+            manager.spawn(SessionData {
+                stream,
+                local_ip: options.local_ip,
+                remote_ip,
+                remote_port,
+                span,
+                in_flight,
+                instance: self.clone(),
             });
+        } else {
+            tracing::info!(
+                context = "throttle",
+                event = "too-many-requests",
+                instance = self.id,
+                protocol = ?self.protocol,
+                remote.ip = remote_ip.to_string(),
+                remote.port = remote_port,
+                max_concurrent = self.limiter.max_concurrent,
+                "Too many concurrent connections."
+            );
+        }
+    }
+
+    fn with_shutdown_rx(&self, shutdown_rx: watch::Receiver<bool>) -> Self {
+        ServerInstance {
+            id: self.id.clone(),
+            listener_id: self.listener_id,
+            protocol: self.protocol,
+            hostname: self.hostname.clone(),
+            data: self.data.clone(),
+            tls_acceptor: self.tls_acceptor.clone(),
+            is_tls_implicit: self.is_tls_implicit,
+            limiter: self.limiter.clone(),
+            connections: self.connections.clone(),
+            max_connections_ip: self.max_connections_ip,
+            max_connections_account: self.max_connections_account,
+            shutdown_rx,
+        }
+    }
+}
+
+impl ServerProtocol {
+    pub fn too_many_connections(&self) -> &'static [u8] {
+        match self {
+            ServerProtocol::Smtp | ServerProtocol::Lmtp => {
+                b"421 4.7.0 Too many concurrent connections, try again later.\r\n"
+            }
+            ServerProtocol::Imap => b"* BYE Too many concurrent connections.\r\n",
+            ServerProtocol::ManageSieve => b"BYE \"Too many concurrent connections.\"\r\n",
+            ServerProtocol::Jmap | ServerProtocol::Http => concat!(
+                "HTTP/1.1 503 Service Unavailable\r\n",
+                "Connection: close\r\n",
+                "Content-Length: 0\r\n\r\n"
+            )
+            .as_bytes(),
         }
     }
 }
 
 impl Servers {
-    pub fn bind(&self, config: &Config) {
+    pub fn bind(&mut self, config: &Config) {
+        // Use any sockets passed by systemd socket activation
+        #[cfg(unix)]
+        let mut activated = crate::systemd::listen_fds();
+
         // Bind as root
-        for server in &self.inner {
-            for listener in &server.listeners {
+        for server in &mut self.inner {
+            for listener in &mut server.listeners {
+                #[cfg(unix)]
+                if let Some(socket) = activated.remove(&listener.addr) {
+                    tracing::debug!(
+                        context = "systemd",
+                        event = "activate",
+                        id = server.id,
+                        bind.ip = listener.addr.ip().to_string(),
+                        bind.port = listener.addr.port(),
+                        "Using socket passed by systemd."
+                    );
+                    listener.socket = socket;
+                    continue;
+                }
+
                 listener
                     .socket
                     .bind(listener.addr)
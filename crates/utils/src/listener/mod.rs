@@ -32,7 +32,7 @@ use tokio_rustls::TlsAcceptor;
 
 use crate::config::ServerProtocol;
 
-use self::limiter::{ConcurrencyLimiter, InFlight};
+use self::limiter::{ConcurrencyLimiter, ConnectionLimiter, InFlight};
 
 pub mod limiter;
 pub mod listen;
@@ -46,6 +46,9 @@ pub struct ServerInstance {
     pub tls_acceptor: Option<TlsAcceptor>,
     pub is_tls_implicit: bool,
     pub limiter: ConcurrencyLimiter,
+    pub connections: Arc<ConnectionLimiter>,
+    pub max_connections_ip: u64,
+    pub max_connections_account: u64,
     pub shutdown_rx: watch::Receiver<bool>,
 }
 
@@ -0,0 +1,378 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::sync::mpsc;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Subscriber,
+};
+use tracing_subscriber::{filter::filter_fn, layer::Context, registry::LookupSpan, Layer};
+
+const MAX_PENDING_EVENTS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceFilter {
+    RemoteIp(IpAddr),
+    Account(String),
+    QueueId(u64),
+}
+
+#[derive(Default)]
+pub struct LiveTracer {
+    sessions: RwLock<Vec<TraceSession>>,
+    is_active: AtomicBool,
+    next_id: AtomicU64,
+}
+
+struct TraceSession {
+    id: u64,
+    filter: TraceFilter,
+    expires: Instant,
+    tx: mpsc::Sender<String>,
+}
+
+pub struct LiveTrace {
+    id: u64,
+    pub expires: Instant,
+    pub rx: mpsc::Receiver<String>,
+}
+
+pub struct LiveTraceLayer;
+
+#[derive(Default)]
+pub(crate) struct TraceFields {
+    is_delivery: bool,
+    pub(crate) remote_ip: Option<IpAddr>,
+    pub(crate) account: Option<String>,
+    pub(crate) queue_id: Option<u64>,
+}
+
+pub(crate) struct EventVisitor(pub(crate) serde_json::Map<String, serde_json::Value>);
+
+impl LiveTracer {
+    pub fn get() -> &'static LiveTracer {
+        static TRACER: OnceLock<LiveTracer> = OnceLock::new();
+        TRACER.get_or_init(LiveTracer::default)
+    }
+
+    pub fn subscribe(&self, filter: TraceFilter, duration: Duration) -> LiveTrace {
+        let (tx, rx) = mpsc::channel(MAX_PENDING_EVENTS);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let expires = Instant::now() + duration;
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.push(TraceSession {
+            id,
+            filter,
+            expires,
+            tx,
+        });
+        self.is_active.store(true, Ordering::Relaxed);
+
+        LiveTrace { id, expires, rx }
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.retain(|session| session.id != id);
+        self.is_active
+            .store(!sessions.is_empty(), Ordering::Relaxed);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for LiveTrace {
+    fn drop(&mut self) {
+        LiveTracer::get().unsubscribe(self.id);
+    }
+}
+
+impl TraceFilter {
+    fn matches(&self, fields: &TraceFields) -> bool {
+        match self {
+            TraceFilter::RemoteIp(ip) => fields.remote_ip.as_ref() == Some(ip),
+            TraceFilter::Account(account) => fields
+                .account
+                .as_ref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(account)),
+            TraceFilter::QueueId(id) => fields.queue_id == Some(*id),
+        }
+    }
+}
+
+impl TraceFields {
+    fn has_values(&self) -> bool {
+        self.remote_ip.is_some() || self.account.is_some() || self.queue_id.is_some()
+    }
+
+    pub(crate) fn merge(&mut self, other: &TraceFields) {
+        if self.remote_ip.is_none() {
+            self.remote_ip = other.remote_ip;
+        }
+        if self.account.is_none() {
+            self.account = other.account.clone();
+        }
+        if self.queue_id.is_none() {
+            self.queue_id = other.queue_id;
+        }
+    }
+}
+
+impl Visit for TraceFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if self.is_delivery && field.name() == "id" {
+            self.queue_id = value.into();
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "remote.ip" => {
+                self.remote_ip = value.parse().ok();
+            }
+            "account" => {
+                self.account = value.to_string().into();
+            }
+            _ => (),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if matches!(field.name(), "remote.ip" | "account") {
+            self.record_str(field, format!("{value:?}").trim_matches('"'));
+        }
+    }
+}
+
+impl Visit for EventVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+impl<S> Layer<S> for LiveTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = TraceFields {
+            is_delivery: attrs.metadata().name() == "delivery",
+            ..Default::default()
+        };
+        attrs.record(&mut fields);
+        if fields.has_values() {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(fields);
+            }
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(fields) = extensions.get_mut::<TraceFields>() {
+                values.record(fields);
+            } else {
+                let mut fields = TraceFields {
+                    is_delivery: span.name() == "delivery",
+                    ..Default::default()
+                };
+                values.record(&mut fields);
+                if fields.has_values() {
+                    extensions.insert(fields);
+                }
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let tracer = LiveTracer::get();
+        if !tracer.is_active() {
+            return;
+        }
+
+        // Collect the identifying fields from all enclosing spans
+        let mut fields = TraceFields::default();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                if let Some(span_fields) = span.extensions().get::<TraceFields>() {
+                    fields.merge(span_fields);
+                }
+            }
+        }
+        if !fields.has_values() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut payload = None;
+        for session in tracer.sessions.read().unwrap().iter() {
+            if session.expires > now && session.filter.matches(&fields) {
+                let payload = payload.get_or_insert_with(|| format_event(event, &fields));
+                let _ = session.tx.try_send(payload.clone());
+            }
+        }
+    }
+}
+
+fn format_event(event: &Event<'_>, fields: &TraceFields) -> String {
+    let metadata = event.metadata();
+    let mut visitor = EventVisitor(serde_json::Map::new());
+    event.record(&mut visitor);
+    if let Some(ip) = &fields.remote_ip {
+        visitor
+            .0
+            .entry("remote.ip")
+            .or_insert_with(|| ip.to_string().into());
+    }
+    if let Some(account) = &fields.account {
+        visitor
+            .0
+            .entry("account")
+            .or_insert_with(|| account.clone().into());
+    }
+    if let Some(queue_id) = fields.queue_id {
+        visitor
+            .0
+            .entry("queue.id")
+            .or_insert_with(|| queue_id.into());
+    }
+
+    serde_json::json!({
+        "timestamp": SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64),
+        "level": metadata.level().as_str(),
+        "target": metadata.target(),
+        "fields": visitor.0,
+    })
+    .to_string()
+}
+
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // Spans are always seen so their fields can be captured, events are only
+    // processed while at least one live trace is active.
+    LiveTraceLayer.with_filter(filter_fn(|metadata| {
+        metadata.is_span() || LiveTracer::get().is_active()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{LiveTracer, TraceFilter};
+
+    #[test]
+    fn live_trace() {
+        let subscriber = tracing_subscriber::registry().with(super::layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let tracer = LiveTracer::get();
+            let mut by_ip = tracer.subscribe(
+                TraceFilter::RemoteIp("10.0.0.1".parse().unwrap()),
+                Duration::from_secs(60),
+            );
+            let mut by_account = tracer.subscribe(
+                TraceFilter::Account("John@example.org".to_string()),
+                Duration::from_secs(60),
+            );
+            let mut by_queue_id =
+                tracer.subscribe(TraceFilter::QueueId(1234), Duration::from_secs(60));
+            let expired = tracer.subscribe(
+                TraceFilter::RemoteIp("10.0.0.1".parse().unwrap()),
+                Duration::ZERO,
+            );
+            assert!(tracer.is_active());
+
+            // Events are matched against the fields of their enclosing spans,
+            // including fields recorded after the span was created
+            let session = tracing::info_span!(
+                "session",
+                remote.ip = "10.0.0.1",
+                account = tracing::field::Empty
+            );
+            session.in_scope(|| tracing::debug!(context = "test", "before auth"));
+            session.record("account", "john@example.org");
+            session.in_scope(|| tracing::trace!(context = "test", "after auth"));
+            tracing::info_span!("session", remote.ip = "10.0.0.2")
+                .in_scope(|| tracing::debug!(context = "test", "other ip"));
+            tracing::info_span!("delivery", id = 1234u64)
+                .in_scope(|| tracing::debug!(context = "test", "queued"));
+            tracing::info_span!("other", id = 1234u64)
+                .in_scope(|| tracing::debug!(context = "test", "not a delivery"));
+            tracing::debug!(context = "test", "no span");
+
+            let events = |rx: &mut tokio::sync::mpsc::Receiver<String>| {
+                let mut events = Vec::new();
+                while let Ok(event) = rx.try_recv() {
+                    let event = serde_json::from_str::<serde_json::Value>(&event).unwrap();
+                    events.push(event["fields"]["message"].as_str().unwrap().to_string());
+                }
+                events
+            };
+            assert_eq!(events(&mut by_ip.rx), ["before auth", "after auth"]);
+            assert_eq!(events(&mut by_account.rx), ["after auth"]);
+            assert_eq!(events(&mut by_queue_id.rx), ["queued"]);
+
+            // Dropped traces are unsubscribed
+            drop(expired);
+            drop(by_ip);
+            drop(by_account);
+            drop(by_queue_id);
+            assert!(!tracer.is_active());
+        });
+    }
+}
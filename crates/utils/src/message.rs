@@ -0,0 +1,257 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_parser::{
+    parsers::MessageStream, Address, Header, HeaderName, HeaderValue, Message, MessageParser,
+    PartType,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct MessageLimits {
+    pub max_headers: usize,
+    pub max_depth: usize,
+    pub max_parts: usize,
+    pub max_addresses: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Headers,
+    Depth,
+    Parts,
+    Addresses,
+}
+
+impl MessageLimits {
+    /// Verifies that a parsed message does not exceed the header count,
+    /// MIME nesting depth, part count and address list limits.
+    pub fn check(&self, message: &Message<'_>) -> Result<(), LimitExceeded> {
+        let mut num_parts = 0;
+        let mut stack = vec![(message, 0, 0)];
+
+        while let Some((message, part_id, depth)) = stack.pop() {
+            let part = if let Some(part) = message.parts.get(part_id) {
+                part
+            } else {
+                continue;
+            };
+
+            num_parts += 1;
+            self.check_part(&part.headers, num_parts, depth)?;
+
+            match &part.body {
+                PartType::Message(nested_message) => {
+                    stack.push((nested_message, 0, depth + 1));
+                }
+                PartType::Multipart(sub_parts) => {
+                    for sub_part_id in sub_parts {
+                        stack.push((message, *sub_part_id, depth + 1));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the same limits on a raw message by walking its MIME structure
+    /// without decoding any of the part bodies.
+    pub fn check_raw(&self, raw_message: &[u8]) -> Result<(), LimitExceeded> {
+        let parser = MessageParser::new();
+        let mut num_parts = 0;
+        let mut stack = vec![(raw_message, 0)];
+
+        while let Some((raw_part, depth)) = stack.pop() {
+            let mut stream = MessageStream::new(raw_part);
+            let mut headers = Vec::new();
+            if !stream.parse_headers(&parser, &mut headers) {
+                continue;
+            }
+
+            num_parts += 1;
+            self.check_part(&headers, num_parts, depth)?;
+
+            let content_type = if let Some(content_type) = headers
+                .iter()
+                .find(|header| header.name == HeaderName::ContentType)
+                .and_then(|header| header.value.as_content_type())
+            {
+                content_type
+            } else {
+                continue;
+            };
+
+            if content_type.c_type.eq_ignore_ascii_case("multipart") {
+                let boundary = if let Some(boundary) = content_type.attribute("boundary") {
+                    boundary.as_bytes()
+                } else {
+                    continue;
+                };
+                if !stream.seek_next_part(boundary) {
+                    continue;
+                }
+                while !stream.is_multipart_end() {
+                    stream.skip_crlf();
+                    let start = stream.offset();
+                    let (end, found) = stream.seek_part_end(boundary.into());
+                    stack.push((stream.bytes(start..end.max(start)), depth + 1));
+                    if !found {
+                        break;
+                    }
+                }
+            } else if content_type.c_type.eq_ignore_ascii_case("message")
+                && content_type.c_subtype.as_ref().is_some_and(|subtype| {
+                    subtype.eq_ignore_ascii_case("rfc822") || subtype.eq_ignore_ascii_case("global")
+                })
+            {
+                stack.push((stream.bytes(stream.offset()..raw_part.len()), depth + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_part(
+        &self,
+        headers: &[Header<'_>],
+        num_parts: usize,
+        depth: usize,
+    ) -> Result<(), LimitExceeded> {
+        if num_parts > self.max_parts {
+            return Err(LimitExceeded::Parts);
+        } else if depth > self.max_depth {
+            return Err(LimitExceeded::Depth);
+        } else if headers.len() > self.max_headers {
+            return Err(LimitExceeded::Headers);
+        }
+
+        for header in headers {
+            let num_addresses = match &header.value {
+                HeaderValue::Address(Address::List(list)) => list.len(),
+                HeaderValue::Address(Address::Group(groups)) => groups
+                    .iter()
+                    .map(|group| group.addresses.len().max(1))
+                    .sum(),
+                _ => 0,
+            };
+            if num_addresses > self.max_addresses {
+                return Err(LimitExceeded::Addresses);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LimitExceeded {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitExceeded::Headers => "headers",
+            LimitExceeded::Depth => "mime-depth",
+            LimitExceeded::Parts => "mime-parts",
+            LimitExceeded::Addresses => "addresses",
+        }
+    }
+
+    pub fn code(&self) -> [u8; 3] {
+        match self {
+            LimitExceeded::Headers => [5, 6, 0],
+            LimitExceeded::Depth => [5, 6, 1],
+            LimitExceeded::Parts => [5, 3, 4],
+            LimitExceeded::Addresses => [5, 6, 0],
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            LimitExceeded::Headers => "Message contains too many header fields.",
+            LimitExceeded::Depth => "Message MIME structure is nested too deeply.",
+            LimitExceeded::Parts => "Message contains too many MIME parts.",
+            LimitExceeded::Addresses => "Message contains too many addresses in a header field.",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::{LimitExceeded, MessageLimits};
+
+    const NESTED_MESSAGE: &str = concat!(
+        "From: john@example.org\r\n",
+        "To: jane@example.org, bill@example.org\r\n",
+        "Subject: Nested\r\n",
+        "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+        "--outer\r\n",
+        "Content-Type: text/plain\r\n\r\n",
+        "Hello\r\n",
+        "--outer\r\n",
+        "Content-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n",
+        "--inner\r\n",
+        "Content-Type: text/plain\r\n\r\n",
+        "Hi\r\n",
+        "--inner\r\n",
+        "Content-Type: text/html\r\n\r\n",
+        "<p>Hi</p>\r\n",
+        "--inner--\r\n",
+        "--outer\r\n",
+        "Content-Type: message/rfc822\r\n\r\n",
+        "From: jane@example.org\r\n",
+        "Subject: Attached\r\n\r\n",
+        "Attached message\r\n",
+        "--outer--\r\n",
+    );
+
+    #[test]
+    fn check_raw_message_limits() {
+        // 7 parts: root, text, alternative, 2 alternatives, message/rfc822 and its body
+        for (max_headers, max_depth, max_parts, max_addresses, expected) in [
+            (10, 10, 10, 10, Ok(())),
+            (10, 10, 7, 10, Ok(())),
+            (10, 10, 6, 10, Err(LimitExceeded::Parts)),
+            (10, 2, 10, 10, Ok(())),
+            (10, 1, 10, 10, Err(LimitExceeded::Depth)),
+            (4, 10, 10, 10, Ok(())),
+            (3, 10, 10, 10, Err(LimitExceeded::Headers)),
+            (10, 10, 10, 1, Err(LimitExceeded::Addresses)),
+        ] {
+            let limits = MessageLimits {
+                max_headers,
+                max_depth,
+                max_parts,
+                max_addresses,
+            };
+            let message = MessageParser::new()
+                .parse(NESTED_MESSAGE.as_bytes())
+                .unwrap();
+            assert_eq!(limits.check(&message), expected, "{limits:?}");
+            assert_eq!(
+                limits.check_raw(NESTED_MESSAGE.as_bytes()),
+                expected,
+                "{limits:?}"
+            );
+        }
+    }
+}
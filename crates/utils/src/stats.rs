@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::{Instant, SystemTime},
+};
+
+use ahash::AHashMap;
+
+use crate::config::ServerProtocol;
+
+const WINDOW_SECS: usize = 60;
+const MAX_TRACKED_DOMAINS: usize = 4096;
+
+pub struct ServerStats {
+    started: Instant,
+    listeners: RwLock<Vec<(ServerProtocol, Arc<AtomicU64>)>>,
+    auth_failures: AtomicU64,
+    auth_window: RollingCounter,
+    messages: AtomicU64,
+    messages_window: RollingCounter,
+    sender_domains: Mutex<AHashMap<String, RollingCounter>>,
+    tls_handshakes: AtomicU64,
+    tls_resumed: AtomicU64,
+}
+
+// Events seen during the last minute, each bucket holds one second
+struct RollingCounter {
+    buckets: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl ServerStats {
+    pub fn get() -> &'static ServerStats {
+        static STATS: OnceLock<ServerStats> = OnceLock::new();
+        STATS.get_or_init(|| ServerStats {
+            started: Instant::now(),
+            listeners: RwLock::new(Vec::new()),
+            auth_failures: AtomicU64::new(0),
+            auth_window: RollingCounter::new(),
+            messages: AtomicU64::new(0),
+            messages_window: RollingCounter::new(),
+            sender_domains: Mutex::new(AHashMap::new()),
+            tls_handshakes: AtomicU64::new(0),
+            tls_resumed: AtomicU64::new(0),
+        })
+    }
+
+    pub fn register_listener(&self, protocol: ServerProtocol, concurrent: Arc<AtomicU64>) {
+        self.listeners.write().unwrap().push((protocol, concurrent));
+    }
+
+    pub fn sessions(&self) -> Vec<(ServerProtocol, u64)> {
+        let mut sessions: Vec<(ServerProtocol, u64)> = Vec::new();
+        for (protocol, concurrent) in self.listeners.read().unwrap().iter() {
+            let concurrent = concurrent.load(Ordering::Relaxed);
+            if let Some((_, total)) = sessions.iter_mut().find(|(p, _)| p == protocol) {
+                *total += concurrent;
+            } else {
+                sessions.push((*protocol, concurrent));
+            }
+        }
+        sessions
+    }
+
+    pub fn record_auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+        self.auth_window.increment(now());
+    }
+
+    pub fn auth_failures(&self) -> u64 {
+        self.auth_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn auth_failures_last_minute(&self) -> u64 {
+        self.auth_window.total(now())
+    }
+
+    pub fn record_message(&self, sender_domain: &str) {
+        let now = now();
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.messages_window.increment(now);
+
+        if !sender_domain.is_empty() {
+            let sender_domain = sender_domain.to_lowercase();
+            let mut domains = self.sender_domains.lock().unwrap();
+            if let Some(counter) = domains.get(&sender_domain) {
+                counter.increment(now);
+            } else {
+                // Forget idle domains before tracking new ones
+                if domains.len() >= MAX_TRACKED_DOMAINS {
+                    domains.retain(|_, counter| counter.total(now) > 0);
+                }
+                if domains.len() < MAX_TRACKED_DOMAINS {
+                    let counter = RollingCounter::new();
+                    counter.increment(now);
+                    domains.insert(sender_domain, counter);
+                }
+            }
+        }
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    pub fn messages_last_minute(&self) -> u64 {
+        self.messages_window.total(now())
+    }
+
+    pub fn top_sender_domains(&self, max_results: usize) -> Vec<(String, u64)> {
+        let now = now();
+        let mut domains = self
+            .sender_domains
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(domain, counter)| {
+                let total = counter.total(now);
+                (total > 0).then(|| (domain.clone(), total))
+            })
+            .collect::<Vec<_>>();
+        domains.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        domains.truncate(max_results);
+        domains
+    }
+
+    // Outbound TLS handshakes, resumed ones skipped certificate verification
+    pub fn record_tls_handshake(&self, is_resumed: bool) {
+        self.tls_handshakes.fetch_add(1, Ordering::Relaxed);
+        if is_resumed {
+            self.tls_resumed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn tls_handshakes(&self) -> u64 {
+        self.tls_handshakes.load(Ordering::Relaxed)
+    }
+
+    pub fn tls_resumed(&self) -> u64 {
+        self.tls_resumed.load(Ordering::Relaxed)
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+}
+
+impl RollingCounter {
+    fn new() -> Self {
+        RollingCounter {
+            buckets: (0..WINDOW_SECS)
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    fn increment(&self, now: u64) {
+        let (second, count) = &self.buckets[now as usize % WINDOW_SECS];
+        if second.swap(now, Ordering::Relaxed) != now {
+            count.store(1, Ordering::Relaxed);
+        } else {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn total(&self, now: u64) -> u64 {
+        self.buckets
+            .iter()
+            .filter(|(second, _)| {
+                now.saturating_sub(second.load(Ordering::Relaxed)) < WINDOW_SECS as u64
+            })
+            .map(|(_, count)| count.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicU64, Arc};
+
+    use crate::config::ServerProtocol;
+
+    use super::{RollingCounter, ServerStats};
+
+    #[test]
+    fn server_stats() {
+        let stats = ServerStats::get();
+        let imap1 = Arc::new(AtomicU64::new(2));
+        let imap2 = Arc::new(AtomicU64::new(3));
+        let smtp = Arc::new(AtomicU64::new(1));
+        stats.register_listener(ServerProtocol::Imap, imap1);
+        stats.register_listener(ServerProtocol::Smtp, smtp);
+        stats.register_listener(ServerProtocol::Imap, imap2);
+        assert_eq!(
+            stats.sessions(),
+            vec![(ServerProtocol::Imap, 5), (ServerProtocol::Smtp, 1)]
+        );
+
+        for _ in 0..3 {
+            stats.record_auth_failure();
+        }
+        assert_eq!(stats.auth_failures(), 3);
+        assert!(stats.auth_failures_last_minute() >= 1);
+
+        for domain in ["example.org", "example.com", "Example.org", ""] {
+            stats.record_message(domain);
+        }
+        assert_eq!(stats.messages(), 4);
+        assert_eq!(
+            stats.top_sender_domains(1),
+            vec![("example.org".to_string(), 2)]
+        );
+
+        stats.record_tls_handshake(false);
+        stats.record_tls_handshake(true);
+        assert_eq!(stats.tls_handshakes(), 2);
+        assert_eq!(stats.tls_resumed(), 1);
+    }
+
+    #[test]
+    fn rolling_counter() {
+        let counter = RollingCounter::new();
+        counter.increment(1000);
+        counter.increment(1000);
+        counter.increment(1030);
+        assert_eq!(counter.total(1030), 3);
+        assert_eq!(counter.total(1059), 3);
+        assert_eq!(counter.total(1060), 1);
+
+        // Buckets are reused once the window wraps around
+        counter.increment(1060);
+        assert_eq!(counter.total(1060), 2);
+        assert_eq!(counter.total(1200), 0);
+    }
+}
@@ -36,6 +36,30 @@ impl PublicSuffix {
             || (!self.exceptions.contains(suffix)
                 && self.wildcards.iter().any(|w| suffix.ends_with(w)))
     }
+
+    /// Returns the registered domain of a lowercase domain name, which is the
+    /// public suffix plus one label.
+    pub fn domain_sld<'x>(&self, domain: &'x str) -> Option<&'x str> {
+        let mut seen_dot = false;
+        for (pos, ch) in domain.as_bytes().iter().enumerate().rev() {
+            if *ch == b'.' {
+                if seen_dot {
+                    let maybe_domain = &domain[pos + 1..];
+                    if !self.contains(maybe_domain) {
+                        return Some(maybe_domain);
+                    }
+                } else {
+                    seen_dot = true;
+                }
+            }
+        }
+
+        if seen_dot {
+            Some(domain)
+        } else {
+            None
+        }
+    }
 }
 
 impl From<&str> for PublicSuffix {
@@ -0,0 +1,660 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashSet;
+use chrono::{SecondsFormat, Utc};
+use rustls_pki_types::ServerName;
+use serde_json::{Map, Value};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    sync::mpsc,
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    config::Config,
+    live_trace::{EventVisitor, TraceFields},
+    rustls_client_config,
+};
+
+const MAX_PENDING_MESSAGES: usize = 4096;
+const MAX_UDP_MESSAGE_SIZE: usize = 8192;
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+const SD_ID: &str = "stalwart@32473";
+const VENDOR: &str = "Stalwart Labs";
+const PRODUCT: &str = "Stalwart Mail Server";
+const DEFAULT_SECURITY_CONTEXTS: &[&str] = &[
+    "auth",
+    "audit",
+    "throttle",
+    "rate_limit",
+    "dlp",
+    "quarantine",
+    "greylist",
+    "policy",
+    "tarpit",
+    "tls",
+];
+
+pub struct SyslogLayer {
+    tx: mpsc::Sender<Vec<u8>>,
+    formatter: SyslogFormatter,
+}
+
+pub struct SyslogFormatter {
+    pub facility: u8,
+    pub format: SyslogFormat,
+    pub hostname: String,
+    pub app_name: String,
+    pub proc_id: String,
+    pub security_contexts: AHashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFormat {
+    Rfc5424,
+    Cef,
+    Leef,
+}
+
+#[derive(Debug, Clone)]
+enum Transport {
+    Udp,
+    Tcp,
+    Tls { allow_invalid_certs: bool },
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+pub fn layer(config: &Config) -> crate::config::Result<SyslogLayer> {
+    let endpoint = config.value_require("global.tracing.endpoint")?.to_string();
+    let transport = match config.value("global.tracing.transport").unwrap_or("udp") {
+        "udp" => Transport::Udp,
+        "tcp" => Transport::Tcp,
+        "tls" => Transport::Tls {
+            allow_invalid_certs: config
+                .property_or_static("global.tracing.allow-invalid-certs", "false")?,
+        },
+        transport => {
+            return Err(format!("Unsupported syslog transport {transport:?}"));
+        }
+    };
+    let facility = config.value("global.tracing.facility").unwrap_or("mail");
+    let facility =
+        parse_facility(facility).ok_or_else(|| format!("Invalid syslog facility {facility:?}"))?;
+    let format = match config.value("global.tracing.format").unwrap_or("rfc5424") {
+        "rfc5424" => SyslogFormat::Rfc5424,
+        "cef" => SyslogFormat::Cef,
+        "leef" => SyslogFormat::Leef,
+        format => {
+            return Err(format!("Unsupported syslog format {format:?}"));
+        }
+    };
+    let mut security_contexts = config
+        .values("global.tracing.security-contexts")
+        .map(|(_, context)| context.to_string())
+        .collect::<AHashSet<_>>();
+    if security_contexts.is_empty() {
+        security_contexts = DEFAULT_SECURITY_CONTEXTS
+            .iter()
+            .map(|context| context.to_string())
+            .collect();
+    }
+
+    let (tx, rx) = mpsc::channel(MAX_PENDING_MESSAGES);
+    tokio::spawn(write_messages(endpoint, transport, rx));
+
+    Ok(SyslogLayer {
+        tx,
+        formatter: SyslogFormatter {
+            facility,
+            format,
+            hostname: sanitize_header(
+                config
+                    .value("global.tracing.hostname")
+                    .or_else(|| config.value("server.hostname"))
+                    .unwrap_or("-"),
+                255,
+            ),
+            app_name: sanitize_header(
+                config
+                    .value("global.tracing.app-name")
+                    .unwrap_or("stalwart"),
+                48,
+            ),
+            proc_id: std::process::id().to_string(),
+            security_contexts,
+        },
+    })
+}
+
+impl<S> Layer<S> for SyslogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor(Map::new());
+        event.record(&mut visitor);
+
+        // Add the identifying fields captured from the enclosing spans
+        let mut fields = TraceFields::default();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                if let Some(span_fields) = span.extensions().get::<TraceFields>() {
+                    fields.merge(span_fields);
+                }
+            }
+        }
+        if let Some(ip) = &fields.remote_ip {
+            visitor
+                .0
+                .entry("remote.ip")
+                .or_insert_with(|| ip.to_string().into());
+        }
+        if let Some(account) = &fields.account {
+            visitor
+                .0
+                .entry("account")
+                .or_insert_with(|| account.clone().into());
+        }
+        if let Some(queue_id) = fields.queue_id {
+            visitor
+                .0
+                .entry("queue.id")
+                .or_insert_with(|| queue_id.into());
+        }
+
+        let message = self.formatter.format(
+            event.metadata().level(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            &visitor.0,
+        );
+
+        // Never block the caller, messages are dropped while the collector is unavailable
+        let _ = self.tx.try_send(message.into_bytes());
+    }
+}
+
+impl SyslogFormatter {
+    pub fn format(&self, level: &Level, timestamp: &str, fields: &Map<String, Value>) -> String {
+        let context = fields.get("context").and_then(|v| v.as_str());
+        let pri = self.facility as u32 * 8 + syslog_severity(level) as u32;
+        let mut message = format!(
+            "<{pri}>1 {timestamp} {} {} {} {} ",
+            self.hostname,
+            self.app_name,
+            self.proc_id,
+            context
+                .map(|context| sanitize_header(context, 32))
+                .unwrap_or_else(|| "-".to_string())
+        );
+
+        match self.format {
+            SyslogFormat::Cef if context.is_some_and(|c| self.security_contexts.contains(c)) => {
+                message.push_str("- ");
+                self.format_cef(&mut message, level, fields);
+            }
+            SyslogFormat::Leef if context.is_some_and(|c| self.security_contexts.contains(c)) => {
+                message.push_str("- ");
+                self.format_leef(&mut message, level, timestamp, fields);
+            }
+            _ => {
+                let mut has_params = false;
+                for (name, value) in fields {
+                    if name != "message" {
+                        message.push_str(if has_params { " " } else { "[" });
+                        if !has_params {
+                            message.push_str(SD_ID);
+                            message.push(' ');
+                            has_params = true;
+                        }
+                        message.push_str(&sanitize_header(name, 32));
+                        message.push_str("=\"");
+                        for ch in value_to_string(value).chars() {
+                            if matches!(ch, '"' | '\\' | ']') {
+                                message.push('\\');
+                            }
+                            message.push(ch);
+                        }
+                        message.push('"');
+                    }
+                }
+                if has_params {
+                    message.push(']');
+                } else {
+                    message.push('-');
+                }
+                if let Some(text) = fields.get("message") {
+                    message.push(' ');
+                    message.push_str(&value_to_string(text));
+                }
+            }
+        }
+
+        message
+    }
+
+    fn format_cef(&self, message: &mut String, level: &Level, fields: &Map<String, Value>) {
+        let name = fields
+            .get("message")
+            .or_else(|| fields.get("event"))
+            .map(value_to_string)
+            .unwrap_or_default();
+        message.push_str(&format!(
+            "CEF:0|{VENDOR}|{PRODUCT}|{}|{}|{}|{}|",
+            env!("CARGO_PKG_VERSION"),
+            escape_cef_header(&event_id(fields)),
+            escape_cef_header(&name),
+            security_severity(level)
+        ));
+
+        let mut has_params = false;
+        for (name, value) in fields {
+            let name = match name.as_str() {
+                "message" => continue,
+                "remote.ip" => "src",
+                "remote.port" => "spt",
+                "local.ip" => "dst",
+                "local.port" => "dpt",
+                "account" => "suser",
+                "result" => "outcome",
+                name => name,
+            };
+            if has_params {
+                message.push(' ');
+            } else {
+                has_params = true;
+            }
+            message.extend(name.chars().filter(|ch| ch.is_ascii_alphanumeric()));
+            message.push('=');
+            for ch in value_to_string(value).chars() {
+                match ch {
+                    '\\' | '=' => {
+                        message.push('\\');
+                        message.push(ch);
+                    }
+                    '\n' => message.push_str("\\n"),
+                    '\r' => message.push_str("\\r"),
+                    _ => message.push(ch),
+                }
+            }
+        }
+    }
+
+    fn format_leef(
+        &self,
+        message: &mut String,
+        level: &Level,
+        timestamp: &str,
+        fields: &Map<String, Value>,
+    ) {
+        message.push_str(&format!(
+            "LEEF:1.0|{VENDOR}|{PRODUCT}|{}|{}|devTime={timestamp}\tsev={}",
+            env!("CARGO_PKG_VERSION"),
+            event_id(fields).replace('|', " "),
+            security_severity(level)
+        ));
+
+        for (name, value) in fields {
+            let name = match name.as_str() {
+                "remote.ip" => "src",
+                "remote.port" => "srcPort",
+                "local.ip" => "dst",
+                "local.port" => "dstPort",
+                "account" => "usrName",
+                "message" => "msg",
+                name => name,
+            };
+            message.push('\t');
+            message.extend(name.chars().filter(|ch| ch.is_ascii_alphanumeric()));
+            message.push('=');
+            message.extend(value_to_string(value).chars().map(|ch| match ch {
+                '\t' | '\n' | '\r' => ' ',
+                _ => ch,
+            }));
+        }
+    }
+}
+
+impl Connection {
+    async fn connect(endpoint: &str, transport: &Transport) -> io::Result<Self> {
+        match transport {
+            Transport::Udp => {
+                let addr = tokio::net::lookup_host(endpoint)
+                    .await?
+                    .next()
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "Endpoint did not resolve")
+                    })?;
+                let socket = UdpSocket::bind(if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                })
+                .await?;
+                socket.connect(addr).await?;
+                Ok(Connection::Udp(socket))
+            }
+            Transport::Tcp => TcpStream::connect(endpoint).await.map(Connection::Tcp),
+            Transport::Tls {
+                allow_invalid_certs,
+            } => {
+                let host = endpoint
+                    .rsplit_once(':')
+                    .map_or(endpoint, |(host, _)| host)
+                    .trim_start_matches('[')
+                    .trim_end_matches(']');
+                let server_name = ServerName::try_from(host.to_string())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                let stream = TcpStream::connect(endpoint).await?;
+                TlsConnector::from(Arc::new(rustls_client_config(*allow_invalid_certs)))
+                    .connect(server_name, stream)
+                    .await
+                    .map(|stream| Connection::Tls(Box::new(stream)))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket
+                .send(&message[..std::cmp::min(message.len(), MAX_UDP_MESSAGE_SIZE)])
+                .await
+                .map(|_| ()),
+            Connection::Tcp(stream) => write_framed(stream, message).await,
+            Connection::Tls(stream) => write_framed(stream.as_mut(), message).await,
+        }
+    }
+}
+
+async fn write_framed(stream: &mut (impl AsyncWrite + Unpin), message: &[u8]) -> io::Result<()> {
+    // RFC 6587 octet-counting framing
+    let mut frame = Vec::with_capacity(message.len() + 8);
+    frame.extend_from_slice(message.len().to_string().as_bytes());
+    frame.push(b' ');
+    frame.extend_from_slice(message);
+    stream.write_all(&frame).await?;
+    stream.flush().await
+}
+
+async fn write_messages(endpoint: String, transport: Transport, mut rx: mpsc::Receiver<Vec<u8>>) {
+    let mut connection = None;
+    let mut retry_at = Instant::now();
+    let mut is_failing = false;
+
+    while let Some(message) = rx.recv().await {
+        if connection.is_none() {
+            if retry_at > Instant::now() {
+                continue;
+            }
+            match Connection::connect(&endpoint, &transport).await {
+                Ok(conn) => {
+                    if is_failing {
+                        eprintln!("Reconnected to syslog collector {endpoint}.");
+                        is_failing = false;
+                    }
+                    connection = Some(conn);
+                }
+                Err(err) => {
+                    if !is_failing {
+                        eprintln!("Failed to connect to syslog collector {endpoint}: {err}");
+                        is_failing = true;
+                    }
+                    retry_at = Instant::now() + RECONNECT_INTERVAL;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(conn) = &mut connection {
+            if let Err(err) = conn.send(&message).await {
+                if !is_failing {
+                    eprintln!("Failed to write to syslog collector {endpoint}: {err}");
+                    is_failing = true;
+                }
+                connection = None;
+                retry_at = Instant::now() + RECONNECT_INTERVAL;
+            }
+        }
+    }
+}
+
+fn parse_facility(facility: &str) -> Option<u8> {
+    match facility {
+        "kern" => Some(0),
+        "user" => Some(1),
+        "mail" => Some(2),
+        "daemon" => Some(3),
+        "auth" => Some(4),
+        "syslog" => Some(5),
+        "lpr" => Some(6),
+        "news" => Some(7),
+        "uucp" => Some(8),
+        "cron" => Some(9),
+        "authpriv" => Some(10),
+        "ftp" => Some(11),
+        _ => facility
+            .strip_prefix("local")
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|n| *n <= 7)
+            .map(|n| 16 + n),
+    }
+}
+
+fn syslog_severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+fn security_severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 8,
+        Level::WARN => 5,
+        Level::INFO => 3,
+        _ => 1,
+    }
+}
+
+fn event_id(fields: &Map<String, Value>) -> String {
+    match (
+        fields.get("context").map(value_to_string),
+        fields.get("event").map(value_to_string),
+    ) {
+        (Some(context), Some(event)) => format!("{context}.{event}"),
+        (Some(id), None) | (None, Some(id)) => id,
+        (None, None) => "event".to_string(),
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn escape_cef_header(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' | '|' => {
+                result.push('\\');
+                result.push(ch);
+            }
+            '\n' | '\r' => result.push(' '),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn sanitize_header(value: &str, max_len: usize) -> String {
+    let value = value
+        .chars()
+        .filter(|ch| ch.is_ascii_graphic() && !matches!(ch, '=' | ']' | '"'))
+        .take(max_len)
+        .collect::<String>();
+    if !value.is_empty() {
+        value
+    } else {
+        "-".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Map, Value};
+    use tracing::Level;
+
+    use super::{parse_facility, SyslogFormat, SyslogFormatter};
+
+    fn formatter(format: SyslogFormat) -> SyslogFormatter {
+        SyslogFormatter {
+            facility: parse_facility("mail").unwrap(),
+            format,
+            hostname: "mx.example.org".to_string(),
+            app_name: "stalwart".to_string(),
+            proc_id: "1234".to_string(),
+            security_contexts: ["auth".to_string()].into_iter().collect(),
+        }
+    }
+
+    fn fields(values: &[(&str, &str)]) -> Map<String, Value> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn syslog_facility() {
+        assert_eq!(parse_facility("kern"), Some(0));
+        assert_eq!(parse_facility("local0"), Some(16));
+        assert_eq!(parse_facility("local7"), Some(23));
+        assert_eq!(parse_facility("local8"), None);
+        assert_eq!(parse_facility("unknown"), None);
+    }
+
+    #[test]
+    fn syslog_rfc5424() {
+        let timestamp = "2023-12-01T10:00:00.000000Z";
+        let event = fields(&[
+            ("context", "auth"),
+            ("event", "authenticate"),
+            ("remote.ip", "192.0.2.1"),
+            ("mechanism", "PLAIN \"x\" [y]"),
+            ("message", "Authentication failed"),
+        ]);
+
+        assert_eq!(
+            formatter(SyslogFormat::Rfc5424).format(&Level::WARN, timestamp, &event),
+            concat!(
+                "<20>1 2023-12-01T10:00:00.000000Z mx.example.org stalwart 1234 auth ",
+                "[stalwart@32473 context=\"auth\" event=\"authenticate\" ",
+                "mechanism=\"PLAIN \\\"x\\\" [y\\]\" remote.ip=\"192.0.2.1\"] ",
+                "Authentication failed"
+            )
+        );
+        assert_eq!(
+            formatter(SyslogFormat::Rfc5424).format(&Level::DEBUG, timestamp, &Map::new()),
+            "<23>1 2023-12-01T10:00:00.000000Z mx.example.org stalwart 1234 - -"
+        );
+    }
+
+    #[test]
+    fn syslog_cef() {
+        let timestamp = "2023-12-01T10:00:00.000000Z";
+        let event = fields(&[
+            ("context", "auth"),
+            ("event", "authenticate"),
+            ("remote.ip", "192.0.2.1"),
+            ("account", "jdoe=admin"),
+            ("result", "failed"),
+            ("message", "Login|failed"),
+        ]);
+
+        assert_eq!(
+            formatter(SyslogFormat::Cef).format(&Level::WARN, timestamp, &event),
+            format!(
+                concat!(
+                    "<20>1 2023-12-01T10:00:00.000000Z mx.example.org stalwart 1234 auth - ",
+                    "CEF:0|Stalwart Labs|Stalwart Mail Server|{}|auth.authenticate|Login\\|failed|5|",
+                    "suser=jdoe\\=admin context=auth event=authenticate src=192.0.2.1 outcome=failed"
+                ),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        // Events outside the security contexts keep the RFC 5424 layout
+        let event = fields(&[("context", "queue"), ("event", "delivered")]);
+        assert_eq!(
+            formatter(SyslogFormat::Cef).format(&Level::INFO, timestamp, &event),
+            concat!(
+                "<22>1 2023-12-01T10:00:00.000000Z mx.example.org stalwart 1234 queue ",
+                "[stalwart@32473 context=\"queue\" event=\"delivered\"]"
+            )
+        );
+    }
+
+    #[test]
+    fn syslog_leef() {
+        let timestamp = "2023-12-01T10:00:00.000000Z";
+        let event = fields(&[
+            ("context", "auth"),
+            ("event", "authenticate"),
+            ("remote.ip", "192.0.2.1"),
+            ("account", "jdoe"),
+            ("message", "Bad\tpassword"),
+        ]);
+
+        assert_eq!(
+            formatter(SyslogFormat::Leef).format(&Level::ERROR, timestamp, &event),
+            format!(
+                concat!(
+                    "<19>1 2023-12-01T10:00:00.000000Z mx.example.org stalwart 1234 auth - ",
+                    "LEEF:1.0|Stalwart Labs|Stalwart Mail Server|{}|auth.authenticate|",
+                    "devTime=2023-12-01T10:00:00.000000Z\tsev=8\tusrName=jdoe\tcontext=auth\t",
+                    "event=authenticate\tmsg=Bad password\tsrc=192.0.2.1"
+                ),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+}
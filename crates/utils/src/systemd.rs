@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    env,
+    future::Future,
+    net::{SocketAddr, TcpStream},
+    os::unix::{ffi::OsStrExt, io::FromRawFd, net::UnixDatagram},
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use tokio::net::TcpSocket;
+
+const SD_LISTEN_FDS_START: i32 = 3;
+
+// Returns the sockets passed by systemd socket activation, keyed by their local address
+pub fn listen_fds() -> AHashMap<SocketAddr, TcpSocket> {
+    let mut sockets = AHashMap::new();
+    if env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        != Some(std::process::id())
+    {
+        return sockets;
+    }
+    let num_fds = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|num_fds| num_fds.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + num_fds {
+        // SAFETY: systemd transfers ownership of the passed descriptors to this process
+        let socket = unsafe { TcpStream::from_raw_fd(fd) };
+        match socket
+            .local_addr()
+            .and_then(|addr| socket.set_nonblocking(true).map(|_| addr))
+        {
+            Ok(addr) => {
+                sockets.insert(addr, TcpSocket::from_std_stream(socket));
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "systemd",
+                    event = "error",
+                    fd = fd,
+                    "Ignoring unsupported socket passed by systemd: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    // Do not pass the descriptors to child processes
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    sockets
+}
+
+pub fn notify(state: &str) {
+    let path = if let Some(path) = env::var_os("NOTIFY_SOCKET") {
+        path
+    } else {
+        return;
+    };
+
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+            return socket
+                .send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)
+                .map(|_| ());
+        }
+
+        socket.send_to(state.as_bytes(), &path).map(|_| ())
+    });
+
+    if let Err(err) = result {
+        tracing::debug!(
+            context = "systemd",
+            event = "error",
+            state = state,
+            "Failed to notify systemd: {}",
+            err
+        );
+    }
+}
+
+// Pings the watchdog as long as the liveness check succeeds, systemd restarts
+// the service once the pings stop.
+pub fn spawn_watchdog<F, R>(is_alive: F)
+where
+    F: Fn() -> R + Send + 'static,
+    R: Future<Output = bool> + Send,
+{
+    if env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid != std::process::id())
+    {
+        return;
+    }
+    let interval = match env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+    {
+        Some(usec) if usec > 0 => Duration::from_micros(usec / 2),
+        _ => return,
+    };
+
+    // Keep pinging while draining connections on shutdown
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if is_alive().await {
+                notify("WATCHDOG=1");
+            } else {
+                tracing::warn!(
+                    context = "systemd",
+                    event = "watchdog",
+                    "Liveness check failed, skipping watchdog notification."
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,186 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jmap::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        JsonResponse,
+    },
+    auth::AccessToken,
+};
+use mail_send::Credentials;
+use serde_json::json;
+use tokio::{net::TcpListener, sync::watch};
+
+use crate::directory::DirectoryTest;
+
+#[tokio::test]
+async fn auth_hook_directory() {
+    // Spawn mock authentication server
+    let shutdown = spawn_mock_auth_server();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // Obtain directory handles
+    let mut config = DirectoryTest::new(None).await;
+    let http = config.directories.directories.remove("hook-http").unwrap();
+    let exec = config.directories.directories.remove("hook-exec").unwrap();
+
+    // The HTTP hook maps the login to a principal name
+    for (credentials, expected) in [
+        (
+            Credentials::Plain {
+                username: "john@example.org".to_string(),
+                secret: "ok".to_string(),
+            },
+            Some("john"),
+        ),
+        (
+            Credentials::Plain {
+                username: "john@example.org".to_string(),
+                secret: "bad".to_string(),
+            },
+            None,
+        ),
+        (
+            Credentials::OAuthBearer {
+                token: "ok".to_string(),
+            },
+            Some("john"),
+        ),
+        (
+            Credentials::Plain {
+                username: "jane@example.org".to_string(),
+                secret: "ok".to_string(),
+            },
+            None,
+        ),
+    ] {
+        assert_eq!(
+            http.query(QueryBy::Credentials(&credentials), false)
+                .await
+                .unwrap()
+                .map(|p| p.name),
+            expected.map(|name| name.to_string())
+        );
+    }
+
+    // The exec hook accepts the login name as is
+    for (secret, expected) in [("ok", true), ("bad", false)] {
+        assert_eq!(
+            exec.query(
+                QueryBy::Credentials(&Credentials::Plain {
+                    username: "john".to_string(),
+                    secret: secret.to_string(),
+                }),
+                false
+            )
+            .await
+            .unwrap()
+            .is_some(),
+            expected
+        );
+    }
+
+    // Hook failures are reported as errors
+    assert!(http
+        .query(
+            QueryBy::Credentials(&Credentials::Plain {
+                username: "john".to_string(),
+                secret: "error".to_string(),
+            }),
+            false
+        )
+        .await
+        .is_err());
+
+    // Shutdown
+    shutdown.send(false).ok();
+}
+
+pub fn spawn_mock_auth_server() -> watch::Sender<bool> {
+    let (tx, mut rx) = watch::channel(true);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9197")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock authentication server to 127.0.0.1:9197: {e}");
+            });
+
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    let (stream, _) = stream.unwrap();
+                    tokio::spawn(async move {
+                        let _ = http1::Builder::new()
+                            .keep_alive(false)
+                            .serve_connection(
+                                TokioIo::new(stream),
+                                service_fn(|mut req: hyper::Request<body::Incoming>| async move {
+                                    let request = serde_json::from_slice::<serde_json::Value>(
+                                        &fetch_body(&mut req, 1024, &AccessToken::default())
+                                            .await
+                                            .unwrap(),
+                                    )
+                                    .unwrap();
+                                    let secret = request
+                                        .get("secret")
+                                        .or_else(|| request.get("token"))
+                                        .and_then(|v| v.as_str())
+                                        .unwrap();
+                                    let username =
+                                        request.get("username").and_then(|v| v.as_str());
+
+                                    let response = match (username, secret) {
+                                        (_, "error") => {
+                                            JsonResponse::with_status(
+                                                hyper::StatusCode::INTERNAL_SERVER_ERROR,
+                                                json!({}),
+                                            )
+                                        }
+                                        (Some("john@example.org") | None, "ok") => {
+                                            JsonResponse::new(
+                                                json!({"result": "accept", "name": "john"}),
+                                            )
+                                        }
+                                        _ => JsonResponse::new(json!({"result": "reject"})),
+                                    };
+
+                                    Ok::<_, hyper::Error>(response.into_http_response())
+                                }),
+                            )
+                            .await;
+                    });
+                },
+                _ = rx.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
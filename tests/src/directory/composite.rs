@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use mail_send::Credentials;
+
+use crate::directory::{map_account_ids, DirectoryTest};
+
+#[tokio::test]
+async fn composite_directory() {
+    // Obtain directory handle
+    let mut config = DirectoryTest::new("rocksdb".into()).await;
+    let handle = config.directories.directories.remove("composite").unwrap();
+    let base_store = config.stores.stores.get("rocksdb").unwrap();
+
+    // Routed domains are only looked up in their directory
+    assert!(handle.is_local_domain("example.org").await.unwrap());
+    assert!(handle.is_local_domain("example.net").await.unwrap());
+    assert!(!handle.is_local_domain("other.org").await.unwrap());
+    assert!(handle.rcpt("jane@example.org").await.unwrap());
+    assert!(handle.rcpt("robert@example.net").await.unwrap());
+    assert!(!handle.rcpt("jane@example.net").await.unwrap());
+    assert_eq!(
+        handle.email_to_ids("robert@example.net").await.unwrap(),
+        map_account_ids(base_store, vec!["robert"]).await
+    );
+
+    // Unrouted logins fall back to the directories in order
+    for (username, secret, expected) in [
+        ("jane", "abcde", true),
+        ("robert", "pass", true),
+        ("robert", "wrong", false),
+        ("unknown", "pass", false),
+    ] {
+        assert_eq!(
+            handle
+                .query(
+                    QueryBy::Credentials(&Credentials::Plain {
+                        username: username.to_string(),
+                        secret: secret.to_string(),
+                    }),
+                    false
+                )
+                .await
+                .unwrap()
+                .is_some(),
+            expected,
+            "failed for {username}:{secret}"
+        );
+    }
+
+    // Group memberships are merged across directories
+    let mut member_of = handle
+        .query(QueryBy::Name("john"), true)
+        .await
+        .unwrap()
+        .unwrap()
+        .member_of;
+    member_of.sort_unstable();
+    let mut expected = map_account_ids(base_store, vec!["sales", "staff"]).await;
+    expected.sort_unstable();
+    assert_eq!(member_of, expected);
+}
@@ -323,6 +323,24 @@ async fn internal_directory() {
             }
         );
 
+        // Expanding a group address should return the addresses of its members
+        assert_eq!(
+            store
+                .update_account(
+                    QueryBy::Name("sales"),
+                    vec![PrincipalUpdate::add_item(
+                        PrincipalField::Emails,
+                        PrincipalValue::String("sales@example.org".to_string()),
+                    )],
+                )
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            store.expn("sales@example.org").await.unwrap(),
+            vec!["john@example.org"]
+        );
+
         // Adding a non-existent user should fail
         assert_eq!(
             store
@@ -563,6 +581,84 @@ async fn internal_directory() {
             );
         }
 
+        // Masked addresses route to their owner without becoming principal e-mails
+        assert_eq!(
+            store.add_masked_email(0, "Mask.1234@example.org").await,
+            Ok(())
+        );
+        assert_eq!(
+            store.add_masked_email(1, "mask.5678@example.org").await,
+            Ok(())
+        );
+        assert!(store.rcpt("mask.1234@example.org").await.unwrap());
+        assert_eq!(
+            store.email_to_ids("mask.1234@example.org").await.unwrap(),
+            vec![0]
+        );
+        assert_eq!(
+            store
+                .query(QueryBy::Id(0), false)
+                .await
+                .unwrap()
+                .unwrap()
+                .emails,
+            vec!["john.doe@example.org"]
+        );
+        assert!(store.vrfy("mask").await.unwrap().is_empty());
+
+        // Masked addresses cannot collide with e-mails or other masks
+        for email in ["mask.1234@example.org", "jane@example.org"] {
+            assert_eq!(
+                store.add_masked_email(1, email).await,
+                Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                    field: PrincipalField::Emails,
+                    value: email.to_string()
+                }))
+            );
+        }
+        assert_eq!(
+            store
+                .update_account(
+                    QueryBy::Id(1),
+                    vec![PrincipalUpdate::add_item(
+                        PrincipalField::Emails,
+                        PrincipalValue::String("mask.1234@example.org".to_string()),
+                    )],
+                )
+                .await,
+            Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                field: PrincipalField::Emails,
+                value: "mask.1234@example.org".to_string()
+            }))
+        );
+        assert_eq!(
+            store.add_masked_email(0, "mask@otherdomain.org").await,
+            Err(DirectoryError::Management(ManagementError::NotFound(
+                "otherdomain.org".to_string()
+            )))
+        );
+
+        // Only the owner can remove a masked address
+        assert_eq!(
+            store.remove_masked_email(1, "mask.1234@example.org").await,
+            Err(DirectoryError::Management(ManagementError::NotFound(
+                "mask.1234@example.org".to_string()
+            )))
+        );
+        assert_eq!(
+            store.add_masked_email(0, "mask.9999@example.org").await,
+            Ok(())
+        );
+        assert_eq!(
+            store.remove_masked_email(0, "mask.9999@example.org").await,
+            Ok(())
+        );
+        assert!(!store.rcpt("mask.9999@example.org").await.unwrap());
+        assert_eq!(
+            store.email_to_ids("mask.9999@example.org").await.unwrap(),
+            Vec::<u32>::new()
+        );
+
         // Delete John's account and make sure his records are gone
         store.delete_account(QueryBy::Id(0)).await.unwrap();
         assert_eq!(store.get_account_id("john.doe").await.unwrap(), None);
@@ -571,6 +667,8 @@ async fn internal_directory() {
             Vec::<u32>::new()
         );
         assert!(!store.rcpt("john.doe@example.org").await.unwrap());
+        assert!(!store.rcpt("mask.1234@example.org").await.unwrap());
+        assert!(store.rcpt("mask.5678@example.org").await.unwrap());
         assert_eq!(
             store.list_accounts(None, None, 0).await.unwrap(),
             vec!["jane", "list", "sales", "support"]
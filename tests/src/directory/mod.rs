@@ -21,6 +21,8 @@
  * for more details.
 */
 
+pub mod auth_hook;
+pub mod composite;
 pub mod imap;
 pub mod internal;
 pub mod ldap;
@@ -67,6 +69,10 @@ store = "sqlite"
 catch-all = true
 subaddressing = true
 
+[directory."sqlite".cache]
+entries = 500
+ttl = {positive = '1h', negative = '10m'}
+
 [directory."sqlite".columns]
 name = "name"
 description = "description"
@@ -242,6 +248,39 @@ ttl = {positive = '10s', negative = '5s'}
 
 ##############################################################################
 
+[directory."hook-http"]
+type = "memory"
+
+[directory."hook-http".auth-hook]
+type = "http"
+url = "http://127.0.0.1:9197/auth"
+timeout = "5s"
+
+[[directory."hook-http".principals]]
+name = "john"
+type = "individual"
+description = "John Doe"
+email = "john@example.org"
+
+##############################################################################
+
+[directory."hook-exec"]
+type = "memory"
+
+[directory."hook-exec".auth-hook]
+type = "exec"
+command = "sh"
+arguments = ["-c", "if grep -q secret.:.ok; then echo '{\"result\": \"accept\"}'; else echo '{\"result\": \"reject\"}'; fi"]
+timeout = "5s"
+
+[[directory."hook-exec".principals]]
+name = "john"
+type = "individual"
+description = "John Doe"
+email = "john@example.org"
+
+##############################################################################
+
 [directory."local"]
 type = "memory"
 
@@ -286,6 +325,37 @@ name = "support"
 type = "group"
 description = "Support Team"
 
+##############################################################################
+
+[directory."local-net"]
+type = "memory"
+
+[[directory."local-net".principals]]
+name = "robert"
+type = "individual"
+description = "Robert Foobar"
+secret = "pass"
+email = "robert@example.net"
+
+[[directory."local-net".principals]]
+name = "john"
+type = "individual"
+description = "John Doe"
+member-of = ["staff"]
+
+[[directory."local-net".principals]]
+name = "staff"
+type = "group"
+description = "Staff"
+
+[directory."composite"]
+type = "composite"
+directories = ["local", "local-net"]
+
+[[directory."composite".routes]]
+domains = ["example.net"]
+directory = "local-net"
+
 "#;
 
 pub struct DirectoryStore {
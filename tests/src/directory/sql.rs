@@ -297,6 +297,67 @@ async fn sql_directory() {
             handle.expn("john@example.org").await.unwrap(),
             Vec::<String>::new()
         );
+
+        // Cached lookups are served until the cache is invalidated
+        if directory_id == "sqlite" {
+            assert_eq!(
+                handle
+                    .query(QueryBy::Name("sales"), true)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .description,
+                Some("Sales Team".to_string())
+            );
+            for query in [
+                "UPDATE accounts SET description = 'Jane Smith' WHERE name = 'jane'",
+                "UPDATE accounts SET description = 'Sales Department' WHERE name = 'sales'",
+            ] {
+                store.store.query::<usize>(query, vec![]).await.unwrap();
+            }
+            store
+                .link_test_address("jane", "unknown@example.org", "alias")
+                .await;
+            assert_eq!(
+                handle
+                    .query(QueryBy::Name("sales"), true)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .description,
+                Some("Sales Team".to_string())
+            );
+            assert_eq!(
+                handle.email_to_ids("unknown@example.org").await.unwrap(),
+                Vec::<u32>::new()
+            );
+
+            // Principals holding secrets are never cached
+            assert_eq!(
+                handle
+                    .query(QueryBy::Name("jane"), true)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .description,
+                Some("Jane Smith".to_string())
+            );
+
+            handle.invalidate_cache();
+            assert_eq!(
+                handle
+                    .query(QueryBy::Name("sales"), true)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .description,
+                Some("Sales Department".to_string())
+            );
+            assert_eq!(
+                handle.email_to_ids("unknown@example.org").await.unwrap(),
+                map_account_ids(base_store, vec!["jane"]).await
+            );
+        }
     }
 }
 
@@ -438,6 +499,20 @@ impl DirectoryStore {
             .unwrap();
     }
 
+    pub async fn set_test_secret(&self, login: &str, secret: &str) {
+        self.store
+            .query::<usize>(
+                if self.is_postgresql() {
+                    "UPDATE accounts SET secret = $1 where name = $2"
+                } else {
+                    "UPDATE accounts SET secret = ? where name = ?"
+                },
+                vec![secret.into(), login.into()],
+            )
+            .await
+            .unwrap();
+    }
+
     pub async fn add_to_group(&self, login: &str, group: &str) {
         self.store
             .query::<usize>(
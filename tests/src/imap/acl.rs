@@ -107,6 +107,22 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
         .assert_equals("* LIST (\\NoSelect) \"/\" \"Shared Folders/jane.smith@example.com\"")
         .assert_equals("* LIST () \"/\" \"Shared Folders/jane.smith@example.com/Inbox\"");
 
+    // Subscriptions to shared folders belong to the subscriber, not to the owner
+    imap_john
+        .send("SUBSCRIBE \"Shared Folders/jane.smith@example.com/Inbox\"")
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john.send("LIST (SUBSCRIBED) \"\" \"*\"").await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("Shared Folders/jane.smith@example.com/Inbox");
+    imap_jane.send("LIST (SUBSCRIBED) \"\" \"*\"").await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("INBOX", 0);
+
     // Grant access to Bill and check ACLs
     imap_jane.send("GETACL INBOX").await;
     imap_jane
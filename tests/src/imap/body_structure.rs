@@ -28,6 +28,7 @@ use imap_proto::{
     protocol::fetch::{BodyContents, DataItem, Section},
     ResponseCode, StatusResponse,
 };
+use jmap::email::metadata::{MessageMetadataContents, MessagePartStats};
 use mail_parser::MessageParser;
 
 use super::resources_dir;
@@ -83,6 +84,29 @@ fn body_structure() {
             buf.extend_from_slice(b"\n\n");
         }
 
+        // Structures built from the stored part statistics must match
+        // the ones obtained from the full message
+        let stats = MessagePartStats::new(&message);
+        let structure =
+            MessageMetadataContents::from(MessageParser::new().parse(&raw_message).unwrap())
+                .into_message_structure();
+        for is_extended in [false, true] {
+            let mut expected = Vec::new();
+            message
+                .body_structure(is_extended)
+                .serialize(&mut expected, is_extended);
+            let mut result = Vec::new();
+            structure
+                .body_structure_with_stats(is_extended, &stats.parts)
+                .serialize(&mut result, is_extended);
+            assert_eq!(
+                String::from_utf8(result).unwrap(),
+                String::from_utf8(expected).unwrap(),
+                "{}",
+                file_name.display()
+            );
+        }
+
         // Serialize body parts
         let mut iter = 1..9;
         let mut stack = Vec::new();
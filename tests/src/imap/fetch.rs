@@ -35,7 +35,7 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
 
     // Fetch all properties available from JMAP
     imap.send(concat!(
-        "FETCH 10 (FLAGS INTERNALDATE PREVIEW EMAILID THREADID ",
+        "FETCH 10 (FLAGS INTERNALDATE PREVIEW EMAILID THREADID SAVEDATE ",
         "RFC822.SIZE UID ENVELOPE BODYSTRUCTURE)"
     ))
     .await;
@@ -47,6 +47,7 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("INTERNALDATE")
         .assert_contains("THREADID (")
         .assert_contains("EMAILID (")
+        .assert_contains("SAVEDATE \"")
         .assert_contains("but then I thought, why not do both?")
         .assert_contains(concat!(
             "ENVELOPE (\"Sat, 20 Nov 2021 14:22:01 -0800\" ",
@@ -168,4 +169,73 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("Some text appears here")
         .assert_contains("plain text version of message goes here")
         .assert_contains("This is implicitly typed plain US-ASCII text.");
+
+    // Partial fetches must return the same bytes as the matching range of a full fetch
+    for section in [
+        "", "HEADER", "TEXT", "1", "1.MIME", "2", "2.HEADER", "2.TEXT", "2.1", "2.1.MIME", "2.2",
+    ] {
+        imap.send(&format!("UID FETCH 10 (BODY.PEEK[{section}])"))
+            .await;
+        let full = fetch_literal(
+            &imap.assert_read(Type::Tagged, ResponseType::Ok).await,
+            &format!("BODY[{section}]"),
+        );
+        assert!(full.len() > 3, "BODY[{section}] is too short");
+
+        let len = full.len() as u32;
+        for (start, count) in [
+            (0, 1),
+            (0, 10),
+            (7, 23),
+            (len / 2, len),
+            (len - 3, 10),
+            (len, 5),
+            (len + 100, 5),
+            (3, u32::MAX),
+        ] {
+            imap.send(&format!(
+                "UID FETCH 10 (BODY.PEEK[{section}]<{start}.{count}>)"
+            ))
+            .await;
+            let partial = fetch_literal(
+                &imap.assert_read(Type::Tagged, ResponseType::Ok).await,
+                &format!("BODY[{section}]<{start}>"),
+            );
+            let from = std::cmp::min(start as usize, full.len());
+            let to = std::cmp::min((start as usize).saturating_add(count as usize), full.len());
+            assert_eq!(partial, &full[from..to], "BODY[{section}]<{start}.{count}>");
+        }
+    }
+
+    // Several partial sections in a single request
+    imap.send("UID FETCH 10 (BODY.PEEK[2.1] BODY.PEEK[1]<0.10> BODY.PEEK[2.2]<5.20>)")
+        .await;
+    let lines = imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UID FETCH 10 (BODY.PEEK[1] BODY.PEEK[2.2])")
+        .await;
+    let full = imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(
+        fetch_literal(&lines, "BODY[1]<0>"),
+        &fetch_literal(&full, "BODY[1]")[..10]
+    );
+    assert_eq!(
+        fetch_literal(&lines, "BODY[2.2]<5>"),
+        &fetch_literal(&full, "BODY[2.2]")[5..25]
+    );
+}
+
+fn fetch_literal(lines: &[String], item: &str) -> Vec<u8> {
+    let prefix = format!("{item} {{");
+    for (pos, line) in lines.iter().enumerate() {
+        if let Some(size) = line
+            .rsplit_once(&prefix)
+            .and_then(|(_, size)| size.strip_suffix('}')?.parse::<usize>().ok())
+        {
+            // Test messages use LF line endings, which are removed by the line reader
+            let mut contents = lines[pos + 1..].join("\n").into_bytes();
+            contents.truncate(size);
+            return contents;
+        }
+    }
+    panic!("Expected {item:?} in response, got {lines:?}");
 }
@@ -151,4 +151,59 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
 
     imap_check.send("NOOP").await;
     imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Idle sessions of the same account share one change broadcaster
+    let mut idlers = Vec::new();
+    for tag in [b"_a ", b"_b "] {
+        let mut idler = ImapConnection::connect(tag).await;
+        idler.assert_read(Type::Untagged, ResponseType::Ok).await;
+        idler
+            .send("AUTHENTICATE PLAIN {32+}\r\nAGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0")
+            .await;
+        idler.assert_read(Type::Tagged, ResponseType::Ok).await;
+        idler.send("SELECT Parmeggiano").await;
+        idler.assert_read(Type::Tagged, ResponseType::Ok).await;
+        idler.send("IDLE").await;
+        idler
+            .assert_read(Type::Continuation, ResponseType::Ok)
+            .await;
+        idlers.push(idler);
+    }
+    imap.send("CREATE Mozzarella").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    for idler in &mut idlers {
+        idler
+            .assert_read(Type::Status, ResponseType::Ok)
+            .await
+            .assert_contains("LIST () \"/\" \"Mozzarella\"");
+    }
+
+    // Bursts of changes larger than the broadcast buffer still reach all sessions
+    for _ in 0..40 {
+        imap.send(&format!("APPEND Parmeggiano {{{}}}", message.len()))
+            .await;
+        imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+        imap.send_untagged(message).await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    }
+    for idler in &mut idlers {
+        while !idler
+            .read(Type::Status)
+            .await
+            .iter()
+            .any(|line| line.contains("* 40 EXISTS"))
+        {}
+        idler.send_raw("DONE").await;
+        idler.assert_read(Type::Tagged, ResponseType::Ok).await;
+        idler.send("LOGOUT").await;
+        idler.assert_read(Type::Untagged, ResponseType::Bye).await;
+    }
+
+    // Remove test data
+    imap.send("STORE 1:* +FLAGS (\\Deleted)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("EXPUNGE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("DELETE Mozzarella").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
 }
@@ -76,6 +76,19 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
     imap.send("CREATE \"Second trash\" (USE (\\Trash))").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
 
+    // UTF8=ACCEPT should exchange mailbox names as raw UTF-8
+    other_conn.send("ENABLE UTF8=ACCEPT").await;
+    other_conn.assert_read(Type::Tagged, ResponseType::Ok).await;
+    other_conn.send("CREATE \"Café\"").await;
+    other_conn.assert_read(Type::Tagged, ResponseType::Ok).await;
+    other_conn.send("LIST \"\" \"Caf*\"").await;
+    other_conn
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_folders([("Café", [""])], true);
+    other_conn.send("DELETE \"Café\"").await;
+    other_conn.assert_read(Type::Tagged, ResponseType::Ok).await;
+
     // Enable IMAP4rev2
     imap.send("ENABLE IMAP4rev2").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
@@ -117,6 +130,8 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
     imap.send("RENAME \"Fruit/Apple/Green\" \"Fruit/Apple/Red\"")
         .await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("RENAME \"Cars\" \"Cars/Electric/Cars\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
     imap.send("RENAME \"Cars\" \"Vehicles\"").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
     imap.send("RENAME \"Vegetable/Broccoli\" \"Veggies/Green/Broccoli\"")
@@ -56,6 +56,47 @@ pub async fn test() {
     sieve.assert_read(ResponseType::Ok).await;
     sieve.send("CHECKSCRIPT \"keep :invalidtag;\"").await;
     sieve.assert_read(ResponseType::No).await;
+    sieve
+        .send_literal(
+            "CHECKSCRIPT ",
+            concat!(
+                "require [\"editheader\", \"regex\", \"index\"];\r\n",
+                "if header :index 1 :regex \"subject\" \"^\\\\[[a-z-]+\\\\]\" {\r\n",
+                "  addheader \"X-List\" \"yes\";\r\n",
+                "}\r\n"
+            ),
+        )
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
+    sieve
+        .send_literal(
+            "CHECKSCRIPT ",
+            "require \"regex\"; if header :regex \"subject\" \"(a+)\\\\1\" { discard; }\r\n",
+        )
+        .await;
+    sieve.assert_read(ResponseType::No).await;
+    sieve
+        .send_literal(
+            "CHECKSCRIPT ",
+            concat!(
+                "require [\"regex\", \"variables\"];\r\n",
+                "set \"pattern\" \"(a+)\\\\1\";\r\n",
+                "if header :regex \"subject\" \"${pattern}\" { discard; }\r\n"
+            ),
+        )
+        .await;
+    sieve.assert_read(ResponseType::No).await;
+    sieve
+        .send_literal(
+            "CHECKSCRIPT ",
+            format!(
+                "require \"regex\"; if header :regex :comparator \"i;ascii-casemap\" \"{}\" \"^a+$\" {{ discard; }}\r\n",
+                "x".repeat(300)
+            )
+            .as_str(),
+        )
+        .await;
+    sieve.assert_read(ResponseType::Ok).await;
 
     // PutScript
     sieve
@@ -126,6 +126,9 @@ protocol = "smtp"
 implicit = false
 allow-invalid-certs = true
 
+[imap.store]
+batch-size = 3
+
 [session.extensions]
 future-release = [ { if = "authenticated-as", ne = "", then = "99999999d"},
                    { else = false } ]
@@ -272,7 +275,7 @@ async fn init_imap_tests(store_id: &str, delete_if_exists: bool) -> IMAPTest {
             .replace("{TMP}", &temp_dir.path.display().to_string()),
     )
     .unwrap();
-    let servers = config.parse_servers().unwrap();
+    let mut servers = config.parse_servers().unwrap();
     let stores = config.parse_stores().await.failed("Invalid configuration");
     let directory = config
         .parse_directory(&stores, store_id.into())
@@ -282,12 +285,27 @@ async fn init_imap_tests(store_id: &str, delete_if_exists: bool) -> IMAPTest {
     // Start JMAP and SMTP servers
     servers.bind(&config);
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &stores, &directory, delivery_tx)
-        .await
-        .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &stores, &directory, delivery_rx, smtp.clone())
-        .await
-        .failed("Invalid configuration file");
+    let (event_tx, event_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
+    let smtp = SMTP::init(
+        &config,
+        &servers,
+        &stores,
+        &directory,
+        delivery_tx,
+        event_tx,
+    )
+    .await
+    .failed("Invalid configuration file");
+    let jmap = JMAP::init(
+        &config,
+        &stores,
+        &directory,
+        delivery_rx,
+        event_rx,
+        smtp.clone(),
+    )
+    .await
+    .failed("Invalid configuration file");
     let imap: Arc<IMAP> = IMAP::init(&config)
         .await
         .failed("Invalid configuration file");
@@ -420,6 +438,47 @@ pub async fn imap_tests() {
         imap.assert_read(Type::Untagged, ResponseType::Bye).await;
     }
 
+    // Revoking a session should disconnect the client
+    let mut imap = ImapConnection::connect(b"_z ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("AUTHENTICATE PLAIN {32+}\r\nAGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("ID (\"name\" \"Mozilla Thunderbird\" \"version\" \"115.3.1\" \"os\" NIL)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* ID (\"name\" \"Stalwart IMAP\"");
+    let live_sessions = handle
+        .jmap
+        .live_sessions
+        .iter()
+        .map(|session| {
+            (
+                *session.key(),
+                session.account_id,
+                session.protocol,
+                session.client.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(live_sessions.len(), 1, "{live_sessions:?}");
+    let (session_id, account_id, protocol, client) = live_sessions[0].clone();
+    assert_eq!(protocol, "IMAP");
+    assert_eq!(client, "Mozilla Thunderbird 115.3.1");
+
+    // Clients identifying themselves should be included in the usage report
+    let today = (::store::write::now() / 86400) as u32;
+    let clients = handle.jmap.client_usage_report(today, today).await.unwrap();
+    assert_eq!(clients.len(), 1, "{clients:?}");
+    assert_eq!(clients[0].protocol, "IMAP");
+    assert_eq!(clients[0].name, "Mozilla Thunderbird");
+    assert_eq!(clients[0].version, "115.3.1");
+    assert_eq!(clients[0].sessions, 1);
+    handle.jmap.client_usage_purge(u32::MAX).await.unwrap();
+    assert!(handle.jmap.revoke_live_session(account_id, session_id));
+    imap.assert_read(Type::Untagged, ResponseType::Bye).await;
+
     // Run ManageSieve tests
     managesieve::test().await;
 
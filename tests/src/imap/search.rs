@@ -75,6 +75,20 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
         .await
         .assert_equals("* SEARCH 10");
 
+    imap_check
+        .send("UID SEARCH SAVEDATESUPPORTED SAVEDSINCE 1-Jan-2000")
+        .await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SEARCH 1 2 3 4 5 6 7 8 9 10");
+
+    imap_check.send("UID SEARCH SAVEDBEFORE 1-Jan-2000").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* SEARCH");
+
     imap_check
         .send("UID SEARCH NOT (FROM nathaniel ANSWERED)")
         .await;
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+use ahash::AHashSet;
 use imap_proto::ResponseType;
 
 use crate::jmap::wait_for_index;
@@ -47,6 +48,20 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         .await
         .assert_count("\\Seen", 10);
 
+    // Updates are written in batches but share a single change
+    imap.send("UID FETCH 1:10 (MODSEQ)").await;
+    let modseqs = imap
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .into_iter()
+        .filter_map(|line| {
+            line.split_once("MODSEQ (")
+                .and_then(|(_, modseq)| modseq.split_once(')'))
+                .map(|(modseq, _)| modseq.to_string())
+        })
+        .collect::<AHashSet<_>>();
+    assert_eq!(modseqs.len(), 1, "{modseqs:?}");
+
     // Check status
     imap.send("STATUS INBOX (UIDNEXT MESSAGES UNSEEN)").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok)
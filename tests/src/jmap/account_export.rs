@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::{
+    mailbox::INBOX_ID,
+    services::{export::ExportStatus, jobs::JobContext},
+};
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running account export tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let client = test_account_login("jdoe@example.com", "12345").await;
+
+    // Import a test message
+    let raw_message = concat!(
+        "From: jane@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: Confidential export test\r\n\r\n",
+        "Personal contents\r\n"
+    );
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let message_id = client
+        .email_import(
+            raw_message.as_bytes().to_vec(),
+            vec![&inbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+
+    // Start export and wait for it to complete
+    let export = server
+        .account_export_start(
+            account_id,
+            "jdoe@example.com",
+            "secret".to_string(),
+            "admin",
+        )
+        .await
+        .unwrap();
+    assert_eq!(export.status, ExportStatus::Running { progress: 0 });
+    let export_id = export.id;
+    let export = loop {
+        let export = server
+            .account_export(account_id, export_id)
+            .await
+            .unwrap()
+            .unwrap();
+        if matches!(export.status, ExportStatus::Running { .. }) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        } else {
+            break export;
+        }
+    };
+
+    // The archive is encrypted
+    let (parts, size) = match &export.status {
+        ExportStatus::Completed { parts, size } => (parts.clone(), *size),
+        other => panic!("Unexpected export status: {other:?}"),
+    };
+    let mut archive = Vec::new();
+    for hash in &parts {
+        archive.extend(server.get_blob(hash, 0..u32::MAX).await.unwrap().unwrap());
+    }
+    assert_eq!(archive.len(), size);
+    assert!(!archive
+        .windows(b"Confidential".len())
+        .any(|window| window == b"Confidential"));
+
+    // Archive contents
+    let mut files = Vec::new();
+    server
+        .account_export_files(&mut export.clone(), &JobContext::new(0), &mut files)
+        .await
+        .unwrap();
+    let manifest = files.last().unwrap();
+    assert_eq!(manifest.path, "manifest.json");
+    let manifest = serde_json::from_slice::<serde_json::Value>(&manifest.contents).unwrap();
+    assert_eq!(manifest["messages"], 1);
+    assert_eq!(manifest["files"].as_array().unwrap().len(), files.len() - 1);
+    let message = files
+        .iter()
+        .find(|file| file.path == format!("messages/{message_id}.eml"))
+        .unwrap();
+    assert_eq!(message.contents, raw_message.as_bytes());
+    for name in [
+        "account.json",
+        "login_history.json",
+        "mailboxes.json",
+        "messages.json",
+        "vacation_response.json",
+    ] {
+        assert!(files.iter().any(|file| file.path == name), "Missing {name}");
+    }
+
+    // Deleting the export removes its record
+    server.account_export_delete(&export).await.unwrap();
+    assert!(server
+        .account_export(account_id, export.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Remove test data
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
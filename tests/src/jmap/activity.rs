@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::activity::{large_attachment, Activity, ActivityType};
+use jmap_proto::types::id::Id;
+use mail_parser::MessageParser;
+
+use crate::jmap::{assert_is_empty, jmap_json_request};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Activity tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let document_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let account_id = Id::from(document_id).to_string();
+
+    // Only attachments above the threshold are reported
+    let message = MessageParser::new()
+        .parse(concat!(
+            "From: jane@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Report\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "See attached.\r\n",
+            "--b\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "Content-Disposition: attachment; filename=\"small.bin\"\r\n\r\n",
+            "0123456789\r\n",
+            "--b\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "Content-Disposition: attachment; filename=\"large.bin\"\r\n\r\n",
+            "01234567890123456789012345678901234567890123456789\r\n",
+            "--b--\r\n"
+        ))
+        .unwrap();
+    assert_eq!(
+        large_attachment(&message, 20).map(|(name, _)| name),
+        Some("large.bin".to_string())
+    );
+    assert_eq!(large_attachment(&message, 1000), None);
+
+    // Record one entry of each type
+    let mut ids = Vec::new();
+    for activity in [
+        Activity::new(ActivityType::NewDevice, "Login from IMAP at 10.0.0.1."),
+        Activity::new(
+            ActivityType::FilterCreated,
+            "Filter \"vacation\" was created.",
+        ),
+        Activity::new(ActivityType::LargeAttachment, "Received attachment.")
+            .with_email_id(Id::new(1)),
+        Activity::new(ActivityType::QuotaWarning, "Storage is 90% full."),
+    ] {
+        ids.push(
+            server
+                .activity_record(document_id, activity)
+                .await
+                .unwrap()
+                .to_string(),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    }
+
+    // Newest entries are returned first, entries can be filtered by type
+    let response = jmap_json_request(
+        r#"[[
+            "Activity/query",
+            {
+             "accountId": "$$"
+            },
+            "R1"
+           ],
+           [
+            "Activity/query",
+            {
+             "accountId": "$$",
+             "filter": {
+              "type": "largeAttachment"
+             }
+            },
+            "R2"
+           ],
+           [
+            "Activity/get",
+            {
+             "accountId": "$$",
+             "ids": ["%id"]
+            },
+            "R3"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%id", &ids[2]),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/ids")
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|id| id.as_str()).collect::<Vec<_>>()),
+        Some(ids.iter().rev().map(|id| id.as_str()).collect::<Vec<_>>()),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/ids/0")
+            .and_then(|v| v.as_str()),
+        Some(ids[2].as_str()),
+        "{response:?}"
+    );
+    let activity = response.pointer("/methodResponses/2/1/list/0").unwrap();
+    assert_eq!(
+        activity["type"].as_str(),
+        Some("largeAttachment"),
+        "{response:?}"
+    );
+    assert_eq!(
+        activity["emailId"].as_str(),
+        Some(Id::new(1).to_string().as_str()),
+        "{response:?}"
+    );
+    assert!(activity["createdAt"].is_string(), "{response:?}");
+
+    // Entries cannot be created or modified by clients, only dismissed
+    let response = jmap_json_request(
+        r#"[[
+            "Activity/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "a1": {
+               "type": "newDevice",
+               "description": "Fake"
+              }
+             },
+             "update": {
+              "%id0": {
+               "description": "Changed"
+              }
+             },
+             "destroy": ["%id1"]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%id0", &ids[0])
+        .replace("%id1", &ids[1]),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/a1/type")
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notUpdated/{}/type", ids[0]))
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(ids[1].as_str()),
+        "{response:?}"
+    );
+
+    // Pruning removes the oldest entries first
+    server.activity_prune(document_id, 1).await.unwrap();
+    let response = jmap_json_request(
+        r#"[[
+            "Activity/query",
+            {
+             "accountId": "$$"
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/ids")
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|id| id.as_str()).collect::<Vec<_>>()),
+        Some(vec![ids[3].as_str()]),
+        "{response:?}"
+    );
+
+    // Remove test data
+    server.activity_prune(document_id, 0).await.unwrap();
+    assert_is_empty(server).await;
+}
@@ -0,0 +1,209 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jmap::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        JsonResponse,
+    },
+    auth::AccessToken,
+    services::alert::{parse_alerts, Incidents},
+};
+use serde_json::{json, Value};
+use store::{ahash::AHashMap, write::now};
+use tokio::{net::TcpListener, sync::mpsc};
+use utils::config::Config;
+
+use crate::{
+    jmap::email_submission::{assert_message_delivery, spawn_mock_smtp_server_on, MockMessage},
+    store::TempDir,
+};
+
+const CONFIG: &str = r#"
+[server]
+hostname = "mx.example.org"
+
+[jmap.alerts]
+interval = "1s"
+
+[jmap.alerts.certificate]
+warn-before = "14d"
+
+[jmap.alerts.email]
+from = "alerts@example.org"
+to = ["ops@example.org"]
+
+[jmap.alerts.email.relay]
+address = "127.0.0.1"
+port = 9994
+tls.enable = false
+
+[jmap.alerts.webhook]
+url = "http://127.0.0.1:9195/enqueue"
+routing-key = "abc"
+
+[certificate.acme]
+cert = "file://{CERT}"
+"#;
+
+#[tokio::test]
+async fn alert_manager() {
+    let temp_dir = TempDir::new("jmap_alert_test", true);
+    let cert_path = temp_dir.path.join("cert.pem");
+    let config =
+        parse_alerts(&Config::new(&CONFIG.replace("{CERT}", cert_path.to_str().unwrap())).unwrap())
+            .unwrap()
+            .unwrap();
+    let (mut smtp_rx, _) = spawn_mock_smtp_server_on("127.0.0.1:9994");
+    let mut webhook_rx = spawn_mock_webhook_server();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Expired certificates raise an alert
+    std::fs::copy("../tests/resources/tls_cert.pem", &cert_path).unwrap();
+    let failing = config
+        .check_certificates(now())
+        .await
+        .into_iter()
+        .collect::<AHashMap<_, _>>();
+    assert_eq!(failing.len(), 1);
+    assert!(failing["certificate.acme"].summary.contains("has expired"));
+
+    // Incidents are notified once until they are resolved
+    let mut incidents = Incidents::default();
+    let notifications = incidents.update(failing.clone());
+    assert_eq!(notifications.len(), 1);
+    assert!(!notifications[0].is_resolved);
+    assert!(incidents.is_active("certificate.acme"));
+    assert!(incidents.update(failing).is_empty());
+
+    // Alerts are sent directly to the relay and to the webhook
+    config.dispatch(&notifications[0]).await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<alerts@example.org>",
+            ["<ops@example.org>"],
+            "@[WARNING] mx.example.org: Certificate \"acme\" has expired.",
+        ),
+    )
+    .await;
+    let event = expect_webhook(&mut webhook_rx).await;
+    assert_eq!(event["routing_key"], "abc");
+    assert_eq!(event["event_action"], "trigger");
+    assert_eq!(event["dedup_key"], "mx.example.org/certificate.acme");
+    assert_eq!(event["payload"]["severity"], "warning");
+
+    // Renewed certificates are picked up on the next check, resolving the incident
+    std::fs::copy("../tests/resources/crypto/cert_smime.pem", &cert_path).unwrap();
+    let failing = config
+        .check_certificates(now())
+        .await
+        .into_iter()
+        .collect::<AHashMap<_, _>>();
+    assert!(failing.is_empty(), "{failing:?}");
+    let notifications = incidents.update(failing);
+    assert_eq!(notifications.len(), 1);
+    assert!(notifications[0].is_resolved);
+    assert!(!incidents.is_active("certificate.acme"));
+
+    config.dispatch(&notifications[0]).await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<alerts@example.org>",
+            ["<ops@example.org>"],
+            "@[RESOLVED] mx.example.org",
+        ),
+    )
+    .await;
+    let event = expect_webhook(&mut webhook_rx).await;
+    assert_eq!(event["event_action"], "resolve");
+    assert_eq!(event["dedup_key"], "mx.example.org/certificate.acme");
+
+    // Certificates about to expire raise a warning
+    let expires = 1916306522;
+    let failing = config.check_certificates(expires - 5 * 86400).await;
+    assert_eq!(failing.len(), 1);
+    assert_eq!(
+        failing[0].1.summary,
+        "Certificate \"acme\" expires in 5 day(s)."
+    );
+    assert!(config
+        .check_certificates(expires - 15 * 86400)
+        .await
+        .is_empty());
+}
+
+async fn expect_webhook(webhook_rx: &mut mpsc::Receiver<Value>) -> Value {
+    match tokio::time::timeout(Duration::from_millis(3000), webhook_rx.recv()).await {
+        Ok(Some(event)) => event,
+        result => panic!("Timeout waiting for webhook, got: {:?}", result),
+    }
+}
+
+fn spawn_mock_webhook_server() -> mpsc::Receiver<Value> {
+    let (event_tx, event_rx) = mpsc::channel(10);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9195")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock webhook server to 127.0.0.1:9195: {e}");
+            });
+
+        while let Ok((stream, _)) = listener.accept().await {
+            let event_tx = event_tx.clone();
+            tokio::spawn(async move {
+                let _ = http1::Builder::new()
+                    .keep_alive(false)
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(|mut req: hyper::Request<body::Incoming>| {
+                            let event_tx = event_tx.clone();
+                            async move {
+                                let event = serde_json::from_slice::<Value>(
+                                    &fetch_body(&mut req, 4096, &AccessToken::default())
+                                        .await
+                                        .unwrap(),
+                                )
+                                .unwrap();
+                                event_tx.send(event).await.unwrap();
+
+                                Ok::<_, hyper::Error>(
+                                    JsonResponse::new(json!({"status": "success"}))
+                                        .into_http_response(),
+                                )
+                            }
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+
+    event_rx
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::auth::devices::{device_network, DeviceStatus};
+
+use crate::jmap::assert_is_empty;
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running trusted device tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+
+    // Logins from the same network share a location
+    assert_eq!(
+        device_network("192.168.1.20".parse().unwrap()),
+        "192.168.1.0/24"
+    );
+    assert_eq!(
+        device_network("2001:db8:1234:5678::1".parse().unwrap()),
+        "2001:db8:1234::/48"
+    );
+
+    // The first device is trusted without notifying the user
+    for (ip, client, expected) in [
+        ("10.0.0.1", "IMAP", DeviceStatus::First),
+        ("10.0.0.2", "IMAP", DeviceStatus::Known),
+        ("10.0.1.1", "IMAP", DeviceStatus::New),
+        ("10.0.0.1", "SMTP", DeviceStatus::New),
+        ("10.0.1.1", "IMAP", DeviceStatus::Known),
+    ] {
+        assert_eq!(
+            server
+                .trusted_device_seen(account_id, ip.parse().unwrap(), client, 20)
+                .await
+                .unwrap(),
+            (expected, false),
+            "{ip} {client}"
+        );
+    }
+    assert_eq!(
+        server
+            .trusted_devices(account_id)
+            .await
+            .unwrap()
+            .devices
+            .len(),
+        3
+    );
+
+    // Opting out is reported and the oldest devices are evicted
+    let mut devices = server.trusted_devices(account_id).await.unwrap();
+    devices.opt_out = true;
+    server
+        .trusted_devices_set(account_id, &devices)
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .trusted_device_seen(account_id, "10.0.2.1".parse().unwrap(), "IMAP", 2)
+            .await
+            .unwrap(),
+        (DeviceStatus::New, true)
+    );
+    let devices = server.trusted_devices(account_id).await.unwrap();
+    assert!(devices.opt_out);
+    assert_eq!(devices.devices.len(), 2);
+
+    // Remove test data
+    server.trusted_devices_clear(account_id).await.unwrap();
+    assert!(server
+        .trusted_devices(account_id)
+        .await
+        .unwrap()
+        .devices
+        .is_empty());
+    assert_is_empty(server).await;
+}
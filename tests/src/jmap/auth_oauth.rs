@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use bytes::Bytes;
 use directory::backend::internal::manage::ManageDirectory;
@@ -226,7 +226,6 @@ pub async fn test(params: &mut JMAPTest) {
     assert_client_auth("jdoe@example.com", "12345", &device_response, "successful").await;
 
     // Obtain token
-    let time_first_token = Instant::now();
     let (token, refresh_token, _) =
         unwrap_token_response(post(&metadata.token_endpoint, &token_params).await);
     let refresh_token = refresh_token.unwrap();
@@ -273,37 +272,37 @@ pub async fn test(params: &mut JMAPTest) {
         }
     );
 
-    // Refreshing the access token before expiration should not include a new refresh token
-    let refresh_params = AHashMap::from_iter([
+    // Refresh tokens are rotated on every use
+    let mut refresh_params = AHashMap::from_iter([
         ("client_id".to_string(), "1234".to_string()),
         ("grant_type".to_string(), "refresh_token".to_string()),
-        ("refresh_token".to_string(), refresh_token),
+        ("refresh_token".to_string(), refresh_token.clone()),
     ]);
-    let time_before_post: Instant = Instant::now();
     let (token, new_refresh_token, _) =
         unwrap_token_response(post(&metadata.token_endpoint, &refresh_params).await);
-    assert_eq!(
-        new_refresh_token,
-        None,
-        "Refreshed token in {:?}, since start {:?}",
-        time_before_post.elapsed(),
-        time_first_token.elapsed()
-    );
+    let new_refresh_token = new_refresh_token.expect("Expected a new refresh token");
+    assert_ne!(new_refresh_token, refresh_token);
 
     // Wait 1 second and make sure the access token expired
     tokio::time::sleep(Duration::from_secs(1)).await;
     assert_unauthorized("https://127.0.0.1:8899", &token).await;
 
-    // Wait another second for the refresh token to be about to expire
-    // and expect a new refresh token
-    tokio::time::sleep(Duration::from_secs(1)).await;
-    let (_, new_refresh_token, _) =
+    // The rotated refresh token can be used once
+    refresh_params.insert("refresh_token".to_string(), new_refresh_token);
+    let (token, latest_refresh_token, _) =
         unwrap_token_response(post(&metadata.token_endpoint, &refresh_params).await);
-    //println!("New refresh token: {:?}", new_refresh_token);
-    assert_ne!(new_refresh_token, None);
+    let latest_refresh_token = latest_refresh_token.expect("Expected a new refresh token");
 
-    // Wait another second and make sure the refresh token expired
-    tokio::time::sleep(Duration::from_secs(1)).await;
+    // Replaying a used refresh token revokes the grant, including the tokens
+    // issued after it
+    assert_eq!(
+        post::<TokenResponse>(&metadata.token_endpoint, &refresh_params).await,
+        TokenResponse::Error {
+            error: ErrorType::InvalidGrant
+        }
+    );
+    assert_unauthorized("https://127.0.0.1:8899", &token).await;
+    refresh_params.insert("refresh_token".to_string(), latest_refresh_token);
     assert_eq!(
         post::<TokenResponse>(&metadata.token_endpoint, &refresh_params).await,
         TokenResponse::Error {
@@ -311,6 +310,60 @@ pub async fn test(params: &mut JMAPTest) {
         }
     );
 
+    // ------------------------
+    // Session management
+    // ------------------------
+
+    // Authorize a new device
+    let (token, refresh_token) = authorize_device(&metadata, "5678").await;
+
+    // The grant should be listed with the device details
+    let sessions = sessions_request(reqwest::Method::GET, "", &token).await;
+    let grant = sessions["data"]["oauth"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|grant| grant["clientId"] == "5678")
+        .unwrap_or_else(|| panic!("Grant not found: {sessions:#?}"));
+    assert_eq!(grant["remoteIp"], "127.0.0.1");
+    let grant_id = grant["id"].as_str().unwrap().to_string();
+
+    // Revoking the grant invalidates both the access and refresh tokens
+    assert_eq!(
+        sessions_request(
+            reqwest::Method::DELETE,
+            &format!("/oauth/{grant_id}"),
+            &token
+        )
+        .await["data"],
+        true
+    );
+    assert_unauthorized("https://127.0.0.1:8899", &token).await;
+    assert_refresh_denied(&metadata, "5678", refresh_token).await;
+
+    // Administrators can revoke all tokens issued to an account
+    let (token, refresh_token) = authorize_device(&metadata, "5678").await;
+    assert!(
+        server
+            .oauth_grants_revoke_all(Id::from_bytes(john_id.as_bytes()).unwrap().document_id())
+            .await
+            .unwrap()
+            >= 1
+    );
+    assert_unauthorized("https://127.0.0.1:8899", &token).await;
+    assert_refresh_denied(&metadata, "5678", refresh_token).await;
+
+    // Refresh tokens that are not used expire
+    let (_, refresh_token) = authorize_device(&metadata, "5678").await;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_refresh_denied(&metadata, "5678", refresh_token).await;
+
+    // Remove the record holding the expired grant
+    server
+        .oauth_grants_revoke_all(Id::from_bytes(john_id.as_bytes()).unwrap().document_id())
+        .await
+        .unwrap();
+
     // Destroy test accounts
     params.client.set_default_account_id(john_id);
     destroy_all_mailboxes(params).await;
@@ -398,6 +451,65 @@ async fn assert_client_auth(
     assert!(html_response.contains(expect), "{:#?}", html_response);
 }
 
+async fn authorize_device(metadata: &OAuthMetadata, client_id: &str) -> (String, String) {
+    let device_response: DeviceAuthResponse = post(
+        &metadata.device_authorization_endpoint,
+        &AHashMap::from_iter([("client_id".to_string(), client_id.to_string())]),
+    )
+    .await;
+    assert_client_auth("jdoe@example.com", "12345", &device_response, "successful").await;
+    let (token, refresh_token, _) = unwrap_token_response(
+        post(
+            &metadata.token_endpoint,
+            &AHashMap::from_iter([
+                ("client_id".to_string(), client_id.to_string()),
+                (
+                    "grant_type".to_string(),
+                    "urn:ietf:params:oauth:grant-type:device_code".to_string(),
+                ),
+                ("device_code".to_string(), device_response.device_code),
+            ]),
+        )
+        .await,
+    );
+
+    (token, refresh_token.unwrap())
+}
+
+async fn assert_refresh_denied(metadata: &OAuthMetadata, client_id: &str, refresh_token: String) {
+    assert_eq!(
+        post::<TokenResponse>(
+            &metadata.token_endpoint,
+            &AHashMap::from_iter([
+                ("client_id".to_string(), client_id.to_string()),
+                ("grant_type".to_string(), "refresh_token".to_string()),
+                ("refresh_token".to_string(), refresh_token),
+            ]),
+        )
+        .await,
+        TokenResponse::Error {
+            error: ErrorType::InvalidGrant
+        }
+    );
+}
+
+async fn sessions_request(method: reqwest::Method, path: &str, token: &str) -> serde_json::Value {
+    let bytes = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .request(method, format!("https://127.0.0.1:8899/sessions{path}"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
 async fn assert_unauthorized(base_url: &str, token: &str) {
     match Client::new()
         .credentials(Credentials::bearer(token))
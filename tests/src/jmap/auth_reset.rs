@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+
+use crate::jmap::assert_is_empty;
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running password reset tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+
+    // Recovery addresses can be set and removed
+    assert_eq!(server.recovery_address(account_id).await.unwrap(), None);
+    server
+        .recovery_address_set(account_id, Some("jdoe@remote.org"))
+        .await
+        .unwrap();
+    assert_eq!(
+        server.recovery_address(account_id).await.unwrap(),
+        Some("jdoe@remote.org".to_string())
+    );
+
+    // New recovery addresses take effect once confirmed, confirmation tokens
+    // are bound to the current address and can only be used once
+    let confirm_token = server
+        .recovery_address_token(account_id, Some("jdoe@remote.org"), "john@other.org", 3600)
+        .unwrap();
+    let stale_token = server
+        .recovery_address_token(account_id, None, "mallory@evil.org", 3600)
+        .unwrap();
+    let expired_token = server
+        .recovery_address_token(account_id, Some("jdoe@remote.org"), "john@other.org", 0)
+        .unwrap();
+    for (token, reason) in [
+        (&stale_token, "Invalid or already used token"),
+        (&expired_token, "Token expired"),
+    ] {
+        assert_eq!(
+            server.recovery_address_confirm(token).await.unwrap_err(),
+            reason
+        );
+    }
+    assert_eq!(
+        server
+            .recovery_address_confirm(&confirm_token)
+            .await
+            .unwrap(),
+        "john@other.org"
+    );
+    assert_eq!(
+        server.recovery_address(account_id).await.unwrap(),
+        Some("john@other.org".to_string())
+    );
+    assert_eq!(
+        server
+            .recovery_address_confirm(&confirm_token)
+            .await
+            .unwrap_err(),
+        "Invalid or already used token"
+    );
+
+    // Tokens identify the account until they expire
+    let principal = server
+        .directory
+        .query(QueryBy::Id(account_id), false)
+        .await
+        .unwrap()
+        .unwrap();
+    let token = server.password_reset_token(&principal, 3600).unwrap();
+    assert_eq!(
+        server
+            .validate_password_reset_token(&token)
+            .await
+            .unwrap()
+            .id,
+        account_id
+    );
+    let expired_token = server.password_reset_token(&principal, 0).unwrap();
+    assert_eq!(
+        server
+            .validate_password_reset_token(&expired_token)
+            .await
+            .unwrap_err(),
+        "Token expired"
+    );
+
+    // Tampered tokens are rejected
+    let tampered_token = format!(
+        "{}{}",
+        if token.starts_with('A') { 'B' } else { 'A' },
+        &token[1..]
+    );
+    assert_eq!(
+        server
+            .validate_password_reset_token(&tampered_token)
+            .await
+            .unwrap_err(),
+        "Invalid or already used token"
+    );
+
+    // Changing the password invalidates outstanding tokens
+    params
+        .directory
+        .set_test_secret("jdoe@example.com", "abcdef")
+        .await;
+    assert_eq!(
+        server
+            .validate_password_reset_token(&token)
+            .await
+            .unwrap_err(),
+        "Invalid or already used token"
+    );
+    params
+        .directory
+        .set_test_secret("jdoe@example.com", "12345")
+        .await;
+
+    // Remove test data
+    server.recovery_address_set(account_id, None).await.unwrap();
+    assert_eq!(server.recovery_address(account_id).await.unwrap(), None);
+    assert_is_empty(server).await;
+}
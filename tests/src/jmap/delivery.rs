@@ -24,15 +24,29 @@
 use std::time::Duration;
 
 use directory::backend::internal::manage::ManageDirectory;
-use jmap::mailbox::{INBOX_ID, JUNK_ID};
-use jmap_proto::types::{collection::Collection, id::Id, property::Property};
-
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jmap::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        JsonResponse,
+    },
+    auth::AccessToken,
+    mailbox::{INBOX_ID, JUNK_ID},
+    services::report::TrafficScope,
+};
+use jmap_proto::types::{
+    blob::BlobId, collection::Collection, id::Id, keyword::Keyword, property::Property,
+};
+use serde_json::json;
+use store::write::now;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
-    net::TcpStream,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
 };
 
-use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
+use crate::jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes};
 
 use super::JMAPTest;
 
@@ -96,6 +110,10 @@ pub async fn test(params: &mut JMAPTest) {
         .link_test_address("bill@example.com", "members@example.com", "list")
         .await;
 
+    // Only deliveries matching a notification rule are announced
+    let (chat_shutdown, mut chat_rx) = spawn_mock_chat_server();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
     // Delivering to individuals
     let mut lmtp = SmtpConnection::connect().await;
 
@@ -144,6 +162,19 @@ pub async fn test(params: &mut JMAPTest) {
         0
     );
 
+    // Inbound traffic should be accounted for the recipient domain
+    let today = (now() / 86400) as u32;
+    let traffic = server
+        .traffic_report(TrafficScope::Domain, today, today)
+        .await
+        .unwrap();
+    let usage = traffic
+        .iter()
+        .find(|usage| usage.name == "example.com")
+        .unwrap();
+    assert!(usage.messages_in >= 1);
+    assert!(usage.bytes_in > 0);
+
     // Delivering to individuals' aliases
     lmtp.ingest(
         "bill@example.com",
@@ -189,6 +220,43 @@ pub async fn test(params: &mut JMAPTest) {
         1
     );
 
+    // Delivery rules send a chat notification for the alias
+    let (uri, authorization, body) = tokio::time::timeout(Duration::from_secs(2), chat_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        uri.starts_with("/_matrix/client/v3/rooms/%21alerts%3Aexample.com/send/m.room.message/"),
+        "{uri}"
+    );
+    assert_eq!(authorization, "Bearer chat-token");
+    assert_eq!(body["msgtype"], "m.text");
+    let text = body["body"].as_str().unwrap();
+    assert!(
+        text.starts_with("New message for john.doe@example.com from bill@example.com: ")
+            && text.contains("Fwd: TPS Report"),
+        "{text}"
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(chat_rx.try_recv().is_err());
+    chat_shutdown.send(false).ok();
+
+    // Delivery rules tag the message before it is indexed
+    assert_eq!(
+        server
+            .get_tag(
+                john_id,
+                Collection::Email,
+                Property::Keywords,
+                Keyword::Junk
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        1
+    );
+
     // EXPN and VRFY
     lmtp.expn("members@example.com", 2)
         .await
@@ -233,6 +301,57 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Local recipients of the same delivery share a single blob
+    let mut blob_hashes = Vec::new();
+    for (account_id, login, secret) in [
+        (&account_id_2, "jane@example.com", "abcdef"),
+        (&account_id_3, "bill@example.com", "098765"),
+    ] {
+        let response = jmap_json_request(
+            r##"[[
+                "Email/query",
+                {
+                 "accountId": "$$"
+                },
+                "R1"
+               ],
+               [
+                "Email/get",
+                {
+                 "accountId": "$$",
+                 "#ids": {
+                  "resultOf": "R1",
+                  "name": "Email/query",
+                  "path": "/ids"
+                 },
+                 "properties": ["blobId", "bodyValues"],
+                 "fetchAllBodyValues": true
+                },
+                "R2"
+               ]]"##
+                .replace("$$", account_id),
+            login,
+            secret,
+        )
+        .await;
+        let blob_id = response
+            .pointer("/methodResponses/1/1/list/0/blobId")
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("{response:?}"));
+        blob_hashes.push(BlobId::from_base32(blob_id).unwrap().hash);
+
+        // Each recipient can read the shared blob
+        assert!(
+            response
+                .pointer("/methodResponses/1/1/list/0/bodyValues")
+                .unwrap()
+                .to_string()
+                .contains("entire staff back in the office"),
+            "{response:?}"
+        );
+    }
+    assert_eq!(blob_hashes[0], blob_hashes[1]);
+
     // Removing members from the mailing list and chunked ingest
     params
         .directory
@@ -308,6 +427,87 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Mailing list messages are filed into their own mailbox when enabled
+    let response = jmap_json_request(
+        format!(
+            concat!(
+                r#"[["ListFiling/set", {{"accountId": "{}", "update": {{"singleton": "#,
+                r#"{{"isEnabled": true, "excludedListIds": ["*.ignored.org"]}}}}}}, "0"]]"#
+            ),
+            account_id_2
+        ),
+        "jane@example.com",
+        "abcdef",
+    )
+    .await;
+    assert!(
+        response["methodResponses"][0][1]["updated"]
+            .as_object()
+            .map_or(false, |updated| updated.contains_key("singleton")),
+        "{response:?}"
+    );
+    for list_id in [
+        "TPS Reports <tps-reports.example.com>",
+        "<news.ignored.org>",
+    ] {
+        lmtp.ingest(
+            "bill@example.com",
+            &["jane@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: jane@example.com\r\n",
+                    "List-Id: {}\r\n",
+                    "Subject: TPS report reminder\r\n",
+                    "\r\n",
+                    "Please remember to use the new cover sheets."
+                ),
+                list_id
+            ),
+        )
+        .await;
+    }
+    let jane_id = Id::from_bytes(account_id_2.as_bytes())
+        .unwrap()
+        .document_id();
+    let list_mailbox_id = server
+        .mailbox_get_by_name(jane_id, "Lists/TPS Reports")
+        .await
+        .unwrap()
+        .expect("list mailbox not created");
+    assert_eq!(
+        server
+            .get_tag(
+                jane_id,
+                Collection::Email,
+                Property::MailboxIds,
+                list_mailbox_id
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        server
+            .get_tag(jane_id, Collection::Email, Property::MailboxIds, INBOX_ID)
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        4
+    );
+    jmap_json_request(
+        format!(
+            r#"[["ListFiling/set", {{"accountId": "{}", "destroy": ["singleton"]}}, "0"]]"#,
+            account_id_2
+        ),
+        "jane@example.com",
+        "abcdef",
+    )
+    .await;
+
     // Remove test data
     for account_id in [&account_id_1, &account_id_2, &account_id_3] {
         params.client.set_default_account_id(account_id);
@@ -532,3 +732,65 @@ impl AssertResult for Vec<String> {
         panic!("Expected response to be {:?}, got {:?}", text, self);
     }
 }
+
+pub fn spawn_mock_chat_server() -> (
+    watch::Sender<bool>,
+    mpsc::Receiver<(String, String, serde_json::Value)>,
+) {
+    let (tx, mut rx) = watch::channel(true);
+    let (request_tx, request_rx) = mpsc::channel(8);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9196")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock chat server to 127.0.0.1:9196: {e}");
+            });
+
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    let (stream, _) = stream.unwrap();
+                    let request_tx = request_tx.clone();
+                    tokio::spawn(async move {
+                        let _ = http1::Builder::new()
+                            .keep_alive(false)
+                            .serve_connection(
+                                TokioIo::new(stream),
+                                service_fn(|mut req: hyper::Request<body::Incoming>| {
+                                    let request_tx = request_tx.clone();
+                                    async move {
+                                        let authorization = req
+                                            .headers()
+                                            .get(hyper::header::AUTHORIZATION)
+                                            .and_then(|v| v.to_str().ok())
+                                            .unwrap_or_default()
+                                            .to_string();
+                                        let uri = req.uri().to_string();
+                                        let body = serde_json::from_slice::<serde_json::Value>(
+                                            &fetch_body(&mut req, 1024, &AccessToken::default())
+                                                .await
+                                                .unwrap(),
+                                        )
+                                        .unwrap();
+                                        request_tx.send((uri, authorization, body)).await.ok();
+
+                                        Ok::<_, hyper::Error>(
+                                            JsonResponse::new(json!({"event_id": "$event"}))
+                                                .into_http_response(),
+                                        )
+                                    }
+                                }),
+                            )
+                            .await;
+                    });
+                },
+                _ = rx.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    (tx, request_rx)
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::id::Id;
+use reqwest::{header, StatusCode};
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes_no_wait, wait_for_index};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running cross-account search tests...");
+    let server = params.server.clone();
+
+    // Create two accounts with messages
+    let mut account_ids = Vec::new();
+    for (name, messages) in [
+        (
+            "jdoe@example.com",
+            [
+                "Subject: Invoice 1234\r\n\r\nPlease pay.\r\n",
+                "Subject: Lunch\r\n\r\nSee you there.\r\n",
+            ],
+        ),
+        (
+            "jane@example.com",
+            [
+                "Subject: Invoice 5678\r\n\r\nFrom the accounting desk.\r\n",
+                "Subject: Holidays\r\n\r\nOut of office.\r\n",
+            ],
+        ),
+    ] {
+        params
+            .directory
+            .create_test_user_with_email(name, "12345", name)
+            .await;
+        let account_id = server.store.get_or_create_account_id(name).await.unwrap();
+        let client = params
+            .client
+            .set_default_account_id(Id::from(account_id).to_string());
+        let inbox_id = Id::new(INBOX_ID as u64).to_string();
+        for message in messages {
+            client
+                .email_import(
+                    message.as_bytes().to_vec(),
+                    [&inbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        account_ids.push(account_id);
+    }
+    wait_for_index(&server).await;
+
+    // Only administrators can search across accounts
+    let query = r#"{"accounts": ["jdoe@example.com", "jane@example.com"],
+                    "filter": {"subject": "invoice"}}"#;
+    assert_eq!(
+        search(query, "jdoe@example.com", "12345").await.0,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Search returns the metadata of matching messages in each account
+    let (status, body) = search(query, "admin", "secret").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let response = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+    let results = response["data"].as_array().unwrap();
+    assert_eq!(results.len(), 2, "{body}");
+    for (result, (account, subject)) in results.iter().zip([
+        ("jdoe@example.com", "Invoice 1234"),
+        ("jane@example.com", "Invoice 5678"),
+    ]) {
+        assert_eq!(result["account"], account);
+        let emails = result["emails"].as_array().unwrap();
+        assert_eq!(emails.len(), 1, "{body}");
+        assert_eq!(emails[0]["subject"], subject);
+    }
+
+    // Unknown accounts are rejected
+    assert_eq!(
+        search(
+            r#"{"accounts": ["unknown@example.com"], "filter": {"subject": "invoice"}}"#,
+            "admin",
+            "secret"
+        )
+        .await
+        .0,
+        StatusCode::NOT_FOUND
+    );
+
+    // Export matching messages as mbox
+    let (status, body) = search(
+        r#"{"accounts": ["jane@example.com"], "export": "mbox",
+            "filter": {"subject": "invoice"}}"#,
+        "admin",
+        "secret",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body.starts_with("From - "), "{body}");
+    assert_eq!(body.matches("\nFrom - ").count(), 0, "{body}");
+    assert!(body.contains("Subject: Invoice 5678"), "{body}");
+    assert!(body.contains("\n>From the accounting desk."), "{body}");
+    assert!(!body.contains("Holidays"), "{body}");
+
+    // Remove test data
+    for account_id in account_ids {
+        destroy_all_mailboxes_no_wait(
+            params
+                .client
+                .set_default_account_id(Id::from(account_id).to_string()),
+        )
+        .await;
+    }
+    assert_is_empty(server).await;
+}
+
+async fn search(query: &str, username: &str, secret: &str) -> (StatusCode, String) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap()
+        .post("https://127.0.0.1:8899/admin/search")
+        .basic_auth(username, Some(secret))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(query.to_string())
+        .send()
+        .await
+        .unwrap();
+    (response.status(), response.text().await.unwrap())
+}
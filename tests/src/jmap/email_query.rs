@@ -32,7 +32,7 @@ use jmap_client::{
     core::query::{Comparator, Filter},
     email,
 };
-use jmap_proto::types::{collection::Collection, id::Id};
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
 use mail_parser::HeaderName;
 
 use store::{ahash::AHashMap, write::BatchBuilder};
@@ -120,6 +120,20 @@ pub async fn test(params: &mut JMAPTest, insert: bool) {
         .unwrap_set_email()
         .unwrap();
 
+    // The "virtual" mailboxes were removed without clearing their counters
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(1)
+        .with_collection(Collection::Mailbox);
+    for mailbox_id in 0..99999 {
+        batch
+            .update_document(mailbox_id)
+            .clear_counter(Property::TotalEmails)
+            .clear_counter(Property::UnreadEmails)
+            .clear_counter(Property::Size);
+    }
+    server.store.write(batch.build()).await.unwrap();
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
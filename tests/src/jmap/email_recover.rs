@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::{email::tombstone::RecoveryStatus, mailbox::INBOX_ID};
+use jmap_client::{email::Property, mailbox::Role};
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running deleted item recovery tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let client = test_account_login("jdoe@example.com", "12345").await;
+
+    // Import a test message into two mailboxes
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let mailbox_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let message_id = client
+        .email_import(
+            concat!(
+                "From: Jane Smith <jane@example.com>\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Quarterly numbers\r\n\r\n",
+                "Please keep this one around.\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            vec![&inbox_id, &mailbox_id],
+            Some(["$seen", "$important"]),
+            Some(1_000_000),
+        )
+        .await
+        .unwrap()
+        .take_id();
+
+    // Delete the message keeping it recoverable
+    let changes = server
+        .email_delete_with_retention(
+            account_id,
+            Id::from_bytes(message_id.as_bytes()).unwrap().document_id(),
+            Some(Duration::from_secs(3600)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    server.commit_changes(account_id, changes).await.unwrap();
+    assert!(client
+        .email_get(&message_id, [Property::Id].into())
+        .await
+        .unwrap()
+        .is_none());
+
+    // The tombstone contains enough details to identify the message
+    let tombstones = server.email_tombstones(account_id).await.unwrap();
+    assert_eq!(tombstones.len(), 1);
+    let tombstone = &tombstones[0];
+    assert_eq!(tombstone.subject.as_deref(), Some("Quarterly numbers"));
+    assert_eq!(tombstone.from.as_deref(), Some("jane@example.com"));
+    assert_eq!(tombstone.received_at, 1_000_000);
+    assert_eq!(tombstone.mailbox_ids.len(), 2);
+    assert!(tombstone.expires > tombstone.deleted_at);
+
+    // Recover the message, unknown ids are reported
+    let items = server
+        .email_recover(
+            account_id,
+            "jdoe@example.com",
+            vec![tombstone.id, u64::MAX],
+            "admin",
+        )
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].status, RecoveryStatus::Recovered);
+    assert_eq!(items[1].status, RecoveryStatus::NotFound);
+    let recovered_id = items[0].email_id.unwrap().to_string();
+    let email = client
+        .email_get(
+            &recovered_id,
+            [
+                Property::Subject,
+                Property::MailboxIds,
+                Property::Keywords,
+                Property::ReceivedAt,
+            ]
+            .into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.subject(), Some("Quarterly numbers"));
+    let mut mailbox_ids = email.mailbox_ids();
+    mailbox_ids.sort_unstable();
+    let mut expected_mailbox_ids = vec![inbox_id.as_str(), mailbox_id.as_str()];
+    expected_mailbox_ids.sort_unstable();
+    assert_eq!(mailbox_ids, expected_mailbox_ids);
+    let mut keywords = email.keywords();
+    keywords.sort_unstable();
+    assert_eq!(keywords, vec!["$important", "$seen"]);
+    assert_eq!(email.received_at(), Some(1_000_000));
+
+    // Tombstones are removed once recovered
+    assert!(server
+        .email_tombstones(account_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Deleted messages are not recoverable when no retention is configured
+    client.email_destroy(&recovered_id).await.unwrap();
+    assert!(server
+        .email_tombstones(account_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Remove test data
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
@@ -23,19 +23,27 @@
 
 use ahash::AHashMap;
 use directory::backend::internal::manage::ManageDirectory;
+use jmap::services::jobs::JobContext;
 use jmap_client::{
     core::set::{SetError, SetErrorType, SetObject},
     email_submission::{query::Filter, Address, Delivered, DeliveryStatus, Displayed, UndoStatus},
     mailbox::Role,
     Error,
 };
-use jmap_proto::types::id::Id;
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
 use mail_parser::DateTime;
 use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use store::parking_lot::Mutex;
+use store::{
+    parking_lot::Mutex,
+    write::{BatchBuilder, Operation},
+    Serialize,
+};
 
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
@@ -44,7 +52,8 @@ use tokio::{
 };
 
 use crate::jmap::{
-    assert_is_empty, email_set::assert_email_properties, mailbox::destroy_all_mailboxes,
+    assert_is_empty, email_set::assert_email_properties, jmap_json_request,
+    mailbox::destroy_all_mailboxes,
 };
 
 use super::JMAPTest;
@@ -240,6 +249,86 @@ pub async fn test(params: &mut JMAPTest) {
     )
     .await;
 
+    // The delivery status of the recipient should be available over JMAP
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let response = jmap_json_request(
+        format!(r#"[["DeliveryStatus/get", {{"accountId": "{account_id}"}}, "0"]]"#),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let statuses = response["methodResponses"][0][1]["list"]
+        .as_array()
+        .unwrap();
+    assert!(
+        statuses
+            .iter()
+            .any(|status| status["email"] == "jane_smith@remote.org"
+                && status["status"] == "delivered"),
+        "{response:?}"
+    );
+
+    // Submissions stored before the queue id was indexed are found once reindexed
+    let account_document_id = Id::from_bytes(account_id.as_bytes()).unwrap().document_id();
+    let mut queue_ids = Vec::new();
+    for document_id in server
+        .get_document_ids(account_document_id, Collection::EmailSubmission)
+        .await
+        .unwrap()
+        .unwrap_or_default()
+    {
+        let queue_id = server
+            .get_property::<Object<Value>>(
+                account_document_id,
+                Collection::EmailSubmission,
+                document_id,
+                Property::Value,
+            )
+            .await
+            .unwrap()
+            .and_then(|submission| submission.get(&Property::MessageId).as_uint());
+        if let Some(queue_id) = queue_id {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_document_id)
+                .with_collection(Collection::EmailSubmission)
+                .update_document(document_id)
+                .ops
+                .push(Operation::Index {
+                    field: Property::MessageId.into(),
+                    key: queue_id.serialize(),
+                    set: false,
+                });
+            server.store.write(batch.build()).await.unwrap();
+            queue_ids.push((queue_id, document_id));
+        }
+    }
+    assert!(!queue_ids.is_empty());
+    for (pass, expect_found) in [(0, false), (1, true)] {
+        if pass == 1 {
+            server
+                .reindex(Some(account_document_id), &JobContext::new(0))
+                .await
+                .unwrap();
+        }
+        for (queue_id, document_id) in &queue_ids {
+            assert_eq!(
+                server
+                    .filter(
+                        account_document_id,
+                        Collection::EmailSubmission,
+                        vec![store::query::Filter::eq(Property::MessageId, *queue_id)],
+                    )
+                    .await
+                    .unwrap()
+                    .results
+                    .contains(*document_id),
+                expect_found,
+                "{queue_id}"
+            );
+        }
+    }
+
     // Manually add recipients to the envelope and confirm submission
     let email_submission_id = client
         .email_submission_create_envelope(
@@ -485,6 +574,58 @@ pub async fn test(params: &mut JMAPTest) {
         .await
         .unwrap()
         .is_none());
+
+    // Both submissions above are delivered to the original recipient
+    for _ in 0..2 {
+        assert_eq!(
+            expect_message_delivery(&mut smtp_rx).await.rcpt_to,
+            ["<jane_smith@remote.org>"]
+        );
+    }
+
+    // Unsubscribe from a mailing list using its List-Unsubscribe mailto address
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: newsletter@remote.org\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Weekly news\r\n",
+                "List-Unsubscribe: <http://remote.org/unsubscribe>,\r\n",
+                " <mailto:unsubscribe@remote.org?subject=leave%20list>\r\n",
+                "\r\n",
+                "test"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let response = jmap_json_request(
+        format!(
+            r#"[["Email/unsubscribe", {{"accountId": "{account_id}", "emailIds": ["{email_id}"]}}, "0"]]"#
+        ),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response["methodResponses"][0][1]["unsubscribed"][0], email_id,
+        "{response:?}"
+    );
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<unsubscribe@remote.org>"],
+            "@Subject: leave list",
+        ),
+    )
+    .await;
+    assert_email_properties(client, &email_id, &[&mailbox_id], &["$unsubscribed"]).await;
     smtp_settings.lock().do_stop = true;
 
     // Destroy the created mailbox, identity and all submissions
@@ -508,6 +649,12 @@ pub async fn test(params: &mut JMAPTest) {
 }
 
 pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockSMTPSettings>>) {
+    spawn_mock_smtp_server_on("127.0.0.1:9999")
+}
+
+pub fn spawn_mock_smtp_server_on(
+    addr: &'static str,
+) -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockSMTPSettings>>) {
     // Create channels
     let (event_tx, event_rx) = mpsc::channel::<MockMessage>(100);
     let _settings = Arc::new(Mutex::new(MockSMTPSettings::default()));
@@ -515,11 +662,9 @@ pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockS
 
     // Start mock SMTP server
     tokio::spawn(async move {
-        let listener = TcpListener::bind("127.0.0.1:9999")
-            .await
-            .unwrap_or_else(|e| {
-                panic!("Failed to bind mock SMTP server to 127.0.0.1:9999: {}", e);
-            });
+        let listener = TcpListener::bind(addr).await.unwrap_or_else(|e| {
+            panic!("Failed to bind mock SMTP server to {}: {}", addr, e);
+        });
 
         while let Ok((mut stream, _)) = listener.accept().await {
             let (rx, mut tx) = stream.split();
@@ -31,6 +31,7 @@ use futures::StreamExt;
 use jmap::mailbox::INBOX_ID;
 use jmap_client::{event_source::Changes, mailbox::Role, TypeState};
 use jmap_proto::types::id::Id;
+use reqwest::{header, StatusCode};
 use store::ahash::AHashSet;
 
 use tokio::sync::mpsc;
@@ -56,6 +57,58 @@ pub async fn test(params: &mut JMAPTest) {
     .to_string();
     let client = test_account_login("jdoe@example.com", "12345").await;
 
+    // Open event sources count towards the connections shared by all listeners
+    let http = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let event_source = http
+        .get("https://127.0.0.1:8899/jmap/eventsource/?types=*&closeafter=no&ping=1")
+        .basic_auth("jdoe@example.com", Some("12345"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(event_source.status(), StatusCode::OK);
+    let jmap_request = |url: &'static str| {
+        http.post(url)
+            .basic_auth("jdoe@example.com", Some("12345"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"using": ["urn:ietf:params:jmap:core"], "methodCalls": []}"#)
+            .send()
+    };
+    let response = jmap_request("https://127.0.0.1:8898/jmap/").await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("maxConcurrentRequests"));
+    assert_eq!(
+        jmap_request("https://127.0.0.1:8899/jmap/")
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::OK
+    );
+
+    // The connection is released once the event source is closed
+    drop(event_source);
+    let mut is_released = false;
+    for _ in 0..10 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        if jmap_request("https://127.0.0.1:8898/jmap/")
+            .await
+            .unwrap()
+            .status()
+            == StatusCode::OK
+        {
+            is_released = true;
+            break;
+        }
+    }
+    assert!(is_released);
+
     let mut changes = client
         .event_source(None::<Vec<_>>, false, 1.into(), None)
         .await
@@ -80,7 +133,11 @@ pub async fn test(params: &mut JMAPTest) {
         .await
         .unwrap()
         .take_id();
-    assert_state(&mut event_rx, &account_id, &[TypeState::Mailbox]).await;
+    let last_event_id = assert_state(&mut event_rx, &account_id, &[TypeState::Mailbox])
+        .await
+        .id()
+        .expect("Missing event id")
+        .to_string();
 
     // Multiple changes should be grouped and delivered in intervals
     for num in 0..5 {
@@ -144,6 +201,62 @@ pub async fn test(params: &mut JMAPTest) {
     assert_ping(&mut event_rx).await;
     assert_ping(&mut event_rx).await;
 
+    // Reconnecting with the last event id should replay all missed changes
+    let mut replay = client
+        .event_source(None::<Vec<_>>, true, None, Some(last_event_id.as_str()))
+        .await
+        .unwrap();
+    match tokio::time::timeout(Duration::from_millis(700), replay.next()).await {
+        Ok(Some(Ok(changes))) => {
+            assert_eq!(
+                changes
+                    .changes(&account_id)
+                    .unwrap()
+                    .map(|x| x.0)
+                    .collect::<AHashSet<&TypeState>>(),
+                [
+                    TypeState::EmailDelivery,
+                    TypeState::Email,
+                    TypeState::Thread,
+                    TypeState::Mailbox,
+                ]
+                .iter()
+                .collect::<AHashSet<&TypeState>>()
+            );
+        }
+        result => {
+            panic!("Timeout waiting for replayed changes: {:?}", result);
+        }
+    }
+
+    // Changes older than the journal can't be replayed, the current state is sent instead
+    let mut replay = client
+        .event_source(
+            [TypeState::Mailbox, TypeState::Email].into(),
+            true,
+            None,
+            Some("1"),
+        )
+        .await
+        .unwrap();
+    match tokio::time::timeout(Duration::from_millis(700), replay.next()).await {
+        Ok(Some(Ok(changes))) => {
+            assert_eq!(
+                changes
+                    .changes(&account_id)
+                    .unwrap()
+                    .map(|x| x.0)
+                    .collect::<AHashSet<&TypeState>>(),
+                [TypeState::Email, TypeState::Mailbox]
+                    .iter()
+                    .collect::<AHashSet<&TypeState>>()
+            );
+        }
+        result => {
+            panic!("Timeout waiting for current state: {:?}", result);
+        }
+    }
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
@@ -152,7 +265,7 @@ async fn assert_state(
     event_rx: &mut mpsc::Receiver<Changes>,
     account_id: &str,
     state: &[TypeState],
-) {
+) -> Changes {
     match tokio::time::timeout(Duration::from_millis(700), event_rx.recv()).await {
         Ok(Some(changes)) => {
             assert_eq!(
@@ -163,6 +276,7 @@ async fn assert_state(
                     .collect::<AHashSet<&TypeState>>(),
                 state.iter().collect::<AHashSet<&TypeState>>()
             );
+            changes
         }
         result => {
             panic!("Timeout waiting for event {:?}: {:?}", state, result);
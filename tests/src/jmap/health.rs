@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::JMAPTest;
+
+pub async fn test(_params: &mut JMAPTest) {
+    println!("Running health check tests...");
+
+    // Liveness only checks the queue manager
+    let (status, response) = health_request("healthz").await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["status"], "ok");
+    assert_eq!(response["checks"]["queue"]["status"], "ok");
+    assert_eq!(response["checks"]["queue"]["overdue"], 0);
+    assert!(response["checks"].get("store").is_none());
+
+    // Readiness also checks the external dependencies
+    let (status, response) = health_request("readyz").await;
+    assert_eq!(status, 200, "{response}");
+    for check in ["store", "blobStore", "directory", "queue"] {
+        assert_eq!(response["checks"][check]["status"], "ok", "{response}");
+    }
+}
+
+async fn health_request(endpoint: &str) -> (u16, Value) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:8899/{endpoint}"))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (
+        status,
+        serde_json::from_slice(&response.bytes().await.unwrap()).unwrap(),
+    )
+}
@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::identity::delegate::SendAsMode;
+use jmap_proto::types::{collection::Collection, id::Id};
+
+use crate::jmap::{assert_is_empty, jmap_raw_request, test_account_login};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running send-as delegation tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    params
+        .directory
+        .create_test_user_with_email("jane@example.com", "abcdef", "Jane Smith")
+        .await;
+    let grantor_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let delegate_id = server
+        .store
+        .get_or_create_account_id("jane@example.com")
+        .await
+        .unwrap();
+    let identity_get = r#"[[ "Identity/get", {
+            "accountId": "$$",
+            "ids": null
+          }, "0" ]]"#
+        .replace("$$", &Id::from(delegate_id).to_string());
+
+    // Granting send-as creates an identity in the delegate's account
+    let grant = server
+        .send_as_grant(grantor_id, delegate_id, SendAsMode::SendAs)
+        .await
+        .unwrap();
+    assert_eq!(grant.account_id, delegate_id);
+    let response = jmap_raw_request(&identity_get, "jane@example.com", "abcdef").await;
+    assert!(
+        response.contains("\"email\":\"jdoe@example.com\""),
+        "{}",
+        response
+    );
+    assert!(
+        response.contains("\"email\":\"jane@example.com\""),
+        "{}",
+        response
+    );
+    assert!(response.contains("\"mayDelete\":false"), "{}", response);
+    assert_eq!(
+        server.send_as_grants(grantor_id).await.unwrap(),
+        vec![grant]
+    );
+    let addresses = server.send_as_addresses(delegate_id).await;
+    assert!(
+        addresses.iter().any(|a| a.address == "jdoe@example.com"),
+        "{addresses:?}"
+    );
+    assert!(addresses.iter().all(|a| !a.on_behalf));
+
+    // Delegated identities cannot be removed by the delegate
+    let delegated_id = Id::from(grant.identity_id).to_string();
+    let response = jmap_raw_request(
+        r#"[[ "Identity/set", {
+            "accountId": "$$",
+            "destroy": ["%%"]
+          }, "0" ]]"#
+            .replace("$$", &Id::from(delegate_id).to_string())
+            .replace("%%", &delegated_id),
+        "jane@example.com",
+        "abcdef",
+    )
+    .await;
+    assert!(response.contains("\"forbidden\""), "{}", response);
+
+    // Changing the mode keeps the same identity
+    let on_behalf = server
+        .send_as_grant(grantor_id, delegate_id, SendAsMode::OnBehalf)
+        .await
+        .unwrap();
+    assert_eq!(on_behalf.identity_id, grant.identity_id);
+    assert_eq!(
+        server.send_as_delegations(delegate_id).await.unwrap()[0].mode,
+        SendAsMode::OnBehalf
+    );
+    assert!(server
+        .send_as_addresses(delegate_id)
+        .await
+        .iter()
+        .all(|a| a.on_behalf));
+
+    // Revoking the grant removes the identity
+    assert!(server
+        .send_as_revoke(grantor_id, delegate_id)
+        .await
+        .unwrap());
+    assert!(!server
+        .send_as_revoke(grantor_id, delegate_id)
+        .await
+        .unwrap());
+    let response = jmap_raw_request(&identity_get, "jane@example.com", "abcdef").await;
+    assert!(
+        !response.contains("\"email\":\"jdoe@example.com\""),
+        "{}",
+        response
+    );
+    assert!(server.send_as_addresses(delegate_id).await.is_empty());
+
+    // Remove test data
+    let client = test_account_login("jane@example.com", "abcdef").await;
+    for identity_id in server
+        .get_document_ids(delegate_id, Collection::Identity)
+        .await
+        .unwrap()
+        .unwrap_or_default()
+    {
+        client
+            .identity_destroy(&Id::from(identity_id).to_string())
+            .await
+            .unwrap();
+    }
+    assert_is_empty(server).await;
+}
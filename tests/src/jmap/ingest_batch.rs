@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
+use jmap::{
+    email::ingest::{IngestBatch, IngestEmail},
+    mailbox::INBOX_ID,
+};
+use jmap_client::{email, mailbox::Role};
+use jmap_proto::types::{collection::Collection, id::Id};
+use mail_parser::MessageParser;
+use store::ahash::AHashSet;
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running batched ingest tests...");
+    let server = params.server.clone();
+
+    // Stage a thread, a reply and a duplicate in the same batch
+    let messages = [
+        "Message-ID: <batch-0@example.com>\r\nSubject: Batch\r\n\r\nfirst\r\n",
+        concat!(
+            "Message-ID: <batch-1@example.com>\r\n",
+            "References: <batch-0@example.com>\r\n",
+            "Subject: re: Batch\r\n\r\nreply\r\n"
+        ),
+        "Message-ID: <batch-0@example.com>\r\nSubject: Batch\r\n\r\nfirst\r\n",
+    ];
+    let mut batch = IngestBatch::new(3);
+    let mut ingested = Vec::new();
+    for message in messages {
+        ingested.push(
+            server
+                .email_ingest_batched(
+                    &mut batch,
+                    IngestEmail {
+                        raw_message: message.as_bytes(),
+                        message: MessageParser::new().parse(message.as_bytes()),
+                        account_id: 0,
+                        account_quota: 0,
+                        mailbox_ids: vec![INBOX_ID],
+                        keywords: vec![],
+                        received_at: None,
+                        skip_duplicates: true,
+                        encrypt: false,
+                    },
+                )
+                .await
+                .unwrap(),
+        );
+    }
+
+    // The duplicate is skipped and the reply joins the staged thread
+    assert_eq!(batch.len(), 2);
+    assert!(!batch.is_full());
+    assert_eq!(ingested[2].change_id, u64::MAX);
+    assert_eq!(ingested[0].change_id, ingested[1].change_id);
+    assert_eq!(
+        ingested[0].id.prefix_id(),
+        ingested[1].id.prefix_id(),
+        "reply was not assigned to the pending thread"
+    );
+
+    // Nothing is visible until the batch is committed
+    assert!(server
+        .get_document_ids(0, Collection::Email)
+        .await
+        .unwrap()
+        .map_or(true, |ids| ids.is_empty()));
+    server.email_ingest_commit(batch).await.unwrap();
+    assert_eq!(
+        server
+            .get_document_ids(0, Collection::Email)
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        2
+    );
+    let thread = params
+        .client
+        .set_default_account_id(Id::new(0u64).to_string())
+        .thread_get(&Id::from(ingested[0].id.prefix_id()).to_string())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(thread.email_ids().len(), 2);
+
+    // Duplicates of committed messages are skipped by later batches
+    let mut batch = IngestBatch::new(3);
+    let duplicate = server
+        .email_ingest_batched(
+            &mut batch,
+            IngestEmail {
+                raw_message: messages[0].as_bytes(),
+                message: MessageParser::new().parse(messages[0].as_bytes()),
+                account_id: 0,
+                account_quota: 0,
+                mailbox_ids: vec![INBOX_ID],
+                keywords: vec![],
+                received_at: None,
+                skip_duplicates: true,
+                encrypt: false,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(duplicate.change_id, u64::MAX);
+    assert!(batch.is_empty());
+    server.email_ingest_commit(batch).await.unwrap();
+    destroy_all_mailboxes(params).await;
+
+    // Email/import spanning multiple batches reports every committed message
+    let client = params
+        .client
+        .set_default_account_id(Id::new(0u64).to_string());
+    let mailbox_id = client
+        .mailbox_create("Batched import", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let num_messages = server.config.mail_ingest_batch_size + 1;
+    let num_existing = server
+        .get_document_ids(0, Collection::Email)
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len() as usize);
+    let mut blob_ids = Vec::with_capacity(num_messages);
+    for num in 0..num_messages {
+        let references = if num > 0 {
+            "References: <import-0@example.com>\r\n"
+        } else {
+            ""
+        };
+        blob_ids.push(
+            client
+                .upload(
+                    None,
+                    format!(
+                        concat!(
+                            "Message-ID: <import-{}@example.com>\r\n",
+                            "{}",
+                            "Subject: Batched import\r\n\r\n",
+                            "Message {}\r\n"
+                        ),
+                        num, references, num
+                    )
+                    .into_bytes(),
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_blob_id(),
+        );
+    }
+    let mut request = client.build();
+    let import_request = request.import_email();
+    let create_ids = blob_ids
+        .into_iter()
+        .map(|blob_id| {
+            import_request
+                .email(blob_id)
+                .mailbox_ids([&mailbox_id])
+                .create_id()
+        })
+        .collect::<Vec<_>>();
+    let mut response = request
+        .send_single::<email::import::EmailImportResponse>()
+        .await
+        .unwrap();
+    assert!(response.not_created_ids().is_none());
+    let mut thread_ids = AHashSet::new();
+    for create_id in &create_ids {
+        thread_ids.insert(
+            response
+                .created(create_id)
+                .unwrap()
+                .thread_id()
+                .unwrap()
+                .to_string(),
+        );
+    }
+    assert_eq!(thread_ids.len(), 1);
+    assert_eq!(
+        server
+            .get_document_ids(0, Collection::Email)
+            .await
+            .unwrap()
+            .unwrap()
+            .len() as usize,
+        num_existing + num_messages
+    );
+
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::{
+    mailbox::INBOX_ID,
+    services::jobs::{Job, JobKind, JobStatus},
+    JMAP,
+};
+use jmap_client::email::query::Filter;
+use jmap_proto::types::id::Id;
+use store::{dispatch::analyze::KeySpace, write::now};
+
+use crate::jmap::{
+    assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login, wait_for_index,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running background job tests...");
+    let server = params.server.clone();
+
+    // Jobs run in the background and report completion
+    let job = server
+        .job_submit(JobKind::StoreMaintenance, "admin", None)
+        .await
+        .unwrap();
+    let job = wait_for_job(&server, job.id).await;
+    assert_eq!(job.status, JobStatus::Completed);
+    assert!(job.started_at.is_some());
+    assert!(job.finished_at.is_some());
+
+    // Scheduled jobs stay queued until they are due
+    let scheduled = server
+        .job_submit(JobKind::StoreMaintenance, "admin", Some(now() + 3600))
+        .await
+        .unwrap();
+    let due = server
+        .job_submit(JobKind::StoreMaintenance, "admin", Some(now() + 1))
+        .await
+        .unwrap();
+    let due = wait_for_job(&server, due.id).await;
+    assert_eq!(due.status, JobStatus::Completed);
+    assert_eq!(
+        server.job_get(scheduled.id).await.unwrap().unwrap().status,
+        JobStatus::Queued
+    );
+    assert_eq!(server.job_list().await.unwrap().len(), 3);
+
+    // Cancelling a queued job prevents it from running
+    let cancelled = server
+        .job_cancel(scheduled.id, "admin")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(cancelled.status, JobStatus::Cancelled);
+    assert!(cancelled.finished_at.is_some());
+    assert_eq!(
+        server.job_get(scheduled.id).await.unwrap().unwrap().status,
+        JobStatus::Cancelled
+    );
+
+    // Cancelling a finished job removes it
+    for job_id in [job.id, due.id, scheduled.id] {
+        server.job_cancel(job_id, "admin").await.unwrap().unwrap();
+        assert!(server.job_get(job_id).await.unwrap().is_none());
+    }
+    assert!(server.job_cancel(job.id, "admin").await.unwrap().is_none());
+    assert!(server.job_list().await.unwrap().is_empty());
+    assert!(server.running_jobs.is_empty());
+
+    // Reindexing keeps messages searchable
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let client = test_account_login("jdoe@example.com", "12345").await;
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let message_id = client
+        .email_import(
+            concat!(
+                "From: jane@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Quarterly harvest\r\n\r\n",
+                "Ripe yellow bananas\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            vec![&inbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    wait_for_index(&server).await;
+    let job = server
+        .job_submit(
+            JobKind::Reindex {
+                account_id: Some(account_id),
+            },
+            "admin",
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        wait_for_job(&server, job.id).await.status,
+        JobStatus::Completed
+    );
+    wait_for_index(&server).await;
+    for filter in [Filter::text("bananas"), Filter::subject("harvest")] {
+        assert_eq!(
+            client
+                .email_query(filter.into(), None::<Vec<_>>)
+                .await
+                .unwrap()
+                .ids(),
+            [message_id.as_str()]
+        );
+    }
+
+    // Key space analysis attributes keys to the account
+    let job = server
+        .job_submit(JobKind::KeySpaceAnalysis, "admin", None)
+        .await
+        .unwrap();
+    assert_eq!(
+        wait_for_job(&server, job.id).await.status,
+        JobStatus::Completed
+    );
+    let analysis = server.key_space_analysis_get().await.unwrap().unwrap();
+    let account = analysis
+        .accounts
+        .iter()
+        .find(|account| account.account_id == account_id)
+        .unwrap();
+    assert_eq!(account.name.as_deref(), Some("jdoe@example.com"));
+    for key_space in [
+        KeySpace::Properties,
+        KeySpace::ChangeLogs,
+        KeySpace::Bitmaps,
+        KeySpace::FullText,
+    ] {
+        assert!(
+            account.usage.get(&key_space).map_or(0, |usage| usage.keys) > 0,
+            "{key_space:?}"
+        );
+    }
+    assert!(analysis.total_bytes >= account.total_bytes);
+    assert!(
+        !analysis
+            .anomalies
+            .iter()
+            .any(|anomaly| anomaly.account_id == Some(account_id)),
+        "{:?}",
+        analysis.anomalies
+    );
+    server.key_space_analysis_clear().await.unwrap();
+
+    // Remove test data
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn wait_for_job(server: &Arc<JMAP>, job_id: u64) -> Job {
+    for _ in 0..100 {
+        let job = server.job_get(job_id).await.unwrap().unwrap();
+        if matches!(job.status, JobStatus::Queued | JobStatus::Running { .. }) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        } else {
+            return job;
+        }
+    }
+    panic!("Job {job_id} did not finish.");
+}
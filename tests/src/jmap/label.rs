@@ -0,0 +1,313 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::mailbox::INBOX_ID;
+use jmap_client::email::Property;
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{
+    assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes, test_account_login,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Label tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("jdoe@example.com")
+            .await
+            .unwrap(),
+    )
+    .to_string();
+
+    // Create a label hierarchy, keywords are derived from the label path
+    let response = jmap_json_request(
+        r##"[[
+            "Label/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "work": {
+               "name": "Work",
+               "color": "#FF8800"
+              },
+              "projects": {
+               "name": "Projects (2024)",
+               "parentId": "#work"
+              },
+              "badColor": {
+               "name": "Bad",
+               "color": "orange"
+              },
+              "badName": {
+               "name": "Bad/Name"
+              },
+              "imported": {
+               "name": "Imported",
+               "keyword": "$label1"
+              }
+             }
+            },
+            "R1"
+           ]]"##
+            .replace("$$", &account_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let created = |id: &str, property: &str| {
+        response
+            .pointer(&format!("/methodResponses/0/1/created/{id}/{property}"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert_eq!(created("work", "keyword"), "Work", "{response:?}");
+    assert_eq!(
+        created("projects", "keyword"),
+        "Work/Projects__2024_",
+        "{response:?}"
+    );
+    assert_eq!(created("imported", "keyword"), "$label1", "{response:?}");
+    for id in ["badColor", "badName"] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some("invalidProperties"),
+            "{response:?}"
+        );
+    }
+    let work_id = created("work", "id");
+    let projects_id = created("projects", "id");
+    let imported_id = created("imported", "id");
+
+    // Keywords are unique
+    let response = jmap_json_request(
+        r#"[[
+            "Label/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "dup": {
+               "name": "Work"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/dup/type")
+            .and_then(|v| v.as_str()),
+        Some("alreadyExists"),
+        "{response:?}"
+    );
+
+    // Renaming a label keeps its keyword, cycles and keyword changes are rejected
+    let response = jmap_json_request(
+        r#"[[
+            "Label/set",
+            {
+             "accountId": "$$",
+             "update": {
+              "%work": {
+               "name": "Job",
+               "color": null
+              },
+              "%projects": {
+               "keyword": "Other"
+              }
+             }
+            },
+            "R1"
+           ],
+           [
+            "Label/set",
+            {
+             "accountId": "$$",
+             "update": {
+              "%work": {
+               "parentId": "%projects"
+              }
+             }
+            },
+            "R2"
+           ],
+           [
+            "Label/get",
+            {
+             "accountId": "$$",
+             "ids": ["%work"]
+            },
+            "R3"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%work", &work_id)
+        .replace("%projects", &projects_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{work_id}"))
+            .is_some(),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer(&format!(
+                "/methodResponses/0/1/notUpdated/{projects_id}/type"
+            ))
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/1/1/notUpdated/{work_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response:?}"
+    );
+    let label = response.pointer("/methodResponses/2/1/list/0").unwrap();
+    assert_eq!(label["name"].as_str(), Some("Job"), "{response:?}");
+    assert_eq!(label["keyword"].as_str(), Some("Work"), "{response:?}");
+    assert!(label["color"].is_null(), "{response:?}");
+
+    // Labels are applied to messages as regular keywords
+    let client = test_account_login("jdoe@example.com", "12345").await;
+    let message_id = client
+        .email_import(
+            concat!(
+                "From: jane@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Project kickoff\r\n\r\n",
+                "See you there.\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            vec![Id::new(INBOX_ID as u64).to_string()],
+            Some(["Work/Projects__2024_"]),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    assert_eq!(
+        client
+            .email_get(&message_id, [Property::Keywords].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .keywords(),
+        vec!["Work/Projects__2024_"]
+    );
+
+    // Labels with children cannot be destroyed
+    let response = jmap_json_request(
+        r#"[[
+            "Label/set",
+            {
+             "accountId": "$$",
+             "destroy": ["%work"]
+            },
+            "R1"
+           ],
+           [
+            "Label/set",
+            {
+             "accountId": "$$",
+             "destroy": ["%projects", "%imported"]
+            },
+            "R2"
+           ],
+           [
+            "Label/set",
+            {
+             "accountId": "$$",
+             "destroy": ["%work"]
+            },
+            "R3"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%work", &work_id)
+        .replace("%projects", &projects_id)
+        .replace("%imported", &imported_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notDestroyed/{work_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(2),
+        "{response:?}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/2/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(work_id.as_str()),
+        "{response:?}"
+    );
+
+    // Destroying a label does not remove the keyword from messages
+    assert_eq!(
+        client
+            .email_get(&message_id, [Property::Keywords].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .keywords(),
+        vec!["Work/Projects__2024_"]
+    );
+
+    // Remove test data
+    params.client.set_default_account_id(&account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
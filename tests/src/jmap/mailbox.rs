@@ -470,6 +470,23 @@ pub async fn test(params: &mut JMAPTest) {
         )
     );
 
+    // Materialized counters must match the values computed from the bitmaps
+    let counters = server.mailbox_counters_build(0).await.unwrap();
+    assert!(!counters.is_empty());
+    for (mailbox_id, count) in counters {
+        assert_eq!(
+            server.mailbox_counters_get(0, mailbox_id).await.unwrap(),
+            count,
+            "mailbox {mailbox_id}"
+        );
+    }
+    let trash_id = Id::from_bytes(id_map["trash"].as_bytes())
+        .unwrap()
+        .document_id();
+    let trash = server.mailbox_counters_get(0, trash_id).await.unwrap();
+    assert_eq!((trash.total, trash.unread), (1, 0));
+    assert!(trash.size > 0);
+
     // Deleting folders with children is not allowed
     let mut request = client.build();
     request.set_mailbox().destroy([&id_map["1"]]);
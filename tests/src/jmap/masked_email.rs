@@ -0,0 +1,401 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::backend::internal::{lookup::DirectoryStore, manage::ManageDirectory};
+use jmap_proto::types::{collection::Collection, date::UTCDate, id::Id};
+use store::write::now;
+use utils::ipc::DeliveryResult;
+
+use crate::jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running MaskedEmail tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let account_id_str = Id::from(account_id).to_string();
+    server.store.create_domain("example.com").await.unwrap();
+
+    // Create masked addresses, invalid prefixes are rejected
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "shop": {
+               "forDomain": "https://shop.example.net",
+               "description": "Online shop",
+               "emailPrefix": "shop"
+              },
+              "news": {
+               "forDomain": "https://news.example.net"
+              },
+              "badPrefix": {
+               "emailPrefix": "Not Valid"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let created = |id: &str, property: &str| {
+        response
+            .pointer(&format!("/methodResponses/0/1/created/{id}/{property}"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let shop_id = created("shop", "id");
+    let shop_email = created("shop", "email");
+    let news_id = created("news", "id");
+    let news_email = created("news", "email");
+    assert!(
+        shop_email.starts_with("shop.") && shop_email.ends_with("@example.com"),
+        "{response:?}"
+    );
+    assert!(news_email.ends_with("@example.com"), "{response:?}");
+    assert_eq!(created("shop", "state"), "pending", "{response:?}");
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/badPrefix/type")
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response:?}"
+    );
+
+    // Masked addresses are resolved through their own lookup
+    for email in [&shop_email, &news_email] {
+        assert!(server.store.rcpt(email).await.unwrap());
+        assert_eq!(
+            server.store.email_to_ids(email).await.unwrap(),
+            vec![account_id]
+        );
+    }
+    assert!(server
+        .store
+        .vrfy(shop_email.split('@').next().unwrap())
+        .await
+        .unwrap()
+        .is_empty());
+
+    // The first delivery enables a pending mask and records when it happened
+    assert!(server
+        .masked_email_deliver(account_id, &shop_email)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(server
+        .masked_email_deliver(account_id, "jdoe@example.com")
+        .await
+        .unwrap()
+        .is_none());
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/get",
+            {
+             "accountId": "$$",
+             "ids": ["%shop", "%news"]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%shop", &shop_id)
+        .replace("%news", &news_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let shop = response.pointer("/methodResponses/0/1/list/0").unwrap();
+    let news = response.pointer("/methodResponses/0/1/list/1").unwrap();
+    assert_eq!(shop["state"].as_str(), Some("enabled"), "{response:?}");
+    assert_eq!(shop["email"].as_str(), Some(shop_email.as_str()));
+    assert_eq!(shop["description"].as_str(), Some("Online shop"));
+    assert!(shop["lastMessageAt"].is_string(), "{response:?}");
+    assert_eq!(news["state"].as_str(), Some("pending"), "{response:?}");
+    assert!(news["lastMessageAt"].is_null(), "{response:?}");
+
+    // Masks cannot go back to pending, disabled masks discard messages
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "update": {
+              "%shop": {
+               "state": "pending"
+              },
+              "%news": {
+               "state": "disabled"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%shop", &shop_id)
+        .replace("%news", &news_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notUpdated/{shop_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response:?}"
+    );
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{news_id}"))
+            .is_some(),
+        "{response:?}"
+    );
+    assert!(server.store.rcpt(&news_email).await.unwrap());
+    assert!(matches!(
+        server
+            .masked_email_deliver(account_id, &news_email)
+            .await
+            .unwrap(),
+        Some(DeliveryResult::Success)
+    ));
+
+    // Deleted masks stop routing, destroyed masks are removed from the lookup
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "update": {
+              "%news": {
+               "state": "deleted"
+              }
+             },
+             "destroy": ["%shop"]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%shop", &shop_id)
+        .replace("%news", &news_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(shop_id.as_str()),
+        "{response:?}"
+    );
+    assert!(matches!(
+        server
+            .masked_email_deliver(account_id, &news_email)
+            .await
+            .unwrap(),
+        Some(DeliveryResult::PermanentFailure { .. })
+    ));
+    for email in [&shop_email, &news_email] {
+        assert!(!server.store.rcpt(email).await.unwrap());
+        assert!(server.store.email_to_ids(email).await.unwrap().is_empty());
+    }
+
+    // Expiry dates have to be in the future
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "temp": {
+               "forDomain": "https://temp.example.net",
+               "expires": "%soon"
+              },
+              "short": {
+               "expires": "%now"
+              },
+              "past": {
+               "expires": "2000-01-01T00:00:00Z"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace(
+            "%soon",
+            &UTCDate::from_timestamp(now() as i64 + 86400).to_string(),
+        )
+        .replace(
+            "%now",
+            &UTCDate::from_timestamp(now() as i64 + 2).to_string(),
+        ),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let created = |id: &str, property: &str| {
+        response
+            .pointer(&format!("/methodResponses/0/1/created/{id}/{property}"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let temp_id = created("temp", "id");
+    let temp_email = created("temp", "email");
+    let short_id = created("short", "id");
+    let short_email = created("short", "email");
+    assert!(!temp_id.is_empty() && !short_id.is_empty(), "{response:?}");
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/past/type")
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response:?}"
+    );
+
+    // Owners are notified once about addresses that expire soon
+    let num_emails = || async {
+        server
+            .get_document_ids(account_id, Collection::Email)
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len())
+    };
+    for _ in 0..2 {
+        server.masked_email_expire(account_id).await.unwrap();
+        assert_eq!(num_emails().await, 2);
+    }
+
+    // Changing the expiry date sends a new notice
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "update": {
+              "%temp": {
+               "expires": "%later"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%temp", &temp_id)
+        .replace(
+            "%later",
+            &UTCDate::from_timestamp(now() as i64 + 2 * 86400).to_string(),
+        ),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{temp_id}"))
+            .is_some(),
+        "{response:?}"
+    );
+    server.masked_email_expire(account_id).await.unwrap();
+    assert_eq!(num_emails().await, 3);
+
+    // Expired addresses are rejected and removed from the lookup
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(matches!(
+        server
+            .masked_email_deliver(account_id, &short_email)
+            .await
+            .unwrap(),
+        Some(DeliveryResult::PermanentFailure {
+            code: [5, 1, 1],
+            ..
+        })
+    ));
+    assert!(server.store.rcpt(&short_email).await.unwrap());
+    server.masked_email_expire(account_id).await.unwrap();
+    assert!(!server.store.rcpt(&short_email).await.unwrap());
+    assert!(server.store.rcpt(&temp_email).await.unwrap());
+    let response = jmap_json_request(
+        r#"[[
+            "MaskedEmail/get",
+            {
+             "accountId": "$$",
+             "ids": ["%short"]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%short", &short_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/state")
+            .and_then(|v| v.as_str()),
+        Some("deleted"),
+        "{response:?}"
+    );
+
+    // Remove test data
+    jmap_json_request(
+        r#"[[
+            "MaskedEmail/set",
+            {
+             "accountId": "$$",
+             "destroy": ["%news", "%temp", "%short"]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id_str)
+        .replace("%news", &news_id)
+        .replace("%temp", &temp_id)
+        .replace("%short", &short_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    params.client.set_default_account_id(&account_id_str);
+    destroy_all_mailboxes(params).await;
+    server.store.delete_domain("example.com").await.unwrap();
+    assert_is_empty(server).await;
+}
@@ -40,25 +40,39 @@ use utils::{config::ServerProtocol, UnwrapFailure};
 
 use crate::{add_test_certs, directory::DirectoryStore, store::TempDir};
 
+pub mod account_export;
+pub mod activity;
+pub mod alert;
 pub mod auth_acl;
+pub mod auth_devices;
 pub mod auth_limits;
 pub mod auth_oauth;
+pub mod auth_reset;
 pub mod blob;
 pub mod crypto;
 pub mod delivery;
+pub mod discovery;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
 pub mod email_parse;
 pub mod email_query;
 pub mod email_query_changes;
+pub mod email_recover;
 pub mod email_search_snippet;
 pub mod email_set;
 pub mod email_submission;
 pub mod event_source;
+pub mod health;
+pub mod identity_send_as;
+pub mod ingest_batch;
+pub mod jobs;
+pub mod label;
 pub mod mailbox;
+pub mod masked_email;
 pub mod push_subscription;
 pub mod quota;
+pub mod redact;
 pub mod sieve_script;
 pub mod stress_test;
 pub mod thread_get;
@@ -76,6 +90,12 @@ url = "https://127.0.0.1:8899"
 protocol = "jmap"
 max-connections = 81920
 
+[server.listener.jmap-limited]
+bind = ["127.0.0.1:8898"]
+url = "https://127.0.0.1:8898"
+protocol = "jmap"
+max-connections-per-account = 1
+
 [server.listener.lmtp-debug]
 bind = ['127.0.0.1:11200']
 greeting = 'Test LMTP instance'
@@ -181,6 +201,19 @@ blob = "{STORE}"
 [jmap.spam]
 header = "X-Spam-Status: Yes"
 
+[jmap.delivery]
+add-keywords = [ { if = "rcpt", eq = "john.doe@example.com", then = ["$junk"] } ]
+subject-prefix = [ { if = "rcpt", eq = "john.doe@example.com", then = "[SPAM]" },
+                   { else = false } ]
+notify = [ { if = "rcpt", eq = "john.doe@example.com", then = ["matrix"] } ]
+
+[jmap.notify.matrix]
+type = "matrix"
+url = "http://127.0.0.1:9196"
+room = "!alerts:example.com"
+token = "chat-token"
+timeout = "1s"
+
 [jmap.protocol.get]
 max-objects = 100000
 
@@ -213,6 +246,8 @@ throttle = "500ms"
 [jmap.push]
 throttle = "500ms"
 attempts.interval = "500ms"
+batch.window = "100ms"
+topic = "jmap-state"
 
 [store."auth"]
 type = "sqlite"
@@ -297,18 +332,31 @@ pub async fn jmap_tests() {
     email_copy::test(&mut params).await;
     thread_get::test(&mut params).await;
     thread_merge::test(&mut params).await;
+    ingest_batch::test(&mut params).await;
     mailbox::test(&mut params).await;
     delivery::test(&mut params).await;
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
     auth_oauth::test(&mut params).await;
+    auth_devices::test(&mut params).await;
+    auth_reset::test(&mut params).await;
     event_source::test(&mut params).await;
+    health::test(&mut params).await;
     push_subscription::test(&mut params).await;
     sieve_script::test(&mut params).await;
     vacation_response::test(&mut params).await;
     email_submission::test(&mut params).await;
+    identity_send_as::test(&mut params).await;
     websocket::test(&mut params).await;
     quota::test(&mut params).await;
+    redact::test(&mut params).await;
+    email_recover::test(&mut params).await;
+    label::test(&mut params).await;
+    masked_email::test(&mut params).await;
+    activity::test(&mut params).await;
+    account_export::test(&mut params).await;
+    discovery::test(&mut params).await;
+    jobs::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
 
@@ -374,6 +422,12 @@ pub async fn assert_is_empty(server: Arc<JMAP>) {
     // Wait for pending FTS index tasks
     wait_for_index(&server).await;
 
+    // Remove usage reports and finished jobs
+    server.traffic_purge(u32::MAX).await.unwrap();
+    for job in server.job_list().await.unwrap() {
+        server.job_cancel(job.id, "admin").await.unwrap();
+    }
+
     // Assert is empty
     server
         .store
@@ -381,6 +435,91 @@ pub async fn assert_is_empty(server: Arc<JMAP>) {
         .await;
 }
 
+#[tokio::test]
+async fn check_config() {
+    let temp_dir = TempDir::new("jmap_check_config", true);
+    let server = add_test_certs(SERVER)
+        .replace("{STORE}", "sqlite")
+        .replace("{TMP}", &temp_dir.path.display().to_string());
+    let check = |toml: String| async move {
+        let config = utils::config::Config::new(&toml).unwrap();
+        let servers = config.parse_servers().unwrap();
+        let stores = config.parse_stores().await.unwrap();
+        let directory = config
+            .parse_directory(&stores, "sqlite".into())
+            .await
+            .unwrap();
+        (
+            SMTP::check_config(&config, &servers, &stores, &directory),
+            JMAP::check_config(&config, &stores, &directory),
+        )
+    };
+
+    // The test configuration is valid
+    let (smtp, jmap) = check(server.clone()).await;
+    smtp.unwrap();
+    jmap.unwrap();
+
+    // Missing stores and directories are reported
+    for (from, to, expected) in [
+        (
+            "[jmap]\ndirectory = \"auth\"",
+            "[jmap]\ndirectory = \"missing\"",
+            "Unable to find directory",
+        ),
+        (
+            "blob = \"sqlite\"",
+            "blob = \"missing\"",
+            "Unable to find blob store",
+        ),
+    ] {
+        let (smtp, jmap) = check(server.replacen(from, to, 1)).await;
+        smtp.unwrap();
+        let err = jmap.unwrap_err();
+        assert!(err.contains(expected), "{err}");
+    }
+
+    // Invalid rule expressions are reported
+    let (smtp, jmap) = check(server.replacen(
+        "add-keywords = [ { if = \"rcpt\"",
+        "add-keywords = [ { if = \"invalid-variable\"",
+        1,
+    ))
+    .await;
+    smtp.unwrap();
+    assert!(jmap.is_err());
+    let (smtp, jmap) = check(server.replacen(
+        "{ if = \"authenticated-as\", ne = \"\", then = true }",
+        "{ if = \"invalid-variable\", ne = \"\", then = true }",
+        1,
+    ))
+    .await;
+    assert!(smtp.is_err());
+    jmap.unwrap();
+
+    // Notifications require a configured connector
+    let (smtp, jmap) =
+        check(server.replacen("then = [\"matrix\"]", "then = [\"missing\"]", 1)).await;
+    smtp.unwrap();
+    let err = jmap.unwrap_err();
+    assert!(
+        err.contains("Notification connector \"missing\" not found"),
+        "{err}"
+    );
+    let (_, jmap) = check(server.replacen("type = \"matrix\"", "type = \"irc\"", 1)).await;
+    let err = jmap.unwrap_err();
+    assert!(err.contains("Invalid notification type \"irc\""), "{err}");
+
+    // Unknown directories and lists referenced by SMTP are reported
+    let (smtp, _) = check(server.replacen(
+        "[session.rcpt]\nrelay = [ { if = \"authenticated-as\", ne = \"\", then = true }, \n          { else = false } ]\ndirectory = \"auth\"",
+        "[session.rcpt]\nrelay = [ { if = \"authenticated-as\", ne = \"\", then = true }, \n          { else = false } ]\ndirectory = \"missing\"",
+        1,
+    ))
+    .await;
+    assert!(smtp.is_err());
+}
+
 async fn init_jmap_tests(store_id: &str, delete_if_exists: bool) -> JMAPTest {
     // Load and parse config
     let temp_dir = TempDir::new("jmap_tests", delete_if_exists);
@@ -390,7 +529,7 @@ async fn init_jmap_tests(store_id: &str, delete_if_exists: bool) -> JMAPTest {
             .replace("{TMP}", &temp_dir.path.display().to_string()),
     )
     .unwrap();
-    let servers = config.parse_servers().unwrap();
+    let mut servers = config.parse_servers().unwrap();
     let stores = config.parse_stores().await.failed("Invalid configuration");
     let directory = config
         .parse_directory(&stores, store_id.into())
@@ -400,12 +539,27 @@ async fn init_jmap_tests(store_id: &str, delete_if_exists: bool) -> JMAPTest {
     // Start JMAP and SMTP servers
     servers.bind(&config);
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
-    let smtp = SMTP::init(&config, &servers, &stores, &directory, delivery_tx)
-        .await
-        .failed("Invalid configuration file");
-    let jmap = JMAP::init(&config, &stores, &directory, delivery_rx, smtp.clone())
-        .await
-        .failed("Invalid configuration file");
+    let (event_tx, event_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
+    let smtp = SMTP::init(
+        &config,
+        &servers,
+        &stores,
+        &directory,
+        delivery_tx,
+        event_tx,
+    )
+    .await
+    .failed("Invalid configuration file");
+    let jmap = JMAP::init(
+        &config,
+        &stores,
+        &directory,
+        delivery_rx,
+        event_rx,
+        smtp.clone(),
+    )
+    .await
+    .failed("Invalid configuration file");
     let (shutdown_tx, _) = servers.spawn(|server, shutdown_rx| {
         match &server.protocol {
             ServerProtocol::Smtp | ServerProtocol::Lmtp => {
@@ -24,7 +24,7 @@
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
@@ -51,7 +51,10 @@ use utils::listener::SessionData;
 
 use crate::{
     add_test_certs,
-    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login},
+    jmap::{
+        assert_is_empty, delivery::SmtpConnection, jmap_raw_request,
+        mailbox::destroy_all_mailboxes, test_account_login,
+    },
 };
 
 use super::JMAPTest;
@@ -110,11 +113,12 @@ pub async fn test(params: &mut JMAPTest) {
         auth_secret: auth_secret.to_vec(),
         tx: event_tx,
         fail_requests: false.into(),
+        last_topic: Mutex::new(None),
     });
 
     // Start mock push server
     let settings = utils::config::Config::new(&add_test_certs(SERVER)).unwrap();
-    let servers = settings.parse_servers().unwrap();
+    let mut servers = settings.parse_servers().unwrap();
 
     // Start JMAP server
     let manager = SessionManager::from(push_server.clone());
@@ -211,6 +215,12 @@ pub async fn test(params: &mut JMAPTest) {
     assert_state(&mut event_rx, &account_id, &[DataType::Mailbox]).await;
     //expect_nothing(&mut event_rx).await;
 
+    // State changes are sent with a topic so push services can collapse them
+    assert_eq!(
+        push_server.last_topic.lock().unwrap().as_deref(),
+        Some("jmap-state")
+    );
+
     // Multiple change updates should be grouped and pushed in intervals
     for num in 0..5 {
         client
@@ -221,6 +231,98 @@ pub async fn test(params: &mut JMAPTest) {
     assert_state(&mut event_rx, &account_id, &[DataType::Mailbox]).await;
     expect_nothing(&mut event_rx).await;
 
+    // Delivery history should include the failed attempts
+    let stats = server
+        .push_stats(
+            account_id.document_id(),
+            Id::from_bytes(push_id.as_bytes()).unwrap().document_id(),
+        )
+        .await
+        .unwrap();
+    assert!(stats.delivered >= 3, "{stats:?}");
+    assert!(stats.failed >= 1, "{stats:?}");
+    assert!(stats.last_success.is_some() && stats.last_failure.is_some());
+    assert!(
+        stats
+            .last_failure_reason
+            .as_deref()
+            .unwrap_or_default()
+            .contains("429"),
+        "{stats:?}"
+    );
+    let response = jmap_raw_request(
+        r#"[[ "PushSubscription/get", {
+            "ids": [ "$$" ],
+            "properties": [ "deviceClientId", "deliveryStats" ]
+          }, "0" ]]"#
+            .replace("$$", &push_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert!(response.contains("\"deliveryStats\""), "{}", response);
+    assert!(response.contains("\"lastFailureReason\""), "{}", response);
+    let summaries = server
+        .push_subscription_summaries(account_id.document_id())
+        .await
+        .unwrap();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0]["host"], "127.0.0.1");
+    assert_eq!(summaries[0]["failed"], stats.failed);
+
+    // Previews require encryption keys
+    let response = jmap_raw_request(
+        r#"[[ "PushSubscription/set", {
+            "create": { "a": {
+                "deviceClientId": "456",
+                "url": "https://127.0.0.1:9000/push",
+                "preview": true
+            } }
+          }, "0" ]]"#,
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert!(
+        response.contains("Previews require encryption keys"),
+        "{}",
+        response
+    );
+
+    // Delivered messages should include a preview once enabled
+    let response = jmap_raw_request(
+        r#"[[ "PushSubscription/set", {
+            "update": { "$$": { "preview": true } }
+          }, "0" ]]"#
+            .replace("$$", &push_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert!(response.contains("\"updated\""), "{}", response);
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "jane@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: Jane Smith <jane@example.com>\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Lunch tomorrow?\r\n",
+            "\r\n",
+            "Are you free?\r\n"
+        ),
+    )
+    .await;
+    let state = expect_push(&mut event_rx).await.unwrap_state_change();
+    assert_eq!(state.previews.len(), 1, "{state:?}");
+    assert_eq!(state.previews[0].account_id, account_id);
+    assert_eq!(state.previews[0].from, "Jane Smith <jane@example.com>");
+    assert_eq!(state.previews[0].subject, "Lunch tomorrow?");
+    assert!(state.changed.get(&account_id).is_some());
+
+    // Payloads with previews are never collapsed
+    assert_eq!(push_server.last_topic.lock().unwrap().as_deref(), None);
+
     // Destroy mailbox
     client.push_subscription_destroy(&push_id).await.unwrap();
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
@@ -245,6 +347,7 @@ pub struct PushServer {
     auth_secret: Vec<u8>,
     tx: mpsc::Sender<PushMessage>,
     fail_requests: AtomicBool,
+    last_topic: Mutex<Option<String>>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -314,6 +417,10 @@ impl utils::listener::SessionManager for SessionManager {
                                 )
                                 .into_http_response());
                             }
+                            *push.last_topic.lock().unwrap() = req
+                                .headers()
+                                .get("topic")
+                                .map(|topic| topic.to_str().unwrap().to_string());
                             let is_encrypted = req
                                 .headers()
                                 .get(CONTENT_ENCODING)
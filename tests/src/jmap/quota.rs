@@ -334,6 +334,24 @@ pub async fn test(params: &mut JMAPTest) {
             .len(),
         1,
     );
+
+    // Storage report should include the account and its domain
+    let report = server.storage_report_build().await.unwrap();
+    let account = report
+        .accounts
+        .iter()
+        .find(|account| account.name == "robert@example.com")
+        .unwrap();
+    assert_eq!(account.used_bytes, quota);
+    assert_eq!(account.messages, 1);
+    let domain = report
+        .domains
+        .iter()
+        .find(|domain| domain.name == "example.com")
+        .unwrap();
+    assert!(domain.used_bytes >= quota);
+    assert!(domain.messages >= 1);
+    server.storage_report_clear().await.unwrap();
     DISABLE_UPLOAD_QUOTA.store(true, std::sync::atomic::Ordering::Relaxed);
 
     // Remove test data
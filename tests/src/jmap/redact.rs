@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::{api::redact::RedactionStatus, mailbox::INBOX_ID};
+use jmap_client::email::Property;
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running message redaction tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("jdoe@example.com")
+        .await
+        .unwrap();
+    let client = test_account_login("jdoe@example.com", "12345").await;
+
+    // Import test messages
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let mut message_ids = Vec::new();
+    for i in 0..2 {
+        message_ids.push(
+            client
+                .email_import(
+                    format!(
+                        concat!(
+                            "From: jane@example.com\r\n",
+                            "To: jdoe@example.com\r\n",
+                            "Message-ID: <redact-{}@example.com>\r\n",
+                            "Subject: Personal data {}\r\n\r\n",
+                            "Sensitive contents {}\r\n"
+                        ),
+                        i, i, i
+                    )
+                    .into_bytes(),
+                    vec![&inbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let blob_id = client
+        .email_get(&message_ids[0], [Property::BlobId].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .take_blob_id();
+
+    // Redact the first message keeping its envelope, unknown ids are reported
+    let unknown_id = Id::from_parts(u32::MAX - 1, u32::MAX - 1);
+    let report = server
+        .email_redact(
+            account_id,
+            "jdoe@example.com",
+            vec![
+                Id::from_bytes(message_ids[0].as_bytes()).unwrap(),
+                unknown_id,
+            ],
+            None,
+            true,
+            "admin",
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.messages.len(), 2);
+    let redacted = &report.messages[0];
+    assert_eq!(redacted.status, RedactionStatus::Redacted);
+    assert!(redacted.blob_purged);
+    assert!(redacted.fts_purged);
+    let envelope = redacted.envelope.as_ref().unwrap();
+    assert_eq!(envelope.from, vec!["jane@example.com".to_string()]);
+    assert_eq!(envelope.to, vec!["jdoe@example.com".to_string()]);
+    assert_eq!(envelope.message_id.as_deref(), Some("redact-0@example.com"));
+    assert_eq!(report.messages[1].status, RedactionStatus::NotFound);
+
+    // Message and blob are gone, the other message is untouched
+    assert!(client
+        .email_get(&message_ids[0], [Property::Id].into())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(client.download(&blob_id).await.is_err());
+    assert!(client
+        .email_get(&message_ids[1], [Property::Id].into())
+        .await
+        .unwrap()
+        .is_some());
+
+    // Reports are signed and tampering is detected
+    assert!(server.redaction_report_verify(&report));
+    let mut tampered_report = report.clone();
+    tampered_report.messages.pop();
+    assert!(!server.redaction_report_verify(&tampered_report));
+    let mut unsigned_report = report.clone();
+    unsigned_report.signature = None;
+    assert!(!server.redaction_report_verify(&unsigned_report));
+
+    // Envelopes are only kept on request
+    let report = server
+        .email_redact(
+            account_id,
+            "jdoe@example.com",
+            vec![Id::from_bytes(message_ids[1].as_bytes()).unwrap()],
+            None,
+            false,
+            "admin",
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.messages[0].status, RedactionStatus::Redacted);
+    assert!(report.messages[0].envelope.is_none());
+    assert!(server.redaction_report_verify(&report));
+
+    // Remove test data
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
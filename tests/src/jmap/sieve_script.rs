@@ -28,7 +28,7 @@ use jmap_client::{
     sieve::query::{Comparator, Filter},
     Error,
 };
-use jmap_proto::types::id::Id;
+use jmap_proto::types::{collection::Collection, id::Id};
 use std::{
     fs,
     path::PathBuf,
@@ -39,6 +39,7 @@ use crate::jmap::{
     assert_is_empty,
     delivery::SmtpConnection,
     email_submission::{assert_message_delivery, spawn_mock_smtp_server, MockMessage},
+    jmap_json_request,
     mailbox::destroy_all_mailboxes,
 };
 
@@ -79,6 +80,67 @@ pub async fn test(params: &mut JMAPTest) {
         }))
     ));
 
+    // Dry-run a script against a sample message, no mailboxes should be created
+    let document_id = Id::from_bytes(account_id.as_bytes()).unwrap().document_id();
+    let mailbox_ids = server
+        .get_document_ids(document_id, Collection::Mailbox)
+        .await
+        .unwrap();
+    let script_blob_id = client
+        .upload(
+            None,
+            concat!(
+                "require [\"fileinto\", \"mailbox\"];\r\n",
+                "if mailboxexists \"Receipts\" { discard; }\r\n",
+                "else { fileinto :create \"Receipts\"; }\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_blob_id();
+    let email_blob_id = client
+        .upload(
+            None,
+            b"From: bill@remote.org\r\nSubject: Invoice\r\n\r\nPlease pay.\r\n".to_vec(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_blob_id();
+    let response = jmap_json_request(
+        format!(
+            concat!(
+                "[[\"SieveScript/test\", {{\"accountId\": \"{}\", \"blobId\": \"{}\", ",
+                "\"emailBlobId\": \"{}\", \"envelopeFrom\": \"bill@remote.org\"}}, \"0\"]]"
+            ),
+            account_id, script_blob_id, email_blob_id
+        ),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let result = &response["methodResponses"][0][1];
+    assert_eq!(
+        result["actions"],
+        serde_json::json!([{"type": "fileInto", "mailbox": "Receipts", "flags": [], "create": true}]),
+        "{response}"
+    );
+    assert!(result["trace"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|trace| trace.as_str().unwrap().starts_with("mailboxexists")));
+    assert_eq!(
+        server
+            .get_document_ids(document_id, Collection::Mailbox)
+            .await
+            .unwrap(),
+        mailbox_ids
+    );
+
     // Create 5 Sieve scripts, all deactivated.
     let mut script_ids = Vec::new();
     for i in 0..5 {
@@ -29,7 +29,10 @@ use crate::{
 };
 use jmap::{email::ingest::IngestEmail, mailbox::INBOX_ID, IngestError};
 use jmap_client::{email, mailbox::Role};
-use jmap_proto::types::{collection::Collection, id::Id};
+use jmap_proto::{
+    method::thread::{MergeThreadRequest, SplitThreadRequest},
+    types::{collection::Collection, id::Id, state::State},
+};
 use mail_parser::{mailbox::mbox::MessageIterator, MessageParser};
 use store::{
     ahash::{AHashMap, AHashSet},
@@ -41,6 +44,7 @@ use super::JMAPTest;
 pub async fn test(params: &mut JMAPTest) {
     test_single_thread(params).await;
     test_multi_thread(params).await;
+    test_split_merge(params).await;
 }
 
 async fn test_single_thread(params: &mut JMAPTest) {
@@ -304,6 +308,123 @@ async fn test_multi_thread(params: &mut JMAPTest) {
     assert_is_empty(params.server.clone()).await;
 }
 
+async fn test_split_merge(params: &mut JMAPTest) {
+    println!("Running Thread split and merge tests...");
+    let server = params.server.clone();
+    let client = params
+        .client
+        .set_default_account_id(Id::new(0u64).to_string());
+
+    // Build a thread with three messages
+    let mailbox_id = client
+        .mailbox_create("Split threads", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut email_ids = Vec::new();
+    for num in 0..3 {
+        let references = if num > 0 {
+            "References: <split-0@example.com>\r\n"
+        } else {
+            ""
+        };
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        concat!(
+                            "Message-ID: <split-{}@example.com>\r\n",
+                            "{}",
+                            "Subject: Weekly sync\r\n\r\n",
+                            "Message {}\r\n"
+                        ),
+                        num, references, num
+                    )
+                    .into_bytes(),
+                    [&mailbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap(),
+        );
+    }
+    let thread_id = email_ids[0].thread_id().unwrap().to_string();
+    assert!(email_ids
+        .iter()
+        .all(|email| email.thread_id().unwrap() == thread_id));
+
+    // Split the last message into its own thread
+    let split_id = Id::from_bytes(email_ids[2].id().unwrap().as_bytes()).unwrap();
+    let response = server
+        .thread_split(SplitThreadRequest {
+            account_id: Id::new(0u64),
+            email_ids: vec![split_id, Id::from_parts(u32::MAX - 1, u32::MAX - 1)],
+        })
+        .await
+        .unwrap();
+    assert_eq!(response.not_split.len(), 1);
+    assert!(matches!(response.new_state, State::Exact(_)));
+    let new_thread_id = response.thread_id.unwrap();
+    assert_ne!(new_thread_id.to_string(), thread_id);
+    assert_eq!(
+        response.email_ids,
+        vec![Id::from_parts(
+            new_thread_id.document_id(),
+            split_id.document_id()
+        )]
+    );
+    assert_eq!(
+        client
+            .thread_get(&thread_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .email_ids()
+            .len(),
+        2
+    );
+    assert_eq!(
+        client
+            .thread_get(&new_thread_id.to_string())
+            .await
+            .unwrap()
+            .unwrap()
+            .email_ids()
+            .len(),
+        1
+    );
+
+    // Merge both threads back together
+    let response = server
+        .thread_merge(MergeThreadRequest {
+            account_id: Id::new(0u64),
+            thread_ids: vec![Id::from_bytes(thread_id.as_bytes()).unwrap(), new_thread_id],
+        })
+        .await
+        .unwrap();
+    assert!(response.not_merged.is_empty());
+    assert_eq!(response.email_ids.len(), 1);
+    assert_eq!(
+        client
+            .thread_get(&thread_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .email_ids()
+            .len(),
+        3
+    );
+    assert!(client
+        .thread_get(&new_thread_id.to_string())
+        .await
+        .unwrap()
+        .is_none());
+
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
 fn build_message(message: usize, in_reply_to: Option<usize>, thread_num: usize) -> String {
     if let Some(in_reply_to) = in_reply_to {
         format!(
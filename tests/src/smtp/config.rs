@@ -35,17 +35,24 @@ use store::{
     config::ConfigStore,
     LookupStore,
 };
-use tokio::net::TcpSocket;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpSocket, TcpStream},
+};
 
-use utils::config::{Config, DynValue, KeyLookup, Listener, Rate, Server, ServerProtocol};
+use utils::{
+    config::{Config, DynValue, KeyLookup, Listener, Rate, Server, ServerProtocol},
+    listener::{SessionData, SessionManager},
+};
 
 use ahash::AHashMap;
 
 use smtp::{
     config::{
-        condition::ConfigCondition, if_block::ConfigIf, throttle::ConfigThrottle, Condition,
-        ConditionMatch, Conditions, ConfigContext, EnvelopeKey, IfBlock, IfThen, IpAddrMask,
-        StringMatch, Throttle, THROTTLE_AUTH_AS, THROTTLE_REMOTE_IP, THROTTLE_SENDER_DOMAIN,
+        condition::ConfigCondition, if_block::ConfigIf, rules::ConfigRules,
+        throttle::ConfigThrottle, Condition, ConditionMatch, Conditions, ConfigContext,
+        EnvelopeKey, IfBlock, IfThen, IpAddrMask, StringMatch, Throttle, THROTTLE_AUTH_AS,
+        THROTTLE_REMOTE_IP, THROTTLE_SENDER_DOMAIN,
     },
     core::Lookup,
 };
@@ -452,6 +459,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: false,
             max_connections: 8192,
+            ..Default::default()
         },
         Server {
             id: "smtps".to_string(),
@@ -480,6 +488,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: true,
             max_connections: 1024,
+            ..Default::default()
         },
         Server {
             id: "submission".to_string(),
@@ -498,6 +507,7 @@ fn parse_servers() {
             tls: None,
             tls_implicit: true,
             max_connections: 8192,
+            ..Default::default()
         },
     ];
 
@@ -554,6 +564,95 @@ fn parse_servers() {
     }
 }
 
+const HANDOVER: &str = r#"
+[server]
+hostname = 'mx.example.org'
+
+[server.listener.handover]
+bind = ['127.0.0.1:9995']
+protocol = 'smtp'
+
+[server.socket]
+reuse-port = true
+
+[server.shutdown]
+drain-timeout = '1s'
+"#;
+
+#[derive(Clone)]
+struct GreetingManager(&'static str);
+
+impl SessionManager for GreetingManager {
+    fn spawn(&self, mut session: SessionData<TcpStream>) {
+        let greeting = self.0;
+        tokio::spawn(async move {
+            let _ = session.stream.write_all(greeting.as_bytes()).await;
+        });
+    }
+
+    fn shutdown(&self) {}
+}
+
+#[tokio::test]
+async fn listener_handover() {
+    // Start two processes listening on the same port
+    let config = Config::new(HANDOVER).unwrap();
+    let mut shutdown_txs = Vec::new();
+    for greeting in ["old", "new"] {
+        let mut servers = config.parse_servers().unwrap();
+        servers.bind(&config);
+        shutdown_txs.push(
+            servers
+                .spawn(|server, shutdown_rx| {
+                    server.spawn(GreetingManager(greeting), shutdown_rx);
+                })
+                .0,
+        );
+    }
+
+    // Keep connecting while the old process shuts down, connections that were
+    // queued on its socket must be served rather than reset
+    let mut clients = Vec::new();
+    for _ in 0..50 {
+        clients.push(tokio::spawn(async {
+            let mut greetings = Vec::new();
+            for _ in 0..20 {
+                let mut stream = TcpStream::connect("127.0.0.1:9995")
+                    .await
+                    .expect("Connection refused");
+                let mut greeting = String::new();
+                stream
+                    .read_to_string(&mut greeting)
+                    .await
+                    .expect("Connection reset");
+                assert!(!greeting.is_empty(), "Connection closed without greeting");
+                greetings.push(greeting);
+            }
+            greetings
+        }));
+    }
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    shutdown_txs[0].send(true).unwrap();
+    let mut greetings = AHashMap::new();
+    for client in clients {
+        for greeting in client.await.unwrap() {
+            *greetings.entry(greeting).or_insert(0) += 1;
+        }
+    }
+    assert_eq!(greetings.values().sum::<i32>(), 1000, "{greetings:?}");
+    assert!(greetings.contains_key("new"), "{greetings:?}");
+
+    // Once the old process is gone all connections go to the new one
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for _ in 0..20 {
+        let mut stream = TcpStream::connect("127.0.0.1:9995").await.unwrap();
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await.unwrap();
+        assert_eq!(greeting, "new");
+    }
+    shutdown_txs[1].send(true).unwrap();
+}
+
 #[tokio::test]
 async fn eval_if() {
     let mut file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -577,6 +676,7 @@ async fn eval_if() {
     ];
     let mut context = ConfigContext::new(&servers);
     context.stores = config.parse_stores().await.unwrap();
+    config.parse_rule_scripts(&mut context).unwrap();
     let conditions = config.parse_conditions(&context).unwrap();
 
     let envelope = TestEnvelope::from_config(&config);
@@ -611,6 +711,7 @@ async fn eval_dynvalue() {
     let config = Config::new(&fs::read_to_string(file).unwrap()).unwrap();
     let mut context = ConfigContext::new(&[]);
     context.stores = config.parse_stores().await.unwrap();
+    config.parse_rule_scripts(&mut context).unwrap();
 
     let envelope = TestEnvelope::from_config(&config);
 
@@ -27,11 +27,12 @@ use store::Stores;
 use utils::config::{Config, DynValue};
 
 use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
     session::{TestSession, VerifyResponse},
-    ParseTestConfig, TestConfig,
+    ParseTestConfig, TestConfig, TestSMTP,
 };
 use smtp::{
-    config::{ConfigContext, EnvelopeKey},
+    config::{ConfigContext, EnvelopeKey, IfBlock},
     core::{Session, State, SMTP},
 };
 
@@ -54,6 +55,12 @@ secret = "p4ssw0rd"
 email = "jane@example.org"
 email-list = ["info@example.org"]
 member-of = ["sales", "support"]
+
+[[directory."local".principals]]
+name = "sales"
+type = "group"
+description = "Sales Team"
+email = "sales@example.org"
 "#;
 
 #[tokio::test]
@@ -171,3 +178,104 @@ async fn auth() {
         .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "503 5.5.1")
         .await;
 }
+
+#[tokio::test]
+async fn auth_sender_match() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_auth_sender_test");
+    let mut ctx = ConfigContext::new(&[]);
+    ctx.directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), None)
+        .await
+        .unwrap();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let config = &mut core.session.config.auth;
+    config.directory = "'local'"
+        .parse_if::<Option<DynValue<EnvelopeKey>>>(&ctx)
+        .map_if_block(&ctx.directory.directories, "", "")
+        .unwrap();
+    config.mechanisms = IfBlock::new(AUTH_PLAIN | AUTH_LOGIN);
+    config.must_match_sender = IfBlock::new(true);
+    config.send_as_groups = IfBlock::new(true);
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.stream.tls = true;
+    session.ehlo("mx.foobar.org").await;
+    session
+        .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "235 2.7.0")
+        .await;
+
+    // MAIL FROM must be one of the account's addresses
+    session.mail_from("bill@foobar.org", "501 5.5.4").await;
+    session.mail_from("jane@example.org", "501 5.5.4").await;
+    session.mail_from("<>", "250").await;
+    session.rset().await;
+
+    // From header must match the account's addresses
+    session.mail_from("JDoe@example.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session
+        .data(
+            "From: Jane <jane@example.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "550 5.7.1",
+        )
+        .await;
+    session
+        .send_message(
+            "jdoe@example.org",
+            &["bill@foobar.org"],
+            "From: John <john.doe@example.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("From: John <john.doe@example.org>");
+
+    // Group addresses are allowed when sending as groups is enabled
+    session
+        .send_message(
+            "sales@example.org",
+            &["bill@foobar.org"],
+            "From: Sales <sales@example.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+
+    // Sending on behalf of another account requires a Sender header
+    session.data.on_behalf_emails = vec!["boss@example.org".to_string()];
+    session.mail_from("boss@example.org", "501 5.5.4").await;
+    session.mail_from("john@example.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session
+        .data(
+            "From: Boss <boss@example.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "550 5.7.1",
+        )
+        .await;
+    session
+        .send_message(
+            "john@example.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: Boss <boss@example.org>\r\n",
+                "Sender: John <john@example.org>\r\n",
+                "Subject: Hi\r\n\r\n",
+                "Hi!\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Sender: John <john@example.org>");
+}
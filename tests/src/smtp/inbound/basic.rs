@@ -63,4 +63,14 @@ async fn basic_commands() {
     // Test QUIT
     session.ingest(b"QUIT\r\n").await.unwrap_err();
     session.response().assert_code("221");
+
+    // Commands that must end a pipelined group should be flagged
+    let mut session = Session::test(SMTP::test());
+    session.ehlo("mx.foobar.org").await;
+    session.ingest(b"RSET\r\nNOOP\r\n").await.unwrap();
+    session.response().assert_code("250");
+    assert_eq!(session.data.pipelining_violations, 0);
+    session.ingest(b"NOOP\r\nRSET\r\n").await.unwrap();
+    session.response().assert_code("250");
+    assert_eq!(session.data.pipelining_violations, 1);
 }
@@ -22,17 +22,24 @@
 */
 
 use directory::core::config::ConfigDirectory;
-use store::Stores;
+use smtp_proto::MAIL_REQUIRETLS;
+use store::{config::ConfigStore, Stores};
 use utils::config::Config;
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
     session::{load_test_message, TestSession, VerifyResponse},
     ParseTestConfig, TestConfig, TestSMTP,
 };
 use smtp::{
-    config::{ConfigContext, IfBlock, MaybeDynValue},
+    config::{
+        ConfigContext, DisclaimerTemplate, DlpAction, DlpMatcher, DlpRule, FromPolicy, IfBlock,
+        MaybeDynValue, SubmissionFixup,
+    },
     core::{Session, SMTP},
+    inbound::{fixup::FixupResult, send_limits},
+    queue::quarantine::Quarantine,
 };
 
 const DIRECTORY: &str = r#"
@@ -98,6 +105,7 @@ async fn data() {
     config.data.add_return_path = config.data.add_auth_results.clone();
     config.data.add_received_spf = config.data.add_auth_results.clone();
     config.data.max_received_headers = IfBlock::new(3);
+    config.data.max_addresses = IfBlock::new(3);
     config.data.max_messages = r"[{if = 'remote-ip', eq = '10.0.0.1', then = 1},
     {else = 100}]"
         .parse_if(&ConfigContext::new(&[]));
@@ -150,6 +158,20 @@ async fn data() {
         )
         .await;
 
+    // Message structure limits
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: john@doe.org\r\n",
+                "To: a@foobar.org, b@foobar.org, c@foobar.org, d@foobar.org\r\n",
+                "Subject: Too many recipients\r\n\r\ntest"
+            ),
+            "550 5.6.0",
+        )
+        .await;
+
     // No headers should be added to messages from 10.0.0.1
     session
         .send_message("john@doe.org", &["bill@foobar.org"], "test:no_msgid", "250")
@@ -244,3 +266,559 @@ async fn data() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn data_banner() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_data_banner_test");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let config = &mut core.session.config.data;
+    config.add_auth_results = IfBlock::new(false);
+    config.add_date = IfBlock::new(false);
+    config.add_message_id = IfBlock::new(false);
+    config.add_received = IfBlock::new(false);
+    config.add_return_path = IfBlock::new(false);
+    config.add_received_spf = IfBlock::new(false);
+    config.banner.enable = "[{if = 'rcpt-domain', eq = 'foobar.org', then = true},
+    {else = false}]"
+        .parse_if(&ConfigContext::new(&[]));
+    config.banner.text = Some("CAUTION: This message originated outside the organization.".into());
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Banner is added to both the text and HTML parts
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            concat!(
+                "From: john@doe.org\r\n",
+                "To: bill@foobar.org\r\n",
+                "Subject: Hello\r\n",
+                "Content-Type: multipart/alternative; boundary=\"b1\"\r\n\r\n",
+                "--b1\r\n",
+                "Content-Type: text/plain; charset=\"iso-8859-1\"\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+                "Caf=E9 tomorrow?\r\n",
+                "--b1\r\n",
+                "Content-Type: text/html\r\n\r\n",
+                "<html><body><p>Caf&eacute; tomorrow?</p></body></html>\r\n",
+                "--b1--\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message().read_message();
+    let message = mail_parser::MessageParser::new()
+        .parse(message.as_bytes())
+        .unwrap();
+    assert_eq!(message.subject(), Some("Hello"));
+    assert!(message.body_text(0).unwrap().starts_with(
+        "CAUTION: This message originated outside the organization.\r\n\r\nCafé tomorrow?"
+    ));
+    assert!(message.body_html(0).unwrap().starts_with(
+        "<html><body><p>CAUTION: This message originated outside the organization.</p><p>Caf"
+    ));
+
+    // Banner is not added for other domains
+    session
+        .send_message("john@doe.org", &["mike@test.com"], "test:no_dkim", "250")
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("CAUTION");
+}
+
+#[tokio::test]
+async fn data_submission_fixup() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_data_fixup_test");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let config = &mut core.session.config.data;
+    config.add_date = IfBlock::new(false);
+    config.add_message_id = IfBlock::new(false);
+    config.fixup.enable = IfBlock::new(true);
+    config.fixup.max_header_size = 1024;
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Unauthenticated messages are not modified
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "Received: from client\r\nSubject: Hello\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("Message-ID:")
+        .assert_contains("Received: from client");
+
+    // Missing headers are added, private headers are removed and From is rewritten
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "Received: from client\r\n",
+                "X-Originating-IP: [192.168.1.10]\r\n",
+                "From: John\r\n",
+                "Subject: Hello\r\n\r\n",
+                "Hi!\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let raw_message = qr.read_event().await.unwrap_message().read_message();
+    assert!(!raw_message.contains("from client"), "{raw_message}");
+    let message = mail_parser::MessageParser::new()
+        .parse(raw_message.as_bytes())
+        .unwrap();
+    assert!(message.date().is_some());
+    assert!(message.message_id().is_some());
+    assert_eq!(
+        message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address()),
+        Some("john@foobar.org")
+    );
+    assert_eq!(message.subject(), Some("Hello"));
+    assert!(message.header("X-Originating-IP").is_none());
+
+    // Compliant messages are left untouched
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: John Doe <john@foobar.org>\r\n",
+                "Date: Mon, 1 Jan 2024 10:00:00 +0000\r\n",
+                "Message-ID: <abc@foobar.org>\r\n",
+                "Subject: Hello\r\n\r\n",
+                "Hi!\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("From: John Doe <john@foobar.org>")
+        .assert_contains("Message-ID: <abc@foobar.org>");
+
+    // Oversized headers are rejected
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &format!(
+                "From: john@foobar.org\r\nSubject: {}\r\n\r\nHi!\r\n",
+                "a".repeat(1024)
+            ),
+            "552 5.3.4",
+        )
+        .await;
+
+    qr.assert_empty_queue();
+
+    // Non-compliant From headers are rejected when configured
+    let fixup = SubmissionFixup {
+        enable: IfBlock::new(true),
+        from: FromPolicy::Reject,
+        strip_headers: vec![],
+        max_header_size: 0,
+    };
+    assert!(matches!(
+        fixup.apply(
+            b"From: john@foobar.org\r\nFrom: jane@foobar.org\r\n\r\nHi!\r\n",
+            "john@foobar.org",
+            "mx.foobar.org"
+        ),
+        FixupResult::Reject(_)
+    ));
+    assert!(matches!(
+        fixup.apply(
+            b"From: undisclosed\r\n\r\nHi!\r\n",
+            "john@foobar.org",
+            "mx.foobar.org"
+        ),
+        FixupResult::Reject(_)
+    ));
+}
+
+#[tokio::test]
+async fn data_disclaimer() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_data_disclaimer_test");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let config = &mut core.session.config.data;
+    config.disclaimer.enable = IfBlock::new(true);
+    config.disclaimer.templates = vec![
+        DisclaimerTemplate {
+            id: "foobar".to_string(),
+            domains: vec!["foobar.org".to_string()],
+            text: Some("This message is confidential.".to_string()),
+            html: None,
+        },
+        DisclaimerTemplate {
+            id: "default".to_string(),
+            domains: vec![],
+            text: Some("Sent from Example Corp.".to_string()),
+            html: Some("<p><i>Sent from Example Corp.</i></p>".to_string()),
+        },
+    ];
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Unauthenticated messages are not modified
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("confidential");
+
+    // Disclaimer is appended to both the text and HTML parts
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Hello\r\n",
+                "Content-Type: multipart/alternative; boundary=\"b1\"\r\n\r\n",
+                "--b1\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "Lunch tomorrow?\r\n",
+                "--b1\r\n",
+                "Content-Type: text/html\r\n\r\n",
+                "<html><body><p>Lunch tomorrow?</p></body></html>\r\n",
+                "--b1--\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message().read_message();
+    let message = mail_parser::MessageParser::new()
+        .parse(message.as_bytes())
+        .unwrap();
+    assert_eq!(
+        message.body_text(0).unwrap().trim_end(),
+        "Lunch tomorrow?\r\n\r\nThis message is confidential."
+    );
+    assert!(message
+        .body_html(0)
+        .unwrap()
+        .contains("<p>Lunch tomorrow?</p><p>This message is confidential.</p></body>"));
+
+    // Senders from other domains use the default template
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@example.org"],
+            "From: jane@example.org\r\nSubject: Hi\r\nContent-Type: text/html\r\n\r\n<p>Hi!</p>\r\n",
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message().read_message();
+    let message = mail_parser::MessageParser::new()
+        .parse(message.as_bytes())
+        .unwrap();
+    assert!(message
+        .body_html(0)
+        .unwrap()
+        .contains("<p>Hi!</p>\r\n<p><i>Sent from Example Corp.</i></p>"));
+
+    // Replies within a thread are not modified
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "In-Reply-To: <abc@example.org>\r\n",
+                "Subject: Re: Hello\r\n\r\n",
+                "Sure.\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Sure.")
+        .assert_not_contains("confidential");
+}
+
+#[tokio::test]
+async fn data_dlp() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_data_dlp_test");
+    let quarantine_dir = make_temp_dir("smtp_data_dlp_quarantine", true);
+    core.queue.quarantine = Quarantine::new(quarantine_dir.temp_dir.clone().into());
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let config = &mut core.session.config.data;
+    config.dlp.enable = IfBlock::new(true);
+    config.dlp.rules = vec![
+        DlpRule {
+            id: "credit-cards".to_string(),
+            matcher: DlpMatcher::CreditCard,
+            action: DlpAction::Quarantine,
+            domains: vec![],
+        },
+        DlpRule {
+            id: "confidential".to_string(),
+            matcher: DlpMatcher::Keywords(vec!["confidential".to_string()]),
+            action: DlpAction::RequireTls,
+            domains: vec!["foobar.org".to_string()],
+        },
+        DlpRule {
+            id: "executables".to_string(),
+            matcher: DlpMatcher::Attachment(vec!["exe".to_string()]),
+            action: DlpAction::Reject,
+            domains: vec![],
+        },
+    ];
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Unauthenticated messages are not scanned
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "From: john@foobar.org\r\nSubject: Card\r\n\r\n4111 1111 1111 1111\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+
+    // Executable attachments are rejected
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "Subject: Tool\r\n",
+                "Content-Type: multipart/mixed; boundary=\"b1\"\r\n\r\n",
+                "--b1\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "See attached.\r\n",
+                "--b1\r\n",
+                "Content-Type: application/octet-stream\r\n",
+                "Content-Disposition: attachment; filename=\"setup.exe\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "TVqQAAMAAAAEAAAA\r\n",
+                "--b1--\r\n"
+            ),
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Card numbers are quarantined
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "From: john@foobar.org\r\nSubject: Card\r\n\r\nMy card is 4111-1111-1111-1111\r\n",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+    let quarantined = session.core.queue.quarantine.list();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].rule, "credit-cards");
+    assert_eq!(
+        quarantined[0].recipients,
+        vec!["bill@example.org".to_string()]
+    );
+
+    // Numbers failing the checksum are delivered
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "From: john@foobar.org\r\nSubject: Order\r\n\r\nOrder 4111 1111 1111 1112\r\n",
+            "250",
+        )
+        .await;
+    assert_eq!(
+        qr.read_event().await.unwrap_message().flags & MAIL_REQUIRETLS,
+        0
+    );
+
+    // Confidential messages require TLS
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "From: john@foobar.org\r\nSubject: Confidential report\r\n\r\nHello\r\n",
+            "250",
+        )
+        .await;
+    assert_ne!(
+        qr.read_event().await.unwrap_message().flags & MAIL_REQUIRETLS,
+        0
+    );
+
+    // Releasing a quarantined message queues it for delivery
+    let span = tracing::info_span!("test");
+    assert!(
+        session
+            .core
+            .queue
+            .release_quarantined(quarantined[0].id, &span)
+            .await
+    );
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.recipients[0].address, "bill@example.org");
+    message.read_lines().assert_contains("4111-1111-1111-1111");
+    assert!(session.core.queue.quarantine.list().is_empty());
+
+    // All matches are recorded as incidents
+    let incidents = session.core.queue.quarantine.incidents();
+    assert_eq!(incidents.len(), 3);
+    assert_eq!(incidents[0].action, DlpAction::RequireTls);
+    assert_eq!(incidents[1].action, DlpAction::Quarantine);
+    assert_eq!(incidents[2].action, DlpAction::Reject);
+}
+
+#[tokio::test]
+async fn data_send_limits() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_data_send_limits_test");
+    let temp_dir = make_temp_dir("smtp_data_send_limits_locks", true);
+    let stores = Config::new(&format!(
+        "[store.\"locks\"]\ntype = \"sqlite\"\npath = \"{}/locks.db\"\n",
+        temp_dir.temp_dir.to_string_lossy()
+    ))
+    .unwrap()
+    .parse_stores()
+    .await
+    .unwrap();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let config = &mut core.session.config.data.send_limits;
+    config.messages_hourly = IfBlock::new(Some(2));
+    config.lock_threshold = IfBlock::new(Some(5));
+    config.store = stores.lookup_stores.get("locks").cloned();
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Unauthenticated senders are not limited
+    for _ in 0..3 {
+        session
+            .send_message(
+                "john@foobar.org",
+                &["bill@example.org"],
+                "From: john@foobar.org\r\nSubject: Hello\r\n\r\nHi\r\n",
+                "250",
+            )
+            .await;
+        qr.read_event().await.unwrap_message();
+    }
+
+    // Sending to too many recipients locks the account
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["a@example.org", "b@example.org"],
+            "From: john@foobar.org\r\nSubject: Hello\r\n\r\nHi\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["c@example.org", "d@example.org", "e@example.org"],
+            "From: john@foobar.org\r\nSubject: Hello\r\n\r\nHi\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    session.mail_from("john@foobar.org", "550 5.7.1").await;
+    let status = send_limits::status(&session.core.session, Some("john")).await;
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].hourly_messages, 2);
+    assert_eq!(status[0].hourly_recipients, 5);
+    assert!(status[0].locked_at.is_some());
+
+    // Locks survive restarts
+    session.core.session.senders.clear();
+    assert!(
+        send_limits::status(&session.core.session, Some("john")).await[0]
+            .locked_at
+            .is_some()
+    );
+    session.mail_from("john@foobar.org", "550 5.7.1").await;
+
+    // Once unlocked, the hourly message cap still applies
+    assert!(send_limits::unlock(&session.core.session, "john")
+        .await
+        .unwrap());
+    session.core.session.senders.clear();
+    session.mail_from("john@foobar.org", "250").await;
+    session.rset().await;
+    for _ in 0..2 {
+        session
+            .send_message(
+                "john@foobar.org",
+                &["bill@example.org"],
+                "From: john@foobar.org\r\nSubject: Hello\r\n\r\nHi\r\n",
+                "250",
+            )
+            .await;
+        qr.read_event().await.unwrap_message();
+    }
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "From: john@foobar.org\r\nSubject: Hello\r\n\r\nHi\r\n",
+            "451 4.7.1",
+        )
+        .await;
+    qr.assert_empty_queue();
+}
@@ -30,7 +30,7 @@ use crate::smtp::{
     ParseTestConfig, TestConfig,
 };
 use smtp::{
-    config::ConfigContext,
+    config::{ConfigContext, IfBlock},
     core::{Session, SMTP},
 };
 
@@ -85,3 +85,39 @@ async fn limits() {
     session.handle_conn_().await;
     session.response().assert_code("221 2.0.0");
 }
+
+#[tokio::test]
+async fn tarpit() {
+    let mut core = SMTP::test();
+    let config = &mut core.session.config;
+    config.tarpit.errors = r"[{if = 'remote-ip', eq = '10.0.0.1', then = 1},
+    {else = false}]"
+        .parse_if(&ConfigContext::new(&[]));
+    config.tarpit.delay = IfBlock::new(Duration::from_millis(100));
+    config.tarpit.max_delay = IfBlock::new(Duration::from_millis(150));
+    config.tarpit.disconnect = IfBlock::new(Some(2));
+
+    // Clients below the error threshold should not be delayed
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.data.rcpt_errors = 1;
+    session.eval_session_params().await;
+    let time = Instant::now();
+    session.cmd("RCPT TO:<bill@foobar.org>", "503 5.5.1").await;
+    assert!(time.elapsed() < Duration::from_millis(100));
+
+    // Delays should increase until the client is disconnected
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    let time = Instant::now();
+    session.cmd("RCPT TO:<bill@foobar.org>", "503 5.5.1").await;
+    assert!(time.elapsed() >= Duration::from_millis(100));
+    let time = Instant::now();
+    session.cmd("RCPT TO:<bill@foobar.org>", "503 5.5.1").await;
+    assert!(time.elapsed() >= Duration::from_millis(150));
+    session
+        .ingest(b"RCPT TO:<bill@foobar.org>\r\n")
+        .await
+        .unwrap_err();
+    session.response().assert_code("421 4.7.0");
+    assert_eq!(session.data.tarpit_count, 2);
+}
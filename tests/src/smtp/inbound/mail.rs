@@ -26,7 +26,10 @@ use std::{
     time::{Duration, Instant, SystemTime},
 };
 
-use mail_auth::{common::parse::TxtRecordParser, spf::Spf, IprevResult, SpfResult};
+use mail_auth::{
+    common::parse::TxtRecordParser, hickory_resolver::proto::rr::RecordType, spf::Spf, IprevResult,
+    SpfResult,
+};
 use smtp_proto::{MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS};
 
 use crate::smtp::{
@@ -36,8 +39,68 @@ use crate::smtp::{
 use smtp::{
     config::{ConfigContext, IfBlock, VerifyStrategy},
     core::{Session, SMTP},
+    outbound::dane::{DnssecMode, DnssecStatus},
 };
 
+#[tokio::test]
+async fn mail_dnssec() {
+    let mut core = SMTP::test();
+    core.resolvers.dnssec.mode = DnssecMode::Validate;
+    core.resolvers.dns.txt_add(
+        "foobar.org",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 ip4:10.0.0.2 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.resolvers.dnssec_add(
+        "foobar.org",
+        RecordType::TXT,
+        DnssecStatus::Bogus,
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.mail_auth.spf.verify_mail_from = r"[{if = 'remote-ip', eq = '10.0.0.2', then = 'strict'},
+    {else = 'relaxed'}]"
+        .parse_if(&ConfigContext::new(&[]));
+
+    // Bogus records are only reported when relaxed
+    let core = Arc::new(core);
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ingest(b"EHLO mx1.foobar.org\r\n").await.unwrap();
+    session.response().assert_code("250");
+    session
+        .ingest(b"MAIL FROM:<bill@foobar.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("250");
+    assert_eq!(session.data.spf_dnssec, Some(DnssecStatus::Bogus));
+    session.rset().await;
+
+    // Strict verification rejects bogus records
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session
+        .ingest(b"MAIL FROM:<bill@foobar.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("451 4.7.24");
+    assert!(session.data.mail_from.is_none());
+
+    // Secure records are accepted
+    core.resolvers.dnssec_add(
+        "foobar.org",
+        RecordType::TXT,
+        DnssecStatus::Secure,
+        Instant::now() + Duration::from_secs(5),
+    );
+    session
+        .ingest(b"MAIL FROM:<bill@foobar.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("250");
+    assert_eq!(session.data.spf_dnssec, Some(DnssecStatus::Secure));
+}
+
 #[tokio::test]
 async fn mail() {
     let mut core = SMTP::test();
@@ -41,12 +41,14 @@ pub mod ehlo;
 pub mod limits;
 pub mod mail;
 pub mod milter;
+pub mod policy;
 pub mod rcpt;
 pub mod rewrite;
 pub mod scripts;
 pub mod sign;
 pub mod throttle;
 pub mod vrfy;
+pub mod wasm;
 
 impl QueueReceiver {
     pub async fn read_event(&mut self) -> queue::Event {
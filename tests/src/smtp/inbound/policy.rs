@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use http_body_util::BodyExt;
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, IfBlock},
+    core::{Session, SMTP},
+};
+use tokio::{net::TcpListener, sync::watch};
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[session.policy."test"]
+url = "http://127.0.0.1:9193/policy"
+stages = ["mail", "rcpt", "data"]
+headers = ["Authorization: Bearer secret"]
+timeout = "5s"
+on-error = "defer"
+cache.ttl = "1h"
+"#;
+
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+#[tokio::test]
+async fn policy_service() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Spawn mock policy service
+    let shutdown = spawn_mock_policy_server();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Prepare config
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_policy_test");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.policy = Config::new(CONFIG)
+        .unwrap()
+        .parse_policy_services(&ConfigContext::new(&[]))
+        .unwrap();
+
+    // Build session
+    let mut session = Session::test(Arc::new(core));
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Service failures are deferred
+    session.mail_from("error@doe.org", "451 4.3.0").await;
+
+    // Test reject with custom reply and defer with default reply
+    session.mail_from("john@doe.org", "250").await;
+    session
+        .rcpt_to("reject@foobar.org", "550 5.7.1 Recipient blocked by policy")
+        .await;
+    session.rcpt_to("defer@foobar.org", "451 4.7.1").await;
+
+    // Verdicts are cached
+    let requests = REQUESTS.load(Ordering::Relaxed);
+    session
+        .rcpt_to("reject@foobar.org", "550 5.7.1 Recipient blocked by policy")
+        .await;
+    assert_eq!(REQUESTS.load(Ordering::Relaxed), requests);
+
+    // Test header addition
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session.data("test:no_dkim", "250").await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("X-Policy: checked 1")
+        .assert_contains("Subject: Is dinner ready?");
+
+    shutdown.send(false).ok();
+}
+
+pub fn spawn_mock_policy_server() -> watch::Sender<bool> {
+    let (tx, mut rx) = watch::channel(true);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9193")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock policy server to 127.0.0.1:9193: {e}");
+            });
+
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    let (stream, _) = stream.unwrap();
+                    tokio::spawn(async move {
+                        let _ = http1::Builder::new()
+                            .keep_alive(false)
+                            .serve_connection(
+                                TokioIo::new(stream),
+                                service_fn(|req: hyper::Request<body::Incoming>| async move {
+                                    REQUESTS.fetch_add(1, Ordering::Relaxed);
+                                    assert_eq!(
+                                        req.headers().get("authorization").unwrap(),
+                                        "Bearer secret"
+                                    );
+                                    let request = serde_json::from_slice::<serde_json::Value>(
+                                        &req.into_body().collect().await.unwrap().to_bytes(),
+                                    )
+                                    .unwrap();
+                                    assert_eq!(request["request"], "smtpd_access_policy");
+                                    assert_eq!(request["client_address"], "10.0.0.1");
+                                    let state = request["protocol_state"].as_str().unwrap();
+                                    let sender = request["sender"].as_str().unwrap();
+                                    let recipient = request["recipient"].as_str().unwrap();
+
+                                    let (status, response) = match state {
+                                        "MAIL" if sender.starts_with("error") => {
+                                            (hyper::StatusCode::INTERNAL_SERVER_ERROR, json!({}))
+                                        }
+                                        "RCPT" if recipient.starts_with("reject") => (
+                                            hyper::StatusCode::OK,
+                                            json!({
+                                                "action": "reject",
+                                                "reply": "550 5.7.1 Recipient blocked by policy"
+                                            }),
+                                        ),
+                                        "RCPT" if recipient.starts_with("defer") => {
+                                            (hyper::StatusCode::OK, json!({"action": "defer"}))
+                                        }
+                                        "END-OF-MESSAGE" => (
+                                            hyper::StatusCode::OK,
+                                            json!({
+                                                "action": "accept",
+                                                "headers": [{
+                                                    "name": "X-Policy",
+                                                    "value": format!(
+                                                        "checked {}",
+                                                        request["recipient_count"]
+                                                    )
+                                                }]
+                                            }),
+                                        ),
+                                        _ => (hyper::StatusCode::OK, json!({"action": "dunno"})),
+                                    };
+
+                                    Ok::<_, hyper::Error>(
+                                        hyper::Response::builder()
+                                            .status(status)
+                                            .header("Content-Type", "application/json")
+                                            .body(http_body_util::Full::new(
+                                                hyper::body::Bytes::from(response.to_string()),
+                                            ))
+                                            .unwrap(),
+                                    )
+                                }),
+                            )
+                            .await;
+                    });
+                },
+                _ = rx.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
@@ -21,20 +21,34 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use ahash::AHashSet;
 use directory::core::config::ConfigDirectory;
+use mail_auth::{common::parse::TxtRecordParser, dmarc::Dmarc, spf::Spf};
+use sieve::runtime::Variable;
 use smtp_proto::{RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_SUCCESS};
-use store::Stores;
+use store::{config::ConfigStore, LookupKey, LookupValue, Stores};
 use utils::config::Config;
 
-use crate::smtp::{
-    session::{TestSession, VerifyResponse},
-    ParseTestConfig, TestConfig,
+use crate::{
+    smtp::{
+        inbound::TestQueueEvent,
+        session::{TestSession, VerifyResponse},
+        ParseTestConfig, TestConfig, TestSMTP,
+    },
+    store::TempDir,
 };
 use smtp::{
-    config::{ConfigContext, IfBlock, MaybeDynValue},
+    config::{ConfigContext, IfBlock, MaybeDynValue, VerifyStrategy},
     core::{Session, State, SMTP},
+    inbound::{
+        greylist::Greylist,
+        honeypot::Honeypot,
+        rcpt_cache::{RcptCache, RcptStatus},
+    },
+    queue::suppression::{SuppressionAction, SuppressionList, SuppressionReason},
+    scripts::plugins::lookup::VariableWrapper,
 };
 
 const DIRECTORY: &str = r#"
@@ -102,6 +116,33 @@ async fn rcpt() {
     rate = '2/1s'
     "
     .parse_throttle(&ConfigContext::new(&[]));
+    let config = &mut core.report.config.analysis;
+    config.role_accounts = vec!["abuse".to_string(), "postmaster".to_string()];
+    config.role_mailbox = "john@foobar.org".to_string().into();
+    let temp_dir = TempDir::new("smtp_rcpt_suppression_tests", true);
+    let stores = Config::new(&format!(
+        "[store.\"suppression\"]\ntype = \"sqlite\"\npath = \"{}/suppression.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap()
+    .parse_stores()
+    .await
+    .unwrap();
+    core.queue.suppression = SuppressionList::new(
+        SuppressionAction::Reject,
+        stores.stores.get("suppression").cloned(),
+    );
+    assert!(core
+        .queue
+        .suppression
+        .add(
+            "example.net",
+            "bounced@domain.com",
+            SuppressionReason::Bounce,
+            "550 5.1.1 User unknown",
+        )
+        .await
+        .unwrap());
 
     // RCPT without MAIL FROM
     let mut session = Session::test(core);
@@ -173,4 +214,388 @@ async fn rcpt() {
     let rcpt = session.data.rcpt_to.last().unwrap();
     assert!((rcpt.flags & (RCPT_NOTIFY_DELAY | RCPT_NOTIFY_SUCCESS | RCPT_NOTIFY_FAILURE)) != 0);
     assert_eq!(rcpt.dsn_info.as_ref().unwrap(), "Jane.Doe@Foobar.org");
+
+    // Role accounts should be routed to the postmaster mailbox
+    session.rset().await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("Abuse@FooBar.org", "250").await;
+    session.rcpt_to("postmaster@foobar.org", "250").await;
+    session.rcpt_to("sales@foobar.org", "550 5.1.2").await;
+    assert_eq!(session.data.rcpt_to.len(), 1);
+    let rcpt = session.data.rcpt_to.last().unwrap();
+    assert_eq!(rcpt.address_lcase, "john@foobar.org");
+    assert_eq!(rcpt.domain, "foobar.org");
+
+    // Submissions to suppressed addresses should be rejected
+    session.rset().await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("bounced@domain.com", "250").await;
+    session.data.authenticated_as = "john".to_string();
+    session.rset().await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("Bounced@Domain.com", "550 5.1.1").await;
+    session.rcpt_to("external@domain.com", "250").await;
+
+    // Removed addresses should be accepted again
+    assert!(session
+        .core
+        .queue
+        .suppression
+        .remove("example.net", "bounced@domain.com")
+        .await
+        .unwrap());
+    session.rcpt_to("bounced@domain.com", "250").await;
+}
+
+#[tokio::test]
+async fn rcpt_dictionary_attack() {
+    let mut core = SMTP::test();
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), None)
+        .await
+        .unwrap();
+    let config = &mut core.session.config.rcpt;
+    config.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    config.errors_max = IfBlock::new(100);
+    config.errors_wait = IfBlock::new(Duration::from_millis(1));
+    config.dictionary.max_unknown = r"[{if = 'remote-ip', eq = '10.0.0.1', then = 2},
+    {else = false}]"
+        .parse_if(&ConfigContext::new(&[]));
+    let core = std::sync::Arc::new(core);
+
+    // Unknown recipients below the threshold are rejected as usual
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("tom@foobar.org", "550 5.1.2").await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+
+    // Exceeding the threshold blocks the remote IP, including new sessions
+    session.rcpt_to("sam@foobar.org", "550 5.1.2").await;
+    session.rcpt_to("bill@foobar.org", "451 4.7.1").await;
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("bill@foobar.org", "451 4.7.1").await;
+
+    // Other IPs are not affected
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("tom@foobar.org", "550 5.1.2").await;
+    session.rcpt_to("sam@foobar.org", "550 5.1.2").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+}
+
+#[tokio::test]
+async fn rcpt_dictionary_attack_persist() {
+    let temp_dir = TempDir::new("smtp_rcpt_dictionary_tests", true);
+    let config = Config::new(&format!(
+        "{DIRECTORY}\n[store.\"dictionary\"]\ntype = \"sqlite\"\npath = \"{}/dictionary.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+    let directory = config.parse_directory(&stores, None).await.unwrap();
+    let new_core = || {
+        let mut core = SMTP::test();
+        let config = &mut core.session.config.rcpt;
+        config.directory = IfBlock::new(Some(MaybeDynValue::Static(
+            directory.directories.get("local").unwrap().clone(),
+        )));
+        config.errors_max = IfBlock::new(100);
+        config.errors_wait = IfBlock::new(Duration::from_millis(1));
+        config.dictionary.max_unknown = IfBlock::new(Some(2));
+        config.dictionary.window = Duration::from_secs(3600);
+        config.dictionary.store = stores
+            .lookup_stores
+            .get("dictionary")
+            .unwrap()
+            .clone()
+            .into();
+        std::sync::Arc::new(core)
+    };
+    let core = new_core();
+
+    // Unknown recipients are counted for the whole IPv6 /64
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "2001:db8::1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("tom@foobar.org", "550 5.1.2").await;
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "2001:db8::2".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("sam@foobar.org", "550 5.1.2").await;
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+
+    // Blocks are kept in the store, a restarted node still rejects the network
+    for (remote_ip, response) in [("2001:db8::ffff", "451 4.7.1"), ("2001:db8:0:1::1", "250")] {
+        let mut session = Session::test(new_core());
+        session.data.remote_ip = remote_ip.parse().unwrap();
+        session.eval_session_params().await;
+        session.ehlo("mx1.foobar.org").await;
+        session.mail_from("john@example.net", "250").await;
+        session.rcpt_to("jane@foobar.org", response).await;
+    }
+}
+
+#[tokio::test]
+async fn rcpt_require_tls() {
+    let mut core = SMTP::test();
+    let config = &mut core.session.config.rcpt;
+    config.relay = IfBlock::new(true);
+    config.require_tls = r"[{if = 'rcpt-domain', eq = 'sensitive.org', then = true},
+    {else = false}]"
+        .parse_if(&ConfigContext::new(&[]));
+    let core = std::sync::Arc::new(core);
+
+    // Plain-text deliveries to sensitive domains are rejected
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("jane@sensitive.org", "530 5.7.0").await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+    assert_eq!(session.data.rcpt_to.len(), 1);
+
+    // Encrypted sessions are accepted
+    let mut session = Session::test(core);
+    session.stream.tls = true;
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+    session.rcpt_to("jane@sensitive.org", "250").await;
+}
+
+#[tokio::test]
+async fn rcpt_cache() {
+    let temp_dir = TempDir::new("smtp_rcpt_cache_tests", true);
+    let config = Config::new(&format!(
+        "{DIRECTORY}\n[store.\"cache\"]\ntype = \"rocksdb\"\npath = \"{}/rcpt_cache\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+    let directory = config.parse_directory(&stores, None).await.unwrap();
+
+    let mut core = SMTP::test();
+    let config = &mut core.session.config.rcpt;
+    config.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    config.cache = Some(RcptCache {
+        store: stores.lookup_stores.get("cache").unwrap().clone(),
+        ttl_exists: Duration::from_secs(60),
+        ttl_unknown: Duration::from_secs(60),
+        ttl_over_quota: Duration::from_secs(60),
+    });
+    let core = std::sync::Arc::new(core);
+    let cache = core.session.config.rcpt.cache.as_ref().unwrap();
+
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+
+    // Verification results are cached
+    session.rcpt_to("jane@foobar.org", "250").await;
+    session.rcpt_to("tom@foobar.org", "550 5.1.2").await;
+    assert_eq!(cache.get("jane@foobar.org").await, Some(RcptStatus::Exists));
+    assert_eq!(cache.get("tom@foobar.org").await, Some(RcptStatus::Unknown));
+
+    // Cached results are used instead of querying the directory
+    cache.set("tom@foobar.org", RcptStatus::Exists).await;
+    session.rcpt_to("tom@foobar.org", "250").await;
+    cache.set("bill@foobar.org", RcptStatus::OverQuota).await;
+    session.rcpt_to("bill@foobar.org", "452 4.2.2").await;
+    assert_eq!(session.data.rcpt_to.len(), 2);
+}
+
+#[tokio::test]
+async fn rcpt_greylist() {
+    let temp_dir = TempDir::new("smtp_rcpt_greylist_tests", true);
+    let config = Config::new(&format!(
+        "[store.\"greylist\"]\ntype = \"rocksdb\"\npath = \"{}/greylist\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+
+    let mut core = SMTP::test();
+    let config = &mut core.session.config.rcpt;
+    config.relay = IfBlock::new(true);
+    config.greylist = Some(Greylist {
+        enable: IfBlock::new(true),
+        store: stores.lookup_stores.get("greylist").unwrap().clone(),
+        delay: Duration::from_secs(1),
+        retry_window: Duration::from_secs(60),
+        ttl: Duration::from_secs(60),
+    });
+    let core = std::sync::Arc::new(core);
+
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+    session.mail_from("john@example.net", "250").await;
+
+    // First attempts are deferred, including early retries
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+    assert!(session.data.rcpt_to.is_empty());
+
+    // Retries after the delay from the same network are accepted
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.rcpt_to("jane@foobar.org", "250").await;
+
+    // Other triplets are greylisted separately
+    session.rcpt_to("bill@foobar.org", "451 4.7.1").await;
+    session.data.remote_ip = "10.0.1.1".parse().unwrap();
+    session.rcpt_to("jane@foobar.org", "451 4.7.1").await;
+    assert_eq!(session.data.rcpt_to.len(), 1);
+}
+
+#[tokio::test]
+async fn rcpt_honeypot() {
+    let temp_dir = TempDir::new("smtp_rcpt_honeypot_tests", true);
+    let config = Config::new(&format!(
+        "[store.\"reputation\"]\ntype = \"sqlite\"\npath = \"{}/reputation.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+    let store = stores.lookup_stores.get("reputation").unwrap().clone();
+
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_rcpt_honeypot_test");
+    core.resolvers.dns.txt_add(
+        "example.net",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.resolvers.dns.txt_add(
+        "football.example.com",
+        Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.resolvers.dns.txt_add(
+        "_dmarc.football.example.com",
+        Dmarc::parse(b"v=DMARC1; p=none;").unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+    core.mail_auth.spf.verify_mail_from = IfBlock::new(VerifyStrategy::Relaxed);
+    core.mail_auth.dmarc.verify = IfBlock::new(VerifyStrategy::Relaxed);
+    let config = &mut core.session.config.rcpt;
+    config.relay = IfBlock::new(true);
+    config.honeypot = Some(Honeypot {
+        addresses: AHashSet::from_iter(["trap@foobar.org".to_string()]),
+        domains: AHashSet::from_iter(["spamtrap.org".to_string()]),
+        store: Some(store.clone()),
+        score: 20.0,
+        ttl: Duration::from_secs(60),
+        webhook: None,
+    });
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx1.foobar.org").await;
+
+    // Messages sent only to spam traps are rejected
+    session
+        .send_message(
+            "john@example.net",
+            &["trap@foobar.org", "bill@spamtrap.org"],
+            "test:no_dkim",
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // The sender is penalized once per transaction, senders that pass SPF
+    // but not DMARC are prefixed as the spam filter does
+    for (token, count) in [
+        ("i:10.0.0.1", 1),
+        ("f:_john@example.net", 1),
+        ("d:_example.net", 1),
+    ] {
+        match store
+            .key_get::<VariableWrapper>(LookupKey::Key(token.as_bytes().to_vec()))
+            .await
+            .unwrap()
+        {
+            LookupValue::Value { value, .. } => {
+                let value = value.into_inner();
+                let rep = value.as_array().unwrap();
+                assert_eq!(rep[0], Variable::Float(20.0), "{token}");
+                assert_eq!(rep[1], Variable::Integer(count), "{token}");
+            }
+            other => panic!("Unexpected value for {token}: {other:?}"),
+        }
+    }
+
+    // Senders passing DMARC are penalized without prefix
+    session
+        .send_message(
+            "joe@football.example.com",
+            &["trap@foobar.org"],
+            "test:no_dkim",
+            "550 5.7.1",
+        )
+        .await;
+    for token in ["f:joe@football.example.com", "d:example.com"] {
+        assert!(
+            matches!(
+                store
+                    .key_get::<VariableWrapper>(LookupKey::Key(token.as_bytes().to_vec()))
+                    .await
+                    .unwrap(),
+                LookupValue::Value { .. }
+            ),
+            "{token}"
+        );
+    }
+
+    // Spam traps are removed from messages with other recipients
+    session
+        .send_message(
+            "john@example.net",
+            &["trap@foobar.org", "jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.recipients.len(), 1);
+    assert_eq!(message.recipients[0].address_lcase, "jane@foobar.org");
+    qr.assert_empty_queue();
+
+    // Authenticated users are not trapped
+    session.data.authenticated_as = "john".to_string();
+    session
+        .send_message(
+            "john@example.net",
+            &["trap@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
 }
@@ -77,11 +77,13 @@ async fn vrfy_expn() {
     )));
 
     let config = &mut core.session.config.extensions;
-    config.vrfy = r"[{if = 'remote-ip', eq = '10.0.0.1', then = true},
+    config.vrfy = r"[{if = 'remote-ip', eq = '10.0.0.1', then = 'full'},
+    {if = 'remote-ip', eq = '10.0.0.3', then = 'ambiguous'},
     {else = false}]"
         .parse_if(&ctx);
     config.expn = r"[{if = 'remote-ip', eq = '10.0.0.1', then = true},
-    {else = false}]"
+    {if = 'remote-ip', eq = '10.0.0.3', then = 'ambiguous'},
+    {else = 'disabled'}]"
         .parse_if(&ctx);
 
     // EHLO should not avertise VRFY/EXPN to 10.0.0.2
@@ -93,8 +95,20 @@ async fn vrfy_expn() {
         .await
         .assert_not_contains("EXPN")
         .assert_not_contains("VRFY");
-    session.cmd("VRFY john", "252 2.5.1").await;
-    session.cmd("EXPN sales@foobar.org", "252 2.5.1").await;
+    session.cmd("VRFY john", "502 5.5.1").await;
+    session.cmd("EXPN sales@foobar.org", "502 5.5.1").await;
+
+    // Ambiguous replies should not disclose whether the address exists
+    session.data.remote_ip = "10.0.0.3".parse().unwrap();
+    session.eval_session_params().await;
+    session
+        .ehlo("mx.foobar.org")
+        .await
+        .assert_not_contains("EXPN")
+        .assert_not_contains("VRFY");
+    session.cmd("VRFY john", "252 2.5.2").await;
+    session.cmd("VRFY robert", "252 2.5.2").await;
+    session.cmd("EXPN sales@foobar.org", "252 2.5.2").await;
 
     // EHLO should advertise VRFY/EXPN for 10.0.0.1
     session.data.remote_ip = "10.0.0.1".parse().unwrap();
@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, sync::Arc, time::Duration};
+
+use smtp::{
+    config::IfBlock,
+    core::{Session, SMTP},
+    wasm::Plugins,
+};
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    make_temp_dir,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[plugin."test"]
+path = "%PATH%/test.wat"
+tempfail-on-error = true
+reload-interval = "100ms"
+
+[plugin."test".limits]
+fuel = 100000
+memory = 1048576
+"#;
+
+const PLUGIN: &str = r#"
+(module
+  (import "stalwart_v1" "get_var" (func $get_var (param i32 i32 i32 i32) (result i32)))
+  (import "stalwart_v1" "header_count" (func $header_count (param i32 i32) (result i32)))
+  (import "stalwart_v1" "add_header" (func $add_header (param i32 i32 i32 i32) (result i32)))
+  (import "stalwart_v1" "set_reply" (func $set_reply (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "recipient")
+  (data (i32.const 16) "sender")
+  (data (i32.const 32) "X-Wasm-Plugin")
+  (data (i32.const 48) "checked")
+  (data (i32.const 64) "550 5.1.1 Mailbox blocked by plugin")
+  (data (i32.const 112) "x-block")
+
+  ;; Senders starting with 'l' loop until the fuel is exhausted
+  (func (export "on_mail") (result i32)
+    (drop (call $get_var (i32.const 16) (i32.const 6) (i32.const 1024) (i32.const 64)))
+    (if (i32.eq (i32.load8_u (i32.const 1024)) (i32.const 108))
+      (then (loop $spin (br $spin))))
+    (i32.const 0))
+
+  ;; Recipients starting with 'b' are rejected
+  (func (export "on_rcpt") (result i32)
+    (drop (call $get_var (i32.const 0) (i32.const 9) (i32.const 1024) (i32.const 64)))
+    (if (result i32) (i32.eq (i32.load8_u (i32.const 1024)) (i32.const 98))
+      (then
+        (drop (call $set_reply (i32.const 64) (i32.const 35)))
+        (i32.const 1))
+      (else (i32.const 0))))
+
+  ;; Messages with an X-Block header are discarded, others are tagged
+  (func (export "on_data") (result i32)
+    (if (result i32) (i32.gt_s (call $header_count (i32.const 112) (i32.const 7)) (i32.const 0))
+      (then (i32.const 2))
+      (else
+        (drop (call $add_header (i32.const 32) (i32.const 13) (i32.const 48) (i32.const 7)))
+        (i32.const 0))))
+)
+"#;
+
+#[tokio::test]
+async fn wasm_plugins() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Write plugin
+    let temp_dir = make_temp_dir("smtp_wasm_test", true);
+    let plugin_path = temp_dir.temp_dir.join("test.wat");
+    fs::write(&plugin_path, PLUGIN).unwrap();
+
+    // Prepare config
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_wasm_test_queue");
+    core.plugins = Plugins::parse(
+        &Config::new(&CONFIG.replace("%PATH%", temp_dir.temp_dir.to_str().unwrap())).unwrap(),
+    )
+    .unwrap();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    // Build session
+    let mut session = Session::test(Arc::new(core));
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Plugins that run out of fuel should tempfail
+    session.mail_from("loop@doe.org", "451 4.3.0").await;
+
+    // Test RCPT rejection with custom reply
+    session.mail_from("john@doe.org", "250").await;
+    session
+        .rcpt_to("bill@foobar.org", "550 5.1.1 Mailbox blocked by plugin")
+        .await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+
+    // Test header addition
+    session.data("test:no_dkim", "250").await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("X-Wasm-Plugin: checked")
+        .assert_contains("Subject: Is dinner ready?");
+
+    // Test discard
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("jane@foobar.org", "250").await;
+    session
+        .data(
+            "X-Block: yes\r\nSubject: Blocked\r\n\r\nThis message is discarded.",
+            "250",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Modified plugins are reloaded, this version accepts all recipients
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    fs::write(
+        &plugin_path,
+        PLUGIN.replace("(i32.const 98)", "(i32.const 0)"),
+    )
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+}
@@ -42,9 +42,11 @@ use smtp::{
     config::IfBlock,
     core::{management::Message, Session, SMTP},
     queue::{
+        history::DeliveryHistory,
         manager::{Queue, SpawnQueue},
         QueueId, Status,
     },
+    reporting::scheduler::{Scheduler, SpawnReport},
 };
 
 const DIRECTORY: &str = r#"
@@ -107,9 +109,16 @@ async fn manage_queue() {
     core.queue.config.retry = IfBlock::new(vec![Duration::from_secs(1000)]);
     core.queue.config.notify = IfBlock::new(vec![Duration::from_secs(2000)]);
     core.queue.config.expire = IfBlock::new(Duration::from_secs(3000));
+    core.queue.history = DeliveryHistory::new(Some(Duration::from_secs(3600)));
     let local_qr = core.init_test_queue("smtp_manage_queue_local");
+    let local_rr = core.init_test_report();
     let core = Arc::new(core);
+
+    // The managers are not alive until they are spawned
+    assert!(!core.is_alive(Duration::from_millis(100)).await);
     local_qr.queue_rx.spawn(core.clone(), Queue::default());
+    local_rr.report_rx.spawn(core.clone(), Scheduler::default());
+    assert!(core.is_alive(Duration::from_secs(1)).await);
     let _rx_manage = start_test_server(core.clone(), &[ServerProtocol::Http]);
 
     // Send test messages
@@ -346,6 +355,10 @@ async fn manage_queue() {
     .await
     .into_iter();
     assert_eq!(messages.next().unwrap(), None);
+    assert_eq!(
+        get_history(*id_map.get("e").unwrap()).await,
+        vec!["received", "queued", "attempt", "completed:delivered"]
+    );
     assert_eq!(
         messages
             .next()
@@ -397,6 +410,10 @@ async fn manage_queue() {
             .len(),
         3
     );
+    assert_eq!(
+        get_history(*id_map.get("d").unwrap()).await,
+        vec!["received", "queued", "completed:cancelled"]
+    );
     for (message, id) in get_messages(&[
         *id_map.get("a").unwrap(),
         *id_map.get("b").unwrap(),
@@ -486,3 +503,22 @@ async fn get_messages(ids: &[QueueId]) -> Vec<Option<Message>> {
     .unwrap()
     .unwrap_data()
 }
+
+async fn get_history(id: QueueId) -> Vec<String> {
+    send_manage_request::<Vec<serde_json::Value>>(&format!("/admin/queue/history?id={id}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+        .into_iter()
+        .next()
+        .unwrap()["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event["type"].as_str().unwrap() {
+            "filter" => None,
+            "completed" => format!("completed:{}", event["disposition"].as_str().unwrap()).into(),
+            typ => typ.to_string().into(),
+        })
+        .collect()
+}
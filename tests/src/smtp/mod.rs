@@ -31,7 +31,6 @@ use mail_auth::{
     hickory_resolver::config::{ResolverConfig, ResolverOpts},
     IpLookupStrategy, Resolver,
 };
-use mail_send::smtp::tls::build_tls_connector;
 use sieve::Runtime;
 use smtp_proto::{AUTH_LOGIN, AUTH_PLAIN};
 use tokio::sync::mpsc;
@@ -39,20 +38,36 @@ use tokio::sync::mpsc;
 use smtp::{
     config::{
         if_block::ConfigIf, queue::ConfigQueue, scripts::SieveContext, session::ConfigSession,
-        throttle::ConfigThrottle, AggregateReport, ArcAuthConfig, Auth, ConfigContext, Connect,
-        Data, DkimAuthConfig, DmarcAuthConfig, Dsn, Ehlo, EnvelopeKey, Extensions, IfBlock,
-        IpRevAuthConfig, Mail, MailAuthConfig, Milter, QueueConfig, QueueOutboundSourceIp,
-        QueueOutboundTimeout, QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, Report,
-        ReportAnalysis, ReportConfig, SessionConfig, SessionThrottle, SpfAuthConfig, Throttle,
-        VerifyStrategy,
+        throttle::ConfigThrottle, AddressDisclosure, AggregateReport, ArcAuthConfig, Auth, Banner,
+        ConfigContext, Connect, Data, DictionaryAttack, Disclaimer, DkimAuthConfig, Dlp,
+        DmarcAuthConfig, Dsn, Ehlo, EnvelopeKey, Extensions, FromPolicy, IfBlock, IpRevAuthConfig,
+        Mail, MailAuthConfig, Milter, QueueConfig, QueueOutboundSourceIp, QueueOutboundTimeout,
+        QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, Report, ReportAnalysis, ReportConfig,
+        SendLimits, SessionConfig, SessionThrottle, SpfAuthConfig, SubmissionFixup, Tarpit,
+        Throttle, VerifyStrategy,
     },
     core::{
         throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, Resolvers, SessionCore,
         SieveCore, TlsConnectors, SMTP,
     },
-    outbound::dane::DnssecResolver,
+    outbound::dane::{DnssecMode, DnssecResolver},
+    queue::{
+        bounce::BounceClassifier,
+        history::DeliveryHistory,
+        quarantine::Quarantine,
+        stream::MessageStreams,
+        suppression::{SuppressionAction, SuppressionList},
+        tuning::AutoTune,
+        warmup::WarmUp,
+    },
+    wasm::Plugins,
+};
+use utils::{
+    config::{utils::ParseValues, Config},
+    durability::FileSync,
+    listener::limiter::ConcurrencyLimiter,
+    map::ttl_dashmap::{TtlDashMap, TtlMap},
 };
-use utils::config::{utils::ParseValues, Config};
 
 pub mod config;
 pub mod inbound;
@@ -172,17 +187,22 @@ impl TestConfig for SMTP {
                 dnssec: DnssecResolver::with_capacity(
                     ResolverConfig::cloudflare(),
                     ResolverOpts::default(),
+                    DnssecMode::Disabled,
                 )
                 .unwrap(),
                 cache: smtp::core::DnsCache {
                     tlsa: LruCache::with_capacity(100),
+                    dnssec: LruCache::with_capacity(100),
                     mta_sts: LruCache::with_capacity(100),
+                    persist: None,
                 },
             },
             mail_auth: MailAuthConfig::test(),
             report: ReportCore::test(),
             sieve: SieveCore::test(),
+            plugins: Plugins::new().unwrap(),
             delivery_tx: mpsc::channel(1).0,
+            event_tx: mpsc::channel(1).0,
         }
     }
 }
@@ -196,6 +216,10 @@ impl TestConfig for SessionCore {
                 ThrottleKeyHasherBuilder::default(),
                 16,
             ),
+            throttle_store: None,
+            dictionary: DashMap::new(),
+            senders: DashMap::new(),
+            policy_cache: TtlDashMap::with_capacity(16, 16),
         }
     }
 }
@@ -211,8 +235,17 @@ impl TestConfig for SessionConfig {
                 mail_from: vec![],
                 rcpt_to: vec![],
             },
+            tarpit: Tarpit {
+                errors: IfBlock::new(None),
+                delay: IfBlock::new(Duration::from_secs(1)),
+                max_delay: IfBlock::new(Duration::from_secs(30)),
+                disconnect: IfBlock::new(None),
+            },
             connect: Connect {
                 script: IfBlock::new(None),
+                greeting_delay: IfBlock::new(None),
+                reject_early_talkers: IfBlock::new(true),
+                tarpit: IfBlock::new(false),
             },
             ehlo: Ehlo {
                 script: IfBlock::new(None),
@@ -228,8 +261,8 @@ impl TestConfig for SessionConfig {
                 deliver_by: IfBlock::new(None),
                 mt_priority: IfBlock::new(None),
                 dsn: IfBlock::new(true),
-                expn: IfBlock::new(true),
-                vrfy: IfBlock::new(true),
+                expn: IfBlock::new(AddressDisclosure::Full),
+                vrfy: IfBlock::new(AddressDisclosure::Full),
             },
             auth: Auth {
                 directory: IfBlock::new(None),
@@ -238,10 +271,14 @@ impl TestConfig for SessionConfig {
                 errors_max: IfBlock::new(10),
                 errors_wait: IfBlock::new(Duration::from_secs(1)),
                 allow_plain_text: IfBlock::new(false),
+                must_match_sender: IfBlock::new(false),
+                send_as_groups: IfBlock::new(false),
+                send_as_delegates: IfBlock::new(false),
             },
             mail: Mail {
                 script: IfBlock::new(None),
                 rewrite: IfBlock::new(None),
+                tarpit: IfBlock::new(false),
             },
             rcpt: Rcpt {
                 script: IfBlock::new(None),
@@ -251,21 +288,68 @@ impl TestConfig for SessionConfig {
                 errors_wait: IfBlock::new(Duration::from_secs(1)),
                 max_recipients: IfBlock::new(3),
                 rewrite: IfBlock::new(None),
+                tarpit: IfBlock::new(false),
+                require_tls: IfBlock::new(false),
+                dictionary: DictionaryAttack {
+                    max_unknown: IfBlock::new(None),
+                    window: Duration::from_secs(600),
+                    cooldown: Duration::from_secs(3600),
+                    store: None,
+                    webhook: None,
+                },
+                cache: None,
+                greylist: None,
+                honeypot: None,
             },
             data: Data {
                 script: IfBlock::new(None),
                 max_messages: IfBlock::new(10),
                 max_message_size: IfBlock::new(1024 * 1024),
                 max_received_headers: IfBlock::new(10),
+                max_headers: IfBlock::new(500),
+                max_mime_depth: IfBlock::new(20),
+                max_mime_parts: IfBlock::new(1000),
+                max_addresses: IfBlock::new(1000),
                 add_received: IfBlock::new(true),
                 add_received_spf: IfBlock::new(true),
                 add_return_path: IfBlock::new(true),
                 add_auth_results: IfBlock::new(true),
                 add_message_id: IfBlock::new(true),
                 add_date: IfBlock::new(true),
+                banner: Banner {
+                    enable: IfBlock::default(),
+                    text: None,
+                    html: None,
+                },
+                disclaimer: Disclaimer {
+                    enable: IfBlock::default(),
+                    skip_replies: true,
+                    templates: vec![],
+                },
+                fixup: SubmissionFixup {
+                    enable: IfBlock::default(),
+                    from: FromPolicy::Rewrite,
+                    strip_headers: vec!["Received".to_string(), "X-Originating-IP".to_string()],
+                    max_header_size: 65536,
+                },
+                dlp: Dlp {
+                    enable: IfBlock::default(),
+                    rules: vec![],
+                },
+                send_limits: SendLimits {
+                    messages_hourly: IfBlock::default(),
+                    messages_daily: IfBlock::default(),
+                    recipients_hourly: IfBlock::default(),
+                    recipients_daily: IfBlock::default(),
+                    lock_threshold: IfBlock::default(),
+                    lock_window: Duration::from_secs(600),
+                    webhook: None,
+                    store: None,
+                },
                 pipe_commands: vec![],
                 milters: vec![],
             },
+            policy: vec![],
         }
     }
 }
@@ -279,6 +363,7 @@ impl TestConfig for QueueCore {
                 ThrottleKeyHasherBuilder::default(),
                 16,
             ),
+            throttle_store: None,
             quota: DashMap::with_capacity_and_hasher_and_shard_amount(
                 10,
                 ThrottleKeyHasherBuilder::default(),
@@ -286,10 +371,17 @@ impl TestConfig for QueueCore {
             ),
             tx: mpsc::channel(1024).0,
             id_seq: 0.into(),
-            connectors: TlsConnectors {
-                pki_verify: build_tls_connector(false),
-                dummy_verify: build_tls_connector(true),
-            },
+            connectors: TlsConnectors::new(16),
+            workers: ConcurrencyLimiter::new(u64::MAX),
+            history: DeliveryHistory::new(None),
+            suppression: SuppressionList::new(SuppressionAction::Disabled, None),
+            warmup: WarmUp::new(),
+            bounce: BounceClassifier::new(),
+            quarantine: Quarantine::new(None),
+            streams: MessageStreams::default(),
+            durability: FileSync::default(),
+            shared: None,
+            auto_tune: AutoTune::new(),
         }
     }
 }
@@ -311,11 +403,13 @@ impl TestConfig for QueueConfig {
                 ipv6: IfBlock::new(vec![]),
             },
             ip_strategy: IfBlock::new(IpLookupStrategy::Ipv4thenIpv6),
+            happy_eyeballs: IfBlock::new(None),
             tls: QueueOutboundTls {
                 dane: IfBlock::new(smtp::config::RequireOptional::Optional),
                 mta_sts: IfBlock::new(smtp::config::RequireOptional::Optional),
                 start: IfBlock::new(smtp::config::RequireOptional::Optional),
                 invalid_certs: IfBlock::new(false),
+                mandatory: IfBlock::default(),
             },
             dsn: Dsn {
                 name: IfBlock::new("Mail Delivery Subsystem".to_string()),
@@ -392,6 +486,8 @@ impl TestConfig for ReportConfig {
                 forward: true,
                 store: None,
                 report_id: 0.into(),
+                role_accounts: vec![],
+                role_mailbox: None,
             },
             dkim: Report::test(),
             spf: Report::test(),
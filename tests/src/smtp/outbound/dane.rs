@@ -56,7 +56,7 @@ use crate::smtp::{
 use smtp::{
     config::{AggregateFrequency, IfBlock, RequireOptional},
     core::{Resolvers, Session, SMTP},
-    outbound::dane::{DnssecResolver, Tlsa, TlsaEntry},
+    outbound::dane::{DnssecMode, DnssecResolver, Tlsa, TlsaEntry},
     queue::{manager::Queue, DeliveryAttempt, Error, ErrorDetails, Status},
     reporting::PolicyType,
 };
@@ -233,10 +233,13 @@ async fn dane_test() {
         dns: Resolver::new_cloudflare().unwrap(),
         dnssec: DnssecResolver {
             resolver: AsyncResolver::tokio(conf, opts),
+            mode: DnssecMode::Validate,
         },
         cache: smtp::core::DnsCache {
             tlsa: LruCache::with_capacity(10),
+            dnssec: LruCache::with_capacity(10),
             mta_sts: LruCache::with_capacity(10),
+            persist: None,
         },
     };
 
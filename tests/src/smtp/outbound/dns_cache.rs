@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use mail_auth::{
+    common::lru::{DnsCache, LruCache},
+    hickory_resolver::{
+        config::{ResolverConfig, ResolverOpts},
+        AsyncResolver,
+    },
+};
+use smtp::{
+    core::SMTP,
+    outbound::cache::{CachedRecord, PersistentCache},
+};
+use store::{config::ConfigStore, Stores};
+use utils::config::Config;
+
+use crate::{smtp::TestConfig, store::TempDir};
+
+#[tokio::test]
+async fn dns_cache_persist() {
+    let temp_dir = TempDir::new("smtp_dns_cache_test", true);
+    let stores = Config::new(&format!(
+        "[store.\"dns\"]\ntype = \"sqlite\"\npath = \"{}/dns.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap()
+    .parse_stores()
+    .await
+    .unwrap();
+    let mut core = SMTP::test();
+    core.resolvers.cache.persist = persistent_cache(&stores).into();
+    let cache = core.resolvers.cache.persist.as_ref().unwrap();
+
+    // Entries keep the TTL they were stored with, negative entries are
+    // capped to the negative TTL
+    cache
+        .set(
+            "mx",
+            "example.org",
+            &CachedRecord::Mx(vec![(vec!["mx1.example.org".to_string()], 10)]),
+            Duration::from_secs(120),
+        )
+        .await;
+    cache
+        .set(
+            "a",
+            "unknown.org",
+            &CachedRecord::NotFound,
+            Duration::from_secs(600),
+        )
+        .await;
+    let (record, valid_until) = cache.get("mx", "example.org").await.unwrap();
+    assert!(matches!(record, CachedRecord::Mx(_)));
+    assert!(valid_until <= Instant::now() + Duration::from_secs(120));
+    assert!(valid_until > Instant::now() + Duration::from_secs(110));
+    let (record, valid_until) = cache.get("a", "unknown.org").await.unwrap();
+    assert!(matches!(record, CachedRecord::NotFound));
+    assert!(valid_until <= Instant::now() + Duration::from_secs(60));
+
+    // Lookups are answered from the store
+    assert_eq!(
+        core.resolvers.mx_lookup("Example.org.").await.unwrap()[0].exchanges,
+        vec!["mx1.example.org".to_string()]
+    );
+    assert!(matches!(
+        core.resolvers.ipv4_lookup("unknown.org").await,
+        Err(mail_auth::Error::DnsRecordNotFound(_))
+    ));
+
+    // Records already in memory do not hit the store again
+    cache
+        .set(
+            "mx",
+            "example.org",
+            &CachedRecord::Mx(vec![(vec!["mx2.example.org".to_string()], 10)]),
+            Duration::from_secs(120),
+        )
+        .await;
+    assert_eq!(
+        core.resolvers.mx_lookup("example.org").await.unwrap()[0].exchanges,
+        vec!["mx1.example.org".to_string()]
+    );
+
+    // After a restart the stored records are loaded again
+    core.resolvers.cache.persist = persistent_cache(&stores).into();
+    assert_eq!(
+        core.resolvers.mx_lookup("example.org").await.unwrap()[0].exchanges,
+        vec!["mx2.example.org".to_string()]
+    );
+}
+
+fn persistent_cache(stores: &Stores) -> PersistentCache {
+    PersistentCache {
+        store: stores.lookup_stores.get("dns").unwrap().clone(),
+        resolver: AsyncResolver::tokio(ResolverConfig::cloudflare(), ResolverOpts::default()),
+        ttl: Duration::from_secs(3600),
+        negative_ttl: Duration::from_secs(60),
+        mx: LruCache::with_capacity(10),
+        ipv4: LruCache::with_capacity(10),
+        ipv6: LruCache::with_capacity(10),
+    }
+}
@@ -22,6 +22,7 @@
 */
 
 use std::{
+    net::IpAddr,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -36,6 +37,7 @@ use crate::smtp::{
 use smtp::{
     config::IfBlock,
     core::{Session, SMTP},
+    outbound::happy_eyeballs::{self, Candidate},
     queue::{manager::Queue, DeliveryAttempt},
 };
 
@@ -108,3 +110,39 @@ async fn ip_lookup_strategy() {
         }
     }
 }
+
+#[tokio::test]
+async fn happy_eyeballs() {
+    // Address families should alternate starting with the preferred one
+    let addrs = ["::1", "::2", "::3", "127.0.0.1", "127.0.0.2"]
+        .into_iter()
+        .map(|ip| ip.parse::<IpAddr>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        happy_eyeballs::interleave(addrs)
+            .into_iter()
+            .map(|ip| ip.to_string())
+            .collect::<Vec<_>>(),
+        vec!["::1", "127.0.0.1", "::2", "127.0.0.2", "::3"]
+    );
+
+    // A broken address family should not prevent connecting to the other one
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let candidates = ["::1", "127.0.0.1"]
+        .into_iter()
+        .map(|ip| Candidate {
+            remote_ip: ip.parse().unwrap(),
+            source_ip: None,
+        })
+        .collect::<Vec<_>>();
+    let (candidate, _) = happy_eyeballs::connect(
+        &candidates,
+        port,
+        Duration::from_millis(250),
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(candidate.remote_ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+}
@@ -35,7 +35,7 @@ use crate::smtp::{
 use smtp::{
     config::{remote::ConfigHost, ConfigContext, IfBlock},
     core::{Session, SMTP},
-    queue::{manager::Queue, DeliveryAttempt, Event, WorkerResult},
+    queue::{bounce::BounceClass, manager::Queue, DeliveryAttempt, Event, WorkerResult},
 };
 use utils::config::{Config, ServerProtocol};
 
@@ -140,7 +140,7 @@ async fn lmtp_delivery() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Ping(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
@@ -153,6 +153,16 @@ async fn lmtp_delivery() {
     assert!(queue.scheduled.is_empty());
     assert_eq!(dsn.len(), 4);
 
+    // Each recipient response is classified once, in the attempt that received it
+    let stats = core.queue.bounce.stats(Some("foobar.org"));
+    assert_eq!(
+        stats
+            .iter()
+            .map(|stats| (stats.class, stats.count))
+            .collect::<Vec<_>>(),
+        vec![(BounceClass::UserUnknown, 1), (BounceClass::Other, 4)]
+    );
+
     let mut dsn = dsn.into_iter();
 
     dsn.next()
@@ -31,10 +31,12 @@ use utils::config::{Config, ServerProtocol};
 use super::add_test_certs;
 
 pub mod dane;
+pub mod dns_cache;
 pub mod extensions;
 pub mod ip_lookup;
 pub mod lmtp;
 pub mod mta_sts;
+pub mod pipelining;
 pub mod smtp;
 pub mod throttle;
 pub mod tls;
@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mail_auth::MX;
+use utils::config::ServerProtocol;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    outbound::start_test_server,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::IfBlock,
+    core::{Session, SMTP},
+    queue::{manager::Queue, DeliveryAttempt, Status},
+};
+
+#[tokio::test]
+#[serial_test::serial]
+async fn pipelining() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start test server without CHUNKING so DATA is pipelined,
+    // and accept a single recipient per transaction
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.rcpt.max_recipients = IfBlock::new(1);
+    core.session.config.extensions.chunking = IfBlock::new(false);
+    let mut remote_qr = core.init_test_queue("smtp_pipe_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    let mut local_qr = core.init_test_queue("smtp_pipe_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // Pipelined transaction where the second recipient is deferred
+    session
+        .send_message(
+            "john@test.org",
+            &["bill@foobar.org", "jane@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let retry = local_qr.read_event().await.unwrap_retry();
+    assert!(
+        matches!(retry.inner.recipients[0].status, Status::Completed(_)),
+        "{:?}",
+        retry.inner.recipients[0].status
+    );
+    assert!(
+        matches!(
+            retry.inner.recipients[1].status,
+            Status::TemporaryFailure(_)
+        ),
+        "{:?}",
+        retry.inner.recipients[1].status
+    );
+    let message = remote_qr.read_event().await.unwrap_message();
+    assert_eq!(message.recipients.len(), 1);
+    assert_eq!(message.recipients[0].address, "bill@foobar.org");
+    message
+        .read_lines()
+        .assert_contains("using TLSv1.3 with cipher");
+    remote_qr.assert_empty_queue();
+}
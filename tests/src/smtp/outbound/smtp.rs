@@ -165,7 +165,7 @@ async fn smtp_delivery() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Ping(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
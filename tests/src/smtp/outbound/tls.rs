@@ -27,7 +27,7 @@ use std::{
 };
 
 use mail_auth::MX;
-use utils::config::ServerProtocol;
+use utils::{config::ServerProtocol, stats::ServerStats};
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent},
@@ -105,3 +105,64 @@ async fn starttls_optional() {
         .read_lines()
         .assert_not_contains("using TLSv1.3 with cipher");
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn starttls_resumption() {
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_resumption_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    let mut local_qr = core.init_test_queue("smtp_resumption_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // The first connection performs a full handshake, the second one
+    // resumes the session cached for the same host
+    let stats = ServerStats::get();
+    let mut handshakes = stats.tls_handshakes();
+    let mut resumed = stats.tls_resumed();
+    for is_resumed in [false, true] {
+        session
+            .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+            .await;
+        DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+            .try_deliver(core.clone(), &mut queue)
+            .await;
+        local_qr.read_event().await.unwrap_done();
+        remote_qr
+            .read_event()
+            .await
+            .unwrap_message()
+            .read_lines()
+            .assert_contains("using TLSv1.3 with cipher");
+
+        assert_eq!(stats.tls_handshakes(), handshakes + 1);
+        assert_eq!(stats.tls_resumed(), resumed + u64::from(is_resumed));
+        handshakes += 1;
+        resumed += u64::from(is_resumed);
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use smtp::queue::bounce::{BounceClass, BounceClassifier};
+use smtp_proto::Response;
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[queue.bounce.rule.storage]
+class = "mailbox-full"
+status = "5.7.*"
+message = "storage allocation"
+"#;
+
+#[test]
+fn queue_bounce_classify() {
+    let classifier = BounceClassifier::new();
+    for (code, esc, message, expected) in [
+        (550, [5, 1, 1], "User unknown", BounceClass::UserUnknown),
+        (550, [5, 2, 1], "Mailbox disabled", BounceClass::UserUnknown),
+        (552, [5, 2, 2], "Mailbox full", BounceClass::MailboxFull),
+        (452, [4, 2, 2], "Over quota", BounceClass::MailboxFull),
+        (550, [5, 7, 1], "Message rejected", BounceClass::PolicyBlock),
+        (
+            451,
+            [4, 7, 1],
+            "Greylisted, come back later",
+            BounceClass::Greylisted,
+        ),
+        (
+            450,
+            [0, 0, 0],
+            "Greylisting in action",
+            BounceClass::Greylisted,
+        ),
+        (
+            451,
+            [4, 7, 1],
+            "Please try again later",
+            BounceClass::Greylisted,
+        ),
+        (
+            554,
+            [0, 0, 0],
+            "Listed by Spamhaus",
+            BounceClass::PolicyBlock,
+        ),
+        (
+            550,
+            [0, 0, 0],
+            "Blocked due to poor reputation",
+            BounceClass::PolicyBlock,
+        ),
+        (
+            550,
+            [0, 0, 0],
+            "No such user here",
+            BounceClass::UserUnknown,
+        ),
+        (
+            550,
+            [0, 0, 0],
+            "Requested action not taken",
+            BounceClass::UserUnknown,
+        ),
+        (
+            552,
+            [0, 0, 0],
+            "Requested action aborted",
+            BounceClass::MailboxFull,
+        ),
+        (
+            550,
+            [5, 0, 0],
+            "Requested action not taken",
+            BounceClass::Other,
+        ),
+        (421, [4, 3, 0], "Service unavailable", BounceClass::Other),
+    ] {
+        assert_eq!(
+            classifier.classify(&Response {
+                code,
+                esc,
+                message: message.to_string(),
+            }),
+            expected,
+            "{code} {esc:?} {message}"
+        );
+    }
+
+    // Custom rules take precedence
+    let classifier = BounceClassifier::parse(&Config::new(CONFIG).unwrap()).unwrap();
+    let response = Response {
+        code: 550,
+        esc: [5, 7, 1],
+        message: "Exceeded storage allocation".to_string(),
+    };
+    assert_eq!(classifier.classify(&response), BounceClass::MailboxFull);
+    assert_eq!(
+        BounceClassifier::new().classify(&response),
+        BounceClass::PolicyBlock
+    );
+
+    // Statistics
+    classifier.record("Example.org", BounceClass::UserUnknown);
+    classifier.record("example.org", BounceClass::UserUnknown);
+    classifier.record("example.com", BounceClass::Greylisted);
+    let stats = classifier.stats(Some("example.org"));
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].class, BounceClass::UserUnknown);
+    assert_eq!(stats[0].count, 2);
+    assert_eq!(classifier.stats(None).len(), 2);
+    classifier.clear();
+    assert!(classifier.stats(None).is_empty());
+}
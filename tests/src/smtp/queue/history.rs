@@ -0,0 +1,63 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use smtp::queue::history::{DeliveryHistory, HistoryDetails};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[queue.history]
+enable = true
+retention = "1h"
+
+[queue.history.limits]
+messages = 10
+events = 3
+"#;
+
+#[test]
+fn queue_history_limits() {
+    let history = DeliveryHistory::parse(&Config::new(CONFIG).unwrap()).unwrap();
+
+    // Only the first and the most recent events are kept
+    for result in ["a", "b", "c", "d", "e"] {
+        history.record(1, HistoryDetails::filter("test", result));
+    }
+    let results = history
+        .get(1)
+        .unwrap()
+        .events
+        .into_iter()
+        .map(|event| match event.details {
+            HistoryDetails::Filter { result, .. } => result,
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results, ["a", "d", "e"]);
+
+    // The least recently updated messages are evicted once the limit is reached
+    for id in 2..=20 {
+        history.record(id, HistoryDetails::filter("test", "ok"));
+    }
+    assert!((1..=20).filter(|id| history.get(*id).is_some()).count() <= 10);
+    assert!(history.get(20).is_some());
+}
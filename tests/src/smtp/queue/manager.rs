@@ -25,7 +25,10 @@ use std::time::{Duration, Instant};
 
 use mail_auth::hickory_resolver::proto::op::ResponseCode;
 
-use smtp::queue::{manager::Queue, Domain, Message, Schedule, Status};
+use smtp::{
+    core::management::QueueStats,
+    queue::{manager::Queue, Domain, Error, Message, OnHold, Schedule, Status},
+};
 
 #[test]
 fn queue_due() {
@@ -66,6 +69,90 @@ fn queue_due() {
     assert!(queue.next_due().is_none());
 }
 
+#[test]
+fn queue_stats() {
+    let mut queue = Queue::default();
+    assert_eq!(queue.stats(), QueueStats::default());
+
+    let mut message = new_message(0);
+    message.created = 200;
+    message.domains.push(domain("a", 1, 2, 3));
+    queue.schedule(Schedule {
+        due: message.next_delivery_event(),
+        inner: message,
+    });
+
+    let mut message = new_message(1);
+    message.created = 100;
+    message.domains.push(domain("b", 1, 2, 3));
+    message.domains[0].status = Status::TemporaryFailure(Error::RateLimited);
+    queue.schedule(Schedule {
+        due: message.next_delivery_event(),
+        inner: message,
+    });
+
+    let mut message = new_message(2);
+    message.created = 300;
+    message.domains.push(domain("c", 1, 2, 3));
+    queue.on_hold(OnHold {
+        next_due: None,
+        limiters: vec![],
+        message,
+    });
+
+    assert_eq!(
+        queue.stats(),
+        QueueStats {
+            scheduled: 1,
+            deferred: 1,
+            on_hold: 1,
+            oldest_created: Some(100),
+        }
+    );
+}
+
+#[test]
+fn queue_overdue() {
+    let mut queue = Queue::default();
+    let now = Instant::now();
+    assert_eq!(queue.overdue(now), 0);
+
+    // Scheduled messages are only overdue once their delivery is late
+    let mut message = new_message(0);
+    message.domains.push(domain("a", 1, 2, 3));
+    queue.schedule(Schedule {
+        due: now - Duration::from_secs(60),
+        inner: message,
+    });
+    let mut message = new_message(1);
+    message.domains.push(domain("b", 1, 2, 3));
+    queue.schedule(Schedule {
+        due: message.next_delivery_event(),
+        inner: message,
+    });
+    assert_eq!(queue.overdue(now - Duration::from_secs(30)), 1);
+    assert_eq!(queue.overdue(now - Duration::from_secs(90)), 0);
+
+    // Messages waiting for a concurrency slot are overdue once their retry is late
+    let mut message = new_message(2);
+    message.domains.push(domain("c", 1, 2, 3));
+    message.domains[0].retry.due = now - Duration::from_secs(60);
+    queue.on_hold(OnHold {
+        next_due: None,
+        limiters: vec![],
+        message,
+    });
+    let mut message = new_message(3);
+    message.domains.push(domain("d", 1, 2, 3));
+    queue.on_hold(OnHold {
+        next_due: None,
+        limiters: vec![],
+        message,
+    });
+    assert_eq!(queue.overdue(now - Duration::from_secs(30)), 2);
+    assert_eq!(queue.overdue(now + Duration::from_secs(10)), 4);
+}
+
 #[test]
 fn delivery_events() {
     let mut message = new_message(0);
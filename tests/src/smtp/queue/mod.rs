@@ -21,7 +21,13 @@
  * for more details.
 */
 
+pub mod bounce;
 pub mod dsn;
+pub mod history;
 pub mod manager;
 pub mod retry;
 pub mod serialize;
+pub mod shared;
+pub mod stream;
+pub mod tuning;
+pub mod warmup;
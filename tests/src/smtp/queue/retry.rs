@@ -125,7 +125,7 @@ async fn queue_retry() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Ping(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
@@ -173,6 +173,16 @@ async fn queue_serialize() {
         &Message::from_path(message.path.clone()).await.unwrap(),
     );
 
+    // Messages are published atomically, incomplete files left behind by
+    // a crash are removed when the queue is loaded
+    assert!(!message.path.with_extension("msg.tmp").exists());
+    let incomplete = message.path.with_file_name("1234_5678.msg.tmp");
+    std::fs::write(&incomplete, b"Subject: incomplete\r\n").unwrap();
+    let queue = core.queue.read_queue().await;
+    assert_eq!(queue.messages.len(), 1);
+    assert!(!incomplete.exists());
+    assert!(message.path.exists());
+
     // Remove
     message.remove().await;
     assert!(!message.path.exists());
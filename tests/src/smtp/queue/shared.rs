@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use smtp::{
+    core::SMTP,
+    queue::{shared::SharedQueue, Message},
+};
+use store::{config::ConfigStore, Stores};
+use utils::config::Config;
+
+use crate::{
+    smtp::{inbound::TestQueueEvent, TestConfig, TestSMTP},
+    store::TempDir,
+};
+
+#[tokio::test]
+async fn queue_shared() {
+    let temp_dir = TempDir::new("smtp_queue_shared_test", true);
+    let stores = Config::new(&format!(
+        "[store.\"queue\"]\ntype = \"sqlite\"\npath = \"{}/queue.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap()
+    .parse_stores()
+    .await
+    .unwrap();
+    let node_a = shared_queue(&stores, 1);
+    let node_b = shared_queue(&stores, 2);
+
+    // Queue a message on the first node
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_queue_shared_test");
+    core.queue.shared = Some(shared_queue(&stores, 1));
+    let mut message = Message::new_boxed("john@foobar.org", "john@foobar.org", "foobar.org");
+    message
+        .add_recipient("jane@example.org", &core.queue.config)
+        .await;
+    assert!(
+        core.queue
+            .queue_message(
+                message,
+                (&b"From: john@foobar.org\r\n"[..]).into(),
+                b"Subject: test\r\n\r\ntest",
+                &tracing::info_span!("test")
+            )
+            .await
+    );
+    let message = qr.read_event().await.unwrap_message();
+
+    // Messages are only orphaned once the lease of their owner expires
+    assert!(node_b.orphans().await.unwrap().is_empty());
+    SharedQueue {
+        lease: Duration::ZERO,
+        ..shared_queue(&stores, 1)
+    }
+    .renew_lease()
+    .await
+    .unwrap();
+    let mut orphans = node_b.orphans().await.unwrap();
+    assert_eq!(orphans.len(), 1);
+    assert!(node_a.orphans().await.unwrap().is_empty());
+
+    // Only one node can claim an orphaned message
+    let (id, record) = orphans.pop().unwrap();
+    assert_eq!(id, message.id);
+    let stale_record = record.clone();
+    let (claimed, raw_message) = node_b.claim(id, record).await.unwrap().unwrap();
+    assert_eq!(claimed.id, message.id);
+    assert_eq!(claimed.size, message.size);
+    assert_eq!(claimed.recipients, message.recipients);
+    assert_eq!(
+        raw_message,
+        b"From: john@foobar.org\r\nSubject: test\r\n\r\ntest".to_vec()
+    );
+    assert!(node_a.claim(id, stale_record).await.unwrap().is_none());
+
+    // The previous owner can no longer modify the message
+    assert!(!node_a.is_owner(id).await.unwrap());
+    assert!(node_b.is_owner(id).await.unwrap());
+    assert!(matches!(
+        node_a.update(&message).await,
+        Err(store::Error::AssertValueFailed)
+    ));
+    assert!(matches!(
+        node_a.remove(id).await,
+        Err(store::Error::AssertValueFailed)
+    ));
+    node_b.update(&claimed).await.unwrap();
+
+    // The local copy is dropped when the previous owner restarts
+    assert!(core.queue.read_queue().await.messages.is_empty());
+    assert!(!message.path.exists());
+
+    // Removed messages are no longer shared
+    node_b.remove(id).await.unwrap();
+    assert!(!node_b.is_owner(id).await.unwrap());
+    assert!(node_b.orphans().await.unwrap().is_empty());
+}
+
+fn shared_queue(stores: &Stores, node_id: u64) -> SharedQueue {
+    SharedQueue::parse(
+        &Config::new(&format!(
+            "[queue.shared]\nstore = \"queue\"\nnode-id = {node_id}\nlease = \"1m\"\n"
+        ))
+        .unwrap(),
+        stores,
+    )
+    .unwrap()
+    .unwrap()
+}
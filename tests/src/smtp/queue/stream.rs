@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use mail_parser::DateTime;
+use smtp::queue::stream::{Direction, MessageStreams, StreamEvent};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[queue.stream."archive"]
+type = "nats"
+url = "nats://127.0.0.1:1"
+subject = "mail.accepted"
+queue-size = 1
+timeout = "1s"
+retry = {attempts = 100, delay = "1s"}
+
+[queue.stream."disabled"]
+type = "kafka"
+enable = false
+brokers = ["127.0.0.1:1"]
+topic = "mail"
+"#;
+
+#[tokio::test]
+async fn queue_stream() {
+    let streams = MessageStreams::parse(&Config::new(CONFIG).unwrap()).unwrap();
+    assert!(streams.is_enabled());
+    assert!(!streams.include_message());
+
+    // Publishing never waits for an unavailable broker, events that do not
+    // fit in the queue are dropped
+    let time = Instant::now();
+    for id in 0..5 {
+        streams.publish(StreamEvent {
+            id,
+            timestamp: DateTime::from_timestamp(0),
+            direction: Direction::Inbound,
+            listener: "smtp".to_string(),
+            remote_ip: "10.0.0.1".parse().unwrap(),
+            authenticated_as: String::new(),
+            return_path: "john@example.org".to_string(),
+            recipients: vec!["jane@example.org".to_string()],
+            size: 1024,
+            message_id: None,
+            raw_message: None,
+        });
+    }
+    assert!(time.elapsed() < Duration::from_millis(500));
+    let dropped = streams.dropped();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].0, "archive");
+    assert!(dropped[0].1 >= 3, "{dropped:?}");
+
+    // Unknown stream types are rejected
+    assert!(MessageStreams::parse(
+        &Config::new("[queue.stream.\"test\"]\ntype = \"amqp\"\n").unwrap()
+    )
+    .is_err());
+}
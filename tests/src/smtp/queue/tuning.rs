@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use smtp::queue::{
+    tuning::{is_throttle_response, AutoTune, Signal},
+    Error, ErrorDetails, HostResponse, Status,
+};
+use smtp_proto::Response;
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[queue.auto-tune]
+enable = true
+backoff = 0.5
+latency.threshold = "2s"
+
+[queue.auto-tune.concurrency]
+min = 1
+max = 4
+initial = 2
+
+[queue.auto-tune.delay]
+initial = "1s"
+max = "4s"
+"#;
+
+#[test]
+fn queue_auto_tune() {
+    let tune = AutoTune::parse(&Config::new(CONFIG).unwrap()).unwrap();
+    let host = "mx.example.org";
+
+    // Concurrency is limited to the initial value
+    let first = tune.is_allowed(host).unwrap().unwrap();
+    let second = tune.is_allowed(host).unwrap().unwrap();
+    assert!(tune.is_allowed(host).is_err());
+    drop(first);
+    drop(second);
+    assert_eq!(tune.limit(host), Some((2, Duration::ZERO)));
+
+    // Successful deliveries increase concurrency up to the maximum
+    for _ in 0..20 {
+        tune.record(host, Signal::Success);
+    }
+    assert_eq!(tune.limit(host), Some((4, Duration::ZERO)));
+
+    // Throttling responses back off and delay new connections
+    let throttled = Status::TemporaryFailure(Error::UnexpectedResponse(HostResponse {
+        hostname: ErrorDetails {
+            entity: host.to_string(),
+            details: "MAIL FROM:<john@foobar.org>".to_string(),
+        },
+        response: Response {
+            code: 421,
+            esc: [4, 7, 0],
+            message: "Rate limit exceeded, slow down".to_string(),
+        },
+    }));
+    tune.record(host, Signal::from_status(&throttled));
+    assert_eq!(tune.limit(host), Some((2, Duration::from_secs(1))));
+    assert!(tune.is_allowed(host).is_err());
+    tune.record(host, Signal::from_status(&throttled));
+    tune.record(host, Signal::from_status(&throttled));
+    tune.record(host, Signal::from_status(&throttled));
+    assert_eq!(tune.limit(host), Some((1, Duration::from_secs(4))));
+
+    // Slow connections are treated as throttling
+    tune.record_latency("mx.example.com", Duration::from_secs(3));
+    assert_eq!(
+        tune.limit("mx.example.com"),
+        Some((1, Duration::from_secs(1)))
+    );
+
+    // Recovery is gradual
+    tune.record(host, Signal::Success);
+    assert_eq!(tune.limit(host), Some((2, Duration::from_secs(2))));
+
+    // Only temporary failures mentioning throttling are signals
+    assert!(is_throttle_response(&Response {
+        code: 450,
+        esc: [4, 2, 1],
+        message: "Too many connections from your IP".to_string(),
+    }));
+    assert!(!is_throttle_response(&Response {
+        code: 450,
+        esc: [4, 2, 0],
+        message: "Greylisted, please try again later".to_string(),
+    }));
+    assert!(!is_throttle_response(&Response {
+        code: 550,
+        esc: [5, 7, 1],
+        message: "Message rate exceeded".to_string(),
+    }));
+    assert!(AutoTune::new().is_allowed(host).unwrap().is_none());
+}
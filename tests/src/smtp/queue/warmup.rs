@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use smtp::queue::warmup::WarmUp;
+use store::{config::ConfigStore, Stores};
+use utils::config::Config;
+
+use crate::store::TempDir;
+
+const CONFIG: &str = r#"
+[queue.warm-up]
+schedule = [2, 5]
+step = "1d"
+
+[queue.warm-up.ip.new]
+address = "10.0.0.12"
+start = "2000-01-01"
+
+[queue.warm-up.ip.fresh]
+address = "10.0.0.13"
+start = "2999-01-01T00:00:00Z"
+
+[queue.warm-up.provider]
+gmail = ["gmail.com", "googlemail.com"]
+"#;
+
+#[tokio::test]
+async fn queue_warmup() {
+    let warmup = WarmUp::parse(&Config::new(CONFIG).unwrap(), &Stores::default()).unwrap();
+    assert!(warmup.is_enabled());
+
+    // Addresses that completed their schedule are not limited
+    for _ in 0..10 {
+        let ip = "10.0.0.12".parse().unwrap();
+        assert!(warmup.is_allowed(ip, "gmail.com").await.is_ok());
+        warmup.record_sent(ip, "gmail.com").await;
+    }
+
+    // Only delivered messages count towards the limit
+    let ip = "10.0.0.13".parse().unwrap();
+    for _ in 0..5 {
+        assert!(warmup.is_allowed(ip, "gmail.com").await.is_ok());
+    }
+
+    // Addresses pending warm-up use the first stage, with domains grouped by provider
+    warmup.record_sent(ip, "gmail.com").await;
+    assert!(warmup.is_allowed(ip, "googlemail.com").await.is_ok());
+    warmup.record_sent(ip, "googlemail.com").await;
+    assert!(warmup.is_allowed(ip, "gmail.com").await.is_err());
+    assert!(warmup.is_allowed(ip, "example.org").await.is_ok());
+    warmup.record_sent(ip, "example.org").await;
+    assert!(warmup
+        .is_allowed("10.0.0.14".parse().unwrap(), "gmail.com")
+        .await
+        .is_ok());
+
+    // Check progress
+    let status = warmup.status();
+    assert_eq!(status.len(), 2);
+    assert!(status[0].complete);
+    assert_eq!(status[0].daily_limit, None);
+    assert!(!status[1].complete);
+    assert_eq!(status[1].stage, 0);
+    assert_eq!(status[1].daily_limit, Some(2));
+    assert_eq!(status[1].providers.len(), 2);
+    assert_eq!(status[1].providers[0].name, "gmail");
+    assert_eq!(status[1].providers[0].sent, 2);
+}
+
+#[tokio::test]
+async fn queue_warmup_persist() {
+    let temp_dir = TempDir::new("smtp_warmup_persist_test", true);
+    let config = Config::new(&format!(
+        "{CONFIG}\n[sieve.trusted.default]\nstore = \"warmup\"\n\n[store.\"warmup\"]\ntype = \"sqlite\"\npath = \"{}/warmup.db\"\n",
+        temp_dir.path.to_string_lossy()
+    ))
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+
+    // Counters are persisted, so they are shared by all instances using the store
+    let ip = "10.0.0.13".parse().unwrap();
+    let warmup = WarmUp::parse(&config, &stores).unwrap();
+    warmup.record_sent(ip, "gmail.com").await;
+    warmup.record_sent(ip, "googlemail.com").await;
+    assert!(warmup.is_allowed(ip, "gmail.com").await.is_err());
+
+    let warmup = WarmUp::parse(&config, &stores).unwrap();
+    assert!(warmup.is_allowed(ip, "gmail.com").await.is_err());
+    assert!(warmup.is_allowed(ip, "example.org").await.is_ok());
+    assert_eq!(warmup.status()[1].providers[0].sent, 2);
+}
@@ -37,6 +37,7 @@ use utils::config::DynValue;
 use crate::smtp::{
     inbound::{sign::TextConfigContext, TestMessage, TestQueueEvent},
     make_temp_dir,
+    reporting::assert_report_removed,
     session::VerifyResponse,
     ParseTestConfig, TestConfig, TestSMTP,
 };
@@ -186,5 +187,5 @@ async fn report_dmarc() {
         }
     }
 
-    assert!(!report_path.exists());
+    assert_report_removed(&report_path).await;
 }
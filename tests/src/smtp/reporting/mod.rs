@@ -21,7 +21,20 @@
  * for more details.
 */
 
+use std::{path::Path, time::Duration};
+
 pub mod analyze;
 pub mod dmarc;
 pub mod scheduler;
 pub mod tls;
+
+// Report files are removed by the worker right after the report is queued
+pub async fn assert_report_removed(path: &Path) {
+    for _ in 0..50 {
+        if !path.exists() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Report file {} was not removed", path.display());
+}
@@ -34,6 +34,7 @@ use utils::config::DynValue;
 use crate::smtp::{
     inbound::{sign::TextConfigContext, TestMessage, TestQueueEvent},
     make_temp_dir,
+    reporting::assert_report_removed,
     session::VerifyResponse,
     ParseTestConfig, TestConfig, TestSMTP,
 };
@@ -212,7 +213,7 @@ async fn report_tls() {
     assert!(seen[2]);
 
     for path in report_path {
-        assert!(!path.exists());
+        assert_report_removed(&path).await;
     }
 
     // Schedule TLS reports to be delivered via https
@@ -258,6 +259,6 @@ async fn report_tls() {
     assert_eq!(report.policies.len(), 1);
 
     for path in report_path {
-        assert!(!path.exists());
+        assert_report_removed(&path).await;
     }
 }
@@ -371,6 +371,9 @@ impl TestServerInstance for ServerInstance {
             tls_acceptor: None,
             is_tls_implicit: false,
             limiter: ConcurrencyLimiter::new(100),
+            connections: Default::default(),
+            max_connections_ip: u64::MAX,
+            max_connections_account: u64::MAX,
             shutdown_rx,
         }
     }
@@ -43,6 +43,120 @@ pub async fn blob_tests() {
         test_store(blob_store.clone()).await;
     }
 
+    // Blobs written before compression was enabled remain readable
+    println!("Testing blob compression...");
+    let compressed = stores.blob_stores.get("fs").unwrap();
+    let plain = stores.blob_stores.get("fs-plain").unwrap();
+    let data = "Subject: Compress me\r\n\r\nThis line repeats.\r\n".repeat(20);
+    let hash = BlobHash::from(data.as_bytes());
+    plain
+        .put_blob(hash.as_ref(), data.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        compressed
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data.as_bytes()
+    );
+    assert!(compressed.compress_blob(hash.as_ref()).await.unwrap());
+    assert!(!compressed.compress_blob(hash.as_ref()).await.unwrap());
+    assert!(
+        plain
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap()
+            .len()
+            < data.len()
+    );
+    assert_eq!(
+        compressed
+            .get_blob(hash.as_ref(), 9..20)
+            .await
+            .unwrap()
+            .unwrap(),
+        &data.as_bytes()[9..20]
+    );
+    assert!(compressed.delete_blob(hash.as_ref()).await.unwrap());
+
+    // Ranged reads of large blobs only decompress the chunks they need
+    let data = (0..20000)
+        .map(|num| format!("Line {num} of a large message.\r\n"))
+        .collect::<String>();
+    let hash = BlobHash::from(data.as_bytes());
+    compressed
+        .put_blob(hash.as_ref(), data.as_bytes())
+        .await
+        .unwrap();
+    assert!(
+        plain
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap()
+            .len()
+            < data.len()
+    );
+    for range in [
+        0..100,
+        65530..65550,
+        100000..300000,
+        data.len() as u32 - 10..data.len() as u32 + 10,
+    ] {
+        let end = std::cmp::min(range.end as usize, data.len());
+        assert_eq!(
+            compressed
+                .get_blob(hash.as_ref(), range.clone())
+                .await
+                .unwrap()
+                .unwrap(),
+            &data.as_bytes()[range.start as usize..end],
+            "{range:?}"
+        );
+    }
+    assert_eq!(
+        compressed
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data.as_bytes()
+    );
+    assert!(compressed.delete_blob(hash.as_ref()).await.unwrap());
+
+    // Blobs moved to the cold tier remain readable until deleted
+    println!("Testing blob tiering...");
+    let tiered = stores.blob_stores.get("tiered").unwrap();
+    let hot = stores.blob_stores.get("fs").unwrap();
+    let cold = stores.blob_stores.get("sqlite").unwrap();
+    let hash = BlobHash::from(b"tiered blob".as_slice());
+    tiered
+        .put_blob(hash.as_ref(), b"tiered blob")
+        .await
+        .unwrap();
+    assert!(!tiered.is_cold(hash.as_ref()).await.unwrap());
+    assert!(tiered.demote_blob(hash.as_ref()).await.unwrap());
+    assert!(!tiered.demote_blob(hash.as_ref()).await.unwrap());
+    assert!(tiered.is_cold(hash.as_ref()).await.unwrap());
+    assert!(hot
+        .get_blob(hash.as_ref(), 0..u32::MAX)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        tiered.get_blob(hash.as_ref(), 0..6).await.unwrap().unwrap(),
+        b"tiered"
+    );
+    assert!(tiered.delete_blob(hash.as_ref()).await.unwrap());
+    assert!(cold
+        .get_blob(hash.as_ref(), 0..u32::MAX)
+        .await
+        .unwrap()
+        .is_none());
+
     for (store_id, store) in stores.stores {
         println!("Testing blob management on store {}...", store_id);
 
@@ -417,6 +531,50 @@ pub async fn blob_tests() {
                     ^ ct
             );
         }
+
+        // Reserved blobs are not purged, even if no document links to them
+        let hash = BlobHash::from(b"purge".as_slice());
+        let until = now() + 3600;
+        blob_store.put_blob(hash.as_ref(), b"purge").await.unwrap();
+        store
+            .write(
+                BatchBuilder::new()
+                    .with_account_id(2)
+                    .set(
+                        BlobOp::Reserve {
+                            until,
+                            hash: hash.clone(),
+                        },
+                        5u32.serialize(),
+                    )
+                    .set(BlobOp::Commit { hash: hash.clone() }, Vec::new())
+                    .build_batch(),
+            )
+            .await
+            .unwrap();
+        assert!(!store.blob_hash_purge(&blob_store, &hash).await.unwrap());
+        assert!(store.blob_exists(&hash).await.unwrap());
+
+        // Once the reservation is released the blob is purged
+        store
+            .write(
+                BatchBuilder::new()
+                    .with_account_id(2)
+                    .clear(BlobOp::Reserve {
+                        until,
+                        hash: hash.clone(),
+                    })
+                    .build_batch(),
+            )
+            .await
+            .unwrap();
+        assert!(store.blob_hash_purge(&blob_store, &hash).await.unwrap());
+        assert!(!store.blob_exists(&hash).await.unwrap());
+        assert!(blob_store
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .is_none());
     }
     temp_dir.delete();
 }
@@ -22,7 +22,7 @@
 */
 
 use store::{config::ConfigStore, LookupKey, LookupStore, LookupValue};
-use utils::config::Config;
+use utils::config::{Config, Rate};
 
 use crate::store::{TempDir, CONFIG};
 
@@ -84,6 +84,43 @@ pub async fn lookup_tests() {
             .key_get::<String>(LookupKey::Key(key.clone()))
             .await
             .unwrap(), LookupValue::Value { value,.. } if value == "hello"));
+
+        // Test expiring counters
+        let counter_key = "rate".as_bytes().to_vec();
+        for num in 1..=2 {
+            assert_eq!(
+                store.counter_incr(counter_key.clone(), 1, 1).await.unwrap(),
+                num
+            );
+        }
+
+        // Test rate limiter, starting at the beginning of a window
+        let rate = Rate {
+            requests: 1,
+            period: tokio::time::Duration::from_secs(1),
+        };
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .subsec_millis();
+        tokio::time::sleep(tokio::time::Duration::from_millis(
+            (1000 - elapsed as u64) + 10,
+        ))
+        .await;
+        assert_eq!(
+            store.is_rate_allowed(b"limit", &rate, false).await.unwrap(),
+            None
+        );
+        assert!(store
+            .is_rate_allowed(b"limit", &rate, true)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(store
+            .is_rate_allowed(b"limit", &rate, false)
+            .await
+            .unwrap()
+            .is_some());
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
         assert_eq!(
             LookupValue::None,
@@ -93,7 +130,15 @@ pub async fn lookup_tests() {
                 .unwrap()
         );
 
+        // Past counters and rate limit windows are removed
         store.purge_expired().await.unwrap();
+        assert_eq!(
+            LookupValue::Counter { num: 0 },
+            store
+                .key_get::<String>(LookupKey::Counter(counter_key))
+                .await
+                .unwrap()
+        );
         if let LookupStore::Store(store) = &store {
             store.assert_is_empty(store.clone().into()).await;
         }
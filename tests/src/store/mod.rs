@@ -49,6 +49,14 @@ bucket = "tmp"
 type = "fs"
 path = "{TMP}"
 
+[store."fs".compression]
+text = "zstd"
+binary = "none"
+
+[store."fs-plain"]
+type = "fs"
+path = "{TMP}"
+
 [store."rocksdb"]
 type = "rocksdb"
 path = "{TMP}/rocksdb"
@@ -80,6 +88,11 @@ password = "password"
 type = "redis"
 url = "redis://127.0.0.1"
 
+[store."tiered"]
+type = "tiered"
+hot = "fs"
+cold = "sqlite"
+
 "#;
 
 #[tokio::test(flavor = "multi_thread")]